@@ -1,7 +1,12 @@
+use std::{fmt::Write, path::Path, process::Command};
+
 use slint_build::{CompilerConfiguration, EmbedResourcesKind};
 
 fn main() {
     linker_be_nice();
+    build_info();
+    embed_assets();
+    link_c_app();
     // make sure linkall.x is the last linker script (otherwise might cause problems with flip-link)
     println!("cargo:rustc-link-arg=-Tlinkall.x");
 
@@ -13,6 +18,258 @@ fn main() {
     println!("cargo:rerun-if-changed=ui.slint");
 }
 
+// exported as BUILD_* env vars, read by src/build_info.rs
+fn build_info() {
+    let git_hash = command_output("git", &["rev-parse", "--short=10", "HEAD"])
+        .map(|hash| {
+            let dirty = command_output("git", &["status", "--porcelain", "--untracked-files=no"])
+                .is_some_and(|status| !status.is_empty());
+            if dirty { format!("{hash}-dirty") } else { hash }
+        })
+        .unwrap_or_else(|| "unknown".into());
+
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+    let rustc_version = command_output(&rustc, &["--version"]).unwrap_or_else(|| "unknown".into());
+
+    // honour SOURCE_DATE_EPOCH so reproducible builds stay reproducible
+    let build_epoch = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0)
+        });
+
+    println!("cargo:rustc-env=BUILD_GIT_HASH={git_hash}");
+    println!("cargo:rustc-env=BUILD_TIME={}", format_utc(build_epoch));
+    println!("cargo:rustc-env=BUILD_RUSTC_VERSION={rustc_version}");
+    println!(
+        "cargo:rustc-env=BUILD_ESP_HAL_VERSION={}",
+        locked_version("esp-hal").unwrap_or_else(|| "unknown".into())
+    );
+    println!(
+        "cargo:rustc-env=BUILD_SLINT_VERSION={}",
+        locked_version("slint").unwrap_or_else(|| "unknown".into())
+    );
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/index");
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+}
+
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn locked_version(package: &str) -> Option<String> {
+    let lock = std::fs::read_to_string("Cargo.lock").ok()?;
+    let mut lines = lock.lines();
+    while let Some(line) = lines.next() {
+        if line == format!("name = \"{package}\"") {
+            return lines
+                .next()?
+                .strip_prefix("version = \"")?
+                .strip_suffix('"')
+                .map(str::to_string);
+        }
+    }
+    None
+}
+
+// the `ffi` feature links the C application, a static library providing esp32_slint_app_start
+fn link_c_app() {
+    println!("cargo:rerun-if-env-changed=C_APP_LIB_DIR");
+    if std::env::var_os("CARGO_FEATURE_FFI").is_none() {
+        return;
+    }
+    let dir = std::env::var("C_APP_LIB_DIR")
+        .expect("the ffi feature needs C_APP_LIB_DIR, the directory holding libapp.a");
+    println!("cargo:rustc-link-search=native={dir}");
+    println!("cargo:rustc-link-lib=static=app");
+    println!("cargo:rerun-if-changed={dir}/libapp.a");
+}
+
+// images in assets/ compressed into OUT_DIR for src/assets.rs, RLE or LZ4 per image, whichever
+// comes out smaller
+fn embed_assets() {
+    println!("cargo:rerun-if-changed=assets");
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    let mut paths: Vec<_> = std::fs::read_dir("assets")
+        .map(|dir| dir.filter_map(|entry| Some(entry.ok()?.path())).collect())
+        .unwrap_or_default();
+    paths.sort();
+
+    let mut table = String::from("static ASSETS: &[Asset] = &[\n");
+    let (mut raw_total, mut packed_total) = (0, 0);
+    for path in paths {
+        let Some((name, width, height, alpha, pixels)) = decode_image(&path) else {
+            continue;
+        };
+        let bytes_per_pixel = if alpha { 4 } else { 3 };
+        let (codec, data) = [
+            ("Stored", pixels.clone()),
+            ("Rle", pack_rle(&pixels, bytes_per_pixel)),
+            ("Lz4", lz4_flex::block::compress(&pixels)),
+        ]
+        .into_iter()
+        .min_by_key(|(_, data)| data.len())
+        .unwrap();
+        raw_total += pixels.len();
+        packed_total += data.len();
+
+        let file = Path::new(&out_dir).join(format!("asset_{name}.bin"));
+        std::fs::write(&file, &data).unwrap();
+        writeln!(
+            table,
+            "    Asset {{ name: {name:?}, width: {width}, height: {height}, alpha: {alpha}, \
+             codec: Codec::{codec}, data: include_bytes!({file:?}) }},"
+        )
+        .unwrap();
+    }
+    table.push_str("];\n");
+    if raw_total > 0 {
+        println!("cargo:warning=assets: {raw_total} bytes of pixels stored in {packed_total}");
+    }
+    std::fs::write(Path::new(&out_dir).join("assets.rs"), table).unwrap();
+}
+
+// (name, width, height, alpha, top-down RGB or RGBA bytes), PNG or uncompressed 24/32 bit BMP
+fn decode_image(path: &Path) -> Option<(String, u32, u32, bool, Vec<u8>)> {
+    let name = path.file_stem()?.to_str()?.to_string();
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    let (width, height, alpha, pixels) = match extension.as_str() {
+        "png" => {
+            let mut decoder = png::Decoder::new(std::fs::File::open(path).ok()?);
+            decoder.set_transformations(png::Transformations::normalize_to_color8());
+            let mut reader = decoder.read_info().unwrap();
+            let mut buffer = vec![0; reader.output_buffer_size()];
+            let info = reader.next_frame(&mut buffer).unwrap();
+            buffer.truncate(info.buffer_size());
+            let (width, height) = (info.width, info.height);
+            match info.color_type {
+                png::ColorType::Rgb => (width, height, false, buffer),
+                png::ColorType::Rgba => (width, height, true, buffer),
+                png::ColorType::Grayscale => (
+                    width,
+                    height,
+                    false,
+                    buffer.iter().flat_map(|&v| [v, v, v]).collect(),
+                ),
+                png::ColorType::GrayscaleAlpha => {
+                    let pixels = buffer.chunks(2).flat_map(|p| [p[0], p[0], p[0], p[1]]);
+                    (width, height, true, pixels.collect())
+                }
+                other => panic!("{}: unsupported PNG colour type {other:?}", path.display()),
+            }
+        }
+        "bmp" => decode_bmp(path, &std::fs::read(path).ok()?),
+        _ => return None,
+    };
+    Some((name, width, height, alpha, pixels))
+}
+
+fn decode_bmp(path: &Path, file: &[u8]) -> (u32, u32, bool, Vec<u8>) {
+    let u32_at = |at: usize| u32::from_le_bytes(file[at..at + 4].try_into().unwrap());
+    let u16_at = |at: usize| u16::from_le_bytes(file[at..at + 2].try_into().unwrap());
+    let offset = u32_at(10) as usize;
+    let width = u32_at(18);
+    let raw_height = u32_at(22) as i32;
+    let bits = u16_at(28);
+    // 0 is BI_RGB, 3 BI_BITFIELDS which 32 bit files with alpha write with the usual BGRA masks
+    let compression = u32_at(30);
+    if file[..2] != *b"BM" || !matches!(bits, 24 | 32) || !matches!(compression, 0 | 3) {
+        panic!(
+            "{}: only uncompressed 24 or 32 bit BMPs are supported",
+            path.display()
+        );
+    }
+    let (height, bottom_up) = (raw_height.unsigned_abs(), raw_height > 0);
+    let src_bpp = bits as usize / 8;
+    let stride = (width as usize * src_bpp).div_ceil(4) * 4;
+    // the fourth byte of a plain 32 bit BMP is padding
+    let alpha = bits == 32 && compression == 3;
+    let mut pixels = Vec::new();
+    for y in 0..height as usize {
+        let row = if bottom_up {
+            height as usize - 1 - y
+        } else {
+            y
+        };
+        let start = offset + row * stride;
+        for pixel in file[start..start + width as usize * src_bpp].chunks(src_bpp) {
+            pixels.extend_from_slice(&[pixel[2], pixel[1], pixel[0]]);
+            if alpha {
+                pixels.push(pixel[3]);
+            }
+        }
+    }
+    (width, height, alpha, pixels)
+}
+
+// packets of whole pixels, see `unpack_rle` in src/assets.rs: a header with 0x80 set repeats the
+// next pixel (header & 0x7F) + 1 times, otherwise header + 1 literal pixels follow
+fn pack_rle(pixels: &[u8], bytes_per_pixel: usize) -> Vec<u8> {
+    let pixels: Vec<&[u8]> = pixels.chunks(bytes_per_pixel).collect();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < pixels.len() {
+        let run = pixels[i..]
+            .iter()
+            .take(128)
+            .take_while(|p| **p == pixels[i])
+            .count();
+        if run >= 2 {
+            out.push(0x80 | (run - 1) as u8);
+            out.extend_from_slice(pixels[i]);
+            i += run;
+            continue;
+        }
+        // literals until the next run of two
+        let mut len = 1;
+        while len < 128 && i + len < pixels.len() {
+            if i + len + 1 < pixels.len() && pixels[i + len] == pixels[i + len + 1] {
+                break;
+            }
+            len += 1;
+        }
+        out.push((len - 1) as u8);
+        for pixel in &pixels[i..i + len] {
+            out.extend_from_slice(pixel);
+        }
+        i += len;
+    }
+    out
+}
+
+// days-to-civil conversion, keeps chrono out of the build dependencies
+fn format_utc(epoch: u64) -> String {
+    let days = (epoch / 86_400) as i64;
+    let secs = epoch % 86_400;
+
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
 fn linker_be_nice() {
     let args: Vec<String> = std::env::args().collect();
     if args.len() > 1 {
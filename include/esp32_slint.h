@@ -0,0 +1,59 @@
+/* C API of the esp32-ili9341-slint firmware, built with the `ffi` feature.
+ * The firmware owns start-up, the heap, wifi and the display; the C application is a static
+ * library (libapp.a, built with xtensa-esp32-elf-gcc) linked into it. Once the peripherals are up
+ * the firmware calls esp32_slint_app_start(), which calls esp32_slint_init() and registers its
+ * callbacks. Setters only queue, the UI loop applies the queue once per pass; callbacks run on the
+ * UI loop and may call back into the API. */
+#ifndef ESP32_SLINT_H
+#define ESP32_SLINT_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define ESP32_SLINT_API_VERSION 1
+
+#define ESP32_SLINT_OK 0
+#define ESP32_SLINT_ERR_ARGUMENT (-1)
+#define ESP32_SLINT_ERR_VERSION (-2)
+/* the queue holds 32 entries until the next pass of the UI loop */
+#define ESP32_SLINT_ERR_FULL (-3)
+#define ESP32_SLINT_ERR_PROPERTY (-4)
+
+typedef enum {
+    /* text: the address */
+    ESP32_SLINT_EVENT_IP_ACQUIRED = 0,
+    ESP32_SLINT_EVENT_WIFI_LOST = 1,
+    /* text: the frame */
+    ESP32_SLINT_EVENT_WS_MESSAGE = 2,
+    ESP32_SLINT_EVENT_POWER_FAIL = 3,
+    /* value: free heap bytes */
+    ESP32_SLINT_EVENT_LOW_HEAP = 4,
+} esp32_slint_event_t;
+
+/* text is NUL-terminated UTF-8, valid only during the call */
+typedef void (*esp32_slint_callback_t)(uint32_t event, const char *text, float value, void *user);
+
+/* implemented by the C application, called once from the UI loop */
+void esp32_slint_app_start(void);
+
+/* pass ESP32_SLINT_API_VERSION; drops callbacks registered before */
+int32_t esp32_slint_init(uint32_t api_version);
+
+/* several callbacks per event are fine, they run in registration order */
+int32_t esp32_slint_register_callback(uint32_t event, esp32_slint_callback_t callback,
+                                      void *user);
+
+/* name: "remote_text", "status" or "notification"; both strings are copied */
+int32_t esp32_slint_set_property(const char *name, const char *value);
+
+/* a value for the rule engine channel `name`, rules.txt can compare against it */
+int32_t esp32_slint_push_event(const char *name, float value);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,79 @@
+"""Builds FONTS/FALLBACK.GLF for the sd card.
+
+Every requested character is rendered from the primary face when it has the glyph and from
+the fallback face otherwise, so the pack is the primary font with the holes filled in.
+
+    pip install pillow fonttools
+    python make_font_pack.py Primary.ttf NotoSans.ttf --sizes 10,12,14 \
+        --chars "ąćęłńóśźżĄĆĘŁŃÓŚŹŻ" --out FALLBACK.GLF
+
+Printable ASCII is always included. Keep CJK sets small, the pack is loaded into RAM.
+"""
+import argparse
+import struct
+
+from fontTools.ttLib import TTFont
+from PIL import ImageFont
+
+
+def main():
+    parser = argparse.ArgumentParser()
+    parser.add_argument("primary")
+    parser.add_argument("fallback")
+    parser.add_argument("--sizes", default="12")
+    parser.add_argument("--chars", default="")
+    parser.add_argument("--weight", type=int, default=400)
+    parser.add_argument("--out", default="FALLBACK.GLF")
+    args = parser.parse_args()
+
+    sizes = [int(s) for s in args.sizes.split(",")]
+    chars = sorted(set(map(chr, range(0x20, 0x7F))) | set(args.chars))
+
+    primary = TTFont(args.primary)
+    primary_cmap = primary.getBestCmap()
+    fallback_cmap = TTFont(args.fallback).getBestCmap()
+    chars = [c for c in chars if ord(c) in primary_cmap or ord(c) in fallback_cmap]
+
+    # metrics come from the primary, fallback glyphs are scaled to its em square by size
+    units_per_em = primary["head"].unitsPerEm
+    os2 = primary["OS/2"]
+    out = bytearray(b"GLF1")
+    out += struct.pack(
+        "<HHhhhhHB",
+        args.weight,
+        units_per_em,
+        primary["hhea"].ascent,
+        primary["hhea"].descent,
+        getattr(os2, "sxHeight", 0),
+        getattr(os2, "sCapHeight", 0),
+        len(chars),
+        len(sizes),
+    )
+    for c in chars:
+        out += struct.pack("<I", ord(c))
+
+    for size in sizes:
+        faces = {
+            True: ImageFont.truetype(args.primary, size),
+            False: ImageFont.truetype(args.fallback, size),
+        }
+        out += struct.pack("<h", size)
+        for c in chars:
+            face = faces[ord(c) in primary_cmap]
+            mask, (ox, oy) = face.getmask2(c, mode="L", anchor="ls")
+            width, height = mask.size
+            alpha = bytes(mask)
+            # baseline relative, y points up to the bottom edge; 26.6 fixed point
+            x = ox * 64
+            y = -(oy + height) * 64
+            advance = round(face.getlength(c) * 64)
+            out += struct.pack("<hhhhhH", x, y, width, height, advance, len(alpha))
+            out += alpha
+
+    with open(args.out, "wb") as f:
+        f.write(out)
+    print(f"{args.out}: {len(chars)} glyphs x {len(sizes)} sizes, {len(out)} bytes")
+
+
+if __name__ == "__main__":
+    main()
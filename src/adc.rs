@@ -0,0 +1,217 @@
+// Battery voltage on GPIO35 through a resistor divider (`battery` feature). A reading is taken
+// every second, turned into the battery side voltage with the calibration from BATTERY.TXT,
+// smoothed, and mapped to a charge estimate along a Li-ion discharge curve. Staying under the
+// low threshold for LOW_SAMPLES readings in a row is reported once, so the loop can shut down
+// cleanly before the cell is drained.
+use esp_hal::{
+    Blocking,
+    analog::adc::{Adc, AdcConfig, AdcPin, Attenuation},
+    peripherals::{ADC1, GPIO35},
+    time::{Duration, Instant},
+};
+use log::warn;
+
+use crate::sdcard::SdStorage;
+
+const BATTERY_FILE: &str = "BATTERY.TXT";
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+// a 12 bit reading at 11 dB attenuation, nominally; the ESP32's ADC is off by up to ~10 %
+// between chips, which is what `cal` in BATTERY.TXT is for
+const RAW_MAX: f32 = 4095.0;
+const FULL_SCALE_MV: f32 = 3300.0;
+// 2x 100k, the usual divider for a single cell
+const DEFAULT_DIVIDER: f32 = 2.0;
+const DEFAULT_LOW_MV: u16 = 3400;
+// a load spike is not an empty cell
+const LOW_SAMPLES: u8 = 10;
+// weight of a new reading in the smoothed voltage
+const SMOOTHING: f32 = 0.125;
+// resting voltage of a Li-ion cell against what is left, linear in between
+const DISCHARGE_CURVE: [(u16, u8); 10] = [
+    (4200, 100),
+    (4100, 90),
+    (4000, 79),
+    (3900, 66),
+    (3800, 52),
+    (3700, 36),
+    (3600, 20),
+    (3500, 9),
+    (3400, 3),
+    (3300, 0),
+];
+
+// battery mV = raw * scale + offset_mv
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Calibration {
+    scale: f32,
+    offset_mv: f32,
+    low_mv: u16,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self::from_divider(DEFAULT_DIVIDER)
+    }
+}
+
+impl Calibration {
+    fn from_divider(divider: f32) -> Self {
+        Self { scale: FULL_SCALE_MV / RAW_MAX * divider, offset_mv: 0.0, low_mv: DEFAULT_LOW_MV }
+    }
+
+    // two raw readings against what a multimeter showed at the battery
+    fn from_points((raw_a, mv_a): (u16, u16), (raw_b, mv_b): (u16, u16)) -> Option<Self> {
+        if raw_a == raw_b {
+            return None;
+        }
+        let scale = (mv_b as f32 - mv_a as f32) / (raw_b as f32 - raw_a as f32);
+        let offset_mv = mv_a as f32 - raw_a as f32 * scale;
+        Some(Self { scale, offset_mv, low_mv: DEFAULT_LOW_MV })
+    }
+
+    fn millivolts(&self, raw: u16) -> f32 {
+        (raw as f32 * self.scale + self.offset_mv).max(0.0)
+    }
+}
+
+// BATTERY.TXT, every line optional:
+//   divider 2.0                    battery voltage over pin voltage
+//   cal <raw> <mV> <raw> <mV>      two measured points, replaces the divider
+//   low 3400                       shut down under this many mV
+pub(crate) fn load_calibration(sd: Option<&SdStorage<'_>>) -> Calibration {
+    let Some(file) = sd.and_then(|sd| sd.read_config(BATTERY_FILE).ok()) else {
+        return Calibration::default();
+    };
+    let mut calibration = Calibration::default();
+    let mut low_mv = DEFAULT_LOW_MV;
+    for line in file.lines() {
+        let mut words = line.split_whitespace();
+        let keyword = words.next();
+        // one more than any keyword takes, so extra numbers don't match
+        let numbers: heapless::Vec<f32, 5> =
+            words.map_while(|w| w.parse().ok()).take(5).collect();
+        let parsed = match (keyword, numbers.as_slice()) {
+            (Some("divider"), &[divider]) if divider > 0.0 => {
+                Some(Calibration::from_divider(divider))
+            }
+            (Some("cal"), &[raw_a, mv_a, raw_b, mv_b]) => Calibration::from_points(
+                (raw_a as u16, mv_a as u16),
+                (raw_b as u16, mv_b as u16),
+            ),
+            (Some("low"), &[mv]) => {
+                low_mv = mv as u16;
+                Some(calibration)
+            }
+            _ => None,
+        };
+        match parsed {
+            Some(parsed) => calibration = parsed,
+            None => warn!("Battery: malformed line {}", line),
+        }
+    }
+    Calibration { low_mv, ..calibration }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct BatteryView {
+    // false without the `battery` feature, the icon is hidden
+    pub(crate) present: bool,
+    pub(crate) millivolts: u16,
+    pub(crate) percent: u8,
+    pub(crate) low: bool,
+}
+
+pub(crate) enum BatteryEvent {
+    // the shown level changed
+    Level(BatteryView),
+    // under the threshold for good, once per boot
+    Low(BatteryView),
+}
+
+pub(crate) struct Battery<'d> {
+    adc: Adc<'d, ADC1<'d>, Blocking>,
+    pin: AdcPin<GPIO35<'d>, ADC1<'d>>,
+    calibration: Calibration,
+    next_sample: Instant,
+    // smoothed, on the battery side of the divider
+    millivolts: Option<f32>,
+    below_low: u8,
+    reported_low: bool,
+    shown: BatteryView,
+}
+
+impl<'d> Battery<'d> {
+    pub(crate) fn new(adc: ADC1<'d>, pin: GPIO35<'d>, calibration: Calibration) -> Self {
+        let mut config = AdcConfig::new();
+        let pin = config.enable_pin(pin, Attenuation::_11dB);
+        Self {
+            adc: Adc::new(adc, config),
+            pin,
+            calibration,
+            next_sample: Instant::now(),
+            millivolts: None,
+            below_low: 0,
+            reported_low: false,
+            shown: BatteryView::default(),
+        }
+    }
+
+    // a conversion takes a few µs, it is picked up on the pass after it was started
+    pub(crate) fn poll(&mut self) -> Option<BatteryEvent> {
+        if Instant::now() < self.next_sample {
+            return None;
+        }
+        let raw = self.adc.read_oneshot(&mut self.pin).ok()?;
+        self.next_sample = Instant::now() + SAMPLE_INTERVAL;
+
+        let sample = self.calibration.millivolts(raw);
+        let millivolts = match self.millivolts {
+            Some(smoothed) => smoothed + (sample - smoothed) * SMOOTHING,
+            None => sample,
+        };
+        self.millivolts = Some(millivolts);
+        let millivolts = millivolts as u16;
+        if millivolts < self.calibration.low_mv {
+            self.below_low = self.below_low.saturating_add(1);
+        } else {
+            self.below_low = 0;
+        }
+
+        let view = BatteryView {
+            present: true,
+            millivolts,
+            percent: percent(millivolts),
+            low: self.below_low >= LOW_SAMPLES,
+        };
+        if view.low && !self.reported_low {
+            self.reported_low = true;
+            self.shown = view;
+            return Some(BatteryEvent::Low(view));
+        }
+        // millivolts alone wander every reading, the page follows the percentage
+        if BatteryView { millivolts: self.shown.millivolts, ..view } == self.shown {
+            return None;
+        }
+        self.shown = view;
+        Some(BatteryEvent::Level(view))
+    }
+
+    pub(crate) fn view(&self) -> BatteryView {
+        self.shown
+    }
+}
+
+fn percent(millivolts: u16) -> u8 {
+    let (top, _) = DISCHARGE_CURVE[0];
+    if millivolts >= top {
+        return 100;
+    }
+    for pair in DISCHARGE_CURVE.windows(2) {
+        let ((high_mv, high_pct), (low_mv, low_pct)) = (pair[0], pair[1]);
+        if millivolts >= low_mv {
+            let span = (high_pct - low_pct) as u32 * (millivolts - low_mv) as u32;
+            return low_pct + (span / (high_mv - low_mv) as u32) as u8;
+        }
+    }
+    0
+}
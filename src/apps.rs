@@ -0,0 +1,100 @@
+// The launcher's apps. A page shows up in the launcher grid by being registered once at startup
+// with a name, a built-in icon and optionally an update function; the grid is built from the
+// registry, so adding an app takes its page in ui.slint, a Page variant and a `register` call,
+// nothing in the launcher itself. Pages are compiled into ui.slint, the Page is what has slint
+// build the component while the app is on top. The update function runs about once a second
+// while its page is shown, with the window to set the app's globals on.
+// Apps that belong to a module (or to code built on top of this firmware) are added by a
+// `Registrar` main passes in, so the module keeps its own tile. Only the component can't come
+// from outside: slint's component factories need its interpreter, which doesn't run here.
+use alloc::{rc::Rc, vec::Vec};
+use core::{cell::RefCell, time::Duration};
+use slint::{Timer, TimerMode};
+
+use crate::{
+    MainWindow,
+    navigation::{Navigator, Page},
+    now_playing,
+};
+
+const UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+pub(crate) type Registrar = fn(&mut AppRegistry);
+
+pub(crate) struct App {
+    pub(crate) name: &'static str,
+    // in assets/, the tile shows the name's first letter without one
+    pub(crate) icon: &'static str,
+    pub(crate) page: Page,
+    pub(crate) update: Option<fn(&MainWindow)>,
+}
+
+impl App {
+    pub(crate) fn new(name: &'static str, icon: &'static str, page: Page) -> Self {
+        Self { name, icon, page, update: None }
+    }
+
+    pub(crate) fn with_update(mut self, update: fn(&MainWindow)) -> Self {
+        self.update = Some(update);
+        self
+    }
+}
+
+pub(crate) struct AppRegistry {
+    apps: Vec<App>,
+}
+
+impl AppRegistry {
+    // the pages this firmware comes with in launcher order, then each registrar's
+    pub(crate) fn with_builtin(registrars: &[Registrar]) -> Self {
+        let mut registry = Self { apps: Vec::new() };
+        registry
+            .register(
+                App::new("Playing", "app-now-playing", Page::NowPlaying)
+                    .with_update(now_playing::advance),
+            )
+            .register(App::new("Agenda", "app-agenda", Page::Agenda))
+            .register(App::new("News", "app-news", Page::News))
+            .register(App::new("Chat", "app-chat", Page::Chat))
+            .register(App::new("Timers", "app-timers", Page::Timers))
+            .register(App::new("Stopwatch", "app-stopwatch", Page::Stopwatch))
+            .register(App::new("GPIO", "app-gpio", Page::Gpio))
+            .register(App::new("Outputs", "app-outputs", Page::Outputs))
+            .register(App::new("Jog", "app-jog", Page::Jog))
+            .register(App::new("Sensors", "app-sensors", Page::Sensors))
+            .register(App::new("Energy", "app-energy", Page::Energy));
+        for register in registrars {
+            register(&mut registry);
+        }
+        registry
+    }
+
+    // a page registered twice keeps its first tile
+    pub(crate) fn register(&mut self, app: App) -> &mut Self {
+        if !self.apps.iter().any(|a| a.page == app.page) {
+            self.apps.push(app);
+        }
+        self
+    }
+
+    pub(crate) fn apps(&self) -> &[App] {
+        &self.apps
+    }
+
+    // runs the update function of the app on top of the stack, if it has one
+    pub(crate) fn start_updates(
+        self: Rc<Self>,
+        navigator: Rc<RefCell<Navigator>>,
+        ui: slint::Weak<MainWindow>,
+    ) -> Timer {
+        let timer = Timer::default();
+        timer.start(TimerMode::Repeated, UPDATE_INTERVAL, move || {
+            let page = navigator.borrow().current();
+            let update = self.apps.iter().find(|a| a.page == page).and_then(|a| a.update);
+            if let (Some(update), Some(ui)) = (update, ui.upgrade()) {
+                update(&ui);
+            }
+        });
+        timer
+    }
+}
@@ -0,0 +1,117 @@
+// Images from assets/ built into the firmware compressed: build.rs stores each one RLE or LZ4
+// coded, whichever is smaller, and it is decoded into a slint image the first time it is asked
+// for. `@image-url` images in ui.slint are embedded by slint uncompressed; these cost flash at
+// their compressed size and heap only while decoded.
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use slint::{Image, Rgb8Pixel, Rgba8Pixel, SharedPixelBuffer};
+use thiserror::Error;
+
+// an RLE packet header with this bit repeats one pixel, otherwise literal pixels follow; the
+// low bits are the count minus one
+const RLE_RUN: u8 = 0x80;
+
+#[derive(Error, Debug)]
+pub(crate) enum AssetError {
+    #[error("No built-in asset named {0}")]
+    NotFound(&'static str),
+    #[error("Built-in asset {0} is corrupt")]
+    Corrupt(&'static str),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Codec {
+    Stored,
+    Rle,
+    Lz4,
+}
+
+// one image as build.rs wrote it, RGB or RGBA bytes once decoded
+struct Asset {
+    name: &'static str,
+    width: u32,
+    height: u32,
+    alpha: bool,
+    codec: Codec,
+    data: &'static [u8],
+}
+
+// ASSETS, generated from the assets/ directory
+include!(concat!(env!("OUT_DIR"), "/assets.rs"));
+
+impl Asset {
+    fn decode(&self) -> Result<Image, AssetError> {
+        if self.alpha {
+            let mut buffer = SharedPixelBuffer::<Rgba8Pixel>::new(self.width, self.height);
+            self.decode_into(buffer.make_mut_bytes(), 4)?;
+            Ok(Image::from_rgba8(buffer))
+        } else {
+            let mut buffer = SharedPixelBuffer::<Rgb8Pixel>::new(self.width, self.height);
+            self.decode_into(buffer.make_mut_bytes(), 3)?;
+            Ok(Image::from_rgb8(buffer))
+        }
+    }
+
+    fn decode_into(&self, out: &mut [u8], bytes_per_pixel: usize) -> Result<(), AssetError> {
+        let corrupt = AssetError::Corrupt(self.name);
+        match self.codec {
+            Codec::Stored if self.data.len() == out.len() => out.copy_from_slice(self.data),
+            Codec::Stored => return Err(corrupt),
+            Codec::Rle => unpack_rle(self.data, bytes_per_pixel, out).ok_or(corrupt)?,
+            Codec::Lz4 => match lz4_flex::block::decompress_into(self.data, out) {
+                Ok(len) if len == out.len() => {}
+                _ => return Err(corrupt),
+            },
+        }
+        Ok(())
+    }
+}
+
+// decoded images, shared with the UI until `release`
+pub(crate) struct Assets {
+    decoded: RefCell<Vec<(&'static str, Image)>>,
+}
+
+impl Assets {
+    pub(crate) fn new() -> Self {
+        Self { decoded: RefCell::new(Vec::new()) }
+    }
+
+    pub(crate) fn get(&self, name: &'static str) -> Result<Image, AssetError> {
+        if let Some((_, image)) = self.decoded.borrow().iter().find(|(n, _)| *n == name) {
+            return Ok(image.clone());
+        }
+        let asset = ASSETS.iter().find(|a| a.name == name).ok_or(AssetError::NotFound(name))?;
+        let image = asset.decode()?;
+        self.decoded.borrow_mut().push((asset.name, image.clone()));
+        Ok(image)
+    }
+
+    // low on heap: images only the cache holds are freed, shown ones stay until replaced
+    pub(crate) fn release(&self) {
+        self.decoded.borrow_mut().clear();
+    }
+}
+
+// None when the packets don't fill `out` exactly
+fn unpack_rle(data: &[u8], bytes_per_pixel: usize, out: &mut [u8]) -> Option<()> {
+    let (mut src, mut dst) = (0, 0);
+    while dst < out.len() {
+        let header = *data.get(src)?;
+        src += 1;
+        let len = ((header & !RLE_RUN) as usize + 1) * bytes_per_pixel;
+        let target = out.get_mut(dst..dst + len)?;
+        if header & RLE_RUN != 0 {
+            let pixel = data.get(src..src + bytes_per_pixel)?;
+            for chunk in target.chunks_exact_mut(bytes_per_pixel) {
+                chunk.copy_from_slice(pixel);
+            }
+            src += bytes_per_pixel;
+        } else {
+            target.copy_from_slice(data.get(src..src + len)?);
+            src += len;
+        }
+        dst += len;
+    }
+    (src == data.len()).then_some(())
+}
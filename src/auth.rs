@@ -0,0 +1,516 @@
+use alloc::{format, rc::Rc, string::String, vec::Vec};
+use core::{cell::RefCell, fmt::Write};
+use esp_hal::{
+    rng::Rng,
+    time::{Duration, Instant},
+};
+use hmac::{Hmac, Mac};
+use log::warn;
+use serde::{Deserialize, de::DeserializeOwned};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::{
+    clock,
+    http_client::{self, HttpClient},
+    sdcard::SdStorage,
+};
+
+pub(crate) const AUTH_FILE: &str = "AUTH.TXT";
+// device flow endpoints, served (or proxied) by the backend server
+const DEVICE_CODE_ROUTE: &str = "/oauth/device/code";
+const TOKEN_ROUTE: &str = "/oauth/token";
+const DEVICE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+const TIMEOUT_SECS: u64 = 10;
+const RETRY_SECS: u64 = 30;
+// RFC 8628: add 5 seconds to the polling interval on every slow_down
+const SLOW_DOWN_SECS: u64 = 5;
+const RENEW_MARGIN_SECS: u64 = 60;
+
+#[derive(Error, Debug)]
+pub(crate) enum AuthError {
+    #[error("Malformed auth config: {0}")]
+    Malformed(String),
+    #[error("Device not authorized yet")]
+    NotAuthorized,
+    #[error("Clock not synced, cannot sign requests")]
+    ClockNotSynced,
+    #[error("Request to {0} failed: {1}")]
+    Http(&'static str, &'static str),
+    #[error("Unexpected JSON from {0}: {1:?}")]
+    Json(&'static str, serde_json_core::de::Error),
+    #[error("Authorization refused: {0}")]
+    Refused(heapless::String<32>),
+}
+
+// what a provider gets to see of an outgoing request
+pub(crate) struct Request<'r> {
+    pub(crate) method: &'r str,
+    pub(crate) route: &'r str,
+    pub(crate) body: &'r [u8],
+}
+
+// Consulted by HttpClient and WsClient before every request. Providers append complete header
+// lines ("Name: value\r\n"), so the clients don't know or care which scheme is configured.
+pub(crate) trait AuthProvider {
+    fn authorize(&self, request: &Request<'_>, headers: &mut String) -> Result<(), AuthError>;
+}
+
+// a fixed header, `Authorization: Bearer ...` or an API key under its own name
+pub(crate) struct StaticToken {
+    header: heapless::String<32>,
+    value: String,
+}
+
+impl StaticToken {
+    pub(crate) fn bearer(token: &str) -> Self {
+        Self {
+            header: heapless::String::try_from("Authorization").unwrap(),
+            value: format!("Bearer {}", token),
+        }
+    }
+
+    pub(crate) fn header(name: &str, value: &str) -> Result<Self, AuthError> {
+        let header = heapless::String::try_from(name)
+            .map_err(|_| AuthError::Malformed(format!("header name too long: {}", name)))?;
+        Ok(Self { header, value: value.into() })
+    }
+}
+
+impl AuthProvider for StaticToken {
+    fn authorize(&self, _: &Request<'_>, headers: &mut String) -> Result<(), AuthError> {
+        write!(headers, "{}: {}\r\n", self.header, self.value).ok();
+        Ok(())
+    }
+}
+
+// Signs "<method>\n<route>\n<unix time>\n<hex sha256 of body>" with HMAC-SHA256. The server
+// recomputes it and rejects stale timestamps, so a captured request can't be replayed later.
+pub(crate) struct HmacSigner {
+    key_id: heapless::String<32>,
+    secret: Vec<u8>,
+}
+
+impl HmacSigner {
+    pub(crate) fn new(key_id: &str, secret: &[u8]) -> Result<Self, AuthError> {
+        let key_id = heapless::String::try_from(key_id)
+            .map_err(|_| AuthError::Malformed(format!("key id too long: {}", key_id)))?;
+        Ok(Self { key_id, secret: secret.into() })
+    }
+}
+
+impl AuthProvider for HmacSigner {
+    fn authorize(&self, request: &Request<'_>, headers: &mut String) -> Result<(), AuthError> {
+        let timestamp = clock::unix_now().ok_or(AuthError::ClockNotSynced)?;
+        let mut body_hash = String::with_capacity(64);
+        push_hex(&mut body_hash, &Sha256::digest(request.body));
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret)
+            .expect("HMAC takes keys of any length");
+        mac.update(request.method.as_bytes());
+        mac.update(b"\n");
+        mac.update(request.route.as_bytes());
+        mac.update(b"\n");
+        mac.update(format!("{}", timestamp).as_bytes());
+        mac.update(b"\n");
+        mac.update(body_hash.as_bytes());
+
+        write!(headers, "X-Key-Id: {}\r\nX-Timestamp: {}\r\nX-Signature: ", self.key_id, timestamp)
+            .ok();
+        push_hex(headers, &mac.finalize().into_bytes());
+        headers.push_str("\r\n");
+        Ok(())
+    }
+}
+
+// what the user has to type in on another device
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct DevicePrompt {
+    pub(crate) user_code: heapless::String<16>,
+    pub(crate) verification_uri: heapless::String<96>,
+    // the same page with the code filled in, empty when the server doesn't offer one
+    pub(crate) verification_uri_complete: heapless::String<128>,
+}
+
+impl DevicePrompt {
+    // what the QR code should open, scanning it saves typing the code
+    pub(crate) fn qr_text(&self) -> &str {
+        if self.verification_uri_complete.is_empty() {
+            &self.verification_uri
+        } else {
+            &self.verification_uri_complete
+        }
+    }
+}
+
+// long lived, stored in the settings sealed with `seal` (flash only, never on the card or in
+// diagnostics)
+pub(crate) type RefreshToken = heapless::String<255>;
+// the device's own, kept apart from the settings, see `ConfigStore`
+pub(crate) type SealKey = [u8; 32];
+// nonce and tag stored next to a sealed token
+pub(crate) const SEAL_LEN: usize = NONCE_LEN + TAG_LEN;
+const NONCE_LEN: usize = 8;
+const TAG_LEN: usize = 16;
+
+pub(crate) enum DeviceEvent {
+    // show this until authorized
+    Prompt(DevicePrompt),
+    // approved on the other device, the refresh token (if any) should be stored
+    Authorized(Option<RefreshToken>),
+    // a refresh came back with a new refresh token, the stored one is no longer valid
+    Rotated(RefreshToken),
+    // the code ran out before anyone entered it, a new one follows on the next poll
+    Expired,
+    // the refresh token was rejected, forget it; a new code follows on the next poll
+    Revoked,
+}
+
+enum DeviceState {
+    Idle {
+        next_attempt: Instant,
+    },
+    Waiting {
+        device_code: heapless::String<128>,
+        interval: Duration,
+        next_poll: Instant,
+        expires_at: Instant,
+    },
+    // renewed at `renew_at`, which is None for tokens without an expiry
+    Authorized {
+        access_token: String,
+        renew_at: Option<Instant>,
+        expires_at: Option<Instant>,
+    },
+}
+
+#[derive(Deserialize)]
+struct DeviceCodeResponse {
+    device_code: heapless::String<128>,
+    user_code: heapless::String<16>,
+    verification_uri: heapless::String<96>,
+    #[serde(default)]
+    verification_uri_complete: heapless::String<128>,
+    expires_in: u32,
+    #[serde(default = "default_interval")]
+    interval: u32,
+}
+
+fn default_interval() -> u32 {
+    5
+}
+
+// error responses come back as 400 with only `error` set
+#[derive(Deserialize)]
+struct TokenResponse {
+    #[serde(default)]
+    access_token: Option<heapless::String<1024>>,
+    #[serde(default)]
+    expires_in: Option<u32>,
+    #[serde(default)]
+    refresh_token: Option<RefreshToken>,
+    #[serde(default)]
+    error: Option<heapless::String<32>>,
+}
+
+// OAuth2 device authorization grant (RFC 8628) for units without a keyboard: the device shows a
+// short code, the user approves it on a phone, the device polls until a token comes back.
+// Requests go out without a token until then and fail with NotAuthorized. Access tokens are
+// renewed with the refresh token shortly before they expire, so the user approves only once.
+pub(crate) struct DeviceCode {
+    client_id: heapless::String<96>,
+    scope: heapless::String<128>,
+    refresh_token: RefCell<Option<RefreshToken>>,
+    state: RefCell<DeviceState>,
+}
+
+impl DeviceCode {
+    pub(crate) fn new(
+        client_id: &str,
+        scope: &str,
+        refresh_token: Option<RefreshToken>,
+    ) -> Result<Self, AuthError> {
+        let too_long = || AuthError::Malformed(format!("device {} {}", client_id, scope));
+        let now = Instant::now();
+        let state = match refresh_token {
+            // no access token yet, the first poll renews it from the stored refresh token
+            Some(_) => DeviceState::Authorized {
+                access_token: String::new(),
+                renew_at: Some(now),
+                expires_at: Some(now),
+            },
+            None => DeviceState::Idle { next_attempt: now },
+        };
+        Ok(Self {
+            client_id: heapless::String::try_from(client_id).map_err(|_| too_long())?,
+            scope: heapless::String::try_from(scope).map_err(|_| too_long())?,
+            refresh_token: RefCell::new(refresh_token),
+            state: RefCell::new(state),
+        })
+    }
+
+    // drives the flow one step per call; `http` must not carry this provider itself
+    pub(crate) fn poll(
+        &self,
+        http: &mut HttpClient<'_>,
+    ) -> Result<Option<DeviceEvent>, AuthError> {
+        let mut state = self.state.borrow_mut();
+        let now = Instant::now();
+        match &mut *state {
+            DeviceState::Idle { next_attempt } if now < *next_attempt => Ok(None),
+            DeviceState::Idle { next_attempt } => {
+                let mut body = String::from("client_id=");
+                http_client::push_form_encoded(&mut body, &self.client_id);
+                body.push_str("&scope=");
+                http_client::push_form_encoded(&mut body, &self.scope);
+                let result = post_form::<DeviceCodeResponse>(http, DEVICE_CODE_ROUTE, &body);
+                let response = match result {
+                    Ok((status, response)) if (200..300).contains(&status) => response,
+                    Ok(_) => {
+                        *next_attempt = now + Duration::from_secs(RETRY_SECS);
+                        return Err(AuthError::Http(DEVICE_CODE_ROUTE, "unexpected status"));
+                    }
+                    Err(e) => {
+                        *next_attempt = now + Duration::from_secs(RETRY_SECS);
+                        return Err(e);
+                    }
+                };
+                let prompt = DevicePrompt {
+                    user_code: response.user_code,
+                    verification_uri: response.verification_uri,
+                    verification_uri_complete: response.verification_uri_complete,
+                };
+                let interval = Duration::from_secs(response.interval.into());
+                *state = DeviceState::Waiting {
+                    device_code: response.device_code,
+                    interval,
+                    next_poll: now + interval,
+                    expires_at: now + Duration::from_secs(response.expires_in.into()),
+                };
+                Ok(Some(DeviceEvent::Prompt(prompt)))
+            }
+            DeviceState::Waiting { expires_at, .. } if now >= *expires_at => {
+                *state = DeviceState::Idle { next_attempt: now };
+                Ok(Some(DeviceEvent::Expired))
+            }
+            DeviceState::Waiting { next_poll, .. } if now < *next_poll => Ok(None),
+            DeviceState::Waiting { device_code, interval, next_poll, .. } => {
+                let mut body = format!("grant_type={}&device_code=", DEVICE_GRANT);
+                http_client::push_form_encoded(&mut body, device_code);
+                body.push_str("&client_id=");
+                http_client::push_form_encoded(&mut body, &self.client_id);
+                // a failed poll is retried on the regular interval, the code stays valid
+                let (_, response) = post_form::<TokenResponse>(http, TOKEN_ROUTE, &body)
+                    .inspect_err(|_| *next_poll = now + *interval)?;
+                match (response.access_token, response.error) {
+                    (Some(token), _) => {
+                        *state = authorized(now, &token, response.expires_in);
+                        let refresh_token = response.refresh_token;
+                        *self.refresh_token.borrow_mut() = refresh_token.clone();
+                        Ok(Some(DeviceEvent::Authorized(refresh_token)))
+                    }
+                    (None, Some(error)) if error == "authorization_pending" => {
+                        *next_poll = now + *interval;
+                        Ok(None)
+                    }
+                    (None, Some(error)) if error == "slow_down" => {
+                        *interval += Duration::from_secs(SLOW_DOWN_SECS);
+                        *next_poll = now + *interval;
+                        Ok(None)
+                    }
+                    // denied or expired on the server side, start over with a new code later
+                    (None, error) => {
+                        *state = DeviceState::Idle {
+                            next_attempt: now + Duration::from_secs(RETRY_SECS),
+                        };
+                        Err(AuthError::Refused(error.unwrap_or_default()))
+                    }
+                }
+            }
+            DeviceState::Authorized { renew_at: Some(renew_at), .. } if now >= *renew_at => {
+                let Some(refresh_token) = self.refresh_token.borrow().clone() else {
+                    // nothing to renew with, the user has to approve the device again
+                    *state = DeviceState::Idle { next_attempt: now };
+                    return Ok(Some(DeviceEvent::Expired));
+                };
+                let mut body = String::from("grant_type=refresh_token&refresh_token=");
+                http_client::push_form_encoded(&mut body, &refresh_token);
+                body.push_str("&client_id=");
+                http_client::push_form_encoded(&mut body, &self.client_id);
+                // the current token keeps working until it really expires, retry until then
+                let (_, response) = post_form::<TokenResponse>(http, TOKEN_ROUTE, &body)
+                    .inspect_err(|_| *renew_at = now + Duration::from_secs(RETRY_SECS))?;
+                let Some(token) = response.access_token else {
+                    // only invalid_grant says the token is dead, anything else may pass
+                    if response.error.as_deref() != Some("invalid_grant") {
+                        *renew_at = now + Duration::from_secs(RETRY_SECS);
+                        return Err(AuthError::Refused(response.error.unwrap_or_default()));
+                    }
+                    *self.refresh_token.borrow_mut() = None;
+                    *state = DeviceState::Idle { next_attempt: now };
+                    return Ok(Some(DeviceEvent::Revoked));
+                };
+                *state = authorized(now, &token, response.expires_in);
+                // servers that rotate refresh tokens invalidate the old one right away
+                match response.refresh_token {
+                    Some(rotated) if rotated != refresh_token => {
+                        *self.refresh_token.borrow_mut() = Some(rotated.clone());
+                        Ok(Some(DeviceEvent::Rotated(rotated)))
+                    }
+                    _ => Ok(None),
+                }
+            }
+            DeviceState::Authorized { .. } => Ok(None),
+        }
+    }
+}
+
+// renews a minute before expiry, or halfway through for short lived tokens
+fn authorized(now: Instant, access_token: &str, expires_in: Option<u32>) -> DeviceState {
+    let lifetime = expires_in.map(|secs| Duration::from_secs(secs.into()));
+    let margin = lifetime.map(|l| (l / 2).min(Duration::from_secs(RENEW_MARGIN_SECS)));
+    DeviceState::Authorized {
+        access_token: access_token.into(),
+        renew_at: lifetime.zip(margin).map(|(l, m)| now + l - m),
+        expires_at: lifetime.map(|l| now + l),
+    }
+}
+
+impl AuthProvider for DeviceCode {
+    fn authorize(&self, _: &Request<'_>, headers: &mut String) -> Result<(), AuthError> {
+        match &*self.state.borrow() {
+            DeviceState::Authorized { access_token, expires_at, .. }
+                if expires_at.is_none_or(|t| Instant::now() < t) =>
+            {
+                write!(headers, "Authorization: Bearer {}\r\n", access_token).ok();
+                Ok(())
+            }
+            _ => Err(AuthError::NotAuthorized),
+        }
+    }
+}
+
+// A refresh token encrypted under `key` as (ciphertext, nonce and tag): HMAC-SHA256 in counter
+// mode for the keystream, then over nonce and ciphertext for the tag. A copy of the settings
+// alone doesn't give the token away.
+pub(crate) fn seal(key: &SealKey, token: &RefreshToken) -> (Vec<u8>, [u8; SEAL_LEN]) {
+    let rng = Rng::new();
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce[..4].copy_from_slice(&rng.random().to_le_bytes());
+    nonce[4..].copy_from_slice(&rng.random().to_le_bytes());
+    let mut sealed = token.as_bytes().to_vec();
+    apply_keystream(key, &nonce, &mut sealed);
+    let mut seal = [0u8; SEAL_LEN];
+    seal[..NONCE_LEN].copy_from_slice(&nonce);
+    seal[NONCE_LEN..].copy_from_slice(&seal_tag(key, &nonce, &sealed));
+    (sealed, seal)
+}
+
+// None when the tag doesn't match, sealed under another key or tampered with
+pub(crate) fn open(key: &SealKey, sealed: &[u8], seal: &[u8]) -> Option<RefreshToken> {
+    if seal.len() != SEAL_LEN {
+        return None;
+    }
+    let (nonce, tag) = seal.split_at(NONCE_LEN);
+    let expected = seal_tag(key, nonce, sealed);
+    if expected.iter().zip(tag).fold(0, |acc, (a, b)| acc | (a ^ b)) != 0 {
+        return None;
+    }
+    let mut token = sealed.to_vec();
+    apply_keystream(key, nonce, &mut token);
+    core::str::from_utf8(&token).ok().and_then(|token| heapless::String::try_from(token).ok())
+}
+
+fn apply_keystream(key: &SealKey, nonce: &[u8], data: &mut [u8]) {
+    for (counter, chunk) in data.chunks_mut(32).enumerate() {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+        mac.update(b"stream");
+        mac.update(nonce);
+        mac.update(&(counter as u32).to_le_bytes());
+        for (byte, key_byte) in chunk.iter_mut().zip(mac.finalize().into_bytes()) {
+            *byte ^= key_byte;
+        }
+    }
+}
+
+fn seal_tag(key: &SealKey, nonce: &[u8], sealed: &[u8]) -> [u8; TAG_LEN] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(b"tag");
+    mac.update(nonce);
+    mac.update(sealed);
+    let mut tag = [0u8; TAG_LEN];
+    tag.copy_from_slice(&mac.finalize().into_bytes()[..TAG_LEN]);
+    tag
+}
+
+fn post_form<T: DeserializeOwned>(
+    http: &mut HttpClient<'_>,
+    route: &'static str,
+    body: &str,
+) -> Result<(u16, T), AuthError> {
+    let raw = http
+        .post_form(route, body, TIMEOUT_SECS)
+        .map_err(|e| AuthError::Http(route, e))?;
+    let (status, body) = http_client::parse_response(&raw)
+        .ok_or(AuthError::Http(route, "malformed response"))?;
+    // tokens and URIs may come with escaped slashes
+    let mut unescaped = [0u8; 1024];
+    let (value, _) = serde_json_core::from_slice_escaped::<T>(body.as_bytes(), &mut unescaped)
+        .map_err(|e| AuthError::Json(route, e))?;
+    Ok((status, value))
+}
+
+// the configured scheme; `device` is set for the device flow, which main has to keep polling
+// and whose refresh tokens it has to store
+pub(crate) struct Auth {
+    pub(crate) provider: Rc<dyn AuthProvider>,
+    pub(crate) device: Option<Rc<DeviceCode>>,
+}
+
+impl Auth {
+    // bearer <token> | header <name> <value> | hmac <key id> <secret> | device <client id> [scope]
+    pub(crate) fn parse(
+        line: &str,
+        refresh_token: Option<RefreshToken>,
+    ) -> Result<Self, AuthError> {
+        let malformed = || AuthError::Malformed(line.into());
+        let (scheme, rest) = line.split_once(' ').ok_or_else(malformed)?;
+        let rest = rest.trim();
+        let provider: Rc<dyn AuthProvider> = match scheme {
+            "bearer" => Rc::new(StaticToken::bearer(rest)),
+            "header" => {
+                let (name, value) = rest.split_once(' ').ok_or_else(malformed)?;
+                Rc::new(StaticToken::header(name, value.trim())?)
+            }
+            "hmac" => {
+                let (key_id, secret) = rest.split_once(' ').ok_or_else(malformed)?;
+                Rc::new(HmacSigner::new(key_id, secret.trim().as_bytes())?)
+            }
+            "device" => {
+                let (client_id, scope) = rest.split_once(' ').unwrap_or((rest, ""));
+                let device = Rc::new(DeviceCode::new(client_id, scope.trim(), refresh_token)?);
+                return Ok(Self { provider: device.clone(), device: Some(device) });
+            }
+            _ => return Err(malformed()),
+        };
+        Ok(Self { provider, device: None })
+    }
+}
+
+// the first non-comment line picks the scheme, no file means requests go out unauthenticated.
+// only the line is read here, the card stays on the UI core while the clients live on the other
+pub(crate) fn read_from_sd(sd: &SdStorage<'_>) -> Option<String> {
+    sd.read_config(AUTH_FILE).ok()?.lines().next().map(String::from)
+}
+
+pub(crate) fn load(line: &str, refresh_token: Option<RefreshToken>) -> Option<Auth> {
+    Auth::parse(line, refresh_token)
+        .inspect_err(|e| warn!("Auth: {}", e))
+        .ok()
+}
+
+fn push_hex(out: &mut String, bytes: &[u8]) {
+    for byte in bytes {
+        write!(out, "{:02x}", byte).ok();
+    }
+}
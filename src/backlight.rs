@@ -0,0 +1,91 @@
+use esp_hal::{
+    gpio::{DriveMode, interconnect::PeripheralOutput},
+    ledc::{
+        LSGlobalClkSource, Ledc, LowSpeed,
+        channel::{self, Channel, ChannelIFace},
+        timer::{self, Timer, TimerIFace},
+    },
+    time::Rate,
+};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub(crate) enum BacklightError {
+    #[error("Failed to configure LEDC timer")]
+    Timer,
+    #[error("Failed to configure LEDC channel")]
+    Channel,
+}
+
+// 24 kHz keeps the PWM out of audible range and away from visible flicker on camera
+pub(crate) fn init_timer<'a>(
+    ledc: &mut Ledc<'a>,
+    number: timer::Number,
+) -> Result<Timer<'a, LowSpeed>, BacklightError> {
+    ledc.set_global_slow_clock(LSGlobalClkSource::APBClk);
+    let mut pwm_timer = ledc.timer::<LowSpeed>(number);
+    pwm_timer
+        .configure(timer::config::Config {
+            duty: timer::config::Duty::Duty10Bit,
+            clock_source: timer::LSClockSource::APBClk,
+            frequency: Rate::from_khz(24),
+        })
+        .map_err(|_| BacklightError::Timer)?;
+    Ok(pwm_timer)
+}
+
+pub(crate) struct Backlight<'a> {
+    channel: Channel<'a, LowSpeed>,
+    brightness: u8,
+}
+
+impl<'a> Backlight<'a> {
+    // starts dark, callers fade in once the first frame is on the panel
+    pub(crate) fn new(
+        ledc: &Ledc<'a>,
+        pwm_timer: &'a Timer<'a, LowSpeed>,
+        number: channel::Number,
+        led_pin: impl PeripheralOutput<'a>,
+    ) -> Result<Self, BacklightError> {
+        let mut channel = ledc.channel(number, led_pin);
+        channel
+            .configure(channel::config::Config {
+                timer: pwm_timer,
+                duty_pct: 0,
+                drive_mode: DriveMode::PushPull,
+            })
+            .map_err(|_| BacklightError::Channel)?;
+
+        Ok(Self { channel, brightness: 0 })
+    }
+
+    pub(crate) fn brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    pub(crate) fn set_brightness(&mut self, percent: u8) -> Result<(), BacklightError> {
+        let percent = percent.min(100);
+        self.channel
+            .set_duty(percent)
+            .map_err(|_| BacklightError::Channel)?;
+        self.brightness = percent;
+        Ok(())
+    }
+
+    // the fade runs in hardware, this returns immediately
+    pub(crate) fn fade_to(&mut self, percent: u8, duration_ms: u16) -> Result<(), BacklightError> {
+        let percent = percent.min(100);
+        if percent == self.brightness {
+            return Ok(());
+        }
+        if duration_ms == 0 {
+            return self.set_brightness(percent);
+        }
+
+        self.channel
+            .start_duty_fade(self.brightness, percent, duration_ms)
+            .map_err(|_| BacklightError::Channel)?;
+        self.brightness = percent;
+        Ok(())
+    }
+}
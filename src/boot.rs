@@ -0,0 +1,61 @@
+// Staged start-up: the display and touch come up first and show the loading page, the card,
+// the radio and the peripherals follow behind it. Each milestone is logged with the time since
+// reset, the whole timeline goes to the log ring once the UI is ready.
+use alloc::{format, string::String, vec::Vec};
+use esp_hal::time::Instant;
+use log::{info, warn};
+
+// stages that run behind the loading page
+pub(crate) const STAGES: u8 = 3;
+// reset to the loading page, past this the display stage needs looking at
+const FIRST_FRAME_BUDGET_MS: u64 = 500;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum BootState {
+    // what is being brought up and how many stages are behind it
+    Loading { stage: &'static str, done: u8 },
+    Ready,
+}
+
+impl BootState {
+    pub(crate) const STARTING: Self = BootState::Loading { stage: "Starting", done: 0 };
+}
+
+pub(crate) struct BootTimeline {
+    last: Instant,
+    // name and ms since reset
+    milestones: Vec<(&'static str, u64)>,
+}
+
+impl BootTimeline {
+    pub(crate) fn new() -> Self {
+        Self { last: Instant::now(), milestones: Vec::new() }
+    }
+
+    pub(crate) fn mark(&mut self, milestone: &'static str) {
+        let at = Instant::now().duration_since_epoch().as_millis();
+        let took = self.last.elapsed().as_millis();
+        info!("Boot: {} at {} ms (+{} ms)", milestone, at, took);
+        self.last = Instant::now();
+        self.milestones.push((milestone, at));
+    }
+
+    // the loading page is on the panel
+    pub(crate) fn first_frame(&mut self) {
+        self.mark("first frame");
+        let at = Instant::now().duration_since_epoch().as_millis();
+        if at > FIRST_FRAME_BUDGET_MS {
+            warn!("Boot: first frame later than {} ms", FIRST_FRAME_BUDGET_MS);
+        }
+    }
+
+    // "Boot: display 180 ms, first frame 230 ms, ..."
+    pub(crate) fn summary(&self) -> String {
+        let milestones: Vec<String> = self
+            .milestones
+            .iter()
+            .map(|(milestone, at)| format!("{} {} ms", milestone, at))
+            .collect();
+        format!("Boot: {}", milestones.join(", "))
+    }
+}
@@ -0,0 +1,259 @@
+// A logo for branded units, drawn straight to the panel from the assets partition as soon as
+// the display is up, before slint draws its first frame; the loading screen follows once it
+// has been up for HOLD_MS. LOGO.BMP (RGB565 bitfields or 24 bit, up to the landscape screen)
+// or LOGO.RAW (headerless little endian RGB565, exactly the landscape screen) on the card is
+// converted into the partition at boot and removed from the card, it shows from the next boot
+// on. In the partition: "LOGO", u16 width, u16 height, u8 bottom-up, 3 reserved, then the
+// rows as RGB565 in the order the file had them.
+use alloc::{vec, vec::Vec};
+use slint::platform::software_renderer::Rgb565Pixel;
+use thiserror::Error;
+
+use crate::{
+    config::{ConfigError, ConfigStore},
+    display_screen::{PANEL_HEIGHT, PANEL_WIDTH},
+    sdcard::{DirEntryInfo, SdCardError, SdStorage},
+};
+
+pub(crate) const HOLD_MS: u64 = 1500;
+const BMP_FILE: &str = "LOGO.BMP";
+const RAW_FILE: &str = "LOGO.RAW";
+const MAGIC: &[u8; 4] = b"LOGO";
+const HEADER_LEN: usize = 12;
+// the flash erase size, the partition is written a sector at a time
+const SECTOR: usize = 4096;
+// everything up to the pixels of any BMP we take
+const BMP_HEADER_MAX: usize = 138;
+
+#[derive(Error, Debug)]
+pub(crate) enum BootLogoError {
+    #[error("Flash: {0}")]
+    Flash(#[from] ConfigError),
+    #[error("Card: {0}")]
+    Card(#[from] SdCardError),
+    #[error("{0}")]
+    Unsupported(&'static str),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Logo {
+    width: usize,
+    height: usize,
+    bottom_up: bool,
+}
+
+impl Logo {
+    // the one in the assets partition, if any
+    pub(crate) fn read(config: &mut ConfigStore<'_>) -> Option<Self> {
+        let mut header = [0u8; HEADER_LEN];
+        config.read_assets(0, &mut header).ok()?;
+        if &header[..4] != MAGIC {
+            return None;
+        }
+        let width = u16::from_le_bytes([header[4], header[5]]) as usize;
+        let height = u16::from_le_bytes([header[6], header[7]]) as usize;
+        let fits = (1..=PANEL_WIDTH as usize).contains(&width)
+            && (1..=PANEL_HEIGHT as usize).contains(&height);
+        fits.then_some(Self { width, height, bottom_up: header[8] != 0 })
+    }
+
+    pub(crate) fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    // row `y` from the top into `line`, `width` pixels long
+    pub(crate) fn row(
+        &self,
+        config: &mut ConfigStore<'_>,
+        y: usize,
+        line: &mut [Rgb565Pixel],
+    ) -> bool {
+        let stored = if self.bottom_up { self.height - 1 - y } else { y };
+        let mut bytes = [0u8; PANEL_WIDTH as usize * 2];
+        let bytes = &mut bytes[..self.width * 2];
+        let offset = HEADER_LEN + stored * self.width * 2;
+        if config.read_assets(offset as u32, bytes).is_err() {
+            return false;
+        }
+        for (pixel, b) in line.iter_mut().zip(bytes.chunks_exact(2)) {
+            *pixel = Rgb565Pixel(u16::from_le_bytes([b[0], b[1]]));
+        }
+        true
+    }
+}
+
+// where the pixels are in a logo file and how they are stored
+struct Layout {
+    width: usize,
+    height: usize,
+    bottom_up: bool,
+    data_offset: usize,
+    bytes_per_pixel: usize,
+    stride: usize,
+}
+
+// a logo file on the card goes into the partition; true when there was one
+pub(crate) fn install(
+    sd: &SdStorage<'_>,
+    config: &mut ConfigStore<'_>,
+) -> Result<bool, BootLogoError> {
+    let files = sd.list_dir("")?;
+    let logo = |f: &&DirEntryInfo| !f.is_dir && (f.name == BMP_FILE || f.name == RAW_FILE);
+    let Some(file) = files.iter().find(logo) else {
+        return Ok(false);
+    };
+    let layout = if file.name == RAW_FILE {
+        raw_layout(file.size as usize)?
+    } else {
+        let mut header = [0u8; BMP_HEADER_MAX];
+        let len = sd.read_file(BMP_FILE, &mut header)?;
+        bmp_layout(&header[..len])?
+    };
+
+    // a logo half overwritten must not be shown, the header goes last
+    config.write_assets(0, &[0; 4])?;
+    let mut writer = SectorWriter::new();
+    let mut row = Vec::with_capacity(layout.stride);
+    let mut position = 0;
+    let mut result = Ok(());
+    let mut buf = [0u8; 512];
+    sd.read_chunks(&file.name, &mut buf, |mut chunk| {
+        let skip = layout.data_offset.saturating_sub(position).min(chunk.len());
+        position += chunk.len();
+        chunk = &chunk[skip..];
+        while !chunk.is_empty() && result.is_ok() && writer.rows < layout.height {
+            let take = (layout.stride - row.len()).min(chunk.len());
+            row.extend_from_slice(&chunk[..take]);
+            chunk = &chunk[take..];
+            if row.len() == layout.stride {
+                result = writer.row(config, &row, &layout);
+                row.clear();
+            }
+        }
+    })?;
+    result?;
+    if writer.rows < layout.height {
+        return Err(BootLogoError::Unsupported("logo file is cut short"));
+    }
+    writer.finish(config, &layout)?;
+    sd.delete(&file.name)?;
+    Ok(true)
+}
+
+fn raw_layout(size: usize) -> Result<Layout, BootLogoError> {
+    let (width, height) = (PANEL_WIDTH as usize, PANEL_HEIGHT as usize);
+    if size != width * height * 2 {
+        return Err(BootLogoError::Unsupported("LOGO.RAW has to be exactly the screen's size"));
+    }
+    let stride = width * 2;
+    Ok(Layout { width, height, bottom_up: false, data_offset: 0, bytes_per_pixel: 2, stride })
+}
+
+fn bmp_layout(header: &[u8]) -> Result<Layout, BootLogoError> {
+    let malformed = BootLogoError::Unsupported("malformed BMP");
+    let u16_at = |i: usize| header.get(i..i + 2).map(|b| u16::from_le_bytes([b[0], b[1]]));
+    let u32_at =
+        |i: usize| header.get(i..i + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+    if !header.starts_with(b"BM") {
+        return Err(malformed);
+    }
+    let (Some(data_offset), Some(width), Some(height), Some(bpp), Some(compression)) =
+        (u32_at(10), u32_at(18), u32_at(22), u16_at(28), u32_at(30))
+    else {
+        return Err(malformed);
+    };
+    let bytes_per_pixel = match (bpp, compression) {
+        (16, 3) => 2,
+        (24, 0) => 3,
+        _ => return Err(BootLogoError::Unsupported("BMP has to be 16 bit 565 or 24 bit")),
+    };
+    let (width, height) = (width as i32, height as i32);
+    let bottom_up = height > 0;
+    let (width, height) = (width.unsigned_abs() as usize, height.unsigned_abs() as usize);
+    if width == 0 || height == 0 {
+        return Err(malformed);
+    }
+    if width > PANEL_WIDTH as usize || height > PANEL_HEIGHT as usize {
+        return Err(BootLogoError::Unsupported("logo is larger than the screen"));
+    }
+    let stride = (width * bytes_per_pixel).div_ceil(4) * 4;
+    let data_offset = data_offset as usize;
+    Ok(Layout { width, height, bottom_up, data_offset, bytes_per_pixel, stride })
+}
+
+// converted rows collected into whole sectors; the first sector, holding the header, is kept
+// back until everything else is written
+struct SectorWriter {
+    first: Vec<u8>,
+    sector: Vec<u8>,
+    // where `sector` goes in the partition
+    offset: usize,
+    rows: usize,
+}
+
+impl SectorWriter {
+    fn new() -> Self {
+        Self { first: Vec::new(), sector: vec![0; HEADER_LEN], offset: 0, rows: 0 }
+    }
+
+    fn row(
+        &mut self,
+        config: &mut ConfigStore<'_>,
+        row: &[u8],
+        layout: &Layout,
+    ) -> Result<(), BootLogoError> {
+        let pixels = &row[..layout.width * layout.bytes_per_pixel];
+        for pixel in pixels.chunks_exact(layout.bytes_per_pixel) {
+            let rgb565 = match *pixel {
+                [low, high] => u16::from_le_bytes([low, high]),
+                // BGR byte order
+                [b, g, r] => ((r as u16 >> 3) << 11) | ((g as u16 >> 2) << 5) | (b as u16 >> 3),
+                _ => 0,
+            };
+            self.sector.extend_from_slice(&rgb565.to_le_bytes());
+            if self.sector.len() == SECTOR {
+                self.flush(config)?;
+            }
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn flush(&mut self, config: &mut ConfigStore<'_>) -> Result<(), BootLogoError> {
+        if self.offset == 0 {
+            self.first = core::mem::take(&mut self.sector);
+        } else {
+            config.write_assets(self.offset as u32, &self.sector)?;
+            self.sector.clear();
+        }
+        self.offset += SECTOR;
+        Ok(())
+    }
+
+    fn finish(
+        mut self,
+        config: &mut ConfigStore<'_>,
+        layout: &Layout,
+    ) -> Result<(), BootLogoError> {
+        if !self.sector.is_empty() {
+            self.flush(config)?;
+        }
+        let [width_low, width_high] = (layout.width as u16).to_le_bytes();
+        let [height_low, height_high] = (layout.height as u16).to_le_bytes();
+        self.first[..HEADER_LEN].copy_from_slice(&[
+            MAGIC[0],
+            MAGIC[1],
+            MAGIC[2],
+            MAGIC[3],
+            width_low,
+            width_high,
+            height_low,
+            height_high,
+            layout.bottom_up as u8,
+            0,
+            0,
+            0,
+        ]);
+        Ok(config.write_assets(0, &self.first)?)
+    }
+}
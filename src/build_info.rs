@@ -0,0 +1,23 @@
+use alloc::{format, string::String};
+
+// injected by build.rs
+pub(crate) const GIT_HASH: &str = env!("BUILD_GIT_HASH");
+pub(crate) const BUILD_TIME: &str = env!("BUILD_TIME");
+pub(crate) const RUSTC_VERSION: &str = env!("BUILD_RUSTC_VERSION");
+pub(crate) const ESP_HAL_VERSION: &str = env!("BUILD_ESP_HAL_VERSION");
+pub(crate) const SLINT_VERSION: &str = env!("BUILD_SLINT_VERSION");
+pub(crate) const PKG_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// one line for logs and the boot banner
+pub(crate) fn summary() -> String {
+    format!("{} ({}, built {})", PKG_VERSION, GIT_HASH, BUILD_TIME)
+}
+
+// shared by the diagnostics bundle and anything else reporting the build over the network
+pub(crate) fn to_json() -> String {
+    format!(
+        "{{\"version\":\"{}\",\"git_hash\":\"{}\",\"build_time\":\"{}\",\"rustc\":\"{}\",\
+        \"esp_hal\":\"{}\",\"slint\":\"{}\"}}",
+        PKG_VERSION, GIT_HASH, BUILD_TIME, RUSTC_VERSION, ESP_HAL_VERSION, SLINT_VERSION
+    )
+}
@@ -0,0 +1,192 @@
+use esp_hal::{
+    delay::Delay,
+    gpio::{DriveMode, interconnect::PeripheralOutput},
+    ledc::{
+        Ledc, LowSpeed,
+        channel::{self, Channel, ChannelIFace},
+        timer::{self, Timer, TimerIFace},
+    },
+    peripherals::LEDC,
+    time::{Duration, Instant, Rate},
+};
+use thiserror::Error;
+
+// on/off times in ms, starting with on
+pub(crate) const ALARM: &[u16] = &[120, 80, 120, 80, 120, 700];
+pub(crate) const ALARM_REPEATS: u8 = 30;
+// the piezo's resonance, patterns always sound here
+const PATTERN_HZ: u32 = 2700;
+// what the divider reaches with a 10 bit duty, and what a piezo still makes audible
+const MIN_TONE_HZ: u32 = 100;
+const MAX_TONE_HZ: u32 = 10_000;
+// longest tone the UI may ask for
+const MAX_TONE_MS: u32 = 5000;
+// short and high, closer to a tick than a beep
+const CLICK_HZ: u32 = 4000;
+const CLICK_US: u32 = 4000;
+
+#[derive(Error, Debug)]
+pub(crate) enum BuzzerError {
+    #[error("Failed to configure LEDC timer")]
+    Timer,
+    #[error("Failed to configure LEDC channel")]
+    Channel,
+}
+
+// passive piezo, loudest around its resonance; the global clock source is set by the backlight
+pub(crate) fn init_timer<'a>(
+    ledc: &Ledc<'a>,
+    number: timer::Number,
+) -> Result<Timer<'a, LowSpeed>, BuzzerError> {
+    let mut pwm_timer = ledc.timer::<LowSpeed>(number);
+    pwm_timer
+        .configure(timer::config::Config {
+            duty: timer::config::Duty::Duty10Bit,
+            clock_source: timer::LSClockSource::APBClk,
+            frequency: Rate::from_hz(PATTERN_HZ),
+        })
+        .map_err(|_| BuzzerError::Timer)?;
+    Ok(pwm_timer)
+}
+
+// plays patterns and tones without blocking, `poll` has to run every loop iteration. Alarm
+// patterns win: tones and clicks are dropped while one plays.
+pub(crate) struct Buzzer<'a> {
+    channel: Channel<'a, LowSpeed>,
+    timer_number: timer::Number,
+    pattern: &'static [u16],
+    step: usize,
+    step_started: Instant,
+    repeats_left: u8,
+    // when the running tone ends
+    tone_until: Option<Instant>,
+    muted: bool,
+}
+
+impl<'a> Buzzer<'a> {
+    pub(crate) fn new(
+        ledc: &Ledc<'a>,
+        pwm_timer: &'a Timer<'a, LowSpeed>,
+        timer_number: timer::Number,
+        number: channel::Number,
+        pin: impl PeripheralOutput<'a>,
+    ) -> Result<Self, BuzzerError> {
+        let mut channel = ledc.channel(number, pin);
+        channel
+            .configure(channel::config::Config {
+                timer: pwm_timer,
+                duty_pct: 0,
+                drive_mode: DriveMode::PushPull,
+            })
+            .map_err(|_| BuzzerError::Channel)?;
+
+        Ok(Self {
+            channel,
+            timer_number,
+            pattern: &[],
+            step: 0,
+            step_started: Instant::now(),
+            repeats_left: 0,
+            tone_until: None,
+            muted: false,
+        })
+    }
+
+    // nothing sounds while muted, a running pattern or tone stops
+    pub(crate) fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+        if muted {
+            self.end_tone();
+            self.stop();
+        }
+    }
+
+    pub(crate) fn play(&mut self, pattern: &'static [u16], repeats: u8) {
+        if self.muted {
+            return;
+        }
+        self.end_tone();
+        self.pattern = pattern;
+        self.repeats_left = repeats;
+        self.enter_step(0);
+    }
+
+    pub(crate) fn stop(&mut self) {
+        self.pattern = &[];
+        self.channel.set_duty(0).ok();
+    }
+
+    pub(crate) fn is_playing(&self) -> bool {
+        !self.pattern.is_empty()
+    }
+
+    // a tone from the UI, clamped to what the piezo and the divider can do; replaces a
+    // running one
+    pub(crate) fn tone(&mut self, hz: u32, ms: u32) {
+        if self.is_playing() || self.muted {
+            return;
+        }
+        self.set_frequency(hz);
+        self.channel.set_duty(50).ok();
+        let ms = ms.min(MAX_TONE_MS) as u64;
+        self.tone_until = Some(Instant::now() + Duration::from_millis(ms));
+    }
+
+    // touch feedback; too short for the loop to time, so it blocks for the few ms it sounds
+    pub(crate) fn click(&mut self) {
+        if self.is_playing() || self.tone_until.is_some() || self.muted {
+            return;
+        }
+        self.set_frequency(CLICK_HZ);
+        self.channel.set_duty(50).ok();
+        Delay::new().delay_micros(CLICK_US);
+        self.channel.set_duty(0).ok();
+        self.set_frequency(PATTERN_HZ);
+    }
+
+    pub(crate) fn poll(&mut self) {
+        if self.tone_until.is_some_and(|until| Instant::now() >= until) {
+            self.end_tone();
+        }
+        let Some(&ms) = self.pattern.get(self.step) else {
+            return;
+        };
+        if self.step_started.elapsed() < Duration::from_millis(ms as u64) {
+            return;
+        }
+
+        if self.step + 1 < self.pattern.len() {
+            self.enter_step(self.step + 1);
+        } else if self.repeats_left > 1 {
+            self.repeats_left -= 1;
+            self.enter_step(0);
+        } else {
+            self.stop();
+        }
+    }
+
+    fn end_tone(&mut self) {
+        if self.tone_until.take().is_some() {
+            self.channel.set_duty(0).ok();
+            self.set_frequency(PATTERN_HZ);
+        }
+    }
+
+    // The HAL only sets a frequency while configuring the timer, which the channel borrows for
+    // good; the divider is rewritten in place instead. APB clock, 10 bit duty:
+    // hz = 80 MHz / (divider / 256) / 1024
+    fn set_frequency(&mut self, hz: u32) {
+        let divider = 20_000_000 / hz.clamp(MIN_TONE_HZ, MAX_TONE_HZ);
+        let timer = LEDC::regs().lstimer(self.timer_number as usize);
+        timer.conf().modify(|_, w| unsafe { w.div_num().bits(divider) });
+        // latches the new divider at the next period
+        timer.conf().modify(|_, w| w.para_up().set_bit());
+    }
+
+    // even steps sound, odd ones are pauses
+    fn enter_step(&mut self, step: usize) {
+        self.step = step;
+        self.step_started = Instant::now();
+        self.channel.set_duty(if step % 2 == 0 { 50 } else { 0 }).ok();
+    }
+}
@@ -0,0 +1,494 @@
+use alloc::{format, string::String, vec::Vec};
+use log::warn;
+
+use crate::{
+    clock,
+    data_binding::{DataBindingError, Source},
+    http_client::{HttpClient, JsonError, Method},
+    sdcard::SdStorage,
+};
+
+const CALENDAR_FILE: &str = "CALENDAR.TXT";
+pub(crate) const REFRESH_SECS: u64 = 15 * 60;
+// recurring events are expanded from today over this many days
+const WINDOW_DAYS: i64 = 14;
+pub(crate) const MAX_EVENTS: usize = 16;
+// bounds the work per recurring event; rules without COUNT skip ahead instead of stepping
+const MAX_STEPS: u32 = 1000;
+// moved or edited instances of recurring events, each hides one generated occurrence
+const MAX_OVERRIDES: usize = 32;
+const MAX_EXDATES: usize = 8;
+const TIMEOUT_SECS: u64 = 15;
+// the most of an .ics document held while it is parsed
+const MAX_BODY: usize = 64 * 1024;
+const DAY_SECS: i64 = 86_400;
+const DAY_CODES: [&str; 7] = ["MO", "TU", "WE", "TH", "FR", "SA", "SU"];
+const DAY_NAMES: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Event {
+    // local time, seconds since 1970-01-01 00:00
+    pub(crate) start: i64,
+    pub(crate) end: i64,
+    pub(crate) all_day: bool,
+    pub(crate) summary: heapless::String<64>,
+    pub(crate) location: heapless::String<48>,
+}
+
+// upcoming events, earliest first
+pub(crate) type Agenda = heapless::Vec<Event, MAX_EVENTS>;
+
+impl Event {
+    // "Today 14:30", "Tomorrow all day", "Thu 16.05 09:00"; ongoing events count as today
+    pub(crate) fn when(&self, now: i64) -> String {
+        let today = now.div_euclid(DAY_SECS);
+        let day = self.start.div_euclid(DAY_SECS).max(today);
+        let date = clock::civil((day * DAY_SECS).max(0) as u64);
+        let mut label = match day - today {
+            0 => String::from("Today"),
+            1 => String::from("Tomorrow"),
+            _ => format!("{} {:02}.{:02}", DAY_NAMES[date.weekday as usize], date.day, date.month),
+        };
+        if self.all_day {
+            label.push_str(" all day");
+        } else {
+            let time = self.start.rem_euclid(DAY_SECS);
+            label.push_str(&format!(" {:02}:{:02}", time / 3600, time / 60 % 60));
+        }
+        label
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Freq {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+// the RRULE subset wall calendars actually produce; anything else shows the first occurrence
+struct Rule {
+    freq: Freq,
+    interval: i64,
+    count: Option<u32>,
+    until: Option<i64>,
+    // bit 0 = Monday
+    by_day: u8,
+}
+
+impl Rule {
+    fn parse(value: &str, offset: i64) -> Option<Self> {
+        let mut freq = None;
+        let mut rule = Rule { freq: Freq::Daily, interval: 1, count: None, until: None, by_day: 0 };
+        for part in value.split(';') {
+            let (key, value) = part.split_once('=')?;
+            match key {
+                "FREQ" => {
+                    freq = Some(match value {
+                        "DAILY" => Freq::Daily,
+                        "WEEKLY" => Freq::Weekly,
+                        "MONTHLY" => Freq::Monthly,
+                        "YEARLY" => Freq::Yearly,
+                        _ => return None,
+                    })
+                }
+                "INTERVAL" => rule.interval = value.parse().ok().filter(|i| *i > 0)?,
+                "COUNT" => rule.count = Some(value.parse().ok()?),
+                "UNTIL" => {
+                    // a date-only UNTIL includes that whole day
+                    let (until, all_day) = parse_time(value, offset)?;
+                    rule.until = Some(if all_day { until + DAY_SECS - 1 } else { until });
+                }
+                // ordinals such as 2TU fail the lookup, they only make sense monthly
+                "BYDAY" => {
+                    for day in value.split(',') {
+                        rule.by_day |= 1 << DAY_CODES.iter().position(|code| *code == day)?;
+                    }
+                }
+                "WKST" => {}
+                _ => return None,
+            }
+        }
+        rule.freq = freq?;
+        let by_day_ok = matches!(rule.freq, Freq::Daily | Freq::Weekly) || rule.by_day == 0;
+        by_day_ok.then_some(rule)
+    }
+
+    // calls `f` with every occurrence start from about `from` on, in order, until it says stop
+    fn expand(&self, start: i64, from: i64, mut f: impl FnMut(i64) -> bool) {
+        let (first_day, time) = (start.div_euclid(DAY_SECS), start.rem_euclid(DAY_SECS));
+        let mut emitted = 0;
+        let mut emit = |day: i64| {
+            let at = day * DAY_SECS + time;
+            let counted_out = self.count.is_some_and(|count| emitted >= count);
+            if counted_out || self.until.is_some_and(|until| at > until) {
+                return false;
+            }
+            emitted += 1;
+            f(at)
+        };
+        // nothing depends on skipped occurrences unless they are counted
+        let skip_days = if self.count.is_none() { (from - start).max(0) / DAY_SECS } else { 0 };
+
+        match self.freq {
+            Freq::Daily | Freq::Weekly => {
+                let weekly = self.freq == Freq::Weekly;
+                let period = if weekly { 7 * self.interval } else { self.interval };
+                // weekly intervals count calendar weeks, starting on Monday
+                let base = if weekly { first_day - weekday(first_day) as i64 } else { first_day };
+                let by_day = match self.by_day {
+                    0 if weekly => 1 << weekday(first_day),
+                    by_day => by_day,
+                };
+                let skipped_periods = (first_day + skip_days - base) / period;
+                let mut day = (base + skipped_periods * period).max(first_day);
+                for _ in 0..MAX_STEPS {
+                    let on_day = by_day == 0 || by_day & (1 << weekday(day)) != 0;
+                    let in_period = if weekly {
+                        (day - base) / 7 % self.interval == 0
+                    } else {
+                        (day - base) % period == 0
+                    };
+                    if (day == first_day || (on_day && in_period)) && !emit(day) {
+                        return;
+                    }
+                    day += 1;
+                }
+            }
+            Freq::Monthly | Freq::Yearly => {
+                let date = clock::civil((first_day * DAY_SECS).max(0) as u64);
+                let step = match self.freq {
+                    Freq::Monthly => self.interval,
+                    _ => 12 * self.interval,
+                };
+                // no month is longer than 31 days, so this never skips past `from`
+                let mut n = skip_days / 31 / step;
+                for _ in 0..MAX_STEPS {
+                    let months = date.month as i64 - 1 + n * step;
+                    let year = date.year as i64 + months.div_euclid(12);
+                    let month = months.rem_euclid(12) as u8 + 1;
+                    // the 31st or Feb 29 are skipped in months without them
+                    if date.day <= days_in_month(year, month)
+                        && !emit(clock::days_from_civil(year, month, date.day))
+                    {
+                        return;
+                    }
+                    n += 1;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Component {
+    uid: u32,
+    // local time and whether it is a date without a time
+    start: Option<(i64, bool)>,
+    end: Option<i64>,
+    duration: Option<i64>,
+    rule: Option<Rule>,
+    exdates: heapless::Vec<i64, MAX_EXDATES>,
+    recurrence_id: Option<i64>,
+    cancelled: bool,
+    summary: heapless::String<64>,
+    location: heapless::String<48>,
+}
+
+impl Component {
+    fn set(&mut self, name: &str, value: &str, offset: i64) {
+        match name {
+            "UID" => self.uid = hash(value),
+            "SUMMARY" => unescape_into(&mut self.summary, value),
+            "LOCATION" => unescape_into(&mut self.location, value),
+            "DTSTART" => self.start = parse_time(value, offset),
+            "DTEND" => self.end = parse_time(value, offset).map(|(end, _)| end),
+            "DURATION" => self.duration = parse_duration(value),
+            "RRULE" => self.rule = Rule::parse(value, offset),
+            "EXDATE" => {
+                for date in value.split(',').filter_map(|v| parse_time(v, offset)) {
+                    self.exdates.push(date.0).ok();
+                }
+            }
+            "RECURRENCE-ID" => self.recurrence_id = parse_time(value, offset).map(|(id, _)| id),
+            "STATUS" => self.cancelled = value == "CANCELLED",
+            _ => {}
+        }
+    }
+}
+
+// Events from an iCalendar document that are still running at `now` or start within
+// WINDOW_DAYS, recurring ones expanded. TZID is not resolved: times with a zone are taken as
+// local, UTC ones are moved by `offset`.
+pub(crate) fn agenda(text: &str, now: i64, offset: i64) -> Agenda {
+    let mut overrides = heapless::Vec::<(u32, i64), MAX_OVERRIDES>::new();
+    for_each_event(text, offset, |event| {
+        if let Some(id) = event.recurrence_id {
+            overrides.push((event.uid, id)).ok();
+        }
+    });
+
+    let window_end = (now.div_euclid(DAY_SECS) + WINDOW_DAYS) * DAY_SECS;
+    let mut agenda = Agenda::new();
+    for_each_event(text, offset, |event| {
+        let Some((start, all_day)) = event.start.filter(|_| !event.cancelled) else {
+            return;
+        };
+        let length = event
+            .end
+            .map(|end| end - start)
+            .or(event.duration)
+            .unwrap_or(if all_day { DAY_SECS } else { 0 })
+            .max(0);
+        let overridden = |at| event.recurrence_id.is_none() && overrides.contains(&(event.uid, at));
+        let mut add = |at: i64| {
+            if at >= window_end {
+                return false;
+            }
+            if at + length.max(1) > now && !event.exdates.contains(&at) && !overridden(at) {
+                insert(&mut agenda, Event {
+                    start: at,
+                    end: at + length,
+                    all_day,
+                    summary: event.summary.clone(),
+                    location: event.location.clone(),
+                });
+            }
+            true
+        };
+        match &event.rule {
+            Some(rule) => rule.expand(start, now - length, add),
+            None => {
+                add(start);
+            }
+        }
+    });
+    agenda
+}
+
+// keeps the earliest MAX_EVENTS, sorted by start
+fn insert(agenda: &mut Agenda, event: Event) {
+    let i = agenda.partition_point(|e| e.start <= event.start);
+    if agenda.is_full() {
+        if i == agenda.len() {
+            return;
+        }
+        agenda.pop();
+    }
+    agenda.insert(i, event).ok();
+}
+
+// calls `f` with each VEVENT once its END is reached; VALARM and other nested components are
+// skipped, VTIMEZONE is ignored altogether
+fn for_each_event(text: &str, offset: i64, mut f: impl FnMut(&Component)) {
+    let mut event: Option<Component> = None;
+    let mut nested = 0u32;
+    for_each_line(text, |line| {
+        let Some((name, value)) = split_property(line) else {
+            return;
+        };
+        match (name, value) {
+            ("BEGIN", "VEVENT") => {
+                event = Some(Component::default());
+                nested = 0;
+            }
+            ("END", "VEVENT") => {
+                if let Some(event) = event.take() {
+                    f(&event);
+                }
+            }
+            ("BEGIN", _) if event.is_some() => nested += 1,
+            ("END", _) if event.is_some() => nested = nested.saturating_sub(1),
+            _ => {
+                if let Some(event) = event.as_mut().filter(|_| nested == 0) {
+                    event.set(name, value, offset);
+                }
+            }
+        }
+    });
+}
+
+// joins folded lines (continuations start with a space or tab) before handing them on
+fn for_each_line(text: &str, mut f: impl FnMut(&str)) {
+    let mut line = String::new();
+    for raw in text.split('\n') {
+        let raw = raw.strip_suffix('\r').unwrap_or(raw);
+        if let Some(rest) = raw.strip_prefix([' ', '\t']) {
+            line.push_str(rest);
+            continue;
+        }
+        if !line.is_empty() {
+            f(&line);
+        }
+        line.clear();
+        line.push_str(raw);
+    }
+    if !line.is_empty() {
+        f(&line);
+    }
+}
+
+// name and value of `NAME;PARAM=..:value`, the value starts at the first colon outside quotes
+fn split_property(line: &str) -> Option<(&str, &str)> {
+    let mut quoted = false;
+    let (colon, _) = line.char_indices().find(|&(_, c)| {
+        if c == '"' {
+            quoted = !quoted;
+        }
+        c == ':' && !quoted
+    })?;
+    let name = line[..colon].split(';').next().unwrap_or("");
+    Some((name, &line[colon + 1..]))
+}
+
+// `20240105` (a whole day) or `20240105T100000` with an optional `Z` for UTC
+fn parse_time(value: &str, offset: i64) -> Option<(i64, bool)> {
+    let value = value.trim();
+    let digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit()).then(|| s.parse::<i64>().ok())?;
+    let year = digits(value.get(..4)?)?;
+    let month = digits(value.get(4..6)?)? as u8;
+    let day = digits(value.get(6..8)?)? as u8;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let midnight = clock::days_from_civil(year, month, day) * DAY_SECS;
+    let time = value.get(8..)?;
+    if time.is_empty() {
+        return Some((midnight, true));
+    }
+    let time = time.strip_prefix('T')?;
+    let (time, utc) = match time.strip_suffix('Z') {
+        Some(time) => (time, true),
+        None => (time, false),
+    };
+    let (hour, minute, second) =
+        (digits(time.get(..2)?)?, digits(time.get(2..4)?)?, digits(time.get(4..)?)?);
+    let local = midnight + hour * 3600 + minute * 60 + second;
+    Some((if utc { local + offset } else { local }, false))
+}
+
+// `P1D`, `PT1H30M`, `P2W`, optionally signed
+fn parse_duration(value: &str) -> Option<i64> {
+    let (sign, rest) = match value.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let mut total = 0i64;
+    let mut n = 0i64;
+    for c in rest.strip_prefix('P')?.chars() {
+        let unit = match c {
+            '0'..='9' => {
+                n = n.saturating_mul(10).saturating_add(c as i64 - '0' as i64);
+                continue;
+            }
+            'T' => continue,
+            'W' => 7 * DAY_SECS,
+            'D' => DAY_SECS,
+            'H' => 3600,
+            'M' => 60,
+            'S' => 1,
+            _ => return None,
+        };
+        total = total.saturating_add(n.saturating_mul(unit));
+        n = 0;
+    }
+    Some(sign * total)
+}
+
+// TEXT values escape `,` `;` `\` and newlines; newlines become spaces in a one line label
+fn unescape_into<const N: usize>(out: &mut heapless::String<N>, value: &str) {
+    out.clear();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        let c = match c {
+            '\\' => match chars.next() {
+                Some('n' | 'N') => ' ',
+                Some(c) => c,
+                None => break,
+            },
+            c => c,
+        };
+        if out.push(c).is_err() {
+            break;
+        }
+    }
+}
+
+// FNV-1a, UIDs are only compared with each other
+fn hash(value: &str) -> u32 {
+    value.bytes().fold(0x811c_9dc5, |h, b| (h ^ b as u32).wrapping_mul(0x0100_0193))
+}
+
+// 0 = Monday, 1970-01-01 was a Thursday
+fn weekday(day: i64) -> u32 {
+    (day + 3).rem_euclid(7) as u32
+}
+
+fn days_in_month(year: i64, month: u8) -> u8 {
+    let (next_year, next_month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+    (clock::days_from_civil(next_year, next_month, 1) - clock::days_from_civil(year, month, 1))
+        as u8
+}
+
+// GETs an .ics route and hands the agenda to `sink`; the whole document is held in RAM while
+// it is parsed, one over MAX_BODY is refused rather than buffered, so let the server trim it
+pub(crate) struct CalendarSource<F> {
+    route: &'static str,
+    sink: F,
+}
+
+impl<F: FnMut(Agenda)> CalendarSource<F> {
+    pub(crate) fn new(route: &'static str, sink: F) -> Self {
+        Self { route, sink }
+    }
+}
+
+impl<F: FnMut(Agenda)> Source for CalendarSource<F> {
+    fn fetch(&mut self, http: &mut HttpClient<'_>) -> Result<(), DataBindingError> {
+        let route = self.route;
+        // recurring events can't be placed without knowing what day it is
+        let now = clock::local_secs_now()
+            .ok_or(DataBindingError::Unusable(route, "clock not synced"))?;
+        let mut body = Vec::new();
+        let mut too_large = false;
+        let response = http
+            .request_streaming(Method::Get, route, "", TIMEOUT_SECS, |chunk| {
+                // the rest of the download is read and thrown away
+                too_large |= body.len() + chunk.len() > MAX_BODY;
+                if !too_large {
+                    body.extend_from_slice(chunk);
+                }
+            })
+            .map_err(|e| DataBindingError::Fetch(route, JsonError::Http(e)))?;
+        if !(200..300).contains(&response.status) {
+            return Err(DataBindingError::Fetch(route, JsonError::Status(response.status)));
+        }
+        if too_large {
+            return Err(DataBindingError::Unusable(route, "calendar over 64 KiB"));
+        }
+        // a cut off download still yields the events before the cut
+        let text = match core::str::from_utf8(&body) {
+            Ok(text) => text,
+            Err(e) => core::str::from_utf8(&body[..e.valid_up_to()]).unwrap_or(""),
+        };
+        if !text.trim_start().starts_with("BEGIN:VCALENDAR") {
+            return Err(DataBindingError::Unusable(route, "not an iCalendar document"));
+        }
+        (self.sink)(agenda(text, now, clock::utc_offset_secs()));
+        Ok(())
+    }
+}
+
+// the first non-comment line of CALENDAR.TXT is the route of the .ics file on the server;
+// https calendars need the server to proxy them
+pub(crate) fn load_route(sd: &SdStorage<'_>) -> Option<&'static str> {
+    let file = sd.read_config(CALENDAR_FILE).ok()?;
+    let route = file.lines().next()?;
+    if !route.starts_with('/') {
+        warn!("Calendar: route must start with /, got {}", route);
+        return None;
+    }
+    // read once per boot, leaking it gives the data binding its &'static str
+    Some(String::from(route).leak())
+}
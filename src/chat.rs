@@ -0,0 +1,109 @@
+use alloc::{format, string::String, vec::Vec};
+use serde::Deserialize;
+
+use crate::{clock, data_binding::truncated, diagnostics::json_string};
+
+// kept in RAM only, a restart starts with an empty history
+pub(crate) const HISTORY_LEN: usize = 32;
+pub(crate) const MAX_TEXT: usize = 160;
+const MESSAGE_TYPE: &str = "message";
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct ChatMessage {
+    // empty for messages sent from here
+    pub(crate) from: heapless::String<24>,
+    pub(crate) text: heapless::String<MAX_TEXT>,
+    // unix time, the server's when it sends one, ours otherwise
+    pub(crate) sent_at: Option<u32>,
+    pub(crate) outgoing: bool,
+}
+
+impl ChatMessage {
+    // "14:30", empty before the clock is set
+    pub(crate) fn when(&self) -> String {
+        let Some(sent_at) = self.sent_at else {
+            return String::new();
+        };
+        let local = (sent_at as i64 + clock::utc_offset_secs()).max(0);
+        let date = clock::civil(local as u64);
+        format!("{:02}:{:02}", date.hour, date.minute)
+    }
+}
+
+#[derive(Deserialize)]
+struct Incoming {
+    #[serde(rename = "type", deserialize_with = "truncated")]
+    kind: heapless::String<16>,
+    #[serde(default, deserialize_with = "truncated")]
+    from: heapless::String<24>,
+    #[serde(deserialize_with = "truncated")]
+    text: heapless::String<MAX_TEXT>,
+    #[serde(default)]
+    ts: Option<u32>,
+}
+
+// Chat on top of the websocket: the server relays `{"type":"message","from":"...","text":"..."}`
+// frames (`ts` optional) and takes `{"type":"message","text":"..."}` from us. Frames of any other
+// shape are left to the ws_message hooks.
+pub(crate) struct ChatClient {
+    history: Vec<ChatMessage>,
+}
+
+impl ChatClient {
+    pub(crate) fn new() -> Self {
+        Self { history: Vec::with_capacity(HISTORY_LEN) }
+    }
+
+    pub(crate) fn history(&self) -> &[ChatMessage] {
+        &self.history
+    }
+
+    // the message, already in the history, if the frame was one
+    pub(crate) fn receive(&mut self, frame: &str) -> Option<&ChatMessage> {
+        let mut unescaped = [0u8; MAX_TEXT * 2];
+        let (incoming, _) =
+            serde_json_core::from_slice_escaped::<Incoming>(frame.as_bytes(), &mut unescaped)
+                .ok()?;
+        if incoming.kind != MESSAGE_TYPE || incoming.text.is_empty() {
+            return None;
+        }
+
+        self.push(ChatMessage {
+            from: incoming.from,
+            text: incoming.text,
+            sent_at: incoming.ts.or_else(clock::unix_now),
+            outgoing: false,
+        });
+        self.history.last()
+    }
+
+    // the frame to put on the websocket, the message is recorded as sent
+    pub(crate) fn send(&mut self, text: &str) -> Option<String> {
+        let text = text.trim();
+        if text.is_empty() {
+            return None;
+        }
+
+        let mut message =
+            ChatMessage { sent_at: clock::unix_now(), outgoing: true, ..Default::default() };
+        for c in text.chars() {
+            if message.text.push(c).is_err() {
+                break;
+            }
+        }
+        let frame = format!(
+            "{{\"type\":\"{}\",\"text\":{}}}",
+            MESSAGE_TYPE,
+            json_string(&message.text)
+        );
+        self.push(message);
+        Some(frame)
+    }
+
+    fn push(&mut self, message: ChatMessage) {
+        if self.history.len() == HISTORY_LEN {
+            self.history.remove(0);
+        }
+        self.history.push(message);
+    }
+}
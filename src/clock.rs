@@ -0,0 +1,125 @@
+use core::sync::atomic::{AtomicI32, AtomicU32, Ordering};
+use embedded_sdmmc::{TimeSource, Timestamp};
+use esp_hal::{rtc_cntl::Rtc, time::Instant};
+
+// anything earlier means the RTC was never set since power-on
+const MIN_VALID_EPOCH: u64 = 1_700_000_000;
+
+// unix time at Instant zero, 0 = not synced yet
+static BOOT_EPOCH_SECS: AtomicU32 = AtomicU32::new(0);
+static UTC_OFFSET_MINUTES: AtomicI32 = AtomicI32::new(0);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct DateTime {
+    pub(crate) year: u16,
+    pub(crate) month: u8,
+    pub(crate) day: u8,
+    pub(crate) hour: u8,
+    pub(crate) minute: u8,
+    pub(crate) second: u8,
+    // 0 = Monday
+    pub(crate) weekday: u8,
+}
+
+// the RTC keeps counting through deep sleep, so a wake from standby has the time without SNTP
+pub(crate) fn restore(rtc: &Rtc<'_>) -> bool {
+    let secs = rtc.current_time_us() / 1_000_000;
+    if secs < MIN_VALID_EPOCH {
+        return false;
+    }
+    set_epoch(secs as u32);
+    true
+}
+
+pub(crate) fn set(unix_secs: u32, rtc: Option<&Rtc<'_>>) {
+    set_epoch(unix_secs);
+    if let Some(rtc) = rtc {
+        rtc.set_current_time_us(unix_secs as u64 * 1_000_000);
+    }
+}
+
+pub(crate) fn set_utc_offset(minutes: i16) {
+    UTC_OFFSET_MINUTES.store(minutes as i32, Ordering::Relaxed);
+}
+
+pub(crate) fn unix_now() -> Option<u32> {
+    let boot = BOOT_EPOCH_SECS.load(Ordering::Relaxed);
+    (boot != 0).then(|| boot + uptime_secs())
+}
+
+// wall clock in the configured time zone
+pub(crate) fn local_now() -> Option<DateTime> {
+    local_secs_now().map(|local| civil(local.max(0) as u64))
+}
+
+// seconds since 1970-01-01 00:00 local time, for arithmetic on local dates
+pub(crate) fn local_secs_now() -> Option<i64> {
+    unix_now().map(|utc| utc as i64 + utc_offset_secs())
+}
+
+pub(crate) fn utc_offset_secs() -> i64 {
+    UTC_OFFSET_MINUTES.load(Ordering::Relaxed) as i64 * 60
+}
+
+fn set_epoch(unix_secs: u32) {
+    BOOT_EPOCH_SECS.store(unix_secs.saturating_sub(uptime_secs()), Ordering::Relaxed);
+}
+
+fn uptime_secs() -> u32 {
+    Instant::now().duration_since_epoch().as_secs() as u32
+}
+
+pub(crate) fn civil(epoch: u64) -> DateTime {
+    let days = (epoch / 86_400) as i64;
+    let secs = epoch % 86_400;
+
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    DateTime {
+        year: year as u16,
+        month: month as u8,
+        day: day as u8,
+        hour: (secs / 3600) as u8,
+        minute: (secs / 60 % 60) as u8,
+        second: (secs % 60) as u8,
+        // 1970-01-01 was a Thursday
+        weekday: ((days + 3).rem_euclid(7)) as u8,
+    }
+}
+
+// inverse of `civil` for the date part, days since 1970-01-01
+pub(crate) fn days_from_civil(year: i64, month: u8, day: u8) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+// FAT timestamps for files written to the card, local time like any desktop would use
+pub(crate) struct WallClock;
+
+impl TimeSource for WallClock {
+    fn get_timestamp(&self) -> Timestamp {
+        let now = local_now().unwrap_or(civil(MIN_VALID_EPOCH));
+        Timestamp::from_calendar(now.year, now.month, now.day, now.hour, now.minute, now.second)
+            .unwrap_or(Timestamp {
+                year_since_1970: 54,
+                zero_indexed_month: 0,
+                zero_indexed_day: 0,
+                hours: 0,
+                minutes: 0,
+                seconds: 0,
+            })
+    }
+}
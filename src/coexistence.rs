@@ -0,0 +1,136 @@
+// Keeping big panel flushes out of the radio's busiest moments. While WiFi connects, scans or
+// pulls a response in, its interrupts and bus traffic hold up the UI core's writes to the panel;
+// a frame stalled halfway shows half old, half new. The network core opens a `RadioWindow` around
+// those bursts and the renderer, once a frame has sent `large` pixels, waits for the window to
+// close before the next write, up to `pause` ms a frame so the screen never freezes. A write that
+// takes over twice what the SPI clock needs for it counts as a glitch, on the perf overlay and in
+// /api/status. The display SPI is driven by the CPU here, not DMA, so there is no DMA priority or
+// arbiter to set; COEX.TXT on the card tunes the pause instead: `large <pixels>`, `pause <ms>`,
+// or `off`.
+use core::sync::atomic::{AtomicU32, Ordering};
+use esp_hal::{
+    delay::Delay,
+    time::{Duration, Instant},
+};
+use log::warn;
+
+use crate::{display_screen::SPI_RATE, sdcard::SdStorage};
+
+const CONFIG_FILE: &str = "COEX.TXT";
+// a quarter of a 320x240 frame
+const LARGE_PIXELS: usize = 19200;
+const PAUSE_MS: u64 = 20;
+// setting the address window and the chip select on top of the pixels themselves
+const SLACK_US: u64 = 300;
+
+// open radio windows, on the network core
+static RADIO_WINDOWS: AtomicU32 = AtomicU32::new(0);
+static GLITCHES: AtomicU32 = AtomicU32::new(0);
+static PAUSES: AtomicU32 = AtomicU32::new(0);
+
+// held by the network core across a burst
+pub(crate) struct RadioWindow;
+
+impl RadioWindow {
+    pub(crate) fn open() -> Self {
+        RADIO_WINDOWS.fetch_add(1, Ordering::Relaxed);
+        RadioWindow
+    }
+}
+
+impl Drop for RadioWindow {
+    fn drop(&mut self) {
+        RADIO_WINDOWS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+fn radio_busy() -> bool {
+    RADIO_WINDOWS.load(Ordering::Relaxed) > 0
+}
+
+// panel writes that stalled, since boot
+pub(crate) fn glitches() -> u32 {
+    GLITCHES.load(Ordering::Relaxed)
+}
+
+// frames that waited for the radio, since boot
+pub(crate) fn pauses() -> u32 {
+    PAUSES.load(Ordering::Relaxed)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct CoexConfig {
+    // sent in a frame before it waits for the radio
+    pub(crate) large_pixels: usize,
+    // the most a frame waits, 0 never does
+    pub(crate) pause_ms: u64,
+}
+
+impl Default for CoexConfig {
+    fn default() -> Self {
+        Self { large_pixels: LARGE_PIXELS, pause_ms: PAUSE_MS }
+    }
+}
+
+pub(crate) fn load_config(sd: &SdStorage<'_>) -> CoexConfig {
+    let mut config = CoexConfig::default();
+    let Ok(file) = sd.read_config(CONFIG_FILE) else {
+        return config;
+    };
+    for line in file.lines() {
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next().map(str::parse::<u64>)) {
+            (Some("large"), Some(Ok(pixels))) => config.large_pixels = pixels as usize,
+            (Some("pause"), Some(Ok(ms))) => config.pause_ms = ms,
+            (Some("off"), None) => config.pause_ms = 0,
+            _ => warn!("Coexistence: malformed line {}", line),
+        }
+    }
+    config
+}
+
+// the renderer's side, one frame at a time
+pub(crate) struct FlushPacer {
+    config: CoexConfig,
+    sent: usize,
+    paused: Duration,
+    waited: bool,
+}
+
+impl FlushPacer {
+    pub(crate) fn new(config: CoexConfig) -> Self {
+        Self { config, sent: 0, paused: Duration::ZERO, waited: false }
+    }
+
+    // before each write; waits while the radio is busy once the frame is a large one
+    pub(crate) fn before(&mut self) {
+        let budget = Duration::from_millis(self.config.pause_ms);
+        if self.sent < self.config.large_pixels || self.paused >= budget || !radio_busy() {
+            return;
+        }
+        if !core::mem::replace(&mut self.waited, true) {
+            PAUSES.fetch_add(1, Ordering::Relaxed);
+        }
+        let started = Instant::now();
+        let delay = Delay::new();
+        while radio_busy() && self.paused + started.elapsed() < budget {
+            delay.delay_micros(100);
+        }
+        self.paused += started.elapsed();
+    }
+
+    // after `pixels` went out, `started` being when `before` returned
+    pub(crate) fn after(&mut self, pixels: usize, started: Instant) {
+        self.sent += pixels;
+        let expected_us = pixels as u64 * 16 / SPI_RATE.as_mhz() as u64;
+        if started.elapsed().as_micros() > expected_us * 2 + SLACK_US {
+            GLITCHES.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn frame_done(&mut self) {
+        self.sent = 0;
+        self.paused = Duration::ZERO;
+        self.waited = false;
+    }
+}
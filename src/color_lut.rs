@@ -0,0 +1,113 @@
+// Colour correction for panels that show colours skewed. Each RGB565 channel goes through a
+// table of its own, built from a gamma and a gain, on its way to the panel; the default
+// calibration is the identity and skips the tables altogether. Screenshots are taken before
+// this, they show what slint drew.
+use slint::platform::software_renderer::Rgb565Pixel;
+
+pub(crate) const GAMMA_RANGE: core::ops::RangeInclusive<u8> = 50..=250;
+pub(crate) const GAIN_RANGE: core::ops::RangeInclusive<u8> = 50..=100;
+
+// per red, green and blue
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct ColorCalibration {
+    // hundredths, the channel's value to this power; 100 leaves it as it is
+    pub(crate) gamma: [u8; 3],
+    // percent of full scale the channel reaches, below 100 pulls a tinted white back
+    pub(crate) gain: [u8; 3],
+}
+
+impl Default for ColorCalibration {
+    fn default() -> Self {
+        Self { gamma: [100; 3], gain: [100; 3] }
+    }
+}
+
+impl ColorCalibration {
+    // out of range values are clamped
+    pub(crate) fn with_channel(mut self, channel: usize, gamma: u8, gain: u8) -> Self {
+        if let (Some(g), Some(k)) = (self.gamma.get_mut(channel), self.gain.get_mut(channel)) {
+            *g = gamma.clamp(*GAMMA_RANGE.start(), *GAMMA_RANGE.end());
+            *k = gain.clamp(*GAIN_RANGE.start(), *GAIN_RANGE.end());
+        }
+        self
+    }
+
+    // night mode's shift, `warmth` in percent; gains may go below GAIN_RANGE here
+    pub(crate) fn warmed(mut self, warmth: u8) -> Self {
+        let warmth = warmth.min(100) as u16;
+        let scale = |gain: u8, percent: u16| (gain as u16 * percent / 100) as u8;
+        self.gain[1] = scale(self.gain[1], 100 - warmth / 4);
+        self.gain[2] = scale(self.gain[2], 100 - warmth * 7 / 10);
+        self
+    }
+
+    pub(crate) fn encode(&self) -> [u8; 6] {
+        let [r, g, b] = self.gamma;
+        let [kr, kg, kb] = self.gain;
+        [r, g, b, kr, kg, kb]
+    }
+
+    pub(crate) fn decode(value: &[u8]) -> Option<Self> {
+        let [r, g, b, kr, kg, kb] = *value else {
+            return None;
+        };
+        let calibration = Self::default();
+        Some(calibration.with_channel(0, r, kr).with_channel(1, g, kg).with_channel(2, b, kb))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ColorCommand {
+    // channel 0..3, red, green or blue
+    Adjust { channel: usize, gamma: u8, gain: u8 },
+    Reset,
+    Save,
+    // saved right away
+    NightMode(bool),
+}
+
+pub(crate) struct ColorLut {
+    red: [u8; 32],
+    green: [u8; 64],
+    blue: [u8; 32],
+    identity: bool,
+}
+
+impl ColorLut {
+    pub(crate) fn new(calibration: &ColorCalibration) -> Self {
+        let ColorCalibration { gamma, gain } = *calibration;
+        Self {
+            red: table(gamma[0], gain[0]),
+            green: table(gamma[1], gain[1]),
+            blue: table(gamma[2], gain[2]),
+            identity: *calibration == ColorCalibration::default(),
+        }
+    }
+
+    pub(crate) fn is_identity(&self) -> bool {
+        self.identity
+    }
+
+    pub(crate) fn apply(&self, pixels: &mut [Rgb565Pixel]) {
+        if self.identity {
+            return;
+        }
+        for pixel in pixels {
+            let v = pixel.0;
+            let r = self.red[(v >> 11) as usize] as u16;
+            let g = self.green[((v >> 5) & 0x3f) as usize] as u16;
+            let b = self.blue[(v & 0x1f) as usize] as u16;
+            pixel.0 = (r << 11) | (g << 5) | b;
+        }
+    }
+}
+
+// N levels of a channel, 32 or 64
+fn table<const N: usize>(gamma: u8, gain: u8) -> [u8; N] {
+    let max = (N - 1) as f32;
+    let (gamma, gain) = (gamma as f32 / 100.0, gain as f32 / 100.0);
+    core::array::from_fn(|level| {
+        let corrected = libm::powf(level as f32 / max, gamma) * gain;
+        (corrected * max + 0.5) as u8
+    })
+}
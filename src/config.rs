@@ -0,0 +1,678 @@
+use alloc::vec::Vec;
+use esp_hal::rng::Rng;
+use esp_storage::FlashStorage;
+use log::{info, warn};
+use smoltcp::wire::IpAddress;
+use thiserror::Error;
+
+use crate::{
+    auth::{self, RefreshToken, SEAL_LEN, SealKey},
+    color_lut::ColorCalibration,
+    display_screen::ScreenOrientation,
+    energy_meter::EnergyTotals,
+    gpio_pins::{PIN_COUNT, PinConfig},
+    night_mode::NightConfig,
+    partitions::{Partition, PartitionError, PartitionTable},
+    provisioning::Credentials,
+    reliability::{ReliabilityRecord, ReliabilityStats},
+    rules::Rule,
+    timers::{Countdown, Countdowns},
+    touch_input::TouchCalibration,
+};
+
+// the settings record lives in the `settings` partition, a flash sector per slot
+const SLOT_LEN: u32 = 0x1000;
+const MAGIC: u32 = 0x4346_4732; // "CFG2"
+// records from before the slots, without a sequence number
+const LEGACY_MAGIC: u32 = 0x4346_4731; // "CFG1"
+const SCHEMA_VERSION: u16 = 1;
+const HEADER_LEN: usize = 16;
+const LEGACY_HEADER_LEN: usize = 12;
+
+const KEY_WIFI_SSID: u8 = 1;
+const KEY_WIFI_PASSWORD: u8 = 2;
+const KEY_TOUCH_CALIBRATION: u8 = 3;
+const KEY_SERVER_HOST: u8 = 4;
+const KEY_SERVER_IP: u8 = 5;
+const KEY_BRIGHTNESS: u8 = 6;
+const KEY_IDLE_TIMEOUT: u8 = 7;
+// repeated, one entry per rule
+const KEY_RULE: u8 = 8;
+const KEY_NTP_SERVER: u8 = 9;
+const KEY_UTC_OFFSET: u8 = 10;
+const KEY_SCHEDULE: u8 = 11;
+const KEY_DRAG_PREDICTION: u8 = 12;
+const KEY_AUTH_REFRESH_TOKEN: u8 = 13;
+// repeated, one entry per countdown
+const KEY_TIMER: u8 = 14;
+// [mode, flags] per spare pin
+const KEY_GPIO: u8 = 15;
+// ScreenOrientation::encode
+const KEY_ORIENTATION: u8 = 16;
+// EnergyTotals::encode
+const KEY_ENERGY: u8 = 17;
+const KEY_TOUCH_CLICKS: u8 = 18;
+const KEY_CONFIG_VERSION: u8 = 19;
+// [light sleep after u16, touch wake]
+const KEY_SLEEP: u8 = 20;
+// [address 4, prefix length, gateway 4, dns 4 (0.0.0.0 for none), dhcp secs u16]
+const KEY_STATIC_IP: u8 = 21;
+// ColorCalibration::encode
+const KEY_COLOR: u8 = 22;
+// NightConfig::encode
+const KEY_NIGHT: u8 = 23;
+const KEY_ROTATION_LOCKED: u8 = 24;
+const KEY_MUTED: u8 = 25;
+// ReliabilityStats::encode, the running build's and the one before
+const KEY_RELIABILITY: u8 = 26;
+const KEY_RELIABILITY_PREVIOUS: u8 = 27;
+// the refresh token sealed with the `secrets` key, and its nonce and tag; KEY_AUTH_REFRESH_TOKEN
+// is only read, from records written before
+const KEY_AUTH_REFRESH_SEALED: u8 = 28;
+const KEY_AUTH_REFRESH_SEAL: u8 = 29;
+const SEAL_KEY_MAGIC: u32 = 0x4B45_5931; // "KEY1"
+
+#[derive(Error, Debug)]
+pub(crate) enum ConfigError {
+    #[error(transparent)]
+    Partition(#[from] PartitionError),
+    #[error("Settings record does not fit into {0} bytes")]
+    TooLarge(usize),
+    #[error("No assets partition")]
+    NoAssets,
+    #[error("No settings partition")]
+    NoSettings,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct ServerConfig {
+    pub(crate) host: heapless::String<64>,
+    pub(crate) ip: Option<[u8; 4]>,
+}
+
+impl ServerConfig {
+    pub(crate) fn address(&self) -> Option<IpAddress> {
+        self.ip.map(|[a, b, c, d]| IpAddress::v4(a, b, c, d))
+    }
+}
+
+// a fixed IPv4 configuration, for networks without DHCP or as a fallback when it doesn't answer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct StaticIp {
+    pub(crate) address: [u8; 4],
+    pub(crate) prefix_len: u8,
+    pub(crate) gateway: [u8; 4],
+    pub(crate) dns: Option<[u8; 4]>,
+    // DHCP is tried this long first, 0 uses the static address right away
+    pub(crate) dhcp_secs: u16,
+}
+
+// seconds without touch before the screen dims and turns off, 0 disables the step
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct IdleConfig {
+    pub(crate) dim_after_secs: u16,
+    pub(crate) off_after_secs: u16,
+}
+
+impl Default for IdleConfig {
+    fn default() -> Self {
+        Self { dim_after_secs: 60, off_after_secs: 300 }
+    }
+}
+
+// seconds with the screen off before WiFi goes down and the loop light-sleeps, 0 never; a
+// touch instead of the button wakes from deep sleep
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct SleepConfig {
+    pub(crate) light_sleep_after_secs: u16,
+    pub(crate) touch_wake: bool,
+}
+
+// local minutes since midnight the screen is on for, `off` before `on` runs past midnight
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct OnHours {
+    pub(crate) on: u16,
+    pub(crate) off: u16,
+}
+
+// signage hours per weekday (0 = Monday), None keeps the screen off the whole day
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct Schedule {
+    pub(crate) days: [Option<OnHours>; 7],
+}
+
+impl Schedule {
+    pub(crate) fn is_on(&self, weekday: u8, minute: u16) -> bool {
+        let today = self.days[weekday as usize % 7].is_some_and(|h| {
+            if h.on <= h.off { (h.on..h.off).contains(&minute) } else { minute >= h.on }
+        });
+        // a window running past midnight keeps going into the next day
+        let carried = self.days[(weekday as usize + 6) % 7]
+            .is_some_and(|h| h.on > h.off && minute < h.off);
+        today || carried
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct TimeConfig {
+    pub(crate) ntp_server: [u8; 4],
+    pub(crate) utc_offset_minutes: i16,
+}
+
+impl TimeConfig {
+    pub(crate) fn ntp_address(&self) -> IpAddress {
+        let [a, b, c, d] = self.ntp_server;
+        IpAddress::v4(a, b, c, d)
+    }
+}
+
+impl Default for TimeConfig {
+    fn default() -> Self {
+        // time.google.com anycast, the stack has no DNS resolver
+        Self { ntp_server: [216, 239, 35, 0], utc_offset_minutes: 0 }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Settings {
+    pub(crate) wifi: Option<Credentials>,
+    pub(crate) touch_calibration: TouchCalibration,
+    pub(crate) server: ServerConfig,
+    pub(crate) brightness: u8,
+    pub(crate) idle: IdleConfig,
+    pub(crate) sleep: SleepConfig,
+    pub(crate) rules: Vec<Rule>,
+    pub(crate) time: TimeConfig,
+    // None = no schedule, the screen only follows the idle timeouts
+    pub(crate) schedule: Option<Schedule>,
+    // extrapolate drags by the measured input latency
+    pub(crate) drag_prediction: bool,
+    // from the OAuth2 device flow, lets the unit sign in again without a new code
+    pub(crate) auth_refresh_token: Option<RefreshToken>,
+    pub(crate) timers: Countdowns,
+    pub(crate) gpio: [PinConfig; PIN_COUNT],
+    pub(crate) orientation: ScreenOrientation,
+    // kWh counted from the energy meter, kept across restarts
+    pub(crate) energy: EnergyTotals,
+    // a short tick from the buzzer on every press
+    pub(crate) touch_clicks: bool,
+    // the server's device config document last applied, 0 before the first
+    pub(crate) config_version: u32,
+    // None = DHCP only
+    pub(crate) static_ip: Option<StaticIp>,
+    // the panel's colour correction, the identity by default
+    pub(crate) color: ColorCalibration,
+    pub(crate) night: NightConfig,
+    // from the quick settings
+    pub(crate) rotation_locked: bool,
+    pub(crate) muted: bool,
+    pub(crate) reliability: ReliabilityRecord,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            wifi: None,
+            touch_calibration: TouchCalibration::default(),
+            server: ServerConfig::default(),
+            brightness: 100,
+            idle: IdleConfig::default(),
+            sleep: SleepConfig::default(),
+            rules: Vec::new(),
+            time: TimeConfig::default(),
+            schedule: None,
+            drag_prediction: false,
+            auth_refresh_token: None,
+            timers: Countdowns::default(),
+            gpio: [PinConfig::default(); PIN_COUNT],
+            orientation: ScreenOrientation::default(),
+            energy: EnergyTotals::default(),
+            touch_clicks: true,
+            config_version: 0,
+            static_ip: None,
+            color: ColorCalibration::default(),
+            night: NightConfig::default(),
+            rotation_locked: false,
+            muted: false,
+            reliability: ReliabilityRecord::default(),
+        }
+    }
+}
+
+// versioned key-value record: header (magic, version, length, crc, sequence) followed by
+// [key, len, value..] entries; unknown keys are skipped so older records stay readable.
+// The partition holds two slots of a sector each and a save goes to the one not holding the
+// newest record, so a write cut short by a power loss leaves the previous record whole
+pub(crate) struct ConfigStore<'d> {
+    flash: FlashStorage<'d>,
+    partitions: Option<PartitionTable>,
+    // where the record is, settings aren't kept without one
+    record: Option<Partition>,
+    // the start of NVS held the record before it had a partition of its own, NVS belongs to
+    // the radio's calibration data so it is only ever read for carrying an old record over
+    legacy: Partition,
+    // (slot, sequence) of the newest record
+    newest: Option<(u32, u32)>,
+    // holds the refresh token's SealKey, made the first time there's a token to keep
+    secrets: Option<Partition>,
+}
+
+impl<'d> ConfigStore<'d> {
+    pub(crate) fn new(flash: esp_hal::peripherals::FLASH<'d>) -> Self {
+        let mut flash = FlashStorage::new(flash);
+        let partitions = PartitionTable::read(&mut flash)
+            .inspect(|table| info!("Partitions: {}", table))
+            .inspect_err(|e| warn!("Partitions: {}", e))
+            .ok();
+        let record = partitions.as_ref().and_then(PartitionTable::settings).copied();
+        if record.is_none() {
+            warn!("Config: no settings partition, flash with partitions.csv to keep settings");
+        }
+        let legacy = partitions
+            .as_ref()
+            .and_then(PartitionTable::nvs)
+            .copied()
+            .unwrap_or(Partition::DEFAULT_NVS);
+        let secrets = partitions.as_ref().and_then(PartitionTable::secrets).copied();
+        Self { flash, partitions, record, legacy, newest: None, secrets }
+    }
+
+    pub(crate) fn partitions(&self) -> Option<&PartitionTable> {
+        self.partitions.as_ref()
+    }
+
+    // the assets partition lives on the same flash, reads and writes go through here
+    pub(crate) fn read_assets(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), ConfigError> {
+        let assets = self.assets()?;
+        Ok(assets.read(&mut self.flash, offset, bytes)?)
+    }
+
+    pub(crate) fn write_assets(&mut self, offset: u32, bytes: &[u8]) -> Result<(), ConfigError> {
+        let assets = self.assets()?;
+        Ok(assets.write(&mut self.flash, offset, bytes)?)
+    }
+
+    fn assets(&self) -> Result<Partition, ConfigError> {
+        let partitions = self.partitions.as_ref();
+        partitions.and_then(PartitionTable::assets).copied().ok_or(ConfigError::NoAssets)
+    }
+
+    // an old record in NVS is taken over until the first save moves it
+    pub(crate) fn load(&mut self) -> Settings {
+        let key = self.seal_key(false);
+        match self.read_newest() {
+            Ok(Some(entries)) => decode(&entries, key.as_ref()),
+            Ok(None) => Settings::default(),
+            Err(e) => {
+                warn!("Config: {}, using defaults", e);
+                Settings::default()
+            }
+        }
+    }
+
+    fn read_newest(&mut self) -> Result<Option<Vec<u8>>, ConfigError> {
+        let Some(partition) = self.record else {
+            return Ok(self.read_record(self.legacy, 0)?.map(|(_, entries)| entries));
+        };
+        let mut newest: Option<(u32, u32, Vec<u8>)> = None;
+        for slot in 0..slots(&partition) {
+            let Some((sequence, entries)) = self.read_record(partition, slot * SLOT_LEN)? else {
+                continue;
+            };
+            // wrapping, a record 2^31 saves ahead is older
+            if newest.as_ref().is_none_or(|n| sequence.wrapping_sub(n.1) as i32 > 0) {
+                newest = Some((slot, sequence, entries));
+            }
+        }
+        match newest {
+            Some((slot, sequence, entries)) => {
+                self.newest = Some((slot, sequence));
+                Ok(Some(entries))
+            }
+            None => Ok(self.read_record(self.legacy, 0)?.map(|(_, entries)| entries)),
+        }
+    }
+
+    pub(crate) fn save(&mut self, settings: &Settings) -> Result<(), ConfigError> {
+        let partition = self.record.ok_or(ConfigError::NoSettings)?;
+        let key = self.seal_key(settings.auth_refresh_token.is_some());
+        let entries = encode(settings, key.as_ref());
+        if HEADER_LEN + entries.len() > SLOT_LEN as usize {
+            return Err(ConfigError::TooLarge(SLOT_LEN as usize));
+        }
+
+        let (slot, sequence) = match self.newest {
+            Some((slot, sequence)) => ((slot + 1) % slots(&partition), sequence.wrapping_add(1)),
+            None => (0, 1),
+        };
+        let mut record = Vec::with_capacity(HEADER_LEN + entries.len());
+        record.extend_from_slice(&MAGIC.to_le_bytes());
+        record.extend_from_slice(&SCHEMA_VERSION.to_le_bytes());
+        record.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        record.extend_from_slice(&crc32(&entries).to_le_bytes());
+        record.extend_from_slice(&sequence.to_le_bytes());
+        record.extend_from_slice(&entries);
+
+        // only the slot's own sector is erased
+        partition.write(&mut self.flash, slot * SLOT_LEN, &record)?;
+        self.newest = Some((slot, sequence));
+        Ok(())
+    }
+
+    // the secrets partition's key, made from the RNG when `create` and there is none yet; None
+    // without the partition, the refresh token isn't kept then
+    fn seal_key(&mut self, create: bool) -> Option<SealKey> {
+        let secrets = self.secrets?;
+        let mut stored = [0u8; 4 + 32];
+        secrets.read(&mut self.flash, 0, &mut stored).ok()?;
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&stored[4..]);
+        if stored[..4] == SEAL_KEY_MAGIC.to_le_bytes() {
+            return Some(key);
+        }
+        if !create {
+            return None;
+        }
+        // only asked for with a token to keep, the radio has been feeding the RNG by then
+        let rng = Rng::new();
+        for chunk in key.chunks_mut(4) {
+            chunk.copy_from_slice(&rng.random().to_le_bytes());
+        }
+        stored[..4].copy_from_slice(&SEAL_KEY_MAGIC.to_le_bytes());
+        stored[4..].copy_from_slice(&key);
+        secrets
+            .write(&mut self.flash, 0, &stored)
+            .inspect_err(|e| warn!("Config: storing the seal key failed: {}", e))
+            .ok()?;
+        Some(key)
+    }
+
+    // (sequence, entries) of the record at `offset`, None when there is no intact one there
+    fn read_record(
+        &mut self,
+        partition: Partition,
+        offset: u32,
+    ) -> Result<Option<(u32, Vec<u8>)>, ConfigError> {
+        let mut header = [0u8; HEADER_LEN];
+        partition.read(&mut self.flash, offset, &mut header)?;
+
+        let magic = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let version = u16::from_le_bytes([header[4], header[5]]);
+        let len = u16::from_le_bytes([header[6], header[7]]) as usize;
+        let crc = u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
+        let sequence = u32::from_le_bytes([header[12], header[13], header[14], header[15]]);
+
+        let (header_len, sequence) = match magic {
+            MAGIC => (HEADER_LEN, sequence),
+            LEGACY_MAGIC => (LEGACY_HEADER_LEN, 0),
+            _ => return Ok(None),
+        };
+        if version > SCHEMA_VERSION || header_len + len > SLOT_LEN as usize {
+            warn!("Config: unsupported record version {}", version);
+            return Ok(None);
+        }
+
+        let mut entries = alloc::vec![0u8; len];
+        partition.read(&mut self.flash, offset + header_len as u32, &mut entries)?;
+
+        if crc32(&entries) != crc {
+            warn!("Config: checksum mismatch at {:#x}", offset);
+            return Ok(None);
+        }
+
+        Ok(Some((sequence, entries)))
+    }
+}
+
+// two when the partition has room for them
+fn slots(partition: &Partition) -> u32 {
+    (partition.size / SLOT_LEN).clamp(1, 2)
+}
+
+fn encode(settings: &Settings, key: Option<&SealKey>) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut put = |key: u8, value: &[u8]| {
+        out.push(key);
+        out.push(value.len() as u8);
+        out.extend_from_slice(value);
+    };
+
+    if let Some(wifi) = &settings.wifi {
+        put(KEY_WIFI_SSID, wifi.ssid.as_bytes());
+        put(KEY_WIFI_PASSWORD, wifi.password.as_bytes());
+    }
+
+    let cal = &settings.touch_calibration;
+    let mut cal_bytes = [0u8; 8];
+    for (chunk, v) in cal_bytes
+        .chunks_mut(2)
+        .zip([cal.x_mul, cal.x_offset, cal.y_mul, cal.y_offset])
+    {
+        chunk.copy_from_slice(&v.to_le_bytes());
+    }
+    put(KEY_TOUCH_CALIBRATION, &cal_bytes);
+
+    put(KEY_SERVER_HOST, settings.server.host.as_bytes());
+    if let Some(ip) = settings.server.ip {
+        put(KEY_SERVER_IP, &ip);
+    }
+    put(KEY_BRIGHTNESS, &[settings.brightness]);
+
+    let mut idle_bytes = [0u8; 4];
+    idle_bytes[..2].copy_from_slice(&settings.idle.dim_after_secs.to_le_bytes());
+    idle_bytes[2..].copy_from_slice(&settings.idle.off_after_secs.to_le_bytes());
+    put(KEY_IDLE_TIMEOUT, &idle_bytes);
+    if settings.sleep != SleepConfig::default() {
+        let [low, high] = settings.sleep.light_sleep_after_secs.to_le_bytes();
+        put(KEY_SLEEP, &[low, high, settings.sleep.touch_wake as u8]);
+    }
+
+    for rule in &settings.rules {
+        put(KEY_RULE, &rule.encode());
+    }
+
+    put(KEY_NTP_SERVER, &settings.time.ntp_server);
+    put(KEY_UTC_OFFSET, &settings.time.utc_offset_minutes.to_le_bytes());
+
+    if let Some(schedule) = &settings.schedule {
+        // [on u16, off u16] per day, 0xFFFF marks a day without hours
+        let mut schedule_bytes = [0xFFu8; 28];
+        for (chunk, day) in schedule_bytes.chunks_mut(4).zip(&schedule.days) {
+            if let Some(hours) = day {
+                chunk[..2].copy_from_slice(&hours.on.to_le_bytes());
+                chunk[2..].copy_from_slice(&hours.off.to_le_bytes());
+            }
+        }
+        put(KEY_SCHEDULE, &schedule_bytes);
+    }
+    put(KEY_DRAG_PREDICTION, &[settings.drag_prediction as u8]);
+    match (&settings.auth_refresh_token, key) {
+        (Some(token), Some(key)) => {
+            let (sealed, seal) = auth::seal(key, token);
+            put(KEY_AUTH_REFRESH_SEALED, &sealed);
+            put(KEY_AUTH_REFRESH_SEAL, &seal);
+        }
+        (Some(_), None) => warn!("Config: no secrets partition, the refresh token isn't kept"),
+        (None, _) => {}
+    }
+    for countdown in settings.timers.iter() {
+        put(KEY_TIMER, &countdown.encode());
+    }
+    if settings.gpio.iter().any(|pin| *pin != PinConfig::default()) {
+        let gpio_bytes: Vec<u8> = settings.gpio.iter().flat_map(PinConfig::encode).collect();
+        put(KEY_GPIO, &gpio_bytes);
+    }
+    put(KEY_ORIENTATION, &[settings.orientation.encode()]);
+    if settings.energy != EnergyTotals::default() {
+        put(KEY_ENERGY, &settings.energy.encode());
+    }
+    put(KEY_TOUCH_CLICKS, &[settings.touch_clicks as u8]);
+    put(KEY_ROTATION_LOCKED, &[settings.rotation_locked as u8]);
+    put(KEY_MUTED, &[settings.muted as u8]);
+    put(KEY_RELIABILITY, &settings.reliability.current.encode());
+    if let Some(previous) = &settings.reliability.previous {
+        put(KEY_RELIABILITY_PREVIOUS, &previous.encode());
+    }
+    if settings.config_version != 0 {
+        put(KEY_CONFIG_VERSION, &settings.config_version.to_le_bytes());
+    }
+    if let Some(static_ip) = &settings.static_ip {
+        let mut static_bytes = [0u8; 15];
+        static_bytes[..4].copy_from_slice(&static_ip.address);
+        static_bytes[4] = static_ip.prefix_len;
+        static_bytes[5..9].copy_from_slice(&static_ip.gateway);
+        static_bytes[9..13].copy_from_slice(&static_ip.dns.unwrap_or_default());
+        static_bytes[13..].copy_from_slice(&static_ip.dhcp_secs.to_le_bytes());
+        put(KEY_STATIC_IP, &static_bytes);
+    }
+    if settings.color != ColorCalibration::default() {
+        put(KEY_COLOR, &settings.color.encode());
+    }
+    if settings.night != NightConfig::default() {
+        put(KEY_NIGHT, &settings.night.encode());
+    }
+
+    out
+}
+
+fn decode(mut entries: &[u8], key: Option<&SealKey>) -> Settings {
+    let mut settings = Settings::default();
+    let mut ssid = "";
+    let mut password = "";
+    let mut sealed: &[u8] = &[];
+    let mut seal: &[u8] = &[];
+
+    while let [key, len, rest @ ..] = entries {
+        let len = *len as usize;
+        if rest.len() < len {
+            break;
+        }
+        let (value, tail) = rest.split_at(len);
+        entries = tail;
+
+        match *key {
+            KEY_WIFI_SSID => ssid = core::str::from_utf8(value).unwrap_or(""),
+            KEY_WIFI_PASSWORD => password = core::str::from_utf8(value).unwrap_or(""),
+            KEY_TOUCH_CALIBRATION if len == 8 => {
+                let v = |i: usize| i16::from_le_bytes([value[i], value[i + 1]]);
+                settings.touch_calibration = TouchCalibration {
+                    x_mul: v(0),
+                    x_offset: v(2),
+                    y_mul: v(4),
+                    y_offset: v(6),
+                };
+            }
+            KEY_SERVER_HOST => {
+                if let Some(host) = core::str::from_utf8(value)
+                    .ok()
+                    .and_then(|h| heapless::String::try_from(h).ok())
+                {
+                    settings.server.host = host;
+                }
+            }
+            KEY_SERVER_IP if len == 4 => {
+                settings.server.ip = Some([value[0], value[1], value[2], value[3]]);
+            }
+            KEY_BRIGHTNESS if len == 1 => settings.brightness = value[0].min(100),
+            KEY_IDLE_TIMEOUT if len == 4 => {
+                settings.idle = IdleConfig {
+                    dim_after_secs: u16::from_le_bytes([value[0], value[1]]),
+                    off_after_secs: u16::from_le_bytes([value[2], value[3]]),
+                };
+            }
+            KEY_SLEEP if len == 3 => {
+                settings.sleep = SleepConfig {
+                    light_sleep_after_secs: u16::from_le_bytes([value[0], value[1]]),
+                    touch_wake: value[2] != 0,
+                };
+            }
+            KEY_RULE => settings.rules.extend(Rule::decode(value)),
+            KEY_NTP_SERVER if len == 4 => {
+                settings.time.ntp_server = [value[0], value[1], value[2], value[3]];
+            }
+            KEY_UTC_OFFSET if len == 2 => {
+                settings.time.utc_offset_minutes = i16::from_le_bytes([value[0], value[1]]);
+            }
+            KEY_SCHEDULE if len == 28 => {
+                let mut schedule = Schedule::default();
+                for (day, chunk) in schedule.days.iter_mut().zip(value.chunks(4)) {
+                    let on = u16::from_le_bytes([chunk[0], chunk[1]]);
+                    let off = u16::from_le_bytes([chunk[2], chunk[3]]);
+                    if on != u16::MAX {
+                        *day = Some(OnHours { on, off });
+                    }
+                }
+                settings.schedule = Some(schedule);
+            }
+            KEY_DRAG_PREDICTION if len == 1 => settings.drag_prediction = value[0] != 0,
+            KEY_AUTH_REFRESH_TOKEN => {
+                settings.auth_refresh_token = core::str::from_utf8(value)
+                    .ok()
+                    .and_then(|token| heapless::String::try_from(token).ok());
+            }
+            KEY_TIMER => {
+                if let Some(countdown) = Countdown::decode(value) {
+                    settings.timers.push_decoded(countdown);
+                }
+            }
+            KEY_GPIO if len == PIN_COUNT * 2 => {
+                for (pin, chunk) in settings.gpio.iter_mut().zip(value.chunks(2)) {
+                    *pin = PinConfig::decode(chunk).unwrap_or_default();
+                }
+            }
+            KEY_ORIENTATION if len == 1 => {
+                settings.orientation = ScreenOrientation::decode(value[0]);
+            }
+            KEY_ENERGY => settings.energy = EnergyTotals::decode(value).unwrap_or_default(),
+            KEY_TOUCH_CLICKS if len == 1 => settings.touch_clicks = value[0] != 0,
+            KEY_ROTATION_LOCKED if len == 1 => settings.rotation_locked = value[0] != 0,
+            KEY_MUTED if len == 1 => settings.muted = value[0] != 0,
+            KEY_RELIABILITY => {
+                settings.reliability.current = ReliabilityStats::decode(value).unwrap_or_default()
+            }
+            KEY_RELIABILITY_PREVIOUS => {
+                settings.reliability.previous = ReliabilityStats::decode(value)
+            }
+            KEY_CONFIG_VERSION if len == 4 => {
+                let version = [value[0], value[1], value[2], value[3]];
+                settings.config_version = u32::from_le_bytes(version);
+            }
+            KEY_STATIC_IP if len == 15 => {
+                let quad = |i: usize| [value[i], value[i + 1], value[i + 2], value[i + 3]];
+                settings.static_ip = Some(StaticIp {
+                    address: quad(0),
+                    prefix_len: value[4].min(32),
+                    gateway: quad(5),
+                    dns: Some(quad(9)).filter(|dns| *dns != [0; 4]),
+                    dhcp_secs: u16::from_le_bytes([value[13], value[14]]),
+                });
+            }
+            KEY_COLOR => settings.color = ColorCalibration::decode(value).unwrap_or_default(),
+            KEY_NIGHT => settings.night = NightConfig::decode(value).unwrap_or_default(),
+            KEY_AUTH_REFRESH_SEALED => sealed = value,
+            KEY_AUTH_REFRESH_SEAL if len == SEAL_LEN => seal = value,
+            _ => {}
+        }
+    }
+
+    if let Some(key) = key.filter(|_| !sealed.is_empty()) {
+        settings.auth_refresh_token = auth::open(key, sealed, seal);
+        if settings.auth_refresh_token.is_none() {
+            warn!("Config: the refresh token doesn't open with this key, dropping it");
+        }
+    }
+    settings.wifi = Credentials::new(ssid, password).ok();
+    settings
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
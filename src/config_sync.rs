@@ -0,0 +1,200 @@
+// Settings rolled out from the server. The network core GETs the device config document with
+// the ETag of the last one and hands a changed document to the UI core, which checks every
+// value, applies the good ones through the settings store and queues a report of the version
+// it is on for the server, through the outbox so it survives being offline.
+use alloc::{format, string::String, vec::Vec};
+use core::fmt::Write as _;
+use serde::Deserialize;
+
+use crate::{
+    config::{IdleConfig, Settings, SleepConfig},
+    data_binding::{DataBindingError, Source},
+    http_client::{self, HttpClient, JsonError, Method},
+    night_mode::NightConfig,
+    power,
+};
+
+const CONFIG_ROUTE: &str = "/api/device/config";
+pub(crate) const APPLIED_ROUTE: &str = "/api/device/config/applied";
+pub(crate) const REFRESH_SECS: u64 = 15 * 60;
+const TIMEOUT_SECS: u64 = 10;
+const UNESCAPE_SIZE: usize = 128;
+
+// `{"version": 7, "brightness": 80, "ntp_server": "192.168.1.1", ...}`, every setting is
+// optional and a missing one is left as it is
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub(crate) struct RemoteConfig {
+    pub(crate) version: u32,
+    brightness: Option<u8>,
+    dim_after_secs: Option<u16>,
+    off_after_secs: Option<u16>,
+    light_sleep_after_secs: Option<u16>,
+    touch_wake: Option<bool>,
+    // dotted quad, the stack has no DNS resolver; used from the next restart on
+    ntp_server: Option<heapless::String<15>>,
+    utc_offset_minutes: Option<i16>,
+    drag_prediction: Option<bool>,
+    touch_clicks: Option<bool>,
+    night_mode: Option<bool>,
+    // `hh:mm-hh:mm` local time
+    night_hours: Option<heapless::String<11>>,
+    night_warmth: Option<u8>,
+}
+
+// what `apply` did with a document, reported back to the server
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Applied {
+    pub(crate) version: u32,
+    pub(crate) changed: Vec<&'static str>,
+    pub(crate) rejected: Vec<&'static str>,
+}
+
+impl Applied {
+    pub(crate) fn to_json(&self) -> String {
+        let list = |names: &[&str]| {
+            let quoted: Vec<String> = names.iter().map(|name| format!("\"{}\"", name)).collect();
+            quoted.join(",")
+        };
+        format!(
+            "{{\"version\":{},\"changed\":[{}],\"rejected\":[{}]}}",
+            self.version,
+            list(&self.changed),
+            list(&self.rejected)
+        )
+    }
+}
+
+impl RemoteConfig {
+    // every value is checked on its own, one out of range doesn't hold up the rest
+    pub(crate) fn apply(&self, settings: &mut Settings) -> Applied {
+        let mut applied = Applied { version: self.version, ..Applied::default() };
+        let mut set = |name: &'static str, valid: bool, changed: bool| {
+            if !valid {
+                applied.rejected.push(name);
+            } else if changed {
+                applied.changed.push(name);
+            }
+            valid && changed
+        };
+
+        if let Some(level) = self.brightness {
+            if set("brightness", (1..=100).contains(&level), level != settings.brightness) {
+                settings.brightness = level;
+            }
+        }
+        if self.dim_after_secs.is_some() || self.off_after_secs.is_some() {
+            let idle = IdleConfig {
+                dim_after_secs: self.dim_after_secs.unwrap_or(settings.idle.dim_after_secs),
+                off_after_secs: self.off_after_secs.unwrap_or(settings.idle.off_after_secs),
+            };
+            // 0 disables a step, dimming after the screen went off makes no sense
+            let valid = idle.dim_after_secs == 0
+                || idle.off_after_secs == 0
+                || idle.dim_after_secs < idle.off_after_secs;
+            if set("idle", valid, idle != settings.idle) {
+                settings.idle = idle;
+            }
+        }
+        if self.light_sleep_after_secs.is_some() || self.touch_wake.is_some() {
+            let sleep = SleepConfig {
+                light_sleep_after_secs: self
+                    .light_sleep_after_secs
+                    .unwrap_or(settings.sleep.light_sleep_after_secs),
+                touch_wake: self.touch_wake.unwrap_or(settings.sleep.touch_wake),
+            };
+            if set("sleep", true, sleep != settings.sleep) {
+                settings.sleep = sleep;
+            }
+        }
+        if let Some(server) = &self.ntp_server {
+            let address = parse_ipv4(server);
+            let changed = address.is_some_and(|a| a != settings.time.ntp_server);
+            if set("ntp_server", address.is_some(), changed) {
+                settings.time.ntp_server = address.unwrap();
+            }
+        }
+        if let Some(offset) = self.utc_offset_minutes {
+            // UTC-12:00 to UTC+14:00
+            let valid = (-720..=840).contains(&offset);
+            if set("utc_offset_minutes", valid, offset != settings.time.utc_offset_minutes) {
+                settings.time.utc_offset_minutes = offset;
+            }
+        }
+        if let Some(enabled) = self.drag_prediction {
+            if set("drag_prediction", true, enabled != settings.drag_prediction) {
+                settings.drag_prediction = enabled;
+            }
+        }
+        if let Some(enabled) = self.touch_clicks {
+            if set("touch_clicks", true, enabled != settings.touch_clicks) {
+                settings.touch_clicks = enabled;
+            }
+        }
+        if self.night_mode.is_some() || self.night_hours.is_some() || self.night_warmth.is_some() {
+            let hours = self.night_hours.as_deref().map(power::parse_range);
+            let night = NightConfig {
+                enabled: self.night_mode.unwrap_or(settings.night.enabled),
+                start: hours.flatten().map_or(settings.night.start, |h| h.on),
+                end: hours.flatten().map_or(settings.night.end, |h| h.off),
+                warmth: self.night_warmth.unwrap_or(settings.night.warmth),
+            };
+            let valid = hours.is_none_or(|h| h.is_some()) && night.warmth <= 100;
+            if set("night", valid, night != settings.night) {
+                settings.night = night;
+            }
+        }
+        settings.config_version = self.version;
+        applied
+    }
+}
+
+pub(crate) fn parse_ipv4(text: &str) -> Option<[u8; 4]> {
+    let mut octets = [0u8; 4];
+    let mut parts = text.split('.');
+    for octet in &mut octets {
+        *octet = parts.next()?.parse().ok()?;
+    }
+    parts.next().is_none().then_some(octets)
+}
+
+// polls the document with the ETag of the last answer, a 304 is the usual outcome
+pub(crate) struct ConfigSource<F> {
+    sink: F,
+    etag: Option<String>,
+}
+
+impl<F: FnMut(RemoteConfig)> ConfigSource<F> {
+    pub(crate) fn new(sink: F) -> Self {
+        Self { sink, etag: None }
+    }
+}
+
+impl<F: FnMut(RemoteConfig)> Source for ConfigSource<F> {
+    fn fetch(&mut self, http: &mut HttpClient<'_>) -> Result<(), DataBindingError> {
+        let mut headers = String::new();
+        if let Some(etag) = &self.etag {
+            write!(headers, "If-None-Match: {}\r\n", etag).ok();
+        }
+        let raw = http
+            .request_bytes_with(Method::Get, CONFIG_ROUTE, &headers, TIMEOUT_SECS)
+            .map_err(|e| DataBindingError::Fetch(CONFIG_ROUTE, JsonError::Http(e)))?;
+        let (status, body) = http_client::parse_response_bytes(&raw)
+            .ok_or(DataBindingError::Fetch(CONFIG_ROUTE, JsonError::Malformed))?;
+        match status {
+            304 => return Ok(()),
+            // no document for this device, nothing to roll out
+            404 => return Ok(()),
+            200..300 => {}
+            status => return Err(DataBindingError::Fetch(CONFIG_ROUTE, JsonError::Status(status))),
+        }
+
+        let mut unescaped = [0u8; UNESCAPE_SIZE];
+        let (config, _) =
+            serde_json_core::from_slice_escaped::<RemoteConfig>(&body, &mut unescaped)
+                .map_err(|e| DataBindingError::Fetch(CONFIG_ROUTE, JsonError::Deserialize(e)))?;
+        // the ETag is only kept for a document that parsed, a broken one is fetched again
+        self.etag = http_client::response_header(&raw, "etag").map(String::from);
+        (self.sink)(config);
+        Ok(())
+    }
+}
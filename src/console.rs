@@ -0,0 +1,173 @@
+// Commands typed on UART0 (115200 8N1, the port the log goes out on) for bring-up without the
+// screen or the network. Bytes are taken as they arrive and echoed, a line is only parsed once
+// Enter comes, so typing never holds up a frame; the loop answers through `reply`.
+use alloc::{
+    format,
+    string::{String, ToString},
+};
+use esp_hal::{
+    Blocking,
+    gpio::interconnect::{PeripheralInput, PeripheralOutput},
+    uart::{self, Uart},
+};
+use thiserror::Error;
+
+use crate::{
+    ping::{self, PingHost},
+    sdcard::DirEntryInfo,
+    shutdown::ShutdownKind,
+};
+
+const BAUD: u32 = 115_200;
+const LINE_LEN: usize = 96;
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7F;
+
+pub(crate) const HELP: &str = "wifi status | heap [now|snap|diff|list|clear] | ls /sd[/dir] | \
+     get <route|http://server/route> | ping <address|gateway|server> [count] | screenshot | \
+     log [level <l>] | fault [...] | reboot | poweroff";
+
+#[derive(Error, Debug)]
+pub(crate) enum ConsoleError {
+    #[error("UART setup failed: {0}")]
+    Uart(String),
+    #[error("Unknown command {0}, try help")]
+    UnknownCommand(String),
+    #[error("Usage: {0}")]
+    Usage(&'static str),
+}
+
+#[derive(Debug)]
+pub(crate) enum Command {
+    Help,
+    WifiStatus,
+    // the arguments go to `HeapSnapshots::command`
+    Heap(String),
+    // a directory on the card, "" is the root
+    Ls(String),
+    // a route on the configured server; a URL's host is ignored, without DNS the server is
+    // all there is to reach
+    Get(String),
+    // up to ping::MAX_COUNT requests
+    Ping { host: PingHost, count: u8 },
+    Screenshot,
+    Log(String),
+    Fault(String),
+    // through the same steps as the settings page's buttons
+    Shutdown(ShutdownKind),
+}
+
+impl Command {
+    pub(crate) fn parse(line: &str) -> Result<Self, ConsoleError> {
+        let line = line.trim();
+        let (name, args) = line.split_once(' ').unwrap_or((line, ""));
+        let args = args.trim();
+        match name {
+            "help" | "?" => Ok(Command::Help),
+            "wifi" if args.is_empty() || args == "status" => Ok(Command::WifiStatus),
+            "wifi" => Err(ConsoleError::Usage("wifi status")),
+            "heap" => Ok(Command::Heap(args.into())),
+            "ls" => match args.strip_prefix("/sd") {
+                Some(dir) if dir.is_empty() || dir.starts_with('/') => Ok(Command::Ls(dir.into())),
+                _ => Err(ConsoleError::Usage("ls /sd[/dir]")),
+            },
+            "get" => match args.strip_prefix("http://") {
+                Some(url) => match url.find('/') {
+                    Some(at) => Ok(Command::Get(url[at..].into())),
+                    None => Ok(Command::Get("/".into())),
+                },
+                None if args.starts_with('/') => Ok(Command::Get(args.into())),
+                None => Err(ConsoleError::Usage("get <route|http://server/route>")),
+            },
+            "ping" => {
+                let mut words = args.split_whitespace();
+                let host = words.next().and_then(PingHost::parse);
+                let count = words.next().map_or(Ok(ping::DEFAULT_COUNT), str::parse::<u8>);
+                match (host, count, words.next()) {
+                    (Some(host), Ok(count), None) => {
+                        Ok(Command::Ping { host, count: count.min(ping::MAX_COUNT) })
+                    }
+                    _ => Err(ConsoleError::Usage("ping <address|gateway|server> [count]")),
+                }
+            }
+            "screenshot" => Ok(Command::Screenshot),
+            "log" => Ok(Command::Log(args.into())),
+            "fault" => Ok(Command::Fault(args.into())),
+            "reboot" => Ok(Command::Shutdown(ShutdownKind::Reboot)),
+            "poweroff" => Ok(Command::Shutdown(ShutdownKind::PowerOff)),
+            other => Err(ConsoleError::UnknownCommand(other.into())),
+        }
+    }
+}
+
+pub(crate) struct Console<'d> {
+    uart: Uart<'d, Blocking>,
+    line: heapless::String<LINE_LEN>,
+}
+
+impl<'d> Console<'d> {
+    pub(crate) fn new(
+        uart: impl uart::Instance + 'd,
+        rx: impl PeripheralInput<'d>,
+        tx: impl PeripheralOutput<'d>,
+    ) -> Result<Self, ConsoleError> {
+        let config = uart::Config::default().with_baudrate(BAUD);
+        let uart = Uart::new(uart, config)
+            .map_err(|e| ConsoleError::Uart(format!("{:?}", e)))?
+            .with_rx(rx)
+            .with_tx(tx);
+        Ok(Self { uart, line: heapless::String::new() })
+    }
+
+    // whatever arrived since the last pass, a command once a line is complete; bytes after it
+    // stay in the FIFO for the next pass, so pasted lines run one per pass
+    pub(crate) fn poll(&mut self) -> Option<Result<Command, ConsoleError>> {
+        let mut byte = [0u8];
+        while let Ok(1) = self.uart.read_buffered(&mut byte) {
+            match byte[0] {
+                b'\r' | b'\n' if self.line.is_empty() => {}
+                b'\r' | b'\n' => {
+                    self.write(b"\r\n");
+                    let command = Command::parse(&self.line);
+                    self.line.clear();
+                    return Some(command);
+                }
+                BACKSPACE | DELETE => {
+                    if self.line.pop().is_some() {
+                        self.write(b"\x08 \x08");
+                    }
+                }
+                byte if byte.is_ascii_graphic() || byte == b' ' => {
+                    // past LINE_LEN the rest of the line is ignored
+                    if self.line.push(byte as char).is_ok() {
+                        self.write(&[byte]);
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    pub(crate) fn reply(&mut self, text: &str) {
+        for line in text.lines() {
+            self.write(line.as_bytes());
+            self.write(b"\r\n");
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        // the log shares the port, a failed echo isn't worth reporting into it
+        self.uart.write(bytes).ok();
+    }
+}
+
+// `ls` output, a line per entry with the size or <dir> right aligned before the name
+pub(crate) fn listing(entries: &[DirEntryInfo]) -> String {
+    let mut listing = String::new();
+    for entry in entries {
+        let size = if entry.is_dir { "<dir>".into() } else { entry.size.to_string() };
+        listing.push_str(&format!("{:>10} {}\n", size, entry.name));
+    }
+    listing
+}
@@ -0,0 +1,159 @@
+use alloc::{boxed::Box, vec::Vec};
+use core::{fmt, marker::PhantomData};
+use esp_hal::time::Duration;
+use serde::{
+    Deserialize, Deserializer,
+    de::{DeserializeOwned, IgnoredAny, SeqAccess, Visitor},
+};
+use thiserror::Error;
+
+use crate::{
+    http_client::{HttpClient, JsonError},
+    scheduler::Scheduler,
+};
+
+// a failed fetch is retried sooner than the regular interval, backing off from this
+const RETRY_SECS: u64 = 30;
+
+#[derive(Error, Debug)]
+pub(crate) enum DataBindingError {
+    #[error("Fetching {0} failed: {1}")]
+    Fetch(&'static str, JsonError),
+    #[error("{0} is unusable: {1}")]
+    Unusable(&'static str, &'static str),
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub(crate) struct Tag {
+    pub(crate) id: heapless::String<32>,
+    #[serde(default)]
+    pub(crate) name: heapless::String<32>,
+}
+
+pub(crate) type Tags = heapless::Vec<Tag, 16>;
+
+// `deserialize_with` for display strings that may be longer than the buffer: cut at a char
+// boundary instead of failing the whole document
+pub(crate) fn truncated<'de, D: Deserializer<'de>, const N: usize>(
+    deserializer: D,
+) -> Result<heapless::String<N>, D::Error> {
+    struct Truncate<const N: usize>;
+
+    impl<'de, const N: usize> Visitor<'de> for Truncate<N> {
+        type Value = heapless::String<N>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a string")
+        }
+
+        fn visit_str<E>(self, v: &str) -> Result<Self::Value, E> {
+            let mut out = heapless::String::new();
+            for c in v.chars() {
+                if out.push(c).is_err() {
+                    break;
+                }
+            }
+            Ok(out)
+        }
+    }
+
+    deserializer.deserialize_str(Truncate::<N>)
+}
+
+// `deserialize_with` for arrays where only the first N entries matter
+pub(crate) fn first_n<'de, D, T, const N: usize>(
+    deserializer: D,
+) -> Result<heapless::Vec<T, N>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    struct FirstN<T, const N: usize>(PhantomData<T>);
+
+    impl<'de, T: Deserialize<'de>, const N: usize> Visitor<'de> for FirstN<T, N> {
+        type Value = heapless::Vec<T, N>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("an array")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut out = heapless::Vec::new();
+            while !out.is_full() {
+                let Some(item) = seq.next_element()? else {
+                    return Ok(out);
+                };
+                out.push(item).ok();
+            }
+            while seq.next_element::<IgnoredAny>()?.is_some() {}
+            Ok(out)
+        }
+    }
+
+    deserializer.deserialize_seq(FirstN::<T, N>(PhantomData))
+}
+
+// something fetched on an interval; JSON routes are the common case, `bind_source` takes others
+pub(crate) trait Source {
+    fn fetch(&mut self, http: &mut HttpClient<'_>) -> Result<(), DataBindingError>;
+}
+
+struct JsonSource<T, F> {
+    route: &'static str,
+    sink: F,
+    _payload: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned, F: FnMut(T)> Source for JsonSource<T, F> {
+    fn fetch(&mut self, http: &mut HttpClient<'_>) -> Result<(), DataBindingError> {
+        let value = http
+            .get_json::<T>(self.route, 10)
+            .map_err(|e| DataBindingError::Fetch(self.route, e))?;
+        (self.sink)(value);
+        Ok(())
+    }
+}
+
+// Periodically GETs JSON routes and hands the typed result to a sink, which usually sends it
+// into a Watch bound to a Slint property or model. Screens declare what they show here
+// instead of issuing requests from the event loop.
+pub(crate) struct DataBindings {
+    sources: Vec<Box<dyn Source>>,
+    // by index into `sources`
+    schedule: Scheduler<usize>,
+}
+
+impl DataBindings {
+    pub(crate) fn new() -> Self {
+        Self { sources: Vec::new(), schedule: Scheduler::new() }
+    }
+
+    pub(crate) fn bind<T: DeserializeOwned + 'static>(
+        &mut self,
+        route: &'static str,
+        interval: Duration,
+        sink: impl FnMut(T) + 'static,
+    ) -> &mut Self {
+        self.bind_source(JsonSource { route, sink, _payload: PhantomData }, interval)
+    }
+
+    pub(crate) fn bind_source(
+        &mut self,
+        source: impl Source + 'static,
+        interval: Duration,
+    ) -> &mut Self {
+        self.schedule.every(self.sources.len(), interval, Duration::from_secs(RETRY_SECS));
+        self.sources.push(Box::new(source));
+        self
+    }
+
+    // fetches at most one due route per call, each request blocks the loop for a while
+    pub(crate) fn poll(&mut self, http: &mut HttpClient<'_>) -> Result<(), DataBindingError> {
+        let Some(i) = self.schedule.due() else {
+            return Ok(());
+        };
+        let result = self.sources[i].fetch(http);
+        self.schedule.done(i, result.is_ok());
+        result
+    }
+}
@@ -0,0 +1,74 @@
+// DHT22 / AM2302 single-bus read: a start pulse from the host, then 40 bits encoded as the
+// length of each high phase. The whole exchange (~5 ms) runs with interrupts off so no pulse is
+// stretched by a handler. Same open drain pad as 1-Wire, the module has its own pull-up.
+use esp_hal::{delay::Delay, gpio::Flex, time::Instant};
+use thiserror::Error;
+
+// the sensor's own limit, reads closer together return the previous values
+pub(crate) const MIN_INTERVAL_MS: u64 = 2000;
+// a 0 is ~27 us high, a 1 ~70 us
+const ONE_THRESHOLD_US: u64 = 48;
+// longer than any phase of the protocol
+const PHASE_TIMEOUT_US: u64 = 120;
+
+#[derive(Error, Debug)]
+pub(crate) enum Dht22Error {
+    #[error("No response from the sensor")]
+    NoResponse,
+    #[error("Read timed out at bit {0}")]
+    Timeout(usize),
+    #[error("Checksum mismatch")]
+    Checksum,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Dht22Reading {
+    pub(crate) temperature: f32,
+    pub(crate) humidity: f32,
+}
+
+pub(crate) fn read(pin: &mut Flex<'_>) -> Result<Dht22Reading, Dht22Error> {
+    let delay = Delay::new();
+    let mut data = [0u8; 5];
+    critical_section::with(|_| {
+        pin.set_low();
+        delay.delay_micros(1100);
+        pin.set_high();
+        // response: the sensor pulls low, high, then low for the first bit
+        wait_for(pin, false).ok_or(Dht22Error::NoResponse)?;
+        wait_for(pin, true).ok_or(Dht22Error::NoResponse)?;
+        wait_for(pin, false).ok_or(Dht22Error::NoResponse)?;
+        for bit in 0..40 {
+            wait_for(pin, true).ok_or(Dht22Error::Timeout(bit))?;
+            let high_us = wait_for(pin, false).ok_or(Dht22Error::Timeout(bit))?;
+            if high_us > ONE_THRESHOLD_US {
+                data[bit / 8] |= 0x80 >> (bit % 8);
+            }
+        }
+        Ok(())
+    })?;
+
+    let sum = data[..4].iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+    if sum != data[4] {
+        return Err(Dht22Error::Checksum);
+    }
+    let humidity = u16::from_be_bytes([data[0], data[1]]) as f32 / 10.0;
+    // sign and magnitude, not two's complement
+    let magnitude = u16::from_be_bytes([data[2] & 0x7F, data[3]]) as f32 / 10.0;
+    let temperature = if data[2] & 0x80 != 0 { -magnitude } else { magnitude };
+    Ok(Dht22Reading { temperature, humidity })
+}
+
+// microseconds until the line reads `high`, None past the timeout
+fn wait_for(pin: &Flex<'_>, high: bool) -> Option<u64> {
+    let start = Instant::now();
+    loop {
+        let elapsed = start.elapsed().as_micros();
+        if pin.is_high() == high {
+            return Some(elapsed);
+        }
+        if elapsed > PHASE_TIMEOUT_US {
+            return None;
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use alloc::{
+    collections::VecDeque,
+    format,
+    rc::Rc,
+    string::{String, ToString},
+    vec::Vec,
+};
+use blocking_network_stack::ipv4::IpInfo;
+use core::{cell::RefCell, fmt::Write};
+use esp_hal::{
+    rtc_cntl::{reset_reason, wakeup_cause},
+    system::Cpu,
+    time::Instant,
+};
+use esp_println::println;
+use log::Level;
+use thiserror::Error;
+
+use crate::{
+    build_info,
+    config::Settings,
+    fat_check::CheckReport,
+    http_client::{HttpClient, Method},
+    logger,
+    partitions::PartitionTable,
+    sdcard::{SdCardError, SdStorage},
+    standby::standby_cycles,
+    telemetry,
+};
+
+// 8.3 name, the card only takes short file names
+pub(crate) const EXPORT_PATH: &str = "DIAG.JSN";
+pub(crate) const UPLOAD_ROUTE: &str = "/api/diagnostics";
+const LOG_CAPACITY: usize = 32;
+
+#[derive(Error, Debug)]
+pub(crate) enum DiagnosticsError {
+    #[error("Failed to write bundle: {0}")]
+    Sd(#[from] SdCardError),
+    #[error("Failed to upload bundle: {0}")]
+    Upload(&'static str),
+}
+
+// shared tail of recent log lines, cloned into event hooks and the backend
+#[derive(Clone)]
+pub(crate) struct LogRing {
+    lines: Rc<RefCell<VecDeque<String>>>,
+}
+
+impl LogRing {
+    pub(crate) fn new() -> Self {
+        Self { lines: Rc::new(RefCell::new(VecDeque::with_capacity(LOG_CAPACITY))) }
+    }
+
+    pub(crate) fn record(&self, line: String) {
+        println!("{}", line);
+        logger::mirror(Level::Info, &line);
+        let mut lines = self.lines.borrow_mut();
+        if lines.len() == LOG_CAPACITY {
+            lines.pop_front();
+        }
+        let uptime = Instant::now().duration_since_epoch().as_millis();
+        lines.push_back(format!("[{}] {}", uptime, line));
+    }
+
+    fn lines(&self) -> Vec<String> {
+        self.lines.borrow().iter().cloned().collect()
+    }
+}
+
+// everything a support request usually asks for, gathered at the moment of export
+pub(crate) struct Snapshot<'s> {
+    pub(crate) log: &'s LogRing,
+    pub(crate) settings: &'s Settings,
+    pub(crate) ip_info: Option<&'s IpInfo>,
+    pub(crate) wifi_connected: bool,
+    pub(crate) sd_report: Option<&'s CheckReport>,
+    pub(crate) brightness: u8,
+    pub(crate) partitions: Option<&'s PartitionTable>,
+}
+
+impl Snapshot<'_> {
+    pub(crate) fn to_json(&self) -> String {
+        let mut out = String::new();
+        out.push('{');
+
+        let _ = write!(out, "\"build\":{},", build_info::to_json());
+        let _ = write!(
+            out,
+            "\"uptime_ms\":{},\"reset_reason\":{},\"wakeup_cause\":{},\"standby_cycles\":{},",
+            Instant::now().duration_since_epoch().as_millis(),
+            json_string(&format!("{:?}", reset_reason(Cpu::ProCpu))),
+            json_string(&format!("{:?}", wakeup_cause())),
+            standby_cycles(),
+        );
+
+        // secrets never leave the device, only whether they are set
+        let settings = self.settings;
+        let cal = &settings.touch_calibration;
+        let _ = write!(
+            out,
+            "\"config\":{{\"ssid\":{},\"password_set\":{},\"server_host\":{},\"server_ip\":{},\
+            \"brightness\":{},\"touch_calibration\":[{},{},{},{}],\"orientation\":\"{}\",\
+            \"auth_token_set\":{}}},",
+            settings.wifi.as_ref().map_or("null".to_string(), |w| json_string(&w.ssid)),
+            settings.wifi.as_ref().is_some_and(|w| !w.password.is_empty()),
+            json_string(&settings.server.host),
+            settings
+                .server
+                .ip
+                .map_or("null".to_string(), |[a, b, c, d]| format!("\"{a}.{b}.{c}.{d}\"")),
+            settings.brightness,
+            cal.x_mul,
+            cal.x_offset,
+            cal.y_mul,
+            cal.y_offset,
+            settings.orientation,
+            settings.auth_refresh_token.is_some(),
+        );
+
+        let _ = write!(out, "\"network\":{{\"connected\":{}", self.wifi_connected);
+        if let Some(info) = self.ip_info {
+            let _ = write!(
+                out,
+                ",\"ip\":\"{}\",\"gateway\":\"{}\",\"mask\":{},\"dns\":{}",
+                info.ip,
+                info.subnet.gateway,
+                info.subnet.mask.0,
+                info.dns.map_or("null".to_string(), |dns| format!("\"{}\"", dns)),
+            );
+        }
+        out.push_str("},");
+
+        let memory = telemetry::sample();
+        let _ = write!(
+            out,
+            "\"hardware\":{{\"heap_used\":{},\"heap_free\":{},\"internal_free\":{},\
+            \"largest_free_block\":{},\"stack_used\":{},\"stack_size\":{},\
+            \"backlight\":{},\"sd\":",
+            memory.heap_used,
+            memory.heap_free,
+            memory.internal_free,
+            memory.largest_free_block,
+            memory.stack_used,
+            memory.stack_size,
+            self.brightness,
+        );
+        match self.sd_report {
+            Some(report) => {
+                let _ = write!(
+                    out,
+                    "{{\"unreadable\":{},\"dirty\":{},\"io_error\":{},\"partial\":{},\
+                    \"orphaned_clusters\":{},\"cross_linked\":{},\"repaired\":{}}}",
+                    report.unreadable,
+                    report.dirty,
+                    report.io_error,
+                    report.partial,
+                    report.orphaned_clusters,
+                    report.cross_linked,
+                    report.repaired,
+                );
+            }
+            None => out.push_str("null"),
+        }
+        out.push_str("},");
+
+        // which layout the unit was flashed with, null when the table couldn't be read
+        out.push_str("\"partitions\":");
+        match self.partitions {
+            Some(table) => {
+                out.push('[');
+                for (i, partition) in table.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    let _ = write!(
+                        out,
+                        "{{\"label\":{},\"kind\":\"{}\",\"offset\":{},\"size\":{}}}",
+                        json_string(partition.label()),
+                        partition.kind,
+                        partition.offset,
+                        partition.size,
+                    );
+                }
+                out.push_str("],");
+            }
+            None => out.push_str("null,"),
+        }
+
+        out.push_str("\"log\":[");
+        for (i, line) in self.log.lines().iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str(&json_string(line));
+        }
+        out.push_str("]}");
+
+        out
+    }
+}
+
+// the card is preferred, uploading is the fallback for units without one
+pub(crate) fn export(
+    snapshot: &Snapshot<'_>,
+    sd: Option<&SdStorage<'_>>,
+    http: Option<&mut HttpClient<'_>>,
+) -> Result<&'static str, DiagnosticsError> {
+    let bundle = snapshot.to_json();
+
+    if let Some(sd) = sd {
+        sd.write_file(EXPORT_PATH, bundle.as_bytes())?;
+        return Ok("Diagnostics saved to SD");
+    }
+
+    upload(&bundle, http.ok_or(DiagnosticsError::Upload("no server configured"))?)
+}
+
+// also called on its own by the network core, with a bundle the UI core rendered
+pub(crate) fn upload(
+    bundle: &str,
+    http: &mut HttpClient<'_>,
+) -> Result<&'static str, DiagnosticsError> {
+    let response = http
+        .request(Method::Post, UPLOAD_ROUTE, Some(bundle.as_bytes()), 10)
+        .map_err(DiagnosticsError::Upload)?;
+    if !response.starts_with("HTTP/1.1 2") && !response.starts_with("HTTP/1.0 2") {
+        return Err(DiagnosticsError::Upload("server rejected the bundle"));
+    }
+    Ok("Diagnostics uploaded")
+}
+
+pub(crate) fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -1,54 +1,230 @@
-use core::cell::RefCell;
-
-use alloc::string::{String, ToString};
-use embedded_hal_bus::spi::{NoDelay, RefCellDevice};
+use core::fmt;
 use esp_hal::{
-    Blocking,
     delay::Delay,
     gpio::{Level, Output, OutputPin},
-    spi::master::Spi,
+    time::Rate,
 };
 use mipidsi::{
     Builder, Display,
     interface::SpiInterface,
-    models::ILI9341Rgb565,
-    options::{ColorOrder, Orientation, Rotation},
+    options::{ColorInversion, ColorOrder, Orientation, Rotation},
 };
 use thiserror::Error;
 
+use crate::spi_bus::{ClockedDevice, SharedSpi};
+
+#[cfg(any(
+    all(feature = "ili9341", feature = "st7789"),
+    all(feature = "ili9341", feature = "ili9486"),
+    all(feature = "st7789", feature = "ili9486"),
+    not(any(feature = "ili9341", feature = "st7789", feature = "ili9486")),
+))]
+compile_error!("enable exactly one panel feature: ili9341 (default), st7789 or ili9486");
+
+// The panel is picked at build time by cargo feature. Everything past this block only sees
+// PanelModel and the landscape size, the window, screenshots and touch follow from that.
+#[cfg(feature = "ili9341")]
+mod panel {
+    use super::*;
+    pub(crate) use mipidsi::models::ILI9341Rgb565 as PanelModel;
+
+    pub(crate) const MODEL: PanelModel = PanelModel;
+    pub(crate) const NAME: &str = "ILI9341";
+    // the controller's own portrait size, and the landscape one the UI is laid out in
+    pub(crate) const NATIVE_SIZE: (u16, u16) = (240, 320);
+    pub(crate) const LANDSCAPE_SIZE: (u32, u32) = (320, 240);
+    // the datasheet allows ~10 MHz writes, in practice panels take 40 MHz fine
+    pub(crate) const SPI_RATE: Rate = Rate::from_mhz(40);
+    pub(crate) const COLOR_ORDER: ColorOrder = ColorOrder::Bgr;
+    pub(crate) const INVERSION: ColorInversion = ColorInversion::Normal;
+
+    // the module this was written on is mounted upside down and mirrored
+    pub(crate) fn mounting() -> Orientation {
+        Orientation::new().rotate(Rotation::Deg270).flip_vertical()
+    }
+
+    // turned a quarter either way from that the window's rows are the controller's, running
+    // against its scan at one turn and with it at three
+    pub(crate) fn scroll_rows(quarter_turns: u8) -> Option<bool> {
+        match quarter_turns % 4 {
+            1 => Some(true),
+            3 => Some(false),
+            _ => None,
+        }
+    }
+}
+
+// 240x240 modules (1.3", 1.54"); mipidsi shifts the window into the 240x320 frame memory
+#[cfg(feature = "st7789")]
+mod panel {
+    use super::*;
+    pub(crate) use mipidsi::models::ST7789 as PanelModel;
+
+    pub(crate) const MODEL: PanelModel = PanelModel;
+    pub(crate) const NAME: &str = "ST7789";
+    pub(crate) const NATIVE_SIZE: (u16, u16) = (240, 240);
+    pub(crate) const LANDSCAPE_SIZE: (u32, u32) = (240, 240);
+    pub(crate) const SPI_RATE: Rate = Rate::from_mhz(40);
+    pub(crate) const COLOR_ORDER: ColorOrder = ColorOrder::Rgb;
+    // these modules come with the inversion bit set
+    pub(crate) const INVERSION: ColorInversion = ColorInversion::Inverted;
+
+    pub(crate) fn mounting() -> Orientation {
+        Orientation::new()
+    }
+
+    // the window sits in part of the frame memory, hardware scrolling isn't worth it on a square
+    pub(crate) fn scroll_rows(_quarter_turns: u8) -> Option<bool> {
+        None
+    }
+}
+
+// 320x480 (3.5") modules that take 16 bit colour over SPI, a bare ILI9486 only does 18 bit
+#[cfg(feature = "ili9486")]
+mod panel {
+    use super::*;
+    pub(crate) use mipidsi::models::ILI9486Rgb565 as PanelModel;
+
+    pub(crate) const MODEL: PanelModel = PanelModel;
+    pub(crate) const NAME: &str = "ILI9486";
+    pub(crate) const NATIVE_SIZE: (u16, u16) = (320, 480);
+    pub(crate) const LANDSCAPE_SIZE: (u32, u32) = (480, 320);
+    // longer traces on the big boards, writes get unreliable past ~20 MHz
+    pub(crate) const SPI_RATE: Rate = Rate::from_mhz(20);
+    pub(crate) const COLOR_ORDER: ColorOrder = ColorOrder::Bgr;
+    pub(crate) const INVERSION: ColorInversion = ColorInversion::Normal;
+
+    pub(crate) fn mounting() -> Orientation {
+        Orientation::new().rotate(Rotation::Deg90)
+    }
+
+    // not tried on one of these yet
+    pub(crate) fn scroll_rows(_quarter_turns: u8) -> Option<bool> {
+        None
+    }
+}
+
+pub(crate) use panel::{PanelModel, SPI_RATE};
+
+pub(crate) const PANEL_WIDTH: u32 = panel::LANDSCAPE_SIZE.0;
+pub(crate) const PANEL_HEIGHT: u32 = panel::LANDSCAPE_SIZE.1;
+
+pub(crate) type PanelDisplay<'a> =
+    Display<SpiInterface<'a, ClockedDevice<'a>, Output<'a>>, PanelModel, Output<'a>>;
+
 #[derive(Error, Debug)]
 pub(crate) enum DisplayScreenError {
-    #[error("Failed to initialize Ili9341 driver")]
-    Ili9341Init,
-    #[error("Failed to initialize SPI device for Xpt2046: {0}")]
-    SpiInit(String),
+    #[error("Failed to initialize the {} driver", panel::NAME)]
+    Init,
+    #[error("Failed to set the display orientation")]
+    Orientation,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum OrientationCommand {
+    Rotate,
+    Mirror,
+}
+
+// How the UI sits on the panel, relative to the landscape layout the touch calibration is
+// taken in. Rotation is in clockwise quarter turns, mirroring flips the result left to right.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct ScreenOrientation {
+    pub(crate) quarter_turns: u8,
+    pub(crate) mirrored: bool,
+}
+
+impl ScreenOrientation {
+    pub(crate) fn apply(self, command: OrientationCommand) -> Self {
+        match command {
+            OrientationCommand::Rotate => {
+                Self { quarter_turns: (self.quarter_turns + 1) % 4, ..self }
+            }
+            OrientationCommand::Mirror => Self { mirrored: !self.mirrored, ..self },
+        }
+    }
+
+    // the window size in pixels
+    pub(crate) fn size(&self) -> (u32, u32) {
+        if self.quarter_turns % 2 == 1 {
+            (PANEL_HEIGHT, PANEL_WIDTH)
+        } else {
+            (PANEL_WIDTH, PANEL_HEIGHT)
+        }
+    }
+
+    // MADCTL for this orientation, the rotation and mirroring are applied on top of the way
+    // the panel is mounted
+    pub(crate) fn madctl(&self) -> Orientation {
+        let rotation = match self.quarter_turns % 4 {
+            0 => Rotation::Deg0,
+            1 => Rotation::Deg90,
+            2 => Rotation::Deg180,
+            _ => Rotation::Deg270,
+        };
+        let orientation = panel::mounting().rotate(rotation);
+        if self.mirrored { orientation.flip_horizontal() } else { orientation }
+    }
+
+    // whether window rows are frame memory rows, for hw_scroll.rs: Some(true) when they run
+    // against the panel's scan, None when the panel can't scroll the window vertically
+    pub(crate) fn scroll_rows(&self) -> Option<bool> {
+        panel::scroll_rows(self.quarter_turns)
+    }
+
+    // from calibrated landscape coordinates to window coordinates
+    pub(crate) fn map_touch(&self, x: i32, y: i32) -> (i32, i32) {
+        let (w, h) = (PANEL_WIDTH as i32, PANEL_HEIGHT as i32);
+        let (x, y) = match self.quarter_turns % 4 {
+            0 => (x, y),
+            1 => (y, w - 1 - x),
+            2 => (w - 1 - x, h - 1 - y),
+            _ => (h - 1 - y, x),
+        };
+        if self.mirrored { (self.size().0 as i32 - 1 - x, y) } else { (x, y) }
+    }
+
+    // [quarter turns | mirrored << 2]
+    pub(crate) fn encode(&self) -> u8 {
+        (self.quarter_turns % 4) | ((self.mirrored as u8) << 2)
+    }
+
+    pub(crate) fn decode(value: u8) -> Self {
+        Self { quarter_turns: value & 3, mirrored: value & 4 != 0 }
+    }
+}
+
+impl fmt::Display for ScreenOrientation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} deg", self.quarter_turns as u32 * 90)?;
+        if self.mirrored {
+            f.write_str(", mirrored")?;
+        }
+        Ok(())
+    }
 }
 
-pub(crate) fn init_ili9341_display<'a>(
-    spi: &'a RefCell<Spi<'a, Blocking>>,
+pub(crate) fn init_display<'a>(
+    spi: &'a SharedSpi<'a>,
     dc_pin: impl OutputPin + 'a,
     cs_pin: impl OutputPin + 'a,
     rst_pin: impl OutputPin + 'a,
     buf512: &'a mut [u8; 512],
-) -> Result<
-    Display<
-        SpiInterface<'a, RefCellDevice<'a, Spi<'a, Blocking>, Output<'a>, NoDelay>, Output<'a>>,
-        ILI9341Rgb565,
-        Output<'a>,
-    >,
-    DisplayScreenError,
-> {
+    orientation: ScreenOrientation,
+) -> Result<PanelDisplay<'a>, DisplayScreenError> {
     let dc = Output::new(dc_pin, Level::Low, Default::default());
-    let cs = Output::new(cs_pin, Level::Low, Default::default());
     let rst = Output::new(rst_pin, Level::Low, Default::default());
-    let spi = RefCellDevice::new_no_delay(spi, cs)
-        .map_err(|e| DisplayScreenError::SpiInit(e.to_string()))?;
+    let spi = ClockedDevice::new(spi, cs_pin, panel::SPI_RATE);
     let interface = SpiInterface::new(spi, dc, buf512);
 
-    Builder::new(ILI9341Rgb565, interface)
+    let (width, height) = panel::NATIVE_SIZE;
+    Builder::new(panel::MODEL, interface)
+        .display_size(width, height)
         .reset_pin(rst)
-        .orientation(Orientation::new().rotate(Rotation::Deg270).flip_vertical())
-        .color_order(ColorOrder::Bgr)
+        .orientation(orientation.madctl())
+        .color_order(panel::COLOR_ORDER)
+        .invert_colors(panel::INVERSION)
         .init(&mut Delay::new())
-        .map_err(|_| DisplayScreenError::Ili9341Init)
+        .map_err(|_| DisplayScreenError::Init)
 }
+
@@ -0,0 +1,86 @@
+// Second core bootstrap and the queues between the cores. The PRO core (0) keeps the UI, the
+// APP core (1) gets the network, see `network`. Each queue has one producer and one consumer,
+// so handing a value over is a pair of atomic index updates, no lock either core could stall on.
+use alloc::{boxed::Box, collections::VecDeque};
+use esp_hal::{interrupt::software::SoftwareInterruptControl, peripherals::CPU_CTRL, system::Stack};
+use heapless::spsc::{Consumer, Producer, Queue};
+use log::warn;
+
+// the blocking http client keeps its request and response buffers on the stack
+const APP_CORE_STACK_SIZE: usize = 32 * 1024;
+// what a sender holds on to for a stalled other core before it starts dropping
+const BACKLOG_LIMIT: usize = 32;
+
+static mut APP_CORE_STACK: Stack<APP_CORE_STACK_SIZE> = Stack::new();
+
+// runs `task` on the APP core; it never returns, the core is its own from here on
+pub(crate) fn start_app_core(
+    cpu_ctrl: CPU_CTRL<'static>,
+    sw_interrupts: SoftwareInterruptControl<'static>,
+    task: impl FnOnce() + Send + 'static,
+) {
+    // called once per boot, nothing else ever touches the stack
+    let stack = unsafe { &mut *core::ptr::addr_of_mut!(APP_CORE_STACK) };
+    esp_rtos::start_second_core(
+        cpu_ctrl,
+        sw_interrupts.software_interrupt0,
+        sw_interrupts.software_interrupt1,
+        stack,
+        task,
+    );
+}
+
+// a queue holds N - 1 values
+pub(crate) fn channel<T, const N: usize>() -> (Sender<T, N>, Receiver<T, N>) {
+    // both ends live until the next reset, leaking the queue is what makes them 'static
+    let queue: &'static mut Queue<T, N> = Box::leak(Box::new(Queue::new()));
+    let (producer, consumer) = queue.split();
+    (Sender { producer, backlog: VecDeque::new() }, Receiver { consumer })
+}
+
+pub(crate) struct Sender<T: 'static, const N: usize> {
+    producer: Producer<'static, T, N>,
+    // what didn't fit yet, stays on the sending core and goes out in order
+    backlog: VecDeque<T>,
+}
+
+impl<T, const N: usize> Sender<T, N> {
+    // never blocks, a full queue grows the backlog up to BACKLOG_LIMIT and `value` is dropped
+    // past that
+    pub(crate) fn send(&mut self, value: T) {
+        self.flush();
+        if self.backlog.len() >= BACKLOG_LIMIT {
+            warn!("Dual core: the other core isn't draining its queue, dropping");
+            return;
+        }
+        self.backlog.push_back(value);
+        self.flush();
+    }
+
+    // whether a value sent now goes straight into the queue, for bulk data that should wait
+    // for room instead of piling up in the backlog
+    pub(crate) fn has_room(&mut self) -> bool {
+        self.flush() && self.producer.ready()
+    }
+
+    // moves the backlog into whatever room the other core made, true once it's empty
+    pub(crate) fn flush(&mut self) -> bool {
+        while let Some(value) = self.backlog.pop_front() {
+            if let Err(value) = self.producer.enqueue(value) {
+                self.backlog.push_front(value);
+                return false;
+            }
+        }
+        true
+    }
+}
+
+pub(crate) struct Receiver<T: 'static, const N: usize> {
+    consumer: Consumer<'static, T, N>,
+}
+
+impl<T, const N: usize> Receiver<T, N> {
+    pub(crate) fn recv(&mut self) -> Option<T> {
+        self.consumer.dequeue()
+    }
+}
@@ -0,0 +1,479 @@
+// Modbus RTU energy meter on UART2: a PZEM-004T v3, or an Eastron SDM120/SDM230 behind an
+// auto-direction RS485 transceiver. A query goes out every POLL_INTERVAL and the reply is
+// collected over the following loop passes, the UI never waits on the bus. kWh come from the
+// meter's own counter, so nothing is lost between polls; today's count rolls over at the reset
+// time from METER.TXT and the totals are kept in the settings.
+use alloc::{collections::VecDeque, format, string::String, vec::Vec};
+use esp_hal::{
+    Blocking,
+    gpio::interconnect::{PeripheralInput, PeripheralOutput},
+    time::{Duration, Instant},
+    uart::{self, Uart},
+};
+use log::warn;
+use thiserror::Error;
+
+use crate::{clock, rules::RuleEngine, sdcard::SdStorage, sensors};
+
+const METER_FILE: &str = "METER.TXT";
+const READ_INPUT_REGISTERS: u8 = 0x04;
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+// a reply is ~50 bytes, under 200 ms even at 2400 baud
+const REPLY_TIMEOUT: Duration = Duration::from_millis(500);
+// missed replies in a row before the page shows the meter offline
+const OFFLINE_AFTER: u8 = 3;
+// the chart's hour, one point per 30 s
+const HISTORY_STEP: Duration = Duration::from_secs(30);
+const HISTORY_LEN: usize = 120;
+// watts the chart covers at least
+const MIN_SPAN: f32 = 10.0;
+// a jump past this is a different meter or a counter that was set, not consumption
+const MAX_STEP_WH: f64 = 5000.0;
+// flash wear: the totals are written at day rollover and at most hourly in between
+const SAVE_INTERVAL: Duration = Duration::from_secs(3600);
+// EnergyTotals::day before the first rollover
+const NO_DAY: i32 = i32::MIN;
+
+#[derive(Error, Debug)]
+pub(crate) enum EnergyMeterError {
+    #[error("UART setup failed: {0}")]
+    Uart(String),
+    #[error("No reply")]
+    Timeout,
+    #[error("CRC mismatch")]
+    Crc,
+    #[error("Modbus exception {0}")]
+    Exception(u8),
+    #[error("Malformed reply")]
+    Malformed,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum MeterKind {
+    Pzem,
+    Sdm,
+}
+
+impl MeterKind {
+    // (first register, count) per query, together they fill a Reading
+    fn queries(self) -> &'static [(u16, u16)] {
+        match self {
+            MeterKind::Pzem => &[(0x0000, 10)],
+            // voltage .. power factor, frequency, total import kWh
+            MeterKind::Sdm => &[(0x0000, 32), (0x0046, 2), (0x0156, 2)],
+        }
+    }
+
+    fn default_baud(self) -> u32 {
+        match self {
+            MeterKind::Pzem => 9600,
+            MeterKind::Sdm => 2400,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct MeterConfig {
+    pub(crate) kind: MeterKind,
+    pub(crate) address: u8,
+    pub(crate) baud: u32,
+    // local minutes since midnight today's count starts over
+    pub(crate) reset_minute: u16,
+}
+
+// kept in the settings, Wh throughout
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct EnergyTotals {
+    pub(crate) total_wh: u64,
+    pub(crate) today_wh: u32,
+    // days since 1970 at the reset time, NO_DAY until the clock was first set
+    pub(crate) day: i32,
+    // yesterday first
+    pub(crate) days: [u32; 7],
+}
+
+impl Default for EnergyTotals {
+    fn default() -> Self {
+        Self { total_wh: 0, today_wh: 0, day: NO_DAY, days: [0; 7] }
+    }
+}
+
+impl EnergyTotals {
+    pub(crate) const ENCODED_LEN: usize = 44;
+
+    // [total u64, today u32, day i32, days u32 x7], little endian
+    pub(crate) fn encode(&self) -> [u8; Self::ENCODED_LEN] {
+        let mut out = [0u8; Self::ENCODED_LEN];
+        out[..8].copy_from_slice(&self.total_wh.to_le_bytes());
+        out[8..12].copy_from_slice(&self.today_wh.to_le_bytes());
+        out[12..16].copy_from_slice(&self.day.to_le_bytes());
+        for (chunk, wh) in out[16..].chunks_mut(4).zip(&self.days) {
+            chunk.copy_from_slice(&wh.to_le_bytes());
+        }
+        out
+    }
+
+    pub(crate) fn decode(value: &[u8]) -> Option<Self> {
+        if value.len() != Self::ENCODED_LEN {
+            return None;
+        }
+        let word = |i: usize| [value[i], value[i + 1], value[i + 2], value[i + 3]];
+        let mut days = [0u32; 7];
+        for (i, wh) in days.iter_mut().enumerate() {
+            *wh = u32::from_le_bytes(word(16 + i * 4));
+        }
+        Some(Self {
+            total_wh: u64::from_le_bytes(value[..8].try_into().ok()?),
+            today_wh: u32::from_le_bytes(word(8)),
+            day: i32::from_le_bytes(word(12)),
+            days,
+        })
+    }
+
+    fn add(&mut self, wh: u32) {
+        self.total_wh += wh as u64;
+        self.today_wh = self.today_wh.saturating_add(wh);
+    }
+
+    // today moves into the week, days the unit was off count as 0. The first day the clock is
+    // known and a clock set backwards keep counting into today.
+    fn roll(&mut self, day: i32) {
+        if self.day != NO_DAY && day > self.day {
+            for i in 0..(day - self.day).min(7) {
+                self.days.rotate_right(1);
+                self.days[0] = if i == 0 { self.today_wh } else { 0 };
+            }
+            self.today_wh = 0;
+        }
+        self.day = day;
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Reading {
+    voltage: f32,
+    current: f32,
+    power: f32,
+    frequency: f32,
+    power_factor: f32,
+    // the meter's own counter
+    energy_wh: f64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct EnergyView {
+    pub(crate) online: bool,
+    pub(crate) voltage: f32,
+    pub(crate) current: f32,
+    pub(crate) power: f32,
+    pub(crate) frequency: f32,
+    pub(crate) power_factor: f32,
+    pub(crate) totals: EnergyTotals,
+    // mean watts per 30 s over the last hour, see `sensors::chart`
+    pub(crate) chart: String,
+    pub(crate) min: f32,
+    pub(crate) max: f32,
+}
+
+pub(crate) struct EnergyMeter<'d> {
+    uart: Uart<'d, Blocking>,
+    config: MeterConfig,
+    // query index being answered, when it went out and the reply so far
+    pending: Option<(usize, Instant, Vec<u8>)>,
+    next_poll: Instant,
+    // filled query by query
+    reading: Reading,
+    live: Option<Reading>,
+    misses: u8,
+    last_energy_wh: Option<f64>,
+    totals: EnergyTotals,
+    unsaved: bool,
+    saved_at: Instant,
+    rolled_over: bool,
+    history: VecDeque<f32>,
+    // watts summed over the running history step
+    bucket: (f32, u32),
+    bucket_start: Instant,
+}
+
+impl<'d> EnergyMeter<'d> {
+    pub(crate) fn new(
+        uart: impl uart::Instance + 'd,
+        rx: impl PeripheralInput<'d>,
+        tx: impl PeripheralOutput<'d>,
+        config: MeterConfig,
+        totals: EnergyTotals,
+    ) -> Result<Self, EnergyMeterError> {
+        let uart_config = uart::Config::default().with_baudrate(config.baud);
+        let uart = Uart::new(uart, uart_config)
+            .map_err(|e| EnergyMeterError::Uart(format!("{:?}", e)))?
+            .with_rx(rx)
+            .with_tx(tx);
+        Ok(Self {
+            uart,
+            config,
+            pending: None,
+            next_poll: Instant::now(),
+            reading: Reading::default(),
+            live: None,
+            misses: 0,
+            last_energy_wh: None,
+            totals,
+            unsaved: false,
+            saved_at: Instant::now(),
+            rolled_over: false,
+            history: VecDeque::with_capacity(HISTORY_LEN),
+            bucket: (0.0, 0),
+            bucket_start: Instant::now(),
+        })
+    }
+
+    // true when there is something new to show
+    pub(crate) fn poll(&mut self) -> bool {
+        let mut changed = self.roll_over();
+        let Some((query, sent, mut reply)) = self.pending.take() else {
+            if Instant::now() >= self.next_poll {
+                self.next_poll = Instant::now() + POLL_INTERVAL;
+                self.send(0);
+            }
+            return changed;
+        };
+        let mut buf = [0u8; 64];
+        while let Ok(n @ 1..) = self.uart.read_buffered(&mut buf) {
+            reply.extend_from_slice(&buf[..n]);
+        }
+        let (_, count) = self.config.kind.queries()[query];
+        match check_reply(self.config.address, count, &reply) {
+            Ok(Some(data)) => {
+                self.parse(query, data);
+                if query + 1 < self.config.kind.queries().len() {
+                    self.send(query + 1);
+                } else {
+                    self.finish();
+                    changed = true;
+                }
+            }
+            Ok(None) if sent.elapsed() < REPLY_TIMEOUT => self.pending = Some((query, sent, reply)),
+            Ok(None) => changed |= self.miss(EnergyMeterError::Timeout),
+            Err(e) => changed |= self.miss(e),
+        }
+        changed
+    }
+
+    // totals to write to the settings, at day rollover or once an hour of counting
+    pub(crate) fn take_save(&mut self) -> Option<EnergyTotals> {
+        let due = self.rolled_over || self.saved_at.elapsed() >= SAVE_INTERVAL;
+        if !self.unsaved || !due {
+            return None;
+        }
+        self.unsaved = false;
+        self.rolled_over = false;
+        self.saved_at = Instant::now();
+        Some(self.totals)
+    }
+
+    // `power` in W and `energy_today` in kWh while the meter answers
+    pub(crate) fn observe(&self, rule_engine: &mut RuleEngine) {
+        if let Some(reading) = &self.live {
+            rule_engine.observe("power", reading.power);
+            rule_engine.observe("energy_today", self.totals.today_wh as f32 / 1000.0);
+        }
+    }
+
+    pub(crate) fn view(&self) -> EnergyView {
+        let (chart, min, max) = sensors::chart(&self.history, MIN_SPAN);
+        let reading = self.live.unwrap_or_default();
+        EnergyView {
+            online: self.live.is_some(),
+            voltage: reading.voltage,
+            current: reading.current,
+            power: reading.power,
+            frequency: reading.frequency,
+            power_factor: reading.power_factor,
+            totals: self.totals,
+            chart,
+            // the axis starts at 0 W unless the whole hour sat far above it
+            min: min.max(0.0),
+            max,
+        }
+    }
+
+    fn send(&mut self, query: usize) {
+        // whatever is left over belongs to an earlier, abandoned reply
+        let mut buf = [0u8; 64];
+        while let Ok(1..) = self.uart.read_buffered(&mut buf) {}
+        let (register, count) = self.config.kind.queries()[query];
+        let mut frame = [0u8; 8];
+        frame[0] = self.config.address;
+        frame[1] = READ_INPUT_REGISTERS;
+        frame[2..4].copy_from_slice(&register.to_be_bytes());
+        frame[4..6].copy_from_slice(&count.to_be_bytes());
+        let crc = crc16(&frame[..6]);
+        frame[6..].copy_from_slice(&crc.to_le_bytes());
+        if let Err(e) = self.uart.write(&frame) {
+            warn!("Energy meter: write failed: {:?}", e);
+            return;
+        }
+        self.pending = Some((query, Instant::now(), Vec::new()));
+    }
+
+    fn parse(&mut self, query: usize, data: &[u8]) {
+        let register = |i: usize| u16::from_be_bytes([data[i * 2], data[i * 2 + 1]]);
+        // 32 bit values are two registers, the PZEM sends the low one first
+        let pzem_u32 = |i: usize| (register(i + 1) as u32) << 16 | register(i) as u32;
+        let float = |i: usize| {
+            f32::from_be_bytes([data[i * 2], data[i * 2 + 1], data[i * 2 + 2], data[i * 2 + 3]])
+        };
+        let reading = &mut self.reading;
+        match (self.config.kind, query) {
+            (MeterKind::Pzem, _) => {
+                reading.voltage = register(0) as f32 / 10.0;
+                reading.current = pzem_u32(1) as f32 / 1000.0;
+                reading.power = pzem_u32(3) as f32 / 10.0;
+                reading.energy_wh = pzem_u32(5) as f64;
+                reading.frequency = register(7) as f32 / 10.0;
+                reading.power_factor = register(8) as f32 / 100.0;
+            }
+            (MeterKind::Sdm, 0) => {
+                reading.voltage = float(0x00);
+                reading.current = float(0x06);
+                reading.power = float(0x0C);
+                reading.power_factor = float(0x1E);
+            }
+            (MeterKind::Sdm, 1) => reading.frequency = float(0),
+            (MeterKind::Sdm, _) => reading.energy_wh = float(0) as f64 * 1000.0,
+        }
+    }
+
+    fn finish(&mut self) {
+        let reading = self.reading;
+        if let Some(last) = self.last_energy_wh.as_mut() {
+            let delta = reading.energy_wh - *last;
+            if (0.0..MAX_STEP_WH).contains(&delta) {
+                // whole Wh only, the rest carries over to the next reading
+                let whole = delta as u32;
+                if whole > 0 {
+                    self.totals.add(whole);
+                    self.unsaved = true;
+                    *last += whole as f64;
+                }
+            } else {
+                *last = reading.energy_wh;
+            }
+        } else {
+            self.last_energy_wh = Some(reading.energy_wh);
+        }
+
+        self.bucket.0 += reading.power;
+        self.bucket.1 += 1;
+        if self.bucket_start.elapsed() >= HISTORY_STEP {
+            if self.history.len() == HISTORY_LEN {
+                self.history.pop_front();
+            }
+            self.history.push_back(self.bucket.0 / self.bucket.1 as f32);
+            self.bucket = (0.0, 0);
+            self.bucket_start = Instant::now();
+        }
+
+        self.live = Some(reading);
+        self.misses = 0;
+    }
+
+    // true when the meter just went offline
+    fn miss(&mut self, e: EnergyMeterError) -> bool {
+        self.misses = self.misses.saturating_add(1);
+        if self.misses < OFFLINE_AFTER || self.live.is_none() {
+            return false;
+        }
+        warn!("Energy meter: {}, offline", e);
+        self.live = None;
+        true
+    }
+
+    fn roll_over(&mut self) -> bool {
+        let Some(local) = clock::local_secs_now() else {
+            return false;
+        };
+        let day = (local - self.config.reset_minute as i64 * 60).div_euclid(86_400) as i32;
+        if day == self.totals.day {
+            return false;
+        }
+        self.totals.roll(day);
+        self.unsaved = true;
+        self.rolled_over = true;
+        true
+    }
+}
+
+// the register bytes of a complete reply, None while it is still coming in
+fn check_reply(address: u8, count: u16, reply: &[u8]) -> Result<Option<&[u8]>, EnergyMeterError> {
+    let exception = reply.get(1) == Some(&(READ_INPUT_REGISTERS | 0x80));
+    let len = if exception { 5 } else { 5 + count as usize * 2 };
+    if reply.len() < len {
+        return Ok(None);
+    }
+    let frame = &reply[..len];
+    if crc16(&frame[..len - 2]).to_le_bytes() != frame[len - 2..] {
+        return Err(EnergyMeterError::Crc);
+    }
+    if exception {
+        return Err(EnergyMeterError::Exception(frame[2]));
+    }
+    if frame[0] != address || frame[1] != READ_INPUT_REGISTERS || frame[2] as u16 != count * 2 {
+        return Err(EnergyMeterError::Malformed);
+    }
+    Ok(Some(&frame[3..len - 2]))
+}
+
+// Modbus CRC-16, polynomial 0xA001 reflected, sent low byte first
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFFu16;
+    for &byte in data {
+        crc ^= byte as u16;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xA001 & mask);
+        }
+    }
+    crc
+}
+
+// METER.TXT: `pzem|sdm [address] [baud]`, optionally `reset HH:MM` (default midnight).
+// No file, no meter: GPIO16/17 stay with the servo and PWM outputs.
+pub(crate) fn load_config(sd: &SdStorage<'_>) -> Option<MeterConfig> {
+    let file = sd.read_config(METER_FILE).ok()?;
+    let mut config = None;
+    let mut reset_minute = 0;
+    for line in file.lines() {
+        let mut words = line.split_whitespace();
+        let kind = match words.next() {
+            Some("pzem") => MeterKind::Pzem,
+            Some("sdm") => MeterKind::Sdm,
+            Some("reset") => {
+                match words.next().and_then(parse_time) {
+                    Some(minute) => reset_minute = minute,
+                    None => warn!("Meter: malformed line {}", line),
+                }
+                continue;
+            }
+            _ => {
+                warn!("Meter: malformed line {}", line);
+                continue;
+            }
+        };
+        let address = words.next().map_or(Some(1), |word| word.parse().ok());
+        let baud = words.next().map_or(Some(kind.default_baud()), |word| word.parse().ok());
+        let (Some(address), Some(baud)) = (address, baud) else {
+            warn!("Meter: malformed line {}", line);
+            continue;
+        };
+        config = Some(MeterConfig { kind, address, baud, reset_minute: 0 });
+    }
+    config.map(|config| MeterConfig { reset_minute, ..config })
+}
+
+fn parse_time(time: &str) -> Option<u16> {
+    let (hour, minute) = time.split_once(':')?;
+    let (hour, minute): (u16, u16) = (hour.parse().ok()?, minute.parse().ok()?);
+    (hour < 24 && minute < 60).then_some(hour * 60 + minute)
+}
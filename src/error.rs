@@ -0,0 +1,172 @@
+// One error type over the drivers' own, so setup code can `?` any of them: failures the device
+// can't run without end the event loop with it (and the panic screen shows it), the ones it can
+// go on without are kept in the error banner and retried with a growing backoff until they
+// recover. WiFi retries itself on the network core, the banner only shows how that is going.
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use esp_hal::{
+    spi::master::ConfigError,
+    time::{Duration, Instant},
+};
+use slint::PlatformError;
+use thiserror::Error;
+
+use crate::{
+    backlight::BacklightError, buzzer::BuzzerError, display_screen::DisplayScreenError,
+    sdcard::SdCardError, servo::ServoError, touch_input::TouchInputError, wifi::WifiError,
+};
+
+const RETRY_FIRST: Duration = Duration::from_secs(5);
+const RETRY_MAX: Duration = Duration::from_secs(60);
+
+#[derive(Error, Debug)]
+pub(crate) enum Error {
+    #[error("Display: {0}")]
+    Display(#[from] DisplayScreenError),
+    #[error("Touch: {0}")]
+    Touch(#[from] TouchInputError),
+    #[error("SD card: {0}")]
+    SdCard(#[from] SdCardError),
+    #[error("Backlight: {0}")]
+    Backlight(#[from] BacklightError),
+    #[error("Buzzer: {0}")]
+    Buzzer(#[from] BuzzerError),
+    #[error("Outputs: {0}")]
+    Servo(#[from] ServoError),
+    #[error("WiFi: {0}")]
+    Wifi(#[from] WifiError),
+    #[error("Radio: {0}")]
+    Radio(String),
+    #[error("SPI: {0:?}")]
+    Spi(ConfigError),
+}
+
+impl From<Error> for PlatformError {
+    fn from(e: Error) -> Self {
+        PlatformError::Other(e.to_string())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Subsystem {
+    Touch,
+    SdCard,
+    Wifi,
+}
+
+impl Subsystem {
+    // the event loop tries these again itself
+    fn retried_here(self) -> bool {
+        self != Subsystem::Wifi
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct ErrorView {
+    // empty hides the banner
+    pub(crate) message: String,
+    pub(crate) can_retry: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ErrorCommand {
+    Retry,
+    Dismiss,
+}
+
+struct Failure {
+    subsystem: Subsystem,
+    message: String,
+    // None while the retry runs, and for what isn't retried here
+    retry_at: Option<Instant>,
+    backoff: Duration,
+    dismissed: bool,
+}
+
+pub(crate) struct ErrorBanner {
+    failures: Vec<Failure>,
+    changed: bool,
+}
+
+impl ErrorBanner {
+    pub(crate) fn new() -> Self {
+        Self { failures: Vec::new(), changed: false }
+    }
+
+    // failed for the first time or again, the next retry waits twice as long
+    pub(crate) fn failed(&mut self, subsystem: Subsystem, error: &Error) {
+        let message = format!("{}", error);
+        let now = Instant::now();
+        let retry = |backoff| subsystem.retried_here().then(|| now + backoff);
+        match self.failures.iter_mut().find(|f| f.subsystem == subsystem) {
+            Some(failure) => {
+                failure.backoff = (failure.backoff * 2).min(RETRY_MAX);
+                failure.retry_at = retry(failure.backoff);
+                self.changed |= failure.message != message;
+                failure.message = message;
+            }
+            None => {
+                self.failures.push(Failure {
+                    subsystem,
+                    message,
+                    retry_at: retry(RETRY_FIRST),
+                    backoff: RETRY_FIRST,
+                    dismissed: false,
+                });
+                self.changed = true;
+            }
+        }
+    }
+
+    pub(crate) fn recovered(&mut self, subsystem: Subsystem) {
+        let before = self.failures.len();
+        self.failures.retain(|f| f.subsystem != subsystem);
+        self.changed |= self.failures.len() != before;
+    }
+
+    // a subsystem whose retry is due; the caller tries it and reports `failed` or `recovered`
+    pub(crate) fn due(&mut self) -> Option<Subsystem> {
+        let failure = self
+            .failures
+            .iter_mut()
+            .find(|f| f.retry_at.is_some_and(|at| at <= Instant::now()))?;
+        failure.retry_at = None;
+        Some(failure.subsystem)
+    }
+
+    pub(crate) fn apply(&mut self, command: ErrorCommand) {
+        let Some(failure) = self.failures.iter_mut().find(|f| !f.dismissed) else {
+            return;
+        };
+        match command {
+            // right away, and from the first backoff again
+            ErrorCommand::Retry if failure.subsystem.retried_here() => {
+                failure.retry_at = Some(Instant::now());
+                failure.backoff = RETRY_FIRST / 2;
+            }
+            ErrorCommand::Retry => {}
+            // retries go on, a recovery clears it all the same
+            ErrorCommand::Dismiss => {
+                failure.dismissed = true;
+                self.changed = true;
+            }
+        }
+    }
+
+    // the first failure not dismissed, when that changed
+    pub(crate) fn changed_view(&mut self) -> Option<ErrorView> {
+        if !core::mem::take(&mut self.changed) {
+            return None;
+        }
+        Some(match self.failures.iter().find(|f| !f.dismissed) {
+            Some(failure) => ErrorView {
+                message: failure.message.clone(),
+                can_retry: failure.subsystem.retried_here(),
+            },
+            None => ErrorView::default(),
+        })
+    }
+}
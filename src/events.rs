@@ -0,0 +1,97 @@
+use alloc::{boxed::Box, vec::Vec};
+use blocking_network_stack::ipv4::IpInfo;
+
+#[cfg(feature = "battery")]
+use crate::adc::BatteryView;
+use crate::{standby::WakeTrigger, telemetry::MemoryStats};
+
+// lifecycle hooks for application code, the backend only emits them
+#[derive(Default)]
+pub(crate) struct EventHooks {
+    ip_acquired: Vec<Box<dyn FnMut(&IpInfo)>>,
+    wifi_lost: Vec<Box<dyn FnMut()>>,
+    ws_message: Vec<Box<dyn FnMut(&str)>>,
+    sd_mounted: Vec<Box<dyn FnMut()>>,
+    woken: Vec<Box<dyn FnMut(WakeTrigger)>>,
+    power_fail: Vec<Box<dyn FnMut()>>,
+    low_heap: Vec<Box<dyn FnMut(&MemoryStats)>>,
+    #[cfg(feature = "battery")]
+    low_battery: Vec<Box<dyn FnMut(&BatteryView)>>,
+}
+
+impl EventHooks {
+    pub(crate) fn on_ip_acquired(&mut self, hook: impl FnMut(&IpInfo) + 'static) -> &mut Self {
+        self.ip_acquired.push(Box::new(hook));
+        self
+    }
+
+    pub(crate) fn on_wifi_lost(&mut self, hook: impl FnMut() + 'static) -> &mut Self {
+        self.wifi_lost.push(Box::new(hook));
+        self
+    }
+
+    pub(crate) fn on_ws_message(&mut self, hook: impl FnMut(&str) + 'static) -> &mut Self {
+        self.ws_message.push(Box::new(hook));
+        self
+    }
+
+    pub(crate) fn on_sd_mounted(&mut self, hook: impl FnMut() + 'static) -> &mut Self {
+        self.sd_mounted.push(Box::new(hook));
+        self
+    }
+
+    pub(crate) fn on_wake(&mut self, hook: impl FnMut(WakeTrigger) + 'static) -> &mut Self {
+        self.woken.push(Box::new(hook));
+        self
+    }
+
+    pub(crate) fn on_power_fail(&mut self, hook: impl FnMut() + 'static) -> &mut Self {
+        self.power_fail.push(Box::new(hook));
+        self
+    }
+
+    pub(crate) fn on_low_heap(&mut self, hook: impl FnMut(&MemoryStats) + 'static) -> &mut Self {
+        self.low_heap.push(Box::new(hook));
+        self
+    }
+
+    // right before the shutdown it causes
+    #[cfg(feature = "battery")]
+    pub(crate) fn on_low_battery(&mut self, hook: impl FnMut(&BatteryView) + 'static) -> &mut Self {
+        self.low_battery.push(Box::new(hook));
+        self
+    }
+
+    pub(crate) fn emit_ip_acquired(&mut self, info: &IpInfo) {
+        self.ip_acquired.iter_mut().for_each(|hook| hook(info));
+    }
+
+    pub(crate) fn emit_wifi_lost(&mut self) {
+        self.wifi_lost.iter_mut().for_each(|hook| hook());
+    }
+
+    pub(crate) fn emit_ws_message(&mut self, msg: &str) {
+        self.ws_message.iter_mut().for_each(|hook| hook(msg));
+    }
+
+    pub(crate) fn emit_sd_mounted(&mut self) {
+        self.sd_mounted.iter_mut().for_each(|hook| hook());
+    }
+
+    pub(crate) fn emit_woken(&mut self, trigger: WakeTrigger) {
+        self.woken.iter_mut().for_each(|hook| hook(trigger));
+    }
+
+    pub(crate) fn emit_power_fail(&mut self) {
+        self.power_fail.iter_mut().for_each(|hook| hook());
+    }
+
+    pub(crate) fn emit_low_heap(&mut self, stats: &MemoryStats) {
+        self.low_heap.iter_mut().for_each(|hook| hook(stats));
+    }
+
+    #[cfg(feature = "battery")]
+    pub(crate) fn emit_low_battery(&mut self, view: &BatteryView) {
+        self.low_battery.iter_mut().for_each(|hook| hook(view));
+    }
+}
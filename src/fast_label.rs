@@ -0,0 +1,50 @@
+// Labels that change a character or two at a time (the clock, the stopwatch at the frame rate)
+// as a row of fixed-width cells, one Text per character. Only the cells whose character changed
+// are touched, so slint redraws those instead of the whole label's dirty region; a label set to
+// what it already shows costs nothing. The one-character strings are kept per character and
+// shared, a changing digit doesn't allocate.
+use alloc::{rc::Rc, vec::Vec};
+use core::cell::RefCell;
+use slint::{Model, ModelRc, SharedString, VecModel};
+
+pub(crate) struct FastLabel {
+    cells: Rc<VecModel<SharedString>>,
+    glyphs: RefCell<Vec<(char, SharedString)>>,
+}
+
+impl FastLabel {
+    pub(crate) fn new() -> Self {
+        Self { cells: Rc::new(VecModel::default()), glyphs: RefCell::new(Vec::new()) }
+    }
+
+    // for the `cells` of a FastLabel in ui.slint
+    pub(crate) fn model(&self) -> ModelRc<SharedString> {
+        ModelRc::from(self.cells.clone())
+    }
+
+    pub(crate) fn set(&self, text: &str) {
+        let mut len = 0;
+        for (i, c) in text.chars().enumerate() {
+            len = i + 1;
+            match self.cells.row_data(i) {
+                Some(cell) if cell.chars().eq([c]) => {}
+                Some(_) => self.cells.set_row_data(i, self.glyph(c)),
+                None => self.cells.push(self.glyph(c)),
+            }
+        }
+        while self.cells.row_count() > len {
+            self.cells.remove(self.cells.row_count() - 1);
+        }
+    }
+
+    fn glyph(&self, c: char) -> SharedString {
+        let mut glyphs = self.glyphs.borrow_mut();
+        if let Some((_, glyph)) = glyphs.iter().find(|(g, _)| *g == c) {
+            return glyph.clone();
+        }
+        let mut utf8 = [0u8; 4];
+        let glyph = SharedString::from(&*c.encode_utf8(&mut utf8));
+        glyphs.push((c, glyph.clone()));
+        glyph
+    }
+}
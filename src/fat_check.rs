@@ -0,0 +1,400 @@
+use alloc::{format, string::String, vec, vec::Vec};
+use embedded_sdmmc::{Block, BlockDevice, BlockIdx};
+use thiserror::Error;
+
+// orphan scan keeps one bit per cluster in RAM, bigger volumes only get the flag checks
+const MAX_SCANNED_CLUSTERS: u32 = 64 * 1024;
+const MAX_DIR_DEPTH: usize = 8;
+const MAX_DIR_SECTORS: usize = 4096;
+
+#[derive(Error, Debug)]
+pub(crate) enum FatCheckError {
+    #[error("Block device error: {0}")]
+    Device(String),
+    #[error("No FAT16/FAT32 partition found")]
+    NoFilesystem,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum CheckLevel {
+    Off,
+    Flags,
+    Full,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum FatType {
+    Fat16,
+    Fat32,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct CheckReport {
+    // reading the card failed part way, nothing below is known
+    pub(crate) unreadable: bool,
+    pub(crate) dirty: bool,
+    pub(crate) io_error: bool,
+    // the directory walk stopped at MAX_DIR_DEPTH or an oversized directory, orphans aren't
+    // counted then since whatever hangs below would look like one
+    pub(crate) partial: bool,
+    pub(crate) orphaned_clusters: u32,
+    pub(crate) cross_linked: bool,
+    pub(crate) repaired: bool,
+}
+
+impl CheckReport {
+    pub(crate) fn needs_reformat(&self) -> bool {
+        self.cross_linked || (self.io_error && !self.repaired)
+    }
+
+    pub(crate) fn is_clean(&self) -> bool {
+        !self.unreadable
+            && !self.dirty
+            && !self.io_error
+            && self.orphaned_clusters == 0
+            && !self.cross_linked
+    }
+}
+
+struct Layout {
+    fat_type: FatType,
+    fat_start: u32,
+    fat_sectors: u32,
+    fat_count: u32,
+    root_dir_start: u32,
+    root_dir_sectors: u32,
+    root_cluster: u32,
+    data_start: u32,
+    sectors_per_cluster: u32,
+    cluster_count: u32,
+}
+
+// Works on raw blocks before the volume manager takes the card, so nothing else has the
+// filesystem open while entries get rewritten.
+pub(crate) fn check<D: BlockDevice>(
+    device: &D,
+    level: CheckLevel,
+    repair: bool,
+) -> Result<CheckReport, FatCheckError> {
+    let mut report = CheckReport::default();
+    if level == CheckLevel::Off {
+        return Ok(report);
+    }
+
+    let layout = read_layout(device)?;
+    let mut fat = FatReader::new(device, &layout);
+
+    let flags = fat.entry(1)?;
+    let (clean_bit, error_bit) = match layout.fat_type {
+        FatType::Fat16 => (0x8000, 0x4000),
+        FatType::Fat32 => (0x0800_0000, 0x0400_0000),
+    };
+    report.dirty = flags & clean_bit == 0;
+    report.io_error = flags & error_bit == 0;
+
+    if level == CheckLevel::Full && layout.cluster_count <= MAX_SCANNED_CLUSTERS {
+        let reachable = mark_reachable(device, &layout, &mut fat, &mut report)?;
+        if !report.partial {
+            let mut orphans = Vec::new();
+            for cluster in 2..layout.cluster_count + 2 {
+                let entry = fat.entry(cluster)?;
+                if entry != 0 && !fat.is_bad(entry) && !bit(&reachable, cluster) {
+                    orphans.push(cluster);
+                }
+            }
+            report.orphaned_clusters = orphans.len() as u32;
+
+            if repair && !report.cross_linked {
+                for cluster in orphans {
+                    fat.set_entry(cluster, 0)?;
+                }
+            }
+        }
+    }
+
+    if repair && !report.cross_linked && !report.is_clean() {
+        fat.set_entry(1, flags | clean_bit | error_bit)?;
+        report.repaired = true;
+    }
+    fat.flush()?;
+
+    Ok(report)
+}
+
+fn read_layout<D: BlockDevice>(device: &D) -> Result<Layout, FatCheckError> {
+    let mut block = [Block::new()];
+    read(device, &mut block, 0)?;
+
+    // MBR partition 1, fall back to a superfloppy layout without partition table
+    let mbr = &block[0].contents;
+    let partition_type = mbr[0x1BE + 4];
+    let lba_start = match partition_type {
+        0x04 | 0x06 | 0x0B | 0x0C | 0x0E => u32_at(mbr, 0x1BE + 8),
+        _ => 0,
+    };
+    read(device, &mut block, lba_start)?;
+    let bs = &block[0].contents;
+
+    if u16_at(bs, 11) != 512 || bs[510] != 0x55 || bs[511] != 0xAA {
+        return Err(FatCheckError::NoFilesystem);
+    }
+
+    let sectors_per_cluster = bs[13] as u32;
+    let reserved = u16_at(bs, 14) as u32;
+    let fat_count = bs[16] as u32;
+    let root_entries = u16_at(bs, 17) as u32;
+    let total_sectors = match u16_at(bs, 19) as u32 {
+        0 => u32_at(bs, 32),
+        n => n,
+    };
+    let fat_sectors = match u16_at(bs, 22) as u32 {
+        0 => u32_at(bs, 36),
+        n => n,
+    };
+    if sectors_per_cluster == 0 || fat_count == 0 || fat_sectors == 0 {
+        return Err(FatCheckError::NoFilesystem);
+    }
+
+    let root_dir_sectors = (root_entries * 32).div_ceil(512);
+    let fat_start = lba_start + reserved;
+    let root_dir_start = fat_start + fat_count * fat_sectors;
+    let data_start = root_dir_start + root_dir_sectors;
+    let cluster_count = total_sectors
+        .checked_sub(data_start - lba_start)
+        .ok_or(FatCheckError::NoFilesystem)?
+        / sectors_per_cluster;
+
+    let fat_type = match cluster_count {
+        0..4085 => return Err(FatCheckError::NoFilesystem),
+        4085..65525 => FatType::Fat16,
+        _ => FatType::Fat32,
+    };
+
+    Ok(Layout {
+        fat_type,
+        fat_start,
+        fat_sectors,
+        fat_count,
+        root_dir_start,
+        root_dir_sectors,
+        root_cluster: u32_at(bs, 44),
+        data_start,
+        sectors_per_cluster,
+        cluster_count,
+    })
+}
+
+fn mark_reachable<D: BlockDevice>(
+    device: &D,
+    layout: &Layout,
+    fat: &mut FatReader<'_, D>,
+    report: &mut CheckReport,
+) -> Result<Vec<u8>, FatCheckError> {
+    let mut reachable = vec![0u8; (layout.cluster_count as usize + 2).div_ceil(8)];
+    // (first cluster, depth); cluster 0 stands for the FAT16 fixed root directory
+    let mut pending: Vec<(u32, usize)> = vec![(0, 0)];
+    if layout.fat_type == FatType::Fat32 {
+        pending[0].0 = layout.root_cluster;
+        report.cross_linked |= !mark_chain(fat, &mut reachable, layout.root_cluster)?;
+    }
+
+    let mut block = [Block::new()];
+    while let Some((dir_cluster, depth)) = pending.pop() {
+        let (sectors, whole) = dir_sectors(fat, layout, dir_cluster)?;
+        report.partial |= !whole;
+        for sector in sectors {
+            read(device, &mut block, sector)?;
+            for entry in block[0].contents.chunks_exact(32) {
+                match entry[0] {
+                    0x00 => break,
+                    0xE5 | b'.' => continue,
+                    _ => {}
+                }
+                let attributes = entry[11];
+                if attributes == 0x0F || attributes & 0x08 != 0 {
+                    continue;
+                }
+
+                let first = (u16_at(entry, 20) as u32) << 16 | u16_at(entry, 26) as u32;
+                if first < 2 {
+                    continue;
+                }
+                report.cross_linked |= !mark_chain(fat, &mut reachable, first)?;
+                if attributes & 0x10 != 0 {
+                    if depth < MAX_DIR_DEPTH {
+                        pending.push((first, depth + 1));
+                    } else {
+                        report.partial = true;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(reachable)
+}
+
+// returns false if the chain runs into an already claimed cluster
+fn mark_chain<D: BlockDevice>(
+    fat: &mut FatReader<'_, D>,
+    reachable: &mut [u8],
+    first: u32,
+) -> Result<bool, FatCheckError> {
+    let mut cluster = first;
+    while cluster >= 2 && cluster < fat.cluster_count + 2 {
+        if bit(reachable, cluster) {
+            return Ok(false);
+        }
+        reachable[cluster as usize / 8] |= 1 << (cluster % 8);
+        let next = fat.entry(cluster)?;
+        if fat.is_end(next) || fat.is_bad(next) {
+            break;
+        }
+        cluster = next;
+    }
+    Ok(true)
+}
+
+// the directory's sectors, and false with the first MAX_DIR_SECTORS of one that is longer
+fn dir_sectors<D: BlockDevice>(
+    fat: &mut FatReader<'_, D>,
+    layout: &Layout,
+    first_cluster: u32,
+) -> Result<(Vec<u32>, bool), FatCheckError> {
+    if first_cluster == 0 {
+        let root = layout.root_dir_start..layout.root_dir_start + layout.root_dir_sectors;
+        return Ok((root.collect(), true));
+    }
+
+    let mut sectors = Vec::new();
+    let mut cluster = first_cluster;
+    while cluster >= 2 && cluster < layout.cluster_count + 2 {
+        let start = layout.data_start + (cluster - 2) * layout.sectors_per_cluster;
+        sectors.extend(start..start + layout.sectors_per_cluster);
+        let next = fat.entry(cluster)?;
+        if fat.is_end(next) || fat.is_bad(next) {
+            break;
+        }
+        if sectors.len() >= MAX_DIR_SECTORS {
+            return Ok((sectors, false));
+        }
+        cluster = next;
+    }
+    Ok((sectors, true))
+}
+
+// single sector cache over the first FAT; writes go to every FAT copy on flush
+struct FatReader<'d, D: BlockDevice> {
+    device: &'d D,
+    fat_type: FatType,
+    fat_start: u32,
+    fat_sectors: u32,
+    fat_count: u32,
+    cluster_count: u32,
+    cached: Option<u32>,
+    dirty: bool,
+    block: [Block; 1],
+}
+
+impl<'d, D: BlockDevice> FatReader<'d, D> {
+    fn new(device: &'d D, layout: &Layout) -> Self {
+        Self {
+            device,
+            fat_type: layout.fat_type,
+            fat_start: layout.fat_start,
+            fat_sectors: layout.fat_sectors,
+            fat_count: layout.fat_count,
+            cluster_count: layout.cluster_count,
+            cached: None,
+            dirty: false,
+            block: [Block::new()],
+        }
+    }
+
+    fn entry_size(&self) -> u32 {
+        match self.fat_type {
+            FatType::Fat16 => 2,
+            FatType::Fat32 => 4,
+        }
+    }
+
+    fn load(&mut self, cluster: u32) -> Result<usize, FatCheckError> {
+        let offset = cluster * self.entry_size();
+        let sector = offset / 512;
+        if self.cached != Some(sector) {
+            self.flush()?;
+            read(self.device, &mut self.block, self.fat_start + sector)?;
+            self.cached = Some(sector);
+        }
+        Ok((offset % 512) as usize)
+    }
+
+    fn entry(&mut self, cluster: u32) -> Result<u32, FatCheckError> {
+        let at = self.load(cluster)?;
+        let contents = &self.block[0].contents;
+        Ok(match self.fat_type {
+            FatType::Fat16 => u16_at(contents, at) as u32,
+            FatType::Fat32 => u32_at(contents, at) & 0x0FFF_FFFF,
+        })
+    }
+
+    fn set_entry(&mut self, cluster: u32, value: u32) -> Result<(), FatCheckError> {
+        let at = self.load(cluster)?;
+        let contents = &mut self.block[0].contents;
+        match self.fat_type {
+            FatType::Fat16 => contents[at..at + 2].copy_from_slice(&(value as u16).to_le_bytes()),
+            FatType::Fat32 => {
+                // the top nibble is reserved and has to be preserved
+                let old = u32_at(contents, at) & 0xF000_0000;
+                contents[at..at + 4].copy_from_slice(&(old | value & 0x0FFF_FFFF).to_le_bytes());
+            }
+        }
+        self.dirty = true;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), FatCheckError> {
+        if let (true, Some(sector)) = (self.dirty, self.cached) {
+            for copy in 0..self.fat_count {
+                let idx = self.fat_start + copy * self.fat_sectors + sector;
+                self.device
+                    .write(&self.block, BlockIdx(idx))
+                    .map_err(|e| FatCheckError::Device(format!("{:?}", e)))?;
+            }
+            self.dirty = false;
+        }
+        Ok(())
+    }
+
+    fn is_end(&self, entry: u32) -> bool {
+        match self.fat_type {
+            FatType::Fat16 => entry >= 0xFFF8,
+            FatType::Fat32 => entry >= 0x0FFF_FFF8,
+        }
+    }
+
+    fn is_bad(&self, entry: u32) -> bool {
+        match self.fat_type {
+            FatType::Fat16 => entry == 0xFFF7,
+            FatType::Fat32 => entry == 0x0FFF_FFF7,
+        }
+    }
+}
+
+fn read<D: BlockDevice>(device: &D, blocks: &mut [Block], idx: u32) -> Result<(), FatCheckError> {
+    device
+        .read(blocks, BlockIdx(idx))
+        .map_err(|e| FatCheckError::Device(format!("{:?}", e)))
+}
+
+fn bit(bitmap: &[u8], cluster: u32) -> bool {
+    bitmap[cluster as usize / 8] & (1 << (cluster % 8)) != 0
+}
+
+fn u16_at(data: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([data[at], data[at + 1]])
+}
+
+fn u32_at(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
@@ -0,0 +1,121 @@
+// Network fault injection for exercising reconnect and retry paths. Without the
+// `fault-injection` feature every hook is an inlined no-op.
+use alloc::string::String;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub(crate) enum FaultError {
+    #[error("Unknown fault command: {0}")]
+    UnknownCommand(String),
+    #[error("Invalid value: {0}")]
+    InvalidValue(String),
+    #[error("Fault injection is not compiled in, enable the fault-injection feature")]
+    Disabled,
+}
+
+pub(crate) use imp::{command, cut_read, delay_read, drop_write};
+#[cfg(feature = "fault-injection")]
+pub(crate) use imp::load_from_sd;
+
+#[cfg(feature = "fault-injection")]
+mod imp {
+    use alloc::{format, string::String};
+    use core::sync::atomic::{AtomicU8, AtomicU32, Ordering};
+    use esp_hal::{delay::Delay, rng::Rng};
+    use log::{info, warn};
+
+    use super::FaultError;
+    use crate::sdcard::SdStorage;
+
+    const FAULT_FILE: &str = "FAULT.TXT";
+
+    static DROP_PERCENT: AtomicU8 = AtomicU8::new(0);
+    static READ_DELAY_MS: AtomicU32 = AtomicU32::new(0);
+    // 0 = never cut
+    static CUT_AFTER_BYTES: AtomicU32 = AtomicU32::new(0);
+
+    // true when the write should be silently discarded, as if the segment got lost
+    pub(crate) fn drop_write() -> bool {
+        let percent = DROP_PERCENT.load(Ordering::Relaxed) as u32;
+        percent != 0 && Rng::new().random() % 100 < percent
+    }
+
+    pub(crate) fn delay_read() {
+        let ms = READ_DELAY_MS.load(Ordering::Relaxed);
+        if ms != 0 {
+            Delay::new().delay_millis(ms);
+        }
+    }
+
+    // true once a stream has received enough to be cut mid-response
+    pub(crate) fn cut_read(received: usize) -> bool {
+        let limit = CUT_AFTER_BYTES.load(Ordering::Relaxed) as usize;
+        limit != 0 && received >= limit
+    }
+
+    // `fault [drop <pct> | delay <ms> | cut <bytes> | off]`, returns the resulting state
+    pub(crate) fn command(args: &str) -> Result<String, FaultError> {
+        let mut parts = args.split_whitespace();
+        let action = parts.next();
+        let raw = parts.next().unwrap_or("");
+        let value = || raw.parse::<u32>().map_err(|_| FaultError::InvalidValue(raw.into()));
+
+        match action {
+            None | Some("status") => {}
+            Some("drop") => DROP_PERCENT.store(value()?.min(100) as u8, Ordering::Relaxed),
+            Some("delay") => READ_DELAY_MS.store(value()?, Ordering::Relaxed),
+            Some("cut") => CUT_AFTER_BYTES.store(value()?, Ordering::Relaxed),
+            Some("off") => {
+                DROP_PERCENT.store(0, Ordering::Relaxed);
+                READ_DELAY_MS.store(0, Ordering::Relaxed);
+                CUT_AFTER_BYTES.store(0, Ordering::Relaxed);
+            }
+            Some(other) => return Err(FaultError::UnknownCommand(other.into())),
+        }
+
+        Ok(format!(
+            "drop {}%, read delay {} ms, cut after {} bytes",
+            DROP_PERCENT.load(Ordering::Relaxed),
+            READ_DELAY_MS.load(Ordering::Relaxed),
+            CUT_AFTER_BYTES.load(Ordering::Relaxed),
+        ))
+    }
+
+    // FAULT.TXT on the card, a `command` per line ('#' starts a comment), applied at boot so a
+    // unit starts out faulty without anyone at its console
+    pub(crate) fn load_from_sd(sd: &SdStorage<'_>) {
+        let Ok(file) = sd.read_config(FAULT_FILE) else {
+            return;
+        };
+        for line in file.lines() {
+            match command(line) {
+                Ok(state) => info!("Fault injection: {}", state),
+                Err(e) => warn!("Fault injection: {}", e),
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "fault-injection"))]
+mod imp {
+    use alloc::string::String;
+
+    use super::FaultError;
+
+    #[inline(always)]
+    pub(crate) fn drop_write() -> bool {
+        false
+    }
+
+    #[inline(always)]
+    pub(crate) fn delay_read() {}
+
+    #[inline(always)]
+    pub(crate) fn cut_read(_received: usize) -> bool {
+        false
+    }
+
+    pub(crate) fn command(_args: &str) -> Result<String, FaultError> {
+        Err(FaultError::Disabled)
+    }
+}
@@ -0,0 +1,263 @@
+use alloc::{format, string::String};
+use core::fmt::Write as _;
+use log::warn;
+
+use crate::{
+    clock,
+    data_binding::{DataBindingError, Source},
+    http_client::{HttpClient, JsonError, Method},
+    sdcard::SdStorage,
+    xml::{XmlEvent, XmlReader},
+};
+
+const FEED_FILE: &str = "FEED.TXT";
+pub(crate) const REFRESH_SECS: u64 = 10 * 60;
+pub(crate) const MAX_ITEMS: usize = 10;
+const TIMEOUT_SECS: u64 = 15;
+const MONTHS: [&str; 12] =
+    ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct FeedItem {
+    pub(crate) title: heapless::String<96>,
+    // unix time, feeds without dates leave it out
+    pub(crate) published: Option<i64>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Feed {
+    pub(crate) title: heapless::String<48>,
+    // in document order, which is newest first for nearly every feed
+    pub(crate) items: heapless::Vec<FeedItem, MAX_ITEMS>,
+}
+
+impl FeedItem {
+    // "14:30" for today, "16.05" before that
+    pub(crate) fn when(&self) -> String {
+        let offset = clock::utc_offset_secs();
+        let (Some(published), Some(now)) = (self.published, clock::local_secs_now()) else {
+            return String::new();
+        };
+        let local = (published + offset).max(0);
+        let date = clock::civil(local as u64);
+        if local / 86_400 == now / 86_400 {
+            format!("{:02}:{:02}", date.hour, date.minute)
+        } else {
+            format!("{:02}.{:02}", date.day, date.month)
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Field {
+    None,
+    Title,
+    Date,
+}
+
+// RSS 2.0 (`channel`/`item`, `pubDate`), Atom (`feed`/`entry`, `published`/`updated`) and
+// RSS 1.0 with `dc:date`, told apart only by element names
+struct FeedParser {
+    feed: Feed,
+    item: Option<FeedItem>,
+    field: Field,
+    text: String,
+}
+
+impl FeedParser {
+    fn new() -> Self {
+        Self { feed: Feed::default(), item: None, field: Field::None, text: String::new() }
+    }
+
+    fn event(&mut self, event: XmlEvent<'_>) {
+        match event {
+            XmlEvent::Start("item" | "entry") => self.item = Some(FeedItem::default()),
+            XmlEvent::End("item" | "entry") => {
+                if let Some(item) = self.item.take().filter(|item| !item.title.is_empty()) {
+                    self.feed.items.push(item).ok();
+                }
+            }
+            XmlEvent::Start(name) => {
+                self.field = match name {
+                    "title" => Field::Title,
+                    "pubDate" | "published" | "updated" | "date" => Field::Date,
+                    _ => Field::None,
+                };
+                self.text.clear();
+            }
+            // titles can arrive in pieces around CDATA sections
+            XmlEvent::Text(text) if self.field != Field::None => self.text.push_str(text),
+            XmlEvent::Text(_) => {}
+            XmlEvent::End(_) => {
+                let field = core::mem::replace(&mut self.field, Field::None);
+                match (&mut self.item, field) {
+                    (Some(item), Field::Title) => copy_truncated(&mut item.title, &self.text),
+                    // Atom entries often carry both published and updated, the first one wins
+                    (Some(item), Field::Date) if item.published.is_none() => {
+                        item.published = parse_date(&self.text)
+                    }
+                    (None, Field::Title) if self.feed.title.is_empty() => {
+                        copy_truncated(&mut self.feed.title, &self.text)
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+fn copy_truncated<const N: usize>(out: &mut heapless::String<N>, text: &str) {
+    out.clear();
+    for c in text.chars() {
+        if out.push(if c.is_whitespace() { ' ' } else { c }).is_err() {
+            break;
+        }
+    }
+}
+
+// RFC 822 (`Tue, 10 Jun 2003 04:00:00 GMT`) for RSS, RFC 3339 (`2003-12-13T18:30:02Z`) for Atom
+fn parse_date(text: &str) -> Option<i64> {
+    let text = text.trim();
+    if text.as_bytes().get(4) == Some(&b'-') {
+        parse_rfc3339(text)
+    } else {
+        parse_rfc822(text)
+    }
+}
+
+fn parse_rfc3339(text: &str) -> Option<i64> {
+    let (date, time) = text.split_once(['T', 't', ' '])?;
+    let mut parts = date.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: u8 = parts.next()?.parse().ok()?;
+    let day: u8 = parts.next()?.parse().ok()?;
+    // the zone starts at the first Z or sign after the seconds
+    let zone_at = time.find(['Z', 'z', '+', '-']).unwrap_or(time.len());
+    let (clock_part, zone) = time.split_at(zone_at);
+    let mut hms = clock_part.split('.').next()?.splitn(3, ':');
+    let hour: i64 = hms.next()?.parse().ok()?;
+    let minute: i64 = hms.next()?.parse().ok()?;
+    let second: i64 = hms.next().unwrap_or("0").parse().ok()?;
+    let offset = match zone {
+        "" | "Z" | "z" => 0,
+        zone => parse_offset(&zone.replace(':', ""))?,
+    };
+    Some(civil_secs(year, month, day)? + hour * 3600 + minute * 60 + second - offset)
+}
+
+fn parse_rfc822(text: &str) -> Option<i64> {
+    // the weekday is optional and carries nothing the date doesn't
+    let text = text.split_once(',').map_or(text, |(_, rest)| rest);
+    let mut parts = text.split_ascii_whitespace();
+    let day: u8 = parts.next()?.parse().ok()?;
+    let month = parts.next()?;
+    let month = MONTHS.iter().position(|m| month.eq_ignore_ascii_case(m))? as u8 + 1;
+    let year: i64 = match parts.next()?.parse().ok()? {
+        year @ 0..=99 => 2000 + year,
+        year => year,
+    };
+    let mut hms = parts.next()?.splitn(3, ':');
+    let hour: i64 = hms.next()?.parse().ok()?;
+    let minute: i64 = hms.next()?.parse().ok()?;
+    let second: i64 = hms.next().unwrap_or("0").parse().ok()?;
+    let offset = match parts.next().unwrap_or("GMT") {
+        "GMT" | "UT" | "UTC" | "Z" => 0,
+        "EDT" => -4 * 3600,
+        "EST" | "CDT" => -5 * 3600,
+        "CST" | "MDT" => -6 * 3600,
+        "MST" | "PDT" => -7 * 3600,
+        "PST" => -8 * 3600,
+        zone => parse_offset(zone).unwrap_or(0),
+    };
+    Some(civil_secs(year, month, day)? + hour * 3600 + minute * 60 + second - offset)
+}
+
+// `+0200` / `-0530`, in seconds east of UTC
+fn parse_offset(zone: &str) -> Option<i64> {
+    let (sign, digits) = match zone.as_bytes().first()? {
+        b'+' => (1, &zone[1..]),
+        b'-' => (-1, &zone[1..]),
+        _ => return None,
+    };
+    if digits.len() != 4 || !digits.is_ascii() {
+        return None;
+    }
+    let hours: i64 = digits[..2].parse().ok()?;
+    let minutes: i64 = digits[2..].parse().ok()?;
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+fn civil_secs(year: i64, month: u8, day: u8) -> Option<i64> {
+    let valid = (1..=12).contains(&month) && (1..=31).contains(&day);
+    valid.then(|| clock::days_from_civil(year, month, day) * 86_400)
+}
+
+// GETs a feed route with the validators of the last answer, a 304 costs no parsing at all
+// and leaves the ticker as it is
+pub(crate) struct FeedSource<F> {
+    route: &'static str,
+    sink: F,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl<F: FnMut(Feed)> FeedSource<F> {
+    pub(crate) fn new(route: &'static str, sink: F) -> Self {
+        Self { route, sink, etag: None, last_modified: None }
+    }
+}
+
+impl<F: FnMut(Feed)> Source for FeedSource<F> {
+    fn fetch(&mut self, http: &mut HttpClient<'_>) -> Result<(), DataBindingError> {
+        let route = self.route;
+        let mut headers = String::new();
+        if let Some(etag) = &self.etag {
+            write!(headers, "If-None-Match: {}\r\n", etag).ok();
+        }
+        if let Some(last_modified) = &self.last_modified {
+            write!(headers, "If-Modified-Since: {}\r\n", last_modified).ok();
+        }
+        // parsed as it arrives, only the items are held and never the document
+        let mut reader = XmlReader::new();
+        let mut parser = FeedParser::new();
+        let response = http
+            .request_streaming(Method::Get, route, &headers, TIMEOUT_SECS, |chunk| {
+                reader.feed(chunk, |event| parser.event(event))
+            })
+            .map_err(|e| DataBindingError::Fetch(route, JsonError::Http(e)))?;
+        if response.status == 304 {
+            return Ok(());
+        }
+        if !(200..300).contains(&response.status) {
+            return Err(DataBindingError::Fetch(route, JsonError::Status(response.status)));
+        }
+        // a cut off download shows the items before the cut but is fetched in full next time
+        let complete = response.complete;
+        self.etag = response.header("etag").filter(|_| complete).map(String::from);
+        self.last_modified =
+            response.header("last-modified").filter(|_| complete).map(String::from);
+
+        let feed = parser.feed;
+        if feed.items.is_empty() && feed.title.is_empty() {
+            // a cached copy must not be validated against a document we couldn't read
+            self.etag = None;
+            self.last_modified = None;
+            return Err(DataBindingError::Unusable(route, "not an RSS or Atom feed"));
+        }
+        (self.sink)(feed);
+        Ok(())
+    }
+}
+
+// the first non-comment line of FEED.TXT is the route of the feed on the server, which
+// proxies it (the client only speaks plain http)
+pub(crate) fn load_route(sd: &SdStorage<'_>) -> Option<&'static str> {
+    let file = sd.read_config(FEED_FILE).ok()?;
+    let route = file.lines().next()?;
+    if !route.starts_with('/') {
+        warn!("Feed: route must start with /, got {}", route);
+        return None;
+    }
+    // read once per boot, leaking it gives the data binding its &'static str
+    Some(String::from(route).leak())
+}
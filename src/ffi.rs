@@ -0,0 +1,199 @@
+// C API for application logic written in C (`ffi` feature), declared in include/esp32_slint.h.
+// The firmware stays the platform: once the peripherals are up it calls the C side's
+// `esp32_slint_app_start`, which calls `esp32_slint_init` and registers its callbacks. Calls
+// from C only queue; the UI loop drains the queue every pass and runs the callbacks itself, so
+// C code never reaches the UI or the rule engine from another context.
+use alloc::{collections::VecDeque, ffi::CString, format, string::String, vec::Vec};
+use core::{
+    cell::RefCell,
+    ffi::{CStr, c_char, c_void},
+};
+use critical_section::Mutex;
+
+use crate::events::EventHooks;
+
+// ESP32_SLINT_API_VERSION, bumped on incompatible changes to the header
+const API_VERSION: u32 = 1;
+const QUEUE_LEN: usize = 32;
+
+// return codes, ESP32_SLINT_* in the header
+const OK: i32 = 0;
+const ERR_ARGUMENT: i32 = -1;
+const ERR_VERSION: i32 = -2;
+const ERR_FULL: i32 = -3;
+const ERR_PROPERTY: i32 = -4;
+
+// ESP32_SLINT_EVENT_*, what a callback is registered for
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Event {
+    // text: the address
+    IpAcquired = 0,
+    WifiLost = 1,
+    // text: the frame
+    WsMessage = 2,
+    PowerFail = 3,
+    // value: free heap bytes
+    LowHeap = 4,
+}
+
+impl Event {
+    fn from_raw(raw: u32) -> Option<Self> {
+        Some(match raw {
+            0 => Event::IpAcquired,
+            1 => Event::WifiLost,
+            2 => Event::WsMessage,
+            3 => Event::PowerFail,
+            4 => Event::LowHeap,
+            _ => return None,
+        })
+    }
+}
+
+type Callback = extern "C" fn(event: u32, text: *const c_char, value: f32, user: *mut c_void);
+
+// the C side's context pointer, only ever handed back to it
+#[derive(Clone, Copy)]
+struct UserData(*mut c_void);
+
+unsafe impl Send for UserData {}
+
+#[derive(Clone, Copy)]
+struct Registration {
+    event: Event,
+    callback: Callback,
+    user: UserData,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Property {
+    RemoteText,
+    Status,
+    Notification,
+}
+
+impl Property {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "remote_text" => Property::RemoteText,
+            "status" => Property::Status,
+            "notification" => Property::Notification,
+            _ => return None,
+        })
+    }
+}
+
+// queued by C, applied by the UI loop
+pub(crate) enum Incoming {
+    Property(Property, String),
+    // a rule engine channel
+    Value(String, f32),
+}
+
+static CALLBACKS: Mutex<RefCell<Vec<Registration>>> = Mutex::new(RefCell::new(Vec::new()));
+static QUEUE: Mutex<RefCell<VecDeque<Incoming>>> = Mutex::new(RefCell::new(VecDeque::new()));
+
+unsafe extern "C" {
+    // the C application's entry point, called once from the UI loop before it starts
+    fn esp32_slint_app_start();
+}
+
+pub(crate) fn start_app() {
+    unsafe { esp32_slint_app_start() };
+}
+
+// forwards the lifecycle events to whatever C registered for them
+pub(crate) fn forward_events(events: &mut EventHooks) {
+    events
+        .on_ip_acquired(|info| dispatch(Event::IpAcquired, &format!("{}", info.ip), 0.0))
+        .on_wifi_lost(|| dispatch(Event::WifiLost, "", 0.0))
+        .on_ws_message(|frame| dispatch(Event::WsMessage, frame, 0.0))
+        .on_power_fail(|| dispatch(Event::PowerFail, "", 0.0))
+        .on_low_heap(|stats| dispatch(Event::LowHeap, "", stats.heap_free as f32));
+}
+
+// what C queued since the last pass
+pub(crate) fn drain() -> VecDeque<Incoming> {
+    critical_section::with(|cs| core::mem::take(&mut *QUEUE.borrow_ref_mut(cs)))
+}
+
+fn dispatch(event: Event, text: &str, value: f32) {
+    let registrations: Vec<Registration> = critical_section::with(|cs| {
+        CALLBACKS.borrow_ref(cs).iter().filter(|r| r.event == event).copied().collect()
+    });
+    if registrations.is_empty() {
+        return;
+    }
+    // a frame with a NUL in it reaches C cut short rather than not at all
+    let text = CString::new(text.split('\0').next().unwrap_or("")).unwrap_or_default();
+    // outside the critical section, a callback may well call back into the API
+    for registration in registrations {
+        (registration.callback)(event as u32, text.as_ptr(), value, registration.user.0);
+    }
+}
+
+fn queue(incoming: Incoming) -> i32 {
+    critical_section::with(|cs| {
+        let mut queue = QUEUE.borrow_ref_mut(cs);
+        if queue.len() >= QUEUE_LEN {
+            return ERR_FULL;
+        }
+        queue.push_back(incoming);
+        OK
+    })
+}
+
+// None for a null pointer or text that isn't UTF-8
+unsafe fn text<'a>(ptr: *const c_char) -> Option<&'a str> {
+    if ptr.is_null() {
+        return None;
+    }
+    unsafe { CStr::from_ptr(ptr) }.to_str().ok()
+}
+
+// checks the header the C side was built against and drops callbacks from an earlier start
+#[unsafe(no_mangle)]
+pub extern "C" fn esp32_slint_init(api_version: u32) -> i32 {
+    if api_version != API_VERSION {
+        return ERR_VERSION;
+    }
+    critical_section::with(|cs| CALLBACKS.borrow_ref_mut(cs).clear());
+    OK
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn esp32_slint_register_callback(
+    event: u32,
+    callback: Option<Callback>,
+    user: *mut c_void,
+) -> i32 {
+    let (Some(event), Some(callback)) = (Event::from_raw(event), callback) else {
+        return ERR_ARGUMENT;
+    };
+    let registration = Registration { event, callback, user: UserData(user) };
+    critical_section::with(|cs| CALLBACKS.borrow_ref_mut(cs).push(registration));
+    OK
+}
+
+// `name` and `value` are NUL-terminated UTF-8, copied before this returns
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn esp32_slint_set_property(
+    name: *const c_char,
+    value: *const c_char,
+) -> i32 {
+    let (Some(name), Some(value)) = (unsafe { text(name) }, unsafe { text(value) }) else {
+        return ERR_ARGUMENT;
+    };
+    match Property::from_name(name) {
+        Some(property) => queue(Incoming::Property(property, value.into())),
+        None => ERR_PROPERTY,
+    }
+}
+
+// `name` is a NUL-terminated rule engine channel, rules can compare against it like any other
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn esp32_slint_push_event(name: *const c_char, value: f32) -> i32 {
+    match unsafe { text(name) } {
+        Some(name) if !name.is_empty() => queue(Incoming::Value(name.into(), value)),
+        _ => ERR_ARGUMENT,
+    }
+}
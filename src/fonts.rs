@@ -0,0 +1,142 @@
+use alloc::{boxed::Box, vec::Vec};
+use i_slint_core::{
+    graphics::{BitmapFont, BitmapGlyph, BitmapGlyphs, CharacterMapEntry},
+    renderer::RendererSealed,
+    slice::Slice,
+    window::WindowInner,
+};
+use thiserror::Error;
+
+use crate::sdcard::{SdCardError, SdStorage};
+
+pub(crate) const FALLBACK_FONT_PATH: &str = "FONTS/FALLBACK.GLF";
+// the UI asks for this family, while nothing is registered under it the embedded font is used
+pub(crate) const FALLBACK_FAMILY: &str = "Fallback";
+
+const MAGIC: &[u8; 4] = b"GLF1";
+
+#[derive(Error, Debug)]
+pub(crate) enum FontError {
+    #[error("Failed to read font pack: {0}")]
+    Io(#[from] SdCardError),
+    #[error("Not a glyph pack")]
+    BadMagic,
+    #[error("Truncated glyph pack")]
+    Truncated,
+}
+
+// The software renderer picks a single bitmap font per text run and skips glyphs it lacks,
+// there is no per-glyph fallback at draw time. The fallback therefore happens when the pack
+// is built: make_font_pack.py takes every glyph the primary face has and fills the missing
+// ones (diacritics, CJK) from the secondary face. Once registered, the pack takes over from
+// the embedded font, which only holds the characters the .slint files use.
+//
+// Pack layout, little endian:
+//   "GLF1", u16 weight, u16 units_per_em, i16 ascent, i16 descent, i16 x_height,
+//   i16 cap_height, u16 glyph_count, u8 set_count,
+//   glyph_count x u32 code point (ascending),
+//   per set: u16 pixel_size, then per glyph: i16 x, i16 y, i16 width, i16 height,
+//   i16 x_advance (x, y, advance are 26.6 fixed point), u16 len, len bytes of alpha
+pub(crate) fn load_fallback(
+    sd: &SdStorage<'_>,
+    path: &str,
+) -> Result<&'static BitmapFont, FontError> {
+    let data = sd.read_to_vec(path)?;
+    // registered fonts live as long as the renderer, which is the whole program
+    Ok(Box::leak(Box::new(parse(&data)?)))
+}
+
+pub(crate) fn register(window: &slint::Window, font: &'static BitmapFont) {
+    WindowInner::from_pub(window)
+        .window_adapter()
+        .renderer()
+        .register_bitmap_font(font);
+}
+
+fn parse(data: &[u8]) -> Result<BitmapFont, FontError> {
+    let mut reader = Reader { data, pos: 0 };
+    if reader.take(4)? != MAGIC {
+        return Err(FontError::BadMagic);
+    }
+
+    let weight = reader.u16()?;
+    let units_per_em = reader.u16()? as f32;
+    let ascent = reader.i16()? as f32;
+    let descent = reader.i16()? as f32;
+    let x_height = reader.i16()? as f32;
+    let cap_height = reader.i16()? as f32;
+    let glyph_count = reader.u16()? as usize;
+    let set_count = reader.u8()? as usize;
+
+    let mut character_map = Vec::with_capacity(glyph_count);
+    for glyph_index in 0..glyph_count {
+        let code_point = char::from_u32(reader.u32()?).unwrap_or(char::REPLACEMENT_CHARACTER);
+        character_map.push(CharacterMapEntry { code_point, glyph_index: glyph_index as u16 });
+    }
+
+    let mut glyph_sets = Vec::with_capacity(set_count);
+    for _ in 0..set_count {
+        let pixel_size = reader.i16()?;
+        let mut glyphs = Vec::with_capacity(glyph_count);
+        for _ in 0..glyph_count {
+            let x = reader.i16()?;
+            let y = reader.i16()?;
+            let width = reader.i16()?;
+            let height = reader.i16()?;
+            let x_advance = reader.i16()?;
+            let len = reader.u16()? as usize;
+            let alpha: &'static [u8] = Box::leak(reader.take(len)?.into());
+            let data = Slice::from_slice(alpha);
+            glyphs.push(BitmapGlyph { x, y, width, height, x_advance, data });
+        }
+        glyph_sets.push(BitmapGlyphs {
+            pixel_size,
+            glyph_data: Slice::from_slice(Box::leak(glyphs.into_boxed_slice())),
+        });
+    }
+
+    Ok(BitmapFont {
+        family_name: Slice::from_slice(FALLBACK_FAMILY.as_bytes()),
+        character_map: Slice::from_slice(Box::leak(character_map.into_boxed_slice())),
+        units_per_em,
+        ascent,
+        descent,
+        x_height,
+        cap_height,
+        glyphs: Slice::from_slice(Box::leak(glyph_sets.into_boxed_slice())),
+        weight,
+        italic: false,
+        sdf: false,
+    })
+}
+
+struct Reader<'d> {
+    data: &'d [u8],
+    pos: usize,
+}
+
+impl<'d> Reader<'d> {
+    fn take(&mut self, len: usize) -> Result<&'d [u8], FontError> {
+        let bytes = self.data.get(self.pos..self.pos + len).ok_or(FontError::Truncated)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, FontError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, FontError> {
+        let b = self.take(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn i16(&mut self) -> Result<i16, FontError> {
+        Ok(self.u16()? as i16)
+    }
+
+    fn u32(&mut self) -> Result<u32, FontError> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+}
@@ -0,0 +1,149 @@
+// FocalTech FT6236 / FT6336 / FT6206 capacitive touch over I2C, the `ft6236` feature builds it in
+// place of the XPT2046. The controller tracks up to two fingers; slint only gets the first one
+// down, until it lifts, any second finger is ignored. INT is held low while a finger is on the
+// panel and shares the PENIRQ handler in `touch_input`.
+use esp_hal::{
+    Blocking,
+    gpio::{Input, InputPin, Io, interconnect::PeripheralOutput},
+    i2c::master::{self, I2c},
+    time::Rate,
+};
+
+use crate::{
+    display_screen::ScreenOrientation,
+    touch_input::{
+        self, TouchCalibration, TouchInputError, TouchInputProvider, TouchInputResponse,
+    },
+};
+
+const ADDRESS: u8 = 0x38;
+const REG_TD_STATUS: u8 = 0x02;
+// G_MODE: 0 keeps INT low for as long as a touch is present
+const REG_INTERRUPT_MODE: u8 = 0xA4;
+const REG_VENDOR_ID: u8 = 0xA8;
+const FOCALTECH_VENDOR_ID: u8 = 0x11;
+const MAX_POINTS: usize = 2;
+// bits 7:6 of a point's XH register
+const EVENT_LIFT_UP: u8 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct TouchPoint {
+    id: u8,
+    x: i32,
+    y: i32,
+}
+
+pub(crate) struct Ft6236TouchInput<'a> {
+    i2c: I2c<'a, Blocking>,
+    // only listened to, see `touch_input::listen_irq`
+    _irq: Input<'a>,
+    // the finger slint follows and where it was last seen
+    primary: Option<(u8, i32, i32)>,
+    // a second finger stayed after the primary lifted, nothing is reported until all lift
+    ignoring: bool,
+    calibration: TouchCalibration,
+    orientation: ScreenOrientation,
+}
+
+impl<'a> Ft6236TouchInput<'a> {
+    pub(crate) fn create(
+        i2c: impl master::Instance + 'a,
+        sda_pin: impl PeripheralOutput<'a>,
+        scl_pin: impl PeripheralOutput<'a>,
+        irq_pin: impl InputPin + 'a,
+        io: &mut Io<'_>,
+        calibration: TouchCalibration,
+        orientation: ScreenOrientation,
+    ) -> Result<Self, TouchInputError> {
+        let config = master::Config::default().with_frequency(Rate::from_khz(400));
+        let i2c = I2c::new(i2c, config)
+            .map_err(|_| TouchInputError::Ft6236Init)?
+            .with_sda(sda_pin)
+            .with_scl(scl_pin);
+        let irq = touch_input::listen_irq(irq_pin, io);
+        Ok(Self { i2c, _irq: irq, primary: None, ignoring: false, calibration, orientation })
+    }
+
+    pub(crate) fn is_active(&self) -> bool {
+        self.primary.is_some() || self.ignoring
+    }
+
+    // follows the display, the calibration itself stays in landscape panel pixels
+    pub(crate) fn set_orientation(&mut self, orientation: ScreenOrientation) {
+        self.orientation = orientation;
+    }
+
+    pub(crate) fn init(&mut self) -> Result<(), TouchInputError> {
+        let mut vendor = [0u8];
+        self.i2c
+            .write_read(ADDRESS, &[REG_VENDOR_ID], &mut vendor)
+            .map_err(|_| TouchInputError::Ft6236Init)?;
+        if vendor[0] != FOCALTECH_VENDOR_ID {
+            return Err(TouchInputError::Ft6236Init);
+        }
+        self.i2c
+            .write(ADDRESS, &[REG_INTERRUPT_MODE, 0])
+            .map_err(|_| TouchInputError::Ft6236Init)
+    }
+
+    // the fingers on the panel in landscape window pixels
+    fn read_points(&mut self) -> Result<heapless::Vec<TouchPoint, MAX_POINTS>, TouchInputError> {
+        // TD_STATUS, then 6 registers per point
+        let mut regs = [0u8; 1 + 6 * MAX_POINTS];
+        self.i2c
+            .write_read(ADDRESS, &[REG_TD_STATUS], &mut regs)
+            .map_err(|_| TouchInputError::AcquireInputData)?;
+        let count = match (regs[0] & 0x0F) as usize {
+            // the register idles at 0x0F before the first touch
+            count if count > MAX_POINTS => 0,
+            count => count,
+        };
+        let mut points = heapless::Vec::new();
+        for point in regs[1..].chunks(6).take(count) {
+            if point[0] >> 6 == EVENT_LIFT_UP {
+                continue;
+            }
+            let raw_x = u16::from_be_bytes([point[0] & 0x0F, point[1]]) as i32;
+            let raw_y = u16::from_be_bytes([point[2] & 0x0F, point[3]]) as i32;
+            // the controller reports in the panel's portrait pixels
+            let (x, y) = self.calibration.apply(raw_y, raw_x);
+            let (x, y) = self.orientation.map_touch(x, y);
+            let _ = points.push(TouchPoint { id: point[2] >> 4, x, y });
+        }
+        Ok(points)
+    }
+}
+
+impl<'a> TouchInputProvider for Ft6236TouchInput<'a> {
+    fn get_input(&mut self) -> Result<TouchInputResponse, TouchInputError> {
+        if !self.is_active() && !touch_input::take_irq() {
+            return Ok(TouchInputResponse::NoInput);
+        }
+
+        let points = self.read_points()?;
+        match self.primary {
+            Some((id, last_x, last_y)) => match points.iter().find(|p| p.id == id) {
+                Some(p) => {
+                    self.primary = Some((id, p.x, p.y));
+                    Ok(TouchInputResponse::Moved { x: p.x, y: p.y })
+                }
+                None => {
+                    self.primary = None;
+                    self.ignoring = !points.is_empty();
+                    Ok(TouchInputResponse::Released { x: last_x, y: last_y })
+                }
+            },
+            None if self.ignoring => {
+                self.ignoring = !points.is_empty();
+                Ok(TouchInputResponse::NoInput)
+            }
+            None => match points.first() {
+                Some(p) => {
+                    self.primary = Some((p.id, p.x, p.y));
+                    Ok(TouchInputResponse::Pressed { x: p.x, y: p.y })
+                }
+                None => Ok(TouchInputResponse::NoInput),
+            },
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use alloc::{format, vec::Vec};
+use core::fmt;
+use esp_hal::gpio::{DriveMode, Flex, InputConfig, Level, OutputConfig, Pull};
+
+use crate::rules::RuleEngine;
+
+// pins nothing on the board uses, see the wiring comments in `run_event_loop`. 1/3 are the
+// serial console, 6-11 the flash, 12/22 the stepper, 16/17 the servo and PWM outputs; 35 has
+// no output driver
+pub(crate) const SPARE_PINS: &[SparePin] = &[
+    // the capacitive touch controller's I2C takes 5
+    #[cfg(not(feature = "ft6236"))]
+    SparePin { number: 5, input_only: false },
+    // the battery divider takes 35
+    #[cfg(not(feature = "battery"))]
+    SparePin { number: 35, input_only: true },
+];
+pub(crate) const PIN_COUNT: usize = SPARE_PINS.len();
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct SparePin {
+    pub(crate) number: u8,
+    pub(crate) input_only: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum PinMode {
+    // input and output disabled, the pin floats
+    #[default]
+    Unused = 0,
+    Input = 1,
+    InputPullUp = 2,
+    Output = 3,
+    // open drain with the pull-up, read by `sensors`
+    OneWire = 4,
+    Dht22 = 5,
+}
+
+impl PinMode {
+    fn from_u8(value: u8) -> Option<Self> {
+        Some(match value {
+            0 => PinMode::Unused,
+            1 => PinMode::Input,
+            2 => PinMode::InputPullUp,
+            3 => PinMode::Output,
+            4 => PinMode::OneWire,
+            5 => PinMode::Dht22,
+            _ => return None,
+        })
+    }
+
+    // the order the UI steps through, skipping output on input-only pins
+    fn next(self, pin: &SparePin) -> Self {
+        match self {
+            PinMode::Unused => PinMode::Input,
+            PinMode::Input => PinMode::InputPullUp,
+            PinMode::InputPullUp if !pin.input_only => PinMode::Output,
+            PinMode::Output => PinMode::OneWire,
+            PinMode::OneWire => PinMode::Dht22,
+            PinMode::InputPullUp | PinMode::Dht22 => PinMode::Unused,
+        }
+    }
+
+    pub(crate) fn is_input(&self) -> bool {
+        matches!(self, PinMode::Input | PinMode::InputPullUp)
+    }
+
+    pub(crate) fn is_sensor(&self) -> bool {
+        matches!(self, PinMode::OneWire | PinMode::Dht22)
+    }
+}
+
+impl fmt::Display for PinMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PinMode::Unused => "unused",
+            PinMode::Input => "input",
+            PinMode::InputPullUp => "pull-up",
+            PinMode::Output => "output",
+            PinMode::OneWire => "ds18b20",
+            PinMode::Dht22 => "dht22",
+        })
+    }
+}
+
+// what is kept in the settings per spare pin
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct PinConfig {
+    pub(crate) mode: PinMode,
+    // inputs report to the rule engine as gpio<n>, outputs follow `output <n>` rules
+    pub(crate) bound: bool,
+    // the level an output is driven to
+    pub(crate) high: bool,
+}
+
+impl PinConfig {
+    // [mode, flags]
+    pub(crate) fn encode(&self) -> [u8; 2] {
+        [self.mode as u8, (self.bound as u8) | ((self.high as u8) << 1)]
+    }
+
+    pub(crate) fn decode(value: &[u8]) -> Option<Self> {
+        let [mode, flags] = value else {
+            return None;
+        };
+        Some(Self { mode: PinMode::from_u8(*mode)?, bound: flags & 1 != 0, high: flags & 2 != 0 })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum GpioCommand {
+    CycleMode(usize),
+    // flips an output's level
+    Toggle(usize),
+    Bind(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct PinView {
+    pub(crate) pin: SparePin,
+    pub(crate) config: PinConfig,
+    // read back from the pad, also for outputs
+    pub(crate) high: bool,
+}
+
+// the spare pins as flexible pads, reconfigured whenever the UI changes a mode
+pub(crate) struct GpioPins<'d> {
+    pins: [Flex<'d>; PIN_COUNT],
+    configs: [PinConfig; PIN_COUNT],
+}
+
+impl<'d> GpioPins<'d> {
+    pub(crate) fn new(pins: [Flex<'d>; PIN_COUNT], configs: [PinConfig; PIN_COUNT]) -> Self {
+        let mut gpio = Self { pins, configs };
+        for i in 0..PIN_COUNT {
+            gpio.configure(i);
+        }
+        gpio
+    }
+
+    pub(crate) fn configs(&self) -> [PinConfig; PIN_COUNT] {
+        self.configs
+    }
+
+    // true when the settings need saving
+    pub(crate) fn apply(&mut self, command: GpioCommand) -> bool {
+        let (GpioCommand::CycleMode(i) | GpioCommand::Toggle(i) | GpioCommand::Bind(i)) = command;
+        let Some(config) = self.configs.get_mut(i) else {
+            return false;
+        };
+        match command {
+            GpioCommand::CycleMode(_) => config.mode = config.mode.next(&SPARE_PINS[i]),
+            GpioCommand::Toggle(_) if config.mode == PinMode::Output => config.high = !config.high,
+            GpioCommand::Toggle(_) => return false,
+            GpioCommand::Bind(_) => config.bound = !config.bound,
+        }
+        self.configure(i);
+        true
+    }
+
+    // a bound output pin with that GPIO number follows the rule, false if there is none
+    pub(crate) fn drive(&mut self, number: u8, high: bool) -> bool {
+        let Some(i) = SPARE_PINS.iter().position(|p| p.number == number) else {
+            return false;
+        };
+        let config = &mut self.configs[i];
+        if config.mode != PinMode::Output || !config.bound {
+            return false;
+        }
+        // not saved, the rule sets it again after a restart
+        config.high = high;
+        self.pins[i].set_level(Level::from(high));
+        true
+    }
+
+    pub(crate) fn observe(&self, rule_engine: &mut RuleEngine) {
+        for ((pin, config), flex) in SPARE_PINS.iter().zip(&self.configs).zip(&self.pins) {
+            if config.bound && config.mode.is_input() {
+                let value = if flex.is_high() { 1.0 } else { 0.0 };
+                rule_engine.observe(&format!("gpio{}", pin.number), value);
+            }
+        }
+    }
+
+    // the pads `sensors` reads, by GPIO number
+    pub(crate) fn sensor_pins(&mut self) -> impl Iterator<Item = (u8, PinMode, &mut Flex<'d>)> {
+        SPARE_PINS
+            .iter()
+            .zip(&self.configs)
+            .zip(self.pins.iter_mut())
+            .filter(|((_, config), _)| config.mode.is_sensor())
+            .map(|((pin, config), flex)| (pin.number, config.mode, flex))
+    }
+
+    pub(crate) fn view(&self) -> Vec<PinView> {
+        SPARE_PINS
+            .iter()
+            .zip(&self.configs)
+            .zip(&self.pins)
+            .map(|((pin, config), flex)| PinView {
+                pin: *pin,
+                config: *config,
+                high: config.mode != PinMode::Unused && flex.is_high(),
+            })
+            .collect()
+    }
+
+    fn configure(&mut self, i: usize) {
+        let config = self.configs[i];
+        let flex = &mut self.pins[i];
+        match config.mode {
+            PinMode::Unused => {
+                flex.set_output_enable(false);
+                flex.set_input_enable(false);
+            }
+            PinMode::Input | PinMode::InputPullUp => {
+                flex.set_output_enable(false);
+                let pull = if config.mode == PinMode::InputPullUp { Pull::Up } else { Pull::None };
+                flex.apply_input_config(&InputConfig::default().with_pull(pull));
+                flex.set_input_enable(true);
+            }
+            PinMode::Output => {
+                flex.set_level(Level::from(config.high));
+                flex.apply_output_config(&OutputConfig::default());
+                flex.set_output_enable(true);
+                // keeps the level readable for the page
+                flex.set_input_enable(true);
+            }
+            PinMode::OneWire | PinMode::Dht22 => {
+                // released until a read pulls it low
+                flex.set_level(Level::High);
+                let open_drain = OutputConfig::default()
+                    .with_drive_mode(DriveMode::OpenDrain)
+                    .with_pull(Pull::Up);
+                flex.apply_output_config(&open_drain);
+                flex.set_output_enable(true);
+                flex.set_input_enable(true);
+            }
+        }
+    }
+}
@@ -0,0 +1,143 @@
+// Labelled allocator snapshots for the `heap` console command, to see what grew between two
+// interactions: `heap snap before`, tap around, `heap diff before`.
+use alloc::{collections::VecDeque, format, string::String};
+use core::fmt::Write;
+use esp_hal::time::Instant;
+use thiserror::Error;
+
+use crate::telemetry::{self, MemoryStats};
+
+const MAX_SNAPSHOTS: usize = 8;
+const LABEL_LEN: usize = 16;
+
+#[derive(Error, Debug)]
+pub(crate) enum HeapError {
+    #[error("Unknown heap command: {0}")]
+    UnknownCommand(String),
+    #[error("No snapshot named {0}")]
+    UnknownSnapshot(String),
+    #[error("No snapshots taken yet, use `heap snap`")]
+    Empty,
+}
+
+struct Snapshot {
+    label: heapless::String<LABEL_LEN>,
+    taken_at: Instant,
+    stats: MemoryStats,
+}
+
+// oldest snapshots are dropped once the list is full
+pub(crate) struct HeapSnapshots {
+    snapshots: VecDeque<Snapshot>,
+    taken: u32,
+}
+
+impl HeapSnapshots {
+    pub(crate) fn new() -> Self {
+        Self { snapshots: VecDeque::with_capacity(MAX_SNAPSHOTS), taken: 0 }
+    }
+
+    // `heap [now | snap [label] | diff [from [to]] | list | clear]`
+    pub(crate) fn command(&mut self, args: &str) -> Result<String, HeapError> {
+        let mut parts = args.split_whitespace();
+        match parts.next() {
+            None | Some("now") => Ok(format!("{}", telemetry::sample())),
+            Some("snap") => Ok(self.snap(parts.next())),
+            Some("diff") => self.diff(parts.next(), parts.next()),
+            Some("list") => Ok(self.list()),
+            Some("clear") => {
+                self.snapshots.clear();
+                Ok(String::from("snapshots cleared"))
+            }
+            Some(other) => Err(HeapError::UnknownCommand(other.into())),
+        }
+    }
+
+    fn snap(&mut self, label: Option<&str>) -> String {
+        self.taken = self.taken.wrapping_add(1);
+        let mut name = heapless::String::new();
+        match label {
+            Some(label) => {
+                for c in label.chars() {
+                    if name.push(c).is_err() {
+                        break;
+                    }
+                }
+            }
+            None => {
+                let _ = write!(name, "#{}", self.taken);
+            }
+        }
+
+        // a label taken again replaces the old snapshot
+        self.snapshots.retain(|s| s.label != name);
+        if self.snapshots.len() == MAX_SNAPSHOTS {
+            self.snapshots.pop_front();
+        }
+        let stats = telemetry::sample();
+        let out = format!("{}: {}", name, stats);
+        self.snapshots.push_back(Snapshot { label: name, taken_at: Instant::now(), stats });
+        out
+    }
+
+    // no arguments compares the latest snapshot with now, one compares that snapshot with now
+    fn diff(&self, from: Option<&str>, to: Option<&str>) -> Result<String, HeapError> {
+        let from = match from {
+            Some(label) => self.find(label)?,
+            None => self.snapshots.back().ok_or(HeapError::Empty)?,
+        };
+        let (to_label, to_stats, to_time) = match to {
+            Some(label) => {
+                let to = self.find(label)?;
+                (to.label.as_str(), to.stats, to.taken_at)
+            }
+            None => ("now", telemetry::sample(), Instant::now()),
+        };
+
+        let elapsed = to_time.duration_since_epoch().as_millis() as i64
+            - from.taken_at.duration_since_epoch().as_millis() as i64;
+        let mut out = format!("{} -> {} ({} ms):", from.label, to_label, signed(elapsed));
+        let (a, b) = (&from.stats, &to_stats);
+        for (name, before, after) in [
+            ("heap_used", a.heap_used, b.heap_used),
+            ("heap_free", a.heap_free, b.heap_free),
+            ("internal_free", a.internal_free, b.internal_free),
+            ("largest_free_block", a.largest_free_block, b.largest_free_block),
+            ("stack_used", a.stack_used, b.stack_used),
+        ] {
+            let _ = write!(out, " {} {}", name, signed(after as i64 - before as i64));
+        }
+        Ok(out)
+    }
+
+    fn list(&self) -> String {
+        if self.snapshots.is_empty() {
+            return String::from("no snapshots");
+        }
+        let mut out = String::new();
+        for (i, snapshot) in self.snapshots.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            let _ = write!(
+                out,
+                "{} at {} ms: {}",
+                snapshot.label,
+                snapshot.taken_at.duration_since_epoch().as_millis(),
+                snapshot.stats
+            );
+        }
+        out
+    }
+
+    fn find(&self, label: &str) -> Result<&Snapshot, HeapError> {
+        self.snapshots
+            .iter()
+            .find(|s| s.label == label)
+            .ok_or_else(|| HeapError::UnknownSnapshot(label.into()))
+    }
+}
+
+fn signed(delta: i64) -> String {
+    if delta > 0 { format!("+{}", delta) } else { format!("{}", delta) }
+}
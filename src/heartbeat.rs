@@ -0,0 +1,114 @@
+// Dead-man's switch: the backend raises an alert when the unit goes quiet, whatever the reason.
+// HEARTBEAT.TXT on the card turns it on: `route /api/heartbeat`, then optionally `every <secs>`
+// (300) and `jitter <pct>` (10). Each heartbeat goes out through the outbox:
+//   -> {"device":"a1b2c3d4e5f6","seq":12,"timestamp":1760000000,"uptime":3600,"next_in":330}
+// `next_in` is the latest the next one is due in seconds, jitter included; once that has passed
+// without one the server alerts. The jitter keeps units restarted together from posting in step.
+// Only the newest heartbeat is worth delivering, it replaces one still waiting in the outbox;
+// one that hasn't got through after LATE_SECS shows on home.
+use alloc::{format, string::String};
+use esp_hal::{
+    rng::Rng,
+    time::{Duration, Instant},
+};
+use log::warn;
+
+use crate::{clock, sdcard::SdStorage};
+
+const CONFIG_FILE: &str = "HEARTBEAT.TXT";
+const INTERVAL_SECS: u64 = 300;
+const JITTER_PERCENT: u64 = 10;
+const LATE_SECS: u64 = 60;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct HeartbeatConfig {
+    pub(crate) route: String,
+    pub(crate) interval_secs: u64,
+    // either way of the interval
+    pub(crate) jitter_percent: u64,
+}
+
+// None without the file or a route in it
+pub(crate) fn load_config(sd: &SdStorage<'_>) -> Option<HeartbeatConfig> {
+    let file = sd.read_config(CONFIG_FILE).ok()?;
+    let mut route = None;
+    let mut interval_secs = INTERVAL_SECS;
+    let mut jitter_percent = JITTER_PERCENT;
+    for line in file.lines() {
+        let mut words = line.split_whitespace();
+        let (key, value) = (words.next(), words.next());
+        match (key, value, value.map(str::parse::<u64>)) {
+            (Some("route"), Some(path), _) if path.starts_with('/') => route = Some(path.into()),
+            (Some("every"), _, Some(Ok(secs))) if secs > 0 => interval_secs = secs,
+            (Some("jitter"), _, Some(Ok(pct))) if pct < 100 => jitter_percent = pct,
+            _ => warn!("Heartbeat: malformed line {}", line),
+        }
+    }
+    let Some(route) = route else {
+        warn!("Heartbeat: {} has no route", CONFIG_FILE);
+        return None;
+    };
+    Some(HeartbeatConfig { route, interval_secs, jitter_percent })
+}
+
+pub(crate) struct Heartbeat {
+    config: HeartbeatConfig,
+    seq: u32,
+    due: Instant,
+    // the oldest heartbeat not delivered yet
+    waiting_since: Option<Instant>,
+}
+
+impl Heartbeat {
+    // the first one goes out right away
+    pub(crate) fn new(config: HeartbeatConfig) -> Self {
+        Self { config, seq: 0, due: Instant::now(), waiting_since: None }
+    }
+
+    pub(crate) fn route(&self) -> &str {
+        &self.config.route
+    }
+
+    fn spread_secs(&self) -> u64 {
+        self.config.interval_secs * self.config.jitter_percent / 100
+    }
+
+    // somewhere in interval ± spread
+    fn next_delay(&self) -> Duration {
+        let spread = self.spread_secs();
+        let offset = Rng::new().random() as u64 % (2 * spread + 1);
+        Duration::from_secs(self.config.interval_secs - spread + offset)
+    }
+
+    // the body of the next heartbeat once it is due
+    pub(crate) fn poll(&mut self, device_id: &str) -> Option<String> {
+        let now = Instant::now();
+        if now < self.due {
+            return None;
+        }
+        self.due = now + self.next_delay();
+        self.seq = self.seq.wrapping_add(1);
+        self.waiting_since.get_or_insert(now);
+        let timestamp = clock::unix_now().map_or(String::from("null"), |t| format!("{}", t));
+        Some(format!(
+            "{{\"device\":\"{}\",\"seq\":{},\"timestamp\":{},\"uptime\":{},\"next_in\":{}}}",
+            device_id,
+            self.seq,
+            timestamp,
+            now.duration_since_epoch().as_secs(),
+            self.config.interval_secs + self.spread_secs(),
+        ))
+    }
+
+    // the outbox got `route` through
+    pub(crate) fn delivered(&mut self, route: &str) {
+        if route == self.config.route {
+            self.waiting_since = None;
+        }
+    }
+
+    // heartbeats aren't getting through, the server is about to alert if it hasn't
+    pub(crate) fn late(&self) -> bool {
+        self.waiting_since.is_some_and(|at| at.elapsed() >= Duration::from_secs(LATE_SECS))
+    }
+}
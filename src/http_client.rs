@@ -1,12 +1,49 @@
-use alloc::{rc::Rc, string::String};
+use alloc::{boxed::Box, rc::Rc, string::String, vec::Vec};
 use alloc::format;
+use core::fmt::Write as _;
 use blocking_network_stack::Stack;
-use embedded_io::{Read, Write};
+use embedded_io::{Read, ReadReady, Write};
 use esp_hal::time::{Duration, Instant};
-use esp_println::println;
 use esp_radio::wifi::WifiDevice;
+use log::warn;
+use serde::{Serialize, de::DeserializeOwned};
+use thiserror::Error;
+
+#[cfg(feature = "mock-server")]
+use crate::mock_server::Fixtures;
+use crate::{
+    auth::{self, AuthProvider},
+    coexistence::RadioWindow,
+    fault_injection,
+    socket_pool::{PooledSocket, SocketPool, SocketUser},
+};
 use smoltcp::wire::IpAddress;
 
+// request bodies are serialized into a stack buffer, nothing here sends more than this
+const JSON_BODY_SIZE: usize = 1024;
+// escaped strings in a response are unescaped into this, the longest single string it can hold
+const JSON_UNESCAPE_SIZE: usize = 512;
+// status line and headers of a streamed response, buffered until the blank line
+const MAX_HEAD_SIZE: usize = 2048;
+// a chunk size line, hex digits and any extension
+const MAX_CHUNK_LINE: usize = 32;
+// after disconnecting, the socket is serviced until it is closed, at most this long
+const LINGER_MS: u64 = 250;
+
+#[derive(Error, Debug)]
+pub enum JsonError {
+    #[error("Request failed: {0}")]
+    Http(&'static str),
+    #[error("Server answered {0}")]
+    Status(u16),
+    #[error("Malformed response")]
+    Malformed,
+    #[error("Request body too large")]
+    Serialize(serde_json_core::ser::Error),
+    #[error("Unexpected JSON: {0:?}")]
+    Deserialize(serde_json_core::de::Error),
+}
+
 #[derive(Copy, Clone)]
 pub enum Method {
     Get,
@@ -28,37 +65,232 @@ impl Method {
     }
 }
 
+// what `request_streaming` got, the body itself went to the sink
+#[derive(Clone, Debug, PartialEq)]
+pub struct StreamedResponse {
+    pub status: u16,
+    // false when the connection ended or timed out before the body did
+    pub complete: bool,
+    // status line and headers, blank line included
+    head: Vec<u8>,
+}
+
+impl StreamedResponse {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        response_header(&self.head, name)
+    }
+}
+
 pub struct HttpClient<'a> {
     pub stack: Rc<Stack<'a, WifiDevice<'a>>>,
     pub host: &'static str,
     pub ip: IpAddress,
-    rx_buf: [u8; 1536],
-    tx_buf: [u8; 1536],
+    auth: Option<Rc<dyn AuthProvider>>,
+    // a request borrows a socket's buffers from here while it runs
+    sockets: SocketPool,
+    // answers in place of the server, nothing goes out
+    #[cfg(feature = "mock-server")]
+    mock: Option<Rc<Fixtures>>,
 }
 
 impl<'a> HttpClient<'a> {
     pub fn new(
         stack: Rc<Stack<'a, WifiDevice<'a>>>,
+        sockets: SocketPool,
         host: &'static str,
         ip: IpAddress,
     ) -> Self {
-        Self { stack, host, ip, tx_buf: [0u8; 1536], rx_buf: [0u8; 1536] }
+        Self {
+            stack,
+            host,
+            ip,
+            auth: None,
+            sockets,
+            #[cfg(feature = "mock-server")]
+            mock: None,
+        }
+    }
+
+    #[cfg(feature = "mock-server")]
+    pub(crate) fn with_mock(mut self, fixtures: Rc<Fixtures>) -> Self {
+        self.mock = Some(fixtures);
+        self
+    }
+
+    // consulted before every request, `None` sends requests without credentials
+    pub(crate) fn with_auth(mut self, auth: Option<Rc<dyn AuthProvider>>) -> Self {
+        self.auth = auth;
+        self
     }
 
     pub fn request(
-        &'a mut self,
+        &mut self,
         method: Method,
         route: &str,
         body: Option<&[u8]>,
         timeout_secs: u64,
     ) -> Result<String, &'static str> {
-        let mut out = String::new();
-        let mut socket = self.stack.get_socket(&mut self.rx_buf, &mut self.tx_buf);
+        let body = body.map(|b| (b, "application/json"));
+        let raw = self.send(method, route, body, "", timeout_secs)?;
+        String::from_utf8(raw).map_err(|_| "utf8 error")
+    }
+
+    // `request` without the wait: connects and sends, then the response is read a piece per
+    // `HttpRequestHandle::poll` and handed to `on_done`, so the loop driving it keeps going
+    pub fn start<'s>(
+        &'s self,
+        method: Method,
+        route: &str,
+        body: Option<&[u8]>,
+        timeout_secs: u64,
+        on_done: impl FnOnce(Result<String, &'static str>) + 's,
+    ) -> Result<HttpRequestHandle<'s, 'a>, &'static str> {
+        let body = body.map(|b| (b, "application/json"));
+        let request = self.head(method, route, body, "")?;
+        #[cfg(feature = "mock-server")]
+        if let Some(fixtures) = &self.mock {
+            let mut handle = HttpRequestHandle::new(None, timeout_secs, on_done);
+            handle.response = fixtures.respond(method.as_str(), route);
+            return Ok(handle);
+        }
+
+        let mut socket = self.sockets.open(&self.stack, SocketUser::Http).ok_or("no free socket")?;
         socket.work();
         socket.open(self.ip, 80).map_err(|_| "open failed")?;
+        if !fault_injection::drop_write() {
+            socket.write(request.as_bytes()).map_err(|_| "write failed")?;
+            if let Some((bytes, _)) = body {
+                socket.write(bytes).map_err(|_| "body write failed")?;
+            }
+        }
+        socket.flush().map_err(|_| "flush failed")?;
+        Ok(HttpRequestHandle::new(Some(socket), timeout_secs, on_done))
+    }
+
+    // `headers` are extra "Name: value\r\n" lines, e.g. validators for a conditional GET
+    pub fn request_bytes_with(
+        &mut self,
+        method: Method,
+        route: &str,
+        headers: &str,
+        timeout_secs: u64,
+    ) -> Result<Vec<u8>, &'static str> {
+        self.send(method, route, None, headers, timeout_secs)
+    }
+
+    // the body goes to `sink` chunk by chunk as it arrives, with chunked framing removed, so
+    // nothing the size of the response is buffered; for downloads to the card or a partition
+    pub fn request_streaming(
+        &mut self,
+        method: Method,
+        route: &str,
+        headers: &str,
+        timeout_secs: u64,
+        sink: impl FnMut(&[u8]),
+    ) -> Result<StreamedResponse, &'static str> {
+        let mut stream = ResponseStream::new(sink);
+        let mut failed = None;
+        self.exchange(method, route, None, headers, timeout_secs, |data| {
+            if failed.is_none() {
+                failed = stream.feed(data).err();
+            }
+        })?;
+        if let Some(e) = failed {
+            return Err(e);
+        }
+        stream.finish()
+    }
+
+    pub fn get_json<U: DeserializeOwned>(
+        &mut self,
+        route: &str,
+        timeout_secs: u64,
+    ) -> Result<U, JsonError> {
+        let raw = self.request(Method::Get, route, None, timeout_secs).map_err(JsonError::Http)?;
+        parse_json(&raw)
+    }
+
+    pub fn post_json<T: Serialize, U: DeserializeOwned>(
+        &mut self,
+        route: &str,
+        body: &T,
+        timeout_secs: u64,
+    ) -> Result<U, JsonError> {
+        self.send_json(Method::Post, route, body, timeout_secs)
+    }
+
+    pub fn put_json<T: Serialize, U: DeserializeOwned>(
+        &mut self,
+        route: &str,
+        body: &T,
+        timeout_secs: u64,
+    ) -> Result<U, JsonError> {
+        self.send_json(Method::Put, route, body, timeout_secs)
+    }
+
+    fn send_json<T: Serialize, U: DeserializeOwned>(
+        &mut self,
+        method: Method,
+        route: &str,
+        body: &T,
+        timeout_secs: u64,
+    ) -> Result<U, JsonError> {
+        let mut buf = [0u8; JSON_BODY_SIZE];
+        let len = serde_json_core::to_slice(body, &mut buf).map_err(JsonError::Serialize)?;
+        let raw = self
+            .request(method, route, Some(&buf[..len]), timeout_secs)
+            .map_err(JsonError::Http)?;
+        parse_json(&raw)
+    }
 
+    // OAuth endpoints only take application/x-www-form-urlencoded
+    pub fn post_form(
+        &mut self,
+        route: &str,
+        body: &str,
+        timeout_secs: u64,
+    ) -> Result<String, &'static str> {
+        let body = (body.as_bytes(), "application/x-www-form-urlencoded");
+        let raw = self.send(Method::Post, route, Some(body), "", timeout_secs)?;
+        String::from_utf8(raw).map_err(|_| "utf8 error")
+    }
+
+    fn send(
+        &mut self,
+        method: Method,
+        route: &str,
+        body: Option<(&[u8], &str)>,
+        headers: &str,
+        timeout_secs: u64,
+    ) -> Result<Vec<u8>, &'static str> {
+        let mut out = Vec::new();
+        self.exchange(method, route, body, headers, timeout_secs, |data| {
+            out.extend_from_slice(data)
+        })?;
+        Ok(out)
+    }
+
+    // request line and headers up to the blank line, with credentials when there is a provider
+    fn head(
+        &self,
+        method: Method,
+        route: &str,
+        body: Option<(&[u8], &str)>,
+        headers: &str,
+    ) -> Result<String, &'static str> {
         let method_str = method.as_str();
-        let body_len = body.map(|b| b.len()).unwrap_or(0);
+        let mut auth_headers = String::new();
+        if let Some(auth) = &self.auth {
+            let request = auth::Request {
+                method: method_str,
+                route,
+                body: body.map(|(b, _)| b).unwrap_or(&[]),
+            };
+            auth.authorize(&request, &mut auth_headers).map_err(|e| {
+                warn!("auth: {}", e);
+                "not authorized"
+            })?;
+        }
 
         let mut request = format!(
             "{} {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: esp32-rust\r\n",
@@ -67,47 +299,357 @@ impl<'a> HttpClient<'a> {
             self.host
         );
 
-        if let Some(_) = body {
-            request.push_str(&format!("Content-Length: {}\r\n", body_len));
-            request.push_str("Content-Type: application/json\r\n");
+        if let Some((bytes, content_type)) = body {
+            request.push_str(&format!("Content-Length: {}\r\n", bytes.len()));
+            request.push_str(&format!("Content-Type: {}\r\n", content_type));
         }
+        request.push_str(headers);
+        request.push_str(&auth_headers);
         request.push_str("Connection: close\r\n\r\n");
-        socket.write(request.as_bytes()).map_err(|_| "write failed")?;
+        Ok(request)
+    }
 
-        if let Some(bytes) = body {
-            socket.write(bytes).map_err(|_| "body write failed")?;
+    // sends the request and hands the raw response to `on_read` as it is read
+    fn exchange(
+        &mut self,
+        method: Method,
+        route: &str,
+        body: Option<(&[u8], &str)>,
+        headers: &str,
+        timeout_secs: u64,
+        mut on_read: impl FnMut(&[u8]),
+    ) -> Result<(), &'static str> {
+        let request = self.head(method, route, body, headers)?;
+        #[cfg(feature = "mock-server")]
+        if let Some(fixtures) = &self.mock {
+            on_read(&fixtures.respond(method.as_str(), route));
+            return Ok(());
+        }
+
+        // the whole exchange is a burst on the radio, large frames wait it out
+        let _radio = RadioWindow::open();
+        let mut socket = self.sockets.open(&self.stack, SocketUser::Http).ok_or("no free socket")?;
+        socket.work();
+        socket.open(self.ip, 80).map_err(|_| "open failed")?;
+        if !fault_injection::drop_write() {
+            socket.write(request.as_bytes()).map_err(|_| "write failed")?;
+
+            if let Some((bytes, _)) = body {
+                socket.write(bytes).map_err(|_| "body write failed")?;
+            }
         }
 
         socket.flush().map_err(|_| "flush failed")?;
         let deadline = Instant::now() + Duration::from_secs(timeout_secs);
         let mut temp = [0u8; 256];
+        let mut received = 0;
 
         loop {
+            if fault_injection::cut_read(received) {
+                socket.disconnect();
+                return Err("connection cut (injected)");
+            }
+
+            fault_injection::delay_read();
             match socket.read(&mut temp) {
                 Ok(0) => break, // EOF
                 Ok(n) => {
-                    if let Ok(s) = core::str::from_utf8(&temp[..n]) {
-                        out.push_str(s);
-                    } else {
-                        return Err("utf8 error");
-                    }
+                    received += n;
+                    on_read(&temp[..n]);
                 }
                 Err(_) => break,
             }
 
             if Instant::now() > deadline {
-                println!("http timeout");
+                warn!("http timeout");
                 break;
             }
         }
 
         socket.disconnect();
+        let deadline = Instant::now() + Duration::from_millis(LINGER_MS);
+        while socket.is_open() && Instant::now() < deadline {
+            socket.work();
+        }
+
+        Ok(())
+    }
+}
 
-        let end_deadline = Instant::now() + Duration::from_secs(5);
-        while Instant::now() < end_deadline {
+// a request `HttpClient::start` sent; dropping it before the response arrived closes the
+// connection and tells `on_done` the request was abandoned
+pub struct HttpRequestHandle<'s, 'a> {
+    // None when the response was there without a connection
+    socket: Option<PooledSocket<'s, 'a>>,
+    deadline: Instant,
+    response: Vec<u8>,
+    // set once the response went to `on_done`, until then the socket is serviced while it
+    // closes
+    closing: Option<Instant>,
+    on_done: Option<Box<dyn FnOnce(Result<String, &'static str>) + 's>>,
+}
+
+impl<'s, 'a> HttpRequestHandle<'s, 'a> {
+    fn new(
+        socket: Option<PooledSocket<'s, 'a>>,
+        timeout_secs: u64,
+        on_done: impl FnOnce(Result<String, &'static str>) + 's,
+    ) -> Self {
+        Self {
+            socket,
+            deadline: Instant::now() + Duration::from_secs(timeout_secs),
+            response: Vec::new(),
+            closing: None,
+            on_done: Some(Box::new(on_done)),
+        }
+    }
+
+    // reads what has arrived without waiting for more; false once the response went to
+    // `on_done` and the connection is closed, the handle can be dropped then
+    pub fn poll(&mut self) -> bool {
+        if let Some(until) = self.closing {
+            let Some(socket) = self.socket.as_mut() else {
+                return false;
+            };
             socket.work();
+            return socket.is_open() && Instant::now() < until;
         }
 
-        Ok(out)
+        let mut temp = [0u8; 256];
+        // like `exchange`, a timeout or a dropped connection ends the response where it is
+        let result = loop {
+            let Some(socket) = self.socket.as_mut() else {
+                break Ok(());
+            };
+            if fault_injection::cut_read(self.response.len()) {
+                break Err("connection cut (injected)");
+            }
+            match socket.read_ready() {
+                Ok(true) => {}
+                Ok(false) if Instant::now() > self.deadline => {
+                    warn!("http timeout");
+                    break Ok(());
+                }
+                Ok(false) => return true,
+                Err(_) => break Ok(()),
+            }
+            fault_injection::delay_read();
+            match socket.read(&mut temp) {
+                Ok(0) | Err(_) => break Ok(()),
+                Ok(n) => self.response.extend_from_slice(&temp[..n]),
+            }
+        };
+
+        if let Some(socket) = self.socket.as_mut() {
+            socket.disconnect();
+        }
+        self.closing = Some(Instant::now() + Duration::from_millis(LINGER_MS));
+        let response = core::mem::take(&mut self.response);
+        let result = result.and_then(|()| String::from_utf8(response).map_err(|_| "utf8 error"));
+        if let Some(on_done) = self.on_done.take() {
+            on_done(result);
+        }
+        true
+    }
+}
+
+impl Drop for HttpRequestHandle<'_, '_> {
+    fn drop(&mut self) {
+        if let Some(on_done) = self.on_done.take() {
+            if let Some(socket) = self.socket.as_mut() {
+                socket.disconnect();
+            }
+            on_done(Err("request abandoned"));
+        }
+    }
+}
+
+enum ChunkState {
+    // reading a size line
+    Size(heapless::Vec<u8, MAX_CHUNK_LINE>),
+    Data(usize),
+    // the CRLF after a chunk's data
+    DataEnd(usize),
+    // the last chunk was seen, trailers are ignored
+    Done,
+}
+
+// splits a response as it is read: the head is kept until it is complete, the body is passed on
+struct ResponseStream<F> {
+    head: Vec<u8>,
+    status: Option<u16>,
+    // None for a plain body, which runs until the connection closes
+    chunked: Option<ChunkState>,
+    // Content-Length of a plain body and how much of it arrived
+    length: Option<usize>,
+    received: usize,
+    sink: F,
+}
+
+impl<F: FnMut(&[u8])> ResponseStream<F> {
+    fn new(sink: F) -> Self {
+        Self { head: Vec::new(), status: None, chunked: None, length: None, received: 0, sink }
+    }
+
+    fn feed(&mut self, data: &[u8]) -> Result<(), &'static str> {
+        if self.status.is_some() {
+            return self.feed_body(data);
+        }
+        // the blank line can straddle two reads
+        let searched = self.head.len().saturating_sub(3);
+        self.head.extend_from_slice(data);
+        let Some(split) = self.head[searched..].windows(4).position(|w| w == b"\r\n\r\n") else {
+            if self.head.len() > MAX_HEAD_SIZE {
+                return Err("response head too large");
+            }
+            return Ok(());
+        };
+        let split = searched + split;
+        let head = core::str::from_utf8(&self.head[..split]).map_err(|_| "malformed head")?;
+        let (status, chunked) = parse_head(head).ok_or("malformed head")?;
+        self.status = Some(status);
+        self.chunked = chunked.then(|| ChunkState::Size(heapless::Vec::new()));
+        self.length = response_header(&self.head, "content-length").and_then(|v| v.parse().ok());
+        // the head stays for `StreamedResponse::header`
+        let body = self.head.split_off(split + 4);
+        self.feed_body(&body)
+    }
+
+    fn feed_body(&mut self, mut data: &[u8]) -> Result<(), &'static str> {
+        let Some(state) = &mut self.chunked else {
+            if !data.is_empty() {
+                self.received += data.len();
+                (self.sink)(data);
+            }
+            return Ok(());
+        };
+        while !data.is_empty() {
+            match state {
+                ChunkState::Size(line) => {
+                    let byte = data[0];
+                    data = &data[1..];
+                    if byte != b'\n' {
+                        line.push(byte).map_err(|_| "chunk size line too long")?;
+                        continue;
+                    }
+                    let line = core::str::from_utf8(line).map_err(|_| "malformed chunk size")?;
+                    let size = line.split(';').next().unwrap_or("").trim();
+                    let size = usize::from_str_radix(size, 16).map_err(|_| "malformed chunk size")?;
+                    *state = match size {
+                        0 => ChunkState::Done,
+                        size => ChunkState::Data(size),
+                    };
+                }
+                ChunkState::Data(left) => {
+                    let n = (*left).min(data.len());
+                    (self.sink)(&data[..n]);
+                    data = &data[n..];
+                    *left -= n;
+                    if *left == 0 {
+                        *state = ChunkState::DataEnd(2);
+                    }
+                }
+                ChunkState::DataEnd(left) => {
+                    let n = (*left).min(data.len());
+                    data = &data[n..];
+                    *left -= n;
+                    if *left == 0 {
+                        *state = ChunkState::Size(heapless::Vec::new());
+                    }
+                }
+                ChunkState::Done => break,
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<StreamedResponse, &'static str> {
+        let status = self.status.ok_or("malformed response")?;
+        // without a length a plain body ends with the connection, a timeout looks the same
+        let complete = match &self.chunked {
+            Some(state) => matches!(state, ChunkState::Done),
+            None => self.length.is_none_or(|length| self.received >= length),
+        };
+        Ok(StreamedResponse { status, complete, head: self.head })
+    }
+}
+
+// status code and whether the body is chunked
+fn parse_head(head: &str) -> Option<(u16, bool)> {
+    let mut lines = head.split("\r\n");
+    let status = lines.next()?.split(' ').nth(1)?.parse().ok()?;
+    let chunked = lines.any(|line| {
+        line.split_once(':').is_some_and(|(name, value)| {
+            name.eq_ignore_ascii_case("transfer-encoding") && value.trim() == "chunked"
+        })
+    });
+    Some((status, chunked))
+}
+
+// status code and body of a raw response, chunked bodies are reassembled
+pub fn parse_response(raw: &str) -> Option<(u16, String)> {
+    let (status, body) = parse_response_bytes(raw.as_bytes())?;
+    Some((status, String::from_utf8(body).ok()?))
+}
+
+pub fn parse_response_bytes(raw: &[u8]) -> Option<(u16, Vec<u8>)> {
+    let split = raw.windows(4).position(|w| w == b"\r\n\r\n")?;
+    let head = core::str::from_utf8(&raw[..split]).ok()?;
+    let body = &raw[split + 4..];
+    let (status, chunked) = parse_head(head)?;
+    if !chunked {
+        return Some((status, body.into()));
+    }
+
+    let mut out = Vec::new();
+    let mut rest = body;
+    loop {
+        let line_end = rest.windows(2).position(|w| w == b"\r\n")?;
+        let size = core::str::from_utf8(&rest[..line_end]).ok()?;
+        let size = usize::from_str_radix(size.split(';').next()?.trim(), 16).ok()?;
+        let tail = &rest[line_end + 2..];
+        if size == 0 {
+            return Some((status, out));
+        }
+        out.extend_from_slice(tail.get(..size)?);
+        rest = tail.get(size..)?.strip_prefix(b"\r\n")?;
+    }
+}
+
+// value of a response header, the first one if it repeats
+pub fn response_header<'r>(raw: &'r [u8], name: &str) -> Option<&'r str> {
+    let split = raw.windows(4).position(|w| w == b"\r\n\r\n")?;
+    let head = core::str::from_utf8(&raw[..split]).ok()?;
+    head.split("\r\n").skip(1).find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.trim().eq_ignore_ascii_case(name).then(|| value.trim())
+    })
+}
+
+// application/x-www-form-urlencoded, also fine for query strings
+pub fn push_form_encoded(out: &mut String, value: &str) {
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                out.push(byte as char)
+            }
+            b' ' => out.push('+'),
+            _ => {
+                write!(out, "%{:02X}", byte).ok();
+            }
+        }
+    }
+}
+
+// a 2xx response with a JSON body; `()` takes an empty body, `IgnoredAny` takes anything
+fn parse_json<U: DeserializeOwned>(raw: &str) -> Result<U, JsonError> {
+    let (status, body) = parse_response(raw).ok_or(JsonError::Malformed)?;
+    if !(200..300).contains(&status) {
+        return Err(JsonError::Status(status));
     }
+    let body = if body.trim().is_empty() { "null" } else { body.as_str() };
+    let mut unescaped = [0u8; JSON_UNESCAPE_SIZE];
+    serde_json_core::from_slice_escaped(body.as_bytes(), &mut unescaped)
+        .map(|(value, _)| value)
+        .map_err(JsonError::Deserialize)
 }
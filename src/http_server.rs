@@ -0,0 +1,289 @@
+use alloc::{format, string::String};
+use blocking_network_stack::Socket;
+use embedded_io::{Read, ReadReady, Write};
+use esp_hal::time::{Duration, Instant};
+use esp_radio::wifi::WifiDevice;
+use serde::Deserialize;
+
+use crate::{
+    build_info, navigation::Page, power::ScreenState, reliability::ReliabilityStats,
+    sdcard::SdStorage, shutdown::ShutdownKind, telemetry::MemoryStats,
+};
+
+pub(crate) const SERVER_PORT: u16 = 80;
+pub(crate) const MAX_TEXT: usize = 128;
+const REQUEST_SIZE: usize = 1024;
+// a client gets this long to send its whole request
+const REQUEST_TIMEOUT_MS: u64 = 2000;
+// followed by a page name, e.g. /api/page/now-playing
+const PAGE_ROUTE: &str = "/api/page/";
+// the shared secret clients send as `Authorization: Bearer <token>`
+const TOKEN_FILE: &str = "API.TXT";
+
+// what a client asked for; anything the server can't answer itself is handed to the UI core
+pub(crate) enum Route {
+    Status,
+    Text(heapless::String<MAX_TEXT>),
+    Navigate(Page),
+    Back,
+    Screenshot,
+    // saved to the SD card instead of sent back
+    SaveScreenshot,
+    // answered before the device goes down
+    Shutdown(ShutdownKind),
+}
+
+// GET /api/status
+pub(crate) struct Status<'s> {
+    pub(crate) uptime_secs: u64,
+    pub(crate) unix_time: Option<u32>,
+    pub(crate) ip: &'s str,
+    pub(crate) screen: ScreenState,
+    pub(crate) brightness: u8,
+    pub(crate) memory: &'s MemoryStats,
+    // percent, see perf::CpuLoad
+    pub(crate) cpu_load: u8,
+    // panel writes held up by the radio and frames that waited for it, see coexistence
+    pub(crate) display_glitches: u32,
+    pub(crate) display_pauses: u32,
+    // the running build's counters, see reliability
+    pub(crate) reliability: &'s ReliabilityStats,
+}
+
+impl Status<'_> {
+    pub(crate) fn to_json(&self) -> String {
+        let time = self.unix_time.map_or(String::from("null"), |t| format!("{}", t));
+        let screen = match self.screen {
+            ScreenState::Active => "on",
+            ScreenState::Dimmed => "dimmed",
+            ScreenState::Off => "off",
+        };
+        format!(
+            "{{\"version\":\"{}\",\"git_hash\":\"{}\",\"uptime\":{},\"time\":{},\"ip\":\"{}\",\
+            \"screen\":\"{}\",\"brightness\":{},\"heap_free\":{},\"heap_used\":{},\
+            \"cpu_load\":{},\"display_glitches\":{},\"display_pauses\":{},\"reliability\":{}}}",
+            build_info::PKG_VERSION,
+            build_info::GIT_HASH,
+            self.uptime_secs,
+            time,
+            self.ip,
+            screen,
+            self.brightness,
+            self.memory.heap_free,
+            self.memory.heap_used,
+            self.cpu_load,
+            self.display_glitches,
+            self.display_pauses,
+            self.reliability.to_json(),
+        )
+    }
+}
+
+#[derive(Deserialize)]
+struct TextBody<'b> {
+    text: &'b str,
+}
+
+// the first non-comment line of API.TXT; without one the server only answers GET /api/status
+pub(crate) fn read_token(sd: &SdStorage<'_>) -> Option<String> {
+    sd.read_config(TOKEN_FILE).ok()?.lines().next().map(String::from)
+}
+
+// Takes one request from the listening socket if a client is connected. Malformed requests,
+// unknown routes and missing credentials are answered here; a returned route has to be
+// answered with one of the `respond` functions, after which `finish` closes the connection.
+pub(crate) fn accept(
+    socket: &mut Socket<'_, '_, WifiDevice<'_>>,
+    token: Option<&str>,
+) -> Option<Route> {
+    socket.work();
+    if !socket.is_open() {
+        socket.listen(SERVER_PORT).ok()?;
+    }
+    if !socket.is_connected() {
+        return None;
+    }
+
+    let mut request = [0u8; REQUEST_SIZE];
+    let route = read_request(socket, &mut request).and_then(|len| {
+        core::str::from_utf8(&request[..len])
+            .map_err(|_| "400 Bad Request")
+            .and_then(|request| route(request, token))
+    });
+    match route {
+        Ok(route) => Some(route),
+        Err(status) => {
+            respond(socket, status, "text/plain", status.as_bytes());
+            finish(socket);
+            None
+        }
+    }
+}
+
+impl Route {
+    // answered without the token; anything not listed here needs it, new routes included
+    fn is_public(&self) -> bool {
+        matches!(self, Route::Status)
+    }
+}
+
+fn route(request: &str, token: Option<&str>) -> Result<Route, &'static str> {
+    let (head, body) = request.split_once("\r\n\r\n").ok_or("400 Bad Request")?;
+    let route = parse(head, body)?;
+    if !route.is_public() {
+        authorize(head, token)?;
+    }
+    Ok(route)
+}
+
+fn parse(head: &str, body: &str) -> Result<Route, &'static str> {
+    let mut request_line = head.lines().next().unwrap_or("").split(' ');
+    let (method, path) = (request_line.next().unwrap_or(""), request_line.next().unwrap_or(""));
+    let path = path.split('?').next().unwrap_or(path);
+
+    match (method, path) {
+        ("GET", "/api/status") => Ok(Route::Status),
+        ("GET", "/api/screenshot") => Ok(Route::Screenshot),
+        ("POST", "/api/screenshot") => Ok(Route::SaveScreenshot),
+        ("POST", "/api/text") => {
+            // JSON `{"text": "..."}` or the text itself as the body
+            let text = if body.trim_start().starts_with('{') {
+                let mut unescaped = [0u8; MAX_TEXT * 2];
+                let (parsed, _) = serde_json_core::from_slice_escaped::<TextBody<'_>>(
+                    body.as_bytes(),
+                    &mut unescaped,
+                )
+                .map_err(|_| "400 Bad Request")?;
+                truncated(parsed.text)
+            } else {
+                truncated(body.trim())
+            };
+            Ok(Route::Text(text))
+        }
+        ("POST", "/api/back") => Ok(Route::Back),
+        ("POST", "/api/reboot") => Ok(Route::Shutdown(ShutdownKind::Reboot)),
+        ("POST", "/api/poweroff") => Ok(Route::Shutdown(ShutdownKind::PowerOff)),
+        ("POST", path) if path.starts_with(PAGE_ROUTE) => {
+            let page = Page::from_name(&path[PAGE_ROUTE.len()..]).ok_or("404 Not Found")?;
+            Ok(Route::Navigate(page))
+        }
+        (
+            _,
+            "/api/status" | "/api/screenshot" | "/api/text" | "/api/back" | "/api/reboot"
+            | "/api/poweroff",
+        ) => Err("405 Method Not Allowed"),
+        _ => Err("404 Not Found"),
+    }
+}
+
+// `Authorization: Bearer <token>` matching the configured one; refused outright without one
+fn authorize(head: &str, token: Option<&str>) -> Result<(), &'static str> {
+    let token = token.ok_or("403 Forbidden")?;
+    let sent = head.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.eq_ignore_ascii_case("authorization").then(|| value.trim())
+    });
+    let sent = sent.and_then(|value| value.strip_prefix("Bearer ")).unwrap_or("");
+    // every byte is compared, the time taken doesn't give away how much of it matched
+    let differs = sent.len() != token.len()
+        || sent.bytes().zip(token.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) != 0;
+    if differs {
+        return Err("401 Unauthorized");
+    }
+    Ok(())
+}
+
+fn truncated(text: &str) -> heapless::String<MAX_TEXT> {
+    let mut out = heapless::String::new();
+    for c in text.chars() {
+        if out.push(c).is_err() {
+            break;
+        }
+    }
+    out
+}
+
+// Reads until the headers and the Content-Length worth of body are in. Only reads what has
+// arrived, a client that stops sending gets a 408 after REQUEST_TIMEOUT_MS instead of blocking
+// the loop; an error is the status to answer with.
+pub(crate) fn read_request(
+    socket: &mut Socket<'_, '_, WifiDevice<'_>>,
+    buf: &mut [u8],
+) -> Result<usize, &'static str> {
+    let deadline = Instant::now() + Duration::from_millis(REQUEST_TIMEOUT_MS);
+    let mut len = 0;
+    loop {
+        let text = core::str::from_utf8(&buf[..len]).unwrap_or("");
+        if let Some(header_end) = text.find("\r\n\r\n") {
+            let body_len = content_length(&text[..header_end]);
+            if body_len > buf.len() {
+                return Err("413 Payload Too Large");
+            }
+            if len >= header_end.saturating_add(4).saturating_add(body_len) {
+                return Ok(len);
+            }
+        }
+        if len == buf.len() {
+            return Err("413 Payload Too Large");
+        }
+
+        match socket.read_ready() {
+            Ok(true) => {}
+            Ok(false) if Instant::now() > deadline => return Err("408 Request Timeout"),
+            Ok(false) => {
+                socket.work();
+                continue;
+            }
+            // closed by the client, whatever came is the request
+            Err(_) => return Ok(len),
+        }
+        match socket.read(&mut buf[len..]) {
+            Ok(0) | Err(_) => return Ok(len),
+            Ok(n) => len += n,
+        }
+    }
+}
+
+fn content_length(head: &str) -> usize {
+    head.lines()
+        .find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.eq_ignore_ascii_case("content-length")
+                .then(|| value.trim().parse().ok())
+                .flatten()
+        })
+        .unwrap_or(0)
+}
+
+pub(crate) fn respond(
+    socket: &mut Socket<'_, '_, WifiDevice<'_>>,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) {
+    respond_head(socket, status, content_type, body.len());
+    write_body(socket, body);
+}
+
+// for bodies that arrive in pieces, each one goes out with `write_body`
+pub(crate) fn respond_head(
+    socket: &mut Socket<'_, '_, WifiDevice<'_>>,
+    status: &str,
+    content_type: &str,
+    len: usize,
+) {
+    let header = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status, content_type, len
+    );
+    let _ = socket.write_all(header.as_bytes());
+}
+
+pub(crate) fn write_body(socket: &mut Socket<'_, '_, WifiDevice<'_>>, bytes: &[u8]) {
+    let _ = socket.write_all(bytes);
+}
+
+pub(crate) fn finish(socket: &mut Socket<'_, '_, WifiDevice<'_>>) {
+    let _ = socket.flush();
+    socket.close();
+}
@@ -0,0 +1,143 @@
+// Hardware vertical scrolling (VSCRDEF/VSCRSADD) for ui.slint's ScrollList. When the list
+// moves, the panel is told to start its rows further into the frame memory instead of being
+// sent them all again: rows that only moved are skipped on the next frame, and the ones that
+// scrolled into view are written to where the panel now shows them. The controller scrolls its
+// own rows across their full width, so this only works in the orientations where those are the
+// window's rows, and only for a list with nothing but background beside it. Anything else inside
+// the list that changed mid-scroll is caught by a full redraw once the list comes to rest.
+use core::ops::Range;
+use esp_hal::time::{Duration, Instant};
+
+use crate::display_screen::PANEL_WIDTH;
+
+// the frame memory's rows, the window height in the orientations that scroll
+const ROWS: i32 = PANEL_WIDTH as i32;
+// a list untouched this long has stopped, its frame memory is redrawn in full
+const SETTLE_AFTER: Duration = Duration::from_millis(300);
+
+// a ScrollList's window rows and viewport position, sent on every move
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct ListScroll {
+    pub(crate) top: i32,
+    pub(crate) height: i32,
+    pub(crate) viewport_y: i32,
+}
+
+impl ListScroll {
+    // from slint's lengths, a flick stops on fractions of a pixel
+    pub(crate) fn from_lengths(top: f32, height: f32, viewport_y: f32) -> Self {
+        Self { top: round(top), height: round(height), viewport_y: round(viewport_y) }
+    }
+}
+
+fn round(length: f32) -> i32 {
+    if length < 0.0 { (length - 0.5) as i32 } else { (length + 0.5) as i32 }
+}
+
+// what the panel has to be sent, in frame memory rows
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ScrollCommand {
+    // VSCRDEF for a new list, then its start row; `redraw` when the list moved or resized while
+    // shifted, the rows it left still show the frame memory out of place
+    Define { top_fixed: u16, bottom_fixed: u16, start: u16, redraw: bool },
+    // VSCRSADD
+    Start(u16),
+}
+
+pub(crate) struct HwScroll {
+    // Some(reversed) where window rows are frame memory rows, see ScreenOrientation::scroll_rows
+    rows: Option<bool>,
+    // the list's window rows and the viewport position its frame memory matches
+    area: Option<(Range<i32>, i32)>,
+    // how many rows into the area window row `top` is written
+    offset: i32,
+    // window rows the panel already shows right for the frame being drawn
+    shifted: Range<i32>,
+    pending: bool,
+    moved_at: Option<Instant>,
+}
+
+impl HwScroll {
+    pub(crate) fn new(rows: Option<bool>) -> Self {
+        Self { rows, area: None, offset: 0, shifted: 0..0, pending: false, moved_at: None }
+    }
+
+    // true when the frame memory was shifted; the caller puts the panel back to no scrolling
+    // and redraws everything
+    pub(crate) fn reset(&mut self) -> bool {
+        let shifted = self.area.is_some() && self.offset != 0;
+        *self = Self::new(self.rows);
+        shifted
+    }
+
+    pub(crate) fn scroll(&mut self, list: ListScroll) -> Option<ScrollCommand> {
+        let reversed = self.rows?;
+        let area = list.top.max(0)..(list.top + list.height).min(ROWS);
+        if area.len() < 2 {
+            return None;
+        }
+        let height = area.len() as i32;
+        let (top_fixed, bottom_fixed) = if reversed {
+            (ROWS - area.end, area.start)
+        } else {
+            (area.start, ROWS - area.end)
+        };
+        let moved = match &self.area {
+            Some((current, viewport_y)) if *current == area => *viewport_y - list.viewport_y,
+            _ => {
+                // the frame memory matches the window as it is, the first move starts from there
+                let redraw = self.offset != 0;
+                *self = Self::new(self.rows);
+                self.area = Some((area, list.viewport_y));
+                let start = top_fixed as u16;
+                let (top_fixed, bottom_fixed) = (top_fixed as u16, bottom_fixed as u16);
+                return Some(ScrollCommand::Define { top_fixed, bottom_fixed, start, redraw });
+            }
+        };
+        if moved == 0 {
+            return None;
+        }
+        // rows already in the frame memory, moved up by `moved`, that are still in the list
+        let known = if self.pending { self.shifted.clone() } else { area.clone() };
+        self.shifted = (known.start - moved).max(area.start)..(known.end - moved).min(area.end);
+        self.pending = true;
+        self.moved_at = Some(Instant::now());
+        self.offset = (self.offset + moved).rem_euclid(height);
+        self.area = Some((area, list.viewport_y));
+        // the panel scans the reversed rows the other way round
+        let start = if reversed { (height - self.offset) % height } else { self.offset };
+        Some(ScrollCommand::Start((top_fixed + start) as u16))
+    }
+
+    // the window row a line is sent to, None for one the panel already shows
+    pub(crate) fn row(&self, line: usize) -> Option<usize> {
+        let line = line as i32;
+        let Some((area, _)) = &self.area else {
+            return Some(line as usize);
+        };
+        if self.pending && self.shifted.contains(&line) {
+            return None;
+        }
+        if !area.contains(&line) {
+            return Some(line as usize);
+        }
+        let height = area.len() as i32;
+        Some((area.start + (line - area.start + self.offset) % height) as usize)
+    }
+
+    pub(crate) fn drawn(&mut self) {
+        self.pending = false;
+        self.shifted = 0..0;
+    }
+
+    // true once, when a list that scrolled has been still for a moment
+    pub(crate) fn settled(&mut self) -> bool {
+        match self.moved_at {
+            Some(at) if !self.pending && at.elapsed() >= SETTLE_AFTER => {
+                self.moved_at = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
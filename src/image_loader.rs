@@ -0,0 +1,166 @@
+use slint::{Image, Rgb8Pixel, SharedPixelBuffer};
+use thiserror::Error;
+
+use crate::sdcard::{SdCardError, SdStorage};
+
+// decoded RGB888 has to fit the ~96 KB heap next to everything else
+const MAX_PIXELS: u32 = 160 * 120;
+// a 32 bpp BMP of MAX_PIXELS and its headers, a PNG of them is smaller
+const MAX_FILE_BYTES: u32 = MAX_PIXELS * 4 + 1024;
+// enough for the BMP info header's dimensions and a PNG's IHDR chunk
+const HEADER_LEN: usize = 34;
+
+#[derive(Error, Debug)]
+pub(crate) enum ImageError {
+    #[error("Failed to read image: {0}")]
+    Io(#[from] SdCardError),
+    #[error("Unsupported image format: {0}")]
+    Unsupported(&'static str),
+    #[error("Malformed image data")]
+    Malformed,
+    #[error("Image of {0}x{1} exceeds the pixel budget")]
+    TooLarge(u32, u32),
+    #[error("Image file of {0} bytes is too large")]
+    FileTooLarge(u32),
+}
+
+// the header and the file size are checked before the file is read into RAM
+pub(crate) fn load_image(sd: &SdStorage<'_>, path: &str) -> Result<Image, ImageError> {
+    let mut header = [0u8; HEADER_LEN];
+    let len = sd.read_file(path, &mut header)?;
+    check_header(&header[..len])?;
+    let size = sd.file_size(path)?;
+    if size > MAX_FILE_BYTES {
+        return Err(ImageError::FileTooLarge(size));
+    }
+    let data = sd.read_to_vec(path)?;
+    decode(&data)
+}
+
+// the dimensions in the first HEADER_LEN bytes against the pixel budget
+fn check_header(header: &[u8]) -> Result<(), ImageError> {
+    let (width, height) = match header {
+        [b'B', b'M', ..] => {
+            let u32_at = |i: usize| {
+                header
+                    .get(i..i + 4)
+                    .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                    .ok_or(ImageError::Malformed)
+            };
+            ((u32_at(18)? as i32).unsigned_abs(), (u32_at(22)? as i32).unsigned_abs())
+        }
+        #[cfg(feature = "png")]
+        [0x89, b'P', b'N', b'G', ..] => {
+            let png = minipng::decode_png_header(header).map_err(|_| ImageError::Malformed)?;
+            (png.width(), png.height())
+        }
+        // decode has the error for it
+        _ => return decode(header).map(|_| ()),
+    };
+    if width.saturating_mul(height) > MAX_PIXELS {
+        return Err(ImageError::TooLarge(width, height));
+    }
+    Ok(())
+}
+
+// BMP, or PNG with the feature; also used for images fetched over http
+pub(crate) fn decode(data: &[u8]) -> Result<Image, ImageError> {
+    match data {
+        [b'B', b'M', ..] => decode_bmp(data).map(Image::from_rgb8),
+        #[cfg(feature = "png")]
+        [0x89, b'P', b'N', b'G', ..] => decode_png(data).map(Image::from_rgba8),
+        #[cfg(not(feature = "png"))]
+        [0x89, b'P', b'N', b'G', ..] => Err(ImageError::Unsupported("PNG (enable the png feature)")),
+        _ => Err(ImageError::Unsupported("unknown signature")),
+    }
+}
+
+// uncompressed 24/32 bpp and RGB565 bitfield BMPs, bottom-up or top-down
+fn decode_bmp(data: &[u8]) -> Result<SharedPixelBuffer<Rgb8Pixel>, ImageError> {
+    let u16_at = |i: usize| -> Result<u16, ImageError> {
+        data.get(i..i + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+            .ok_or(ImageError::Malformed)
+    };
+    let u32_at = |i: usize| -> Result<u32, ImageError> {
+        data.get(i..i + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or(ImageError::Malformed)
+    };
+
+    let pixel_offset = u32_at(10)? as usize;
+    let width = u32_at(18)? as i32;
+    let height = u32_at(22)? as i32;
+    let bpp = u16_at(28)?;
+    let compression = u32_at(30)?;
+
+    let top_down = height < 0;
+    let (width, height) = (width.unsigned_abs(), height.unsigned_abs());
+    if width == 0 || height == 0 {
+        return Err(ImageError::Malformed);
+    }
+    if width.saturating_mul(height) > MAX_PIXELS {
+        return Err(ImageError::TooLarge(width, height));
+    }
+
+    match (bpp, compression) {
+        (24, 0) | (32, 0) | (32, 3) | (16, 3) => {}
+        (16, 0) => return Err(ImageError::Unsupported("RGB555 BMP")),
+        _ => return Err(ImageError::Unsupported("compressed or palettized BMP")),
+    }
+
+    let bytes_per_pixel = bpp as usize / 8;
+    let stride = (width as usize * bytes_per_pixel).div_ceil(4) * 4;
+    // the offset comes from the file, a bogus one mustn't overflow the bounds check
+    let end = stride.checked_mul(height as usize).and_then(|len| len.checked_add(pixel_offset));
+    if end.is_none_or(|end| end > data.len()) {
+        return Err(ImageError::Malformed);
+    }
+
+    let mut buffer = SharedPixelBuffer::<Rgb8Pixel>::new(width, height);
+    let pixels = buffer.make_mut_slice();
+    for y in 0..height as usize {
+        let src_row = if top_down { y } else { height as usize - 1 - y };
+        let row = &data[pixel_offset + src_row * stride..][..width as usize * bytes_per_pixel];
+        let dst = &mut pixels[y * width as usize..][..width as usize];
+
+        for (px, src) in dst.iter_mut().zip(row.chunks_exact(bytes_per_pixel)) {
+            *px = match bytes_per_pixel {
+                2 => {
+                    let v = u16::from_le_bytes([src[0], src[1]]);
+                    let r = ((v >> 11) & 0x1f) as u8;
+                    let g = ((v >> 5) & 0x3f) as u8;
+                    let b = (v & 0x1f) as u8;
+                    Rgb8Pixel::new(r << 3 | r >> 2, g << 2 | g >> 4, b << 3 | b >> 2)
+                }
+                // BGR(A) byte order
+                _ => Rgb8Pixel::new(src[2], src[1], src[0]),
+            };
+        }
+    }
+
+    Ok(buffer)
+}
+
+#[cfg(feature = "png")]
+fn decode_png(
+    data: &[u8],
+) -> Result<SharedPixelBuffer<slint::Rgba8Pixel>, ImageError> {
+    let header = minipng::decode_png_header(data).map_err(|_| ImageError::Malformed)?;
+    if header.width().saturating_mul(header.height()) > MAX_PIXELS {
+        return Err(ImageError::TooLarge(header.width(), header.height()));
+    }
+
+    let mut scratch = alloc::vec![0u8; header.required_bytes_rgba8bpc()];
+    let mut image =
+        minipng::decode_png(data, &mut scratch).map_err(|_| ImageError::Malformed)?;
+    image
+        .convert_to_rgba8bpc()
+        .map_err(|_| ImageError::Unsupported("PNG color type"))?;
+
+    Ok(SharedPixelBuffer::clone_from_slice(
+        image.pixels(),
+        image.width(),
+        image.height(),
+    ))
+}
@@ -0,0 +1,102 @@
+use esp_hal::time::Instant;
+
+use crate::touch_input::TouchInputResponse;
+
+// how far ahead a drag may be pushed, beyond this a wrong guess is more visible than the lag
+const MAX_LEAD_PX: f32 = 40.0;
+// samples further apart than this are a new gesture, not a velocity
+const MAX_SAMPLE_GAP_MS: u64 = 100;
+// a touch that changed nothing on screen is only "presented" by some unrelated later frame
+const MAX_LATENCY_MS: u64 = 500;
+
+// Input-to-photon latency: from the touch sample that changed something to the end of the
+// first frame flushed to the panel after it. Smoothed, since single frames vary with how much
+// of the screen was dirty.
+pub(crate) struct LatencyTracker {
+    pending: Option<Instant>,
+    average_ms: Option<f32>,
+}
+
+impl LatencyTracker {
+    pub(crate) fn new() -> Self {
+        Self { pending: None, average_ms: None }
+    }
+
+    pub(crate) fn input(&mut self, input: &TouchInputResponse) {
+        if !matches!(input, TouchInputResponse::NoInput) && self.pending.is_none() {
+            self.pending = Some(Instant::now());
+        }
+    }
+
+    // call after a frame went out, returns the updated average in ms
+    pub(crate) fn presented(&mut self) -> Option<u32> {
+        let sampled = self.pending.take()?;
+        let sample = sampled.elapsed().as_millis();
+        if sample > MAX_LATENCY_MS {
+            return None;
+        }
+        let sample = sample as f32;
+        let average = match self.average_ms {
+            Some(average) => average * 0.8 + sample * 0.2,
+            None => sample,
+        };
+        self.average_ms = Some(average);
+        Some(average as u32)
+    }
+}
+
+// extrapolates drag positions by the measured latency so the element under the finger
+// lands where the finger is by the time the frame is visible
+pub(crate) struct DragPredictor {
+    last: Option<(i32, i32, Instant)>,
+    velocity: (f32, f32),
+}
+
+impl DragPredictor {
+    pub(crate) fn new() -> Self {
+        Self { last: None, velocity: (0.0, 0.0) }
+    }
+
+    pub(crate) fn predict(
+        &mut self,
+        input: TouchInputResponse,
+        latency_ms: u32,
+    ) -> TouchInputResponse {
+        match input {
+            TouchInputResponse::Pressed { x, y } => {
+                self.last = Some((x, y, Instant::now()));
+                self.velocity = (0.0, 0.0);
+                input
+            }
+            TouchInputResponse::Moved { x, y } => {
+                let now = Instant::now();
+                if let Some((last_x, last_y, at)) = self.last {
+                    let dt = (now - at).as_millis();
+                    if dt > 0 && dt <= MAX_SAMPLE_GAP_MS {
+                        let dt = dt as f32;
+                        // light smoothing, resistive panels jitter by a pixel or two
+                        let vx = (x - last_x) as f32 / dt;
+                        let vy = (y - last_y) as f32 / dt;
+                        self.velocity = (
+                            self.velocity.0 * 0.5 + vx * 0.5,
+                            self.velocity.1 * 0.5 + vy * 0.5,
+                        );
+                    }
+                }
+                self.last = Some((x, y, now));
+
+                let lead = |v: f32| (v * latency_ms as f32).clamp(-MAX_LEAD_PX, MAX_LEAD_PX);
+                TouchInputResponse::Moved {
+                    x: x + lead(self.velocity.0) as i32,
+                    y: y + lead(self.velocity.1) as i32,
+                }
+            }
+            // the release lands where the finger actually was
+            TouchInputResponse::Released { .. } => {
+                self.last = None;
+                input
+            }
+            TouchInputResponse::NoInput => input,
+        }
+    }
+}
@@ -0,0 +1,239 @@
+// `log` sink replacing esp-println's logger: records still go to serial, and those at or above
+// the file level are queued with a timestamp for LOG<n>.TXT on the SD card. The queue is
+// written out by the main loop, which owns the card.
+use alloc::{collections::VecDeque, format, string::String};
+use core::{
+    cell::RefCell,
+    fmt::Write,
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use critical_section::Mutex;
+use esp_hal::time::{Duration, Instant};
+use esp_println::println;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use thiserror::Error;
+
+use crate::{
+    clock,
+    sdcard::{SdCardError, SdStorage},
+};
+
+const LOG_FILES: u8 = 5;
+// the next file is started once this one would grow past it
+const MAX_FILE_BYTES: u32 = 32 * 1024;
+// which LOG<n>.TXT is being written, so a restart carries on instead of overwriting LOG0
+const INDEX_FILE: &str = "LOG.IDX";
+const QUEUE_LINES: usize = 64;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+// LevelFilter as usize, Off = 0 .. Trace = 5
+static SERIAL_LEVEL: AtomicUsize = AtomicUsize::new(LevelFilter::Info as usize);
+static FILE_LEVEL: AtomicUsize = AtomicUsize::new(LevelFilter::Info as usize);
+static QUEUE: Mutex<RefCell<Queue>> = Mutex::new(RefCell::new(Queue::new()));
+static LOGGER: SdLogger = SdLogger;
+
+#[derive(Error, Debug)]
+pub(crate) enum LoggerError {
+    #[error("Unknown log command: {0}")]
+    UnknownCommand(String),
+    #[error("Invalid level: {0}, use off, error, warn, info, debug or trace")]
+    InvalidLevel(String),
+}
+
+struct Queue {
+    lines: VecDeque<String>,
+    // lines lost to a full queue since the last write
+    dropped: u32,
+    // an error was queued, written on the next poll regardless of the interval
+    urgent: bool,
+}
+
+impl Queue {
+    const fn new() -> Self {
+        Self { lines: VecDeque::new(), dropped: 0, urgent: false }
+    }
+}
+
+struct SdLogger;
+
+impl Log for SdLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        if record.level() <= load_level(&SERIAL_LEVEL) {
+            println!("{} - {}", record.level(), record.args());
+        }
+        if record.level() <= load_level(&FILE_LEVEL) {
+            queue(record.level(), format!("{}", record.args()));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+// the serial level comes from ESP_LOG at build time like before, the file starts at info
+pub(crate) fn init() {
+    let serial = option_env!("ESP_LOG")
+        .and_then(|l| LevelFilter::from_str(l).ok())
+        .unwrap_or(LevelFilter::Info);
+    SERIAL_LEVEL.store(serial as usize, Ordering::Relaxed);
+    if log::set_logger(&LOGGER).is_ok() {
+        update_max_level();
+    }
+}
+
+// Off when there is no card, nothing would ever empty the queue
+pub(crate) fn set_file_level(filter: LevelFilter) {
+    FILE_LEVEL.store(filter as usize, Ordering::Relaxed);
+    update_max_level();
+    if filter == LevelFilter::Off {
+        critical_section::with(|cs| QUEUE.borrow_ref_mut(cs).lines.clear());
+    }
+}
+
+// for lines that were printed already, the log ring's
+pub(crate) fn mirror(level: Level, line: &str) {
+    if level <= load_level(&FILE_LEVEL) {
+        queue(level, line.into());
+    }
+}
+
+// `log [level <off|error|warn|info|debug|trace>]`, sets what reaches the card
+pub(crate) fn command(args: &str) -> Result<String, LoggerError> {
+    let mut parts = args.split_whitespace();
+    match (parts.next(), parts.next()) {
+        (None, _) | (Some("status"), _) => {}
+        (Some("level"), Some(value)) => {
+            let filter = LevelFilter::from_str(value)
+                .map_err(|_| LoggerError::InvalidLevel(value.into()))?;
+            set_file_level(filter);
+        }
+        (Some(other), _) => return Err(LoggerError::UnknownCommand(other.into())),
+    }
+    Ok(format!("serial {}, sd card {}", load_level(&SERIAL_LEVEL), load_level(&FILE_LEVEL)))
+}
+
+fn load_level(filter: &AtomicUsize) -> LevelFilter {
+    match filter.load(Ordering::Relaxed) {
+        0 => LevelFilter::Off,
+        1 => LevelFilter::Error,
+        2 => LevelFilter::Warn,
+        3 => LevelFilter::Info,
+        4 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+// formatting is skipped for records neither sink wants
+fn update_max_level() {
+    log::set_max_level(load_level(&SERIAL_LEVEL).max(load_level(&FILE_LEVEL)));
+}
+
+fn queue(level: Level, message: String) {
+    let mut line = String::with_capacity(message.len() + 28);
+    match clock::local_now() {
+        Some(t) => {
+            let _ = write!(
+                line,
+                "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+                t.year, t.month, t.day, t.hour, t.minute, t.second
+            );
+        }
+        // before the first sntp sync only the uptime is known
+        None => {
+            let _ = write!(line, "+{} ms", Instant::now().duration_since_epoch().as_millis());
+        }
+    }
+    let _ = writeln!(line, " {:<5} {}", level, message);
+
+    critical_section::with(|cs| {
+        let mut queue = QUEUE.borrow_ref_mut(cs);
+        if queue.lines.len() == QUEUE_LINES {
+            queue.lines.pop_front();
+            queue.dropped += 1;
+        }
+        queue.lines.push_back(line);
+        queue.urgent |= level == Level::Error;
+    });
+}
+
+// the card end of the sink, appends the queue to the current file every FLUSH_INTERVAL
+pub(crate) struct LogFile {
+    index: u8,
+    size: u32,
+    last_write: Instant,
+}
+
+impl LogFile {
+    // picks up the file the previous boot was writing to
+    pub(crate) fn open(sd: &SdStorage<'_>) -> Self {
+        let mut buf = [0u8; 4];
+        let index = sd
+            .read_file(INDEX_FILE, &mut buf)
+            .ok()
+            .and_then(|n| core::str::from_utf8(&buf[..n]).ok()?.trim().parse().ok())
+            .filter(|i| *i < LOG_FILES)
+            .unwrap_or(0);
+        let size = sd
+            .list_dir("")
+            .ok()
+            .and_then(|entries| entries.into_iter().find(|e| e.name == path(index)))
+            .map_or(0, |e| e.size);
+        Self { index, size, last_write: Instant::now() }
+    }
+
+    pub(crate) fn poll(&mut self, sd: &SdStorage<'_>) -> Result<(), SdCardError> {
+        let urgent = critical_section::with(|cs| QUEUE.borrow_ref(cs).urgent);
+        if !urgent && self.last_write.elapsed() < FLUSH_INTERVAL {
+            return Ok(());
+        }
+        self.flush(sd)
+    }
+
+    // whatever is queued, now; before a shutdown
+    pub(crate) fn flush(&mut self, sd: &SdStorage<'_>) -> Result<(), SdCardError> {
+        self.last_write = Instant::now();
+
+        let (lines, dropped) = critical_section::with(|cs| {
+            let mut queue = QUEUE.borrow_ref_mut(cs);
+            queue.urgent = false;
+            let dropped = core::mem::take(&mut queue.dropped);
+            (core::mem::take(&mut queue.lines), dropped)
+        });
+        if lines.is_empty() {
+            return Ok(());
+        }
+        let mut text = String::new();
+        if dropped > 0 {
+            let _ = writeln!(text, "... {} lines dropped", dropped);
+        }
+        for line in &lines {
+            text.push_str(line);
+        }
+
+        if self.size > 0 && self.size + text.len() as u32 > MAX_FILE_BYTES {
+            self.rotate(sd)?;
+        }
+        sd.append(&path(self.index), text.as_bytes())?;
+        self.size += text.len() as u32;
+        Ok(())
+    }
+
+    // the oldest file is truncated and becomes the current one
+    fn rotate(&mut self, sd: &SdStorage<'_>) -> Result<(), SdCardError> {
+        self.index = (self.index + 1) % LOG_FILES;
+        self.size = 0;
+        sd.write_file(&path(self.index), &[])?;
+        sd.write_file(INDEX_FILE, format!("{}", self.index).as_bytes())
+    }
+}
+
+fn path(index: u8) -> String {
+    format!("LOG{}.TXT", index)
+}
@@ -6,56 +6,214 @@
     holding buffers for the duration of a data transfer."
 )]
 
-use alloc::{boxed::Box, rc::Rc};
+use alloc::{boxed::Box, format, rc::Rc, string::{String, ToString}, vec::Vec};
 use core::cell::RefCell;
-use embedded_hal_bus::spi::RefCellDevice;
-use embedded_sdmmc::{Mode, SdCard, TimeSource, Timestamp, VolumeIdx, VolumeManager};
 use esp_backtrace as _;
 use esp_hal::{
     Blocking,
     clock::CpuClock,
-    delay::Delay,
     gpio::{
-        Level, Output, OutputPin,
+        Flex, Io, Level, Output,
         interconnect::{PeripheralInput, PeripheralOutput},
     },
+    interrupt::software::SoftwareInterruptControl,
+    ledc::{Ledc, channel, timer},
     main,
     peripherals::Peripherals,
     rng::Rng,
+    rtc_cntl::{Rtc, reset_reason},
     spi::master::Spi,
-    time::{Instant, Rate},
+    system::Cpu,
+    time::{Duration, Instant, Rate},
     timer::timg::TimerGroup,
 };
 use esp_println::println;
+use log::{error, info, warn};
 use slint::{
-    PhysicalPosition, PhysicalSize, PlatformError,
+    ComponentHandle, Image, ModelRc, PhysicalPosition, PhysicalSize, PlatformError, SharedString,
+    VecModel,
     platform::{
         Platform, PointerEventButton, WindowAdapter, WindowEvent,
         software_renderer::{MinimalSoftwareWindow, RepaintBufferType},
         update_timers_and_animations,
     },
 };
-use smoltcp::iface::SocketStorage;
 
+#[cfg(feature = "battery")]
+use crate::adc::{BatteryEvent, BatteryView};
+#[cfg(feature = "scripting")]
+use crate::script::{ScriptAction, ScriptView, Trigger};
 use crate::{
-    display_screen::init_ili9341_display,
-    http_client::{HttpClient, Method},
-    secrets::{TEST_ADDRESS, TEST_IP, WIFI_PASSWORD, WIFI_SSID},
-    slint_renderer::SlintRenderer,
-    touch_input::{TouchInputProvider, TouchInputResponse, Xpt2046TouchInput},
-    wifi::{Wifi, obtain_ip},
-    ws_client::WsClient,
+    apps::{AppRegistry, Registrar},
+    assets::Assets,
+    auth::{DeviceEvent, DevicePrompt},
+    backlight::Backlight,
+    boot::{BootState, BootTimeline},
+    buzzer::Buzzer,
+    calendar::Agenda,
+    chat::ChatMessage,
+    color_lut::{ColorCalibration, ColorCommand},
+    config::{ConfigStore, Settings},
+    console::{Command, Console},
+    data_binding::Tag,
+    diagnostics::{LogRing, Snapshot},
+    display_screen::{OrientationCommand, ScreenOrientation, init_display},
+    energy_meter::{EnergyMeter, EnergyView},
+    error::{Error, ErrorBanner, ErrorCommand, ErrorView, Subsystem},
+    events::EventHooks,
+    fast_label::FastLabel,
+    fat_check::CheckLevel,
+    feed::Feed,
+    gpio_pins::{GpioCommand, GpioPins, PinMode, PinView},
+    heap_diff::HeapSnapshots,
+    heartbeat::Heartbeat,
+    hw_scroll::ListScroll,
+    latency::{DragPredictor, LatencyTracker},
+    logger::LogFile,
+    http_server::Route,
+    navigation::{BackButton, BackSwipe, Navigator, PullDown},
+    night_mode::{NightMode, NightView},
+    network::{NetCommand, NetConfig, NetEvent},
+    notifications::Notifications,
+    now_playing::{NowPlayingEvent, PlayerCommand, Track},
+    perf::{FramePacer, PerfStats},
+    ping::PingHost,
+    power::{IdleTimeout, ScreenState},
+    provisioning::{CredentialStore, Credentials, SdCredentialStore},
+    quick_settings::{QuickCommand, QuickState},
+    reliability::{Reliability, ReliabilityRecord},
+    render_profile::RenderProfile,
+    rules::{Builtin, RuleAction, RuleEngine, RuleEvent},
+    screenshot::ScreenshotError,
+    sdcard::SdStorage,
+    sensors::{SensorView, Sensors},
+    servo::{PwmOutput, Servo},
+    shutdown::{Shutdown, ShutdownKind},
+    sleep::{LowPower, SleepAction},
+    stopwatch::{Lap, StopwatchCommand},
+    slint_renderer::{Sampled, SlintRenderer},
+    spi_bus::SharedSpi,
+    standby::{Standby, WakeTrigger, standby_cycles},
+    stepper::{Stepper, StepperCommand, StepperView},
+    telemetry::{MemoryStats, Telemetry, TelemetryEvent},
+    timers::{CountdownView, TimerCommand},
+    touch_heatmap::TouchHeatmap,
+    touch_input::{InputQueue, TouchInputProvider, TouchInputResponse},
+    watch::{Watch, WatchReceiver},
+    watchdog::{Phase, Watchdog},
+    wifi::{AccessPoint, Wifi, WifiCommand},
+    webhook::{Trigger, Webhooks},
+    ws_topics::{TopicRouter, TopicValue},
 };
+#[cfg(feature = "psram")]
+use crate::slint_renderer::FrameBuffers;
+#[cfg(feature = "ft6236")]
+use crate::ft6236::Ft6236TouchInput;
+#[cfg(not(feature = "ft6236"))]
+use crate::touch_input::Xpt2046TouchInput;
 
 extern crate alloc;
 
+#[cfg(feature = "battery")]
+mod adc;
+mod apps;
+mod assets;
+mod auth;
+mod backlight;
+mod boot;
+mod boot_logo;
+mod build_info;
+mod buzzer;
+mod calendar;
+mod chat;
+mod clock;
+mod coexistence;
+mod color_lut;
+mod config;
+mod config_sync;
+mod console;
+mod data_binding;
+mod dht22;
+mod diagnostics;
 mod display_screen;
+mod dual_core;
+mod energy_meter;
+mod error;
+mod events;
+mod fast_label;
+mod fat_check;
+mod fault_injection;
+#[cfg(feature = "ffi")]
+mod ffi;
+mod feed;
+mod fonts;
+#[cfg(feature = "ft6236")]
+mod ft6236;
+mod gpio_pins;
+mod heap_diff;
+mod heartbeat;
 mod http_client;
-mod secrets;
+mod http_server;
+mod hw_scroll;
+mod image_loader;
+mod latency;
+mod logger;
+mod mdns;
+#[cfg(feature = "mock-server")]
+mod mock_server;
+mod navigation;
+mod night_mode;
+mod network;
+mod notifications;
+mod now_playing;
+mod onewire;
+mod outbox;
+#[cfg(feature = "palette")]
+mod palette;
+mod panic_screen;
+mod partitions;
+mod perf;
+mod ping;
+mod power;
+mod power_fail;
+mod provisioning;
+mod qr;
+mod quick_settings;
+mod reliability;
+mod render_profile;
+mod rules;
+mod safe_mode;
+mod scheduler;
+#[cfg(feature = "scripting")]
+mod script;
+mod screenshot;
+mod sdcard;
+mod sensors;
+mod servo;
+mod shutdown;
+mod signal;
+mod sleep;
 mod slint_renderer;
+mod sntp;
+mod socket_pool;
+mod span_flush;
+mod spi_bus;
+mod standby;
+mod stepper;
+mod stopwatch;
+mod telemetry;
+mod timers;
+mod touch_heatmap;
 mod touch_input;
+mod udp;
+mod watch;
+mod watchdog;
+mod webhook;
 mod wifi;
 mod ws_client;
+mod ws_session;
+mod ws_topics;
+mod xml;
 
 // This creates a default app-descriptor required by the esp-idf bootloader.
 // For more information see: <https://docs.espressif.com/projects/esp-idf/en/stable/esp32/api-reference/system/app_image_format.html#application-description>
@@ -63,81 +221,230 @@ esp_bootloader_esp_idf::esp_app_desc!();
 
 slint::include_modules!();
 
+const ICON_PATH: &str = "ICON.BMP";
+const BACKLIGHT_FADE_MS: u16 = 400;
+const SLIDER_FADE_MS: u16 = 100;
+// slider drags produce a stream of values, only the one the user settles on hits flash
+const BRIGHTNESS_SAVE_DELAY_MS: u64 = 2000;
+const IDLE_DIM_LEVEL: u8 = 15;
+const TARGET_FPS: u32 = 30;
+const SCREENSHOT_DELAY_SECS: u64 = 5;
+const TELEMETRY_INTERVAL_SECS: u64 = 10;
+// how long deep sleep waits for the network core to leave the AP
+const NET_SUSPEND_TIMEOUT_SECS: u64 = 2;
+// below this buffering an HTTP response or decoding a PNG is likely to fail
+const LOW_HEAP_THRESHOLD: usize = 12 * 1024;
+// apps that modules add to the launcher after the built-in ones
+const APP_REGISTRARS: &[Registrar] = &[
+    #[cfg(feature = "scripting")]
+    script::register_app,
+];
+
 fn handle_input(
     window: &Rc<MinimalSoftwareWindow>,
-    touch_input_provider: &mut impl TouchInputProvider,
+    input: TouchInputResponse,
 ) -> Result<(), PlatformError> {
-    if let Ok(x) = touch_input_provider.get_input() {
-        match x {
-            TouchInputResponse::Moved { x, y } => {
-                let logical = PhysicalPosition::new(x, y).to_logical(window.scale_factor());
-                window.try_dispatch_event(WindowEvent::PointerMoved { position: logical })?;
-            }
-            TouchInputResponse::Pressed { x, y } => {
-                let logical = PhysicalPosition::new(x, y).to_logical(window.scale_factor());
-                window.try_dispatch_event(WindowEvent::PointerPressed {
-                    position: logical,
-                    button: PointerEventButton::Left,
-                })?;
-            }
-            TouchInputResponse::Released { x, y } => {
-                window.try_dispatch_event(WindowEvent::PointerReleased {
-                    position: PhysicalPosition::new(x, y).to_logical(window.scale_factor()),
-                    button: PointerEventButton::Left,
-                })?;
-                window.try_dispatch_event(WindowEvent::PointerExited)?;
-            }
-            TouchInputResponse::NoInput => (),
+    match input {
+        TouchInputResponse::Moved { x, y } => {
+            let logical = PhysicalPosition::new(x, y).to_logical(window.scale_factor());
+            window.try_dispatch_event(WindowEvent::PointerMoved { position: logical })?;
+        }
+        TouchInputResponse::Pressed { x, y } => {
+            let logical = PhysicalPosition::new(x, y).to_logical(window.scale_factor());
+            window.try_dispatch_event(WindowEvent::PointerPressed {
+                position: logical,
+                button: PointerEventButton::Left,
+            })?;
         }
+        TouchInputResponse::Released { x, y } => {
+            window.try_dispatch_event(WindowEvent::PointerReleased {
+                position: PhysicalPosition::new(x, y).to_logical(window.scale_factor()),
+                button: PointerEventButton::Left,
+            })?;
+            window.try_dispatch_event(WindowEvent::PointerExited)?;
+        }
+        TouchInputResponse::NoInput => (),
     }
 
     Ok(())
 }
 
-struct DummyTime;
-impl TimeSource for DummyTime {
-    fn get_timestamp(&self) -> Timestamp {
-        Timestamp::from_calendar(2024, 1, 1, 0, 0, 0).unwrap()
-    }
+// renders the current frame to the card, the panel gets a full frame afterwards
+fn save_screenshot(
+    window: &MinimalSoftwareWindow,
+    sd: Option<&SdStorage<'_>>,
+) -> Result<String, ScreenshotError> {
+    let mut result = Err(ScreenshotError::NoCard);
+    window.request_redraw();
+    let size = window.window().size();
+    window.draw_if_needed(|renderer| result = screenshot::save(sd, renderer, size));
+    window.request_redraw();
+    result
 }
 
-fn init_sd_card<'a>(spi: &'a RefCell<Spi<'a, Blocking>>, sd_cs_pin: impl OutputPin + 'a) {
-    let sd_cs = Output::new(sd_cs_pin, Level::High, Default::default());
-    let sd_spi_dev = RefCellDevice::new_no_delay(spi, sd_cs).unwrap();
+// for changes slint can't see, like the colour correction on the way to the panel: the next
+// frame draws every line instead of only what slint thinks changed
+fn repaint_all(window: &MinimalSoftwareWindow) {
+    window.request_redraw();
+    window.draw_if_needed(|renderer| {
+        // switching the buffer type drops the partial rendering state
+        let repaint_buffer = renderer.repaint_buffer_type();
+        renderer.set_repaint_buffer_type(RepaintBufferType::NewBuffer);
+        renderer.set_repaint_buffer_type(repaint_buffer);
+    });
+    window.request_redraw();
+}
 
-    let sd = SdCard::new(sd_spi_dev, Delay::new());
-    let controller = VolumeManager::new(sd, DummyTime);
+fn page_to_ui(page: navigation::Page) -> Page {
+    match page {
+        navigation::Page::Home => Page::Home,
+        navigation::Page::Settings => Page::Settings,
+        navigation::Page::WifiSetup => Page::WifiSetup,
+        navigation::Page::About => Page::About,
+        navigation::Page::NowPlaying => Page::NowPlaying,
+        navigation::Page::Agenda => Page::Agenda,
+        navigation::Page::News => Page::News,
+        navigation::Page::Chat => Page::Chat,
+        navigation::Page::Timers => Page::Timers,
+        navigation::Page::Stopwatch => Page::Stopwatch,
+        navigation::Page::Gpio => Page::Gpio,
+        navigation::Page::Outputs => Page::Outputs,
+        navigation::Page::Jog => Page::Jog,
+        navigation::Page::Sensors => Page::Sensors,
+        navigation::Page::Energy => Page::Energy,
+        navigation::Page::Script => Page::Script,
+        navigation::Page::Launcher => Page::Launcher,
+        navigation::Page::Color => Page::Color,
+        navigation::Page::Reliability => Page::Reliability,
+        navigation::Page::Network => Page::Network,
+    }
+}
 
-    let mut attempt = 0;
-    let max_attempts = 5;
-    loop {
-        attempt += 1;
-        match controller.open_volume(VolumeIdx(0)) {
-            Ok(volume) => {
-                if let Ok(root) = volume.open_root_dir() {
-                    if let Ok(file) = root.open_file_in_dir("HELLO.TXT", Mode::ReadOnly) {
-                        let mut buf = [0u8; 64];
-                        if let Ok(n) = file.read(&mut buf) {
-                            esp_println::println!("SD: Read {} bytes: {:?}", n, &buf[..n]);
-                        }
-                    }
-                }
-                break;
-            }
-            Err(e) => {
-                esp_println::println!("SD: Attempt {}/{} failed: {:?}", attempt, max_attempts, e);
-                if attempt >= max_attempts {
-                    break;
-                }
-                Delay::new().delay_millis(50u32);
-            }
-        }
+fn page_from_ui(page: Page) -> navigation::Page {
+    match page {
+        // nothing navigates to the loading screen
+        Page::Loading | Page::Home => navigation::Page::Home,
+        Page::Settings => navigation::Page::Settings,
+        Page::WifiSetup => navigation::Page::WifiSetup,
+        Page::About => navigation::Page::About,
+        Page::NowPlaying => navigation::Page::NowPlaying,
+        Page::Agenda => navigation::Page::Agenda,
+        Page::News => navigation::Page::News,
+        Page::Chat => navigation::Page::Chat,
+        Page::Timers => navigation::Page::Timers,
+        Page::Stopwatch => navigation::Page::Stopwatch,
+        Page::Gpio => navigation::Page::Gpio,
+        Page::Outputs => navigation::Page::Outputs,
+        Page::Jog => navigation::Page::Jog,
+        Page::Sensors => navigation::Page::Sensors,
+        Page::Energy => navigation::Page::Energy,
+        Page::Script => navigation::Page::Script,
+        Page::Launcher => navigation::Page::Launcher,
+        Page::Color => navigation::Page::Color,
+        Page::Reliability => navigation::Page::Reliability,
+        Page::Network => navigation::Page::Network,
     }
 }
 
 struct EspBackend {
     window: RefCell<Option<Rc<MinimalSoftwareWindow>>>,
     peripherals: RefCell<Option<Peripherals>>,
+    events: RefCell<EventHooks>,
+    wifi_connected: Watch<bool>,
+    // 0..=4, from the smoothed RSSI
+    wifi_bars: Watch<i32>,
+    ip_address: Watch<SharedString>,
+    icon: Watch<Image>,
+    storage_warning: Watch<SharedString>,
+    // heartbeats to the server aren't getting through
+    heartbeat_late: Watch<bool>,
+    // what failed and is being retried, and the banner's retry and dismiss taps
+    errors: Watch<ErrorView>,
+    error_command_requested: RefCell<Option<WatchReceiver<(u32, ErrorCommand)>>>,
+    standby_requested: RefCell<Option<WatchReceiver<bool>>>,
+    // the shutdown screen's text, empty while running; restart and power off taps
+    shutdown_message: Watch<SharedString>,
+    shutdown_requested: RefCell<Option<WatchReceiver<(u32, ShutdownKind)>>>,
+    brightness: Watch<i32>,
+    brightness_requested: RefCell<Option<WatchReceiver<i32>>>,
+    touch_clicks: Watch<bool>,
+    touch_clicks_requested: RefCell<Option<WatchReceiver<bool>>>,
+    // (hz, ms) from Sound.play-tone
+    tone_requested: RefCell<Option<WatchReceiver<(u32, (i32, i32))>>>,
+    quick_settings: Watch<QuickState>,
+    quick_command_requested: RefCell<Option<WatchReceiver<(u32, QuickCommand)>>>,
+    reliability: Watch<ReliabilityRecord>,
+    // the network page's ping, and what came back
+    ping_requested: RefCell<Option<WatchReceiver<(u32, PingHost)>>>,
+    ping_result: Watch<SharedString>,
+    status: Watch<SharedString>,
+    diagnostics_requested: RefCell<Option<WatchReceiver<u32>>>,
+    log: LogRing,
+    notifications: Notifications,
+    clock_text: Watch<SharedString>,
+    webhook_requested: RefCell<Option<WatchReceiver<(u32, SharedString)>>>,
+    perf: Watch<PerfStats>,
+    heatmap: Watch<(Vec<u8>, u16)>,
+    memory: Watch<MemoryStats>,
+    latency_ms: Watch<i32>,
+    tags: Watch<Vec<Tag>>,
+    factory_reset_requested: RefCell<Option<WatchReceiver<u32>>>,
+    device_prompt: Watch<DevicePrompt>,
+    back_requested: Watch<u32>,
+    forget_wifi_requested: RefCell<Option<WatchReceiver<u32>>>,
+    // the last scan and a status line for the WiFi page
+    wifi_networks: Watch<(Vec<AccessPoint>, SharedString)>,
+    wifi_command_requested: RefCell<Option<WatchReceiver<(u32, WifiCommand)>>>,
+    now_playing: Watch<Track>,
+    album_art: Watch<Image>,
+    agenda: Watch<Agenda>,
+    feed: Watch<Feed>,
+    player_command_requested: RefCell<Option<WatchReceiver<(u32, PlayerCommand)>>>,
+    remote_text: Watch<SharedString>,
+    navigate_requested: Watch<(u32, navigation::Page)>,
+    chat: Watch<Vec<ChatMessage>>,
+    topics: Watch<Vec<TopicValue>>,
+    chat_send_requested: RefCell<Option<WatchReceiver<(u32, SharedString)>>>,
+    timers: Watch<Vec<CountdownView>>,
+    timer_alert: Watch<SharedString>,
+    timer_command_requested: RefCell<Option<WatchReceiver<(u32, TimerCommand)>>>,
+    timer_dismiss_requested: RefCell<Option<WatchReceiver<u32>>>,
+    screenshot_requested: RefCell<Option<WatchReceiver<u32>>>,
+    stopwatch_time: Watch<(SharedString, bool)>,
+    laps: Watch<Vec<Lap>>,
+    stopwatch_command_requested: RefCell<Option<WatchReceiver<(u32, StopwatchCommand)>>>,
+    gpio_pins: Watch<Vec<PinView>>,
+    gpio_command_requested: RefCell<Option<WatchReceiver<(u32, GpioCommand)>>>,
+    outputs: Watch<(i32, i32)>,
+    servo_requested: RefCell<Option<WatchReceiver<i32>>>,
+    pwm_requested: RefCell<Option<WatchReceiver<i32>>>,
+    stepper: Watch<StepperView>,
+    stepper_command_requested: RefCell<Option<WatchReceiver<(u32, StepperCommand)>>>,
+    orientation: Watch<SharedString>,
+    orientation_requested: RefCell<Option<WatchReceiver<(u32, OrientationCommand)>>>,
+    color: Watch<ColorCalibration>,
+    color_command_requested: RefCell<Option<WatchReceiver<(u32, ColorCommand)>>>,
+    night: Watch<NightView>,
+    sensors: Watch<Vec<SensorView>>,
+    energy: Watch<EnergyView>,
+    boot: Watch<BootState>,
+    // images built in from assets/, decoded on first use
+    assets: Assets,
+    // the page on screen, None while loading, for the render profile
+    shown_page_requested: RefCell<Option<WatchReceiver<Option<navigation::Page>>>>,
+    // where a ScrollList is and how far it has scrolled, the panel shifts it where it can
+    list_scroll_requested: RefCell<Option<WatchReceiver<ListScroll>>>,
+    // the divider on GPIO35, for the battery icon
+    #[cfg(feature = "battery")]
+    battery: Watch<BatteryView>,
+    // SCRIPT.TXT's labels and buttons, and the index of a pressed button
+    #[cfg(feature = "scripting")]
+    script: Watch<ScriptView>,
+    #[cfg(feature = "scripting")]
+    script_press_requested: RefCell<Option<WatchReceiver<(u32, usize)>>>,
+    // allocated with the window, None when PSRAM is short and frames go out line by line
+    #[cfg(feature = "psram")]
+    frames: RefCell<Option<FrameBuffers>>,
 }
 
 impl Default for EspBackend {
@@ -145,6 +452,89 @@ impl Default for EspBackend {
         Self {
             window: RefCell::new(None),
             peripherals: RefCell::new(None),
+            events: RefCell::new(EventHooks::default()),
+            wifi_connected: Watch::new(false),
+            wifi_bars: Watch::new(0),
+            ip_address: Watch::new(SharedString::new()),
+            icon: Watch::new(Image::default()),
+            storage_warning: Watch::new(SharedString::new()),
+            heartbeat_late: Watch::new(false),
+            errors: Watch::new(ErrorView::default()),
+            error_command_requested: RefCell::new(None),
+            standby_requested: RefCell::new(None),
+            shutdown_message: Watch::new(SharedString::new()),
+            shutdown_requested: RefCell::new(None),
+            brightness: Watch::new(100),
+            brightness_requested: RefCell::new(None),
+            touch_clicks: Watch::new(true),
+            touch_clicks_requested: RefCell::new(None),
+            tone_requested: RefCell::new(None),
+            quick_settings: Watch::new(QuickState::default()),
+            quick_command_requested: RefCell::new(None),
+            reliability: Watch::new(ReliabilityRecord::default()),
+            ping_requested: RefCell::new(None),
+            ping_result: Watch::new(SharedString::new()),
+            status: Watch::new(SharedString::new()),
+            diagnostics_requested: RefCell::new(None),
+            log: LogRing::new(),
+            notifications: Notifications::new(),
+            clock_text: Watch::new(SharedString::new()),
+            webhook_requested: RefCell::new(None),
+            perf: Watch::new(PerfStats::default()),
+            heatmap: Watch::new((Vec::new(), 0)),
+            memory: Watch::new(MemoryStats::default()),
+            latency_ms: Watch::new(0),
+            tags: Watch::new(Vec::new()),
+            factory_reset_requested: RefCell::new(None),
+            device_prompt: Watch::new(DevicePrompt::default()),
+            back_requested: Watch::new(0),
+            forget_wifi_requested: RefCell::new(None),
+            wifi_networks: Watch::new((Vec::new(), SharedString::new())),
+            wifi_command_requested: RefCell::new(None),
+            now_playing: Watch::new(Track::default()),
+            album_art: Watch::new(Image::default()),
+            agenda: Watch::new(Agenda::new()),
+            feed: Watch::new(Feed::default()),
+            player_command_requested: RefCell::new(None),
+            remote_text: Watch::new(SharedString::new()),
+            navigate_requested: Watch::new((0, navigation::Page::Home)),
+            chat: Watch::new(Vec::new()),
+            topics: Watch::new(Vec::new()),
+            chat_send_requested: RefCell::new(None),
+            timers: Watch::new(Vec::new()),
+            timer_alert: Watch::new(SharedString::new()),
+            timer_command_requested: RefCell::new(None),
+            timer_dismiss_requested: RefCell::new(None),
+            screenshot_requested: RefCell::new(None),
+            stopwatch_time: Watch::new((SharedString::new(), false)),
+            laps: Watch::new(Vec::new()),
+            stopwatch_command_requested: RefCell::new(None),
+            gpio_pins: Watch::new(Vec::new()),
+            gpio_command_requested: RefCell::new(None),
+            outputs: Watch::new((0, 0)),
+            servo_requested: RefCell::new(None),
+            pwm_requested: RefCell::new(None),
+            stepper: Watch::new(StepperView::default()),
+            stepper_command_requested: RefCell::new(None),
+            orientation: Watch::new(SharedString::new()),
+            orientation_requested: RefCell::new(None),
+            color: Watch::new(ColorCalibration::default()),
+            color_command_requested: RefCell::new(None),
+            night: Watch::new(NightView::default()),
+            sensors: Watch::new(Vec::new()),
+            energy: Watch::new(EnergyView::default()),
+            boot: Watch::new(BootState::STARTING),
+            assets: Assets::new(),
+            shown_page_requested: RefCell::new(None),
+            list_scroll_requested: RefCell::new(None),
+            #[cfg(feature = "battery")]
+            battery: Watch::new(BatteryView::default()),
+            #[cfg(feature = "scripting")]
+            script: Watch::new(ScriptView::default()),
+            #[cfg(feature = "scripting")]
+            script_press_requested: RefCell::new(None),
+            #[cfg(feature = "psram")]
+            frames: RefCell::new(None),
         }
     }
 }
@@ -155,17 +545,15 @@ fn create_spi<'a>(
     mosi: impl PeripheralOutput<'a>,
     miso: impl PeripheralInput<'a>,
     frequency: Rate,
-) -> Spi<'a, Blocking> {
-    Spi::<esp_hal::Blocking>::new(
+) -> Result<Spi<'a, Blocking>, Error> {
+    let spi = Spi::<esp_hal::Blocking>::new(
         spi,
         esp_hal::spi::master::Config::default()
             .with_frequency(frequency)
             .with_mode(esp_hal::spi::Mode::_0),
     )
-    .unwrap()
-    .with_sck(sck)
-    .with_mosi(mosi)
-    .with_miso(miso)
+    .map_err(Error::Spi)?;
+    Ok(spi.with_sck(sck).with_mosi(mosi).with_miso(miso))
 }
 
 impl Platform for EspBackend {
@@ -174,7 +562,20 @@ impl Platform for EspBackend {
     }
 
     fn create_window_adapter(&self) -> Result<Rc<dyn WindowAdapter>, PlatformError> {
-        let w = MinimalSoftwareWindow::new(RepaintBufferType::ReusedBuffer);
+        // the window is told up front whether the renderer keeps frames of its own
+        #[cfg(feature = "psram")]
+        let swapped = {
+            let frames = FrameBuffers::try_new();
+            if frames.is_none() {
+                info!("Display: no room for two framebuffers, drawing line by line");
+            }
+            let swapped = frames.is_some();
+            self.frames.replace(frames);
+            swapped
+        };
+        #[cfg(not(feature = "psram"))]
+        let swapped = false;
+        let w = MinimalSoftwareWindow::new(slint_renderer::repaint_buffer(swapped));
         self.window.replace(Some(w.clone()));
         Ok(w)
     }
@@ -190,105 +591,1782 @@ impl Platform for EspBackend {
         let rng = Rng::new();
         esp_rtos::start(timg0.timer0);
 
-        let radio_init = esp_radio::init().unwrap();
-        let mut sockets_buf: [SocketStorage; 4] = Default::default();
-        let mut wifi = Wifi::new(peripherals.WIFI, &radio_init, WIFI_SSID, WIFI_PASSWORD);
-        wifi.initialize();
-        let mut stack = Rc::new(wifi::build_stack(
-            wifi.interfaces.sta,
-            &mut sockets_buf,
-            || Instant::now().duration_since_epoch().as_millis(),
-            rng.random(),
+        self.log.record(format!("Firmware {}", build_info::summary()));
+        if safe_mode::is_active() {
+            return self.run_safe_mode(peripherals);
+        }
+        let mut boot = BootTimeline::new();
+
+        let trigger = WakeTrigger::current();
+        info!("Woken by {:?} after {} standby cycles", trigger, standby_cycles());
+        self.events.borrow_mut().emit_woken(trigger);
+        // GPIO39 - wake button, GPIO34 - sensor line; both RTC capable input-only pins. GPIO36,
+        // the touch IRQ, belongs to the touch driver; standby only borrows its RTC side to wake
+        // on a touch
+        let mut rtc = Rtc::new(peripherals.LPWR);
+        if clock::restore(&rtc) {
+            info!("Clock restored from RTC");
+        }
+        power_fail::arm(&mut rtc, power_fail::DEFAULT_THRESHOLD);
+        let mut standby = Some(Standby::new(
+            rtc,
+            peripherals.GPIO39,
+            peripherals.GPIO34,
+            unsafe { esp_hal::peripherals::GPIO36::steal() },
         ));
-        obtain_ip(&mut stack);
-
-        let mut http = HttpClient::new(stack.clone(), TEST_ADDRESS, TEST_IP);
-        let response = http
-            .request(Method::Get, "/api/Tags/tag-crime", None, 10)
-            .unwrap();
-        println!("{}", response);
-
-        let mut http = HttpClient::new(stack.clone(), TEST_ADDRESS, TEST_IP);
-        let response = http
-            .request(Method::Delete, "/api/Tags/tag-crime", None, 10)
-            .unwrap();
-        println!("{}", response);
-
-        let mut http = HttpClient::new(stack.clone(), TEST_ADDRESS, TEST_IP);
-        let body = br#"{"hello":"esp32"}"#;
-        let response = http.request(Method::Post, "/api/Tags/tag-crime", Some(body), 10)?;
-        println!("{}", response);
-
-        let mut http = HttpClient::new(stack.clone(), TEST_ADDRESS, TEST_IP);
-        let body = br#"{"hello":"esp32"}"#;
-        let response = http.request(Method::Put, "/api/Tags/tag-crime", Some(body), 10)?;
-        println!("{}", response);
-
-        let mut http = HttpClient::new(stack.clone(), TEST_ADDRESS, TEST_IP);
-        let body = br#"{"hello":"esp32"}"#;
-        let response = http.request(Method::Patch, "/api/Tags/tag-crime", Some(body), 10)?;
-        println!("{}", response);
-
-        let mut ws = WsClient::new("testtest", TEST_IP);
-
-        let mut tcp_rx = [0u8; 1536];
-        let mut tcp_tx = [0u8; 1536];
-        let mut socket = stack.get_socket(&mut tcp_rx, &mut tcp_tx);
-        ws.connect(&mut socket).unwrap();
-        ws.poll(&mut socket, Some(b"test"));
+        let mut standby_requested = self
+            .standby_requested
+            .borrow_mut()
+            .take()
+            .expect("Standby request receiver already taken");
+        let mut shutdown_requested = self
+            .shutdown_requested
+            .borrow_mut()
+            .take()
+            .expect("Shutdown request receiver already taken");
+        shutdown_requested.changed();
+        let mut shutdown = Shutdown::default();
+        let mut brightness_requested = self
+            .brightness_requested
+            .borrow_mut()
+            .take()
+            .expect("Brightness request receiver already taken");
+        // the stored setting decides the boot brightness, not the watch's initial value
+        brightness_requested.changed();
+        let mut touch_clicks_requested = self
+            .touch_clicks_requested
+            .borrow_mut()
+            .take()
+            .expect("Touch clicks request receiver already taken");
+        touch_clicks_requested.changed();
+        let mut tone_requested = self
+            .tone_requested
+            .borrow_mut()
+            .take()
+            .expect("Tone request receiver already taken");
+        tone_requested.changed();
+        let mut quick_command_requested = self
+            .quick_command_requested
+            .borrow_mut()
+            .take()
+            .expect("Quick settings receiver already taken");
+        quick_command_requested.changed();
+        let mut ping_requested = self
+            .ping_requested
+            .borrow_mut()
+            .take()
+            .expect("Ping request receiver already taken");
+        ping_requested.changed();
+        let mut diagnostics_requested = self
+            .diagnostics_requested
+            .borrow_mut()
+            .take()
+            .expect("Diagnostics request receiver already taken");
+        diagnostics_requested.changed();
+        let mut webhook_requested = self
+            .webhook_requested
+            .borrow_mut()
+            .take()
+            .expect("Webhook request receiver already taken");
+        webhook_requested.changed();
+        let mut forget_wifi_requested = self
+            .forget_wifi_requested
+            .borrow_mut()
+            .take()
+            .expect("Forget WiFi receiver already taken");
+        forget_wifi_requested.changed();
+        let mut wifi_command_requested = self
+            .wifi_command_requested
+            .borrow_mut()
+            .take()
+            .expect("WiFi command receiver already taken");
+        wifi_command_requested.changed();
+        let mut player_command_requested = self
+            .player_command_requested
+            .borrow_mut()
+            .take()
+            .expect("Player command receiver already taken");
+        player_command_requested.changed();
+        let mut chat_send_requested = self
+            .chat_send_requested
+            .borrow_mut()
+            .take()
+            .expect("Chat send receiver already taken");
+        chat_send_requested.changed();
+        let mut timer_command_requested = self
+            .timer_command_requested
+            .borrow_mut()
+            .take()
+            .expect("Timer command receiver already taken");
+        timer_command_requested.changed();
+        let mut timer_dismiss_requested = self
+            .timer_dismiss_requested
+            .borrow_mut()
+            .take()
+            .expect("Timer dismiss receiver already taken");
+        timer_dismiss_requested.changed();
+        let mut screenshot_requested = self
+            .screenshot_requested
+            .borrow_mut()
+            .take()
+            .expect("Screenshot receiver already taken");
+        screenshot_requested.changed();
+        let mut stopwatch_command_requested = self
+            .stopwatch_command_requested
+            .borrow_mut()
+            .take()
+            .expect("Stopwatch command receiver already taken");
+        stopwatch_command_requested.changed();
+        let mut gpio_command_requested = self
+            .gpio_command_requested
+            .borrow_mut()
+            .take()
+            .expect("GPIO command receiver already taken");
+        gpio_command_requested.changed();
+        #[cfg(feature = "scripting")]
+        let mut script_press_requested = self
+            .script_press_requested
+            .borrow_mut()
+            .take()
+            .expect("Script press receiver already taken");
+        #[cfg(feature = "scripting")]
+        script_press_requested.changed();
+        let mut servo_requested = self
+            .servo_requested
+            .borrow_mut()
+            .take()
+            .expect("Servo request receiver already taken");
+        // the servo stays limp until the slider is touched
+        servo_requested.changed();
+        let mut pwm_requested = self
+            .pwm_requested
+            .borrow_mut()
+            .take()
+            .expect("PWM request receiver already taken");
+        pwm_requested.changed();
+        let mut stepper_command_requested = self
+            .stepper_command_requested
+            .borrow_mut()
+            .take()
+            .expect("Stepper command receiver already taken");
+        stepper_command_requested.changed();
+        let mut orientation_requested = self
+            .orientation_requested
+            .borrow_mut()
+            .take()
+            .expect("Orientation receiver already taken");
+        orientation_requested.changed();
+        let mut color_command_requested = self
+            .color_command_requested
+            .borrow_mut()
+            .take()
+            .expect("Color command receiver already taken");
+        color_command_requested.changed();
+        let mut shown_page_requested = self
+            .shown_page_requested
+            .borrow_mut()
+            .take()
+            .expect("Shown page receiver already taken");
+        let mut error_command_requested = self
+            .error_command_requested
+            .borrow_mut()
+            .take()
+            .expect("Error command receiver already taken");
+        error_command_requested.changed();
+        let mut error_banner = ErrorBanner::new();
+        let mut list_scroll_requested = self
+            .list_scroll_requested
+            .borrow_mut()
+            .take()
+            .expect("List scroll receiver already taken");
+        list_scroll_requested.changed();
 
         //SD requires 100kHz-400kHz
-        //Display in order to be fast needs like 40MHz
-        //XPT 2046 can have around 4MHz - it doesn't work on values that are too big
+        //Display and touch share the fast bus, each device switches it to its own clock
+        //(40MHz for the ILI9341/ST7789, 20MHz for the ILI9486, 2MHz for the XPT2046) when it
+        //takes the bus
         let fast_spi = create_spi(
             peripherals.SPI3,
             peripherals.GPIO18,
             peripherals.GPIO23,
             peripherals.GPIO19,
-            Rate::from_mhz(4),
-        );
+            Rate::from_mhz(2),
+        )?;
         let slow_spi = create_spi(
             peripherals.SPI2,
             peripherals.GPIO14,
             peripherals.GPIO13,
             peripherals.GPIO27, //GPIO12 is a bootstrapping pin and doin lotsa trouble on boot
             Rate::from_khz(400),
+        )?;
+
+        let fast_spi_bus = SharedSpi::new(fast_spi);
+        let slow_spi_ref_cell = RefCell::new(slow_spi);
+
+        let mut config = ConfigStore::new(peripherals.FLASH);
+        let mut settings = config.load();
+        clock::set_utc_offset(settings.time.utc_offset_minutes);
+        self.brightness.send(settings.brightness as i32);
+        self.touch_clicks.send(settings.touch_clicks);
+        let mut quick = QuickState {
+            rotation_locked: settings.rotation_locked,
+            muted: settings.muted,
+            ..Default::default()
+        };
+        self.quick_settings.send(quick);
+        let mut reliability = Reliability::start(settings.reliability.clone());
+        if let Some(standby) = standby.as_mut() {
+            standby.set_touch_wake(settings.sleep.touch_wake);
+        }
+
+        let mut buf512 = [0u8; 512];
+        let display = init_display(
+            &fast_spi_bus,
+            peripherals.GPIO2,
+            peripherals.GPIO15,
+            peripherals.GPIO4,
+            &mut buf512,
+            settings.orientation,
+        )
+        .map_err(Error::from)?;
+        let mut slint_renderer =
+            SlintRenderer::new(display, settings.orientation, &settings.color);
+        #[cfg(feature = "psram")]
+        let mut frames = self.frames.borrow_mut().take();
+
+        // GPIO32 - panel LED pin, dark until the first frame is drawn
+        let mut ledc = Ledc::new(peripherals.LEDC);
+        let backlight_timer =
+            backlight::init_timer(&mut ledc, timer::Number::Timer0).map_err(Error::from)?;
+        let mut backlight = Backlight::new(
+            &ledc,
+            &backlight_timer,
+            channel::Number::Channel0,
+            peripherals.GPIO32,
+        )
+        .map_err(Error::from)?;
+        let mut brightness_changed_at: Option<Instant> = None;
+
+        let window = self.window.borrow().clone().unwrap();
+        let (width, height) = settings.orientation.size();
+        window.set_size(PhysicalSize::new(width, height));
+        self.orientation.send(format!("{}", settings.orientation).into());
+        let mut color = settings.color;
+        self.color.send(color);
+        let mut night = NightMode::new(settings.night);
+        self.night.send(night.view());
+        // the loading screen waits until the logo has been up for a while
+        let logo_until = boot_logo::Logo::read(&mut config)
+            .filter(|logo| {
+                let screen = (width as usize, height as usize);
+                let mut row = |y, line: &mut _| logo.row(&mut config, y, line);
+                slint_renderer.draw_image(logo.size(), screen, &mut row)
+            })
+            .map(|_| Instant::now() + Duration::from_millis(boot_logo::HOLD_MS));
+
+        // GPIO36 - PENIRQ, the controller is only read after it fires
+        let mut io = Io::new(peripherals.IO_MUX);
+        #[cfg(not(feature = "ft6236"))]
+        let mut touch_input = Xpt2046TouchInput::create(
+            &fast_spi_bus,
+            peripherals.GPIO33,
+            peripherals.GPIO36,
+            &mut io,
+            settings.touch_calibration,
+            settings.orientation,
+        )
+        .map_err(Error::from)?;
+        // capacitive: GPIO33 - SDA, GPIO5 - SCL, GPIO36 - INT
+        #[cfg(feature = "ft6236")]
+        let mut touch_input = Ft6236TouchInput::create(
+            peripherals.I2C0,
+            peripherals.GPIO33,
+            peripherals.GPIO5,
+            peripherals.GPIO36,
+            &mut io,
+            settings.touch_calibration,
+            settings.orientation,
+        )
+        .map_err(Error::from)?;
+        // a glitch on the bus, the panel still works and the loop retries it
+        if let Err(e) = touch_input.init() {
+            warn!("Touch: {}", e);
+            error_banner.failed(Subsystem::Touch, &e.into());
+        }
+
+        // the loading screen goes up before the card and the radio are touched
+        boot.mark("display");
+        self.boot.send(BootState::Loading { stage: "Reading SD card", done: 0 });
+        let mut draw_boot_frame = || {
+            if logo_until.is_some_and(|until| Instant::now() < until) {
+                return;
+            }
+            update_timers_and_animations();
+            let drawn = window.draw_if_needed(|renderer| {
+                #[cfg(feature = "psram")]
+                if let Some(frames) = frames.as_mut() {
+                    let stride = window.window().size().width as usize;
+                    frames.render(renderer, &mut slint_renderer, stride, || {});
+                    return;
+                }
+                renderer.render_by_line(&mut slint_renderer);
+            });
+            if drawn {
+                slint_renderer.frame_drawn();
+            }
+        };
+        draw_boot_frame();
+        backlight.fade_to(settings.brightness, BACKLIGHT_FADE_MS).ok();
+        boot.first_frame();
+
+        // SD goes first, it holds the WiFi credentials
+        let mounted = SdStorage::mount(&slow_spi_ref_cell, peripherals.GPIO21, CheckLevel::Full);
+        let mut sd = match mounted {
+            Ok(sd) => Some(sd),
+            Err(e) => {
+                warn!("SD: {}", e);
+                error_banner.failed(Subsystem::SdCard, &e.into());
+                None
+            }
+        };
+        let mut log_file = sd.as_ref().map(LogFile::open);
+        if sd.is_none() {
+            logger::set_file_level(log::LevelFilter::Off);
+        }
+        if let Some(sd) = &sd {
+            let report = sd.check_report();
+            if report.needs_reformat() {
+                self.storage_warning.send("SD card damaged, reformat needed".into());
+            } else if report.unreadable {
+                self.storage_warning.send("SD card check couldn't read the card".into());
+            } else if report.repaired {
+                self.storage_warning.send("SD card was repaired".into());
+            }
+            self.events.borrow_mut().emit_sd_mounted();
+            slint_renderer.set_coexistence(coexistence::load_config(sd));
+            // has to be registered before a page lays out any text, so far only the loading
+            // screen has
+            match fonts::load_fallback(sd, fonts::FALLBACK_FONT_PATH) {
+                Ok(font) => fonts::register(window.window(), font),
+                Err(e) => warn!("Fonts: {}", e),
+            }
+            // shown from the next boot on, this one is past showing a logo
+            match boot_logo::install(sd, &mut config) {
+                Ok(true) => info!("Boot logo: installed from the card"),
+                Ok(false) => {}
+                Err(e) => warn!("Boot logo: {}", e),
+            }
+        }
+
+        // one on the card replaces the built-in icon
+        let icon = sd.as_ref().and_then(|sd| {
+            image_loader::load_image(sd, ICON_PATH).inspect_err(|e| warn!("Icon: {}", e)).ok()
+        });
+        match icon.map_or_else(|| self.assets.get("icon"), Ok) {
+            Ok(icon) => self.icon.send(icon),
+            Err(e) => warn!("Icon: {}", e),
+        }
+
+        if settings.wifi.is_none() {
+            settings.wifi = sd
+                .as_ref()
+                .and_then(|sd| SdCredentialStore::new(sd).load());
+        }
+        if settings.rules.is_empty() {
+            settings.rules = sd.as_ref().map(rules::load_from_sd).unwrap_or_default();
+        }
+        if settings.schedule.is_none() {
+            settings.schedule = sd.as_ref().and_then(power::load_schedule);
+        }
+        #[cfg(feature = "fault-injection")]
+        if let Some(sd) = &sd {
+            fault_injection::load_from_sd(sd);
+        }
+        let endpoints = sd.as_ref().map(webhook::load_from_sd).unwrap_or_default();
+        let calendar_route = sd.as_ref().and_then(calendar::load_route);
+        let feed_route = sd.as_ref().and_then(feed::load_route);
+        let ws_topics = sd.as_ref().map(ws_session::load_topics).unwrap_or_default();
+        // the WS.TXT topics are listed on home, these few drive widgets of their own
+        let mut topics = TopicRouter::new(self.topics.clone(), &ws_topics);
+        topics.bind("text", self.remote_text.clone());
+        topics.bind("status", self.status.clone());
+        let notifications = self.notifications.clone();
+        topics.on("notify", move |text| notifications.notify(text));
+        let mut webhooks = Webhooks::new(endpoints);
+        if let Some(sd) = &sd {
+            webhooks.outbox().restore(sd);
+        }
+        let mut heartbeat = sd.as_ref().and_then(heartbeat::load_config).map(Heartbeat::new);
+
+        boot.mark("storage");
+        let stage = match settings.wifi {
+            _ if cfg!(feature = "mock-server") => "Starting offline",
+            Some(_) => "Starting WiFi",
+            None => "WiFi setup: join the esp32-display-setup network",
+        };
+        self.boot.send(BootState::Loading { stage, done: 1 });
+        draw_boot_frame();
+
+        // leaked so the driver can move to the APP core with the rest of the network
+        let radio = esp_radio::init().map_err(|e| Error::Radio(format!("{:?}", e)))?;
+        let radio_init: &'static _ = Box::leak(Box::new(radio));
+        // without the driver the device runs on offline, the banner says why
+        let mut wifi = match Wifi::new(peripherals.WIFI, radio_init) {
+            Ok(wifi) => Some(wifi),
+            Err(e) => {
+                error_banner.failed(Subsystem::Wifi, &e.into());
+                None
+            }
+        };
+        // offline the network core never joins an AP, there is nothing to set up
+        if let Some(wifi) = wifi.as_mut().filter(|_| !cfg!(feature = "mock-server")) {
+            let credentials = match settings.wifi.clone() {
+                Some(credentials) => Ok(credentials),
+                None => provisioning::provision(wifi, rng.random()).map(|submission| {
+                    settings.wifi = Some(submission.credentials.clone());
+                    if let Some(server) = submission.server {
+                        settings.server = server;
+                    }
+                    settings.static_ip = submission.static_ip;
+                    if let Err(e) = config.save(&settings) {
+                        error!("Failed to persist settings: {}", e);
+                    }
+                    submission.credentials
+                }),
+            };
+            let configured = credentials.and_then(|credentials| {
+                wifi.configure_client(&credentials.ssid, &credentials.password)
+            });
+            if let Err(e) = configured {
+                error_banner.failed(Subsystem::Wifi, &e.into());
+            }
+        }
+
+        let net_config = NetConfig {
+            // clients take &'static str, settings are loaded once per boot so leaking is fine
+            server_host: String::from(settings.server.host.as_str()).leak(),
+            server_ip: settings.server.address(),
+            ntp_address: settings.time.ntp_address(),
+            auth: sd.as_ref().and_then(auth::read_from_sd),
+            refresh_token: settings.auth_refresh_token.clone(),
+            calendar_route,
+            feed_route,
+            ws_topics,
+            api_token: sd.as_ref().and_then(http_server::read_token),
+            static_ip: settings.static_ip,
+            seed: rng.random(),
+            #[cfg(feature = "mock-server")]
+            fixtures: sd.as_ref().map(mock_server::load).unwrap_or_default(),
+        };
+        let (mut net, net_link) = network::link();
+        // rendering stays on this core, a slow server or a lost link only ever stalls the other
+        if let Some(wifi) = wifi {
+            dual_core::start_app_core(
+                peripherals.CPU_CTRL,
+                SoftwareInterruptControl::new(peripherals.SW_INTERRUPT),
+                move || network::run(wifi, net_config, net_link),
+            );
+        }
+        let mut ip_info = None;
+        let mut navigations = 0u32;
+        // where the next band of a screenshot served over http starts
+        let mut screenshot_row: Option<usize> = None;
+
+        boot.mark("network");
+        self.boot.send(BootState::Loading { stage: "Starting peripherals", done: 2 });
+        draw_boot_frame();
+
+        // GPIO26 - passive buzzer, timer alarms
+        let buzzer_timer = buzzer::init_timer(&ledc, timer::Number::Timer1).map_err(Error::from)?;
+        let mut buzzer = Buzzer::new(
+            &ledc,
+            &buzzer_timer,
+            timer::Number::Timer1,
+            channel::Number::Channel1,
+            peripherals.GPIO26,
+        )
+        .map_err(Error::from)?;
+        buzzer.set_muted(settings.muted);
+
+        // GPIO25 - alarm output (relay/LED), rule output 0
+        let mut alarm_outputs = [Output::new(peripherals.GPIO25, Level::Low, Default::default())];
+        // GPIO16 - servo signal, GPIO17 - PWM output (motor driver, LED MOSFET); Outputs page.
+        // With METER.TXT on the card they are UART2 RX/TX to the energy meter instead.
+        // WROVER modules wire their PSRAM to these two, PSRAM builds leave them alone
+        let servo_timer =
+            servo::init_servo_timer(&ledc, timer::Number::Timer2).map_err(Error::from)?;
+        let pwm_timer = servo::init_pwm_timer(&ledc, timer::Number::Timer3).map_err(Error::from)?;
+        let (mut outputs, mut meter) = match sd.as_ref().and_then(energy_meter::load_config) {
+            _ if cfg!(feature = "psram") => (None, None),
+            Some(meter_config) => {
+                let meter = EnergyMeter::new(
+                    peripherals.UART2,
+                    peripherals.GPIO16,
+                    peripherals.GPIO17,
+                    meter_config,
+                    settings.energy,
+                );
+                match meter {
+                    Ok(meter) => (None, Some(meter)),
+                    Err(e) => {
+                        warn!("Energy meter: {}", e);
+                        (None, None)
+                    }
+                }
+            }
+            None => {
+                let servo = Servo::new(
+                    &ledc,
+                    &servo_timer,
+                    channel::Number::Channel2,
+                    peripherals.GPIO16,
+                )
+                .map_err(Error::from)?;
+                let pwm = PwmOutput::new(
+                    &ledc,
+                    &pwm_timer,
+                    channel::Number::Channel3,
+                    peripherals.GPIO17,
+                )
+                .map_err(Error::from)?;
+                (Some((servo, pwm)), None)
+            }
+        };
+
+        // GPIO5, 35 - spare, set up from the GPIO page; GPIO5 is the touch SCL with ft6236,
+        // GPIO35 the battery divider's tap with the battery feature
+        let spare_pins = [
+            #[cfg(not(feature = "ft6236"))]
+            Flex::new(peripherals.GPIO5),
+            #[cfg(not(feature = "battery"))]
+            Flex::new(peripherals.GPIO35),
+        ];
+        #[cfg(feature = "battery")]
+        let mut battery = adc::Battery::new(
+            peripherals.ADC1,
+            peripherals.GPIO35,
+            adc::load_calibration(sd.as_ref()),
+        );
+        let mut gpio = GpioPins::new(spare_pins, settings.gpio);
+        let mut rule_engine = RuleEngine::new(&settings.rules);
+        let rule_builtins = rule_engine.builtins().to_vec();
+        // a broken script shows its error on the Script page, the rest of the unit runs on
+        #[cfg(feature = "scripting")]
+        let mut script = match sd.as_ref().and_then(script::load_from_sd) {
+            Some(Ok(mut script)) => {
+                script.run(Trigger::Boot);
+                Some(script)
+            }
+            Some(Err(e)) => {
+                warn!("Script: {}", e);
+                self.script.send(ScriptView { error: e.to_string(), ..Default::default() });
+                None
+            }
+            None => None,
+        };
+        // DS18B20 / DHT22 on the spare pins set to them
+        let mut sensors = Sensors::new();
+
+        boot.mark("peripherals");
+        // the navigation stack's page replaces the loading screen
+        self.boot.send(BootState::Ready);
+        self.log.record(boot.summary());
+        // the C application registers its callbacks from here
+        #[cfg(feature = "ffi")]
+        ffi::start_app();
+
+        // GPIO0 - BOOT button, goes back a page like the edge swipe
+        let mut back_button = BackButton::new(peripherals.GPIO0);
+        let mut back_swipe = BackSwipe::new();
+        let mut pull_down = PullDown::new();
+        let mut back_presses = 0u32;
+        // GPIO1 - TX, GPIO3 - RX: UART0 behind the USB serial adapter, shared with the log
+        let mut console = Console::new(peripherals.UART0, peripherals.GPIO3, peripherals.GPIO1)
+            .inspect_err(|e| warn!("Console: {}", e))
+            .ok();
+        let mut heap_snapshots = HeapSnapshots::new();
+        // the next ping result is also the console's answer
+        let mut console_ping = false;
+
+        let mut wifi_connected = false;
+        let mut idle = IdleTimeout::new(settings.idle);
+        idle.set_schedule(settings.schedule);
+        let mut low_power = LowPower::new(settings.sleep);
+        let mut shown_minute = None;
+        let mut shown_timer_secs = None;
+        let mut screenshot_at: Option<Instant> = None;
+        // TIMG0 drives the scheduler, TIMG1's first timer is the stopwatch counter
+        let timg1 = TimerGroup::new(peripherals.TIMG1);
+        let mut stopwatch = stopwatch::Stopwatch::new(timg1.timer0);
+        // the second one times the stepper's steps; GPIO22 - STEP, GPIO12 - DIR (a strapping
+        // pin, fine for the driver's unpulled DIR input but nothing that pulls it high at reset)
+        let mut stepper = Stepper::new(timg1.timer1, peripherals.GPIO22, peripherals.GPIO12);
+        self.stopwatch_time.send((stopwatch::format_us(0).into(), false));
+        let mut pacer = FramePacer::new(TARGET_FPS);
+        let mut render_profile =
+            RenderProfile::new(Duration::from_micros(1_000_000 / TARGET_FPS as u64));
+        let mut heatmap = TouchHeatmap::new();
+        heatmap.resize(width, height);
+        let mut latency = LatencyTracker::new();
+        let mut drag = DragPredictor::new();
+        let mut touch_queue = InputQueue::new();
+        let mut latency_ms = 0;
+        // for the status route and the rules, updated with the perf stats once a second
+        let mut cpu_load = 0u8;
+        let mut telemetry = Telemetry::new(
+            Duration::from_secs(TELEMETRY_INTERVAL_SECS),
+            LOW_HEAP_THRESHOLD,
         );
+        let mut stable = false;
+        let mut watchdog = Watchdog::start(timg1.wdt);
+        loop {
+            pacer.begin();
+            update_timers_and_animations();
+            let uptime = Instant::now().duration_since_epoch().as_secs();
+            if !stable && uptime >= safe_mode::STABLE_AFTER_SECS {
+                stable = true;
+                safe_mode::mark_stable();
+            }
+            watchdog.enter(Phase::Touch);
+            touch_queue.sample(&mut touch_input);
+            watchdog.enter(Phase::Loop);
+            watchdog.check_network();
+            // thinned out while the loop is overrunning, see `FramePacer`
+            let deferred = pacer.deferred_due();
 
-        let fast_spi_ref_cell = RefCell::new(fast_spi);
+            // whatever the network core finished since the last pass, it never waits on us
+            while let Some(event) = net.recv() {
+                match event {
+                    NetEvent::Ip(info) => {
+                        error_banner.recovered(Subsystem::Wifi);
+                        wifi_connected = true;
+                        self.wifi_connected.send(true);
+                        self.ip_address.send(format!("{}", info.ip).into());
+                        self.events.borrow_mut().emit_ip_acquired(&info);
+                        ip_info = Some(info);
+                    }
+                    NetEvent::WifiFailed(e) => error_banner.failed(Subsystem::Wifi, &e.into()),
+                    NetEvent::Signal(bars) => self.wifi_bars.send(bars as i32),
+                    NetEvent::Link(connected) => {
+                        if wifi_connected && !connected {
+                            self.events.borrow_mut().emit_wifi_lost();
+                        }
+                        if connected && !wifi_connected {
+                            reliability.reconnected();
+                        }
+                        wifi_connected = connected;
+                        self.wifi_connected.send(connected);
+                    }
+                    NetEvent::ClockSynced(unix_secs) => {
+                        clock::set(unix_secs, standby.as_ref().map(|s| s.rtc()));
+                    }
+                    NetEvent::WsMessage(frame) => {
+                        topics.dispatch(&frame);
+                        self.events.borrow_mut().emit_ws_message(&frame);
+                        #[cfg(feature = "scripting")]
+                        if let Some(script) = script.as_mut() {
+                            script.run(Trigger::WsMessage(&frame));
+                        }
+                    }
+                    NetEvent::Chat { history, incoming } => {
+                        if let Some(message) = incoming {
+                            let from = if message.from.is_empty() { "Chat" } else { &message.from };
+                            self.notifications.notify(&format!("{}: {}", from, message.text));
+                            idle.wake();
+                        }
+                        self.chat.send(history);
+                    }
+                    NetEvent::Notify(text) => self.notifications.notify(text),
+                    NetEvent::Request(route) => match route {
+                        Route::Status => {
+                            let ip = ip_info
+                                .as_ref()
+                                .map(|info| format!("{}", info.ip))
+                                .unwrap_or_default();
+                            let record = reliability.record();
+                            let status = http_server::Status {
+                                uptime_secs: uptime,
+                                unix_time: clock::unix_now(),
+                                ip: &ip,
+                                screen: idle.state(),
+                                brightness: backlight.brightness(),
+                                memory: &telemetry::sample(),
+                                cpu_load,
+                                display_glitches: coexistence::glitches(),
+                                display_pauses: coexistence::pauses(),
+                                reliability: &record.current,
+                            };
+                            let json = status.to_json();
+                            net.respond("200 OK", "application/json", json.into_bytes());
+                        }
+                        Route::Text(text) => {
+                            self.remote_text.send(text.as_str().into());
+                            idle.wake();
+                            net.respond("200 OK", "text/plain", b"ok".to_vec());
+                        }
+                        Route::Navigate(page) => {
+                            navigations = navigations.wrapping_add(1);
+                            self.navigate_requested.send((navigations, page));
+                            net.respond("200 OK", "text/plain", b"ok".to_vec());
+                        }
+                        Route::Back => {
+                            back_presses = back_presses.wrapping_add(1);
+                            self.back_requested.send(back_presses);
+                            net.respond("200 OK", "text/plain", b"ok".to_vec());
+                        }
+                        Route::Screenshot => {
+                            net.stream_head("200 OK", "image/bmp", screenshot::FILE_LEN);
+                            net.stream_body(screenshot::stream_header(window.window().size()));
+                            screenshot_row = Some(0);
+                        }
+                        Route::SaveScreenshot => {
+                            let (status, body) = match save_screenshot(&window, sd.as_ref()) {
+                                Ok(path) => ("200 OK", path),
+                                Err(e @ ScreenshotError::NoCard) => {
+                                    ("503 Service Unavailable", e.to_string())
+                                }
+                                Err(e) => ("500 Internal Server Error", e.to_string()),
+                            };
+                            net.respond(status, "text/plain", body.into_bytes());
+                        }
+                        Route::Shutdown(kind) => {
+                            if shutdown.request(kind) {
+                                self.shutdown_message.send(kind.message().into());
+                            }
+                            let body = kind.message().as_bytes().to_vec();
+                            net.respond("202 Accepted", "text/plain", body);
+                        }
+                    },
+                    NetEvent::Device(DeviceEvent::Prompt(prompt)) => {
+                        let (code, uri) = (&prompt.user_code, &prompt.verification_uri);
+                        info!("Auth: enter {} at {}", code, uri);
+                        self.device_prompt.send(prompt);
+                    }
+                    NetEvent::Device(DeviceEvent::Authorized(refresh_token)) => {
+                        self.device_prompt.send(DevicePrompt::default());
+                        self.notifications.notify("Device authorized");
+                        settings.auth_refresh_token = refresh_token;
+                        if let Err(e) = config.save(&settings) {
+                            error!("Failed to persist settings: {}", e);
+                        }
+                    }
+                    NetEvent::Device(DeviceEvent::Rotated(refresh_token)) => {
+                        settings.auth_refresh_token = Some(refresh_token);
+                        if let Err(e) = config.save(&settings) {
+                            error!("Failed to persist settings: {}", e);
+                        }
+                    }
+                    NetEvent::Device(DeviceEvent::Revoked) => {
+                        settings.auth_refresh_token = None;
+                        if let Err(e) = config.save(&settings) {
+                            error!("Failed to persist settings: {}", e);
+                        }
+                    }
+                    NetEvent::Device(DeviceEvent::Expired) => {
+                        self.device_prompt.send(DevicePrompt::default());
+                    }
+                    NetEvent::Tags(tags) => self.tags.send((*tags).into_iter().collect()),
+                    NetEvent::Agenda(agenda) => self.agenda.send(*agenda),
+                    NetEvent::Feed(feed) => self.feed.send(*feed),
+                    // a restart fetches the document again, the version it was applied at
+                    // keeps it from being applied and reported twice
+                    NetEvent::Config(remote) if remote.version != settings.config_version => {
+                        let applied = remote.apply(&mut settings);
+                        clock::set_utc_offset(settings.time.utc_offset_minutes);
+                        idle.set_config(settings.idle);
+                        low_power.set_config(settings.sleep);
+                        night.set_config(settings.night);
+                        self.night.send(night.view());
+                        if let Some(standby) = standby.as_mut() {
+                            standby.set_touch_wake(settings.sleep.touch_wake);
+                        }
+                        self.touch_clicks.send(settings.touch_clicks);
+                        self.brightness.send(settings.brightness as i32);
+                        if idle.state() == ScreenState::Active {
+                            backlight.fade_to(settings.brightness, SLIDER_FADE_MS).ok();
+                        }
+                        if let Err(e) = config.save(&settings) {
+                            error!("Failed to persist settings: {}", e);
+                        }
+                        self.log.record(format!(
+                            "config v{}: changed {:?}, rejected {:?}",
+                            applied.version, applied.changed, applied.rejected
+                        ));
+                        webhooks.outbox().push(config_sync::APPLIED_ROUTE, applied.to_json());
+                    }
+                    NetEvent::Config(_) => {}
+                    NetEvent::NowPlaying(NowPlayingEvent::Track(track)) => {
+                        self.now_playing.send(track)
+                    }
+                    NetEvent::NowPlaying(NowPlayingEvent::Art(bytes)) => {
+                        let art = if bytes.is_empty() {
+                            Ok(Image::default())
+                        } else {
+                            image_loader::decode(&bytes)
+                        };
+                        match art {
+                            Ok(art) => self.album_art.send(art),
+                            Err(e) => warn!("Now playing: {}", e),
+                        }
+                    }
+                    NetEvent::Posted(delivery) => {
+                        let route = webhooks.outbox().sent(delivery);
+                        if let (Some(heartbeat), Some(route)) = (heartbeat.as_mut(), route) {
+                            heartbeat.delivered(&route);
+                        }
+                    }
+                    NetEvent::Exported(status) => self.status.send(status.into()),
+                    NetEvent::Scanned(Ok(networks)) => {
+                        let status = match networks.len() {
+                            0 => String::from("No networks found"),
+                            n => format!("{} networks", n),
+                        };
+                        self.wifi_networks.send((networks, status.into()));
+                    }
+                    NetEvent::Scanned(Err(e)) => {
+                        self.wifi_networks.send((Vec::new(), format!("{}", e).into()));
+                    }
+                    NetEvent::Fetched(reply) => {
+                        if let Some(console) = console.as_mut() {
+                            console.reply(&reply);
+                        }
+                    }
+                    NetEvent::Pinged(report) => {
+                        let text = report.map_or_else(|e| e.to_string(), |r| r.to_text());
+                        if let Some(console) = console.as_mut().filter(|_| console_ping) {
+                            console.reply(&text);
+                        }
+                        console_ping = false;
+                        self.ping_result.send(text.into());
+                    }
+                    #[cfg(feature = "scripting")]
+                    NetEvent::Response { name, status, body } => {
+                        if let Some(script) = script.as_mut() {
+                            script.run(Trigger::Response { name: &name, status, body: &body });
+                        }
+                    }
+                    NetEvent::Suspended => {
+                        info!("WiFi suspended, light sleeping between passes");
+                        low_power.network_suspended();
+                    }
+                }
+            }
+
+            // the script's timers and buttons, then what it asked for since the last pass
+            #[cfg(feature = "scripting")]
+            if let Some(script) = script.as_mut() {
+                script.poll();
+                if let Some((_, index)) = script_press_requested.changed() {
+                    script.run(Trigger::Button(index));
+                }
+                for action in script.take_actions() {
+                    match action {
+                        ScriptAction::RemoteText(text) => {
+                            self.remote_text.send(text.as_str().into());
+                            idle.wake();
+                        }
+                        ScriptAction::Status(text) => self.status.send(text.as_str().into()),
+                        ScriptAction::Notify(text) => {
+                            self.notifications.notify(&text);
+                            idle.wake();
+                        }
+                        ScriptAction::Observe(channel, value) => {
+                            rule_engine.observe(&channel, value)
+                        }
+                        ScriptAction::Get { name, route } => {
+                            net.send(NetCommand::Fetch { name, route })
+                        }
+                        ScriptAction::Post { route, body } => webhooks.outbox().push(&route, body),
+                    }
+                }
+                if let Some(view) = script.changed_view() {
+                    self.script.send(view);
+                }
+            }
+            // what C code queued, before the rules look at their channels
+            #[cfg(feature = "ffi")]
+            for incoming in ffi::drain() {
+                match incoming {
+                    ffi::Incoming::Property(ffi::Property::RemoteText, text) => {
+                        self.remote_text.send(text.as_str().into());
+                        idle.wake();
+                    }
+                    ffi::Incoming::Property(ffi::Property::Status, text) => {
+                        self.status.send(text.as_str().into())
+                    }
+                    ffi::Incoming::Property(ffi::Property::Notification, text) => {
+                        self.notifications.notify(&text);
+                        idle.wake();
+                    }
+                    ffi::Incoming::Value(channel, value) => rule_engine.observe(&channel, value),
+                }
+            }
+            for builtin in &rule_builtins {
+                let value = match builtin {
+                    Builtin::Wifi => f32::from(u8::from(wifi_connected)),
+                    Builtin::Brightness => backlight.brightness() as f32,
+                    Builtin::HeapFree => esp_alloc::HEAP.free() as f32,
+                    Builtin::CpuLoad => cpu_load as f32,
+                    #[cfg(feature = "battery")]
+                    Builtin::Battery => battery.view().percent as f32,
+                    #[cfg(not(feature = "battery"))]
+                    Builtin::Battery => continue,
+                };
+                rule_engine.observe(builtin.name(), value);
+            }
+            gpio.observe(&mut rule_engine);
+            sensors.observe(&mut rule_engine);
+            if let Some(meter) = &meter {
+                meter.observe(&mut rule_engine);
+            }
+            for event in rule_engine.poll() {
+                let (rule, breached) = match &event {
+                    RuleEvent::Breached { rule, .. } => (rule, true),
+                    RuleEvent::Cleared { rule, .. } => (rule, false),
+                };
+                let state = if breached { "breached" } else { "cleared" };
+                self.log.record(format!("rule {} {}", rule, state));
+
+                match &rule.action {
+                    RuleAction::Notify => {
+                        if breached {
+                            self.notifications.notify(&format!("Alarm: {}", rule));
+                            idle.wake();
+                        }
+                    }
+                    RuleAction::Output(n) => match alarm_outputs.get_mut(*n as usize) {
+                        Some(output) => output.set_level(Level::from(breached)),
+                        None => {
+                            if !gpio.drive(*n, breached) {
+                                warn!("Rules: output {} is not a bound GPIO output", n);
+                            }
+                        }
+                    },
+                    RuleAction::Webhook(target) => {
+                        let trigger = Trigger::from_rule_event(&event);
+                        if let Err(e) = webhooks.fire(target, &trigger) {
+                            warn!("Webhook: {}", e);
+                        }
+                    }
+                }
+            }
+            if let Some((_, text)) = chat_send_requested.changed() {
+                net.send(NetCommand::Chat(text.as_str().into()));
+            }
+            if let Some((_, name)) = webhook_requested.changed() {
+                let trigger = Trigger { channel: "ui", value: None, state: "pressed" };
+                if let Err(e) = webhooks.fire(&name, &trigger) {
+                    warn!("Webhook: {}", e);
+                }
+            }
+            if let Some((_, command)) = player_command_requested.changed() {
+                net.send(NetCommand::Player(command));
+            }
+            if let Some((_, host)) = ping_requested.changed() {
+                self.ping_result.send("Pinging...".into());
+                net.send(NetCommand::Ping { host, count: ping::DEFAULT_COUNT });
+            }
+            if let Some(console) = console.as_mut() {
+                let reply = match console.poll() {
+                    None => None,
+                    Some(Err(e)) => Some(e.to_string()),
+                    Some(Ok(Command::Help)) => Some(console::HELP.into()),
+                    Some(Ok(Command::WifiStatus)) => Some(match (wifi_connected, &ip_info) {
+                        (true, Some(info)) => {
+                            format!("connected, ip {} gateway {}", info.ip, info.subnet.gateway)
+                        }
+                        (false, Some(_)) => String::from("link down, reconnecting"),
+                        (_, None) => String::from("no ip yet"),
+                    }),
+                    Some(Ok(Command::Heap(args))) => {
+                        Some(heap_snapshots.command(&args).unwrap_or_else(|e| e.to_string()))
+                    }
+                    Some(Ok(Command::Ls(dir))) => Some(match &sd {
+                        Some(sd) => sd
+                            .list_dir(&dir)
+                            .map_or_else(|e| e.to_string(), |entries| console::listing(&entries)),
+                        None => String::from("no sd card"),
+                    }),
+                    // answered by `NetEvent::Fetched`
+                    Some(Ok(Command::Get(route))) => {
+                        net.send(NetCommand::Get(route));
+                        None
+                    }
+                    // answered by `NetEvent::Pinged`
+                    Some(Ok(Command::Ping { host, count })) => {
+                        console_ping = true;
+                        net.send(NetCommand::Ping { host, count });
+                        None
+                    }
+                    Some(Ok(Command::Screenshot)) => {
+                        let saved = save_screenshot(&window, sd.as_ref());
+                        Some(saved.map_or_else(|e| e.to_string(), |path| format!("saved {}", path)))
+                    }
+                    Some(Ok(Command::Log(args))) => {
+                        Some(logger::command(&args).unwrap_or_else(|e| e.to_string()))
+                    }
+                    Some(Ok(Command::Fault(args))) => {
+                        Some(fault_injection::command(&args).unwrap_or_else(|e| e.to_string()))
+                    }
+                    Some(Ok(Command::Shutdown(kind))) => {
+                        if shutdown.request(kind) {
+                            self.shutdown_message.send(kind.message().into());
+                        }
+                        Some(kind.message().into())
+                    }
+                };
+                if let Some(reply) = reply {
+                    console.reply(&reply);
+                }
+            }
+            if let Some(heartbeat) = heartbeat.as_mut() {
+                if let Some(body) = heartbeat.poll(webhooks.device_id()) {
+                    webhooks.outbox().replace(heartbeat.route(), body);
+                }
+                self.heartbeat_late.send(heartbeat.late());
+            }
+            if wifi_connected {
+                if let Some((route, body)) = webhooks.outbox().due() {
+                    net.send(NetCommand::Post { route, body });
+                }
+            }
+            net.flush();
+            // a band of the screenshot being served per pass, once the last one has been taken
+            if let Some(row) = screenshot_row.filter(|_| net.stream_ready()) {
+                window.request_redraw();
+                window.draw_if_needed(|renderer| {
+                    let (band, next) =
+                        screenshot::stream_band(renderer, window.window().size(), row);
+                    net.stream_body(band);
+                    screenshot_row = next;
+                });
+                if screenshot_row.is_none() {
+                    net.stream_end();
+                }
+                // the panel missed that frame, it gets a full one next
+                window.request_redraw();
+            }
+            buzzer.poll();
+            if let Some((_, command)) = error_command_requested.changed() {
+                error_banner.apply(command);
+            }
+            match error_banner.due() {
+                Some(Subsystem::Touch) => match touch_input.init() {
+                    Ok(()) => error_banner.recovered(Subsystem::Touch),
+                    Err(e) => error_banner.failed(Subsystem::Touch, &e.into()),
+                },
+                // a card put in later; what else boot reads from it waits for the next restart
+                Some(Subsystem::SdCard) => {
+                    let cs = unsafe { esp_hal::peripherals::GPIO21::steal() };
+                    match SdStorage::mount(&slow_spi_ref_cell, cs, CheckLevel::Flags) {
+                        Ok(card) => {
+                            log_file = Some(LogFile::open(&card));
+                            logger::set_file_level(log::LevelFilter::Info);
+                            sd = Some(card);
+                            error_banner.recovered(Subsystem::SdCard);
+                            self.events.borrow_mut().emit_sd_mounted();
+                        }
+                        Err(e) => error_banner.failed(Subsystem::SdCard, &e.into()),
+                    }
+                }
+                Some(Subsystem::Wifi) | None => {}
+            }
+            if let Some(view) = error_banner.changed_view() {
+                self.errors.send(view);
+            }
+            if let (true, Some(log_file), Some(sd)) = (deferred, log_file.as_mut(), sd.as_ref()) {
+                // println, a failing card must not log about itself into the same card
+                if let Err(e) = log_file.poll(sd) {
+                    println!("Log file: {}", e);
+                }
+            }
+            if let Some((_, command)) = timer_command_requested.changed() {
+                match settings.timers.apply(command, clock::unix_now()) {
+                    Ok(()) => {
+                        if let Err(e) = config.save(&settings) {
+                            error!("Failed to persist settings: {}", e);
+                        }
+                        // refresh the page right away
+                        shown_timer_secs = None;
+                    }
+                    Err(e) => self.notifications.notify(&format!("Timers: {}", e)),
+                }
+            }
+            let stopwatch_command = stopwatch_command_requested.changed();
+            if let Some((_, command)) = stopwatch_command {
+                match command {
+                    StopwatchCommand::Toggle => stopwatch.toggle(),
+                    StopwatchCommand::Lap => {
+                        if let (Some(lap), Some(sd)) = (stopwatch.lap(), &sd) {
+                            if let Err(e) = stopwatch.save_lap(sd, &lap) {
+                                warn!("Stopwatch: {}", e);
+                            }
+                        }
+                    }
+                    StopwatchCommand::Reset => stopwatch.reset(),
+                }
+                self.laps.send(stopwatch.laps().collect());
+            }
+            // only what is shown follows the frame rate, the counter keeps its own time
+            if stopwatch.is_running() || stopwatch_command.is_some() {
+                let time = stopwatch::format_us(stopwatch.elapsed_us());
+                self.stopwatch_time.send((time.into(), stopwatch.is_running()));
+            }
+            if let Some((_, command)) = gpio_command_requested.changed() {
+                if gpio.apply(command) {
+                    settings.gpio = gpio.configs();
+                    if let Err(e) = config.save(&settings) {
+                        error!("Failed to persist settings: {}", e);
+                    }
+                }
+            }
+            // inputs are sampled once per pass, the page only hears about changed levels
+            self.gpio_pins.send(gpio.view());
+            if deferred && sensors.poll(&mut gpio) {
+                self.sensors.send(sensors.view());
+            }
+            if let Some((servo, pwm)) = outputs.as_mut() {
+                if let Some(angle) = servo_requested.changed() {
+                    servo.set_angle(angle.clamp(0, servo::MAX_ANGLE as i32) as u16);
+                }
+                if let Some(duty) = pwm_requested.changed() {
+                    pwm.set_duty(duty.clamp(0, 100) as u8);
+                }
+                // both follow their targets at a limited rate, the page shows where they are
+                servo.poll();
+                pwm.poll();
+                self.outputs.send((servo.angle() as i32, pwm.duty() as i32));
+            }
+            if let (true, Some(meter)) = (deferred, meter.as_mut()) {
+                if meter.poll() {
+                    self.energy.send(meter.view());
+                }
+                if let Some(totals) = meter.take_save() {
+                    settings.energy = totals;
+                    if let Err(e) = config.save(&settings) {
+                        error!("Failed to persist settings: {}", e);
+                    }
+                }
+            }
+            // the panel, the window and the touch transform change together
+            let orientation_command = orientation_requested.changed();
+            if orientation_command.is_some() && quick.rotation_locked {
+                self.notifications.notify("Rotation is locked");
+            } else if let Some((_, command)) = orientation_command {
+                let orientation = settings.orientation.apply(command);
+                match slint_renderer.set_orientation(orientation) {
+                    Ok(()) => {
+                        let (width, height) = orientation.size();
+                        window.set_size(PhysicalSize::new(width, height));
+                        touch_input.set_orientation(orientation);
+                        heatmap.resize(width, height);
+                        window.request_redraw();
+                        self.orientation.send(format!("{}", orientation).into());
+                        settings.orientation = orientation;
+                        if let Err(e) = config.save(&settings) {
+                            error!("Failed to persist settings: {}", e);
+                        }
+                    }
+                    Err(e) => self.notifications.notify(&format!("Display: {}", e)),
+                }
+            }
+            // previewed as the sliders move, only saving keeps it
+            if let Some((_, command)) = color_command_requested.changed() {
+                color = match command {
+                    ColorCommand::Adjust { channel, gamma, gain } => {
+                        color.with_channel(channel, gamma, gain)
+                    }
+                    ColorCommand::Reset => ColorCalibration::default(),
+                    ColorCommand::Save => {
+                        settings.color = color;
+                        if let Err(e) = config.save(&settings) {
+                            error!("Failed to persist settings: {}", e);
+                        }
+                        color
+                    }
+                    ColorCommand::NightMode(enabled) => {
+                        settings.night.enabled = enabled;
+                        night.set_config(settings.night);
+                        self.night.send(night.view());
+                        if let Err(e) = config.save(&settings) {
+                            error!("Failed to persist settings: {}", e);
+                        }
+                        color
+                    }
+                };
+                slint_renderer.set_color(&color.warmed(night.warmth()));
+                repaint_all(&window);
+                self.color.send(color);
+            }
+            if let Some(warmth) = night.poll() {
+                slint_renderer.set_color(&color.warmed(warmth));
+                repaint_all(&window);
+                self.night.send(night.view());
+            }
+            if let Some((_, command)) = stepper_command_requested.changed() {
+                stepper.apply(command);
+            }
+            self.stepper.send(stepper.view());
+            if screenshot_requested.changed().is_some() {
+                screenshot_at = Some(Instant::now() + Duration::from_secs(SCREENSHOT_DELAY_SECS));
+            }
+            if screenshot_at.is_some_and(|at| Instant::now() >= at) {
+                screenshot_at = None;
+                // after the capture, so the banner isn't in the picture
+                match save_screenshot(&window, sd.as_ref()) {
+                    Ok(path) => self.notifications.notify(&format!("Saved {}", path)),
+                    Err(e) => self.notifications.notify(&format!("Screenshot: {}", e)),
+                }
+            }
+            if timer_dismiss_requested.changed().is_some() {
+                buzzer.stop();
+                self.timer_alert.send(SharedString::new());
+            }
+            if let Some(now) = clock::unix_now() {
+                let expired = settings.timers.expire(now);
+                if !expired.is_empty() {
+                    let names: Vec<&str> = expired.iter().map(|name| name.as_str()).collect();
+                    let names = names.join(", ");
+                    self.log.record(format!("timer expired: {}", names));
+                    self.notifications.notify(&format!("Timer done: {}", names));
+                    self.timer_alert.send(names.into());
+                    buzzer.play(buzzer::ALARM, buzzer::ALARM_REPEATS);
+                    idle.wake();
+                    if let Err(e) = config.save(&settings) {
+                        error!("Failed to persist settings: {}", e);
+                    }
+                }
+                if shown_timer_secs != Some(now) {
+                    shown_timer_secs = Some(now);
+                    self.timers.send(settings.timers.view(now));
+                }
+            }
+            if let Some(now) = clock::local_now() {
+                if shown_minute != Some(now.minute) {
+                    shown_minute = Some(now.minute);
+                    self.clock_text.send(format!("{:02}:{:02}", now.hour, now.minute).into());
+                }
+            }
+
+            #[cfg(feature = "battery")]
+            match battery.poll() {
+                Some(BatteryEvent::Level(view)) => self.battery.send(view),
+                // deep sleep draws next to nothing, the wake button starts over once it's charged
+                Some(BatteryEvent::Low(view)) => {
+                    warn!("Battery low at {} mV, shutting down", view.millivolts);
+                    self.battery.send(view);
+                    self.events.borrow_mut().emit_low_battery(&view);
+                    backlight.set_brightness(0).ok();
+                    slint_renderer.blank();
+                    settings.reliability = reliability.record();
+                    if let Err(e) = config.save(&settings) {
+                        error!("Failed to persist settings: {}", e);
+                    }
+                    if let Some(sd) = &sd {
+                        if let Err(e) = webhooks.outbox().save(sd) {
+                            warn!("Outbox: {}", e);
+                        }
+                    }
+                    watchdog.disable();
+                    match standby.take() {
+                        Some(standby) => standby.enter(None),
+                        None => loop {},
+                    }
+                }
+                None => {}
+            }
+
+            if power_fail::pending() {
+                error!("Power fail detected, shutting down");
+                backlight.set_brightness(0).ok();
+                slint_renderer.blank();
+                self.events.borrow_mut().emit_power_fail();
+                settings.reliability = reliability.record();
+                if let Err(e) = config.save(&settings) {
+                    error!("Failed to persist settings: {}", e);
+                }
+                if let Some(sd) = &sd {
+                    if let Err(e) = webhooks.outbox().save(sd) {
+                        warn!("Outbox: {}", e);
+                    }
+                }
+                // files are closed after every access, releasing the volume manager is all that's left
+                drop(sd);
+                // the brown-out reset is due any moment, this is for a supply that came back
+                esp_hal::system::software_reset();
+            }
+
+            if let Some(record) = reliability.take_save() {
+                settings.reliability = record;
+                if let Err(e) = config.save(&settings) {
+                    error!("Failed to persist settings: {}", e);
+                }
+            }
+            if let Some(record) = reliability.poll() {
+                self.reliability.send(record);
+            }
+
+            if let Some((_, command)) = quick_command_requested.changed() {
+                let changed = quick.apply(command);
+                match (quick.wifi_off, changed.wifi_off) {
+                    (false, true) => net.send(NetCommand::Suspend),
+                    (true, false) => net.send(NetCommand::Resume),
+                    _ => {}
+                }
+                buzzer.set_muted(changed.muted);
+                let kept = |q: QuickState| (q.rotation_locked, q.muted);
+                if kept(changed) != kept(quick) {
+                    settings.rotation_locked = changed.rotation_locked;
+                    settings.muted = changed.muted;
+                    if let Err(e) = config.save(&settings) {
+                        error!("Failed to persist settings: {}", e);
+                    }
+                }
+                quick = changed;
+                self.quick_settings.send(quick);
+            }
+            if let Some(enabled) = touch_clicks_requested.changed() {
+                settings.touch_clicks = enabled;
+                if let Err(e) = config.save(&settings) {
+                    error!("Failed to persist settings: {}", e);
+                }
+            }
+            if let Some((_, (hz, ms))) = tone_requested.changed() {
+                buzzer.tone(hz.max(0) as u32, ms.max(0) as u32);
+            }
+            if let Some(level) = brightness_requested.changed() {
+                let level = level.clamp(0, 100) as u8;
+                if let Err(e) = backlight.fade_to(level, SLIDER_FADE_MS) {
+                    warn!("Backlight: {}", e);
+                }
+                settings.brightness = level;
+                brightness_changed_at = Some(Instant::now());
+            }
+            if brightness_changed_at
+                .is_some_and(|t| t.elapsed().as_millis() >= BRIGHTNESS_SAVE_DELAY_MS)
+            {
+                brightness_changed_at = None;
+                if let Err(e) = config.save(&settings) {
+                    error!("Failed to persist settings: {}", e);
+                }
+            }
+
+            if diagnostics_requested.changed().is_some() {
+                let snapshot = Snapshot {
+                    log: &self.log,
+                    settings: &settings,
+                    ip_info: ip_info.as_ref(),
+                    wifi_connected,
+                    sd_report: sd.as_ref().map(|sd| sd.check_report()),
+                    brightness: backlight.brightness(),
+                    partitions: config.partitions(),
+                };
+                match &sd {
+                    Some(sd) => {
+                        let status = diagnostics::export(&snapshot, Some(sd), None)
+                            .unwrap_or_else(|e| {
+                                warn!("Diagnostics: {}", e);
+                                "Diagnostics export failed"
+                            });
+                        self.status.send(status.into());
+                    }
+                    // without a card the bundle is uploaded, the status comes back as an event
+                    None => net.send(NetCommand::UploadDiagnostics(snapshot.to_json())),
+                }
+            }
+
+            match wifi_command_requested.changed() {
+                Some((_, WifiCommand::Scan)) => {
+                    self.wifi_networks.send((Vec::new(), "Scanning...".into()));
+                    net.send(NetCommand::Scan);
+                }
+                Some((_, WifiCommand::Join { ssid, password })) => {
+                    match Credentials::new(&ssid, &password) {
+                        Ok(credentials) => {
+                            settings.wifi = Some(credentials);
+                            if let Err(e) = config.save(&settings) {
+                                error!("Failed to persist settings: {}", e);
+                            }
+                            // the client is configured once, at boot
+                            watchdog.disable();
+                            safe_mode::restart();
+                        }
+                        Err(e) => self.notifications.notify(&format!("WiFi: {}", e)),
+                    }
+                }
+                None => {}
+            }
+
+            if forget_wifi_requested.changed().is_some() {
+                settings.wifi = None;
+                if let Err(e) = config.save(&settings) {
+                    error!("Failed to persist settings: {}", e);
+                }
+                // provisioning only runs at boot
+                watchdog.disable();
+                safe_mode::restart();
+            }
+
+            if standby_requested.changed() == Some(true) {
+                if let Some(standby) = standby.take() {
+                    // dark first, then the panel's sleep mode, then WiFi leaves the AP
+                    backlight.set_brightness(0).ok();
+                    slint_renderer.sleep();
+                    watchdog.disable();
+                    if !net.suspend_blocking(Duration::from_secs(NET_SUSPEND_TIMEOUT_SECS)) {
+                        warn!("WiFi didn't go down in time, sleeping anyway");
+                    }
+                    settings.reliability = reliability.record();
+                    if let Err(e) = config.save(&settings) {
+                        error!("Failed to persist settings: {}", e);
+                    }
+                    standby.enter(None);
+                }
+            }
+
+            if let Some((_, kind)) = shutdown_requested.changed() {
+                if shutdown.request(kind) {
+                    self.shutdown_message.send(kind.message().into());
+                }
+            }
+            // the screen is up by now, the rest happens behind it
+            if let Some(kind) = shutdown.due() {
+                info!("{}", kind.message());
+                // the server sees the websocket close, the reply to a REST request is out
+                if !net.suspend_blocking(Duration::from_secs(NET_SUSPEND_TIMEOUT_SECS)) {
+                    warn!("WiFi didn't go down in time, shutting down anyway");
+                }
+                settings.reliability = reliability.record();
+                if let Err(e) = config.save(&settings) {
+                    error!("Failed to persist settings: {}", e);
+                }
+                if let Some(sd) = &sd {
+                    if let Err(e) = webhooks.outbox().save(sd) {
+                        warn!("Outbox: {}", e);
+                    }
+                    if let Some(log_file) = log_file.as_mut() {
+                        if let Err(e) = log_file.flush(sd) {
+                            println!("Log file: {}", e);
+                        }
+                    }
+                }
+                backlight.set_brightness(0).ok();
+                slint_renderer.blank();
+                watchdog.disable();
+                match (kind, standby.take()) {
+                    (ShutdownKind::Reboot, _) => safe_mode::restart(),
+                    // only the wake button brings it back, not a brush against the screen
+                    (ShutdownKind::PowerOff, Some(mut standby)) => {
+                        standby.set_touch_wake(false);
+                        standby.enter(None)
+                    }
+                    (ShutdownKind::PowerOff, None) => loop {},
+                }
+            }
+
+            // everything touched since the last frame reaches slint before it draws again
+            watchdog.enter(Phase::Touch);
+            touch_queue.sample(&mut touch_input);
+            let mut back = back_button.pressed();
+            while let Some(input) = touch_queue.pop() {
+                // every touch counts, including the ones that only wake the screen
+                heatmap.record(&input);
+                latency.input(&input);
+                let input = if settings.drag_prediction {
+                    drag.predict(input, latency_ms)
+                } else {
+                    input
+                };
+                let input = idle.filter(input);
+                back |= back_swipe.detect(&input);
+                if pull_down.detect(&input) && !quick.open {
+                    quick.open = true;
+                    self.quick_settings.send(quick);
+                }
+                let pressed = matches!(input, TouchInputResponse::Pressed { .. });
+                handle_input(&window, input)?;
+                // after the dispatch, the click must not delay the press
+                if pressed && settings.touch_clicks {
+                    buzzer.click();
+                }
+            }
+            // the quick settings are over the page, back closes them first
+            if back && quick.open {
+                quick.open = false;
+                self.quick_settings.send(quick);
+            } else if back {
+                back_presses = back_presses.wrapping_add(1);
+                self.back_requested.send(back_presses);
+            }
+            watchdog.enter(Phase::Loop);
+
+            match idle.poll() {
+                Some(ScreenState::Active) => {
+                    slint_renderer.wake();
+                    backlight.fade_to(settings.brightness, SLIDER_FADE_MS).ok();
+                }
+                Some(ScreenState::Dimmed) => {
+                    let level = settings.brightness.min(IDLE_DIM_LEVEL);
+                    backlight.fade_to(level, BACKLIGHT_FADE_MS).ok();
+                }
+                Some(ScreenState::Off) => {
+                    backlight.set_brightness(0).ok();
+                    slint_renderer.sleep();
+                }
+                None => {}
+            }
+            match low_power.poll(idle.state()) {
+                Some(SleepAction::SuspendNetwork) => net.send(NetCommand::Suspend),
+                // turned off from the quick settings it stays off
+                Some(SleepAction::ResumeNetwork) if !quick.wifi_off => {
+                    net.send(NetCommand::Resume)
+                }
+                Some(SleepAction::ResumeNetwork) => {}
+                None => {}
+            }
+
+            watchdog.enter(Phase::Render);
+            // the panel keeps its frame memory, there is nothing to draw for while it sleeps
+            if let Some(page) = shown_page_requested.changed() {
+                render_profile.set_page(page);
+                // the list that shifted the frame memory is gone with the page
+                if slint_renderer.reset_scroll() {
+                    window.request_redraw();
+                }
+            }
+            if let Some(list) = list_scroll_requested.changed() {
+                if slint_renderer.scroll_list(list) {
+                    window.request_redraw();
+                }
+            }
+            if slint_renderer.scroll_settled() {
+                window.request_redraw();
+            }
+            if idle.state() != ScreenState::Off && pacer.frame_due() {
+                let started = Instant::now();
+                let drawn = window.draw_if_needed(|renderer| {
+                    #[cfg(feature = "psram")]
+                    if let Some(frames) = frames.as_mut() {
+                        frames.render(
+                            renderer,
+                            &mut slint_renderer,
+                            window.window().size().width as usize,
+                            || touch_queue.sample(&mut touch_input),
+                        );
+                        return;
+                    }
+                    renderer.render_by_line(Sampled::new(&mut slint_renderer, || {
+                        touch_queue.sample(&mut touch_input)
+                    }));
+                });
+                if drawn {
+                    slint_renderer.frame_drawn();
+                    pacer.rendered(started.elapsed());
+                    render_profile.rendered(started.elapsed());
+                    if let Some(ms) = latency.presented() {
+                        latency_ms = ms;
+                        self.latency_ms.send(ms as i32);
+                    }
+                }
+            }
+            watchdog.enter(Phase::Loop);
+
+            if let Some(stats) = pacer.stats() {
+                cpu_load = stats.cpu_load;
+                self.perf.send(stats);
+            }
+            if let Some(line) = render_profile.report() {
+                self.log.record(line);
+            }
+            if deferred {
+                if let Some(cells) = heatmap.publish() {
+                    self.heatmap.send(cells);
+                }
+                match telemetry.poll() {
+                    Some(TelemetryEvent::Sample(stats)) => {
+                        info!("mem: {}", stats);
+                        self.memory.send(stats);
+                    }
+                    Some(TelemetryEvent::LowHeap(stats)) => {
+                        self.events.borrow_mut().emit_low_heap(&stats);
+                        self.assets.release();
+                        self.memory.send(stats);
+                    }
+                    None => {}
+                }
+            }
+            // slint asks for a redraw itself when something changed, no need to force one; a
+            // moving stepper keeps the position readout at the frame rate
+            let busy = window.window().has_active_animations()
+                || touch_input.is_active()
+                || !touch_queue.is_empty()
+                || stepper.is_moving();
+            match standby.as_mut().filter(|_| !busy && low_power.may_sleep()) {
+                Some(standby) => {
+                    let nap = pacer.idle_time();
+                    if standby.light_sleep(core::time::Duration::from_millis(nap.as_millis())) {
+                        idle.wake();
+                    }
+                    pacer.slept(nap);
+                    // the timers behind Instant stop with the APB clock, the RTC doesn't
+                    clock::restore(standby.rtc());
+                }
+                None => pacer.idle(busy),
+            }
+        }
+    }
+}
+
+impl EspBackend {
+    // Crash loop fallback: no WiFi, no fonts or images from the card, no rules. Only what is
+    // needed to look at the unit, save a diagnostics bundle and reset the settings.
+    fn run_safe_mode(&self, peripherals: Peripherals) -> Result<(), PlatformError> {
+        self.log.record(format!("Safe mode after {} failed boots", safe_mode::failed_boots()));
+        let mut diagnostics_requested = self
+            .diagnostics_requested
+            .borrow_mut()
+            .take()
+            .expect("Diagnostics request receiver already taken");
+        diagnostics_requested.changed();
+        let mut factory_reset_requested = self
+            .factory_reset_requested
+            .borrow_mut()
+            .take()
+            .expect("Factory reset receiver already taken");
+        factory_reset_requested.changed();
+
+        let fast_spi = create_spi(
+            peripherals.SPI3,
+            peripherals.GPIO18,
+            peripherals.GPIO23,
+            peripherals.GPIO19,
+            Rate::from_mhz(2),
+        )?;
+        let slow_spi = create_spi(
+            peripherals.SPI2,
+            peripherals.GPIO14,
+            peripherals.GPIO13,
+            peripherals.GPIO27,
+            Rate::from_khz(400),
+        )?;
+        let fast_spi_bus = SharedSpi::new(fast_spi);
         let slow_spi_ref_cell = RefCell::new(slow_spi);
 
+        // a damaged card may be what keeps crashing, skip the full filesystem walk
+        let sd = SdStorage::mount(&slow_spi_ref_cell, peripherals.GPIO21, CheckLevel::Flags)
+            .inspect_err(|e| warn!("SD: {}", e))
+            .ok();
+
+        let mut config = ConfigStore::new(peripherals.FLASH);
+        let mut settings = config.load();
+
         let mut buf512 = [0u8; 512];
-        let display = init_ili9341_display(
-            &fast_spi_ref_cell,
+        let display = init_display(
+            &fast_spi_bus,
             peripherals.GPIO2,
             peripherals.GPIO15,
             peripherals.GPIO4,
             &mut buf512,
+            // the recovery screen is laid out for landscape, whatever the settings say
+            ScreenOrientation::default(),
         )
-        .unwrap();
-        let mut slint_renderer = SlintRenderer::new(display);
+        .map_err(Error::from)?;
+        let mut slint_renderer = SlintRenderer::new(
+            display,
+            ScreenOrientation::default(),
+            &ColorCalibration::default(),
+        );
+
+        let mut ledc = Ledc::new(peripherals.LEDC);
+        let backlight_timer =
+            backlight::init_timer(&mut ledc, timer::Number::Timer0).map_err(Error::from)?;
+        let mut backlight = Backlight::new(
+            &ledc,
+            &backlight_timer,
+            channel::Number::Channel0,
+            peripherals.GPIO32,
+        )
+        .map_err(Error::from)?;
 
         let window = self.window.borrow().clone().unwrap();
-        window.set_size(PhysicalSize::new(320, 240));
+        let (width, height) = ScreenOrientation::default().size();
+        window.set_size(PhysicalSize::new(width, height));
 
+        let mut io = Io::new(peripherals.IO_MUX);
+        #[cfg(not(feature = "ft6236"))]
         let mut touch_input = Xpt2046TouchInput::create(
-            &fast_spi_ref_cell,
+            &fast_spi_bus,
+            peripherals.GPIO33,
+            peripherals.GPIO36,
+            &mut io,
+            settings.touch_calibration,
+            ScreenOrientation::default(),
+        )
+        .map_err(Error::from)?;
+        #[cfg(feature = "ft6236")]
+        let mut touch_input = Ft6236TouchInput::create(
+            peripherals.I2C0,
             peripherals.GPIO33,
+            peripherals.GPIO5,
             peripherals.GPIO36,
-            320,
+            &mut io,
+            settings.touch_calibration,
+            ScreenOrientation::default(),
         )
-        .unwrap();
-        touch_input.init().unwrap();
-        init_sd_card(&slow_spi_ref_cell, peripherals.GPIO21);
+        .map_err(Error::from)?;
+        // the recovery screen still shows what happened without touch
+        if let Err(e) = touch_input.init() {
+            warn!("Touch: {}", e);
+        }
+
+        let mut first_frame = true;
+        let mut pacer = FramePacer::new(TARGET_FPS);
         loop {
+            pacer.begin();
             update_timers_and_animations();
-            handle_input(&window, &mut touch_input)?;
+            let input = touch_input.get_input().unwrap_or(TouchInputResponse::NoInput);
+            handle_input(&window, input)?;
 
-            window.draw_if_needed(|renderer| {
+            if diagnostics_requested.changed().is_some() {
+                let snapshot = Snapshot {
+                    log: &self.log,
+                    settings: &settings,
+                    ip_info: None,
+                    wifi_connected: false,
+                    sd_report: sd.as_ref().map(|sd| sd.check_report()),
+                    brightness: backlight.brightness(),
+                    partitions: config.partitions(),
+                };
+                let status = diagnostics::export(&snapshot, sd.as_ref(), None).unwrap_or_else(|e| {
+                    warn!("Diagnostics: {}", e);
+                    "Diagnostics export failed"
+                });
+                self.status.send(status.into());
+            }
+
+            if factory_reset_requested.changed().is_some() {
+                settings = Settings::default();
+                let status = match config.save(&settings) {
+                    Ok(()) => "Settings reset to defaults",
+                    Err(e) => {
+                        error!("Failed to persist settings: {}", e);
+                        "Settings reset failed"
+                    }
+                };
+                self.status.send(status.into());
+            }
+
+            let drawn = window.draw_if_needed(|renderer| {
                 renderer.render_by_line(&mut slint_renderer);
             });
-            window.request_redraw();
+            if drawn {
+                slint_renderer.frame_drawn();
+            }
+            if first_frame {
+                first_frame = false;
+                backlight.fade_to(settings.brightness.max(30), BACKLIGHT_FADE_MS).ok();
+            }
+            pacer.idle(window.window().has_active_animations() || touch_input.is_active());
         }
     }
 }
@@ -299,15 +2377,987 @@ fn main() -> ! {
 
     let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
     let peripherals = esp_hal::init(config);
-    esp_println::logger::init_logger_from_env();
+    // added after the internal heap, which is tried first and so keeps the small and dma
+    // allocations; the framebuffers and large buffers spill into the 4 MiB of PSRAM
+    #[cfg(feature = "psram")]
+    esp_alloc::psram_allocator!(peripherals.PSRAM, esp_hal::psram);
+    logger::init();
+    telemetry::paint_stack();
+    safe_mode::record_boot();
+
+    // hooks log through the ring so the lines end up in diagnostics bundles
+    let log = LogRing::new();
+    let notifications = Notifications::new();
+    if let Some(phase) = watchdog::last_reset() {
+        let budget = phase.budget().as_secs();
+        log.record(format!("Watchdog reset: {} phase ran past {} s", phase, budget));
+        notifications.notify(&format!("Restarted after a hang in {}", phase));
+    }
+    let mut events = EventHooks::default();
+    events
+        .on_ip_acquired({
+            let log = log.clone();
+            move |info| log.record(format!("event: ip acquired {:?}", info.ip))
+        })
+        .on_wifi_lost({
+            let log = log.clone();
+            move || log.record("event: wifi lost".into())
+        })
+        .on_ws_message({
+            let log = log.clone();
+            move |msg| log.record(format!("event: ws message {msg}"))
+        })
+        .on_sd_mounted({
+            let log = log.clone();
+            move || log.record("event: sd mounted".into())
+        })
+        .on_wake({
+            let log = log.clone();
+            move |trigger| log.record(format!("event: woken by {:?}", trigger))
+        })
+        .on_power_fail({
+            let log = log.clone();
+            move || log.record("event: power fail".into())
+        })
+        .on_low_heap({
+            let log = log.clone();
+            let notifications = notifications.clone();
+            move |stats| {
+                log.record(format!("event: low heap, {}", stats));
+                notifications.notify("Low memory");
+            }
+        });
+    #[cfg(feature = "ffi")]
+    ffi::forward_events(&mut events);
+    #[cfg(feature = "battery")]
+    events.on_low_battery({
+        let log = log.clone();
+        move |view| log.record(format!("event: battery low, {} mV", view.millivolts))
+    });
+
+    let wifi_connected = Watch::new(false);
+    let wifi_bars = Watch::new(0);
+    let ip_address = Watch::new(SharedString::new());
+    let wifi_connected_rx = wifi_connected.subscribe();
+    let wifi_bars_rx = wifi_bars.subscribe();
+    let ip_address_rx = ip_address.subscribe();
+    let icon = Watch::new(Image::default());
+    let icon_rx = icon.subscribe();
+    let storage_warning = Watch::new(SharedString::new());
+    let storage_warning_rx = storage_warning.subscribe();
+    let heartbeat_late = Watch::new(false);
+    let heartbeat_late_rx = heartbeat_late.subscribe();
+    let errors = Watch::new(ErrorView::default());
+    let errors_rx = errors.subscribe();
+    let error_command_request = Watch::new((0u32, ErrorCommand::Retry));
+    let clock_text = Watch::new(SharedString::new());
+    let clock_rx = clock_text.subscribe();
+    let standby_request = Watch::new(false);
+    let shutdown_message = Watch::new(SharedString::new());
+    let shutdown_message_rx = shutdown_message.subscribe();
+    let shutdown_request = Watch::new((0u32, ShutdownKind::Reboot));
+    let brightness = Watch::new(100);
+    let brightness_rx = brightness.subscribe();
+    let brightness_request = Watch::new(100);
+    let touch_clicks = Watch::new(true);
+    let touch_clicks_rx = touch_clicks.subscribe();
+    let touch_clicks_request = Watch::new(true);
+    let tone_request = Watch::new((0u32, (0, 0)));
+    let quick_settings = Watch::new(QuickState::default());
+    let quick_settings_rx = quick_settings.subscribe();
+    let quick_command_request = Watch::new((0u32, QuickCommand::Close));
+    let reliability = Watch::new(ReliabilityRecord::default());
+    let reliability_rx = reliability.subscribe();
+    let ping_request = Watch::new((0u32, PingHost::Gateway));
+    let ping_result = Watch::new(SharedString::new());
+    let ping_result_rx = ping_result.subscribe();
+    let status = Watch::new(SharedString::new());
+    let status_rx = status.subscribe();
+    let diagnostics_request = Watch::new(0u32);
+    let notification_rx = notifications.subscribe();
+    let webhook_request = Watch::new((0u32, SharedString::new()));
+    let perf = Watch::new(PerfStats::default());
+    let perf_rx = perf.subscribe();
+    let heatmap = Watch::new((Vec::new(), 0u16));
+    let heatmap_rx = heatmap.subscribe();
+    let memory = Watch::new(MemoryStats::default());
+    let memory_rx = memory.subscribe();
+    let latency_ms = Watch::new(0);
+    let latency_rx = latency_ms.subscribe();
+    let tags = Watch::new(Vec::new());
+    let tags_rx = tags.subscribe();
+    let factory_reset_request = Watch::new(0u32);
+    let device_prompt = Watch::new(DevicePrompt::default());
+    let device_prompt_rx = device_prompt.subscribe();
+    let back_request = Watch::new(0u32);
+    let back_rx = back_request.subscribe();
+    let forget_wifi_request = Watch::new(0u32);
+    let wifi_networks = Watch::new((Vec::new(), SharedString::new()));
+    let wifi_networks_rx = wifi_networks.subscribe();
+    let wifi_command_request = Watch::new((0u32, WifiCommand::Scan));
+    let now_playing = Watch::new(Track::default());
+    let now_playing_rx = now_playing.subscribe();
+    let album_art = Watch::new(Image::default());
+    let album_art_rx = album_art.subscribe();
+    let player_command_request = Watch::new((0u32, PlayerCommand::Pause));
+    let agenda = Watch::new(Agenda::new());
+    let agenda_rx = agenda.subscribe();
+    let feed = Watch::new(Feed::default());
+    let feed_rx = feed.subscribe();
+    let remote_text = Watch::new(SharedString::new());
+    let remote_text_rx = remote_text.subscribe();
+    let navigate_request = Watch::new((0u32, navigation::Page::Home));
+    let navigate_rx = navigate_request.subscribe();
+    let chat = Watch::new(Vec::new());
+    let chat_rx = chat.subscribe();
+    let topics = Watch::new(Vec::new());
+    let topics_rx = topics.subscribe();
+    let chat_send_request = Watch::new((0u32, SharedString::new()));
+    let timers = Watch::new(Vec::new());
+    let timers_rx = timers.subscribe();
+    let timer_alert = Watch::new(SharedString::new());
+    let timer_alert_rx = timer_alert.subscribe();
+    let timer_command_request = Watch::new((0u32, TimerCommand::Remove(0)));
+    let timer_dismiss_request = Watch::new(0u32);
+    let screenshot_request = Watch::new(0u32);
+    let stopwatch_time = Watch::new((SharedString::new(), false));
+    let stopwatch_time_rx = stopwatch_time.subscribe();
+    let laps = Watch::new(Vec::new());
+    let laps_rx = laps.subscribe();
+    let stopwatch_command_request = Watch::new((0u32, StopwatchCommand::Reset));
+    let gpio_pins = Watch::new(Vec::new());
+    let gpio_pins_rx = gpio_pins.subscribe();
+    let gpio_command_request = Watch::new((0u32, GpioCommand::Bind(0)));
+    let outputs = Watch::new((0, 0));
+    let outputs_rx = outputs.subscribe();
+    // out of range, so even the slider's starting angle counts as a change
+    let servo_request = Watch::new(-1);
+    let pwm_request = Watch::new(0);
+    let stepper = Watch::new(StepperView::default());
+    let stepper_rx = stepper.subscribe();
+    let stepper_command_request = Watch::new((0u32, StepperCommand::Stop));
+    let orientation = Watch::new(SharedString::new());
+    let orientation_rx = orientation.subscribe();
+    let orientation_request = Watch::new((0u32, OrientationCommand::Rotate));
+    let color = Watch::new(ColorCalibration::default());
+    let color_rx = color.subscribe();
+    let color_command_request = Watch::new((0u32, ColorCommand::Reset));
+    let night = Watch::new(NightView::default());
+    let night_rx = night.subscribe();
+    let shown_page = Watch::new(None);
+    let list_scroll = Watch::new(ListScroll::default());
+    let sensors = Watch::new(Vec::new());
+    let sensors_rx = sensors.subscribe();
+    let energy = Watch::new(EnergyView::default());
+    let energy_rx = energy.subscribe();
+    #[cfg(feature = "battery")]
+    let battery = Watch::new(BatteryView::default());
+    #[cfg(feature = "battery")]
+    let battery_rx = battery.subscribe();
+    #[cfg(feature = "scripting")]
+    let script = Watch::new(ScriptView::default());
+    #[cfg(feature = "scripting")]
+    let script_rx = script.subscribe();
+    #[cfg(feature = "scripting")]
+    let script_press = Watch::new((0u32, 0usize));
+    let boot = Watch::new(BootState::STARTING);
+    let boot_rx = boot.subscribe();
 
     slint::platform::set_platform(Box::new(EspBackend {
         peripherals: RefCell::new(Some(peripherals)),
         window: RefCell::new(None),
+        events: RefCell::new(events),
+        wifi_connected,
+        wifi_bars,
+        ip_address,
+        icon,
+        storage_warning,
+        heartbeat_late,
+        errors,
+        error_command_requested: RefCell::new(Some(error_command_request.subscribe())),
+        standby_requested: RefCell::new(Some(standby_request.subscribe())),
+        shutdown_message,
+        shutdown_requested: RefCell::new(Some(shutdown_request.subscribe())),
+        brightness,
+        brightness_requested: RefCell::new(Some(brightness_request.subscribe())),
+        touch_clicks,
+        touch_clicks_requested: RefCell::new(Some(touch_clicks_request.subscribe())),
+        tone_requested: RefCell::new(Some(tone_request.subscribe())),
+        quick_settings,
+        quick_command_requested: RefCell::new(Some(quick_command_request.subscribe())),
+        reliability,
+        ping_requested: RefCell::new(Some(ping_request.subscribe())),
+        ping_result,
+        status,
+        diagnostics_requested: RefCell::new(Some(diagnostics_request.subscribe())),
+        log,
+        notifications,
+        clock_text,
+        webhook_requested: RefCell::new(Some(webhook_request.subscribe())),
+        perf,
+        heatmap,
+        memory,
+        latency_ms,
+        tags,
+        factory_reset_requested: RefCell::new(Some(factory_reset_request.subscribe())),
+        device_prompt,
+        back_requested: back_request,
+        forget_wifi_requested: RefCell::new(Some(forget_wifi_request.subscribe())),
+        wifi_networks,
+        wifi_command_requested: RefCell::new(Some(wifi_command_request.subscribe())),
+        now_playing,
+        album_art,
+        agenda,
+        feed,
+        player_command_requested: RefCell::new(Some(player_command_request.subscribe())),
+        remote_text,
+        navigate_requested: navigate_request,
+        chat,
+        topics,
+        chat_send_requested: RefCell::new(Some(chat_send_request.subscribe())),
+        timers,
+        timer_alert,
+        timer_command_requested: RefCell::new(Some(timer_command_request.subscribe())),
+        timer_dismiss_requested: RefCell::new(Some(timer_dismiss_request.subscribe())),
+        screenshot_requested: RefCell::new(Some(screenshot_request.subscribe())),
+        stopwatch_time,
+        laps,
+        stopwatch_command_requested: RefCell::new(Some(stopwatch_command_request.subscribe())),
+        gpio_pins,
+        gpio_command_requested: RefCell::new(Some(gpio_command_request.subscribe())),
+        outputs,
+        servo_requested: RefCell::new(Some(servo_request.subscribe())),
+        pwm_requested: RefCell::new(Some(pwm_request.subscribe())),
+        stepper,
+        stepper_command_requested: RefCell::new(Some(stepper_command_request.subscribe())),
+        orientation,
+        orientation_requested: RefCell::new(Some(orientation_request.subscribe())),
+        color,
+        color_command_requested: RefCell::new(Some(color_command_request.subscribe())),
+        night,
+        sensors,
+        energy,
+        boot,
+        assets: Assets::new(),
+        shown_page_requested: RefCell::new(Some(shown_page.subscribe())),
+        list_scroll_requested: RefCell::new(Some(list_scroll.subscribe())),
+        #[cfg(feature = "battery")]
+        battery,
+        #[cfg(feature = "scripting")]
+        script,
+        #[cfg(feature = "scripting")]
+        script_press_requested: RefCell::new(Some(script_press.subscribe())),
+        #[cfg(feature = "psram")]
+        frames: RefCell::new(None),
     }))
     .expect("backend already initialized");
 
+    if safe_mode::is_active() {
+        let app = SafeModeWindow::new().unwrap();
+        app.set_failed_boots(safe_mode::failed_boots() as i32);
+        app.set_reset_reason(format!("{:?}", reset_reason(Cpu::ProCpu)).into());
+        app.set_version(build_info::summary().into());
+        let _status_binding = watch::bind(status_rx, app.as_weak(), |ui, v| ui.set_status(v));
+        let mut diagnostics_taps = 0u32;
+        app.on_export_diagnostics(move || {
+            diagnostics_taps = diagnostics_taps.wrapping_add(1);
+            diagnostics_request.send(diagnostics_taps);
+        });
+        let mut reset_taps = 0u32;
+        app.on_reset_settings(move || {
+            reset_taps = reset_taps.wrapping_add(1);
+            factory_reset_request.send(reset_taps);
+        });
+        app.on_restart(|| safe_mode::restart());
+        app.run().unwrap();
+        loop {}
+    }
+
     let app = MainWindow::new().unwrap();
+    let about = app.global::<BuildInfo>();
+    about.set_version(build_info::PKG_VERSION.into());
+    about.set_git_hash(build_info::GIT_HASH.into());
+    about.set_build_time(build_info::BUILD_TIME.into());
+    about.set_rustc(build_info::RUSTC_VERSION.into());
+    about.set_esp_hal(build_info::ESP_HAL_VERSION.into());
+    about.set_slint(build_info::SLINT_VERSION.into());
+    let _wifi_binding = watch::bind(wifi_connected_rx, app.as_weak(), |ui, v| {
+        ui.set_wifi_connected(v)
+    });
+    let _wifi_bars_binding = watch::bind(wifi_bars_rx, app.as_weak(), |ui, v| ui.set_wifi_bars(v));
+    let _ip_binding = watch::bind(ip_address_rx, app.as_weak(), |ui, v| ui.set_ip_address(v));
+    let _icon_binding = watch::bind(icon_rx, app.as_weak(), |ui, v| ui.set_icon(v));
+    let clock_label = FastLabel::new();
+    app.set_clock(clock_label.model());
+    let _clock_binding = watch::bind(clock_rx, app.as_weak(), move |_, v| clock_label.set(&v));
+    let _errors_binding = watch::bind(errors_rx, app.as_weak(), |ui, view| {
+        let errors = ui.global::<Errors>();
+        errors.set_message(view.message.as_str().into());
+        errors.set_can_retry(view.can_retry);
+    });
+    let error_taps = Rc::new(RefCell::new(0u32));
+    let send_error_command = Rc::new(move |command: ErrorCommand| {
+        let mut taps = error_taps.borrow_mut();
+        *taps = taps.wrapping_add(1);
+        error_command_request.send((*taps, command));
+    });
+    let errors_ui = app.global::<Errors>();
+    errors_ui.on_retry({
+        let send_error_command = send_error_command.clone();
+        move || send_error_command(ErrorCommand::Retry)
+    });
+    errors_ui.on_dismiss(move || send_error_command(ErrorCommand::Dismiss));
+    let _storage_binding = watch::bind(storage_warning_rx, app.as_weak(), |ui, v| {
+        ui.set_storage_warning(v)
+    });
+    let _heartbeat_binding = watch::bind(heartbeat_late_rx, app.as_weak(), |ui, v| {
+        ui.set_heartbeat_late(v)
+    });
+    let _touch_clicks_binding = watch::bind(touch_clicks_rx, app.as_weak(), |ui, enabled| {
+        ui.global::<Sound>().set_clicks(enabled)
+    });
+    let _brightness_binding = watch::bind(brightness_rx, app.as_weak(), |ui, v| {
+        ui.global::<Brightness>().set_level(v)
+    });
+    let _quick_settings_binding = watch::bind(quick_settings_rx, app.as_weak(), |ui, state| {
+        let quick = ui.global::<QuickSettings>();
+        quick.set_open(state.open);
+        quick.set_rotation_locked(state.rotation_locked);
+        quick.set_wifi(!state.wifi_off);
+        quick.set_muted(state.muted);
+    });
+    let _status_binding = watch::bind(status_rx, app.as_weak(), |ui, v| ui.set_status(v));
+    let _notification_binding = watch::bind(notification_rx, app.as_weak(), |ui, (_, text)| {
+        ui.set_notification(text)
+    });
+    let _perf_binding = watch::bind(perf_rx, app.as_weak(), |ui, stats| {
+        let perf = ui.global::<Perf>();
+        perf.set_fps(stats.fps as i32);
+        perf.set_frame_ms(stats.frame_ms as i32);
+        perf.set_worst_ms(stats.worst_ms as i32);
+        perf.set_heap_kb(stats.heap_used_kb as i32);
+        perf.set_degraded(stats.degraded);
+        perf.set_cpu_load(stats.cpu_load as i32);
+        perf.set_glitches(stats.glitches as i32);
+    });
+    let _heatmap_binding = watch::bind(heatmap_rx, app.as_weak(), |ui, (cells, off_screen)| {
+        let heatmap = ui.global::<Heatmap>();
+        let cells: Vec<i32> = cells.into_iter().map(i32::from).collect();
+        heatmap.set_cells(ModelRc::new(VecModel::from(cells)));
+        heatmap.set_off_screen(off_screen as i32);
+    });
+    let _memory_binding = watch::bind(memory_rx, app.as_weak(), |ui, stats| {
+        let memory = ui.global::<Memory>();
+        memory.set_heap_size(stats.heap_size as i32);
+        memory.set_heap_used(stats.heap_used as i32);
+        memory.set_internal_free(stats.internal_free as i32);
+        memory.set_largest_block(stats.largest_free_block as i32);
+        memory.set_stack_used(stats.stack_used as i32);
+        memory.set_stack_size(stats.stack_size as i32);
+    });
+    let _latency_binding = watch::bind(latency_rx, app.as_weak(), |ui, ms| {
+        ui.global::<Latency>().set_input(ms as i64)
+    });
+    let _tags_binding = watch::bind(tags_rx, app.as_weak(), |ui, tags| {
+        let items: Vec<TagItem> = tags
+            .iter()
+            .map(|tag| {
+                let name = if tag.name.is_empty() { &tag.id } else { &tag.name };
+                TagItem { id: tag.id.as_str().into(), name: name.as_str().into() }
+            })
+            .collect();
+        ui.set_tags(ModelRc::new(VecModel::from(items)));
+    });
+    let _device_prompt_binding = watch::bind(device_prompt_rx, app.as_weak(), |ui, prompt| {
+        let device_auth = ui.global::<DeviceAuth>();
+        device_auth.set_user_code(prompt.user_code.as_str().into());
+        device_auth.set_verification_uri(prompt.verification_uri.as_str().into());
+        let qr = if prompt.user_code.is_empty() { None } else { qr::encode(prompt.qr_text()) };
+        device_auth.set_qr(qr.unwrap_or_default());
+    });
+    // pages are only built while on top, the stack itself is all that survives navigation
+    let navigator = Rc::new(RefCell::new(Navigator::new()));
+    // the top of the stack to the UI, and to the render profile in the event loop
+    let show_current = {
+        let navigator = navigator.clone();
+        move |ui: &MainWindow| {
+            let page = navigator.borrow().current();
+            shown_page.send(Some(page));
+            ui.global::<Nav>().set_page(page_to_ui(page));
+        }
+    };
+    let nav = app.global::<Nav>();
+    nav.on_navigate({
+        let navigator = navigator.clone();
+        let show_current = show_current.clone();
+        let ui = app.as_weak();
+        move |page| {
+            navigator.borrow_mut().push(page_from_ui(page));
+            if let Some(ui) = ui.upgrade() {
+                show_current(&ui);
+            }
+        }
+    });
+    nav.on_back({
+        let navigator = navigator.clone();
+        let show_current = show_current.clone();
+        let ui = app.as_weak();
+        move || {
+            navigator.borrow_mut().back();
+            if let Some(ui) = ui.upgrade() {
+                show_current(&ui);
+            }
+        }
+    });
+    // the launcher grid, built once from the registered apps; the icons are only held by the tiles
+    let apps = Rc::new(AppRegistry::with_builtin(APP_REGISTRARS));
+    let icons = Assets::new();
+    let tiles: Vec<LauncherApp> = apps
+        .apps()
+        .iter()
+        .map(|entry| LauncherApp {
+            name: entry.name.into(),
+            initial: entry.name.chars().take(1).collect::<String>().as_str().into(),
+            icon: icons.get(entry.icon).unwrap_or_default(),
+            page: page_to_ui(entry.page),
+        })
+        .collect();
+    app.global::<Launcher>().set_apps(ModelRc::new(VecModel::from(tiles)));
+    let _apps_timer = apps.start_updates(navigator.clone(), app.as_weak());
+    // swipes, the hardware button and the http server arrive from the event loop
+    let _back_binding = watch::bind(back_rx, app.as_weak(), {
+        let navigator = navigator.clone();
+        let show_current = show_current.clone();
+        move |ui, _| {
+            navigator.borrow_mut().back();
+            show_current(ui);
+        }
+    });
+    let _boot_binding = watch::bind(boot_rx, app.as_weak(), {
+        let show_current = show_current.clone();
+        move |ui, state| match state {
+            BootState::Loading { stage, done } => {
+                let loading = ui.global::<Boot>();
+                loading.set_stage(stage.into());
+                loading.set_percent(done as i32 * 100 / boot::STAGES as i32);
+            }
+            BootState::Ready => show_current(ui),
+        }
+    });
+    let _navigate_binding = watch::bind(navigate_rx, app.as_weak(), move |ui, (_, page)| {
+        navigator.borrow_mut().push(page);
+        show_current(ui);
+    });
+    let _remote_text_binding =
+        watch::bind(remote_text_rx, app.as_weak(), |ui, v| ui.set_remote_text(v));
+    app.on_standby(move || standby_request.send(true));
+    let _shutdown_binding = watch::bind(shutdown_message_rx, app.as_weak(), |ui, message| {
+        ui.global::<Power>().set_message(message)
+    });
+    let power_ui = app.global::<Power>();
+    let shutdown_taps = Rc::new(RefCell::new(0u32));
+    let send_shutdown = Rc::new(move |kind: ShutdownKind| {
+        let mut taps = shutdown_taps.borrow_mut();
+        *taps = taps.wrapping_add(1);
+        shutdown_request.send((*taps, kind));
+    });
+    power_ui.on_reboot({
+        let send_shutdown = send_shutdown.clone();
+        move || send_shutdown(ShutdownKind::Reboot)
+    });
+    power_ui.on_power_off(move || send_shutdown(ShutdownKind::PowerOff));
+    // a counter so every tap is a change
+    let mut diagnostics_taps = 0u32;
+    app.on_export_diagnostics(move || {
+        diagnostics_taps = diagnostics_taps.wrapping_add(1);
+        diagnostics_request.send(diagnostics_taps);
+    });
+    let mut webhook_taps = 0u32;
+    app.on_webhook(move |name| {
+        webhook_taps = webhook_taps.wrapping_add(1);
+        webhook_request.send((webhook_taps, name));
+    });
+    let _now_playing_binding = watch::bind(now_playing_rx, app.as_weak(), |ui, track| {
+        let player = ui.global::<NowPlaying>();
+        player.set_title(track.title.as_str().into());
+        player.set_artist(track.artist.as_str().into());
+        player.set_album(track.album.as_str().into());
+        player.set_playing(track.playing);
+        let progress = track.progress_ms as f32 / track.duration_ms.max(1) as f32;
+        player.set_progress(progress.min(1.0));
+        player.set_duration_ms(track.duration_ms as i32);
+    });
+    let _album_art_binding = watch::bind(album_art_rx, app.as_weak(), |ui, art| {
+        ui.global::<NowPlaying>().set_art(art)
+    });
+    let _agenda_binding = watch::bind(agenda_rx, app.as_weak(), |ui, agenda| {
+        let now = clock::local_secs_now().unwrap_or(0);
+        let items: Vec<AgendaItem> = agenda
+            .iter()
+            .map(|event| AgendaItem {
+                when: event.when(now).into(),
+                title: event.summary.as_str().into(),
+                location: event.location.as_str().into(),
+            })
+            .collect();
+        ui.global::<Calendar>().set_events(ModelRc::new(VecModel::from(items)));
+    });
+    let _feed_binding = watch::bind(feed_rx, app.as_weak(), |ui, feed| {
+        let items: Vec<NewsItem> = feed
+            .items
+            .iter()
+            .map(|item| NewsItem { title: item.title.as_str().into(), when: item.when().into() })
+            .collect();
+        let news = ui.global::<News>();
+        news.set_source(feed.title.as_str().into());
+        news.set_items(ModelRc::new(VecModel::from(items)));
+    });
+    app.global::<Scrolling>().on_moved(move |top, height, viewport_y| {
+        list_scroll.send(ListScroll::from_lengths(top, height, viewport_y));
+    });
+    let _chat_binding = watch::bind(chat_rx, app.as_weak(), |ui, history| {
+        let lines: Vec<ChatLine> = history
+            .iter()
+            .map(|message| ChatLine {
+                from: message.from.as_str().into(),
+                text: message.text.as_str().into(),
+                when: message.when().into(),
+                outgoing: message.outgoing,
+            })
+            .collect();
+        ui.global::<Chat>().set_messages(ModelRc::new(VecModel::from(lines)));
+    });
+    let _topics_binding = watch::bind(topics_rx, app.as_weak(), |ui, values| {
+        let items: Vec<TopicItem> = values
+            .iter()
+            .map(|v| TopicItem { topic: v.topic.as_str().into(), value: v.value.as_str().into() })
+            .collect();
+        ui.global::<WsTopics>().set_items(ModelRc::new(VecModel::from(items)));
+    });
+    let _reliability_binding = watch::bind(reliability_rx, app.as_weak(), |ui, record| {
+        let report = ui.global::<ReliabilityReport>();
+        report.set_build(record.current.build.as_str().into());
+        report.set_previous_build(
+            record.previous.as_ref().map_or(SharedString::new(), |p| p.build.as_str().into()),
+        );
+        let rows: Vec<ReliabilityRow> = record
+            .rows()
+            .into_iter()
+            .map(|(label, current, previous)| ReliabilityRow {
+                label: label.into(),
+                current: current.into(),
+                previous: previous.into(),
+            })
+            .collect();
+        report.set_rows(ModelRc::new(VecModel::from(rows)));
+    });
+    let _ping_binding = watch::bind(ping_result_rx, app.as_weak(), |ui, text| {
+        ui.global::<NetworkDiag>().set_result(text)
+    });
+    let mut pings = 0u32;
+    app.global::<NetworkDiag>().on_ping(move |host| {
+        if let Some(host) = PingHost::parse(&host) {
+            pings = pings.wrapping_add(1);
+            ping_request.send((pings, host));
+        }
+    });
+    let chat_ui = app.global::<Chat>();
+    let mut chat_sends = 0u32;
+    chat_ui.on_send(move |text| {
+        chat_sends = chat_sends.wrapping_add(1);
+        chat_send_request.send((chat_sends, text));
+    });
+    chat_ui.on_drop_last(|text| {
+        let mut text = String::from(text.as_str());
+        text.pop();
+        text.into()
+    });
+    let _timers_binding = watch::bind(timers_rx, app.as_weak(), |ui, timers| {
+        let items: Vec<TimerItem> = timers
+            .iter()
+            .map(|timer| TimerItem {
+                name: timer.name.as_str().into(),
+                remaining: timer.remaining.as_str().into(),
+                running: timer.running,
+                expired: timer.expired,
+            })
+            .collect();
+        ui.global::<Timers>().set_items(ModelRc::new(VecModel::from(items)));
+    });
+    let _timer_alert_binding = watch::bind(timer_alert_rx, app.as_weak(), |ui, name| {
+        ui.global::<Timers>().set_alert(name)
+    });
+    let timers_ui = app.global::<Timers>();
+    let timer_taps = Rc::new(RefCell::new(0u32));
+    let send_timer_command = Rc::new(move |command: TimerCommand| {
+        let mut taps = timer_taps.borrow_mut();
+        *taps = taps.wrapping_add(1);
+        timer_command_request.send((*taps, command));
+    });
+    timers_ui.on_add({
+        let send_timer_command = send_timer_command.clone();
+        move |name, minutes| {
+            let mut label = heapless::String::new();
+            for c in name.chars() {
+                if label.push(c).is_err() {
+                    break;
+                }
+            }
+            send_timer_command(TimerCommand::Add(label, minutes.max(1) as u32 * 60))
+        }
+    });
+    timers_ui.on_toggle({
+        let send_timer_command = send_timer_command.clone();
+        move |i| send_timer_command(TimerCommand::Toggle(i as usize))
+    });
+    timers_ui.on_reset({
+        let send_timer_command = send_timer_command.clone();
+        move |i| send_timer_command(TimerCommand::Reset(i as usize))
+    });
+    timers_ui.on_remove(move |i| send_timer_command(TimerCommand::Remove(i as usize)));
+    let mut timer_dismissals = 0u32;
+    timers_ui.on_dismiss(move || {
+        timer_dismissals = timer_dismissals.wrapping_add(1);
+        timer_dismiss_request.send(timer_dismissals);
+    });
+    // ten times a second while running, mostly only the last digits change
+    let stopwatch_label = FastLabel::new();
+    app.global::<Stopwatch>().set_time(stopwatch_label.model());
+    let _stopwatch_binding =
+        watch::bind(stopwatch_time_rx, app.as_weak(), move |ui, (time, running)| {
+            stopwatch_label.set(&time);
+            ui.global::<Stopwatch>().set_running(running);
+        });
+    let _laps_binding = watch::bind(laps_rx, app.as_weak(), |ui, laps| {
+        // newest on top
+        let items: Vec<LapItem> = laps
+            .iter()
+            .rev()
+            .map(|lap| LapItem {
+                number: lap.number as i32,
+                split: stopwatch::format_us(lap.split_us).into(),
+                total: stopwatch::format_us(lap.total_us).into(),
+            })
+            .collect();
+        ui.global::<Stopwatch>().set_laps(ModelRc::new(VecModel::from(items)));
+    });
+    let stopwatch_ui = app.global::<Stopwatch>();
+    let stopwatch_taps = Rc::new(RefCell::new(0u32));
+    let send_stopwatch_command = Rc::new(move |command: StopwatchCommand| {
+        let mut taps = stopwatch_taps.borrow_mut();
+        *taps = taps.wrapping_add(1);
+        stopwatch_command_request.send((*taps, command));
+    });
+    stopwatch_ui.on_toggle({
+        let send_stopwatch_command = send_stopwatch_command.clone();
+        move || send_stopwatch_command(StopwatchCommand::Toggle)
+    });
+    stopwatch_ui.on_lap({
+        let send_stopwatch_command = send_stopwatch_command.clone();
+        move || send_stopwatch_command(StopwatchCommand::Lap)
+    });
+    stopwatch_ui.on_reset(move || send_stopwatch_command(StopwatchCommand::Reset));
+    let _gpio_binding = watch::bind(gpio_pins_rx, app.as_weak(), |ui, pins| {
+        let items: Vec<PinItem> = pins
+            .iter()
+            .map(|view| PinItem {
+                name: format!("GPIO{}", view.pin.number).into(),
+                mode: format!("{}", view.config.mode).into(),
+                high: view.high,
+                output: view.config.mode == PinMode::Output,
+                bound: view.config.bound,
+            })
+            .collect();
+        ui.global::<Gpio>().set_pins(ModelRc::new(VecModel::from(items)));
+    });
+    let gpio_ui = app.global::<Gpio>();
+    let gpio_taps = Rc::new(RefCell::new(0u32));
+    let send_gpio_command = Rc::new(move |command: GpioCommand| {
+        let mut taps = gpio_taps.borrow_mut();
+        *taps = taps.wrapping_add(1);
+        gpio_command_request.send((*taps, command));
+    });
+    gpio_ui.on_cycle_mode({
+        let send_gpio_command = send_gpio_command.clone();
+        move |i| send_gpio_command(GpioCommand::CycleMode(i as usize))
+    });
+    gpio_ui.on_toggle({
+        let send_gpio_command = send_gpio_command.clone();
+        move |i| send_gpio_command(GpioCommand::Toggle(i as usize))
+    });
+    gpio_ui.on_bind(move |i| send_gpio_command(GpioCommand::Bind(i as usize)));
+    let _outputs_binding = watch::bind(outputs_rx, app.as_weak(), |ui, (angle, duty)| {
+        let outputs = ui.global::<Outputs>();
+        outputs.set_servo_angle(angle);
+        outputs.set_pwm_duty(duty);
+    });
+    let outputs_ui = app.global::<Outputs>();
+    outputs_ui.on_servo_changed(move |angle| servo_request.send(angle));
+    outputs_ui.on_pwm_changed(move |duty| pwm_request.send(duty));
+    let _stepper_binding = watch::bind(stepper_rx, app.as_weak(), |ui, view| {
+        let jog = ui.global::<Jog>();
+        jog.set_position(view.position);
+        jog.set_target(view.target);
+        jog.set_speed(view.speed as i32);
+    });
+    let jog_ui = app.global::<Jog>();
+    let stepper_taps = Rc::new(RefCell::new(0u32));
+    let send_stepper_command = Rc::new(move |command: StepperCommand| {
+        let mut taps = stepper_taps.borrow_mut();
+        *taps = taps.wrapping_add(1);
+        stepper_command_request.send((*taps, command));
+    });
+    jog_ui.on_jog({
+        let send_stepper_command = send_stepper_command.clone();
+        move |forward| send_stepper_command(StepperCommand::Jog(forward))
+    });
+    jog_ui.on_stop({
+        let send_stepper_command = send_stepper_command.clone();
+        move || send_stepper_command(StepperCommand::Stop)
+    });
+    jog_ui.on_move_by({
+        let send_stepper_command = send_stepper_command.clone();
+        move |steps| send_stepper_command(StepperCommand::MoveBy(steps))
+    });
+    jog_ui.on_move_to({
+        let send_stepper_command = send_stepper_command.clone();
+        move |position| send_stepper_command(StepperCommand::MoveTo(position))
+    });
+    jog_ui.on_zero(move || send_stepper_command(StepperCommand::Zero));
+    let _orientation_binding = watch::bind(orientation_rx, app.as_weak(), |ui, orientation| {
+        ui.global::<Screen>().set_orientation(orientation);
+    });
+    let screen_ui = app.global::<Screen>();
+    let orientation_taps = Rc::new(RefCell::new(0u32));
+    let send_orientation_command = Rc::new(move |command: OrientationCommand| {
+        let mut taps = orientation_taps.borrow_mut();
+        *taps = taps.wrapping_add(1);
+        orientation_request.send((*taps, command));
+    });
+    screen_ui.on_rotate({
+        let send_orientation_command = send_orientation_command.clone();
+        move || send_orientation_command(OrientationCommand::Rotate)
+    });
+    screen_ui.on_mirror(move || send_orientation_command(OrientationCommand::Mirror));
+    let _color_binding = watch::bind(color_rx, app.as_weak(), |ui, color| {
+        let color_ui = ui.global::<ColorCal>();
+        let gamma: Vec<i32> = color.gamma.iter().map(|&g| g as i32).collect();
+        let gain: Vec<i32> = color.gain.iter().map(|&k| k as i32).collect();
+        color_ui.set_gamma(ModelRc::new(VecModel::from(gamma)));
+        color_ui.set_gain(ModelRc::new(VecModel::from(gain)));
+    });
+    let color_ui = app.global::<ColorCal>();
+    let color_taps = Rc::new(RefCell::new(0u32));
+    let send_color_command = Rc::new(move |command: ColorCommand| {
+        let mut taps = color_taps.borrow_mut();
+        *taps = taps.wrapping_add(1);
+        color_command_request.send((*taps, command));
+    });
+    color_ui.on_adjust({
+        let send_color_command = send_color_command.clone();
+        move |channel, gamma, gain| {
+            send_color_command(ColorCommand::Adjust {
+                channel: channel as usize,
+                gamma: gamma.clamp(0, u8::MAX as i32) as u8,
+                gain: gain.clamp(0, u8::MAX as i32) as u8,
+            })
+        }
+    });
+    color_ui.on_reset({
+        let send_color_command = send_color_command.clone();
+        move || send_color_command(ColorCommand::Reset)
+    });
+    color_ui.on_night_toggled({
+        let send_color_command = send_color_command.clone();
+        move |enabled| send_color_command(ColorCommand::NightMode(enabled))
+    });
+    let _night_binding = watch::bind(night_rx, app.as_weak(), |ui, night| {
+        let color_ui = ui.global::<ColorCal>();
+        color_ui.set_night_enabled(night.enabled);
+        color_ui.set_night_hours(night.hours.as_str().into());
+        color_ui.set_night_active(night.active);
+    });
+    color_ui.on_save(move || send_color_command(ColorCommand::Save));
+    let _sensors_binding = watch::bind(sensors_rx, app.as_weak(), |ui, views| {
+        let items: Vec<SensorItem> = views
+            .iter()
+            .map(|view| SensorItem {
+                name: view.name.as_str().into(),
+                value: match view.value {
+                    Some(value) => format!("{:.1} {}", value, view.unit).into(),
+                    None => "--".into(),
+                },
+                chart: view.chart.as_str().into(),
+                range: format!("{:.1} .. {:.1} {}", view.min, view.max, view.unit).into(),
+            })
+            .collect();
+        ui.global::<SensorReadings>().set_items(ModelRc::new(VecModel::from(items)));
+    });
+    let _energy_binding = watch::bind(energy_rx, app.as_weak(), |ui, view| {
+        let energy = ui.global::<Energy>();
+        energy.set_online(view.online);
+        energy.set_power(format!("{:.0} W", view.power).into());
+        energy.set_details(
+            format!(
+                "{:.1} V  {:.2} A  {:.1} Hz  PF {:.2}",
+                view.voltage, view.current, view.frequency, view.power_factor
+            )
+            .into(),
+        );
+        let kwh = |wh: u64| format!("{:.2} kWh", wh as f32 / 1000.0);
+        energy.set_today(kwh(view.totals.today_wh as u64).into());
+        energy.set_total(kwh(view.totals.total_wh).into());
+        energy.set_chart(view.chart.as_str().into());
+        energy.set_range(format!("{:.0} .. {:.0} W", view.min, view.max).into());
+        // oldest first, today last, bars relative to the largest
+        let days: Vec<u32> =
+            view.totals.days.iter().rev().copied().chain([view.totals.today_wh]).collect();
+        let most = days.iter().copied().max().unwrap_or(0).max(1);
+        let bars: Vec<EnergyDay> = days
+            .iter()
+            .map(|wh| EnergyDay {
+                kwh: format!("{:.1}", *wh as f32 / 1000.0).into(),
+                share: *wh as f32 / most as f32,
+            })
+            .collect();
+        energy.set_days(ModelRc::new(VecModel::from(bars)));
+    });
+    #[cfg(feature = "battery")]
+    let _battery_binding = watch::bind(battery_rx, app.as_weak(), |ui, view| {
+        let battery = ui.global::<Battery>();
+        battery.set_present(view.present);
+        battery.set_percent(view.percent as i32);
+        battery.set_low(view.low);
+    });
+    #[cfg(feature = "scripting")]
+    let _script_binding = watch::bind(script_rx, app.as_weak(), |ui, view| {
+        let labels: Vec<ScriptLabel> = view
+            .labels
+            .iter()
+            .map(|(name, text)| ScriptLabel {
+                name: name.as_str().into(),
+                text: text.as_str().into(),
+            })
+            .collect();
+        let buttons: Vec<SharedString> = view.buttons.iter().map(|b| b.as_str().into()).collect();
+        let script = ui.global::<Script>();
+        script.set_labels(ModelRc::new(VecModel::from(labels)));
+        script.set_buttons(ModelRc::new(VecModel::from(buttons)));
+        script.set_error(view.error.as_str().into());
+    });
+    #[cfg(feature = "scripting")]
+    {
+        let presses = RefCell::new(0u32);
+        app.global::<Script>().on_pressed(move |i| {
+            let mut presses = presses.borrow_mut();
+            *presses = presses.wrapping_add(1);
+            script_press.send((*presses, i as usize));
+        });
+    }
+    let player = app.global::<NowPlaying>();
+    let player_taps = Rc::new(RefCell::new(0u32));
+    let send_command = Rc::new(move |command: PlayerCommand| {
+        let mut taps = player_taps.borrow_mut();
+        *taps = taps.wrapping_add(1);
+        player_command_request.send((*taps, command));
+    });
+    player.on_play({
+        let send_command = send_command.clone();
+        move || send_command(PlayerCommand::Play)
+    });
+    player.on_pause({
+        let send_command = send_command.clone();
+        move || send_command(PlayerCommand::Pause)
+    });
+    player.on_next({
+        let send_command = send_command.clone();
+        move || send_command(PlayerCommand::Next)
+    });
+    player.on_previous(move || send_command(PlayerCommand::Previous));
+    let mut screenshot_taps = 0u32;
+    app.on_screenshot(move || {
+        screenshot_taps = screenshot_taps.wrapping_add(1);
+        screenshot_request.send(screenshot_taps);
+    });
+    let mut forget_wifi_taps = 0u32;
+    app.on_forget_wifi(move || {
+        forget_wifi_taps = forget_wifi_taps.wrapping_add(1);
+        forget_wifi_request.send(forget_wifi_taps);
+    });
+    let _wifi_networks_binding =
+        watch::bind(wifi_networks_rx, app.as_weak(), |ui, (networks, status)| {
+            let items: Vec<WifiNetwork> = networks
+                .iter()
+                .map(|ap| WifiNetwork {
+                    ssid: ap.ssid.as_str().into(),
+                    bars: signal::bars(ap.rssi as i32) as i32,
+                    rssi: ap.rssi as i32,
+                    secured: ap.secured(),
+                    auth: ap.auth_name().into(),
+                    channel: ap.channel as i32,
+                })
+                .collect();
+            let scan_ui = ui.global::<WifiScan>();
+            scan_ui.set_networks(ModelRc::new(VecModel::from(items)));
+            scan_ui.set_status(status);
+        });
+    let scan_ui = app.global::<WifiScan>();
+    let wifi_taps = Rc::new(RefCell::new(0u32));
+    let send_wifi_command = Rc::new(move |command: WifiCommand| {
+        let mut taps = wifi_taps.borrow_mut();
+        *taps = taps.wrapping_add(1);
+        wifi_command_request.send((*taps, command));
+    });
+    scan_ui.on_scan({
+        let send_wifi_command = send_wifi_command.clone();
+        move || send_wifi_command(WifiCommand::Scan)
+    });
+    scan_ui.on_join(move |ssid, password| {
+        let (ssid, password) = (ssid.as_str().into(), password.as_str().into());
+        send_wifi_command(WifiCommand::Join { ssid, password })
+    });
+    app.global::<Brightness>()
+        .on_level_changed(move |level| brightness_request.send(level));
+    let quick_ui = app.global::<QuickSettings>();
+    let quick_taps = Rc::new(RefCell::new(0u32));
+    let send_quick_command = Rc::new(move |command: QuickCommand| {
+        let mut taps = quick_taps.borrow_mut();
+        *taps = taps.wrapping_add(1);
+        quick_command_request.send((*taps, command));
+    });
+    quick_ui.on_close({
+        let send_quick_command = send_quick_command.clone();
+        move || send_quick_command(QuickCommand::Close)
+    });
+    quick_ui.on_toggle_rotation_lock({
+        let send_quick_command = send_quick_command.clone();
+        move || send_quick_command(QuickCommand::RotationLock)
+    });
+    quick_ui.on_toggle_wifi({
+        let send_quick_command = send_quick_command.clone();
+        move || send_quick_command(QuickCommand::Wifi)
+    });
+    quick_ui.on_toggle_mute(move || send_quick_command(QuickCommand::Mute));
+    let sound = app.global::<Sound>();
+    sound.on_clicks_changed(move |enabled| touch_clicks_request.send(enabled));
+    let mut tone_taps = 0u32;
+    sound.on_play_tone(move |hz, ms| {
+        tone_taps = tone_taps.wrapping_add(1);
+        tone_request.send((tone_taps, (hz, ms)));
+    });
+    // replayed once the tap that typed it is dispatched, slint doesn't take events re-entrantly;
+    // the focused LineEdit handles it like a key from a real keyboard
+    let keys_app = app.as_weak();
+    app.global::<TextEntry>().on_key(move |text| {
+        let keys_app = keys_app.clone();
+        slint::Timer::single_shot(core::time::Duration::ZERO, move || {
+            if let Some(app) = keys_app.upgrade() {
+                let window = app.window();
+                window.dispatch_event(WindowEvent::KeyPressed { text: text.clone() });
+                window.dispatch_event(WindowEvent::KeyReleased { text });
+            }
+        });
+    });
 
     app.run().unwrap();
 
@@ -0,0 +1,302 @@
+use blocking_network_stack::Stack;
+use esp_hal::time::{Duration, Instant};
+use esp_radio::wifi::WifiDevice;
+use log::warn;
+use smoltcp::wire::{IpAddress, Ipv4Address};
+use thiserror::Error;
+
+use crate::udp::{Datagram, UdpError};
+
+// also sent as the DHCP host name, so routers list the device under the same name
+pub(crate) const HOSTNAME: &str = "esp32-display";
+const MDNS_PORT: u16 = 5353;
+const MDNS_GROUP: Ipv4Address = Ipv4Address::new(224, 0, 0, 251);
+const SERVICE: &str = "_http._tcp.local";
+// DNS-SD service type enumeration, lets browsers discover that http is offered at all
+const SERVICE_TYPES: &str = "_services._dns-sd._udp.local";
+const HTTP_PORT: u16 = 80;
+// RFC 6762 recommendations: 2 minutes for records naming the host, 75 for the rest
+const HOST_TTL: u32 = 120;
+const SERVICE_TTL: u32 = 4500;
+const ANNOUNCEMENTS: u8 = 2;
+const PACKET_SIZE: usize = 512;
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+// on records: replace what caches hold, on questions: the asker wants a unicast reply
+const CLASS_TOP_BIT: u16 = 0x8000;
+const FLAGS_RESPONSE: u16 = 0x8400;
+
+// records in an answer, as bits
+const A: u8 = 1;
+const PTR: u8 = 2;
+const SRV: u8 = 4;
+const TXT: u8 = 8;
+const SERVICE_TYPE: u8 = 16;
+
+#[derive(Error, Debug)]
+pub(crate) enum MdnsError {
+    #[error("UDP socket error: {0}")]
+    Socket(#[from] UdpError),
+}
+
+// Answers A queries for HOSTNAME.local and DNS-SD queries for its http service, and announces
+// both after joining. There is no probing, a second unit on the LAN needs another HOSTNAME.
+pub(crate) struct Mdns {
+    ip: [u8; 4],
+    host: heapless::String<64>,
+    instance: heapless::String<64>,
+    announced: u8,
+    next_announcement: Instant,
+}
+
+impl Mdns {
+    pub(crate) fn new(ip: [u8; 4]) -> Self {
+        let mut host = heapless::String::new();
+        let mut instance = heapless::String::new();
+        for part in [HOSTNAME, ".local"] {
+            host.push_str(part).ok();
+        }
+        for part in [HOSTNAME, ".", SERVICE] {
+            instance.push_str(part).ok();
+        }
+        Self { ip, host, instance, announced: 0, next_announcement: Instant::now() }
+    }
+
+    // the socket `poll` answers on, bound and in the group
+    pub(crate) fn open<'s, 'a>(
+        stack: &'s Stack<'a, WifiDevice<'a>>,
+    ) -> Result<Datagram<'s, 'a>, MdnsError> {
+        let mut socket = Datagram::bind(stack, MDNS_PORT, 4, 1024)?;
+        socket.join(IpAddress::Ipv4(MDNS_GROUP))?;
+        Ok(socket)
+    }
+
+    // answers whatever queries arrived since the last call, never waits
+    pub(crate) fn poll(&mut self, socket: &mut Datagram<'_, '_>) {
+        let mut packet = [0u8; PACKET_SIZE];
+        while let Some((len, addr, port)) = socket.recv_from(&mut packet) {
+            // anything not from 5353 is a plain DNS resolver asking, it gets a plain DNS reply
+            let legacy = port != MDNS_PORT;
+            let Some((reply, unicast)) = self.answer(&packet[..len], legacy) else {
+                continue;
+            };
+            let (to, to_port) = if legacy || unicast {
+                (addr, port)
+            } else {
+                (IpAddress::Ipv4(MDNS_GROUP), MDNS_PORT)
+            };
+            if let Err(e) = socket.send_to(to, to_port, &reply) {
+                warn!("mDNS: reply not sent: {}", e);
+            }
+        }
+
+        if self.announced < ANNOUNCEMENTS && Instant::now() >= self.next_announcement {
+            let mut writer = Writer::new(0, false);
+            self.write_records(&mut writer, A | PTR | SRV | TXT, 0);
+            let group = IpAddress::Ipv4(MDNS_GROUP);
+            if let Err(e) = socket.send_to(group, MDNS_PORT, writer.finish()) {
+                warn!("mDNS: announcement not sent: {}", e);
+            }
+            self.announced += 1;
+            self.next_announcement = Instant::now() + Duration::from_secs(1);
+        }
+    }
+
+    // the reply to a query, if any question is about us, and whether it goes back unicast
+    fn answer(
+        &self,
+        packet: &[u8],
+        legacy: bool,
+    ) -> Option<(heapless::Vec<u8, PACKET_SIZE>, bool)> {
+        let header = packet.get(..12)?;
+        let id = u16::from_be_bytes([header[0], header[1]]);
+        let flags = u16::from_be_bytes([header[2], header[3]]);
+        let questions = u16::from_be_bytes([header[4], header[5]]);
+        if flags & 0x8000 != 0 {
+            return None;
+        }
+
+        let mut answers = 0u8;
+        let mut unicast = true;
+        let mut pos = 12;
+        for _ in 0..questions {
+            let mut name = heapless::String::<128>::new();
+            pos = read_name(packet, pos, &mut name)?;
+            let fields = packet.get(pos..pos + 4)?;
+            let qtype = u16::from_be_bytes([fields[0], fields[1]]);
+            let qclass = u16::from_be_bytes([fields[2], fields[3]]);
+            pos += 4;
+
+            let wanted = |t: u16| qtype == t || qtype == TYPE_ANY;
+            let matched = if name == self.host.as_str() {
+                if wanted(TYPE_A) { A } else { 0 }
+            } else if name == SERVICE {
+                if wanted(TYPE_PTR) { PTR } else { 0 }
+            } else if name == self.instance.as_str() {
+                (if wanted(TYPE_SRV) { SRV } else { 0 }) | (if wanted(TYPE_TXT) { TXT } else { 0 })
+            } else if name == SERVICE_TYPES {
+                if wanted(TYPE_PTR) { SERVICE_TYPE } else { 0 }
+            } else {
+                0
+            };
+            if matched != 0 {
+                answers |= matched;
+                unicast &= qclass & CLASS_TOP_BIT != 0;
+            }
+        }
+        if answers == 0 {
+            return None;
+        }
+
+        // a PTR is only useful with the SRV, TXT and address it leads to, and a SRV with the
+        // address of its target
+        let mut additional = 0;
+        if answers & PTR != 0 {
+            additional |= SRV | TXT | A;
+        }
+        if answers & SRV != 0 {
+            additional |= A;
+        }
+        let mut writer = Writer::new(if legacy { id } else { 0 }, legacy);
+        self.write_records(&mut writer, answers, additional & !answers);
+        Some((writer.finish().iter().copied().collect(), unicast))
+    }
+
+    fn write_records(&self, writer: &mut Writer, answers: u8, additional: u8) {
+        for (section, records) in [(Section::Answer, answers), (Section::Additional, additional)] {
+            if records & A != 0 {
+                writer.record(section, &self.host, TYPE_A, true, HOST_TTL, |w| w.bytes(&self.ip));
+            }
+            if records & PTR != 0 {
+                writer.record(section, SERVICE, TYPE_PTR, false, SERVICE_TTL, |w| {
+                    w.name(&self.instance)
+                });
+            }
+            if records & SRV != 0 {
+                writer.record(section, &self.instance, TYPE_SRV, true, HOST_TTL, |w| {
+                    // priority, weight, port, target
+                    w.bytes(&[0, 0, 0, 0]);
+                    w.bytes(&HTTP_PORT.to_be_bytes());
+                    w.name(&self.host);
+                });
+            }
+            if records & TXT != 0 {
+                writer.record(section, &self.instance, TYPE_TXT, true, SERVICE_TTL, |w| {
+                    let entry = b"path=/";
+                    w.bytes(&[entry.len() as u8]);
+                    w.bytes(entry);
+                });
+            }
+            if records & SERVICE_TYPE != 0 {
+                writer.record(section, SERVICE_TYPES, TYPE_PTR, false, SERVICE_TTL, |w| {
+                    w.name(SERVICE)
+                });
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Section {
+    Answer,
+    Additional,
+}
+
+// response packets without name compression, everything we send fits comfortably anyway
+struct Writer {
+    buf: heapless::Vec<u8, PACKET_SIZE>,
+    // legacy unicast replies must not set the cache flush bit
+    legacy: bool,
+}
+
+impl Writer {
+    fn new(id: u16, legacy: bool) -> Self {
+        let mut buf = heapless::Vec::new();
+        buf.extend_from_slice(&id.to_be_bytes()).ok();
+        buf.extend_from_slice(&FLAGS_RESPONSE.to_be_bytes()).ok();
+        buf.extend_from_slice(&[0; 8]).ok();
+        Self { buf, legacy }
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes).ok();
+    }
+
+    fn name(&mut self, name: &str) {
+        for label in name.split('.') {
+            self.bytes(&[label.len() as u8]);
+            self.bytes(label.as_bytes());
+        }
+        self.bytes(&[0]);
+    }
+
+    fn record(
+        &mut self,
+        section: Section,
+        name: &str,
+        rtype: u16,
+        unique: bool,
+        ttl: u32,
+        rdata: impl FnOnce(&mut Self),
+    ) {
+        self.name(name);
+        let class = if unique && !self.legacy { CLASS_IN | CLASS_TOP_BIT } else { CLASS_IN };
+        self.bytes(&rtype.to_be_bytes());
+        self.bytes(&class.to_be_bytes());
+        self.bytes(&ttl.to_be_bytes());
+        let len_at = self.buf.len();
+        self.bytes(&[0, 0]);
+        rdata(self);
+        let len = (self.buf.len().saturating_sub(len_at + 2)) as u16;
+        if let Some(slot) = self.buf.get_mut(len_at..len_at + 2) {
+            slot.copy_from_slice(&len.to_be_bytes());
+        }
+
+        // ANCOUNT at 6, ARCOUNT at 10
+        let at = match section {
+            Section::Answer => 6,
+            Section::Additional => 10,
+        };
+        let count = u16::from_be_bytes([self.buf[at], self.buf[at + 1]]) + 1;
+        self.buf[at..at + 2].copy_from_slice(&count.to_be_bytes());
+    }
+
+    fn finish(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+// lowercased dotted name at `pos`, returns where the name ends in the packet; follows
+// compression pointers, but only a few to stay clear of loops
+fn read_name(packet: &[u8], mut pos: usize, out: &mut heapless::String<128>) -> Option<usize> {
+    let mut end = None;
+    let mut jumps = 0;
+    loop {
+        let len = *packet.get(pos)? as usize;
+        if len == 0 {
+            return Some(end.unwrap_or(pos + 1));
+        }
+        if len & 0xC0 == 0xC0 {
+            jumps += 1;
+            if jumps > 8 {
+                return None;
+            }
+            end.get_or_insert(pos + 2);
+            pos = ((len & 0x3F) << 8) | *packet.get(pos + 1)? as usize;
+            continue;
+        }
+        let label = packet.get(pos + 1..pos + 1 + len)?;
+        if !out.is_empty() {
+            out.push('.').ok()?;
+        }
+        for &b in label {
+            out.push(b.to_ascii_lowercase() as char).ok()?;
+        }
+        pos += 1 + len;
+    }
+}
@@ -0,0 +1,138 @@
+// Offline development and demos without a backend or an access point (`mock-server`
+// feature). The network core never joins WiFi; the http clients are answered from fixtures
+// on the card and the websocket plays canned frames, so every page that shows server data
+// can be worked on at a desk. MOCK/ROUTES.TXT maps requests to files in MOCK/, one per line:
+// `GET /api/Tags 200 TAGS.JSN`, `-` for no body; a route with a query string only matches
+// that query, one without matches any. Requests without a line get a 404. MOCK/WS.TXT holds
+// frames, one per line, delivered one every FRAME_SECS and then from the top again.
+use alloc::{
+    format,
+    rc::Rc,
+    string::{String, ToString},
+    vec::Vec,
+};
+use esp_hal::time::{Duration, Instant};
+use log::{info, warn};
+use thiserror::Error;
+
+use crate::sdcard::{DirEntryInfo, SdCardError, SdStorage};
+
+const DIR: &str = "MOCK";
+const ROUTES_FILE: &str = "MOCK/ROUTES.TXT";
+const FRAMES_FILE: &str = "MOCK/WS.TXT";
+const MAX_ROUTES: usize = 32;
+// the fixtures stay in RAM, bigger bodies are left out
+const MAX_BODY: u32 = 16 * 1024;
+const FRAME_SECS: u64 = 10;
+
+#[derive(Error, Debug)]
+pub(crate) enum MockError {
+    #[error("expected <method> <route> <status> <file|->")]
+    Malformed,
+    #[error("{0} is over {MAX_BODY} bytes")]
+    TooLarge(String),
+    #[error("Card: {0}")]
+    Card(#[from] SdCardError),
+}
+
+struct Fixture {
+    method: String,
+    route: String,
+    status: u16,
+    body: Vec<u8>,
+}
+
+#[derive(Default)]
+pub(crate) struct Fixtures {
+    routes: Vec<Fixture>,
+    frames: Vec<String>,
+}
+
+impl Fixtures {
+    // the raw response a server would have sent
+    pub(crate) fn respond(&self, method: &str, route: &str) -> Vec<u8> {
+        let path = route.split('?').next().unwrap_or(route);
+        let matching = |route: &str| {
+            self.routes.iter().find(move |f| f.method == method && f.route == route)
+        };
+        let (status, body) = matching(route)
+            .or_else(|| matching(path))
+            .map_or((404, &[][..]), |f| (f.status, f.body.as_slice()));
+        let mut response = format!(
+            "HTTP/1.1 {} Mock\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            status,
+            body.len()
+        )
+        .into_bytes();
+        response.extend_from_slice(body);
+        response
+    }
+}
+
+// what is on the card, empty without one
+pub(crate) fn load(sd: &SdStorage<'_>) -> Fixtures {
+    let mut fixtures = Fixtures::default();
+    let sizes = sd.list_dir(DIR).unwrap_or_default();
+    if let Ok(file) = sd.read_config(ROUTES_FILE) {
+        for line in file.lines().take(MAX_ROUTES) {
+            match parse_route(sd, &sizes, line) {
+                Ok(fixture) => fixtures.routes.push(fixture),
+                Err(e) => warn!("Mock: {}: {}", line, e),
+            }
+        }
+    }
+    if let Ok(file) = sd.read_config(FRAMES_FILE) {
+        fixtures.frames = file.lines().map(String::from).collect();
+    }
+    info!("Mock: {} routes, {} ws frames", fixtures.routes.len(), fixtures.frames.len());
+    fixtures
+}
+
+fn parse_route(
+    sd: &SdStorage<'_>,
+    sizes: &[DirEntryInfo],
+    line: &str,
+) -> Result<Fixture, MockError> {
+    let mut fields = line.split_whitespace();
+    let (Some(method), Some(route), Some(status), Some(file), None) =
+        (fields.next(), fields.next(), fields.next(), fields.next(), fields.next())
+    else {
+        return Err(MockError::Malformed);
+    };
+    let status = status.parse().map_err(|_| MockError::Malformed)?;
+    let body = if file == "-" {
+        Vec::new()
+    } else {
+        let size = sizes.iter().find(|entry| entry.name == file).map_or(0, |entry| entry.size);
+        if size > MAX_BODY {
+            return Err(MockError::TooLarge(file.into()));
+        }
+        sd.read_to_vec(&format!("{}/{}", DIR, file))?
+    };
+    Ok(Fixture { method: method.to_string(), route: route.to_string(), status, body })
+}
+
+// the websocket's side of the fixtures
+pub(crate) struct MockWs {
+    fixtures: Rc<Fixtures>,
+    next: usize,
+    due: Instant,
+}
+
+impl MockWs {
+    pub(crate) fn new(fixtures: Rc<Fixtures>) -> Self {
+        Self { fixtures, next: 0, due: Instant::now() + Duration::from_secs(FRAME_SECS) }
+    }
+
+    // the next frame once it is due
+    pub(crate) fn recv(&mut self) -> Option<String> {
+        let frames = &self.fixtures.frames;
+        if frames.is_empty() || Instant::now() < self.due {
+            return None;
+        }
+        self.due = Instant::now() + Duration::from_secs(FRAME_SECS);
+        let frame = frames[self.next % frames.len()].clone();
+        self.next += 1;
+        Some(frame)
+    }
+}
@@ -0,0 +1,210 @@
+use esp_hal::{
+    gpio::{Input, InputConfig, InputPin, Pull},
+    time::{Duration, Instant},
+};
+
+use crate::touch_input::TouchInputResponse;
+
+const MAX_DEPTH: usize = 8;
+// a back swipe has to start this close to the left edge, a pull-down this close to the top...
+const EDGE_PX: i32 = 24;
+// ...travel at least this far right (down), and stay roughly straight
+const SWIPE_MIN_PX: i32 = 80;
+const SWIPE_MAX_DRIFT_PX: i32 = 60;
+const DEBOUNCE_MS: u64 = 30;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Page {
+    Home,
+    Settings,
+    WifiSetup,
+    About,
+    NowPlaying,
+    Agenda,
+    News,
+    Chat,
+    Timers,
+    Stopwatch,
+    Gpio,
+    Outputs,
+    Jog,
+    Sensors,
+    Energy,
+    Script,
+    Launcher,
+    Color,
+    Reliability,
+    Network,
+}
+
+impl Page {
+    // the names the http server and the ui use, matching the slint enum
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "home" => Page::Home,
+            "settings" => Page::Settings,
+            "wifi-setup" => Page::WifiSetup,
+            "about" => Page::About,
+            "now-playing" => Page::NowPlaying,
+            "agenda" => Page::Agenda,
+            "news" => Page::News,
+            "chat" => Page::Chat,
+            "timers" => Page::Timers,
+            "stopwatch" => Page::Stopwatch,
+            "gpio" => Page::Gpio,
+            "outputs" => Page::Outputs,
+            "jog" => Page::Jog,
+            "sensors" => Page::Sensors,
+            "energy" => Page::Energy,
+            "script" => Page::Script,
+            "launcher" => Page::Launcher,
+            "color" => Page::Color,
+            "reliability" => Page::Reliability,
+            "network" => Page::Network,
+            _ => return None,
+        })
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Page::Home => "home",
+            Page::Settings => "settings",
+            Page::WifiSetup => "wifi-setup",
+            Page::About => "about",
+            Page::NowPlaying => "now-playing",
+            Page::Agenda => "agenda",
+            Page::News => "news",
+            Page::Chat => "chat",
+            Page::Timers => "timers",
+            Page::Stopwatch => "stopwatch",
+            Page::Gpio => "gpio",
+            Page::Outputs => "outputs",
+            Page::Jog => "jog",
+            Page::Sensors => "sensors",
+            Page::Energy => "energy",
+            Page::Script => "script",
+            Page::Launcher => "launcher",
+            Page::Color => "color",
+            Page::Reliability => "reliability",
+            Page::Network => "network",
+        }
+    }
+}
+
+// Stack of open pages with Home at the bottom. Only the top page exists in the UI, the others
+// are torn down and rebuilt when navigated back to, so deep stacks cost no RAM.
+pub(crate) struct Navigator {
+    stack: heapless::Vec<Page, MAX_DEPTH>,
+}
+
+impl Navigator {
+    pub(crate) fn new() -> Self {
+        let mut stack = heapless::Vec::new();
+        stack.push(Page::Home).ok();
+        Self { stack }
+    }
+
+    pub(crate) fn current(&self) -> Page {
+        *self.stack.last().unwrap_or(&Page::Home)
+    }
+
+    // a page already on the stack is returned to instead of opened twice
+    pub(crate) fn push(&mut self, page: Page) {
+        if let Some(i) = self.stack.iter().position(|p| *p == page) {
+            self.stack.truncate(i + 1);
+        } else if self.stack.push(page).is_err() {
+            // full, drop the oldest page above Home to make room
+            self.stack.remove(1);
+            self.stack.push(page).ok();
+        }
+    }
+
+    // false on Home, there is nothing to go back to
+    pub(crate) fn back(&mut self) -> bool {
+        if self.stack.len() <= 1 {
+            return false;
+        }
+        self.stack.pop();
+        true
+    }
+}
+
+// edge swipe to the right, the usual back gesture; taps at the edge still reach the UI
+pub(crate) struct BackSwipe {
+    start: Option<(i32, i32)>,
+}
+
+impl BackSwipe {
+    pub(crate) fn new() -> Self {
+        Self { start: None }
+    }
+
+    pub(crate) fn detect(&mut self, input: &TouchInputResponse) -> bool {
+        match *input {
+            TouchInputResponse::Pressed { x, y } => {
+                self.start = (x <= EDGE_PX).then_some((x, y));
+                false
+            }
+            TouchInputResponse::Released { x, y } => match self.start.take() {
+                Some((start_x, start_y)) => {
+                    x - start_x >= SWIPE_MIN_PX && (y - start_y).abs() <= SWIPE_MAX_DRIFT_PX
+                }
+                None => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+// a swipe down from the top edge, opens the quick settings
+pub(crate) struct PullDown {
+    start: Option<(i32, i32)>,
+}
+
+impl PullDown {
+    pub(crate) fn new() -> Self {
+        Self { start: None }
+    }
+
+    pub(crate) fn detect(&mut self, input: &TouchInputResponse) -> bool {
+        match *input {
+            TouchInputResponse::Pressed { x, y } => {
+                self.start = (y <= EDGE_PX).then_some((x, y));
+                false
+            }
+            TouchInputResponse::Released { x, y } => match self.start.take() {
+                Some((start_x, start_y)) => {
+                    y - start_y >= SWIPE_MIN_PX && (x - start_x).abs() <= SWIPE_MAX_DRIFT_PX
+                }
+                None => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+// active low push button (the BOOT button on most boards), reports each press once
+pub(crate) struct BackButton<'d> {
+    pin: Input<'d>,
+    pressed: bool,
+    changed_at: Instant,
+}
+
+impl<'d> BackButton<'d> {
+    pub(crate) fn new(pin: impl InputPin + 'd) -> Self {
+        let pin = Input::new(pin, InputConfig::default().with_pull(Pull::Up));
+        Self { pin, pressed: false, changed_at: Instant::now() }
+    }
+
+    pub(crate) fn pressed(&mut self) -> bool {
+        let down = self.pin.is_low();
+        if down == self.pressed
+            || self.changed_at.elapsed() < Duration::from_millis(DEBOUNCE_MS)
+        {
+            return false;
+        }
+        self.pressed = down;
+        self.changed_at = Instant::now();
+        down
+    }
+}
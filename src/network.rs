@@ -0,0 +1,671 @@
+// Everything that waits on the network, run on the APP core: WiFi, sntp, the http clients, the
+// websocket, mDNS and the http server. The UI core hears about results through `NetEvent`s and
+// asks for things with `NetCommand`s; requests to the http server are answered by the UI core
+// with `Reply`s, since only it knows the screen state and has the renderer.
+use alloc::{boxed::Box, format, rc::Rc, string::String, vec::Vec};
+use blocking_network_stack::ipv4::IpInfo;
+use core::{
+    cell::RefCell,
+    sync::atomic::{AtomicU32, Ordering},
+};
+use esp_hal::{
+    delay::Delay,
+    time::{Duration, Instant},
+};
+use log::{info, warn};
+use smoltcp::{iface::SocketStorage, wire::IpAddress};
+
+#[cfg(feature = "mock-server")]
+use crate::mock_server::Fixtures;
+use crate::{
+    auth::{self, DeviceEvent, RefreshToken},
+    calendar::{self, Agenda, CalendarSource},
+    chat::{ChatClient, ChatMessage},
+    config::StaticIp,
+    config_sync::{self, ConfigSource, RemoteConfig},
+    data_binding::{DataBindings, Tags},
+    diagnostics::{self, DiagnosticsError},
+    dual_core::{self, Receiver, Sender},
+    feed::{self, Feed, FeedSource},
+    http_client::{self, HttpClient, HttpRequestHandle, Method},
+    http_server::{self, Route},
+    mdns::Mdns,
+    now_playing::{NowPlayingEvent, Player, PlayerCommand},
+    outbox::{self, Delivery},
+    ping::{self, PingError, PingHost, PingReport},
+    scheduler::Scheduler,
+    signal::{self, SignalMonitor},
+    sntp::SntpClient,
+    socket_pool::{self, SocketPool, SocketUser},
+    udp,
+    wifi::{self, AccessPoint, Wifi, WifiError},
+    ws_client::WsClient,
+    ws_session::{Received, WsSession},
+};
+
+const SNTP_RESYNC_SECS: u64 = 3600;
+const SNTP_RETRY_SECS: u64 = 60;
+const TAGS_ROUTE: &str = "/api/Tags";
+const TAGS_REFRESH_SECS: u64 = 60;
+const WS_PING_SECS: u64 = 30;
+// first reconnect attempt after the websocket dropped, backing off up to WS_PING_SECS
+const WS_RETRY_SECS: u64 = 5;
+const SOCKET_CHECK_SECS: u64 = 30;
+// a failed first connection is tried again after this, doubling up to WIFI_RETRY_MAX_SECS
+const WIFI_RETRY_SECS: u32 = 5;
+const WIFI_RETRY_MAX_SECS: u32 = 60;
+// big payloads are boxed, a slot is still a few hundred bytes; the backlog takes bursts
+const EVENT_SLOTS: usize = 8;
+const COMMAND_SLOTS: usize = 8;
+// a screenshot is a few hundred rows, the UI core renders the next band once there's room
+const REPLY_SLOTS: usize = 16;
+// the console's `get`, only the start of the body is printed
+const CONSOLE_GET_TIMEOUT_SECS: u64 = 10;
+const CONSOLE_GET_PREVIEW: usize = 512;
+// a script's `get`, the body it sees is cut to this many characters
+#[cfg(feature = "scripting")]
+const SCRIPT_GET_TIMEOUT_SECS: u64 = 10;
+#[cfg(feature = "scripting")]
+const SCRIPT_BODY_LIMIT: usize = 2048;
+
+// milliseconds since boot at the start of the loop's last pass, 0 until it first runs; connecting
+// and waiting for an address come before, they can take as long as the AP does
+static HEARTBEAT: AtomicU32 = AtomicU32::new(0);
+
+// how long ago the network loop last came round, None before it started
+pub(crate) fn since_heartbeat() -> Option<Duration> {
+    let beat = HEARTBEAT.load(Ordering::Relaxed);
+    let now = Instant::now().duration_since_epoch().as_millis() as u32;
+    (beat != 0).then(|| Duration::from_millis(now.wrapping_sub(beat) as u64))
+}
+
+// what the loop runs on an interval rather than on every pass
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Job {
+    ClockSync,
+    // pings the websocket, or reconnects it once it dropped
+    WsKeepalive,
+    SignalSample,
+    // looks for socket buffers requests never gave back
+    SocketCheck,
+}
+
+// network core -> UI core
+pub(crate) enum NetEvent {
+    Ip(IpInfo),
+    // connecting failed, it is tried again after a while
+    WifiFailed(WifiError),
+    // signal bars, 0..=4, when they changed
+    Signal(u8),
+    // the link dropped or came back after the first IP
+    Link(bool),
+    ClockSynced(u32),
+    // frames the chat doesn't understand, for the event hooks
+    WsMessage(String),
+    Chat { history: Vec<ChatMessage>, incoming: Option<ChatMessage> },
+    Notify(&'static str),
+    // answered with `UiLink::respond` or a streamed reply, the server takes nothing else until
+    // then
+    Request(Route),
+    Device(DeviceEvent),
+    Tags(Box<Tags>),
+    Agenda(Box<Agenda>),
+    Feed(Box<Feed>),
+    // a changed device config document, for the UI core to check and apply
+    Config(Box<RemoteConfig>),
+    NowPlaying(NowPlayingEvent),
+    // how the outbox entry's delivery went
+    Posted(Delivery),
+    // status line for the diagnostics upload
+    Exported(&'static str),
+    // the console's `get`: status, length and the start of the body, or what went wrong
+    Fetched(String),
+    Pinged(Result<PingReport, PingError>),
+    // the WiFi page's scan, strongest first
+    Scanned(Result<Vec<AccessPoint>, WifiError>),
+    // status 0 when the request didn't get an answer
+    #[cfg(feature = "scripting")]
+    Response { name: String, status: u16, body: String },
+    // the radio is off, nothing is half sent
+    Suspended,
+}
+
+// UI core -> network core
+pub(crate) enum NetCommand {
+    Chat(String),
+    Player(PlayerCommand),
+    // an outbox entry, answered with `Posted`
+    Post { route: String, body: String },
+    // a rendered bundle, for units without a card
+    UploadDiagnostics(String),
+    // a route for the console's `get`, answered with `Fetched`
+    Get(String),
+    // answered with `Pinged`
+    Ping { host: PingHost, count: u8 },
+    // a script's `get`, answered with `Response` under the same name
+    #[cfg(feature = "scripting")]
+    Fetch { name: String, route: String },
+    // answered with `Scanned`
+    Scan,
+    // WiFi down for light sleep, answered with `Suspended`
+    Suspend,
+    Resume,
+}
+
+// an http server response in pieces, always ending with `End`
+pub(crate) enum Reply {
+    Head { status: &'static str, content_type: &'static str, len: usize },
+    Body(Vec<u8>),
+    End,
+}
+
+// what the network core needs from the settings and the card, read before it starts
+pub(crate) struct NetConfig {
+    pub(crate) server_host: &'static str,
+    pub(crate) server_ip: Option<IpAddress>,
+    pub(crate) ntp_address: IpAddress,
+    // AUTH.TXT's scheme line; the providers are Rc, so they are built over there
+    pub(crate) auth: Option<String>,
+    pub(crate) refresh_token: Option<RefreshToken>,
+    pub(crate) calendar_route: Option<&'static str>,
+    pub(crate) feed_route: Option<&'static str>,
+    // WS.TXT's topics for the websocket hello
+    pub(crate) ws_topics: Vec<String>,
+    // API.TXT's shared secret for the http server
+    pub(crate) api_token: Option<String>,
+    pub(crate) static_ip: Option<StaticIp>,
+    pub(crate) seed: u32,
+    // what answers the clients offline, from the card
+    #[cfg(feature = "mock-server")]
+    pub(crate) fixtures: Fixtures,
+}
+
+// the UI core's end
+pub(crate) struct UiLink {
+    events: Receiver<NetEvent, EVENT_SLOTS>,
+    commands: Sender<NetCommand, COMMAND_SLOTS>,
+    replies: Sender<Reply, REPLY_SLOTS>,
+}
+
+// the network core's end
+pub(crate) struct NetLink {
+    events: Sender<NetEvent, EVENT_SLOTS>,
+    commands: Receiver<NetCommand, COMMAND_SLOTS>,
+    replies: Receiver<Reply, REPLY_SLOTS>,
+}
+
+pub(crate) fn link() -> (UiLink, NetLink) {
+    let (event_tx, event_rx) = dual_core::channel();
+    let (command_tx, command_rx) = dual_core::channel();
+    let (reply_tx, reply_rx) = dual_core::channel();
+    (
+        UiLink { events: event_rx, commands: command_tx, replies: reply_tx },
+        NetLink { events: event_tx, commands: command_rx, replies: reply_rx },
+    )
+}
+
+impl UiLink {
+    pub(crate) fn recv(&mut self) -> Option<NetEvent> {
+        self.events.recv()
+    }
+
+    pub(crate) fn send(&mut self, command: NetCommand) {
+        self.commands.send(command);
+    }
+
+    // before deep sleep: the radio goes down cleanly, whatever else arrives meanwhile is dropped
+    pub(crate) fn suspend_blocking(&mut self, timeout: Duration) -> bool {
+        self.send(NetCommand::Suspend);
+        let started = Instant::now();
+        while started.elapsed() < timeout {
+            if let Some(NetEvent::Suspended) = self.recv() {
+                return true;
+            }
+        }
+        false
+    }
+
+    pub(crate) fn respond(
+        &mut self,
+        status: &'static str,
+        content_type: &'static str,
+        body: Vec<u8>,
+    ) {
+        self.replies.send(Reply::Head { status, content_type, len: body.len() });
+        self.replies.send(Reply::Body(body));
+        self.replies.send(Reply::End);
+    }
+
+    // a reply of `len` bytes produced in pieces over several passes, so the body never has to
+    // be in RAM as a whole: `stream_body` each piece once `stream_ready`, then `stream_end`
+    pub(crate) fn stream_head(
+        &mut self,
+        status: &'static str,
+        content_type: &'static str,
+        len: usize,
+    ) {
+        self.replies.send(Reply::Head { status, content_type, len });
+    }
+
+    // the network core has taken what came before and there's room for another piece
+    pub(crate) fn stream_ready(&mut self) -> bool {
+        self.replies.has_room()
+    }
+
+    pub(crate) fn stream_body(&mut self, bytes: Vec<u8>) {
+        self.replies.send(Reply::Body(bytes));
+    }
+
+    pub(crate) fn stream_end(&mut self) {
+        self.replies.send(Reply::End);
+    }
+
+    // once per pass, hands over what didn't fit before
+    pub(crate) fn flush(&mut self) {
+        self.commands.flush();
+        self.replies.flush();
+    }
+}
+
+// the APP core's loop, `wifi` is configured but not started yet
+pub(crate) fn run(mut wifi: Wifi<'static>, config: NetConfig, link: NetLink) -> ! {
+    let NetLink { events, mut commands, mut replies } = link;
+    // the data binding sinks send from inside `poll`
+    let events = Rc::new(RefCell::new(events));
+    let emit = |event: NetEvent| events.borrow_mut().send(event);
+
+    // connecting blocks, which is why it happens here and not before the UI comes up; offline
+    // the radio stays off and the stack only carries sockets that never open
+    let mut retry_secs = WIFI_RETRY_SECS;
+    while !cfg!(feature = "mock-server") {
+        let Err(e) = wifi.initialize() else {
+            break;
+        };
+        warn!("WiFi: {}, retrying in {} s", e, retry_secs);
+        emit(NetEvent::WifiFailed(e));
+        Delay::new().delay_millis(retry_secs * 1000);
+        retry_secs = (retry_secs * 2).min(WIFI_RETRY_MAX_SECS);
+    }
+    #[cfg(feature = "mock-server")]
+    let fixtures = Rc::new(config.fixtures);
+    // offline every client is answered from the fixtures, whatever server the settings name
+    let loopback = cfg!(feature = "mock-server").then_some(IpAddress::v4(127, 0, 0, 1));
+    let server_ip = config.server_ip.or(loopback);
+
+    // dhcp, the pool's TCP sockets, the datagram ones and ping's; the websocket and the http
+    // server stay open, requests borrow the rest
+    let mut sockets_buf: [SocketStorage; 2 + socket_pool::SLOTS + udp::SLOTS] =
+        Default::default();
+    let sockets = SocketPool::new();
+    let stack = Rc::new(wifi::build_stack(
+        wifi.take_sta(),
+        &mut sockets_buf,
+        || Instant::now().duration_since_epoch().as_millis(),
+        config.seed,
+        config.static_ip.as_ref(),
+    ));
+    let ip_info = if cfg!(feature = "mock-server") {
+        wifi::loopback_info()
+    } else {
+        let mut retry_secs = WIFI_RETRY_SECS;
+        loop {
+            match wifi::obtain_ip(&stack, config.static_ip.as_ref()) {
+                Ok(info) => break info,
+                Err(e) => {
+                    warn!("WiFi: {}, retrying in {} s", e, retry_secs);
+                    emit(NetEvent::WifiFailed(e));
+                    Delay::new().delay_millis(retry_secs * 1000);
+                    retry_secs = (retry_secs * 2).min(WIFI_RETRY_MAX_SECS);
+                }
+            }
+        }
+    };
+    let ip = ip_info.ip.octets();
+    let [a, b, c, d] = ip_info.subnet.gateway.octets();
+    let gateway = IpAddress::v4(a, b, c, d);
+    emit(NetEvent::Ip(ip_info));
+
+    let mut sntp = SntpClient::new(stack.clone(), config.ntp_address);
+    let mut jobs = Scheduler::new();
+    jobs.every(
+        Job::ClockSync,
+        Duration::from_secs(SNTP_RESYNC_SECS),
+        Duration::from_secs(SNTP_RETRY_SECS),
+    )
+    .every(
+        Job::WsKeepalive,
+        Duration::from_secs(WS_PING_SECS),
+        Duration::from_secs(WS_RETRY_SECS),
+    )
+    .every(
+        Job::SignalSample,
+        Duration::from_secs(signal::SAMPLE_SECS),
+        Duration::from_secs(signal::SAMPLE_SECS),
+    )
+    .every(
+        Job::SocketCheck,
+        Duration::from_secs(SOCKET_CHECK_SECS),
+        Duration::from_secs(SOCKET_CHECK_SECS),
+    );
+    let mut signal = SignalMonitor::new();
+
+    // every client consults the same provider, switching schemes is only a matter of AUTH.TXT
+    let auth = config
+        .auth
+        .as_deref()
+        .and_then(|line| auth::load(line, config.refresh_token.clone()));
+    let auth_provider = auth.as_ref().map(|auth| auth.provider.clone());
+    let device_auth = auth.and_then(|auth| auth.device);
+    let client = |ip: IpAddress| {
+        let http = HttpClient::new(stack.clone(), sockets.clone(), config.server_host, ip);
+        #[cfg(feature = "mock-server")]
+        let http = http.with_mock(fixtures.clone());
+        http
+    };
+    // the device flow talks to the token endpoints without credentials of its own
+    let mut device_auth_http = device_auth.as_ref().and(server_ip).map(&client);
+
+    let authorized_client =
+        || server_ip.map(|ip| client(ip).with_auth(auth_provider.clone()));
+    let mut data_http = authorized_client();
+    let mut diagnostics_http = authorized_client();
+    let webhook_http = authorized_client();
+    // the outbox entry being posted, read a little every pass so it doesn't hold up the loop
+    let mut posting: Option<HttpRequestHandle<'_, '_>> = None;
+
+    let mut bindings = DataBindings::new();
+    bindings.bind(TAGS_ROUTE, Duration::from_secs(TAGS_REFRESH_SECS), {
+        let events = events.clone();
+        move |tags: Tags| events.borrow_mut().send(NetEvent::Tags(Box::new(tags)))
+    });
+    bindings.bind_source(
+        ConfigSource::new({
+            let events = events.clone();
+            move |config| events.borrow_mut().send(NetEvent::Config(Box::new(config)))
+        }),
+        Duration::from_secs(config_sync::REFRESH_SECS),
+    );
+    if let Some(route) = config.calendar_route {
+        let events = events.clone();
+        let source = CalendarSource::new(route, move |agenda| {
+            events.borrow_mut().send(NetEvent::Agenda(Box::new(agenda)))
+        });
+        bindings.bind_source(source, Duration::from_secs(calendar::REFRESH_SECS));
+    }
+    if let Some(route) = config.feed_route {
+        let events = events.clone();
+        let source = FeedSource::new(route, move |feed| {
+            events.borrow_mut().send(NetEvent::Feed(Box::new(feed)))
+        });
+        bindings.bind_source(source, Duration::from_secs(feed::REFRESH_SECS));
+    }
+
+    // the media API needs credentials, without AUTH.TXT there is nothing to poll
+    let mut player = auth_provider.is_some().then(Player::new);
+
+    let ws = WsClient::new(config.server_host, server_ip.unwrap_or(IpAddress::v4(0, 0, 0, 0)));
+    #[cfg(feature = "mock-server")]
+    let ws = ws.with_mock(fixtures.clone());
+    let mut ws = ws.with_auth(auth_provider);
+    let mut session = WsSession::new(config.ws_topics);
+
+    // connected by the keepalive job, which also keeps retrying while a device waits for its
+    // token and can't open it yet; opened before any request, the pool has a pair for it
+    let mut socket = sockets.open(&stack, SocketUser::Ws).expect("no socket for the websocket");
+    let mut chat = ChatClient::new();
+
+    let mut mdns = Mdns::new(ip);
+    let mut mdns_socket = Mdns::open(&stack).inspect_err(|e| warn!("mDNS: {}", e)).ok();
+    let mut server_socket =
+        sockets.open(&stack, SocketUser::Server).expect("no socket for the http server");
+    let api_token = config.api_token.as_deref();
+    // a request went to the UI core and its reply isn't complete yet
+    let mut answering = false;
+    info!("HTTP server listening on port {}", http_server::SERVER_PORT);
+
+    let mut connected = true;
+    loop {
+        let beat = Instant::now().duration_since_epoch().as_millis() as u32;
+        HEARTBEAT.store(beat.max(1), Ordering::Relaxed);
+        let now_connected = cfg!(feature = "mock-server") || wifi.is_connected();
+        if now_connected != connected {
+            connected = now_connected;
+            signal.reset();
+            emit(NetEvent::Link(connected));
+        }
+
+        while let Some(command) = commands.recv() {
+            match command {
+                NetCommand::Chat(text) => {
+                    if !connected || !ws.is_connected() {
+                        emit(NetEvent::Notify("Chat: not connected"));
+                    } else if let Some(frame) = chat.send(&text) {
+                        ws.poll_send(&mut socket, Some(frame.as_bytes()));
+                        let history = chat.history().to_vec();
+                        emit(NetEvent::Chat { history, incoming: None });
+                    }
+                }
+                NetCommand::Player(command) => {
+                    if let (Some(player), Some(http)) = (player.as_mut(), data_http.as_mut()) {
+                        if let Err(e) = player.command(http, command) {
+                            warn!("Now playing: {}", e);
+                        }
+                    }
+                }
+                NetCommand::Post { route, body } => {
+                    let posted = {
+                        let events = events.clone();
+                        move |delivery| events.borrow_mut().send(NetEvent::Posted(delivery))
+                    };
+                    posting = webhook_http
+                        .as_ref()
+                        .filter(|_| connected)
+                        .and_then(|http| outbox::start_delivery(http, &route, &body, posted));
+                    if posting.is_none() {
+                        emit(NetEvent::Posted(Delivery::Failed));
+                    }
+                }
+                NetCommand::UploadDiagnostics(bundle) => {
+                    let result = match diagnostics_http.as_mut() {
+                        Some(http) => diagnostics::upload(&bundle, http),
+                        None => Err(DiagnosticsError::Upload("no server configured")),
+                    };
+                    let status = result.unwrap_or_else(|e| {
+                        warn!("Diagnostics: {}", e);
+                        "Diagnostics export failed"
+                    });
+                    emit(NetEvent::Exported(status));
+                }
+                NetCommand::Get(route) => {
+                    let reply = match data_http.as_mut().filter(|_| connected) {
+                        Some(http) => fetch_preview(http, &route),
+                        None => String::from("not connected"),
+                    };
+                    emit(NetEvent::Fetched(reply));
+                }
+                NetCommand::Ping { host, count } => {
+                    let target = match host {
+                        PingHost::Address(address) => Some(IpAddress::Ipv4(address)),
+                        PingHost::Gateway => Some(gateway),
+                        PingHost::Server => server_ip,
+                    };
+                    let report = match target {
+                        _ if !connected => Err(PingError::NotConnected),
+                        Some(target) => ping::ping(&stack, target, count),
+                        None => Err(PingError::NoServer),
+                    };
+                    emit(NetEvent::Pinged(report));
+                }
+                #[cfg(feature = "scripting")]
+                NetCommand::Fetch { name, route } => {
+                    let (status, body) = match data_http.as_mut().filter(|_| connected) {
+                        Some(http) => fetch_body(http, &route),
+                        None => (0, String::new()),
+                    };
+                    emit(NetEvent::Response { name, status, body });
+                }
+                NetCommand::Scan => emit(NetEvent::Scanned(wifi.scan())),
+                NetCommand::Suspend => {
+                    // the server sees the websocket close rather than time out
+                    socket.disconnect();
+                    if posting.take().is_some() {
+                        emit(NetEvent::Posted(Delivery::Failed));
+                    }
+                    wifi.suspend();
+                    emit(NetEvent::Suspended);
+                }
+                NetCommand::Resume => wifi.resume(),
+            }
+        }
+
+        if posting.as_mut().is_some_and(|request| !request.poll()) {
+            posting = None;
+        }
+
+        // also while the link is down, so the UI core's reply is never left half sent
+        while answering {
+            let Some(reply) = replies.recv() else {
+                break;
+            };
+            match reply {
+                Reply::Head { status, content_type, len } => {
+                    http_server::respond_head(&mut server_socket, status, content_type, len);
+                }
+                Reply::Body(bytes) => http_server::write_body(&mut server_socket, &bytes),
+                Reply::End => {
+                    http_server::finish(&mut server_socket);
+                    answering = false;
+                }
+            }
+        }
+
+        if connected {
+            if let Some(socket) = mdns_socket.as_mut() {
+                mdns.poll(socket);
+            }
+            if !answering {
+                if let Some(route) = http_server::accept(&mut server_socket, api_token) {
+                    answering = true;
+                    emit(NetEvent::Request(route));
+                }
+            }
+            let frame = ws.poll_recv(&mut socket);
+            if let Some(frame) = frame.filter(|f| session.receive(f) == Received::Message) {
+                match chat.receive(&frame) {
+                    Some(message) => {
+                        let incoming = Some(message.clone());
+                        let history = chat.history().to_vec();
+                        emit(NetEvent::Chat { history, incoming });
+                    }
+                    None => emit(NetEvent::WsMessage(frame)),
+                }
+            }
+            if let (Some(device), Some(http)) = (&device_auth, device_auth_http.as_mut()) {
+                match device.poll(http) {
+                    Ok(Some(event)) => emit(NetEvent::Device(event)),
+                    Ok(None) => {}
+                    Err(e) => warn!("Auth: {}", e),
+                }
+            }
+            if let Some(http) = data_http.as_mut() {
+                if let Err(e) = bindings.poll(http) {
+                    warn!("Data binding: {}", e);
+                }
+                if let Some(player) = player.as_mut() {
+                    match player.poll(http) {
+                        Ok(Some(event)) => emit(NetEvent::NowPlaying(event)),
+                        Ok(None) => {}
+                        Err(e) => warn!("Now playing: {}", e),
+                    }
+                }
+            }
+        }
+
+        if let Some(job) = connected.then(|| jobs.due()).flatten() {
+            let ok = match job {
+                // no time server to ask offline
+                Job::ClockSync if cfg!(feature = "mock-server") => false,
+                Job::ClockSync => match sntp.query(Duration::from_secs(3)) {
+                    Ok(unix_secs) => {
+                        emit(NetEvent::ClockSynced(unix_secs));
+                        true
+                    }
+                    Err(e) => {
+                        warn!("SNTP: {}", e);
+                        false
+                    }
+                },
+                Job::WsKeepalive if ws.is_connected() => {
+                    ws.ping(&mut socket).inspect_err(|e| warn!("WS: {}", e)).is_ok()
+                }
+                // the server gets its context back and replays what we missed
+                Job::WsKeepalive => {
+                    socket.disconnect();
+                    let opened =
+                        ws.connect(&mut socket).inspect_err(|e| warn!("WS: {}", e)).is_ok();
+                    if opened {
+                        ws.poll_send(&mut socket, Some(session.hello().as_bytes()));
+                    }
+                    opened
+                }
+                Job::SignalSample => match wifi.rssi() {
+                    Some(rssi) => {
+                        if let Some(bars) = signal.sample(rssi) {
+                            emit(NetEvent::Signal(bars));
+                        }
+                        true
+                    }
+                    None => false,
+                },
+                Job::SocketCheck => {
+                    let leaked = sockets.check();
+                    if leaked > 0 {
+                        let stats = sockets.stats();
+                        warn!(
+                            "Sockets: {} of {} in use, peak {}, {} times none free",
+                            stats.in_use,
+                            socket_pool::SLOTS,
+                            stats.peak,
+                            stats.exhausted
+                        );
+                    }
+                    leaked == 0
+                }
+            };
+            jobs.done(job, ok);
+        }
+        events.borrow_mut().flush();
+    }
+}
+
+// blocks this core for up to the timeout, the console is for bring-up and waits anyway
+fn fetch_preview(http: &mut HttpClient<'_>, route: &str) -> String {
+    let raw = match http.request(Method::Get, route, None, CONSOLE_GET_TIMEOUT_SECS) {
+        Ok(raw) => raw,
+        Err(e) => return format!("GET {}: {}", route, e),
+    };
+    let Some((status, body)) = http_client::parse_response(&raw) else {
+        return format!("GET {}: malformed response", route);
+    };
+    let cut = body.char_indices().nth(CONSOLE_GET_PREVIEW).map_or(body.len(), |(at, _)| at);
+    let more = if cut < body.len() { "\n..." } else { "" };
+    format!("{} {}, {} bytes\n{}{}", status, route, body.len(), &body[..cut], more)
+}
+
+#[cfg(feature = "scripting")]
+fn fetch_body(http: &mut HttpClient<'_>, route: &str) -> (u16, String) {
+    let raw = match http.request(Method::Get, route, None, SCRIPT_GET_TIMEOUT_SECS) {
+        Ok(raw) => raw,
+        Err(e) => {
+            warn!("Script: GET {}: {}", route, e);
+            return (0, String::new());
+        }
+    };
+    let Some((status, mut body)) = http_client::parse_response(&raw) else {
+        return (0, String::new());
+    };
+    if let Some((cut, _)) = body.char_indices().nth(SCRIPT_BODY_LIMIT) {
+        body.truncate(cut);
+    }
+    (status, body)
+}
@@ -0,0 +1,117 @@
+// Warmer colours at night. Between the configured hours the blue and some of the green are
+// pulled back through the colour tables, on top of the panel's own calibration; the shift
+// fades in over RAMP_MINUTES after the start and out over the ones before the end, so the
+// screen doesn't change all at once in front of someone. Without a synced clock it stays off.
+use alloc::{format, string::String};
+use esp_hal::time::Instant;
+
+use crate::clock;
+
+// per side, shorter windows fade in and out over half of them
+const RAMP_MINUTES: u16 = 20;
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+// local minutes since midnight, `end` before `start` runs past midnight
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct NightConfig {
+    pub(crate) enabled: bool,
+    pub(crate) start: u16,
+    pub(crate) end: u16,
+    // percent, 100 takes blue down to 30 % and green to 75 %
+    pub(crate) warmth: u8,
+}
+
+impl Default for NightConfig {
+    fn default() -> Self {
+        Self { enabled: false, start: 22 * 60, end: 7 * 60, warmth: 60 }
+    }
+}
+
+impl NightConfig {
+    pub(crate) fn encode(&self) -> [u8; 6] {
+        let [start_low, start_high] = self.start.to_le_bytes();
+        let [end_low, end_high] = self.end.to_le_bytes();
+        [self.enabled as u8, start_low, start_high, end_low, end_high, self.warmth]
+    }
+
+    pub(crate) fn decode(value: &[u8]) -> Option<Self> {
+        let [enabled, start_low, start_high, end_low, end_high, warmth] = *value else {
+            return None;
+        };
+        let start = u16::from_le_bytes([start_low, start_high]);
+        let end = u16::from_le_bytes([end_low, end_high]);
+        (start < MINUTES_PER_DAY && end < MINUTES_PER_DAY).then_some(Self {
+            enabled: enabled != 0,
+            start,
+            end,
+            warmth: warmth.min(100),
+        })
+    }
+
+    // `hh:mm-hh:mm`, for the colour page
+    pub(crate) fn hours(&self) -> String {
+        let time = |minute: u16| format!("{:02}:{:02}", minute / 60, minute % 60);
+        format!("{}-{}", time(self.start), time(self.end))
+    }
+
+    // 0..=warmth at this minute of the day
+    fn warmth_at(&self, minute: u16) -> u8 {
+        let length = (self.end + MINUTES_PER_DAY - self.start) % MINUTES_PER_DAY;
+        let into = (minute + MINUTES_PER_DAY - self.start) % MINUTES_PER_DAY;
+        if !self.enabled || length == 0 || into >= length {
+            return 0;
+        }
+        let ramp = RAMP_MINUTES.min(length / 2).max(1);
+        let edge = into.min(length - into).min(ramp);
+        (self.warmth as u32 * edge as u32 / ramp as u32) as u8
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct NightView {
+    pub(crate) enabled: bool,
+    pub(crate) hours: String,
+    pub(crate) active: bool,
+}
+
+pub(crate) struct NightMode {
+    config: NightConfig,
+    warmth: u8,
+    checked: Option<Instant>,
+}
+
+impl NightMode {
+    pub(crate) fn new(config: NightConfig) -> Self {
+        Self { config, warmth: 0, checked: None }
+    }
+
+    pub(crate) fn set_config(&mut self, config: NightConfig) {
+        self.config = config;
+        self.checked = None;
+    }
+
+    // what the colour tables are built with now
+    pub(crate) fn warmth(&self) -> u8 {
+        self.warmth
+    }
+
+    // the new warmth when it changed, checked once a second
+    pub(crate) fn poll(&mut self) -> Option<u8> {
+        if self.checked.is_some_and(|t| t.elapsed().as_secs() < 1) {
+            return None;
+        }
+        self.checked = Some(Instant::now());
+        let warmth = clock::local_now()
+            .map(|now| self.config.warmth_at(now.hour as u16 * 60 + now.minute as u16))
+            .unwrap_or(0);
+        (core::mem::replace(&mut self.warmth, warmth) != warmth).then_some(warmth)
+    }
+
+    pub(crate) fn view(&self) -> NightView {
+        NightView {
+            enabled: self.config.enabled,
+            hours: self.config.hours(),
+            active: self.warmth > 0,
+        }
+    }
+}
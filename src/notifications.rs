@@ -0,0 +1,28 @@
+use alloc::rc::Rc;
+use core::cell::Cell;
+use slint::SharedString;
+
+use crate::watch::{Watch, WatchReceiver};
+
+// banner messages for the UI; the sequence number makes a repeated message show up again
+#[derive(Clone)]
+pub(crate) struct Notifications {
+    banner: Watch<(u32, SharedString)>,
+    seq: Rc<Cell<u32>>,
+}
+
+impl Notifications {
+    pub(crate) fn new() -> Self {
+        Self { banner: Watch::new((0, SharedString::new())), seq: Rc::new(Cell::new(0)) }
+    }
+
+    pub(crate) fn subscribe(&self) -> WatchReceiver<(u32, SharedString)> {
+        self.banner.subscribe()
+    }
+
+    pub(crate) fn notify(&self, text: &str) {
+        let seq = self.seq.get().wrapping_add(1);
+        self.seq.set(seq);
+        self.banner.send((seq, text.into()));
+    }
+}
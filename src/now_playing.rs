@@ -0,0 +1,260 @@
+use alloc::{format, vec::Vec};
+use esp_hal::time::{Duration, Instant};
+use serde::{Deserialize, Serialize, de::IgnoredAny};
+use slint::ComponentHandle;
+use thiserror::Error;
+
+use crate::{
+    MainWindow, NowPlaying,
+    data_binding::{first_n, truncated},
+    http_client::{self, HttpClient, JsonError, Method},
+};
+
+// Spotify Web API player routes; the server is expected to be (or proxy to) api.spotify.com,
+// with the bearer token coming from the device flow in AUTH.TXT
+const PLAYER_ROUTE: &str = "/v1/me/player";
+const CURRENTLY_PLAYING_ROUTE: &str = "/v1/me/player/currently-playing";
+// the art is JPEG upstream, the server has to transcode it to a small BMP
+const ART_ROUTE: &str = "/api/art";
+const ART_SIZE: u32 = 96;
+const PLAYING_POLL_SECS: u64 = 5;
+const IDLE_POLL_SECS: u64 = 15;
+const TIMEOUT_SECS: u64 = 10;
+
+#[derive(Error, Debug)]
+pub(crate) enum NowPlayingError {
+    #[error("Player state: {0}")]
+    State(JsonError),
+    #[error("Player command failed: {0}")]
+    Command(JsonError),
+    #[error("Player command rejected with {0}")]
+    Rejected(u16),
+    #[error("Album art request failed: {0}")]
+    Art(&'static str),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum PlayerCommand {
+    Play,
+    Pause,
+    Next,
+    Previous,
+}
+
+impl PlayerCommand {
+    fn action(&self) -> &'static str {
+        match self {
+            PlayerCommand::Play => "/play",
+            PlayerCommand::Pause => "/pause",
+            PlayerCommand::Next => "/next",
+            PlayerCommand::Previous => "/previous",
+        }
+    }
+}
+
+// every player command takes an optional JSON body; resuming sends the position on screen so
+// playback picks up where it paused, the rest send `{}`
+#[derive(Default, Serialize)]
+struct CommandBody {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    position_ms: Option<u32>,
+}
+
+// an empty title means nothing is playing
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Track {
+    pub(crate) title: heapless::String<64>,
+    pub(crate) artist: heapless::String<48>,
+    pub(crate) album: heapless::String<48>,
+    pub(crate) playing: bool,
+    pub(crate) progress_ms: u32,
+    pub(crate) duration_ms: u32,
+}
+
+pub(crate) enum NowPlayingEvent {
+    Track(Track),
+    // sent after the track it belongs to, still encoded: slint images stay on the UI core.
+    // empty clears it
+    Art(Vec<u8>),
+}
+
+#[derive(Deserialize)]
+struct Playback {
+    #[serde(default)]
+    is_playing: bool,
+    #[serde(default)]
+    progress_ms: Option<u32>,
+    #[serde(default)]
+    item: Option<Item>,
+}
+
+#[derive(Deserialize)]
+struct Item {
+    #[serde(deserialize_with = "truncated")]
+    name: heapless::String<64>,
+    #[serde(default)]
+    duration_ms: u32,
+    #[serde(default, deserialize_with = "first_n")]
+    artists: heapless::Vec<Named, 1>,
+    #[serde(default)]
+    album: Option<Album>,
+}
+
+#[derive(Deserialize)]
+struct Named {
+    #[serde(deserialize_with = "truncated")]
+    name: heapless::String<48>,
+}
+
+#[derive(Deserialize)]
+struct Album {
+    #[serde(deserialize_with = "truncated")]
+    name: heapless::String<48>,
+    // largest first, the smallest is plenty for this screen
+    #[serde(default, deserialize_with = "first_n")]
+    images: heapless::Vec<AlbumImage, 3>,
+}
+
+#[derive(Deserialize)]
+struct AlbumImage {
+    url: heapless::String<128>,
+}
+
+impl Playback {
+    fn into_track(self) -> (Track, heapless::String<128>) {
+        let Some(item) = self.item else {
+            return Default::default();
+        };
+        let (album, art_url) = match item.album {
+            Some(album) => {
+                let art_url = album.images.last().map(|i| i.url.clone()).unwrap_or_default();
+                (album.name, art_url)
+            }
+            None => Default::default(),
+        };
+        let track = Track {
+            title: item.name,
+            artist: item.artists.first().map(|a| a.name.clone()).unwrap_or_default(),
+            album,
+            playing: self.is_playing,
+            progress_ms: self.progress_ms.unwrap_or(0),
+            duration_ms: item.duration_ms,
+        };
+        (track, art_url)
+    }
+}
+
+// Polls the player state faster while something plays, and fetches album art one call after
+// the track changed so a single loop iteration never blocks for both.
+pub(crate) struct Player {
+    track: Track,
+    art_url: heapless::String<128>,
+    art_pending: bool,
+    next_poll: Instant,
+}
+
+impl Player {
+    pub(crate) fn new() -> Self {
+        Self {
+            track: Track::default(),
+            art_url: heapless::String::new(),
+            art_pending: false,
+            next_poll: Instant::now(),
+        }
+    }
+
+    pub(crate) fn poll(
+        &mut self,
+        http: &mut HttpClient<'_>,
+    ) -> Result<Option<NowPlayingEvent>, NowPlayingError> {
+        if self.art_pending {
+            self.art_pending = false;
+            let art = if self.art_url.is_empty() {
+                Vec::new()
+            } else {
+                fetch_art(http, &self.art_url)?
+            };
+            return Ok(Some(NowPlayingEvent::Art(art)));
+        }
+        if Instant::now() < self.next_poll {
+            return Ok(None);
+        }
+
+        let result = http.get_json::<Option<Playback>>(CURRENTLY_PLAYING_ROUTE, TIMEOUT_SECS);
+        let wait = if self.track.playing { PLAYING_POLL_SECS } else { IDLE_POLL_SECS };
+        self.next_poll = Instant::now() + Duration::from_secs(wait);
+        // 204 with no body when nothing is playing
+        let playback = result.map_err(NowPlayingError::State)?;
+
+        let (track, art_url) = playback.map(Playback::into_track).unwrap_or_default();
+
+        if art_url != self.art_url {
+            self.art_url = art_url;
+            self.art_pending = true;
+        }
+        if track == self.track {
+            return Ok(None);
+        }
+        self.track = track.clone();
+        Ok(Some(NowPlayingEvent::Track(track)))
+    }
+
+    pub(crate) fn command(
+        &mut self,
+        http: &mut HttpClient<'_>,
+        command: PlayerCommand,
+    ) -> Result<(), NowPlayingError> {
+        let route = format!("{}{}", PLAYER_ROUTE, command.action());
+        let result = match command {
+            PlayerCommand::Play => {
+                let body = CommandBody { position_ms: Some(self.track.progress_ms) };
+                http.put_json::<_, IgnoredAny>(&route, &body, TIMEOUT_SECS)
+            }
+            PlayerCommand::Pause => {
+                http.put_json::<_, IgnoredAny>(&route, &CommandBody::default(), TIMEOUT_SECS)
+            }
+            PlayerCommand::Next | PlayerCommand::Previous => {
+                http.post_json::<_, IgnoredAny>(&route, &CommandBody::default(), TIMEOUT_SECS)
+            }
+        };
+        match result {
+            Ok(_) => {}
+            Err(JsonError::Status(status)) => return Err(NowPlayingError::Rejected(status)),
+            Err(e) => return Err(NowPlayingError::Command(e)),
+        }
+        // the API applies commands asynchronously, look again shortly after
+        self.next_poll = Instant::now() + Duration::from_secs(1);
+        Ok(())
+    }
+}
+
+// the launcher's update for the page: the state is only polled every 5 s, in between the bar
+// moves on by itself, the next poll puts it right again
+pub(crate) fn advance(ui: &MainWindow) {
+    let player = ui.global::<NowPlaying>();
+    let duration_ms = player.get_duration_ms();
+    if !player.get_playing() || duration_ms <= 0 {
+        return;
+    }
+    let progress = player.get_progress() + 1000.0 / duration_ms as f32;
+    player.set_progress(progress.min(1.0));
+}
+
+fn fetch_art(http: &mut HttpClient<'_>, url: &str) -> Result<Vec<u8>, NowPlayingError> {
+    let mut route = format!("{}?size={}&url=", ART_ROUTE, ART_SIZE);
+    http_client::push_form_encoded(&mut route, url);
+    let mut body = Vec::new();
+    let response = http
+        .request_streaming(Method::Get, &route, "", TIMEOUT_SECS, |chunk| {
+            body.extend_from_slice(chunk)
+        })
+        .map_err(NowPlayingError::Art)?;
+    if !(200..300).contains(&response.status) {
+        return Err(NowPlayingError::Art("unexpected status"));
+    }
+    // half an image doesn't decode
+    if !response.complete {
+        return Err(NowPlayingError::Art("truncated response"));
+    }
+    Ok(body)
+}
@@ -0,0 +1,186 @@
+// Bit-banged 1-Wire master on an open drain pad and the DS18B20 on top of it. Every slot is
+// timed with interrupts off, a few microseconds late is already a different bit. The pad needs
+// a 4.7k pull-up to 3.3V, the internal one is too weak past a short cable.
+use alloc::vec::Vec;
+use esp_hal::{delay::Delay, gpio::Flex};
+use thiserror::Error;
+
+const SEARCH_ROM: u8 = 0xF0;
+const MATCH_ROM: u8 = 0x55;
+const SKIP_ROM: u8 = 0xCC;
+const CONVERT_T: u8 = 0x44;
+const READ_SCRATCHPAD: u8 = 0xBE;
+const DS18B20_FAMILY: u8 = 0x28;
+// a bus this long is a wiring fault, not a sensor array
+const MAX_DEVICES: usize = 8;
+// at the default 12 bit resolution
+pub(crate) const CONVERSION_MS: u64 = 750;
+
+#[derive(Error, Debug)]
+pub(crate) enum OneWireError {
+    #[error("No presence pulse, nothing on the bus")]
+    NoPresence,
+    #[error("Bus held low")]
+    Shorted,
+    #[error("CRC mismatch")]
+    Crc,
+}
+
+pub(crate) type Rom = [u8; 8];
+
+pub(crate) struct OneWire<'a, 'd> {
+    pin: &'a mut Flex<'d>,
+    delay: Delay,
+}
+
+impl<'a, 'd> OneWire<'a, 'd> {
+    // the pad is expected to be open drain and released, see `GpioPins::configure`
+    pub(crate) fn new(pin: &'a mut Flex<'d>) -> Self {
+        Self { pin, delay: Delay::new() }
+    }
+
+    // reset pulse, then listens for a device pulling the bus low
+    pub(crate) fn reset(&mut self) -> Result<(), OneWireError> {
+        if self.pin.is_low() {
+            return Err(OneWireError::Shorted);
+        }
+        let present = critical_section::with(|_| {
+            self.pin.set_low();
+            self.delay.delay_micros(480);
+            self.pin.set_high();
+            self.delay.delay_micros(70);
+            let present = self.pin.is_low();
+            self.delay.delay_micros(410);
+            present
+        });
+        if present { Ok(()) } else { Err(OneWireError::NoPresence) }
+    }
+
+    fn write_bit(&mut self, bit: bool) {
+        critical_section::with(|_| {
+            self.pin.set_low();
+            // a short low is a 1, holding it through the slot a 0
+            let (low, high) = if bit { (6, 64) } else { (60, 10) };
+            self.delay.delay_micros(low);
+            self.pin.set_high();
+            self.delay.delay_micros(high);
+        });
+    }
+
+    fn read_bit(&mut self) -> bool {
+        critical_section::with(|_| {
+            self.pin.set_low();
+            self.delay.delay_micros(3);
+            self.pin.set_high();
+            self.delay.delay_micros(10);
+            let bit = self.pin.is_high();
+            self.delay.delay_micros(53);
+            bit
+        })
+    }
+
+    pub(crate) fn write_byte(&mut self, byte: u8) {
+        for i in 0..8 {
+            self.write_bit(byte >> i & 1 != 0);
+        }
+    }
+
+    pub(crate) fn read_byte(&mut self) -> u8 {
+        (0..8).fold(0, |byte, i| byte | (self.read_bit() as u8) << i)
+    }
+
+    // every ROM code on the bus (Maxim AN187), each checked against its CRC
+    pub(crate) fn search(&mut self) -> Result<Vec<Rom>, OneWireError> {
+        let mut roms = Vec::new();
+        let mut rom = [0u8; 8];
+        let mut last_discrepancy = 0;
+        loop {
+            self.reset()?;
+            self.write_byte(SEARCH_ROM);
+            let mut discrepancy = 0;
+            for bit_index in 1..=64 {
+                let (byte, mask) = ((bit_index - 1) / 8, 1 << ((bit_index - 1) % 8));
+                let (bit, complement) = (self.read_bit(), self.read_bit());
+                let direction = match (bit, complement) {
+                    // devices left the bus mid-search
+                    (true, true) => return Err(OneWireError::NoPresence),
+                    (true, false) => true,
+                    (false, true) => false,
+                    // both values present: follow the earlier choice, then take 1, then 0
+                    (false, false) => {
+                        let direction = if bit_index < last_discrepancy {
+                            rom[byte] & mask != 0
+                        } else {
+                            bit_index == last_discrepancy
+                        };
+                        if !direction {
+                            discrepancy = bit_index;
+                        }
+                        direction
+                    }
+                };
+                if direction {
+                    rom[byte] |= mask;
+                } else {
+                    rom[byte] &= !mask;
+                }
+                self.write_bit(direction);
+            }
+            if crc8(&rom[..7]) != rom[7] {
+                return Err(OneWireError::Crc);
+            }
+            roms.push(rom);
+            last_discrepancy = discrepancy;
+            if last_discrepancy == 0 || roms.len() == MAX_DEVICES {
+                return Ok(roms);
+            }
+        }
+    }
+
+    // every DS18B20 on the bus starts converting at once
+    pub(crate) fn start_conversion(&mut self) -> Result<(), OneWireError> {
+        self.reset()?;
+        self.write_byte(SKIP_ROM);
+        self.write_byte(CONVERT_T);
+        Ok(())
+    }
+
+    // degrees C from the scratchpad of a finished conversion
+    pub(crate) fn read_temperature(&mut self, rom: &Rom) -> Result<f32, OneWireError> {
+        self.reset()?;
+        self.write_byte(MATCH_ROM);
+        for byte in rom {
+            self.write_byte(*byte);
+        }
+        self.write_byte(READ_SCRATCHPAD);
+        let mut scratchpad = [0u8; 9];
+        for byte in scratchpad.iter_mut() {
+            *byte = self.read_byte();
+        }
+        // an unplugged sensor reads all ones, which fails the CRC too
+        if crc8(&scratchpad[..8]) != scratchpad[8] {
+            return Err(OneWireError::Crc);
+        }
+        Ok(i16::from_le_bytes([scratchpad[0], scratchpad[1]]) as f32 / 16.0)
+    }
+}
+
+pub(crate) fn is_ds18b20(rom: &Rom) -> bool {
+    rom[0] == DS18B20_FAMILY
+}
+
+// Dallas/Maxim CRC-8, x^8 + x^5 + x^4 + 1, LSB first
+pub(crate) fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0, |mut crc, &byte| {
+        let mut byte = byte;
+        for _ in 0..8 {
+            let mix = (crc ^ byte) & 1;
+            crc >>= 1;
+            if mix != 0 {
+                crc ^= 0x8C;
+            }
+            byte >>= 1;
+        }
+        crc
+    })
+}
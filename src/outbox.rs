@@ -0,0 +1,185 @@
+use alloc::{collections::VecDeque, string::String, vec::Vec};
+use esp_hal::time::{Duration, Instant};
+use log::warn;
+
+use crate::{
+    http_client::{HttpClient, HttpRequestHandle, Method},
+    sdcard::{SdCardError, SdStorage},
+};
+
+pub(crate) const OUTBOX_FILE: &str = "OUTBOX.TXT";
+const CAPACITY: usize = 16;
+const FIRST_RETRY_SECS: u64 = 5;
+const MAX_RETRY_SECS: u64 = 300;
+
+// how a delivery went
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Delivery {
+    // 2xx
+    Accepted,
+    // any other status the server would give again: most 4xx, also 3xx since redirects aren't
+    // followed; the entry is dropped
+    Rejected(u16),
+    // no answer, 408, 429 and 5xx; tried again later
+    Failed,
+}
+
+struct Pending {
+    route: String,
+    body: String,
+    attempts: u8,
+    next_attempt: Instant,
+}
+
+// POSTs that have to reach the server eventually; kept in RAM while running and written to the
+// card on power fail so they survive a reboot
+pub(crate) struct Outbox {
+    queue: VecDeque<Pending>,
+    // the front entry is with the network core
+    in_flight: bool,
+}
+
+impl Outbox {
+    pub(crate) fn new() -> Self {
+        Self { queue: VecDeque::new(), in_flight: false }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    // the oldest entry goes when full, a fresh event is worth more than a stale one
+    pub(crate) fn push(&mut self, route: &str, body: String) {
+        if self.queue.len() >= CAPACITY {
+            // not the one being sent, its result would be applied to the next
+            if let Some(dropped) = self.queue.remove(self.in_flight as usize) {
+                warn!("Outbox: full, dropping {}", dropped.route);
+            }
+        }
+        self.queue.push_back(Pending {
+            route: route.into(),
+            body,
+            attempts: 0,
+            next_attempt: Instant::now(),
+        });
+    }
+
+    // for entries where only the newest counts: any of `route` still waiting goes first
+    pub(crate) fn replace(&mut self, route: &str, body: String) {
+        let in_flight = self.in_flight;
+        let mut index = 0;
+        self.queue.retain(|entry| {
+            // the one being sent stays, its result is on the way
+            let keep = (index == 0 && in_flight) || entry.route != route;
+            index += 1;
+            keep
+        });
+        self.push(route, body);
+    }
+
+    // the front entry as (route, body) once its retry is due; nothing else is handed out until
+    // `sent` reports back, one request at a time keeps the delivery in order
+    pub(crate) fn due(&mut self) -> Option<(String, String)> {
+        if self.in_flight {
+            return None;
+        }
+        let entry = self.queue.front()?;
+        if Instant::now() < entry.next_attempt {
+            return None;
+        }
+        self.in_flight = true;
+        Some((entry.route.clone(), entry.body.clone()))
+    }
+
+    // the route of the entry when the server took it
+    pub(crate) fn sent(&mut self, delivery: Delivery) -> Option<String> {
+        if !core::mem::take(&mut self.in_flight) {
+            return None;
+        }
+        match delivery {
+            Delivery::Accepted => return self.queue.pop_front().map(|entry| entry.route),
+            Delivery::Rejected(status) => {
+                if let Some(entry) = self.queue.pop_front() {
+                    warn!("Outbox: {} rejected with {}, dropping it", entry.route, status);
+                }
+                return None;
+            }
+            Delivery::Failed => {}
+        }
+        if let Some(entry) = self.queue.front_mut() {
+            // exponential backoff, the entry keeps its place
+            entry.attempts = entry.attempts.saturating_add(1);
+            let delay = (FIRST_RETRY_SECS << entry.attempts.min(6)).min(MAX_RETRY_SECS);
+            entry.next_attempt = Instant::now() + Duration::from_secs(delay);
+        }
+        None
+    }
+
+    // one `<route> <body>` per line, bodies are single line JSON; appended, `restore` deleted
+    // the file, and an append cut short by a power loss only costs the line it was writing
+    pub(crate) fn save(&self, sd: &SdStorage<'_>) -> Result<(), SdCardError> {
+        if self.queue.is_empty() {
+            return match sd.delete(OUTBOX_FILE) {
+                Err(SdCardError::NotFound) => Ok(()),
+                result => result,
+            };
+        }
+        let mut out = Vec::new();
+        for entry in &self.queue {
+            out.extend_from_slice(entry.route.as_bytes());
+            out.push(b' ');
+            out.extend_from_slice(entry.body.as_bytes());
+            out.push(b'\n');
+        }
+        sd.append(OUTBOX_FILE, &out)
+    }
+
+    pub(crate) fn restore(&mut self, sd: &SdStorage<'_>) {
+        let Ok(data) = sd.read_to_vec(OUTBOX_FILE) else {
+            return;
+        };
+        let text = match core::str::from_utf8(&data) {
+            Ok(text) => text,
+            Err(e) => core::str::from_utf8(&data[..e.valid_up_to()]).unwrap_or(""),
+        };
+        // a line without its newline is one the power went out on
+        for line in text.split_inclusive('\n').filter_map(|l| l.strip_suffix('\n')) {
+            if let Some((route, body)) = line.split_once(' ') {
+                self.push(route, body.into());
+            }
+        }
+        // entries are back in RAM, a second power fail writes them out again
+        sd.delete(OUTBOX_FILE).ok();
+    }
+}
+
+// runs on the network core, `on_done` hears how the delivery went once the handle has been
+// polled through the response; None when the request couldn't be sent
+pub(crate) fn start_delivery<'s, 'a>(
+    http: &'s HttpClient<'a>,
+    route: &str,
+    body: &str,
+    on_done: impl FnOnce(Delivery) + 's,
+) -> Option<HttpRequestHandle<'s, 'a>> {
+    let name = String::from(route);
+    http.start(Method::Post, route, Some(body.as_bytes()), 5, move |result| {
+        on_done(match result {
+            Ok(response) => delivery(&response),
+            Err(e) => {
+                warn!("Outbox: {} failed: {}", name, e);
+                Delivery::Failed
+            }
+        })
+    })
+    .inspect_err(|e| warn!("Outbox: {} failed: {}", route, e))
+    .ok()
+}
+
+fn delivery(response: &str) -> Delivery {
+    let status = response.split(' ').nth(1).and_then(|status| status.parse::<u16>().ok());
+    match status {
+        Some(200..=299) => Delivery::Accepted,
+        Some(408 | 429 | 500..) | None => Delivery::Failed,
+        Some(status) => Delivery::Rejected(status),
+    }
+}
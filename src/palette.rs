@@ -0,0 +1,57 @@
+// 8 bit frames for the `palette` feature: slint renders the full frames into bytes indexing a
+// 256 entry RGB565 palette, and each line is expanded back to RGB565 on its way to the panel,
+// colour correction after that. The frames take half the PSRAM, for 256 colours: the palette
+// is RGB332, 8 levels of red and green and 4 of blue, so gradients band and pale tints drift
+// towards the nearest level. Anti-aliased edges are blended in RGB and snapped back onto it.
+use slint::platform::software_renderer::{PremultipliedRgbaColor, Rgb565Pixel, TargetPixel};
+
+// the RGB565 of every index
+static PALETTE: [Rgb565Pixel; 256] = {
+    let mut palette = [Rgb565Pixel(0); 256];
+    let mut index = 0;
+    while index < 256 {
+        let (r, g, b) = channels(index as u8);
+        let (r, g, b) = (r as u16 >> 3, g as u16 >> 2, b as u16 >> 3);
+        palette[index] = Rgb565Pixel((r << 11) | (g << 5) | b);
+        index += 1;
+    }
+    palette
+};
+
+// 0..=max to 0..=255
+const fn widen(level: u8, max: u8) -> u8 {
+    (level as u16 * 255 / max as u16) as u8
+}
+
+// 0..=255 to the nearest of 0..=max
+const fn narrow(value: u8, max: u8) -> u8 {
+    ((value as u16 * max as u16 + 127) / 255) as u8
+}
+
+const fn channels(index: u8) -> (u8, u8, u8) {
+    (widen(index >> 5, 7), widen((index >> 2) & 7, 7), widen(index & 3, 3))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(transparent)]
+pub(crate) struct IndexedPixel(pub(crate) u8);
+
+impl TargetPixel for IndexedPixel {
+    fn blend(&mut self, color: PremultipliedRgbaColor) {
+        let (r, g, b) = channels(self.0);
+        let behind = (u8::MAX - color.alpha) as u16;
+        let mix = |under: u8, over: u8| (over as u16 + under as u16 * behind / 255) as u8;
+        *self = Self::from_rgb(mix(r, color.red), mix(g, color.green), mix(b, color.blue));
+    }
+
+    fn from_rgb(red: u8, green: u8, blue: u8) -> Self {
+        Self((narrow(red, 7) << 5) | (narrow(green, 7) << 2) | narrow(blue, 3))
+    }
+}
+
+// a rendered line into `out`, as much of it as fits
+pub(crate) fn expand(pixels: &[IndexedPixel], out: &mut [Rgb565Pixel]) {
+    for (out, pixel) in out.iter_mut().zip(pixels) {
+        *out = PALETTE[pixel.0 as usize];
+    }
+}
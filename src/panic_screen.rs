@@ -0,0 +1,287 @@
+// Panic handler that puts the message on the panel, a panic used to only reach the serial
+// console. The display is brought up again from scratch on stolen peripherals: whatever the
+// loop was doing with the SPI bus at the time is abandoned. The chip restarts after
+// RESTART_SECS, counted towards safe mode, so a crash loop ends up there on its own.
+use core::{
+    fmt::{self, Write},
+    panic::PanicInfo,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use embedded_graphics_core::{
+    draw_target::DrawTarget,
+    geometry::{Point, Size},
+    pixelcolor::{Rgb565, RgbColor},
+    primitives::Rectangle,
+};
+use esp_hal::{
+    delay::Delay,
+    gpio::{Level, Output},
+    peripherals::Peripherals,
+    time::Rate,
+};
+use esp_println::println;
+
+use crate::{
+    create_spi,
+    display_screen::{PANEL_HEIGHT, PANEL_WIDTH, ScreenOrientation, init_display},
+    reliability,
+    safe_mode,
+    spi_bus::SharedSpi,
+    watchdog,
+};
+
+const WIDTH: i32 = PANEL_WIDTH as i32;
+const HEIGHT: i32 = PANEL_HEIGHT as i32;
+const MARGIN: i32 = 8;
+// 5x7 glyphs in a 6x8 cell
+const CELL_W: i32 = 6;
+const CELL_H: i32 = 8;
+const MAX_FRAMES: usize = 8;
+const BACKGROUND: Rgb565 = Rgb565::new(12, 0, 0);
+// long enough to read the message or take a photo of it
+const RESTART_SECS: u32 = 15;
+
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+#[panic_handler]
+fn panic(info: &PanicInfo<'_>) -> ! {
+    // a panic while drawing the first one only gets the serial treatment
+    let nested = PANICKING.swap(true, Ordering::Relaxed);
+    watchdog::disarm();
+    reliability::record_panic();
+    safe_mode::record_panic();
+
+    println!("\n\n====================== PANIC ======================");
+    println!("{}", info);
+    let backtrace = esp_backtrace::Backtrace::capture();
+    let frames = backtrace.frames();
+    println!("Backtrace:");
+    for frame in frames.iter().take(MAX_FRAMES) {
+        println!("0x{:08x}", frame.program_counter());
+    }
+
+    if !nested {
+        show(info, frames.iter().take(MAX_FRAMES).map(|f| f.program_counter()));
+    }
+
+    Delay::new().delay_millis(RESTART_SECS * 1000);
+    esp_hal::system::software_reset()
+}
+
+// the same wiring as the main loop, see `run_event_loop`; the heap may be what failed, so
+// nothing here allocates
+fn show(info: &PanicInfo<'_>, backtrace: impl Iterator<Item = usize>) {
+    let peripherals = unsafe { Peripherals::steal() };
+    let Ok(fast_spi) = create_spi(
+        peripherals.SPI3,
+        peripherals.GPIO18,
+        peripherals.GPIO23,
+        peripherals.GPIO19,
+        Rate::from_mhz(2),
+    ) else {
+        return;
+    };
+    let bus = SharedSpi::new(fast_spi);
+    let mut buf512 = [0u8; 512];
+    let Ok(mut display) = init_display(
+        &bus,
+        peripherals.GPIO2,
+        peripherals.GPIO15,
+        peripherals.GPIO4,
+        &mut buf512,
+        // always landscape, the layout below is
+        ScreenOrientation::default(),
+    ) else {
+        return;
+    };
+    // full brightness, the LEDC channel driving it is abandoned with the rest
+    let _backlight = Output::new(peripherals.GPIO32, Level::High, Default::default());
+
+    let _ = display.clear(BACKGROUND);
+    let mut text = TextWriter::new(&mut display, 2);
+    let _ = write!(text, "PANIC");
+    text.set_scale(1);
+    text.newline();
+    text.newline();
+    if let Some(location) = info.location() {
+        let _ = write!(text, "at {}:{}", location.file(), location.line());
+        text.newline();
+        text.newline();
+    }
+    let _ = write!(text, "{}", info.message());
+    text.newline();
+    text.newline();
+    let _ = write!(text, "backtrace:");
+    for pc in backtrace {
+        let _ = write!(text, " 0x{:08x}", pc);
+    }
+    text.newline();
+    text.newline();
+    let _ = write!(text, "Restarting in {} s", RESTART_SECS);
+}
+
+// draws straight to the panel, wrapping at the right edge and dropping what's below the bottom
+struct TextWriter<'d, D> {
+    display: &'d mut D,
+    scale: i32,
+    x: i32,
+    y: i32,
+}
+
+impl<'d, D: DrawTarget<Color = Rgb565>> TextWriter<'d, D> {
+    fn new(display: &'d mut D, scale: i32) -> Self {
+        Self { display, scale, x: MARGIN, y: MARGIN }
+    }
+
+    fn set_scale(&mut self, scale: i32) {
+        self.scale = scale;
+    }
+
+    fn newline(&mut self) {
+        self.x = MARGIN;
+        self.y += CELL_H * self.scale;
+    }
+
+    fn draw_char(&mut self, c: char) {
+        if c == '\n' {
+            self.newline();
+            return;
+        }
+        if self.x + CELL_W * self.scale > WIDTH - MARGIN {
+            self.newline();
+        }
+        if self.y + CELL_H * self.scale > HEIGHT - MARGIN {
+            return;
+        }
+
+        let glyph = glyph(c);
+        let scale = self.scale;
+        let area = Rectangle::new(
+            Point::new(self.x, self.y),
+            Size::new((CELL_W * scale) as u32, (CELL_H * scale) as u32),
+        );
+        let pixels = (0..CELL_H * scale).flat_map(|py| {
+            (0..CELL_W * scale).map(move |px| {
+                let (col, row) = ((px / scale) as usize, py / scale);
+                let lit = col < 5 && (glyph[col] >> row) & 1 != 0;
+                if lit { Rgb565::WHITE } else { BACKGROUND }
+            })
+        });
+        let _ = self.display.fill_contiguous(&area, pixels);
+        self.x += CELL_W * scale;
+    }
+}
+
+impl<D: DrawTarget<Color = Rgb565>> fmt::Write for TextWriter<'_, D> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            self.draw_char(c);
+        }
+        Ok(())
+    }
+}
+
+// printable ASCII only, anything else shows as '?'
+fn glyph(c: char) -> &'static [u8; 5] {
+    let index = (c as u32).wrapping_sub(0x20) as usize;
+    FONT.get(index).unwrap_or(&FONT['?' as usize - 0x20])
+}
+
+// classic 5x7 font, one byte per column, least significant bit at the top
+const FONT: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x00, 0x00, 0x5F, 0x00, 0x00], // !
+    [0x00, 0x07, 0x00, 0x07, 0x00], // "
+    [0x14, 0x7F, 0x14, 0x7F, 0x14], // #
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12], // $
+    [0x23, 0x13, 0x08, 0x64, 0x62], // %
+    [0x36, 0x49, 0x55, 0x22, 0x50], // &
+    [0x00, 0x05, 0x03, 0x00, 0x00], // '
+    [0x00, 0x1C, 0x22, 0x41, 0x00], // (
+    [0x00, 0x41, 0x22, 0x1C, 0x00], // )
+    [0x08, 0x2A, 0x1C, 0x2A, 0x08], // *
+    [0x08, 0x08, 0x3E, 0x08, 0x08], // +
+    [0x00, 0x50, 0x30, 0x00, 0x00], // ,
+    [0x08, 0x08, 0x08, 0x08, 0x08], // -
+    [0x00, 0x60, 0x60, 0x00, 0x00], // .
+    [0x20, 0x10, 0x08, 0x04, 0x02], // /
+    [0x3E, 0x51, 0x49, 0x45, 0x3E], // 0
+    [0x00, 0x42, 0x7F, 0x40, 0x00], // 1
+    [0x42, 0x61, 0x51, 0x49, 0x46], // 2
+    [0x21, 0x41, 0x45, 0x4B, 0x31], // 3
+    [0x18, 0x14, 0x12, 0x7F, 0x10], // 4
+    [0x27, 0x45, 0x45, 0x45, 0x39], // 5
+    [0x3C, 0x4A, 0x49, 0x49, 0x30], // 6
+    [0x01, 0x71, 0x09, 0x05, 0x03], // 7
+    [0x36, 0x49, 0x49, 0x49, 0x36], // 8
+    [0x06, 0x49, 0x49, 0x29, 0x1E], // 9
+    [0x00, 0x36, 0x36, 0x00, 0x00], // :
+    [0x00, 0x56, 0x36, 0x00, 0x00], // ;
+    [0x08, 0x14, 0x22, 0x41, 0x00], // <
+    [0x14, 0x14, 0x14, 0x14, 0x14], // =
+    [0x00, 0x41, 0x22, 0x14, 0x08], // >
+    [0x02, 0x01, 0x51, 0x09, 0x06], // ?
+    [0x32, 0x49, 0x79, 0x41, 0x3E], // @
+    [0x7E, 0x11, 0x11, 0x11, 0x7E], // A
+    [0x7F, 0x49, 0x49, 0x49, 0x36], // B
+    [0x3E, 0x41, 0x41, 0x41, 0x22], // C
+    [0x7F, 0x41, 0x41, 0x22, 0x1C], // D
+    [0x7F, 0x49, 0x49, 0x49, 0x41], // E
+    [0x7F, 0x09, 0x09, 0x01, 0x01], // F
+    [0x3E, 0x41, 0x41, 0x51, 0x32], // G
+    [0x7F, 0x08, 0x08, 0x08, 0x7F], // H
+    [0x00, 0x41, 0x7F, 0x41, 0x00], // I
+    [0x20, 0x40, 0x41, 0x3F, 0x01], // J
+    [0x7F, 0x08, 0x14, 0x22, 0x41], // K
+    [0x7F, 0x40, 0x40, 0x40, 0x40], // L
+    [0x7F, 0x02, 0x04, 0x02, 0x7F], // M
+    [0x7F, 0x04, 0x08, 0x10, 0x7F], // N
+    [0x3E, 0x41, 0x41, 0x41, 0x3E], // O
+    [0x7F, 0x09, 0x09, 0x09, 0x06], // P
+    [0x3E, 0x41, 0x51, 0x21, 0x5E], // Q
+    [0x7F, 0x09, 0x19, 0x29, 0x46], // R
+    [0x46, 0x49, 0x49, 0x49, 0x31], // S
+    [0x01, 0x01, 0x7F, 0x01, 0x01], // T
+    [0x3F, 0x40, 0x40, 0x40, 0x3F], // U
+    [0x1F, 0x20, 0x40, 0x20, 0x1F], // V
+    [0x7F, 0x20, 0x18, 0x20, 0x7F], // W
+    [0x63, 0x14, 0x08, 0x14, 0x63], // X
+    [0x03, 0x04, 0x78, 0x04, 0x03], // Y
+    [0x61, 0x51, 0x49, 0x45, 0x43], // Z
+    [0x00, 0x7F, 0x41, 0x41, 0x00], // [
+    [0x02, 0x04, 0x08, 0x10, 0x20], // \
+    [0x00, 0x41, 0x41, 0x7F, 0x00], // ]
+    [0x04, 0x02, 0x01, 0x02, 0x04], // ^
+    [0x40, 0x40, 0x40, 0x40, 0x40], // _
+    [0x00, 0x01, 0x02, 0x04, 0x00], // `
+    [0x20, 0x54, 0x54, 0x54, 0x78], // a
+    [0x7F, 0x48, 0x44, 0x44, 0x38], // b
+    [0x38, 0x44, 0x44, 0x44, 0x20], // c
+    [0x38, 0x44, 0x44, 0x48, 0x7F], // d
+    [0x38, 0x54, 0x54, 0x54, 0x18], // e
+    [0x08, 0x7E, 0x09, 0x01, 0x02], // f
+    [0x0C, 0x52, 0x52, 0x52, 0x3E], // g
+    [0x7F, 0x08, 0x04, 0x04, 0x78], // h
+    [0x00, 0x44, 0x7D, 0x40, 0x00], // i
+    [0x20, 0x40, 0x44, 0x3D, 0x00], // j
+    [0x7F, 0x10, 0x28, 0x44, 0x00], // k
+    [0x00, 0x41, 0x7F, 0x40, 0x00], // l
+    [0x7C, 0x04, 0x18, 0x04, 0x78], // m
+    [0x7C, 0x08, 0x04, 0x04, 0x78], // n
+    [0x38, 0x44, 0x44, 0x44, 0x38], // o
+    [0x7C, 0x14, 0x14, 0x14, 0x08], // p
+    [0x08, 0x14, 0x14, 0x18, 0x7C], // q
+    [0x7C, 0x08, 0x04, 0x04, 0x08], // r
+    [0x48, 0x54, 0x54, 0x54, 0x20], // s
+    [0x04, 0x3F, 0x44, 0x40, 0x20], // t
+    [0x3C, 0x40, 0x40, 0x20, 0x7C], // u
+    [0x1C, 0x20, 0x40, 0x20, 0x1C], // v
+    [0x3C, 0x40, 0x30, 0x40, 0x3C], // w
+    [0x44, 0x28, 0x10, 0x28, 0x44], // x
+    [0x0C, 0x50, 0x50, 0x50, 0x3C], // y
+    [0x44, 0x64, 0x54, 0x4C, 0x44], // z
+    [0x00, 0x08, 0x36, 0x41, 0x00], // {
+    [0x00, 0x00, 0x7F, 0x00, 0x00], // |
+    [0x00, 0x41, 0x36, 0x08, 0x00], // }
+    [0x08, 0x04, 0x08, 0x10, 0x08], // ~
+];
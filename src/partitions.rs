@@ -0,0 +1,228 @@
+// The esp-idf partition table, read from flash at boot so the settings, OTA slots and a data
+// partition for assets are found by type and label instead of offsets baked into the firmware.
+use alloc::{format, string::String, vec, vec::Vec};
+use core::fmt;
+use embedded_storage::{ReadStorage, Storage};
+use esp_storage::FlashStorage;
+use thiserror::Error;
+
+// where the second stage bootloader expects it, with room for 95 entries and the MD5 entry
+const TABLE_OFFSET: u32 = 0x8000;
+const TABLE_LEN: usize = 0xC00;
+const ENTRY_LEN: usize = 32;
+const ENTRY_MAGIC: [u8; 2] = [0xAA, 0x50];
+// the checksum entry after the last partition, not verified here
+const MD5_MAGIC: [u8; 2] = [0xEB, 0xEB];
+const LABEL_LEN: usize = 16;
+const ASSETS_LABEL: &str = "assets";
+const SETTINGS_LABEL: &str = "settings";
+const SECRETS_LABEL: &str = "secrets";
+
+#[derive(Error, Debug)]
+pub(crate) enum PartitionError {
+    #[error("Flash access failed: {0}")]
+    Flash(String),
+    #[error("No partition table at {:#x}", TABLE_OFFSET)]
+    Missing,
+    #[error("Partition table entry {0} is malformed")]
+    Malformed(usize),
+    #[error("Access past the end of partition {0}")]
+    OutOfBounds(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum PartitionKind {
+    Factory,
+    // app slot ota_0 .. ota_15
+    Ota(u8),
+    Test,
+    // which OTA slot boots next
+    OtaData,
+    Phy,
+    Nvs,
+    CoreDump,
+    NvsKeys,
+    Fat,
+    Spiffs,
+    LittleFs,
+    Other { kind: u8, subtype: u8 },
+}
+
+impl PartitionKind {
+    fn from_raw(kind: u8, subtype: u8) -> Self {
+        match (kind, subtype) {
+            (0x00, 0x00) => PartitionKind::Factory,
+            (0x00, 0x10..=0x1F) => PartitionKind::Ota(subtype - 0x10),
+            (0x00, 0x20) => PartitionKind::Test,
+            (0x01, 0x00) => PartitionKind::OtaData,
+            (0x01, 0x01) => PartitionKind::Phy,
+            (0x01, 0x02) => PartitionKind::Nvs,
+            (0x01, 0x03) => PartitionKind::CoreDump,
+            (0x01, 0x04) => PartitionKind::NvsKeys,
+            (0x01, 0x81) => PartitionKind::Fat,
+            (0x01, 0x82) => PartitionKind::Spiffs,
+            (0x01, 0x83) => PartitionKind::LittleFs,
+            (kind, subtype) => PartitionKind::Other { kind, subtype },
+        }
+    }
+}
+
+impl fmt::Display for PartitionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PartitionKind::Factory => f.write_str("factory"),
+            PartitionKind::Ota(slot) => write!(f, "ota_{}", slot),
+            PartitionKind::Test => f.write_str("test"),
+            PartitionKind::OtaData => f.write_str("otadata"),
+            PartitionKind::Phy => f.write_str("phy"),
+            PartitionKind::Nvs => f.write_str("nvs"),
+            PartitionKind::CoreDump => f.write_str("coredump"),
+            PartitionKind::NvsKeys => f.write_str("nvs_keys"),
+            PartitionKind::Fat => f.write_str("fat"),
+            PartitionKind::Spiffs => f.write_str("spiffs"),
+            PartitionKind::LittleFs => f.write_str("littlefs"),
+            PartitionKind::Other { kind, subtype } => write!(f, "{:#04x}/{:#04x}", kind, subtype),
+        }
+    }
+}
+
+// one entry of the table; reads and writes through it are relative to its start and can't
+// reach into the neighbours
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Partition {
+    pub(crate) kind: PartitionKind,
+    pub(crate) offset: u32,
+    pub(crate) size: u32,
+    label: [u8; LABEL_LEN],
+}
+
+impl Partition {
+    // the default table's NVS, for finding an old record on flash without a readable table
+    pub(crate) const DEFAULT_NVS: Partition = Partition {
+        kind: PartitionKind::Nvs,
+        offset: 0x9000,
+        size: 0x6000,
+        label: *b"nvs\0\0\0\0\0\0\0\0\0\0\0\0\0",
+    };
+
+    fn parse(entry: &[u8]) -> Self {
+        let word = |at: usize| {
+            u32::from_le_bytes([entry[at], entry[at + 1], entry[at + 2], entry[at + 3]])
+        };
+        let mut label = [0u8; LABEL_LEN];
+        label.copy_from_slice(&entry[12..12 + LABEL_LEN]);
+        Self {
+            kind: PartitionKind::from_raw(entry[2], entry[3]),
+            offset: word(4),
+            size: word(8),
+            label,
+        }
+    }
+
+    pub(crate) fn label(&self) -> &str {
+        let len = self.label.iter().position(|&b| b == 0).unwrap_or(LABEL_LEN);
+        core::str::from_utf8(&self.label[..len]).unwrap_or("?")
+    }
+
+    pub(crate) fn read(
+        &self,
+        flash: &mut FlashStorage<'_>,
+        offset: u32,
+        bytes: &mut [u8],
+    ) -> Result<(), PartitionError> {
+        let address = self.address(offset, bytes.len())?;
+        flash.read(address, bytes).map_err(|e| PartitionError::Flash(format!("{:?}", e)))
+    }
+
+    pub(crate) fn write(
+        &self,
+        flash: &mut FlashStorage<'_>,
+        offset: u32,
+        bytes: &[u8],
+    ) -> Result<(), PartitionError> {
+        let address = self.address(offset, bytes.len())?;
+        flash.write(address, bytes).map_err(|e| PartitionError::Flash(format!("{:?}", e)))
+    }
+
+    fn address(&self, offset: u32, len: usize) -> Result<u32, PartitionError> {
+        let end = offset as u64 + len as u64;
+        if end > self.size as u64 {
+            return Err(PartitionError::OutOfBounds(self.label().into()));
+        }
+        Ok(self.offset + offset)
+    }
+}
+
+pub(crate) struct PartitionTable {
+    partitions: Vec<Partition>,
+}
+
+impl PartitionTable {
+    pub(crate) fn read(flash: &mut FlashStorage<'_>) -> Result<Self, PartitionError> {
+        let mut table = vec![0u8; TABLE_LEN];
+        flash
+            .read(TABLE_OFFSET, &mut table)
+            .map_err(|e| PartitionError::Flash(format!("{:?}", e)))?;
+        let mut partitions = Vec::new();
+        for (i, entry) in table.chunks_exact(ENTRY_LEN).enumerate() {
+            match [entry[0], entry[1]] {
+                ENTRY_MAGIC => partitions.push(Partition::parse(entry)),
+                MD5_MAGIC | [0xFF, 0xFF] => break,
+                _ => return Err(PartitionError::Malformed(i)),
+            }
+        }
+        if partitions.is_empty() {
+            return Err(PartitionError::Missing);
+        }
+        Ok(Self { partitions })
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Partition> {
+        self.partitions.iter()
+    }
+
+    // a data partition labelled "settings", the settings record has it to itself
+    pub(crate) fn settings(&self) -> Option<&Partition> {
+        self.iter().find(|p| p.label() == SETTINGS_LABEL && !is_app(p.kind))
+    }
+
+    // a data partition labelled "secrets", for the key the refresh token is sealed with
+    pub(crate) fn secrets(&self) -> Option<&Partition> {
+        self.iter().find(|p| p.label() == SECRETS_LABEL && !is_app(p.kind))
+    }
+
+    // the first NVS partition, where earlier firmware kept the settings record
+    pub(crate) fn nvs(&self) -> Option<&Partition> {
+        self.iter().find(|p| p.kind == PartitionKind::Nvs)
+    }
+
+    // ota_0, ota_1, ... in slot order
+    pub(crate) fn ota_slots(&self) -> impl Iterator<Item = &Partition> {
+        (0..16).filter_map(|slot| self.iter().find(|p| p.kind == PartitionKind::Ota(slot)))
+    }
+
+    // a data partition labelled "assets", images and fonts flashed apart from the firmware
+    pub(crate) fn assets(&self) -> Option<&Partition> {
+        self.iter().find(|p| p.label() == ASSETS_LABEL && !is_app(p.kind))
+    }
+}
+
+impl fmt::Display for PartitionTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.settings() {
+            Some(settings) => write!(f, "settings at {:#x}", settings.offset)?,
+            None => f.write_str("no settings partition")?,
+        }
+        write!(f, ", {} OTA slots", self.ota_slots().count())?;
+        match self.assets() {
+            Some(assets) => {
+                write!(f, ", assets at {:#x} ({} KiB)", assets.offset, assets.size / 1024)
+            }
+            None => f.write_str(", no assets partition"),
+        }
+    }
+}
+
+fn is_app(kind: PartitionKind) -> bool {
+    matches!(kind, PartitionKind::Factory | PartitionKind::Ota(_) | PartitionKind::Test)
+}
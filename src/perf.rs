@@ -0,0 +1,218 @@
+use esp_hal::{
+    delay::Delay,
+    time::{Duration, Instant},
+};
+use log::{info, warn};
+
+use crate::{coexistence, touch_input};
+
+// upper bound on an idle sleep so network, rules and the power state still get polled
+const MAX_IDLE_MS: u64 = 100;
+// passes over budget in a row before the frame rate is halved, a single slow card write or
+// screenshot is not load
+const OVERRUNS_TO_DEGRADE: u32 = 3;
+// without an overrun for this long the full frame rate comes back
+const RECOVER_AFTER: Duration = Duration::from_secs(2);
+// deferred work still gets a pass this often while degraded
+const DEFERRED_INTERVAL: Duration = Duration::from_millis(250);
+// a second's load moves the shown one this far, a single slow pass doesn't make it jump
+const LOAD_SMOOTHING: f32 = 0.3;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct PerfStats {
+    pub(crate) fps: u32,
+    pub(crate) frame_ms: u32,
+    pub(crate) worst_ms: u32,
+    pub(crate) heap_used_kb: u32,
+    // running at half the target rate because the loop kept overrunning
+    pub(crate) degraded: bool,
+    // percent of the UI core's time spent outside the idle wait, smoothed
+    pub(crate) cpu_load: u8,
+    // panel writes the radio held up, since boot
+    pub(crate) glitches: u32,
+}
+
+// Load from idle accounting: whatever the loop doesn't spend waiting in `FramePacer::idle` or
+// light sleep is work, its own or the radio's interrupts taking the core from it. The network
+// core polls without ever waiting, so it has no idle to measure; its load shows here only as
+// far as it starves this core.
+struct CpuLoad {
+    idle: Duration,
+    slept: Duration,
+    smoothed: Option<f32>,
+}
+
+impl CpuLoad {
+    fn new() -> Self {
+        Self { idle: Duration::ZERO, slept: Duration::ZERO, smoothed: None }
+    }
+
+    // over the window that just ended, `elapsed` by Instant, which stands still in light sleep
+    fn sample(&mut self, elapsed: Duration) -> u8 {
+        let total = (elapsed + self.slept).as_micros().max(1) as f32;
+        let idle = (self.idle + self.slept).as_micros() as f32;
+        let load = (1.0 - idle / total).clamp(0.0, 1.0);
+        let smoothed = match self.smoothed {
+            Some(smoothed) => smoothed + (load - smoothed) * LOAD_SMOOTHING,
+            None => load,
+        };
+        self.smoothed = Some(smoothed);
+        self.idle = Duration::ZERO;
+        self.slept = Duration::ZERO;
+        (smoothed * 100.0 + 0.5) as u8
+    }
+}
+
+// caps the loop at the target frame rate while something animates and sleeps until the next
+// slint timer otherwise; also gathers the numbers for the performance overlay.
+// When passes keep running over the frame budget (the radio or the network core starving this
+// one) frames are drawn at half the rate and the loop's deferrable work is thinned out, touch
+// is still read and dispatched on every pass.
+pub(crate) struct FramePacer {
+    frame_budget: Duration,
+    frame_start: Instant,
+    window_start: Instant,
+    frames: u32,
+    render_time: Duration,
+    worst: Duration,
+    overruns: u32,
+    // while degraded, the last pass that ran over
+    degraded_at: Option<Instant>,
+    last_frame: Instant,
+    last_deferred: Instant,
+    load: CpuLoad,
+}
+
+impl FramePacer {
+    pub(crate) fn new(target_fps: u32) -> Self {
+        Self {
+            frame_budget: Duration::from_micros(1_000_000 / target_fps.max(1) as u64),
+            frame_start: Instant::now(),
+            window_start: Instant::now(),
+            frames: 0,
+            render_time: Duration::ZERO,
+            worst: Duration::ZERO,
+            overruns: 0,
+            degraded_at: None,
+            last_frame: Instant::now(),
+            last_deferred: Instant::now(),
+            load: CpuLoad::new(),
+        }
+    }
+
+    pub(crate) fn is_degraded(&self) -> bool {
+        self.degraded_at.is_some()
+    }
+
+    // every pass normally, every other frame budget while degraded
+    pub(crate) fn frame_due(&self) -> bool {
+        !self.is_degraded() || self.last_frame.elapsed() >= self.degraded_budget()
+    }
+
+    // work that can wait a little (sensors, telemetry, the heat map, card logging): every pass
+    // normally, every DEFERRED_INTERVAL while degraded
+    pub(crate) fn deferred_due(&mut self) -> bool {
+        if !self.is_degraded() {
+            return true;
+        }
+        if self.last_deferred.elapsed() < DEFERRED_INTERVAL {
+            return false;
+        }
+        self.last_deferred = Instant::now();
+        true
+    }
+
+    pub(crate) fn begin(&mut self) {
+        self.frame_start = Instant::now();
+    }
+
+    pub(crate) fn rendered(&mut self, took: Duration) {
+        self.last_frame = Instant::now();
+        self.frames += 1;
+        self.render_time += took;
+        self.worst = self.worst.max(took);
+    }
+
+    // once a second, over the frames actually drawn in it
+    pub(crate) fn stats(&mut self) -> Option<PerfStats> {
+        let elapsed = self.window_start.elapsed();
+        if elapsed.as_millis() < 1000 {
+            return None;
+        }
+        let stats = PerfStats {
+            fps: (self.frames as u64 * 1000 / elapsed.as_millis()) as u32,
+            frame_ms: self
+                .render_time
+                .as_millis()
+                .checked_div(self.frames as u64)
+                .unwrap_or(0) as u32,
+            worst_ms: self.worst.as_millis() as u32,
+            heap_used_kb: (esp_alloc::HEAP.used() / 1024) as u32,
+            degraded: self.is_degraded(),
+            cpu_load: self.load.sample(elapsed),
+            glitches: coexistence::glitches(),
+        };
+        self.window_start = Instant::now();
+        self.frames = 0;
+        self.render_time = Duration::ZERO;
+        self.worst = Duration::ZERO;
+        Some(stats)
+    }
+
+    // `busy` is an animation or a finger on the panel, both want the full frame rate
+    pub(crate) fn idle(&mut self, busy: bool) {
+        self.track_overruns();
+        let until = if busy {
+            self.frame_start + self.frame_budget
+        } else {
+            Instant::now() + self.idle_time()
+        };
+
+        // a touch ends the sleep early so the press is not delayed by a whole idle period
+        let waiting = Instant::now();
+        let delay = Delay::new();
+        while Instant::now() < until && !touch_input::irq_pending() {
+            delay.delay_micros(500);
+        }
+        self.load.idle += waiting.elapsed();
+    }
+
+    // a light sleep of at most `nap` instead of `idle`
+    pub(crate) fn slept(&mut self, nap: Duration) {
+        self.load.slept += nap;
+    }
+
+    // nothing animating: until slint's next timer, capped so the loop still comes round
+    pub(crate) fn idle_time(&self) -> Duration {
+        let next_timer = slint::platform::duration_until_next_timer_update()
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(MAX_IDLE_MS)
+            .min(MAX_IDLE_MS);
+        Duration::from_millis(next_timer)
+    }
+
+    fn degraded_budget(&self) -> Duration {
+        Duration::from_micros(self.frame_budget.as_micros() * 2)
+    }
+
+    // the pass that just ended against the budget it had, twice the frame budget while degraded
+    fn track_overruns(&mut self) {
+        let budget = if self.is_degraded() { self.degraded_budget() } else { self.frame_budget };
+        if self.frame_start.elapsed() <= budget {
+            self.overruns = 0;
+            if self.degraded_at.is_some_and(|at| at.elapsed() >= RECOVER_AFTER) {
+                self.degraded_at = None;
+                info!("Perf: load gone, back to the full frame rate");
+            }
+            return;
+        }
+        self.overruns += 1;
+        if self.is_degraded() {
+            self.degraded_at = Some(Instant::now());
+        } else if self.overruns >= OVERRUNS_TO_DEGRADE {
+            self.degraded_at = Some(Instant::now());
+            self.last_deferred = Instant::now();
+            warn!("Perf: loop overrunning, halving the frame rate");
+        }
+    }
+}
@@ -0,0 +1,201 @@
+// ICMP echo for finding out where the network stops: `ping <host> [count]` on the console and
+// the buttons on the network page. A request goes out every INTERVAL_MS and waits up to that
+// long for its reply; it runs on the network core and holds its loop meanwhile, like the
+// console's `get`. A host is an address, `gateway` or `server`, there is no DNS to resolve
+// names with.
+use alloc::{boxed::Box, format, string::String, vec, vec::Vec};
+use blocking_network_stack::Stack;
+use esp_hal::time::{Duration, Instant};
+use esp_radio::wifi::WifiDevice;
+use smoltcp::{
+    iface::SocketHandle,
+    phy::ChecksumCapabilities,
+    socket::icmp::{self, Endpoint, PacketBuffer, PacketMetadata},
+    wire::{Icmpv4Packet, Icmpv4Repr, IpAddress, Ipv4Address},
+};
+use thiserror::Error;
+
+pub(crate) const DEFAULT_COUNT: u8 = 4;
+pub(crate) const MAX_COUNT: u8 = 10;
+const INTERVAL_MS: u64 = 1000;
+const IDENT: u16 = 0xe532;
+const PAYLOAD: &[u8] = b"esp32-display";
+const BUFFER_SIZE: usize = 256;
+
+#[derive(Error, Debug)]
+pub(crate) enum PingError {
+    #[error("Not connected")]
+    NotConnected,
+    #[error("No server configured")]
+    NoServer,
+    #[error("Binding the ICMP socket failed")]
+    Bind,
+    #[error("Sending to {0} failed")]
+    Send(IpAddress),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum PingHost {
+    Address(Ipv4Address),
+    Gateway,
+    Server,
+}
+
+impl PingHost {
+    pub(crate) fn parse(text: &str) -> Option<Self> {
+        match text {
+            "gateway" => Some(PingHost::Gateway),
+            "server" => Some(PingHost::Server),
+            address => address.parse().ok().map(PingHost::Address),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct PingReport {
+    pub(crate) host: IpAddress,
+    // the round trip of each request, None when its reply didn't come in time
+    pub(crate) replies: Vec<Option<Duration>>,
+}
+
+impl PingReport {
+    pub(crate) fn received(&self) -> usize {
+        self.replies.iter().flatten().count()
+    }
+
+    pub(crate) fn loss_percent(&self) -> usize {
+        let sent = self.replies.len().max(1);
+        (sent - self.received()) * 100 / sent
+    }
+
+    // "4 sent, 3 received, 25% loss, rtt min/avg/max 2.1/3.4/5.0 ms"
+    pub(crate) fn summary(&self) -> String {
+        let ms = |rtt: Duration| rtt.as_micros() as f32 / 1000.0;
+        let rtts: Vec<f32> = self.replies.iter().flatten().map(|rtt| ms(*rtt)).collect();
+        let mut summary = format!(
+            "{} sent, {} received, {}% loss",
+            self.replies.len(),
+            rtts.len(),
+            self.loss_percent()
+        );
+        if !rtts.is_empty() {
+            let min = rtts.iter().copied().fold(f32::MAX, f32::min);
+            let max = rtts.iter().copied().fold(0.0, f32::max);
+            let avg = rtts.iter().sum::<f32>() / rtts.len() as f32;
+            summary.push_str(&format!(", rtt min/avg/max {:.1}/{:.1}/{:.1} ms", min, avg, max));
+        }
+        summary
+    }
+
+    // a line per request, then the summary
+    pub(crate) fn to_text(&self) -> String {
+        let mut text = format!("ping {}\n", self.host);
+        for (seq, reply) in self.replies.iter().enumerate() {
+            match reply {
+                Some(rtt) => {
+                    let ms = rtt.as_micros() as f32 / 1000.0;
+                    text.push_str(&format!("seq {}: {:.1} ms\n", seq, ms));
+                }
+                None => text.push_str(&format!("seq {}: timeout\n", seq)),
+            }
+        }
+        text.push_str(&self.summary());
+        text
+    }
+}
+
+// the socket's buffers, for as long as it is in the stack's socket set
+struct IcmpBuffers {
+    rx_meta: Box<[PacketMetadata]>,
+    rx: Box<[u8]>,
+    tx_meta: Box<[PacketMetadata]>,
+    tx: Box<[u8]>,
+}
+
+pub(crate) fn ping<'a>(
+    stack: &Stack<'a, WifiDevice<'a>>,
+    host: IpAddress,
+    count: u8,
+) -> Result<PingReport, PingError> {
+    let mut buffers = IcmpBuffers {
+        rx_meta: vec![PacketMetadata::EMPTY; 2].into_boxed_slice(),
+        rx: vec![0u8; BUFFER_SIZE].into_boxed_slice(),
+        tx_meta: vec![PacketMetadata::EMPTY; 2].into_boxed_slice(),
+        tx: vec![0u8; BUFFER_SIZE].into_boxed_slice(),
+    };
+    // the socket leaves the set below, before `buffers` is dropped
+    let (rx_meta, rx, tx_meta, tx): (
+        &'a mut [PacketMetadata],
+        &'a mut [u8],
+        &'a mut [PacketMetadata],
+        &'a mut [u8],
+    ) = unsafe {
+        (
+            &mut *(buffers.rx_meta.as_mut() as *mut [PacketMetadata]),
+            &mut *(buffers.rx.as_mut() as *mut [u8]),
+            &mut *(buffers.tx_meta.as_mut() as *mut [PacketMetadata]),
+            &mut *(buffers.tx.as_mut() as *mut [u8]),
+        )
+    };
+    let socket = icmp::Socket::new(PacketBuffer::new(rx_meta, rx), PacketBuffer::new(tx_meta, tx));
+    let handle = stack.with_mut(|_, _, sockets| sockets.add(socket));
+    let result = exchange(stack, handle, host, count.clamp(1, MAX_COUNT));
+    stack.with_mut(|_, _, sockets| {
+        sockets.remove(handle);
+    });
+    drop(buffers);
+    result
+}
+
+fn exchange(
+    stack: &Stack<'_, WifiDevice<'_>>,
+    handle: SocketHandle,
+    host: IpAddress,
+    count: u8,
+) -> Result<PingReport, PingError> {
+    let with_socket = |f: &mut dyn FnMut(&mut icmp::Socket<'_>)| {
+        stack.with_mut(|_, _, sockets| f(sockets.get_mut::<icmp::Socket>(handle)))
+    };
+    let mut bound = Ok(());
+    with_socket(&mut |socket| bound = socket.bind(Endpoint::Ident(IDENT)));
+    bound.map_err(|_| PingError::Bind)?;
+
+    let checksums = ChecksumCapabilities::default();
+    let interval = Duration::from_millis(INTERVAL_MS);
+    let mut report = PingReport { host, replies: Vec::new() };
+    for seq_no in 0..count as u16 {
+        let request = Icmpv4Repr::EchoRequest { ident: IDENT, seq_no, data: PAYLOAD };
+        let mut bytes = vec![0u8; request.buffer_len()];
+        request.emit(&mut Icmpv4Packet::new_unchecked(&mut bytes), &checksums);
+        let mut sent = Ok(());
+        with_socket(&mut |socket| sent = socket.send_slice(&bytes, host));
+        sent.map_err(|_| PingError::Send(host))?;
+
+        let sent_at = Instant::now();
+        let mut rtt = None;
+        while sent_at.elapsed() < interval {
+            stack.work();
+            let mut answered = false;
+            with_socket(&mut |socket| {
+                while let Ok((data, from)) = socket.recv() {
+                    answered |= from == host && is_reply(data, seq_no, &checksums);
+                }
+            });
+            if answered && rtt.is_none() {
+                rtt = Some(sent_at.elapsed());
+            }
+        }
+        report.replies.push(rtt);
+    }
+    Ok(report)
+}
+
+fn is_reply(data: &[u8], seq: u16, checksums: &ChecksumCapabilities) -> bool {
+    let Ok(packet) = Icmpv4Packet::new_checked(data) else {
+        return false;
+    };
+    matches!(
+        Icmpv4Repr::parse(&packet, checksums),
+        Ok(Icmpv4Repr::EchoReply { ident: IDENT, seq_no, .. }) if seq_no == seq
+    )
+}
@@ -0,0 +1,200 @@
+use esp_hal::time::{Duration, Instant};
+use log::warn;
+
+use crate::{
+    clock,
+    config::{IdleConfig, OnHours, Schedule},
+    sdcard::SdStorage,
+    touch_input::TouchInputResponse,
+};
+
+pub(crate) const SCHEDULE_FILE: &str = "SCHEDULE.TXT";
+const DAY_NAMES: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+// how long a touch outside the schedule keeps the screen up when idle never turns it off
+const OVERRIDE_SECS: u16 = 300;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ScreenState {
+    Active,
+    Dimmed,
+    Off,
+}
+
+// tracks the last touch and steps the screen down Active -> Dimmed -> Off
+pub(crate) struct IdleTimeout {
+    config: IdleConfig,
+    last_activity: Instant,
+    state: ScreenState,
+    pending: Option<ScreenState>,
+    // the tap that wakes the screen must not also press whatever is under the finger
+    swallow_until_release: bool,
+    schedule: Option<Schedule>,
+    in_hours: bool,
+    schedule_checked: Option<Instant>,
+}
+
+impl IdleTimeout {
+    pub(crate) fn new(config: IdleConfig) -> Self {
+        Self {
+            config,
+            last_activity: Instant::now(),
+            state: ScreenState::Active,
+            pending: None,
+            swallow_until_release: false,
+            schedule: None,
+            in_hours: true,
+            schedule_checked: None,
+        }
+    }
+
+    pub(crate) fn state(&self) -> ScreenState {
+        self.state
+    }
+
+    pub(crate) fn set_config(&mut self, config: IdleConfig) {
+        self.config = config;
+        self.last_activity = Instant::now();
+    }
+
+    pub(crate) fn set_schedule(&mut self, schedule: Option<Schedule>) {
+        self.schedule = schedule;
+        self.schedule_checked = None;
+    }
+
+    // counts any touch as activity and hides the waking touch from the UI
+    pub(crate) fn filter(&mut self, input: TouchInputResponse) -> TouchInputResponse {
+        if matches!(input, TouchInputResponse::NoInput) {
+            return input;
+        }
+
+        self.last_activity = Instant::now();
+        if self.state != ScreenState::Active {
+            self.transition(ScreenState::Active);
+            self.swallow_until_release = true;
+        }
+
+        if self.swallow_until_release {
+            if matches!(input, TouchInputResponse::Released { .. }) {
+                self.swallow_until_release = false;
+            }
+            return TouchInputResponse::NoInput;
+        }
+        input
+    }
+
+    // any explicit wake, e.g. an alarm that should be seen
+    pub(crate) fn wake(&mut self) {
+        self.last_activity = Instant::now();
+        if self.state != ScreenState::Active {
+            self.transition(ScreenState::Active);
+        }
+    }
+
+    // returns the new state once per transition
+    pub(crate) fn poll(&mut self) -> Option<ScreenState> {
+        self.poll_schedule();
+
+        // outside hours a touch only overrides the schedule until the screen idles off again
+        let off_after = match self.config.off_after_secs {
+            0 if !self.in_hours => OVERRIDE_SECS,
+            secs => secs,
+        };
+        let idle = self.last_activity.elapsed();
+        let target = if reached(idle, off_after) {
+            ScreenState::Off
+        } else if reached(idle, self.config.dim_after_secs) {
+            ScreenState::Dimmed
+        } else {
+            ScreenState::Active
+        };
+
+        // timeouts only ever step down, waking goes through touch
+        let deeper = matches!(
+            (self.state, target),
+            (ScreenState::Active, ScreenState::Dimmed | ScreenState::Off)
+                | (ScreenState::Dimmed, ScreenState::Off)
+        );
+        if deeper {
+            self.transition(target);
+        }
+
+        self.pending.take()
+    }
+
+    // checked once a second, the windows have minute resolution anyway
+    fn poll_schedule(&mut self) {
+        let Some(schedule) = self.schedule else {
+            return;
+        };
+        if self.schedule_checked.is_some_and(|t| t.elapsed().as_secs() < 1) {
+            return;
+        }
+        self.schedule_checked = Some(Instant::now());
+
+        // without a synced clock the hours mean nothing, keep the screen usable
+        let in_hours = clock::local_now()
+            .map(|now| schedule.is_on(now.weekday, now.hour as u16 * 60 + now.minute as u16))
+            .unwrap_or(true);
+        if in_hours == self.in_hours {
+            return;
+        }
+        self.in_hours = in_hours;
+        if in_hours {
+            self.wake();
+        } else if self.state != ScreenState::Off {
+            self.transition(ScreenState::Off);
+        }
+    }
+
+    fn transition(&mut self, state: ScreenState) {
+        self.state = state;
+        self.pending = Some(state);
+    }
+}
+
+fn reached(idle: Duration, after_secs: u16) -> bool {
+    after_secs != 0 && idle.as_secs() >= after_secs as u64
+}
+
+// `<day|daily> <hh:mm>-<hh:mm>` or `<day> off` per line, days not mentioned stay off;
+// imported when flash holds no schedule
+pub(crate) fn load_schedule(sd: &SdStorage<'_>) -> Option<Schedule> {
+    let file = sd.read_config(SCHEDULE_FILE).ok()?;
+    let mut schedule = Schedule::default();
+    for line in file.lines() {
+        let Some((day, hours)) = line.split_once(' ') else {
+            warn!("Schedule: malformed line {}", line);
+            continue;
+        };
+        let hours = match hours.trim() {
+            "off" => None,
+            range => match parse_range(range) {
+                Some(hours) => Some(hours),
+                None => {
+                    warn!("Schedule: malformed line {}", line);
+                    continue;
+                }
+            },
+        };
+        match day {
+            "daily" => schedule.days = [hours; 7],
+            day => match DAY_NAMES.iter().position(|name| *name == day) {
+                Some(i) => schedule.days[i] = hours,
+                None => warn!("Schedule: unknown day {}", day),
+            },
+        }
+    }
+    Some(schedule)
+}
+
+// `hh:mm-hh:mm`, also night mode's hours
+pub(crate) fn parse_range(range: &str) -> Option<OnHours> {
+    let (on, off) = range.split_once('-')?;
+    Some(OnHours { on: parse_time(on)?, off: parse_time(off)? })
+}
+
+fn parse_time(time: &str) -> Option<u16> {
+    let (hour, minute) = time.trim().split_once(':')?;
+    let (hour, minute): (u16, u16) = (hour.parse().ok()?, minute.parse().ok()?);
+    (hour < 24 && minute < 60).then_some(hour * 60 + minute)
+}
@@ -0,0 +1,43 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+use esp_hal::{handler, peripherals::LPWR, rtc_cntl::Rtc};
+
+static POWER_FAIL: AtomicBool = AtomicBool::new(false);
+
+// ESP32 thresholds, 0 = ~2.43V .. 7 = ~2.80V
+pub(crate) const DEFAULT_THRESHOLD: u8 = 7;
+
+// The detector interrupts right away and still resets the chip, `rst_wait` slow clock cycles
+// later (~7 ms at 0x3ff). That grace window on the bulk capacitance is all the main loop gets,
+// so what it writes then has to survive being cut off: the settings go to the spare of their two
+// slots and the outbox is appended, nothing is rewritten in place.
+pub(crate) fn arm(rtc: &mut Rtc<'_>, threshold: u8) {
+    let regs = LPWR::regs();
+    regs.brown_out().write(|w| unsafe {
+        w.thres().bits(threshold.min(7));
+        w.rst_wait().bits(0x3ff);
+        w.rst_ena().set_bit();
+        w.close_flash_ena().clear_bit();
+        // the radio is the biggest consumer, drop it right away
+        w.pd_rf_ena().set_bit();
+        w.ena().set_bit()
+    });
+
+    rtc.set_interrupt_handler(brown_out_handler);
+    regs.int_clr().write(|w| w.brown_out().clear_bit_by_one());
+    regs.int_ena().modify(|_, w| w.brown_out().set_bit());
+}
+
+pub(crate) fn pending() -> bool {
+    POWER_FAIL.load(Ordering::Relaxed)
+}
+
+#[handler]
+fn brown_out_handler() {
+    let regs = LPWR::regs();
+    if regs.int_st().read().brown_out().bit_is_set() {
+        // one-shot, the supply only goes down from here
+        regs.int_ena().modify(|_, w| w.brown_out().clear_bit());
+        regs.int_clr().write(|w| w.brown_out().clear_bit_by_one());
+        POWER_FAIL.store(true, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,345 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+};
+use blocking_network_stack::{Socket, Stack};
+use embedded_io::Write;
+use esp_hal::time::Instant;
+use esp_radio::wifi::WifiDevice;
+use log::info;
+use smoltcp::iface::SocketStorage;
+use thiserror::Error;
+
+use crate::{
+    config::{ServerConfig, StaticIp},
+    config_sync::parse_ipv4,
+    http_server,
+    sdcard::SdStorage,
+    wifi::{self, AP_IP, Wifi, WifiError},
+};
+
+pub(crate) const PORTAL_SSID: &str = "esp32-display-setup";
+pub(crate) const PORTAL_PORT: u16 = 80;
+const CREDENTIALS_FILE: &str = "WIFI.TXT";
+
+const FORM_PAGE: &str = "<!DOCTYPE html><html><body><h3>WiFi setup</h3>\
+<form method=\"POST\" action=\"/\">\
+SSID <input name=\"ssid\"><br>\
+Password <input name=\"password\" type=\"password\"><br>\
+Server host <input name=\"server_host\"><br>\
+Server IP <input name=\"server_ip\"><br>\
+Static IP <input name=\"static_ip\" placeholder=\"192.168.1.50/24, blank for DHCP\"><br>\
+Gateway <input name=\"gateway\"><br>\
+DNS <input name=\"dns\"><br>\
+DHCP first for <input name=\"dhcp_secs\" value=\"0\"> s<br>\
+<input type=\"submit\" value=\"Save\"></form></body></html>";
+const SAVED_PAGE: &str = "<!DOCTYPE html><html><body><h3>Saved, connecting...</h3></body></html>";
+
+#[derive(Error, Debug)]
+pub(crate) enum ProvisioningError {
+    #[error("Malformed credentials")]
+    Malformed,
+    #[error("Malformed server address")]
+    MalformedServer,
+    #[error("Malformed static IP configuration")]
+    MalformedStaticIp,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Credentials {
+    pub(crate) ssid: heapless::String<32>,
+    pub(crate) password: heapless::String<64>,
+}
+
+impl Credentials {
+    pub(crate) fn new(ssid: &str, password: &str) -> Result<Self, ProvisioningError> {
+        if ssid.is_empty() {
+            return Err(ProvisioningError::Malformed);
+        }
+
+        Ok(Self {
+            ssid: heapless::String::try_from(ssid).map_err(|_| ProvisioningError::Malformed)?,
+            password: heapless::String::try_from(password)
+                .map_err(|_| ProvisioningError::Malformed)?,
+        })
+    }
+}
+
+// what the portal hands back; the server part is optional and only overrides when given, the
+// static IP replaces what was set before, DHCP when left blank
+pub(crate) struct Submission {
+    pub(crate) credentials: Credentials,
+    pub(crate) server: Option<ServerConfig>,
+    pub(crate) static_ip: Option<StaticIp>,
+}
+
+// the static IP fields as typed into the form
+struct StaticFields<'f> {
+    address: &'f str,
+    gateway: &'f str,
+    dns: &'f str,
+    dhcp_secs: &'f str,
+}
+
+pub(crate) trait CredentialStore {
+    fn load(&self) -> Option<Credentials>;
+}
+
+// credentials dropped on the card as "ssid\npassword", imported when flash holds none
+pub(crate) struct SdCredentialStore<'s, 'a> {
+    sd: &'s SdStorage<'a>,
+}
+
+impl<'s, 'a> SdCredentialStore<'s, 'a> {
+    pub(crate) fn new(sd: &'s SdStorage<'a>) -> Self {
+        Self { sd }
+    }
+}
+
+impl CredentialStore for SdCredentialStore<'_, '_> {
+    fn load(&self) -> Option<Credentials> {
+        let mut buf = [0u8; 128];
+        let n = self.sd.read_file(CREDENTIALS_FILE, &mut buf).ok()?;
+        let text = core::str::from_utf8(&buf[..n]).ok()?;
+        let (ssid, password) = text.split_once('\n')?;
+        Credentials::new(ssid.trim(), password.trim()).ok()
+    }
+}
+
+// brings up the SoftAP, waits for credentials and tears the AP down again
+pub(crate) fn provision(wifi: &mut Wifi<'_>, rng_seed: u32) -> Result<Submission, WifiError> {
+    wifi.start_access_point(PORTAL_SSID)?;
+
+    let mut sockets_buf: [SocketStorage; 2] = Default::default();
+    let stack = wifi::build_ap_stack(
+        wifi.take_ap(),
+        &mut sockets_buf,
+        || Instant::now().duration_since_epoch().as_millis(),
+        rng_seed,
+    )?;
+    info!(
+        "Join '{}' and open http://{}.{}.{}.{}/",
+        PORTAL_SSID, AP_IP[0], AP_IP[1], AP_IP[2], AP_IP[3]
+    );
+
+    let submission = run_portal(&stack)?;
+    wifi.stop()?;
+    Ok(submission)
+}
+
+// serves the setup form until valid credentials are submitted (form or JSON body)
+pub(crate) fn run_portal(
+    stack: &Stack<'_, WifiDevice<'_>>,
+) -> Result<Submission, WifiError> {
+    let mut rx_buf = [0u8; 1536];
+    let mut tx_buf = [0u8; 1536];
+    let mut socket = stack.get_socket(&mut rx_buf, &mut tx_buf);
+
+    info!("Provisioning portal listening on port {}", PORTAL_PORT);
+    loop {
+        socket.work();
+        if !socket.is_open() {
+            socket.listen(PORTAL_PORT).map_err(|e| WifiError::Portal(format!("{:?}", e)))?;
+        }
+
+        if !socket.is_connected() {
+            continue;
+        }
+
+        let mut request = [0u8; 1024];
+        let submission = match http_server::read_request(&mut socket, &mut request) {
+            Ok(len) => core::str::from_utf8(&request[..len])
+                .ok()
+                .and_then(|req| handle_request(&mut socket, req)),
+            Err(status) => {
+                respond(&mut socket, status, status);
+                None
+            }
+        };
+
+        let _ = socket.flush();
+        socket.close();
+        if let Some(submission) = submission {
+            return Ok(submission);
+        }
+    }
+}
+
+fn handle_request(
+    socket: &mut Socket<'_, '_, WifiDevice<'_>>,
+    request: &str,
+) -> Option<Submission> {
+    let (head, body) = request.split_once("\r\n\r\n").unwrap_or((request, ""));
+
+    if head.starts_with("POST") {
+        let parsed = if body.trim_start().starts_with('{') {
+            parse_json(body)
+        } else {
+            parse_form(body)
+        };
+
+        return match parsed {
+            Ok(submission) => {
+                respond(socket, "200 OK", SAVED_PAGE);
+                Some(submission)
+            }
+            Err(e) => {
+                respond(socket, "400 Bad Request", &e.to_string());
+                None
+            }
+        };
+    }
+
+    // every other path gets the form, which is what captive portal checks expect
+    respond(socket, "200 OK", FORM_PAGE);
+    None
+}
+
+fn respond(socket: &mut Socket<'_, '_, WifiDevice<'_>>, status: &str, body: &str) {
+    http_server::respond(socket, status, "text/html", body.as_bytes());
+}
+
+fn parse_form(body: &str) -> Result<Submission, ProvisioningError> {
+    let mut ssid = String::new();
+    let mut password = String::new();
+    let mut server_host = String::new();
+    let mut server_ip = String::new();
+    let mut static_ip = String::new();
+    let mut gateway = String::new();
+    let mut dns = String::new();
+    let mut dhcp_secs = String::new();
+    for pair in body.trim().split('&') {
+        match pair.split_once('=') {
+            Some(("ssid", value)) => ssid = url_decode(value),
+            Some(("password", value)) => password = url_decode(value),
+            Some(("server_host", value)) => server_host = url_decode(value),
+            Some(("server_ip", value)) => server_ip = url_decode(value),
+            Some(("static_ip", value)) => static_ip = url_decode(value),
+            Some(("gateway", value)) => gateway = url_decode(value),
+            Some(("dns", value)) => dns = url_decode(value),
+            Some(("dhcp_secs", value)) => dhcp_secs = url_decode(value),
+            _ => {}
+        }
+    }
+
+    Ok(Submission {
+        credentials: Credentials::new(&ssid, &password)?,
+        server: parse_server(&server_host, &server_ip)?,
+        static_ip: parse_static_ip(StaticFields {
+            address: &static_ip,
+            gateway: &gateway,
+            dns: &dns,
+            dhcp_secs: &dhcp_secs,
+        })?,
+    })
+}
+
+// `a.b.c.d/prefix` (24 without one); the gateway is required, DNS optional
+fn parse_static_ip(fields: StaticFields<'_>) -> Result<Option<StaticIp>, ProvisioningError> {
+    let address = fields.address.trim();
+    if address.is_empty() {
+        return Ok(None);
+    }
+    let (address, prefix_len): (&str, u8) = match address.split_once('/') {
+        Some((address, prefix)) => (
+            address,
+            prefix.parse().map_err(|_| ProvisioningError::MalformedStaticIp)?,
+        ),
+        None => (address, 24),
+    };
+    let dns = match fields.dns.trim() {
+        "" => None,
+        dns => Some(parse_ipv4(dns).ok_or(ProvisioningError::MalformedStaticIp)?),
+    };
+    let dhcp_secs = match fields.dhcp_secs.trim() {
+        "" => 0,
+        secs => secs.parse().map_err(|_| ProvisioningError::MalformedStaticIp)?,
+    };
+    if !(1..=32).contains(&prefix_len) {
+        return Err(ProvisioningError::MalformedStaticIp);
+    }
+
+    Ok(Some(StaticIp {
+        address: parse_ipv4(address).ok_or(ProvisioningError::MalformedStaticIp)?,
+        prefix_len,
+        gateway: parse_ipv4(fields.gateway.trim()).ok_or(ProvisioningError::MalformedStaticIp)?,
+        dns,
+        dhcp_secs,
+    }))
+}
+
+fn parse_server(host: &str, ip: &str) -> Result<Option<ServerConfig>, ProvisioningError> {
+    if host.is_empty() && ip.is_empty() {
+        return Ok(None);
+    }
+
+    let mut octets = [0u8; 4];
+    let mut parts = ip.trim().split('.');
+    for octet in octets.iter_mut() {
+        *octet = parts
+            .next()
+            .and_then(|p| p.parse().ok())
+            .ok_or(ProvisioningError::MalformedServer)?;
+    }
+    if parts.next().is_some() {
+        return Err(ProvisioningError::MalformedServer);
+    }
+
+    Ok(Some(ServerConfig {
+        host: heapless::String::try_from(host).map_err(|_| ProvisioningError::MalformedServer)?,
+        ip: Some(octets),
+    }))
+}
+
+fn url_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = alloc::vec::Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = core::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+                match u8::from_str_radix(hex, 16) {
+                    Ok(b) => {
+                        out.push(b);
+                        i += 2;
+                    }
+                    Err(_) => out.push(b'%'),
+                }
+            }
+            b => out.push(b),
+        }
+        i += 1;
+    }
+
+    String::from_utf8(out).unwrap_or_default()
+}
+
+// minimal extraction of {"ssid": "...", "password": "...", ...}, escapes are not supported
+fn parse_json(body: &str) -> Result<Submission, ProvisioningError> {
+    let ssid = json_string_field(body, "ssid").ok_or(ProvisioningError::Malformed)?;
+    let password = json_string_field(body, "password").unwrap_or("");
+    let server_host = json_string_field(body, "server_host").unwrap_or("");
+    let server_ip = json_string_field(body, "server_ip").unwrap_or("");
+    let field = |key| json_string_field(body, key).unwrap_or("");
+
+    Ok(Submission {
+        credentials: Credentials::new(ssid, password)?,
+        server: parse_server(server_host, server_ip)?,
+        static_ip: parse_static_ip(StaticFields {
+            address: field("static_ip"),
+            gateway: field("gateway"),
+            dns: field("dns"),
+            dhcp_secs: field("dhcp_secs"),
+        })?,
+    })
+}
+
+fn json_string_field<'b>(body: &'b str, key: &str) -> Option<&'b str> {
+    let quoted = alloc::format!("\"{}\"", key);
+    let after_key = &body[body.find(&quoted)? + quoted.len()..];
+    let after_colon = after_key.trim_start().strip_prefix(':')?.trim_start();
+    let value = after_colon.strip_prefix('"')?;
+    Some(&value[..value.find('"')?])
+}
@@ -0,0 +1,36 @@
+use alloc::vec;
+use qrcodegen_no_heap::{QrCode, QrCodeEcc, Version};
+use slint::{Image, Rgb8Pixel, SharedPixelBuffer};
+
+// version 6 holds ~130 characters at low error correction, plenty for a verification URL
+const MAX_VERSION: u8 = 6;
+// the quiet zone scanners need around the symbol, in modules
+const BORDER: u32 = 2;
+
+// one pixel per module, the UI scales it up with pixelated rendering
+pub(crate) fn encode(text: &str) -> Option<Image> {
+    let len = Version::new(MAX_VERSION).buffer_len();
+    let (mut temp, mut out) = (vec![0u8; len], vec![0u8; len]);
+    let qr = QrCode::encode_text(
+        text,
+        &mut temp,
+        &mut out,
+        QrCodeEcc::Low,
+        Version::MIN,
+        Version::new(MAX_VERSION),
+        None,
+        true,
+    )
+    .ok()?;
+
+    let size = qr.size() as u32 + 2 * BORDER;
+    let mut buffer = SharedPixelBuffer::<Rgb8Pixel>::new(size, size);
+    for (i, pixel) in buffer.make_mut_slice().iter_mut().enumerate() {
+        let x = (i as u32 % size) as i32 - BORDER as i32;
+        let y = (i as u32 / size) as i32 - BORDER as i32;
+        // out of range modules read as light, which is the border
+        let v = if qr.get_module(x, y) { 0 } else { 255 };
+        *pixel = Rgb8Pixel::new(v, v, v);
+    }
+    Some(Image::from_rgb8(buffer))
+}
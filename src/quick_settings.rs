@@ -0,0 +1,34 @@
+// The pull-down over whatever page is up: a swipe down from the top edge opens it, the back
+// button, the back swipe or a tap below it closes it. It is the backend's overlay, the page
+// stack doesn't know about it and the page under it stays as it was. Brightness is the same
+// setting as on the settings page; rotation lock and mute are kept in the settings, WiFi off
+// lasts until it is turned on again or the next boot.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct QuickState {
+    pub(crate) open: bool,
+    // the rotate and mirror buttons do nothing while set
+    pub(crate) rotation_locked: bool,
+    pub(crate) wifi_off: bool,
+    // the buzzer stays quiet, alarms included
+    pub(crate) muted: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum QuickCommand {
+    Close,
+    RotationLock,
+    Wifi,
+    Mute,
+}
+
+impl QuickState {
+    // the caller carries out what changed
+    pub(crate) fn apply(self, command: QuickCommand) -> Self {
+        match command {
+            QuickCommand::Close => Self { open: false, ..self },
+            QuickCommand::RotationLock => Self { rotation_locked: !self.rotation_locked, ..self },
+            QuickCommand::Wifi => Self { wifi_off: !self.wifi_off, ..self },
+            QuickCommand::Mute => Self { muted: !self.muted, ..self },
+        }
+    }
+}
@@ -0,0 +1,228 @@
+// Stability in numbers, kept in the settings across restarts and power cuts: how long the unit
+// has run, boots, crashes, WiFi reconnects and SD card errors, counted since the running firmware
+// was flashed and kept for the build before it, so an update can be held against what it
+// replaced. A crash is a boot after a panic or a watchdog reset, pulling the plug or restarting
+// on purpose isn't one; the crash-free uptime is what has run since the last. Uptime is folded
+// in every VIEW_SECS and written out with the settings once an hour and on the way down, a
+// power cut loses at most the hour. The panic flag sits in RTC memory, it only survives the
+// resets that leave RTC memory alone (EN after the panic screen usually does).
+use alloc::{format, string::String, vec::Vec};
+use core::fmt::Write as _;
+use esp_hal::{
+    rtc_cntl::{SocResetReason, reset_reason},
+    system::Cpu,
+    time::{Duration, Instant},
+};
+
+use crate::{build_info, sdcard};
+
+// RTC fast memory holds noise after power-on, the flag only counts with this in it
+const PANIC_MAGIC: u32 = 0xDEAD_B007;
+const SAVE_INTERVAL: Duration = Duration::from_secs(3600);
+const VIEW_SECS: u64 = 10;
+const COUNTERS_LEN: usize = 24;
+
+#[esp_hal::ram(unstable(rtc_fast, persistent))]
+static mut PANICKED: u32 = 0;
+
+// from the panic handler, the next boot counts the crash
+pub(crate) fn record_panic() {
+    unsafe { PANICKED = PANIC_MAGIC };
+}
+
+// true once per crash, on the boot after it
+fn take_crash() -> bool {
+    let panicked = unsafe { PANICKED } == PANIC_MAGIC;
+    unsafe { PANICKED = 0 };
+    let watchdog = matches!(
+        reset_reason(Cpu::ProCpu),
+        Some(
+            SocResetReason::CoreMwdt0
+                | SocResetReason::CoreMwdt1
+                | SocResetReason::CoreRtcWdt
+                | SocResetReason::SysRtcWdt
+        )
+    );
+    panicked || watchdog
+}
+
+// the running build, what the counters are kept per
+fn build() -> heapless::String<32> {
+    let mut build = heapless::String::new();
+    let _ = write!(build, "{} {}", build_info::PKG_VERSION, build_info::GIT_HASH);
+    build
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct ReliabilityStats {
+    pub(crate) build: heapless::String<32>,
+    pub(crate) uptime_secs: u32,
+    // since the last crash, or since the build was flashed
+    pub(crate) crash_free_secs: u32,
+    pub(crate) boots: u32,
+    pub(crate) crashes: u32,
+    pub(crate) reconnects: u32,
+    pub(crate) sd_errors: u32,
+}
+
+impl ReliabilityStats {
+    // the counters, then the build's name
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let counters = [
+            self.uptime_secs,
+            self.crash_free_secs,
+            self.boots,
+            self.crashes,
+            self.reconnects,
+            self.sd_errors,
+        ];
+        let mut bytes: Vec<u8> = counters.iter().flat_map(|c| c.to_le_bytes()).collect();
+        bytes.extend_from_slice(self.build.as_bytes());
+        bytes
+    }
+
+    pub(crate) fn decode(value: &[u8]) -> Option<Self> {
+        let (counters, build) = value.split_at_checked(COUNTERS_LEN)?;
+        let mut counters = counters
+            .chunks_exact(4)
+            .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]));
+        let mut next = || counters.next().unwrap_or(0);
+        Some(Self {
+            uptime_secs: next(),
+            crash_free_secs: next(),
+            boots: next(),
+            crashes: next(),
+            reconnects: next(),
+            sd_errors: next(),
+            build: heapless::String::try_from(core::str::from_utf8(build).ok()?).ok()?,
+        })
+    }
+
+    // hours of uptime per crash, all of it while there were none
+    pub(crate) fn hours_per_crash(&self) -> f32 {
+        self.uptime_secs as f32 / 3600.0 / self.crashes.max(1) as f32
+    }
+
+    pub(crate) fn to_json(&self) -> String {
+        format!(
+            "{{\"build\":\"{}\",\"uptime\":{},\"crash_free\":{},\"boots\":{},\"crashes\":{},\
+            \"reconnects\":{},\"sd_errors\":{}}}",
+            self.build,
+            self.uptime_secs,
+            self.crash_free_secs,
+            self.boots,
+            self.crashes,
+            self.reconnects,
+            self.sd_errors,
+        )
+    }
+}
+
+// the running build's counters and the last ones of the build before
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct ReliabilityRecord {
+    pub(crate) current: ReliabilityStats,
+    pub(crate) previous: Option<ReliabilityStats>,
+}
+
+impl ReliabilityRecord {
+    // the report page's table: what is counted, this build, the one before
+    pub(crate) fn rows(&self) -> Vec<(&'static str, String, String)> {
+        fn hours(secs: u32) -> String {
+            format!("{:.1} h", secs as f32 / 3600.0)
+        }
+        let fields: [(&'static str, fn(&ReliabilityStats) -> String); 7] = [
+            ("Uptime", |s| hours(s.uptime_secs)),
+            ("Crash-free", |s| hours(s.crash_free_secs)),
+            ("Boots", |s| format!("{}", s.boots)),
+            ("Crashes", |s| format!("{}", s.crashes)),
+            ("Hours per crash", |s| format!("{:.1}", s.hours_per_crash())),
+            ("Reconnects", |s| format!("{}", s.reconnects)),
+            ("SD errors", |s| format!("{}", s.sd_errors)),
+        ];
+        fields
+            .iter()
+            .map(|(label, value)| {
+                let previous = self.previous.as_ref().map_or(String::from("-"), value);
+                (*label, value(&self.current), previous)
+            })
+            .collect()
+    }
+}
+
+pub(crate) struct Reliability {
+    record: ReliabilityRecord,
+    // uptime and card errors already in the counters
+    counted_secs: u64,
+    counted_sd_errors: u32,
+    viewed_at: Instant,
+    saved_at: Option<Instant>,
+}
+
+impl Reliability {
+    // once per boot, with what the settings had
+    pub(crate) fn start(mut record: ReliabilityRecord) -> Self {
+        let build = build();
+        if record.current.build != build {
+            let replaced = core::mem::take(&mut record.current);
+            if replaced.boots > 0 {
+                record.previous = Some(replaced);
+            }
+            record.current.build = build;
+        }
+        let current = &mut record.current;
+        current.boots = current.boots.saturating_add(1);
+        if take_crash() {
+            current.crashes = current.crashes.saturating_add(1);
+            current.crash_free_secs = 0;
+        }
+        Self {
+            record,
+            counted_secs: 0,
+            counted_sd_errors: 0,
+            viewed_at: Instant::now(),
+            saved_at: None,
+        }
+    }
+
+    pub(crate) fn reconnected(&mut self) {
+        let current = &mut self.record.current;
+        current.reconnects = current.reconnects.saturating_add(1);
+    }
+
+    fn fold(&mut self) {
+        let uptime = Instant::now().duration_since_epoch().as_secs();
+        let secs = (uptime - self.counted_secs) as u32;
+        let sd_errors = sdcard::errors();
+        let current = &mut self.record.current;
+        current.uptime_secs = current.uptime_secs.saturating_add(secs);
+        current.crash_free_secs = current.crash_free_secs.saturating_add(secs);
+        current.sd_errors = current.sd_errors.saturating_add(sd_errors - self.counted_sd_errors);
+        self.counted_secs = uptime;
+        self.counted_sd_errors = sd_errors;
+    }
+
+    // the counters as they are now
+    pub(crate) fn record(&mut self) -> ReliabilityRecord {
+        self.fold();
+        self.record.clone()
+    }
+
+    // every VIEW_SECS, for the report page
+    pub(crate) fn poll(&mut self) -> Option<ReliabilityRecord> {
+        if self.viewed_at.elapsed().as_secs() < VIEW_SECS {
+            return None;
+        }
+        self.viewed_at = Instant::now();
+        Some(self.record())
+    }
+
+    // what to write to the settings, right after boot and then once an hour
+    pub(crate) fn take_save(&mut self) -> Option<ReliabilityRecord> {
+        if self.saved_at.is_some_and(|at| at.elapsed() < SAVE_INTERVAL) {
+            return None;
+        }
+        self.saved_at = Some(Instant::now());
+        Some(self.record())
+    }
+}
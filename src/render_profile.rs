@@ -0,0 +1,123 @@
+// Frame times by the page that was on screen, logged every minute with the most expensive pages
+// first so it is clear which screen makes the ESP32 chug. The first frame after a switch redraws
+// everything and is kept apart from the page's steady state.
+use alloc::{format, string::String, vec::Vec};
+use core::fmt::Write;
+use esp_hal::time::{Duration, Instant};
+
+use crate::navigation::Page;
+
+const REPORT_INTERVAL: Duration = Duration::from_secs(60);
+// pages listed per report
+const WORST_PAGES: usize = 3;
+
+struct PageStats {
+    // None is the loading screen
+    page: Option<Page>,
+    frames: u32,
+    total_us: u64,
+    worst_us: u64,
+    over_budget: u32,
+    // the longest first frame after switching to the page
+    switch_us: u64,
+}
+
+impl PageStats {
+    fn name(&self) -> &'static str {
+        self.page.map_or("loading", |page| page.name())
+    }
+
+    fn mean_us(&self) -> u64 {
+        self.total_us.checked_div(self.frames as u64).unwrap_or(0)
+    }
+}
+
+pub(crate) struct RenderProfile {
+    budget_us: u64,
+    pages: Vec<PageStats>,
+    page: Option<Page>,
+    // the next frame is the first of `page`
+    switched: bool,
+    since: Instant,
+}
+
+impl RenderProfile {
+    pub(crate) fn new(frame_budget: Duration) -> Self {
+        Self {
+            budget_us: frame_budget.as_micros(),
+            pages: Vec::new(),
+            page: None,
+            switched: true,
+            since: Instant::now(),
+        }
+    }
+
+    pub(crate) fn set_page(&mut self, page: Option<Page>) {
+        if page != self.page {
+            self.page = page;
+            self.switched = true;
+        }
+    }
+
+    pub(crate) fn rendered(&mut self, took: Duration) {
+        let page = self.page;
+        let index = match self.pages.iter().position(|s| s.page == page) {
+            Some(index) => index,
+            None => {
+                self.pages.push(PageStats {
+                    page,
+                    frames: 0,
+                    total_us: 0,
+                    worst_us: 0,
+                    over_budget: 0,
+                    switch_us: 0,
+                });
+                self.pages.len() - 1
+            }
+        };
+        let stats = &mut self.pages[index];
+        let us = took.as_micros();
+        if core::mem::take(&mut self.switched) {
+            stats.switch_us = stats.switch_us.max(us);
+            return;
+        }
+        stats.frames += 1;
+        stats.total_us += us;
+        stats.worst_us = stats.worst_us.max(us);
+        if us > self.budget_us {
+            stats.over_budget += 1;
+        }
+    }
+
+    // the report line once per REPORT_INTERVAL with anything drawn in it, then starts over
+    pub(crate) fn report(&mut self) -> Option<String> {
+        if self.since.elapsed() < REPORT_INTERVAL {
+            return None;
+        }
+        self.since = Instant::now();
+        if self.pages.is_empty() {
+            return None;
+        }
+        self.pages.sort_by_key(|s| core::cmp::Reverse((s.mean_us(), s.switch_us)));
+        let mut line = String::from("render:");
+        for (i, stats) in self.pages.iter().take(WORST_PAGES).enumerate() {
+            let _ = write!(
+                line,
+                "{} {} {}.{} ms avg, {} ms worst, {}/{} over budget, switch {} ms",
+                if i == 0 { "" } else { ";" },
+                stats.name(),
+                stats.mean_us() / 1000,
+                stats.mean_us() % 1000 / 100,
+                stats.worst_us / 1000,
+                stats.over_budget,
+                stats.frames,
+                stats.switch_us / 1000,
+            );
+        }
+        if self.pages.len() > WORST_PAGES {
+            line.push_str(&format!("; {} more", self.pages.len() - WORST_PAGES));
+        }
+        self.pages.clear();
+        Some(line)
+    }
+}
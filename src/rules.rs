@@ -0,0 +1,262 @@
+use alloc::{string::String, vec::Vec};
+use core::fmt;
+use esp_hal::time::{Duration, Instant};
+use log::warn;
+use thiserror::Error;
+
+use crate::sdcard::SdStorage;
+
+pub(crate) const RULES_FILE: &str = "RULES.TXT";
+pub(crate) const MAX_RULES: usize = 8;
+
+#[derive(Error, Debug)]
+pub(crate) enum RuleError {
+    #[error("Malformed rule: {0}")]
+    Malformed(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Comparison {
+    Above,
+    Below,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum RuleAction {
+    Notify,
+    // index into the alarm outputs, driven high while the rule is breached; numbers past them
+    // name a spare pin bound on the GPIO page
+    Output(u8),
+    // endpoint name from WEBHOOKS.TXT, or a bare route posted with the default template
+    Webhook(heapless::String<48>),
+}
+
+// `<channel> <'>'|'<'> <limit> for <secs> <notify | output <n> | webhook <endpoint|route>>`
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Rule {
+    pub(crate) channel: heapless::String<16>,
+    pub(crate) comparison: Comparison,
+    pub(crate) limit: f32,
+    pub(crate) hold_secs: u16,
+    pub(crate) action: RuleAction,
+}
+
+impl Rule {
+    pub(crate) fn parse(line: &str) -> Result<Self, RuleError> {
+        let malformed = || RuleError::Malformed(line.into());
+        let mut parts = line.split_whitespace();
+
+        let channel = heapless::String::try_from(parts.next().ok_or_else(malformed)?)
+            .map_err(|_| malformed())?;
+        let comparison = match parts.next() {
+            Some(">") => Comparison::Above,
+            Some("<") => Comparison::Below,
+            _ => return Err(malformed()),
+        };
+        let limit = parts.next().and_then(|v| v.parse().ok()).ok_or_else(malformed)?;
+
+        let mut hold_secs = 0;
+        let mut next = parts.next();
+        if next == Some("for") {
+            hold_secs = parts.next().and_then(|v| v.parse().ok()).ok_or_else(malformed)?;
+            next = parts.next();
+        }
+
+        let action = match (next, parts.next()) {
+            (Some("notify"), None) => RuleAction::Notify,
+            (Some("output"), Some(n)) => RuleAction::Output(n.parse().map_err(|_| malformed())?),
+            (Some("webhook"), Some(route)) => {
+                RuleAction::Webhook(heapless::String::try_from(route).map_err(|_| malformed())?)
+            }
+            _ => return Err(malformed()),
+        };
+
+        Ok(Self { channel, comparison, limit, hold_secs, action })
+    }
+
+    fn breached(&self, value: f32) -> bool {
+        match self.comparison {
+            Comparison::Above => value > self.limit,
+            Comparison::Below => value < self.limit,
+        }
+    }
+
+    // [comparison, limit f32, hold u16, channel len, channel.., action, arg..]
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.push(self.comparison as u8);
+        out.extend_from_slice(&self.limit.to_le_bytes());
+        out.extend_from_slice(&self.hold_secs.to_le_bytes());
+        out.push(self.channel.len() as u8);
+        out.extend_from_slice(self.channel.as_bytes());
+        match &self.action {
+            RuleAction::Notify => out.push(0),
+            RuleAction::Output(n) => out.extend_from_slice(&[1, *n]),
+            RuleAction::Webhook(route) => {
+                out.push(2);
+                out.extend_from_slice(route.as_bytes());
+            }
+        }
+        out
+    }
+
+    pub(crate) fn decode(value: &[u8]) -> Option<Self> {
+        let [comparison, l0, l1, l2, l3, h0, h1, channel_len, rest @ ..] = value else {
+            return None;
+        };
+        let comparison = match comparison {
+            0 => Comparison::Above,
+            1 => Comparison::Below,
+            _ => return None,
+        };
+        let (channel, rest) = rest.split_at_checked(*channel_len as usize)?;
+        let channel = heapless::String::try_from(core::str::from_utf8(channel).ok()?).ok()?;
+        let action = match rest {
+            [0] => RuleAction::Notify,
+            [1, n] => RuleAction::Output(*n),
+            [2, route @ ..] => RuleAction::Webhook(
+                heapless::String::try_from(core::str::from_utf8(route).ok()?).ok()?,
+            ),
+            _ => return None,
+        };
+
+        Some(Self {
+            channel,
+            comparison,
+            limit: f32::from_le_bytes([*l0, *l1, *l2, *l3]),
+            hold_secs: u16::from_le_bytes([*h0, *h1]),
+            action,
+        })
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self.comparison {
+            Comparison::Above => '>',
+            Comparison::Below => '<',
+        };
+        write!(f, "{} {} {} for {}s", self.channel, op, self.limit, self.hold_secs)
+    }
+}
+
+// one rule per line, '#' starts a comment; imported when flash holds no rules
+pub(crate) fn load_from_sd(sd: &SdStorage<'_>) -> Vec<Rule> {
+    let Ok(file) = sd.read_config(RULES_FILE) else {
+        return Vec::new();
+    };
+    file.lines()
+        .filter_map(|line| {
+            Rule::parse(line)
+                .inspect_err(|e| warn!("Rules: {}", e))
+                .ok()
+        })
+        .take(MAX_RULES)
+        .collect()
+}
+
+// the channels the firmware reads itself, only those some rule names are read; the rest come
+// from pins, sensors, the meter, scripts and the ffi
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Builtin {
+    Wifi,
+    Brightness,
+    HeapFree,
+    CpuLoad,
+    Battery,
+}
+
+impl Builtin {
+    const ALL: [Builtin; 5] =
+        [Builtin::Wifi, Builtin::Brightness, Builtin::HeapFree, Builtin::CpuLoad, Builtin::Battery];
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Builtin::Wifi => "wifi",
+            Builtin::Brightness => "brightness",
+            Builtin::HeapFree => "heap_free",
+            Builtin::CpuLoad => "cpu_load",
+            Builtin::Battery => "battery",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum RuleEvent {
+    Breached { rule: Rule, value: f32 },
+    Cleared { rule: Rule, value: f32 },
+}
+
+struct RuleState {
+    rule: Rule,
+    breached_since: Option<Instant>,
+    active: bool,
+}
+
+// channels are plain named numbers, whoever owns a value reports it through `observe`
+pub(crate) struct RuleEngine {
+    rules: Vec<RuleState>,
+    values: Vec<(heapless::String<16>, f32)>,
+    builtins: Vec<Builtin>,
+}
+
+impl RuleEngine {
+    pub(crate) fn new(rules: &[Rule]) -> Self {
+        let builtins = Builtin::ALL
+            .into_iter()
+            .filter(|builtin| rules.iter().any(|rule| rule.channel == builtin.name()))
+            .collect();
+        Self {
+            rules: rules
+                .iter()
+                .map(|rule| RuleState { rule: rule.clone(), breached_since: None, active: false })
+                .collect(),
+            values: Vec::new(),
+            builtins,
+        }
+    }
+
+    // the built-in channels the rules use, for the loop to read and `observe`
+    pub(crate) fn builtins(&self) -> &[Builtin] {
+        &self.builtins
+    }
+
+    pub(crate) fn observe(&mut self, channel: &str, value: f32) {
+        match self.values.iter_mut().find(|(name, _)| name == channel) {
+            Some((_, v)) => *v = value,
+            None => {
+                if let Ok(name) = heapless::String::try_from(channel) {
+                    self.values.push((name, value));
+                }
+            }
+        }
+    }
+
+    // a rule fires once after holding for its duration and clears as soon as it stops holding
+    pub(crate) fn poll(&mut self) -> Vec<RuleEvent> {
+        let mut events = Vec::new();
+        for state in &mut self.rules {
+            let Some((_, value)) = self.values.iter().find(|(name, _)| *name == state.rule.channel)
+            else {
+                continue;
+            };
+            let value = *value;
+
+            if state.rule.breached(value) {
+                let since = *state.breached_since.get_or_insert_with(Instant::now);
+                let hold = Duration::from_secs(state.rule.hold_secs as u64);
+                if !state.active && since.elapsed() >= hold {
+                    state.active = true;
+                    events.push(RuleEvent::Breached { rule: state.rule.clone(), value });
+                }
+            } else {
+                state.breached_since = None;
+                if state.active {
+                    state.active = false;
+                    events.push(RuleEvent::Cleared { rule: state.rule.clone(), value });
+                }
+            }
+        }
+        events
+    }
+}
@@ -0,0 +1,67 @@
+use esp_hal::{
+    rtc_cntl::{SocResetReason, reset_reason},
+    system::Cpu,
+};
+
+// consecutive boots that never got to run for this long count as a crash loop
+pub(crate) const STABLE_AFTER_SECS: u64 = 60;
+const CRASH_LIMIT: u32 = 3;
+// RTC fast memory is left alone on reset but holds noise after power-on
+const MAGIC: u32 = 0x5AFE_B007;
+// MAGIC, from a boot that ended in a panic
+const PANICKED: u32 = 0x5AFE_DEAD;
+
+#[esp_hal::ram(unstable(rtc_fast, persistent))]
+static mut BOOT_STATE: [u32; 2] = [0; 2];
+
+// Every boot is counted as a failed attempt until the firmware has run for STABLE_AFTER_SECS
+// or leaves on purpose (standby, restart from the UI). Watchdog resets and the panic screen's
+// own restart keep the counter climbing, and so does EN pressed on the panic screen, thanks to
+// `record_panic`; otherwise EN reads as a power-on and starts over.
+pub(crate) fn record_boot() {
+    let fresh = matches!(
+        reset_reason(Cpu::ProCpu),
+        None | Some(SocResetReason::ChipPowerOn) | Some(SocResetReason::CoreDeepSleep)
+    );
+    unsafe {
+        let state = &mut *core::ptr::addr_of_mut!(BOOT_STATE);
+        let counting = match state[0] {
+            PANICKED => true,
+            MAGIC => !fresh,
+            _ => false,
+        };
+        if !counting {
+            state[1] = 0;
+        }
+        state[0] = MAGIC;
+        state[1] = state[1].saturating_add(1);
+    }
+}
+
+// from the panic handler, the boot it ended counts as failed however the next one starts
+pub(crate) fn record_panic() {
+    unsafe {
+        let state = &mut *core::ptr::addr_of_mut!(BOOT_STATE);
+        if state[0] == MAGIC {
+            state[0] = PANICKED;
+        }
+    }
+}
+
+pub(crate) fn failed_boots() -> u32 {
+    unsafe { (*core::ptr::addr_of!(BOOT_STATE))[1].saturating_sub(1) }
+}
+
+pub(crate) fn is_active() -> bool {
+    failed_boots() >= CRASH_LIMIT
+}
+
+// called once the firmware has proven itself, or right before a deliberate reset/sleep
+pub(crate) fn mark_stable() {
+    unsafe { *core::ptr::addr_of_mut!(BOOT_STATE) = [MAGIC, 0] };
+}
+
+pub(crate) fn restart() -> ! {
+    mark_stable();
+    esp_hal::system::software_reset()
+}
@@ -0,0 +1,58 @@
+// Periodic jobs for the network core's loop, which has no slint timers: every job has its own
+// interval, a failed run is retried after a backoff that doubles from `retry` up to the
+// interval, and a run that worked goes back to the interval.
+use alloc::vec::Vec;
+use esp_hal::time::{Duration, Instant};
+
+struct Job<J> {
+    id: J,
+    interval: Duration,
+    retry: Duration,
+    failures: u8,
+    next_run: Instant,
+}
+
+pub(crate) struct Scheduler<J> {
+    jobs: Vec<Job<J>>,
+}
+
+impl<J: Copy + PartialEq> Scheduler<J> {
+    pub(crate) fn new() -> Self {
+        Self { jobs: Vec::new() }
+    }
+
+    // runs first on the next `due`
+    pub(crate) fn every(&mut self, id: J, interval: Duration, retry: Duration) -> &mut Self {
+        let retry = retry.min(interval);
+        self.jobs.push(Job { id, interval, retry, failures: 0, next_run: Instant::now() });
+        self
+    }
+
+    // the job that has waited longest past its time, taken to be running until `done`
+    pub(crate) fn due(&mut self) -> Option<J> {
+        let now = Instant::now();
+        let job = self
+            .jobs
+            .iter_mut()
+            .filter(|job| now >= job.next_run)
+            .min_by_key(|job| job.next_run)?;
+        // a job that is never reported back isn't picked again in a tight loop
+        job.next_run = now + job.retry;
+        Some(job.id)
+    }
+
+    pub(crate) fn done(&mut self, id: J, ok: bool) {
+        let Some(job) = self.jobs.iter_mut().find(|job| job.id == id) else {
+            return;
+        };
+        let wait = if ok {
+            job.failures = 0;
+            job.interval
+        } else {
+            job.failures = job.failures.saturating_add(1);
+            let backoff = job.retry.as_millis() << (job.failures - 1).min(16);
+            Duration::from_millis(backoff).min(job.interval)
+        };
+        job.next_run = Instant::now() + wait;
+    }
+}
@@ -0,0 +1,174 @@
+use alloc::{format, string::String, vec::Vec};
+use slint::{
+    PhysicalSize,
+    platform::software_renderer::{
+        LineBufferProvider, RepaintBufferType, Rgb565Pixel, SoftwareRenderer,
+    },
+};
+use thiserror::Error;
+
+use crate::{
+    display_screen::{PANEL_HEIGHT, PANEL_WIDTH},
+    sdcard::{SdCardError, SdStorage},
+};
+
+const PIXELS: usize = (PANEL_WIDTH * PANEL_HEIGHT) as usize;
+// a full frame (150 KiB) is more than the whole heap, so it goes out in bands of this many rows
+const BAND_LINES: usize = 16;
+// file header, BITMAPINFOHEADER and the three RGB565 channel masks
+const HEADER_LEN: usize = 14 + 40 + 12;
+// the same in landscape and portrait
+pub(crate) const FILE_LEN: usize = HEADER_LEN + PIXELS * 2;
+// SCRN0001.BMP, SCRN0002.BMP, ... in the card's root
+const FILE_PREFIX: &str = "SCRN";
+const FILE_SUFFIX: &str = ".BMP";
+
+#[derive(Error, Debug)]
+pub(crate) enum ScreenshotError {
+    #[error("No SD card")]
+    NoCard,
+    #[error("Failed to write screenshot: {0}")]
+    Sd(#[from] SdCardError),
+}
+
+// Renders the whole window once more as a top-down RGB565 BMP, handing it to `sink` piece by
+// piece: the header first, then bands of rows. Has to run inside `draw_if_needed`, the panel
+// misses that frame, so the caller requests another redraw afterwards.
+pub(crate) fn capture(
+    renderer: &SoftwareRenderer,
+    size: PhysicalSize,
+    mut sink: impl FnMut(&[u8]),
+) {
+    sink(&header(size.width as usize, size.height as usize));
+    render_rows(renderer, size, 0..size.height as usize, &mut sink);
+}
+
+// `capture` spread over passes of the loop, for a reply that goes out as the network core takes
+// it: `stream_header` first, then `stream_band` from row 0 on, each returning the row the next
+// band starts at, None after the last. A frame that changes between bands shows as a seam.
+pub(crate) fn stream_header(size: PhysicalSize) -> Vec<u8> {
+    header(size.width as usize, size.height as usize).to_vec()
+}
+
+// the whole window is rendered for every band, only the band's rows are kept; same rules about
+// `draw_if_needed` as `capture`
+pub(crate) fn stream_band(
+    renderer: &SoftwareRenderer,
+    size: PhysicalSize,
+    first: usize,
+) -> (Vec<u8>, Option<usize>) {
+    let height = size.height as usize;
+    let end = (first + BAND_LINES).min(height);
+    let mut band = Vec::new();
+    render_rows(renderer, size, first..end, &mut |bytes| band.extend_from_slice(bytes));
+    (band, (end < height).then_some(end))
+}
+
+fn render_rows(
+    renderer: &SoftwareRenderer,
+    size: PhysicalSize,
+    rows: core::ops::Range<usize>,
+    sink: &mut impl FnMut(&[u8]),
+) {
+    let width = size.width as usize;
+    // a new buffer means nothing is assumed to be on screen already, so every line is drawn
+    let repaint_buffer = renderer.repaint_buffer_type();
+    renderer.set_repaint_buffer_type(RepaintBufferType::NewBuffer);
+    renderer.render_by_line(Bands {
+        sink,
+        line: [Rgb565Pixel(0); PANEL_WIDTH as usize],
+        width,
+        rows,
+        band: Vec::with_capacity(BAND_LINES * width * 2),
+    });
+    // switching back drops the partial rendering state, the next frame repaints the panel fully
+    renderer.set_repaint_buffer_type(repaint_buffer);
+}
+
+// writes the frame to the next free SCRNnnnn.BMP and returns its name
+pub(crate) fn save(
+    sd: Option<&SdStorage<'_>>,
+    renderer: &SoftwareRenderer,
+    size: PhysicalSize,
+) -> Result<String, ScreenshotError> {
+    let sd = sd.ok_or(ScreenshotError::NoCard)?;
+    let path = next_path(sd)?;
+    sd.write_file(&path, &[])?;
+
+    // every band reopens the file, the first failure is kept and the rest skipped
+    let mut result = Ok(());
+    capture(renderer, size, |bytes| {
+        if result.is_ok() {
+            result = sd.append(&path, bytes);
+        }
+    });
+    result?;
+    Ok(path)
+}
+
+fn next_path(sd: &SdStorage<'_>) -> Result<String, SdCardError> {
+    let last = sd
+        .list_dir("")?
+        .iter()
+        .filter_map(|entry| {
+            let number = entry.name.strip_prefix(FILE_PREFIX)?.strip_suffix(FILE_SUFFIX)?;
+            number.parse::<u16>().ok()
+        })
+        .max()
+        .unwrap_or(0);
+    Ok(format!("{}{:04}{}", FILE_PREFIX, (last + 1) % 10_000, FILE_SUFFIX))
+}
+
+fn header(width: usize, height: usize) -> [u8; HEADER_LEN] {
+    let mut header = [0u8; HEADER_LEN];
+    let mut put = |at: usize, bytes: &[u8]| header[at..at + bytes.len()].copy_from_slice(bytes);
+    put(0, b"BM");
+    put(2, &(FILE_LEN as u32).to_le_bytes());
+    put(10, &(HEADER_LEN as u32).to_le_bytes());
+    put(14, &40u32.to_le_bytes());
+    put(18, &(width as i32).to_le_bytes());
+    // negative height: rows top to bottom, the order slint renders them in
+    put(22, &(-(height as i32)).to_le_bytes());
+    put(26, &1u16.to_le_bytes());
+    put(28, &16u16.to_le_bytes());
+    // BI_BITFIELDS with the RGB565 masks
+    put(30, &3u32.to_le_bytes());
+    put(34, &((width * 2 * height) as u32).to_le_bytes());
+    put(54, &0xF800u32.to_le_bytes());
+    put(58, &0x07E0u32.to_le_bytes());
+    put(62, &0x001Fu32.to_le_bytes());
+    header
+}
+
+struct Bands<'s, F: FnMut(&[u8])> {
+    sink: &'s mut F,
+    line: [Rgb565Pixel; PANEL_WIDTH as usize],
+    width: usize,
+    // the rest is drawn and dropped
+    rows: core::ops::Range<usize>,
+    band: Vec<u8>,
+}
+
+impl<F: FnMut(&[u8])> LineBufferProvider for Bands<'_, F> {
+    type TargetPixel = Rgb565Pixel;
+
+    // with a new buffer every call covers a full line, in order
+    fn process_line(
+        &mut self,
+        line: usize,
+        range: core::ops::Range<usize>,
+        render_fn: impl FnOnce(&mut [Rgb565Pixel]),
+    ) {
+        render_fn(&mut self.line[range]);
+        if !self.rows.contains(&line) {
+            return;
+        }
+        for pixel in self.line[..self.width].iter() {
+            self.band.extend_from_slice(&pixel.0.to_le_bytes());
+        }
+        if self.band.len() == BAND_LINES * self.width * 2 || line + 1 == self.rows.end {
+            (self.sink)(&self.band);
+            self.band.clear();
+        }
+    }
+}
@@ -0,0 +1,777 @@
+// Behaviour from SCRIPT.TXT on the card (`scripting` feature), so a unit can be tweaked without
+// reflashing. A script is a list of handlers, one statement per line, `#` starts a comment:
+//
+//   on boot | on every <secs> | on button "<label>" | on ws | on response <name>
+//     let <var> = <expr>
+//     set remote_text|status <expr>          set label "<name>" <expr>
+//     notify <expr>                           observe <channel> <expr>
+//     get <route> as <name>                   post <route> <expr>
+//     if <expr> ... [else ...] end
+//   end
+//
+// Expressions are numbers, "strings", variables, `+` (adds numbers, joins anything else),
+// `- * /`, `== != < >` and `json(<text>, "<key>")`. Variables are shared by all handlers; `ws`
+// sets `message`, `response` sets `status` and `body` before the handler runs. Nothing here
+// touches the UI or the network: a run leaves `ScriptAction`s for the loop to apply, a `get`
+// comes back as a `response` trigger once the network core has the answer.
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use esp_hal::time::{Duration, Instant};
+use log::warn;
+use thiserror::Error;
+
+use crate::{
+    apps::{App, AppRegistry},
+    navigation::Page,
+    sdcard::SdStorage,
+};
+
+pub(crate) const SCRIPT_FILE: &str = "SCRIPT.TXT";
+const MAX_HANDLERS: usize = 32;
+const MAX_VARS: usize = 32;
+const MAX_LABELS: usize = 12;
+// ifs inside ifs
+const MAX_DEPTH: usize = 8;
+// parentheses, `-` and json() inside each other in one expression
+const MAX_NESTING: usize = 16;
+// per expression, which bounds how deep a chain like `a + b + c ...` evaluates
+const MAX_TOKENS: usize = 64;
+// bytes in a string value, the same as a response body gets
+const MAX_STRING: usize = 2048;
+// `every` handlers run at most this often
+const MIN_EVERY_SECS: u64 = 1;
+
+#[derive(Error, Debug)]
+pub(crate) enum ScriptError {
+    #[error("Line {0}: {1}")]
+    Syntax(usize, &'static str),
+    #[error("Line {0}: {1}")]
+    Runtime(usize, String),
+    #[error("Too many {0}")]
+    TooMany(&'static str),
+}
+
+// what a run asks of the loop, in the order the statements ran
+#[derive(Debug)]
+pub(crate) enum ScriptAction {
+    RemoteText(String),
+    Status(String),
+    Notify(String),
+    // a rule engine channel
+    Observe(String, f32),
+    // answered with `Trigger::Response` under the same name
+    Get { name: String, route: String },
+    // queued in the outbox like a webhook
+    Post { route: String, body: String },
+}
+
+// what the Script page shows
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct ScriptView {
+    pub(crate) labels: Vec<(String, String)>,
+    pub(crate) buttons: Vec<String>,
+    // the last error, kept until the next one
+    pub(crate) error: String,
+}
+
+pub(crate) enum Trigger<'a> {
+    Boot,
+    // index into `buttons()`
+    Button(usize),
+    WsMessage(&'a str),
+    Response { name: &'a str, status: u16, body: &'a str },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Event {
+    Boot,
+    Every(u64),
+    Button(String),
+    Ws,
+    Response(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Num(f32),
+    Str(String),
+}
+
+impl Value {
+    fn text(&self) -> String {
+        match self {
+            // whole numbers without the ".0", they end up in labels and routes
+            Value::Num(n) if n.fract() == 0.0 && n.abs() < 1e9 => format!("{}", *n as i64),
+            Value::Num(n) => format!("{}", n),
+            Value::Str(s) => s.clone(),
+        }
+    }
+
+    fn number(&self) -> Option<f32> {
+        match self {
+            Value::Num(n) => Some(*n),
+            Value::Str(s) => s.trim().parse().ok(),
+        }
+    }
+
+    fn truthy(&self) -> bool {
+        match self {
+            Value::Num(n) => *n != 0.0,
+            Value::Str(s) => !s.is_empty(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Value(Value),
+    Var(String),
+    Neg(Box<Expr>),
+    Binary(Box<Expr>, Op, Box<Expr>),
+    Json(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Target {
+    RemoteText,
+    Status,
+    Label(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Stmt {
+    Let(String, Expr),
+    Set(Target, Expr),
+    Notify(Expr),
+    Observe(String, Expr),
+    Get { route: String, name: String },
+    Post { route: String, body: Expr },
+    If { condition: Expr, then: Vec<Line>, otherwise: Vec<Line> },
+}
+
+// a statement and where it came from, for errors
+#[derive(Clone, Debug, PartialEq)]
+struct Line {
+    number: usize,
+    stmt: Stmt,
+}
+
+struct Handler {
+    event: Event,
+    body: Vec<Line>,
+    // `every` handlers only
+    next_run: Option<Instant>,
+}
+
+pub(crate) struct Script {
+    handlers: Vec<Handler>,
+    vars: Vec<(String, Value)>,
+    labels: Vec<(String, String)>,
+    error: String,
+    // the view is out of date
+    changed: bool,
+    actions: Vec<ScriptAction>,
+}
+
+// the Script tile, main hands this to the launcher
+pub(crate) fn register_app(apps: &mut AppRegistry) {
+    apps.register(App::new("Script", "app-script", Page::Script));
+}
+
+// None without a script on the card
+pub(crate) fn load_from_sd(sd: &SdStorage<'_>) -> Option<Result<Script, ScriptError>> {
+    let data = sd.read_to_vec(SCRIPT_FILE).ok()?;
+    Some(Script::parse(core::str::from_utf8(&data).unwrap_or("")))
+}
+
+impl Script {
+    pub(crate) fn parse(text: &str) -> Result<Self, ScriptError> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, strip_comment(line).trim()))
+            .filter(|(_, line)| !line.is_empty());
+        let mut handlers = Vec::new();
+        while let Some((number, line)) = lines.next() {
+            let event = parse_event(number, line)?;
+            let (body, closing) = parse_block(&mut lines, 0)?;
+            if closing != Some(Closing::End) {
+                return Err(ScriptError::Syntax(number, "handler without end"));
+            }
+            if handlers.len() == MAX_HANDLERS {
+                return Err(ScriptError::TooMany("handlers"));
+            }
+            let next_run = matches!(event, Event::Every(_)).then(Instant::now);
+            handlers.push(Handler { event, body, next_run });
+        }
+        Ok(Self {
+            handlers,
+            vars: Vec::new(),
+            labels: Vec::new(),
+            error: String::new(),
+            changed: true,
+            actions: Vec::new(),
+        })
+    }
+
+    // once after labels were set or a run failed
+    pub(crate) fn changed_view(&mut self) -> Option<ScriptView> {
+        if !core::mem::take(&mut self.changed) {
+            return None;
+        }
+        // `on button` handlers in script order, `Trigger::Button` counts the same way
+        let buttons = self
+            .handlers
+            .iter()
+            .filter_map(|h| match &h.event {
+                Event::Button(label) => Some(label.clone()),
+                _ => None,
+            })
+            .collect();
+        Some(ScriptView { labels: self.labels.clone(), buttons, error: self.error.clone() })
+    }
+
+    pub(crate) fn take_actions(&mut self) -> Vec<ScriptAction> {
+        core::mem::take(&mut self.actions)
+    }
+
+    // runs every handler for `trigger`, a failing one doesn't stop the rest
+    pub(crate) fn run(&mut self, trigger: Trigger<'_>) {
+        let bound = match &trigger {
+            Trigger::WsMessage(frame) => self.assign("message", Value::Str(capped(frame))),
+            Trigger::Response { status, body, .. } => self
+                .assign("status", Value::Num(*status as f32))
+                .and_then(|_| self.assign("body", Value::Str(capped(body)))),
+            Trigger::Boot | Trigger::Button(_) => Ok(()),
+        };
+        if let Err(e) = bound {
+            self.failed(e);
+            return;
+        }
+        let mut button = 0;
+        let mut matching = Vec::new();
+        for (i, handler) in self.handlers.iter().enumerate() {
+            let matches = match (&handler.event, &trigger) {
+                (Event::Boot, Trigger::Boot) => true,
+                (Event::Button(_), Trigger::Button(index)) => {
+                    button += 1;
+                    button == index + 1
+                }
+                (Event::Ws, Trigger::WsMessage(_)) => true,
+                (Event::Response(name), Trigger::Response { name: wanted, .. }) => name == wanted,
+                _ => false,
+            };
+            if matches {
+                matching.push(i);
+            }
+        }
+        self.run_handlers(&matching)
+    }
+
+    // the `every` handlers that are due
+    pub(crate) fn poll(&mut self) {
+        let now = Instant::now();
+        let mut due = Vec::new();
+        for (i, handler) in self.handlers.iter_mut().enumerate() {
+            if let (Event::Every(secs), Some(next_run)) = (&handler.event, handler.next_run) {
+                if now >= next_run {
+                    handler.next_run = Some(now + Duration::from_secs((*secs).max(MIN_EVERY_SECS)));
+                    due.push(i);
+                }
+            }
+        }
+        self.run_handlers(&due)
+    }
+
+    fn run_handlers(&mut self, indices: &[usize]) {
+        for &i in indices {
+            // cloned so the body can't change under the statements running it
+            let body = self.handlers[i].body.clone();
+            if let Err(e) = self.exec(&body) {
+                self.failed(e);
+            }
+        }
+    }
+
+    fn failed(&mut self, e: ScriptError) {
+        warn!("Script: {}", e);
+        self.error = e.to_string();
+        self.changed = true;
+    }
+
+    fn exec(&mut self, body: &[Line]) -> Result<(), ScriptError> {
+        for line in body {
+            let runtime = |message: String| ScriptError::Runtime(line.number, message);
+            match &line.stmt {
+                Stmt::Let(name, expr) => {
+                    let value = self.eval(expr).map_err(runtime)?;
+                    self.assign(name, value)?;
+                }
+                Stmt::Set(target, expr) => {
+                    let text = self.eval(expr).map_err(runtime)?.text();
+                    match target {
+                        Target::RemoteText => self.actions.push(ScriptAction::RemoteText(text)),
+                        Target::Status => self.actions.push(ScriptAction::Status(text)),
+                        Target::Label(name) => self.set_label(name, text)?,
+                    }
+                }
+                Stmt::Notify(expr) => {
+                    let text = self.eval(expr).map_err(runtime)?.text();
+                    self.actions.push(ScriptAction::Notify(text));
+                }
+                Stmt::Observe(channel, expr) => {
+                    let value = self.eval(expr).map_err(runtime)?;
+                    let value = value
+                        .number()
+                        .ok_or_else(|| runtime(format!("{} is not a number", value.text())))?;
+                    self.actions.push(ScriptAction::Observe(channel.clone(), value));
+                }
+                Stmt::Get { route, name } => self
+                    .actions
+                    .push(ScriptAction::Get { name: name.clone(), route: route.clone() }),
+                Stmt::Post { route, body } => {
+                    let body = self.eval(body).map_err(runtime)?.text();
+                    self.actions.push(ScriptAction::Post { route: route.clone(), body });
+                }
+                Stmt::If { condition, then, otherwise } => {
+                    if self.eval(condition).map_err(runtime)?.truthy() {
+                        self.exec(then)?;
+                    } else {
+                        self.exec(otherwise)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn eval(&self, expr: &Expr) -> Result<Value, String> {
+        Ok(match expr {
+            Expr::Value(value) => value.clone(),
+            Expr::Var(name) => match self.vars.iter().find(|(n, _)| n == name) {
+                Some((_, value)) => value.clone(),
+                None => return Err(format!("{} is not set", name)),
+            },
+            Expr::Neg(inner) => Value::Num(-number(&self.eval(inner)?)?),
+            Expr::Binary(left, op, right) => binary(self.eval(left)?, *op, self.eval(right)?)?,
+            Expr::Json(text, key) => {
+                let (text, key) = (self.eval(text)?.text(), self.eval(key)?.text());
+                json_field(&text, &key).ok_or_else(|| format!("no {} in the document", key))?
+            }
+        })
+    }
+
+    fn assign(&mut self, name: &str, value: Value) -> Result<(), ScriptError> {
+        match self.vars.iter_mut().find(|(n, _)| n == name) {
+            Some((_, slot)) => *slot = value,
+            None if self.vars.len() == MAX_VARS => return Err(ScriptError::TooMany("variables")),
+            None => self.vars.push((name.into(), value)),
+        }
+        Ok(())
+    }
+
+    fn set_label(&mut self, name: &str, text: String) -> Result<(), ScriptError> {
+        match self.labels.iter_mut().find(|(n, _)| n == name) {
+            Some((_, shown)) if *shown == text => return Ok(()),
+            Some((_, shown)) => *shown = text,
+            None if self.labels.len() == MAX_LABELS => return Err(ScriptError::TooMany("labels")),
+            None => self.labels.push((name.into(), text)),
+        }
+        self.changed = true;
+        Ok(())
+    }
+}
+
+// `text` cut to MAX_STRING on a character boundary
+fn capped(text: &str) -> String {
+    let mut end = text.len().min(MAX_STRING);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    text[..end].into()
+}
+
+fn number(value: &Value) -> Result<f32, String> {
+    value.number().ok_or_else(|| format!("{} is not a number", value.text()))
+}
+
+fn binary(left: Value, op: Op, right: Value) -> Result<Value, String> {
+    let flag = |b: bool| Value::Num(if b { 1.0 } else { 0.0 });
+    // equality compares as numbers when both sides are numbers, as text otherwise
+    let same = || match (left.number(), right.number()) {
+        (Some(a), Some(b)) => a == b,
+        _ => left.text() == right.text(),
+    };
+    Ok(match op {
+        Op::Add => match (&left, &right) {
+            (Value::Num(a), Value::Num(b)) => Value::Num(a + b),
+            _ => {
+                let joined = left.text() + &right.text();
+                if joined.len() > MAX_STRING {
+                    return Err(format!("string longer than {} bytes", MAX_STRING));
+                }
+                Value::Str(joined)
+            }
+        },
+        Op::Sub => Value::Num(number(&left)? - number(&right)?),
+        Op::Mul => Value::Num(number(&left)? * number(&right)?),
+        Op::Div => Value::Num(number(&left)? / number(&right)?),
+        Op::Eq => flag(same()),
+        Op::Ne => flag(!same()),
+        Op::Lt => flag(number(&left)? < number(&right)?),
+        Op::Gt => flag(number(&left)? > number(&right)?),
+    })
+}
+
+// the first `"key": value` anywhere in the document, a string or a number; good enough for the
+// flat documents a script reads, nested objects are searched too
+fn json_field(text: &str, key: &str) -> Option<Value> {
+    let quoted = format!("\"{}\"", key);
+    let mut rest = text;
+    while let Some(at) = rest.find(&quoted) {
+        rest = &rest[at + quoted.len()..];
+        let Some(value) = rest.trim_start().strip_prefix(':') else {
+            continue;
+        };
+        let value = value.trim_start();
+        if let Some(string) = value.strip_prefix('"') {
+            return unquote(string).map(|(s, _)| Value::Str(s));
+        }
+        let end = value.find([',', '}', ']']).unwrap_or(value.len());
+        return match value[..end].trim() {
+            "true" => Some(Value::Num(1.0)),
+            "false" | "null" => Some(Value::Num(0.0)),
+            number => number.parse().ok().map(Value::Num),
+        };
+    }
+    None
+}
+
+// the string up to the closing quote and what follows it, `after` the opening quote
+fn unquote(after: &str) -> Option<(String, &str)> {
+    let mut out = String::new();
+    let mut chars = after.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((out, &after[i + 1..])),
+            '\\' => match chars.next()?.1 {
+                'n' => out.push('\n'),
+                't' => out.push('\t'),
+                other => out.push(other),
+            },
+            c => out.push(c),
+        }
+    }
+    None
+}
+
+fn strip_comment(line: &str) -> &str {
+    // a # inside a string is not a comment
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_event(number: usize, line: &str) -> Result<Event, ScriptError> {
+    let malformed = ScriptError::Syntax(number, "expected on boot|every|button|ws|response");
+    let Some(rest) = line.strip_prefix("on ") else {
+        return Err(malformed);
+    };
+    let (kind, arg) = rest.trim().split_once(' ').unwrap_or((rest.trim(), ""));
+    let arg = arg.trim();
+    Ok(match kind {
+        "boot" if arg.is_empty() => Event::Boot,
+        "ws" if arg.is_empty() => Event::Ws,
+        "every" => Event::Every(
+            arg.parse()
+                .map_err(|_| ScriptError::Syntax(number, "every needs whole seconds"))?,
+        ),
+        "button" => match arg.strip_prefix('"').and_then(unquote) {
+            Some((label, rest)) if rest.trim().is_empty() => Event::Button(label),
+            _ => return Err(ScriptError::Syntax(number, "button needs a \"label\"")),
+        },
+        "response" if is_name(arg) => Event::Response(arg.into()),
+        _ => return Err(malformed),
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Closing {
+    End,
+    Else,
+}
+
+// statements up to the `end` or `else` closing the block, None when the script ran out
+fn parse_block<'a>(
+    lines: &mut impl Iterator<Item = (usize, &'a str)>,
+    depth: usize,
+) -> Result<(Vec<Line>, Option<Closing>), ScriptError> {
+    let mut body = Vec::new();
+    while let Some((number, line)) = lines.next() {
+        let (word, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let rest = rest.trim();
+        let syntax = |message| ScriptError::Syntax(number, message);
+        let stmt = match word {
+            "end" => return Ok((body, Some(Closing::End))),
+            "else" => return Ok((body, Some(Closing::Else))),
+            "let" => {
+                let (name, expr) = rest.split_once('=').ok_or(syntax("let <var> = <expr>"))?;
+                let name = name.trim();
+                if !is_name(name) {
+                    return Err(syntax("let <var> = <expr>"));
+                }
+                Stmt::Let(name.into(), parse_expr(number, expr)?)
+            }
+            "set" => {
+                let (target, expr) = rest.split_once(' ').ok_or(syntax("set <target> <expr>"))?;
+                match target {
+                    "remote_text" => Stmt::Set(Target::RemoteText, parse_expr(number, expr)?),
+                    "status" => Stmt::Set(Target::Status, parse_expr(number, expr)?),
+                    "label" => {
+                        let (name, expr) = expr
+                            .trim()
+                            .strip_prefix('"')
+                            .and_then(unquote)
+                            .ok_or(syntax("set label \"<name>\" <expr>"))?;
+                        Stmt::Set(Target::Label(name), parse_expr(number, expr)?)
+                    }
+                    _ => return Err(syntax("set remote_text|status|label")),
+                }
+            }
+            "notify" => Stmt::Notify(parse_expr(number, rest)?),
+            "observe" => {
+                let (channel, expr) = rest.split_once(' ').ok_or(syntax("observe <ch> <expr>"))?;
+                if !is_name(channel) {
+                    return Err(syntax("observe <channel> <expr>"));
+                }
+                Stmt::Observe(channel.into(), parse_expr(number, expr)?)
+            }
+            "get" => match rest.split_whitespace().collect::<Vec<_>>().as_slice() {
+                [route, "as", name] if route.starts_with('/') && is_name(name) => {
+                    Stmt::Get { route: route.to_string(), name: name.to_string() }
+                }
+                _ => return Err(syntax("get <route> as <name>")),
+            },
+            "post" => match rest.split_once(' ') {
+                Some((route, body)) if route.starts_with('/') => {
+                    Stmt::Post { route: route.into(), body: parse_expr(number, body)? }
+                }
+                _ => return Err(syntax("post <route> <expr>")),
+            },
+            "if" => {
+                if depth == MAX_DEPTH {
+                    return Err(syntax("ifs nested too deep"));
+                }
+                let condition = parse_expr(number, rest)?;
+                let (then, closing) = parse_block(lines, depth + 1)?;
+                let otherwise = match closing {
+                    Some(Closing::End) => Vec::new(),
+                    Some(Closing::Else) => match parse_block(lines, depth + 1)? {
+                        (otherwise, Some(Closing::End)) => otherwise,
+                        _ => return Err(syntax("else without end")),
+                    },
+                    None => return Err(syntax("if without end")),
+                };
+                Stmt::If { condition, then, otherwise }
+            }
+            _ => return Err(syntax("unknown statement")),
+        };
+        body.push(Line { number, stmt });
+    }
+    Ok((body, None))
+}
+
+fn is_name(word: &str) -> bool {
+    let mut chars = word.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Num(f32),
+    Str(String),
+    Name(String),
+    Op(Op),
+    Minus,
+    Open,
+    Close,
+    Comma,
+}
+
+fn tokenize(number: usize, text: &str) -> Result<Vec<Token>, ScriptError> {
+    let syntax = |message| ScriptError::Syntax(number, message);
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let (token, len) = match c {
+            '"' => {
+                let (string, after) = unquote(&rest[1..]).ok_or(syntax("unterminated string"))?;
+                (Token::Str(string), rest.len() - after.len())
+            }
+            '0'..='9' | '.' => {
+                let len =
+                    rest.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(rest.len());
+                let value = rest[..len].parse().map_err(|_| syntax("malformed number"))?;
+                (Token::Num(value), len)
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len());
+                (Token::Name(rest[..len].into()), len)
+            }
+            '=' if rest.starts_with("==") => (Token::Op(Op::Eq), 2),
+            '!' if rest.starts_with("!=") => (Token::Op(Op::Ne), 2),
+            '<' => (Token::Op(Op::Lt), 1),
+            '>' => (Token::Op(Op::Gt), 1),
+            '+' => (Token::Op(Op::Add), 1),
+            '-' => (Token::Minus, 1),
+            '*' => (Token::Op(Op::Mul), 1),
+            '/' => (Token::Op(Op::Div), 1),
+            '(' => (Token::Open, 1),
+            ')' => (Token::Close, 1),
+            ',' => (Token::Comma, 1),
+            _ => return Err(syntax("unexpected character")),
+        };
+        tokens.push(token);
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+fn parse_expr(number: usize, text: &str) -> Result<Expr, ScriptError> {
+    let tokens = tokenize(number, text)?;
+    if tokens.len() > MAX_TOKENS {
+        return Err(ScriptError::Syntax(number, "expression too long"));
+    }
+    let mut parser = Parser { tokens: &tokens, at: 0, number, depth: 0 };
+    let expr = parser.comparison()?;
+    if parser.at != tokens.len() {
+        return Err(ScriptError::Syntax(number, "unexpected text after the expression"));
+    }
+    Ok(expr)
+}
+
+// comparison := sum [(== != < >) sum], sum := product ((+ -) product)*,
+// product := factor ((* /) factor)*, factor := - factor | value | name | json(a, b) | (comparison)
+struct Parser<'t> {
+    tokens: &'t [Token],
+    at: usize,
+    number: usize,
+    // factors being parsed inside each other
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.at);
+        self.at += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.at)
+    }
+
+    fn expect(&mut self, token: Token, message: &'static str) -> Result<(), ScriptError> {
+        match self.next() {
+            Some(next) if *next == token => Ok(()),
+            _ => Err(ScriptError::Syntax(self.number, message)),
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Expr, ScriptError> {
+        let left = self.sum()?;
+        match self.peek() {
+            Some(Token::Op(op @ (Op::Eq | Op::Ne | Op::Lt | Op::Gt))) => {
+                let op = *op;
+                self.at += 1;
+                Ok(Expr::Binary(Box::new(left), op, Box::new(self.sum()?)))
+            }
+            _ => Ok(left),
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expr, ScriptError> {
+        let mut left = self.product()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Op(Op::Add)) => Op::Add,
+                Some(Token::Minus) => Op::Sub,
+                _ => return Ok(left),
+            };
+            self.at += 1;
+            left = Expr::Binary(Box::new(left), op, Box::new(self.product()?));
+        }
+    }
+
+    fn product(&mut self) -> Result<Expr, ScriptError> {
+        let mut left = self.factor()?;
+        while let Some(Token::Op(op @ (Op::Mul | Op::Div))) = self.peek() {
+            let op = *op;
+            self.at += 1;
+            left = Expr::Binary(Box::new(left), op, Box::new(self.factor()?));
+        }
+        Ok(left)
+    }
+
+    fn factor(&mut self) -> Result<Expr, ScriptError> {
+        if self.depth == MAX_NESTING {
+            return Err(ScriptError::Syntax(self.number, "expression nested too deep"));
+        }
+        self.depth += 1;
+        let factor = self.factor_inner();
+        self.depth -= 1;
+        factor
+    }
+
+    fn factor_inner(&mut self) -> Result<Expr, ScriptError> {
+        let number = self.number;
+        Ok(match self.next().cloned() {
+            Some(Token::Minus) => Expr::Neg(Box::new(self.factor()?)),
+            Some(Token::Num(n)) => Expr::Value(Value::Num(n)),
+            Some(Token::Str(s)) => Expr::Value(Value::Str(s)),
+            Some(Token::Name(name)) if name == "json" => {
+                self.expect(Token::Open, "json(<text>, \"<key>\")")?;
+                let text = self.comparison()?;
+                self.expect(Token::Comma, "json(<text>, \"<key>\")")?;
+                let key = self.comparison()?;
+                self.expect(Token::Close, "json(<text>, \"<key>\")")?;
+                Expr::Json(Box::new(text), Box::new(key))
+            }
+            Some(Token::Name(name)) => Expr::Var(name),
+            Some(Token::Open) => {
+                let inner = self.comparison()?;
+                self.expect(Token::Close, "missing )")?;
+                inner
+            }
+            _ => return Err(ScriptError::Syntax(number, "expected a value")),
+        })
+    }
+}
@@ -0,0 +1,257 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{
+    cell::RefCell,
+    sync::atomic::{AtomicU32, Ordering},
+};
+use embedded_hal_bus::spi::{NoDelay, RefCellDevice};
+use embedded_sdmmc::{
+    BlockDevice, Directory, Mode, SdCard, VolumeIdx, VolumeManager,
+};
+use esp_hal::{
+    Blocking,
+    delay::Delay,
+    gpio::{Level, Output, OutputPin},
+    spi::master::Spi,
+};
+use log::{debug, warn};
+use thiserror::Error;
+
+use crate::{
+    clock::WallClock,
+    fat_check::{self, CheckLevel, CheckReport},
+};
+
+const MOUNT_ATTEMPTS: u32 = 5;
+
+// filesystem errors since boot; a missing file or no card at all isn't one
+static ERRORS: AtomicU32 = AtomicU32::new(0);
+
+pub(crate) fn errors() -> u32 {
+    ERRORS.load(Ordering::Relaxed)
+}
+
+#[derive(Error, Debug)]
+pub(crate) enum SdCardError {
+    #[error("Failed to initialize SPI device for SD card: {0}")]
+    SpiInit(String),
+    #[error("SD card not found after {0} attempts")]
+    Mount(u32),
+    #[error("File or directory not found")]
+    NotFound,
+    #[error("Invalid path: {0}")]
+    InvalidPath(String),
+    #[error("Filesystem error: {0}")]
+    Filesystem(String),
+}
+
+impl<E: core::fmt::Debug> From<embedded_sdmmc::Error<E>> for SdCardError {
+    fn from(e: embedded_sdmmc::Error<E>) -> Self {
+        match e {
+            embedded_sdmmc::Error::NotFound => SdCardError::NotFound,
+            e => {
+                ERRORS.fetch_add(1, Ordering::Relaxed);
+                SdCardError::Filesystem(format!("{:?}", e))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct DirEntryInfo {
+    pub(crate) name: String,
+    pub(crate) size: u32,
+    pub(crate) is_dir: bool,
+}
+
+// a settings file from the card read whole, `lines` goes through it
+pub(crate) struct ConfigFile {
+    path: String,
+    data: Vec<u8>,
+}
+
+impl ConfigFile {
+    // trimmed, without blank lines and `#` comments; every line is decoded on its own, so a byte
+    // that isn't UTF-8 only loses its line
+    pub(crate) fn lines(&self) -> impl Iterator<Item = &str> {
+        self.data
+            .split(|&byte| byte == b'\n')
+            .enumerate()
+            .filter_map(|(i, line)| match core::str::from_utf8(line) {
+                Ok(line) => Some(line.trim()),
+                Err(_) => {
+                    warn!("{}: line {} is not UTF-8, skipped", self.path, i + 1);
+                    None
+                }
+            })
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+    }
+}
+
+type SdDevice<'a> = SdCard<RefCellDevice<'a, Spi<'a, Blocking>, Output<'a>, NoDelay>, Delay>;
+type SdDirectory<'v, 'a> = Directory<'v, SdDevice<'a>, WallClock, 4, 4, 1>;
+
+// long-lived handle to the first FAT volume, paths are '/' separated 8.3 names
+pub(crate) struct SdStorage<'a> {
+    volume_manager: VolumeManager<SdDevice<'a>, WallClock>,
+    check_report: CheckReport,
+}
+
+impl<'a> SdStorage<'a> {
+    pub(crate) fn mount(
+        spi: &'a RefCell<Spi<'a, Blocking>>,
+        sd_cs_pin: impl OutputPin + 'a,
+        check_level: CheckLevel,
+    ) -> Result<Self, SdCardError> {
+        let sd_cs = Output::new(sd_cs_pin, Level::High, Default::default());
+        let sd_spi_dev = RefCellDevice::new_no_delay(spi, sd_cs)
+            .map_err(|e| SdCardError::SpiInit(e.to_string()))?;
+
+        let sd = SdCard::new(sd_spi_dev, Delay::new());
+        let mut attempt = 0;
+        // the card initializes lazily on its first access
+        while let Err(e) = sd.num_blocks() {
+            attempt += 1;
+            warn!("SD: Attempt {}/{} failed: {:?}", attempt, MOUNT_ATTEMPTS, e);
+            if attempt >= MOUNT_ATTEMPTS {
+                return Err(SdCardError::Mount(MOUNT_ATTEMPTS));
+            }
+            Delay::new().delay_millis(50u32);
+        }
+
+        let check_report = match fat_check::check(&sd, check_level, true) {
+            Ok(report) => report,
+            Err(e) => {
+                warn!("SD: filesystem check failed: {}", e);
+                CheckReport { unreadable: true, ..Default::default() }
+            }
+        };
+        if !check_report.is_clean() {
+            debug!("SD: {:?}", check_report);
+        }
+
+        let storage = Self { volume_manager: VolumeManager::new(sd, WallClock), check_report };
+        storage.volume_manager.open_volume(VolumeIdx(0)).map(drop)?;
+        Ok(storage)
+    }
+
+    pub(crate) fn check_report(&self) -> &CheckReport {
+        &self.check_report
+    }
+
+    pub(crate) fn read_file(&self, path: &str, buf: &mut [u8]) -> Result<usize, SdCardError> {
+        let (dir_path, name) = split_path(path)?;
+        self.with_dir(dir_path, |dir| {
+            let file = dir.open_file_in_dir(name, Mode::ReadOnly)?;
+            let mut len = 0;
+            while len < buf.len() && !file.is_eof() {
+                len += file.read(&mut buf[len..])?;
+            }
+            Ok(len)
+        })
+    }
+
+    pub(crate) fn file_size(&self, path: &str) -> Result<u32, SdCardError> {
+        let (dir_path, name) = split_path(path)?;
+        self.with_dir(dir_path, |dir| Ok(dir.open_file_in_dir(name, Mode::ReadOnly)?.length()))
+    }
+
+    pub(crate) fn read_to_vec(&self, path: &str) -> Result<Vec<u8>, SdCardError> {
+        let (dir_path, name) = split_path(path)?;
+        self.with_dir(dir_path, |dir| {
+            let file = dir.open_file_in_dir(name, Mode::ReadOnly)?;
+            let mut out = alloc::vec![0u8; file.length() as usize];
+            let mut len = 0;
+            while len < out.len() && !file.is_eof() {
+                len += file.read(&mut out[len..])?;
+            }
+            out.truncate(len);
+            Ok(out)
+        })
+    }
+
+    // for the .TXT settings files, one entry per line
+    pub(crate) fn read_config(&self, path: &str) -> Result<ConfigFile, SdCardError> {
+        Ok(ConfigFile { path: path.to_string(), data: self.read_to_vec(path)? })
+    }
+
+    // the whole file through `buf` a piece at a time, for files bigger than the heap
+    pub(crate) fn read_chunks(
+        &self,
+        path: &str,
+        buf: &mut [u8],
+        mut f: impl FnMut(&[u8]),
+    ) -> Result<(), SdCardError> {
+        let (dir_path, name) = split_path(path)?;
+        self.with_dir(dir_path, |dir| {
+            let file = dir.open_file_in_dir(name, Mode::ReadOnly)?;
+            while !file.is_eof() {
+                let len = file.read(buf)?;
+                f(&buf[..len]);
+            }
+            Ok(())
+        })
+    }
+
+    pub(crate) fn write_file(&self, path: &str, data: &[u8]) -> Result<(), SdCardError> {
+        self.write_with_mode(path, data, Mode::ReadWriteCreateOrTruncate)
+    }
+
+    pub(crate) fn append(&self, path: &str, data: &[u8]) -> Result<(), SdCardError> {
+        self.write_with_mode(path, data, Mode::ReadWriteCreateOrAppend)
+    }
+
+    pub(crate) fn list_dir(&self, path: &str) -> Result<Vec<DirEntryInfo>, SdCardError> {
+        self.with_dir(path, |dir| {
+            let mut entries = Vec::new();
+            dir.iterate_dir(|entry| {
+                entries.push(DirEntryInfo {
+                    name: format!("{}", entry.name),
+                    size: entry.size,
+                    is_dir: entry.attributes.is_directory(),
+                });
+            })?;
+            Ok(entries)
+        })
+    }
+
+    pub(crate) fn delete(&self, path: &str) -> Result<(), SdCardError> {
+        let (dir_path, name) = split_path(path)?;
+        self.with_dir(dir_path, |dir| Ok(dir.delete_file_in_dir(name)?))
+    }
+
+    fn write_with_mode(&self, path: &str, data: &[u8], mode: Mode) -> Result<(), SdCardError> {
+        let (dir_path, name) = split_path(path)?;
+        self.with_dir(dir_path, |dir| {
+            let file = dir.open_file_in_dir(name, mode)?;
+            file.write(data)?;
+            Ok(file.flush()?)
+        })
+    }
+
+    // every call opens and closes its own handles so nothing stays dirty between calls
+    fn with_dir<R>(
+        &self,
+        dir_path: &str,
+        f: impl FnOnce(&SdDirectory<'_, 'a>) -> Result<R, SdCardError>,
+    ) -> Result<R, SdCardError> {
+        let volume = self.volume_manager.open_volume(VolumeIdx(0))?;
+        let mut dir = volume.open_root_dir()?;
+        for part in dir_path.split('/').filter(|p| !p.is_empty()) {
+            dir = dir.open_dir(part)?;
+        }
+        f(&dir)
+    }
+}
+
+fn split_path(path: &str) -> Result<(&str, &str), SdCardError> {
+    let path = path.trim_start_matches('/');
+    let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+    if name.is_empty() {
+        return Err(SdCardError::InvalidPath(path.to_string()));
+    }
+    Ok((dir, name))
+}
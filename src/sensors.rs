@@ -0,0 +1,221 @@
+// Temperature and humidity from the pins the GPIO page sets to ds18b20 or dht22. Every value is
+// a rule engine channel (`temp5`, `hum5`, `temp5_1` for the second DS18B20 on GPIO5, ...) and
+// keeps an hour of samples for the chart on the sensors page.
+use alloc::{collections::VecDeque, format, string::String, vec::Vec};
+use core::fmt::Write;
+use esp_hal::time::{Duration, Instant};
+use log::warn;
+
+use crate::{
+    dht22,
+    gpio_pins::{GpioPins, PinMode},
+    onewire::{self, OneWire, Rom},
+    rules::RuleEngine,
+};
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(30);
+const HISTORY_LEN: usize = 120;
+// attempts per read before the channel shows no value; CRC errors are mostly a disturbed slot
+const ATTEMPTS: usize = 3;
+// degrees or percent the chart covers at least
+const MIN_SPAN: f32 = 1.0;
+const CHART_HEIGHT: f32 = 100.0;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct SensorView {
+    pub(crate) name: String,
+    pub(crate) unit: &'static str,
+    pub(crate) value: Option<f32>,
+    // slint path commands, x is the sample index and y runs 0 (max) to CHART_HEIGHT (min)
+    pub(crate) chart: String,
+    pub(crate) min: f32,
+    pub(crate) max: f32,
+}
+
+struct Channel {
+    name: String,
+    unit: &'static str,
+    // GPIO number, the channel goes away with the pin's sensor mode
+    pin: u8,
+    value: Option<f32>,
+    history: VecDeque<f32>,
+}
+
+pub(crate) struct Sensors {
+    channels: Vec<Channel>,
+    next_sample: Instant,
+    // DS18B20s found per pin at the start of the running conversion
+    converting: Option<(Instant, Vec<(u8, Vec<Rom>)>)>,
+}
+
+impl Sensors {
+    pub(crate) fn new() -> Self {
+        Self { channels: Vec::new(), next_sample: Instant::now(), converting: None }
+    }
+
+    // true when there are new values to show
+    pub(crate) fn poll(&mut self, gpio: &mut GpioPins<'_>) -> bool {
+        if let Some((started, _)) = &self.converting {
+            if started.elapsed().as_millis() >= onewire::CONVERSION_MS {
+                let (_, buses) = self.converting.take().unwrap();
+                self.read_conversions(gpio, buses);
+                return true;
+            }
+        }
+        if Instant::now() < self.next_sample {
+            return false;
+        }
+        self.next_sample = Instant::now() + SAMPLE_INTERVAL;
+
+        let mut active = Vec::new();
+        let mut buses = Vec::new();
+        for (number, mode, flex) in gpio.sensor_pins() {
+            active.push(number);
+            match mode {
+                PinMode::Dht22 => match retry(|| dht22::read(flex)) {
+                    // a retry straight away gets the previous measurement, still recent enough
+                    Ok(reading) => {
+                        self.record(number, format!("temp{}", number), "C", reading.temperature);
+                        self.record(number, format!("hum{}", number), "%", reading.humidity);
+                    }
+                    Err(e) => {
+                        warn!("Sensors: DHT22 on GPIO{}: {}", number, e);
+                        self.fail(number);
+                    }
+                },
+                PinMode::OneWire => {
+                    let mut bus = OneWire::new(flex);
+                    let found = retry(|| bus.search()).and_then(|roms| {
+                        let roms: Vec<Rom> = roms.into_iter().filter(onewire::is_ds18b20).collect();
+                        bus.start_conversion()?;
+                        Ok(roms)
+                    });
+                    match found {
+                        Ok(roms) => buses.push((number, roms)),
+                        Err(e) => {
+                            warn!("Sensors: 1-Wire on GPIO{}: {}", number, e);
+                            self.fail(number);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.channels.retain(|c| active.contains(&c.pin));
+        if !buses.is_empty() {
+            self.converting = Some((Instant::now(), buses));
+        }
+        true
+    }
+
+    pub(crate) fn observe(&self, rule_engine: &mut RuleEngine) {
+        for channel in &self.channels {
+            if let Some(value) = channel.value {
+                rule_engine.observe(&channel.name, value);
+            }
+        }
+    }
+
+    pub(crate) fn view(&self) -> Vec<SensorView> {
+        self.channels
+            .iter()
+            .map(|channel| {
+                let (chart, min, max) = chart(&channel.history, MIN_SPAN);
+                SensorView {
+                    name: channel.name.clone(),
+                    unit: channel.unit,
+                    value: channel.value,
+                    chart,
+                    min,
+                    max,
+                }
+            })
+            .collect()
+    }
+
+    fn read_conversions(&mut self, gpio: &mut GpioPins<'_>, buses: Vec<(u8, Vec<Rom>)>) {
+        for (number, mode, flex) in gpio.sensor_pins() {
+            let Some((_, roms)) = buses.iter().find(|(n, _)| *n == number) else {
+                continue;
+            };
+            // switched to another mode while converting
+            if mode != PinMode::OneWire {
+                continue;
+            }
+            let mut bus = OneWire::new(flex);
+            for (i, rom) in roms.iter().enumerate() {
+                let name = match i {
+                    0 => format!("temp{}", number),
+                    _ => format!("temp{}_{}", number, i),
+                };
+                match retry(|| bus.read_temperature(rom)) {
+                    Ok(celsius) => self.record(number, name, "C", celsius),
+                    Err(e) => {
+                        warn!("Sensors: DS18B20 {}: {}", name, e);
+                        if let Some(channel) = self.channels.iter_mut().find(|c| c.name == name) {
+                            channel.value = None;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn record(&mut self, pin: u8, name: String, unit: &'static str, value: f32) {
+        let index = match self.channels.iter().position(|c| c.name == name) {
+            Some(index) => index,
+            None => {
+                let history = VecDeque::with_capacity(HISTORY_LEN);
+                self.channels.push(Channel { name, unit, pin, value: None, history });
+                self.channels.len() - 1
+            }
+        };
+        let channel = &mut self.channels[index];
+        channel.value = Some(value);
+        if channel.history.len() == HISTORY_LEN {
+            channel.history.pop_front();
+        }
+        channel.history.push_back(value);
+    }
+
+    // the pin's channels keep their history but show no value until a read works again
+    fn fail(&mut self, pin: u8) {
+        for channel in self.channels.iter_mut().filter(|c| c.pin == pin) {
+            channel.value = None;
+        }
+    }
+}
+
+// slint path commands over `values`, x is the index and y runs 0 (max) to CHART_HEIGHT (min),
+// empty below two values; with the range the height covers, at least `min_span` so noise
+// stays flat
+pub(crate) fn chart(values: &VecDeque<f32>, min_span: f32) -> (String, f32, f32) {
+    let min = values.iter().copied().fold(f32::MAX, f32::min);
+    let max = values.iter().copied().fold(f32::MIN, f32::max);
+    // widened around the middle when the values barely move
+    let mid = (min + max) / 2.0;
+    let (min, max) = if max - min < min_span {
+        (mid - min_span / 2.0, mid + min_span / 2.0)
+    } else {
+        (min, max)
+    };
+    let mut path = String::new();
+    if values.len() >= 2 {
+        for (i, value) in values.iter().enumerate() {
+            let y = (max - value) / (max - min) * CHART_HEIGHT;
+            let _ = write!(path, "{}{} {:.1} ", if i == 0 { 'M' } else { 'L' }, i, y);
+        }
+    }
+    (path, min, max)
+}
+
+fn retry<T, E>(mut read: impl FnMut() -> Result<T, E>) -> Result<T, E> {
+    let mut result = read();
+    for _ in 1..ATTEMPTS {
+        if result.is_ok() {
+            break;
+        }
+        result = read();
+    }
+    result
+}
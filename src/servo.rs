@@ -0,0 +1,200 @@
+// Hobby servo and a plain PWM output on the remaining LEDC timers. Targets from the sliders are
+// approached at a limited rate, a flicked slider must not slam a linkage or a motor driver.
+use esp_hal::{
+    gpio::{DriveMode, interconnect::PeripheralOutput},
+    ledc::{
+        Ledc, LowSpeed,
+        channel::{self, Channel, ChannelHW, ChannelIFace},
+        timer::{self, Timer, TimerIFace},
+    },
+    time::{Instant, Rate},
+};
+use thiserror::Error;
+
+pub(crate) const MAX_ANGLE: u16 = 180;
+// standard hobby servo frame: a 0.5 .. 2.5 ms pulse every 20 ms for 0 .. 180 degrees
+const SERVO_FRAME_US: u32 = 20_000;
+const SERVO_MIN_PULSE_US: u32 = 500;
+const SERVO_MAX_PULSE_US: u32 = 2_500;
+const SERVO_DUTY_MAX: u32 = (1 << 14) - 1;
+// degrees per second, well under what an unloaded SG90 does
+const SERVO_SLEW: u32 = 120;
+const PWM_FREQUENCY_HZ: u32 = 1000;
+const PWM_DUTY_MAX: u32 = (1 << 10) - 1;
+// percent per second, full scale in half a second
+const PWM_SLEW: u32 = 200;
+
+#[derive(Error, Debug)]
+pub(crate) enum ServoError {
+    #[error("Failed to configure LEDC timer")]
+    Timer,
+    #[error("Failed to configure LEDC channel")]
+    Channel,
+}
+
+// 50 Hz with 14 bits leaves ~1.2 us steps, about a tenth of a degree
+pub(crate) fn init_servo_timer<'a>(
+    ledc: &Ledc<'a>,
+    number: timer::Number,
+) -> Result<Timer<'a, LowSpeed>, ServoError> {
+    init_timer(ledc, number, timer::config::Duty::Duty14Bit, Rate::from_hz(50))
+}
+
+pub(crate) fn init_pwm_timer<'a>(
+    ledc: &Ledc<'a>,
+    number: timer::Number,
+) -> Result<Timer<'a, LowSpeed>, ServoError> {
+    init_timer(ledc, number, timer::config::Duty::Duty10Bit, Rate::from_hz(PWM_FREQUENCY_HZ))
+}
+
+// the global clock source is set by the backlight
+fn init_timer<'a>(
+    ledc: &Ledc<'a>,
+    number: timer::Number,
+    duty: timer::config::Duty,
+    frequency: Rate,
+) -> Result<Timer<'a, LowSpeed>, ServoError> {
+    let mut pwm_timer = ledc.timer::<LowSpeed>(number);
+    pwm_timer
+        .configure(timer::config::Config {
+            duty,
+            clock_source: timer::LSClockSource::APBClk,
+            frequency,
+        })
+        .map_err(|_| ServoError::Timer)?;
+    Ok(pwm_timer)
+}
+
+fn init_channel<'a>(
+    ledc: &Ledc<'a>,
+    pwm_timer: &'a Timer<'a, LowSpeed>,
+    number: channel::Number,
+    pin: impl PeripheralOutput<'a>,
+) -> Result<Channel<'a, LowSpeed>, ServoError> {
+    let mut channel = ledc.channel(number, pin);
+    channel
+        .configure(channel::config::Config {
+            timer: pwm_timer,
+            duty_pct: 0,
+            drive_mode: DriveMode::PushPull,
+        })
+        .map_err(|_| ServoError::Channel)?;
+    Ok(channel)
+}
+
+// moves towards the target by at most `per_sec` units per second, in thousandths of a unit so
+// short loop passes still make progress
+struct Slew {
+    current: Option<u32>,
+    target: u32,
+    per_sec: u32,
+    last_step: Instant,
+    // the position was set without a step, the output still has to hear about it
+    unsent: bool,
+}
+
+impl Slew {
+    fn new(per_sec: u32) -> Self {
+        Self { current: None, target: 0, per_sec, last_step: Instant::now(), unsent: false }
+    }
+
+    fn set(&mut self, target: u32) {
+        self.target = target * 1000;
+        // the first target is jumped to, there is nothing known to ramp from
+        if self.current.is_none() {
+            self.current = Some(self.target);
+            self.unsent = true;
+        }
+    }
+
+    fn current(&self) -> u32 {
+        self.current.unwrap_or(0) / 1000
+    }
+
+    // the new position in thousandths, None when there was nothing to move
+    fn step(&mut self) -> Option<u32> {
+        let elapsed = self.last_step.elapsed().as_micros();
+        self.last_step = Instant::now();
+        let current = self.current?;
+        if current == self.target && !core::mem::take(&mut self.unsent) {
+            return None;
+        }
+        let max_step = (elapsed * self.per_sec as u64 / 1000).max(1) as u32;
+        let next = if current < self.target {
+            current.saturating_add(max_step).min(self.target)
+        } else {
+            current.saturating_sub(max_step).max(self.target)
+        };
+        self.current = Some(next);
+        Some(next)
+    }
+}
+
+// limp until the first angle is set, there is no telling where the horn was left
+pub(crate) struct Servo<'a> {
+    channel: Channel<'a, LowSpeed>,
+    slew: Slew,
+}
+
+impl<'a> Servo<'a> {
+    pub(crate) fn new(
+        ledc: &Ledc<'a>,
+        pwm_timer: &'a Timer<'a, LowSpeed>,
+        number: channel::Number,
+        pin: impl PeripheralOutput<'a>,
+    ) -> Result<Self, ServoError> {
+        let channel = init_channel(ledc, pwm_timer, number, pin)?;
+        Ok(Self { channel, slew: Slew::new(SERVO_SLEW) })
+    }
+
+    pub(crate) fn angle(&self) -> u16 {
+        self.slew.current() as u16
+    }
+
+    pub(crate) fn set_angle(&mut self, degrees: u16) {
+        self.slew.set(degrees.min(MAX_ANGLE) as u32);
+    }
+
+    // has to run every loop iteration
+    pub(crate) fn poll(&mut self) {
+        if let Some(milli_degrees) = self.slew.step() {
+            let span = SERVO_MAX_PULSE_US - SERVO_MIN_PULSE_US;
+            let pulse_us = SERVO_MIN_PULSE_US + milli_degrees * span / (MAX_ANGLE as u32 * 1000);
+            self.channel.set_duty_hw(pulse_us * SERVO_DUTY_MAX / SERVO_FRAME_US);
+        }
+    }
+}
+
+pub(crate) struct PwmOutput<'a> {
+    channel: Channel<'a, LowSpeed>,
+    slew: Slew,
+}
+
+impl<'a> PwmOutput<'a> {
+    // starts low; the first duty ramps up from there
+    pub(crate) fn new(
+        ledc: &Ledc<'a>,
+        pwm_timer: &'a Timer<'a, LowSpeed>,
+        number: channel::Number,
+        pin: impl PeripheralOutput<'a>,
+    ) -> Result<Self, ServoError> {
+        let channel = init_channel(ledc, pwm_timer, number, pin)?;
+        let mut slew = Slew::new(PWM_SLEW);
+        slew.set(0);
+        Ok(Self { channel, slew })
+    }
+
+    pub(crate) fn duty(&self) -> u8 {
+        self.slew.current() as u8
+    }
+
+    pub(crate) fn set_duty(&mut self, percent: u8) {
+        self.slew.set(percent.min(100) as u32);
+    }
+
+    pub(crate) fn poll(&mut self) {
+        if let Some(milli_percent) = self.slew.step() {
+            self.channel.set_duty_hw(milli_percent * PWM_DUTY_MAX / 100_000);
+        }
+    }
+}
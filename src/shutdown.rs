@@ -0,0 +1,49 @@
+// Restarting or turning off on request, from the settings page, the console or the http
+// server, instead of by pulling the plug halfway through a card write. The request puts its
+// screen up first; once that has had SCREEN_MS to be drawn, the loop closes the websocket and
+// takes WiFi off the AP, saves the settings and the outbox, writes out the log queue and
+// darkens the panel before the reset or deep sleep. Powered off, the wake button starts it
+// again.
+use esp_hal::time::Instant;
+
+// long enough for the bindings to run and a frame to go out
+const SCREEN_MS: u64 = 300;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ShutdownKind {
+    Reboot,
+    PowerOff,
+}
+
+impl ShutdownKind {
+    // on the screen while it happens
+    pub(crate) fn message(self) -> &'static str {
+        match self {
+            ShutdownKind::Reboot => "Restarting...",
+            ShutdownKind::PowerOff => "Shutting down...",
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Shutdown {
+    pending: Option<(ShutdownKind, Instant)>,
+}
+
+impl Shutdown {
+    // false when one is already under way, the first one wins
+    pub(crate) fn request(&mut self, kind: ShutdownKind) -> bool {
+        if self.pending.is_some() {
+            return false;
+        }
+        self.pending = Some((kind, Instant::now()));
+        true
+    }
+
+    // the request, once its screen is up
+    pub(crate) fn due(&self) -> Option<ShutdownKind> {
+        self.pending
+            .filter(|(_, at)| at.elapsed().as_millis() >= SCREEN_MS)
+            .map(|(kind, _)| kind)
+    }
+}
@@ -0,0 +1,57 @@
+// WiFi signal strength for the bars on home. The RSSI is sampled every few seconds on the
+// network core and smoothed, a single sample swings by several dB with people walking past;
+// the UI only hears about it when the number of bars changes. Falling below WEAK_DBM is
+// logged once, and again once it has recovered by a few dB.
+use log::{info, warn};
+
+pub(crate) const SAMPLE_SECS: u64 = 5;
+// a sample moves the average a quarter of the way
+const SMOOTHING: f32 = 0.25;
+const WEAK_DBM: f32 = -80.0;
+const RECOVER_DBM: f32 = -75.0;
+// lowest RSSI for 1, 2, 3 and 4 bars
+const BAR_DBM: [f32; 4] = [-85.0, -75.0, -65.0, -55.0];
+
+pub(crate) struct SignalMonitor {
+    smoothed: Option<f32>,
+    bars: Option<u8>,
+    weak: bool,
+}
+
+impl SignalMonitor {
+    pub(crate) fn new() -> Self {
+        Self { smoothed: None, bars: None, weak: false }
+    }
+
+    // 0..=4 bars when that changed
+    pub(crate) fn sample(&mut self, rssi: i32) -> Option<u8> {
+        let rssi = rssi as f32;
+        let smoothed = match self.smoothed {
+            Some(smoothed) => smoothed + (rssi - smoothed) * SMOOTHING,
+            None => rssi,
+        };
+        self.smoothed = Some(smoothed);
+
+        if !self.weak && smoothed < WEAK_DBM {
+            self.weak = true;
+            warn!("WiFi: weak signal, {} dBm", smoothed as i32);
+        } else if self.weak && smoothed >= RECOVER_DBM {
+            self.weak = false;
+            info!("WiFi: signal recovered, {} dBm", smoothed as i32);
+        }
+
+        let bars = bars(smoothed as i32);
+        (self.bars.replace(bars) != Some(bars)).then_some(bars)
+    }
+
+    // after a reconnect the old average says nothing about the new link
+    pub(crate) fn reset(&mut self) {
+        self.smoothed = None;
+        self.bars = None;
+    }
+}
+
+// 0..=4, also for the networks of a scan
+pub(crate) fn bars(rssi: i32) -> u8 {
+    BAR_DBM.iter().filter(|dbm| rssi as f32 >= **dbm).count() as u8
+}
@@ -0,0 +1,70 @@
+// Light sleep while nobody is looking. Once the screen has been off for `light_sleep_after_secs`
+// the network core is asked to take WiFi down (the radio doesn't survive light sleep), and once
+// it has, the loop light-sleeps between passes instead of spinning: until slint's next timer, or
+// until the touch IRQ pulls EXT0 low. The panel is already in its sleep mode by then, the screen
+// going off put it there. Waking the screen, by touch or by anything that calls `idle.wake()`,
+// brings WiFi back.
+use esp_hal::time::Instant;
+
+use crate::{config::SleepConfig, power::ScreenState};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Awake,
+    // Suspend sent, the network core hasn't confirmed yet
+    Suspending,
+    Asleep,
+}
+
+// what the network core has to be told
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum SleepAction {
+    SuspendNetwork,
+    ResumeNetwork,
+}
+
+pub(crate) struct LowPower {
+    config: SleepConfig,
+    off_since: Option<Instant>,
+    state: State,
+}
+
+impl LowPower {
+    pub(crate) fn new(config: SleepConfig) -> Self {
+        Self { config, off_since: None, state: State::Awake }
+    }
+
+    pub(crate) fn set_config(&mut self, config: SleepConfig) {
+        self.config = config;
+    }
+
+    // once per pass with the screen state
+    pub(crate) fn poll(&mut self, screen: ScreenState) -> Option<SleepAction> {
+        if screen != ScreenState::Off {
+            self.off_since = None;
+            if self.state == State::Awake {
+                return None;
+            }
+            self.state = State::Awake;
+            return Some(SleepAction::ResumeNetwork);
+        }
+        let off_since = *self.off_since.get_or_insert_with(Instant::now);
+        let after = self.config.light_sleep_after_secs as u64;
+        if self.state != State::Awake || after == 0 || off_since.elapsed().as_secs() < after {
+            return None;
+        }
+        self.state = State::Suspending;
+        Some(SleepAction::SuspendNetwork)
+    }
+
+    // the network core is down, light sleep can't cut it off mid-transfer any more
+    pub(crate) fn network_suspended(&mut self) {
+        if self.state == State::Suspending {
+            self.state = State::Asleep;
+        }
+    }
+
+    pub(crate) fn may_sleep(&self) -> bool {
+        self.state == State::Asleep
+    }
+}
@@ -1,35 +1,235 @@
+use alloc::{vec, vec::Vec};
 use core::ops::Range;
 
-use embedded_graphics_core::pixelcolor::raw::RawU16;
-use esp_hal::gpio::Output;
+use embedded_graphics_core::{draw_target::DrawTarget, pixelcolor::raw::RawU16};
+use esp_hal::{delay::Delay, gpio::Output, time::Instant};
 use mipidsi::{
     Display,
     interface::{Interface, InterfacePixelFormat},
     models::Model,
 };
-use slint::platform::software_renderer::{LineBufferProvider, Rgb565Pixel};
+#[cfg(feature = "psram")]
+use slint::platform::software_renderer::{SoftwareRenderer, TargetPixel};
+use slint::platform::software_renderer::{LineBufferProvider, RepaintBufferType, Rgb565Pixel};
 
-pub(crate) struct SlintRenderer<'a, DI: Interface, MODEL: Model>
+#[cfg(feature = "psram")]
+use crate::display_screen::{PANEL_HEIGHT, PANEL_WIDTH};
+#[cfg(feature = "palette")]
+use crate::palette::{self, IndexedPixel};
+use crate::{
+    coexistence::{CoexConfig, FlushPacer},
+    color_lut::{ColorCalibration, ColorLut},
+    display_screen::{DisplayScreenError, ScreenOrientation},
+    hw_scroll::{HwScroll, ListScroll, ScrollCommand},
+    span_flush::SpanFlush,
+};
+
+// lines sent between two touch samples while a frame is drawn, about 2 ms at 40 MHz
+const SAMPLE_EVERY_LINES: usize = 16;
+
+// what the full frames hold, expanded to RGB565 on the way out with `palette`
+#[cfg(all(feature = "psram", not(feature = "palette")))]
+type FramePixel = Rgb565Pixel;
+#[cfg(feature = "palette")]
+type FramePixel = IndexedPixel;
+
+// with two full frames slint redraws what changed in either of them; drawn line by line the
+// panel's frame memory is the one buffer, reused every frame. Partial buffers can't be
+// swapped, slint needs whole frames to know what the older one still shows.
+pub(crate) fn repaint_buffer(swapped: bool) -> RepaintBufferType {
+    if swapped {
+        RepaintBufferType::SwappedBuffers
+    } else {
+        RepaintBufferType::ReusedBuffer
+    }
+}
+
+pub(crate) struct SlintRenderer<'a, DI: Interface<Word = u8>, MODEL: Model>
 where
     MODEL::ColorFormat: InterfacePixelFormat<DI::Word> + From<RawU16>,
 {
     display: Display<DI, MODEL, Output<'a>>,
-    buffer: [Rgb565Pixel; 320],
+    // a line of the window as it is now, resized when the orientation changes
+    buffer: Vec<Rgb565Pixel>,
+    scroll: HwScroll,
+    flush: SpanFlush,
+    lut: ColorLut,
+    pacer: FlushPacer,
 }
 
-impl<'a, DI: Interface, MODEL: Model> SlintRenderer<'a, DI, MODEL>
+impl<'a, DI: Interface<Word = u8>, MODEL: Model> SlintRenderer<'a, DI, MODEL>
 where
     MODEL::ColorFormat: InterfacePixelFormat<DI::Word> + From<RawU16>,
 {
-    pub(crate) fn new(display: Display<DI, MODEL, Output<'a>>) -> Self {
+    // `orientation` is the one the display was initialized with
+    pub(crate) fn new(
+        display: Display<DI, MODEL, Output<'a>>,
+        orientation: ScreenOrientation,
+        color: &ColorCalibration,
+    ) -> Self {
         Self {
             display,
-            buffer: [Rgb565Pixel(0); 320],
+            buffer: vec![Rgb565Pixel(0); orientation.size().0 as usize],
+            scroll: HwScroll::new(orientation.scroll_rows()),
+            flush: SpanFlush::new(),
+            lut: ColorLut::new(color),
+            pacer: FlushPacer::new(CoexConfig::default()),
+        }
+    }
+
+    // how large frames make way for the radio, COEX.TXT once the card is up
+    pub(crate) fn set_coexistence(&mut self, config: CoexConfig) {
+        self.pacer = FlushPacer::new(config);
+    }
+
+    // from the next frame on, the caller redraws everything
+    pub(crate) fn set_color(&mut self, color: &ColorCalibration) {
+        self.lut = ColorLut::new(color);
+    }
+
+    // an image straight to the panel, centered on black, before slint draws anything; `row`
+    // fills in one row of the image at a time, false when it couldn't
+    pub(crate) fn draw_image(
+        &mut self,
+        (width, height): (usize, usize),
+        (screen_width, screen_height): (usize, usize),
+        mut row: impl FnMut(usize, &mut [Rgb565Pixel]) -> bool,
+    ) -> bool {
+        if width > screen_width || height > screen_height || width > self.buffer.len() {
+            return false;
+        }
+        let _ = self.display.clear(MODEL::ColorFormat::from(RawU16::new(0)));
+        // slint's first frame has to cover all of it
+        self.flush.invalidate();
+        let (left, top) = ((screen_width - width) / 2, (screen_height - height) / 2);
+        for y in 0..height {
+            let line = &mut self.buffer[..width];
+            if !row(y, line) {
+                return false;
+            }
+            self.lut.apply(line);
+            let (columns, rows) = (left..left + width, top + y..top + y + 1);
+            send_window(&mut self.display, &mut self.pacer, columns, rows, line);
         }
+        true
+    }
+
+    // paints the panel black and puts the controller to sleep
+    pub(crate) fn blank(&mut self) {
+        self.send_buffered();
+        self.flush.invalidate();
+        let _ = self.display.clear(MODEL::ColorFormat::from(RawU16::new(0)));
+        let _ = self.display.sleep(&mut Delay::new());
+    }
+
+    // frame memory is retained while asleep, waking shows the last frame again
+    pub(crate) fn sleep(&mut self) {
+        let _ = self.display.sleep(&mut Delay::new());
+    }
+
+    pub(crate) fn wake(&mut self) {
+        let _ = self.display.wake(&mut Delay::new());
+    }
+
+    // rewrites MADCTL, the caller resizes the window to match and redraws everything
+    pub(crate) fn set_orientation(
+        &mut self,
+        orientation: ScreenOrientation,
+    ) -> Result<(), DisplayScreenError> {
+        self.reset_scroll();
+        self.scroll = HwScroll::new(orientation.scroll_rows());
+        self.buffer.resize(orientation.size().0 as usize, Rgb565Pixel(0));
+        // the same frame memory shows somewhere else now
+        self.send_buffered();
+        self.flush.invalidate();
+        self.display
+            .set_orientation(orientation.madctl())
+            .map_err(|_| DisplayScreenError::Orientation)
+    }
+
+    // a ScrollList moved, the panel shifts it where it can and the next frame only sends the
+    // rows that came into view; true when the caller has to redraw everything
+    pub(crate) fn scroll_list(&mut self, list: ListScroll) -> bool {
+        match self.scroll.scroll(list) {
+            Some(ScrollCommand::Define { top_fixed, bottom_fixed, start, redraw }) => {
+                let _ = self.display.set_vertical_scroll_region(top_fixed, bottom_fixed);
+                let _ = self.display.set_vertical_scroll_offset(start);
+                redraw
+            }
+            Some(ScrollCommand::Start(start)) => {
+                let _ = self.display.set_vertical_scroll_offset(start);
+                false
+            }
+            None => false,
+        }
+    }
+
+    // back to unscrolled frame memory, e.g. when the list's page is left; true when the caller
+    // has to redraw everything
+    pub(crate) fn reset_scroll(&mut self) -> bool {
+        if !self.scroll.reset() {
+            return false;
+        }
+        let _ = self.display.set_vertical_scroll_region(0, 0);
+        let _ = self.display.set_vertical_scroll_offset(0);
+        true
+    }
+
+    // after every frame rendered, it isn't all on the panel before this
+    pub(crate) fn frame_drawn(&mut self) {
+        self.send_buffered();
+        self.scroll.drawn();
+        self.pacer.frame_done();
+    }
+
+    // true once a scrolled list has come to rest, the caller redraws everything for whatever
+    // changed in it while the panel was shifting it
+    pub(crate) fn scroll_settled(&mut self) -> bool {
+        self.scroll.settled()
+    }
+
+    fn send_buffered(&mut self) {
+        let (display, pacer) = (&mut self.display, &mut self.pacer);
+        self.flush.finish(&mut |columns, rows, pixels| {
+            send_window(display, pacer, columns, rows, pixels)
+        });
+    }
+
+    #[cfg(feature = "psram")]
+    fn send(&mut self, line: usize, range: Range<usize>, pixels: &[FramePixel]) {
+        let Some(row) = self.scroll.row(line) else {
+            return;
+        };
+        #[cfg(feature = "palette")]
+        let pixels = {
+            let Some(expanded) = self.buffer.get_mut(..pixels.len()) else {
+                return;
+            };
+            palette::expand(pixels, expanded);
+            self.lut.apply(expanded);
+            &*expanded
+        };
+        // the frame is slint's to compare the next one against, corrected pixels go elsewhere
+        #[cfg(not(feature = "palette"))]
+        let pixels = if self.lut.is_identity() {
+            pixels
+        } else {
+            let Some(corrected) = self.buffer.get_mut(..pixels.len()) else {
+                return;
+            };
+            corrected.copy_from_slice(pixels);
+            self.lut.apply(corrected);
+            &*corrected
+        };
+        let (display, pacer) = (&mut self.display, &mut self.pacer);
+        self.flush.line(row, range, pixels, &mut |columns, rows, pixels| {
+            send_window(display, pacer, columns, rows, pixels)
+        });
     }
 }
 
-impl<'a, DI: Interface, MODEL: Model> LineBufferProvider for &mut SlintRenderer<'a, DI, MODEL>
+impl<'a, DI: Interface<Word = u8>, MODEL: Model> LineBufferProvider
+    for &mut SlintRenderer<'a, DI, MODEL>
 where
     MODEL::ColorFormat: InterfacePixelFormat<DI::Word> + From<RawU16>,
 {
@@ -41,17 +241,134 @@ where
         range: Range<usize>,
         render_fn: impl FnOnce(&mut [Rgb565Pixel]),
     ) {
-        let buf = &mut self.buffer[range.clone()];
+        // rows the panel already shows aren't rendered either
+        let Some(row) = self.scroll.row(line) else {
+            return;
+        };
+        let this = &mut **self;
+        // a window wider than the orientation the renderer was given, the line is dropped
+        let Some(buf) = this.buffer.get_mut(range.clone()) else {
+            return;
+        };
         render_fn(buf);
-        self.display
-            .set_pixels(
-                range.start as u16,
-                line as u16,
-                range.end as u16,
-                line as u16,
-                buf.iter()
-                    .map(|x| MODEL::ColorFormat::from(RawU16::new(x.0))),
-            )
-            .unwrap();
+        this.lut.apply(buf);
+        let (display, pacer) = (&mut this.display, &mut this.pacer);
+        this.flush.line(row, range, buf, &mut |columns, rows, pixels| {
+            send_window(display, pacer, columns, rows, pixels)
+        });
+    }
+}
+
+// runs `sample` every few lines of a frame, the loop reads touch with it so taps during a slow
+// redraw are queued instead of missed
+pub(crate) struct Sampled<P, F> {
+    target: P,
+    sample: F,
+    lines: usize,
+}
+
+impl<P, F: FnMut()> Sampled<P, F> {
+    pub(crate) fn new(target: P, sample: F) -> Self {
+        Self { target, sample, lines: 0 }
+    }
+}
+
+impl<P: LineBufferProvider, F: FnMut()> LineBufferProvider for Sampled<P, F> {
+    type TargetPixel = P::TargetPixel;
+
+    fn process_line(
+        &mut self,
+        line: usize,
+        range: Range<usize>,
+        render_fn: impl FnOnce(&mut [P::TargetPixel]),
+    ) {
+        self.target.process_line(line, range, render_fn);
+        self.lines += 1;
+        if self.lines % SAMPLE_EVERY_LINES == 0 {
+            (self.sample)();
+        }
+    }
+}
+
+// one address window, `pixels` row by row; the window's ends are inclusive. mipidsi sets the
+// window, with whatever offset the panel has, and starts the memory write without pixels; the
+// line then goes to the interface as it is, each pixel's bytes swapped to the high first order
+// the panels take, instead of through a colour conversion per pixel. Large frames hold back
+// while the radio is busy, see `coexistence`
+fn send_window<DI: Interface<Word = u8>, MODEL: Model>(
+    display: &mut Display<DI, MODEL, Output<'_>>,
+    pacer: &mut FlushPacer,
+    columns: Range<usize>,
+    rows: Range<usize>,
+    pixels: &[Rgb565Pixel],
+) where
+    MODEL::ColorFormat: InterfacePixelFormat<DI::Word> + From<RawU16>,
+{
+    pacer.before();
+    let started = Instant::now();
+    display
+        .set_pixels(
+            columns.start as u16,
+            rows.start as u16,
+            columns.end as u16 - 1,
+            rows.end as u16 - 1,
+            core::iter::empty(),
+        )
+        .unwrap();
+    // data until the next command still goes to frame memory
+    let interface = unsafe { display.dcs() };
+    interface.send_pixels(pixels.iter().map(|x| x.0.to_be_bytes())).unwrap();
+    pacer.after(pixels.len(), started);
+}
+
+// Two full frames for WROVER builds: slint renders the dirty area in one pass instead of line by
+// line, and only the changed rectangles are sent to the panel. The window has to be created
+// with `repaint_buffer(true)`.
+#[cfg(feature = "psram")]
+pub(crate) struct FrameBuffers {
+    frames: [Vec<FramePixel>; 2],
+    back: usize,
+}
+
+#[cfg(feature = "psram")]
+impl FrameBuffers {
+    // 150 KB each (300 KB for the ILI9486, half that with `palette`), far past what internal
+    // RAM has left, so the allocator places them in PSRAM; None when the heap can't hold both
+    pub(crate) fn try_new() -> Option<Self> {
+        let len = (PANEL_WIDTH * PANEL_HEIGHT) as usize;
+        let frame = || {
+            let mut frame = Vec::new();
+            frame.try_reserve_exact(len).ok()?;
+            frame.resize(len, FramePixel::from_rgb(0, 0, 0));
+            Some(frame)
+        };
+        Some(Self { frames: [frame()?, frame()?], back: 0 })
+    }
+
+    // `stride` is the window width, 240 in portrait; `sample` runs between lines like `Sampled`
+    pub(crate) fn render<DI: Interface<Word = u8>, MODEL: Model>(
+        &mut self,
+        renderer: &SoftwareRenderer,
+        target: &mut SlintRenderer<'_, DI, MODEL>,
+        stride: usize,
+        mut sample: impl FnMut(),
+    ) where
+        MODEL::ColorFormat: InterfacePixelFormat<DI::Word> + From<RawU16>,
+    {
+        let frame = &mut self.frames[self.back];
+        let region = renderer.render(frame, stride);
+        let mut lines = 0;
+        for (origin, size) in region.iter() {
+            let (x, width) = (origin.x as usize, size.width as usize);
+            for y in origin.y as usize..origin.y as usize + size.height as usize {
+                let start = y * stride + x;
+                target.send(y, x..x + width, &frame[start..start + width]);
+                lines += 1;
+                if lines % SAMPLE_EVERY_LINES == 0 {
+                    sample();
+                }
+            }
+        }
+        self.back ^= 1;
     }
 }
@@ -0,0 +1,75 @@
+use alloc::rc::Rc;
+use blocking_network_stack::Stack;
+use esp_hal::time::{Duration, Instant};
+use esp_radio::wifi::WifiDevice;
+use smoltcp::wire::IpAddress;
+use thiserror::Error;
+
+use crate::udp::{Datagram, UdpError};
+
+const NTP_PORT: u16 = 123;
+const LOCAL_PORT: u16 = 50123;
+// seconds between 1900-01-01 (NTP era 0) and 1970-01-01
+const NTP_UNIX_OFFSET: u32 = 2_208_988_800;
+const PACKET_LEN: usize = 48;
+
+#[derive(Error, Debug)]
+pub(crate) enum SntpError {
+    #[error("UDP socket error: {0}")]
+    Socket(#[from] UdpError),
+    #[error("No reply within the timeout")]
+    Timeout,
+    #[error("Server sent an invalid or kiss-o'-death reply")]
+    BadReply,
+}
+
+// single request/response SNTPv4 client, accurate to about a second which is all the clock needs
+pub(crate) struct SntpClient<'a> {
+    stack: Rc<Stack<'a, WifiDevice<'a>>>,
+    server: IpAddress,
+}
+
+impl<'a> SntpClient<'a> {
+    pub(crate) fn new(stack: Rc<Stack<'a, WifiDevice<'a>>>, server: IpAddress) -> Self {
+        Self { stack, server }
+    }
+
+    // returns unix seconds
+    pub(crate) fn query(&mut self, timeout: Duration) -> Result<u32, SntpError> {
+        // leaves the socket set when dropped, between queries it takes no room
+        let mut socket = Datagram::bind(&self.stack, LOCAL_PORT, 2, 128)?;
+
+        // LI 0, version 4, mode 3 (client); the transmit timestamp doubles as a nonce
+        let nonce = Instant::now().duration_since_epoch().as_micros().to_be_bytes();
+        let mut request = [0u8; PACKET_LEN];
+        request[0] = 0x23;
+        request[40..48].copy_from_slice(&nonce);
+        socket.send_to(self.server, NTP_PORT, &request)?;
+
+        let deadline = Instant::now() + timeout;
+        let mut reply = [0u8; PACKET_LEN];
+        loop {
+            if let Some((len, _, port)) = socket.recv_from(&mut reply) {
+                if len >= PACKET_LEN && port == NTP_PORT {
+                    return parse_reply(&reply, &nonce);
+                }
+            }
+            if Instant::now() > deadline {
+                return Err(SntpError::Timeout);
+            }
+        }
+    }
+}
+
+fn parse_reply(reply: &[u8; PACKET_LEN], nonce: &[u8; 8]) -> Result<u32, SntpError> {
+    let mode = reply[0] & 0x07;
+    let stratum = reply[1];
+    // the originate timestamp echoes our transmit timestamp
+    if mode != 4 || stratum == 0 || &reply[24..32] != nonce {
+        return Err(SntpError::BadReply);
+    }
+
+    let ntp_secs = u32::from_be_bytes([reply[40], reply[41], reply[42], reply[43]]);
+    // era 1 starts in 2036, values below the offset have wrapped
+    Ok(ntp_secs.wrapping_sub(NTP_UNIX_OFFSET))
+}
@@ -0,0 +1,187 @@
+// The rx and tx buffers of every TCP socket on the network core. smoltcp wants a socket's
+// buffers for as long as the socket exists; the pool allocates them once, when the stack is
+// built, and never frees them, so a lent buffer can't dangle whatever the socket does. A pair
+// is lent to whoever opens a socket: the websocket and the http server for good, requests for
+// as long as they run. A `PooledSocket` owns the socket and its pair and drops them in that
+// order, so nothing outside this file has to reason about the buffers' lifetime. Requests
+// holding a pair longer than LEAK_SECS are reported as leaks, once each, by `check`.
+use alloc::{boxed::Box, rc::Rc, vec, vec::Vec};
+use blocking_network_stack::{Socket, Stack};
+use core::{
+    cell::RefCell,
+    ops::{Deref, DerefMut},
+};
+use esp_hal::time::Instant;
+use esp_radio::wifi::WifiDevice;
+use log::warn;
+
+// the websocket, the http server, a blocking request and the outbox post running beside it
+pub(crate) const SLOTS: usize = 4;
+const BUFFER_SIZE: usize = 1536;
+// a request that ran this long has missed its timeout and linger several times over
+const LEAK_SECS: u64 = 60;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum SocketUser {
+    Http,
+    Ws,
+    Server,
+}
+
+impl SocketUser {
+    // only these are expected to give their pair back
+    fn short_lived(self) -> bool {
+        self == SocketUser::Http
+    }
+}
+
+// a buffer pair, leaked so it lives as long as the program
+struct Pair {
+    rx: *mut [u8],
+    tx: *mut [u8],
+}
+
+struct Slot {
+    // None while the pair is lent out, the `TcpBuffers` guard holds it then
+    pair: Option<Pair>,
+    user: Option<SocketUser>,
+    since: Instant,
+    reported: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct PoolStats {
+    pub(crate) in_use: usize,
+    // the most in use at once since boot
+    pub(crate) peak: usize,
+    // takes that found every pair lent out
+    pub(crate) exhausted: u32,
+}
+
+struct Inner {
+    slots: Vec<Slot>,
+    stats: PoolStats,
+}
+
+#[derive(Clone)]
+pub(crate) struct SocketPool {
+    inner: Rc<RefCell<Inner>>,
+}
+
+impl SocketPool {
+    pub(crate) fn new() -> Self {
+        let slots = (0..SLOTS)
+            .map(|_| Slot {
+                pair: Some(Pair {
+                    rx: Box::into_raw(vec![0u8; BUFFER_SIZE].into_boxed_slice()),
+                    tx: Box::into_raw(vec![0u8; BUFFER_SIZE].into_boxed_slice()),
+                }),
+                user: None,
+                since: Instant::now(),
+                reported: false,
+            })
+            .collect();
+        Self { inner: Rc::new(RefCell::new(Inner { slots, stats: PoolStats::default() })) }
+    }
+
+    // a TCP socket on `stack` with a pair of the pool's buffers, None when every pair is lent out
+    pub(crate) fn open<'s, 'a>(
+        &self,
+        stack: &'s Stack<'a, WifiDevice<'a>>,
+        user: SocketUser,
+    ) -> Option<PooledSocket<'s, 'a>> {
+        let buffers = self.take(user)?;
+        let pair = buffers.pair.as_ref()?;
+        // SAFETY: the pair is leaked, so valid for 'static, and it left its slot for `buffers`,
+        // so no other socket can be given it until `buffers` puts it back. That happens after
+        // the socket is dropped, and blocking_network_stack's `Socket` removes itself from the
+        // stack's socket set when it is dropped, so smoltcp doesn't keep the references past it
+        let (rx, tx): (&'static mut [u8], &'static mut [u8]) =
+            unsafe { (&mut *pair.rx, &mut *pair.tx) };
+        Some(PooledSocket { socket: stack.get_socket(rx, tx), _buffers: buffers })
+    }
+
+    fn take(&self, user: SocketUser) -> Option<TcpBuffers> {
+        let mut inner = self.inner.borrow_mut();
+        let Some(index) = inner.slots.iter().position(|slot| slot.pair.is_some()) else {
+            inner.stats.exhausted += 1;
+            warn!("Sockets: none free for {:?}", user);
+            return None;
+        };
+        let slot = &mut inner.slots[index];
+        let pair = slot.pair.take();
+        slot.user = Some(user);
+        slot.since = Instant::now();
+        slot.reported = false;
+        let stats = &mut inner.stats;
+        stats.in_use += 1;
+        stats.peak = stats.peak.max(stats.in_use);
+        Some(TcpBuffers { pool: self.inner.clone(), index, pair })
+    }
+
+    pub(crate) fn stats(&self) -> PoolStats {
+        self.inner.borrow().stats
+    }
+
+    // logs pairs held past LEAK_SECS, each once; the number of them
+    pub(crate) fn check(&self) -> usize {
+        let mut inner = self.inner.borrow_mut();
+        let mut leaked = 0;
+        for (index, slot) in inner.slots.iter_mut().enumerate() {
+            let Some(user) = slot.user.filter(|user| user.short_lived()) else {
+                continue;
+            };
+            if slot.since.elapsed().as_secs() < LEAK_SECS {
+                continue;
+            }
+            leaked += 1;
+            if !core::mem::replace(&mut slot.reported, true) {
+                warn!(
+                    "Sockets: slot {} held by {:?} for {} s",
+                    index,
+                    user,
+                    slot.since.elapsed().as_secs()
+                );
+            }
+        }
+        leaked
+    }
+}
+
+pub(crate) struct PooledSocket<'s, 'a> {
+    socket: Socket<'s, 'a, WifiDevice<'a>>,
+    // after the socket, fields are dropped in order
+    _buffers: TcpBuffers,
+}
+
+impl<'s, 'a> Deref for PooledSocket<'s, 'a> {
+    type Target = Socket<'s, 'a, WifiDevice<'a>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.socket
+    }
+}
+
+impl DerefMut for PooledSocket<'_, '_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.socket
+    }
+}
+
+// a lent pair, back in its slot when dropped
+struct TcpBuffers {
+    pool: Rc<RefCell<Inner>>,
+    index: usize,
+    // only None once it is dropped
+    pair: Option<Pair>,
+}
+
+impl Drop for TcpBuffers {
+    fn drop(&mut self) {
+        let mut inner = self.pool.borrow_mut();
+        let slot = &mut inner.slots[self.index];
+        slot.pair = self.pair.take();
+        slot.user = None;
+        inner.stats.in_use -= 1;
+    }
+}
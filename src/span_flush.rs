@@ -0,0 +1,190 @@
+// What actually goes over SPI for a rendered line. slint hands a line over as one range from
+// its leftmost to its rightmost dirty pixel, so a clock in one corner and a counter in the
+// other send the whole row between them. Every 32 pixel chunk of frame memory keeps a hash of
+// what was last written to it, and of a rendered line only the chunks that differ are sent,
+// each run of them in an address window of its own. Runs covering the same columns on
+// consecutive rows are buffered and go out as one window, the column and row addresses are
+// then set once for the lot instead of for every line.
+use alloc::vec::Vec;
+use core::ops::Range;
+use slint::platform::software_renderer::Rgb565Pixel;
+
+use crate::display_screen::PANEL_WIDTH;
+
+const CHUNK: usize = 32;
+// the long side, the most rows (portrait) and the most columns (landscape) there can be
+const LONG_SIDE: usize = PANEL_WIDTH as usize;
+const CHUNKS_PER_ROW: usize = LONG_SIDE.div_ceil(CHUNK);
+// pixels a batch holds before it is sent, about 2 KB each
+const BATCH_PIXELS: usize = 1024;
+// windows open at once, slint's dirty region has at most three rectangles
+const MAX_BATCHES: usize = 3;
+// a chunk nothing is known about; a hash that comes out as this is stored as 1
+const UNKNOWN: u32 = 0;
+
+struct Batch {
+    columns: Range<usize>,
+    // empty while the batch is unused
+    rows: Range<usize>,
+    pixels: Vec<Rgb565Pixel>,
+}
+
+pub(crate) struct SpanFlush {
+    // per frame memory row and chunk; empty when the heap couldn't spare it, every range is
+    // sent as given then
+    hashes: Vec<u32>,
+    // empty when the heap couldn't spare them, runs are sent right away then
+    batches: Vec<Batch>,
+}
+
+impl SpanFlush {
+    pub(crate) fn new() -> Self {
+        let mut hashes = Vec::new();
+        if hashes.try_reserve_exact(LONG_SIDE * CHUNKS_PER_ROW).is_ok() {
+            hashes.resize(LONG_SIDE * CHUNKS_PER_ROW, UNKNOWN);
+        }
+        let mut batches = Vec::new();
+        for _ in 0..MAX_BATCHES {
+            let mut pixels = Vec::new();
+            if pixels.try_reserve_exact(BATCH_PIXELS).is_err() {
+                batches.clear();
+                break;
+            }
+            batches.push(Batch { columns: 0..0, rows: 0..0, pixels });
+        }
+        Self { hashes, batches }
+    }
+
+    // `row` in frame memory, `pixels` the rendered `columns`; `send` gets a window's columns,
+    // rows and pixels row by row
+    pub(crate) fn line(
+        &mut self,
+        row: usize,
+        columns: Range<usize>,
+        pixels: &[Rgb565Pixel],
+        send: &mut impl FnMut(Range<usize>, Range<usize>, &[Rgb565Pixel]),
+    ) {
+        self.flush_stale(row, send);
+        let offset = columns.start;
+        let mut run = None;
+        let mut x = columns.start;
+        while x < columns.end {
+            let end = ((x / CHUNK + 1) * CHUNK).min(columns.end);
+            let changed = self.update(row, x..end, &pixels[x - offset..end - offset]);
+            match (changed, run) {
+                (true, None) => run = Some(x),
+                (false, Some(start)) => {
+                    self.queue(row, start..x, &pixels[start - offset..x - offset], send);
+                    run = None;
+                }
+                _ => {}
+            }
+            x = end;
+        }
+        if let Some(start) = run {
+            self.queue(row, start..columns.end, &pixels[start - offset..], send);
+        }
+    }
+
+    // at the end of a frame, whatever is still buffered
+    pub(crate) fn finish(
+        &mut self,
+        send: &mut impl FnMut(Range<usize>, Range<usize>, &[Rgb565Pixel]),
+    ) {
+        for i in 0..self.batches.len() {
+            self.flush(i, send);
+        }
+    }
+
+    // frame memory was written around this, or now maps differently; call `finish` first
+    pub(crate) fn invalidate(&mut self) {
+        self.hashes.fill(UNKNOWN);
+    }
+
+    // false when the chunk already holds exactly these pixels
+    fn update(&mut self, row: usize, columns: Range<usize>, pixels: &[Rgb565Pixel]) -> bool {
+        let Some(slot) = self.hashes.get_mut(row * CHUNKS_PER_ROW + columns.start / CHUNK) else {
+            return true;
+        };
+        let hash = hash(columns.start % CHUNK, pixels);
+        core::mem::replace(slot, hash) != hash
+    }
+
+    fn queue(
+        &mut self,
+        row: usize,
+        columns: Range<usize>,
+        pixels: &[Rgb565Pixel],
+        send: &mut impl FnMut(Range<usize>, Range<usize>, &[Rgb565Pixel]),
+    ) {
+        if self.batches.is_empty() {
+            send(columns, row..row + 1, pixels);
+            return;
+        }
+        let continued = self.batches.iter().position(|b| b.columns == columns && b.rows.end == row);
+        if let Some(i) = continued {
+            let batch = &mut self.batches[i];
+            if batch.pixels.len() + pixels.len() <= BATCH_PIXELS {
+                batch.pixels.extend_from_slice(pixels);
+                batch.rows.end += 1;
+                return;
+            }
+        }
+        // the batch it would have continued, a free one, or the one open longest
+        let i = continued
+            .or_else(|| self.batches.iter().position(|b| b.rows.is_empty()))
+            .or_else(|| (0..self.batches.len()).min_by_key(|&i| self.batches[i].rows.start))
+            .unwrap_or(0);
+        self.flush(i, send);
+        if pixels.len() > BATCH_PIXELS {
+            send(columns, row..row + 1, pixels);
+            return;
+        }
+        let batch = &mut self.batches[i];
+        batch.columns = columns;
+        batch.rows = row..row + 1;
+        batch.pixels.extend_from_slice(pixels);
+    }
+
+    // batches the line can no longer continue, a batch that took this row already stays
+    fn flush_stale(
+        &mut self,
+        row: usize,
+        send: &mut impl FnMut(Range<usize>, Range<usize>, &[Rgb565Pixel]),
+    ) {
+        for i in 0..self.batches.len() {
+            let rows = &self.batches[i].rows;
+            if !rows.is_empty() && rows.end != row && rows.end != row + 1 {
+                self.flush(i, send);
+            }
+        }
+    }
+
+    fn flush(
+        &mut self,
+        i: usize,
+        send: &mut impl FnMut(Range<usize>, Range<usize>, &[Rgb565Pixel]),
+    ) {
+        let batch = &mut self.batches[i];
+        if batch.rows.is_empty() {
+            return;
+        }
+        send(batch.columns.clone(), batch.rows.clone(), &batch.pixels);
+        batch.rows = 0..0;
+        batch.pixels.clear();
+    }
+}
+
+// FNV-1a over the pixels and where in the chunk they start, a partial chunk never matches
+// the whole one
+fn hash(start: usize, pixels: &[Rgb565Pixel]) -> u32 {
+    let mut hash = 0x811c_9dc5u32;
+    let mut mix = |byte: u8| hash = (hash ^ byte as u32).wrapping_mul(0x0100_0193);
+    mix(start as u8);
+    mix(pixels.len() as u8);
+    for pixel in pixels {
+        mix(pixel.0 as u8);
+        mix((pixel.0 >> 8) as u8);
+    }
+    if hash == UNKNOWN { 1 } else { hash }
+}
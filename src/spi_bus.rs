@@ -0,0 +1,93 @@
+use core::cell::{Cell, RefCell};
+use embedded_hal::{
+    delay::DelayNs,
+    spi::{ErrorKind, ErrorType, Operation, SpiBus, SpiDevice},
+};
+use esp_hal::{
+    Blocking,
+    delay::Delay,
+    gpio::{Level, Output, OutputPin},
+    spi::{
+        self,
+        master::{Config, ConfigError, Spi},
+    },
+    time::Rate,
+};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub(crate) enum SpiBusError {
+    #[error("SPI transfer failed: {0:?}")]
+    Spi(spi::Error),
+    #[error("SPI clock rejected: {0:?}")]
+    Config(ConfigError),
+}
+
+impl embedded_hal::spi::Error for SpiBusError {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+// a bus whose clock follows the device currently talking on it
+pub(crate) struct SharedSpi<'a> {
+    spi: RefCell<Spi<'a, Blocking>>,
+    // what the peripheral is configured for right now, None until the first device runs
+    rate: Cell<Option<Rate>>,
+}
+
+impl<'a> SharedSpi<'a> {
+    pub(crate) fn new(spi: Spi<'a, Blocking>) -> Self {
+        Self { spi: RefCell::new(spi), rate: Cell::new(None) }
+    }
+}
+
+// like embedded-hal-bus' RefCellDevice, but each device brings its own clock: the ILI9341
+// takes 40 MHz writes while the XPT2046 needs 2 MHz to give sane readings. Reconfiguring
+// only happens when the other device used the bus last.
+pub(crate) struct ClockedDevice<'a> {
+    bus: &'a SharedSpi<'a>,
+    cs: Output<'a>,
+    rate: Rate,
+}
+
+impl<'a> ClockedDevice<'a> {
+    pub(crate) fn new(bus: &'a SharedSpi<'a>, cs_pin: impl OutputPin + 'a, rate: Rate) -> Self {
+        Self { bus, cs: Output::new(cs_pin, Level::High, Default::default()), rate }
+    }
+}
+
+impl ErrorType for ClockedDevice<'_> {
+    type Error = SpiBusError;
+}
+
+impl SpiDevice for ClockedDevice<'_> {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), SpiBusError> {
+        let bus = &mut *self.bus.spi.borrow_mut();
+        if self.bus.rate.get() != Some(self.rate) {
+            let config = Config::default()
+                .with_frequency(self.rate)
+                .with_mode(spi::Mode::_0);
+            bus.apply_config(&config).map_err(SpiBusError::Config)?;
+            self.bus.rate.set(Some(self.rate));
+        }
+
+        self.cs.set_low();
+        let result = operations.iter_mut().try_for_each(|op| match op {
+            Operation::Read(buf) => SpiBus::read(bus, buf),
+            Operation::Write(buf) => SpiBus::write(bus, buf),
+            Operation::Transfer(read, write) => SpiBus::transfer(bus, read, write),
+            Operation::TransferInPlace(buf) => SpiBus::transfer_in_place(bus, buf),
+            Operation::DelayNs(ns) => {
+                SpiBus::flush(bus)?;
+                Delay::new().delay_ns(*ns);
+                Ok(())
+            }
+        });
+        // release CS even when a transfer failed, the other device needs the bus
+        let flushed = SpiBus::flush(bus);
+        self.cs.set_high();
+
+        result.and(flushed).map_err(SpiBusError::Spi)
+    }
+}
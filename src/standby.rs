@@ -0,0 +1,116 @@
+use core::time::Duration;
+use esp_hal::{
+    delay::Delay,
+    gpio::RtcPin,
+    peripherals::GPIO36,
+    rtc_cntl::{
+        Rtc, SocResetReason, reset_reason,
+        sleep::{Ext0WakeupSource, Ext1WakeupSource, TimerWakeupSource, WakeSource, WakeupLevel},
+        wakeup_cause,
+    },
+    system::{Cpu, SleepSource},
+};
+use log::info;
+
+// survives deep sleep, zeroed only on power-on
+#[esp_hal::ram(unstable(rtc_fast, persistent))]
+static mut STANDBY_CYCLES: u32 = 0;
+// EXT0 watched the touch IRQ instead of the button on the way down
+#[esp_hal::ram(unstable(rtc_fast, persistent))]
+static mut EXT0_TOUCH: bool = false;
+
+pub(crate) fn standby_cycles() -> u32 {
+    unsafe { STANDBY_CYCLES }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum WakeTrigger {
+    PowerOn,
+    Button,
+    Touch,
+    Sensor,
+    Timer,
+    Other,
+}
+
+impl WakeTrigger {
+    pub(crate) fn current() -> Self {
+        if reset_reason(Cpu::ProCpu) != Some(SocResetReason::CoreDeepSleep) {
+            return WakeTrigger::PowerOn;
+        }
+
+        match wakeup_cause() {
+            SleepSource::Ext0 if unsafe { EXT0_TOUCH } => WakeTrigger::Touch,
+            SleepSource::Ext0 => WakeTrigger::Button,
+            SleepSource::Ext1 => WakeTrigger::Sensor,
+            SleepSource::Timer => WakeTrigger::Timer,
+            _ => WakeTrigger::Other,
+        }
+    }
+}
+
+// The FSM ULP of the original ESP32 isn't supported by esp-hal, so the monitoring is left to
+// the RTC IO controller: EXT0 watches the button (or the touch IRQ), EXT1 the sensor line, both
+// keep running while the cores are powered down. EXT1 on the ESP32 only wakes on all pins low
+// or any pin high, the active low touch IRQ can't share it with the sensor.
+pub(crate) struct Standby<'d, B: RtcPin, S: RtcPin> {
+    rtc: Rtc<'d>,
+    button: B,
+    sensor: S,
+    // the touch driver's PENIRQ/INT; EXT0 only takes the pad over while asleep
+    touch: GPIO36<'d>,
+    touch_wake: bool,
+}
+
+impl<'d, B: RtcPin, S: RtcPin> Standby<'d, B, S> {
+    pub(crate) fn new(rtc: Rtc<'d>, button: B, sensor: S, touch: GPIO36<'d>) -> Self {
+        Self { rtc, button, sensor, touch, touch_wake: false }
+    }
+
+    // a touch on the panel wakes from deep sleep instead of the button
+    pub(crate) fn set_touch_wake(&mut self, touch_wake: bool) {
+        self.touch_wake = touch_wake;
+    }
+
+    pub(crate) fn rtc(&self) -> &Rtc<'d> {
+        &self.rtc
+    }
+
+    // the cores stop and RAM is kept, the loop carries on from here; true when a touch ended it
+    // rather than the timer. Dropping the EXT0 source hands the pad back to the GPIO matrix, so
+    // the touch driver's input works again right away.
+    pub(crate) fn light_sleep(&mut self, duration: Duration) -> bool {
+        let touch = Ext0WakeupSource::new(self.touch.reborrow(), WakeupLevel::Low);
+        let timer = TimerWakeupSource::new(duration);
+        self.rtc.sleep_light(&[&touch, &timer]);
+        matches!(wakeup_cause(), SleepSource::Ext0)
+    }
+
+    // button and touch IRQ are active low (external pull-up), the sensor raises its line on a
+    // trigger
+    pub(crate) fn enter(mut self, timeout: Option<Duration>) -> ! {
+        unsafe {
+            STANDBY_CYCLES = STANDBY_CYCLES.wrapping_add(1);
+            EXT0_TOUCH = self.touch_wake;
+        }
+
+        let button = Ext0WakeupSource::new(self.button, WakeupLevel::Low);
+        let touch = Ext0WakeupSource::new(self.touch, WakeupLevel::Low);
+        let ext0: &dyn WakeSource = if self.touch_wake { &touch } else { &button };
+        let mut sensor_pins: [&mut dyn RtcPin; 1] = [&mut self.sensor];
+        let sensor = Ext1WakeupSource::new(&mut sensor_pins, WakeupLevel::High);
+
+        info!("Entering standby, timeout {:?}, touch wake {}", timeout, self.touch_wake);
+        // let the UART drain before the cores go down
+        Delay::new().delay_millis(100u32);
+
+        match timeout {
+            Some(duration) => {
+                let timer = TimerWakeupSource::new(duration);
+                let sources: [&dyn WakeSource; 3] = [ext0, &sensor, &timer];
+                self.rtc.sleep_deep(&sources)
+            }
+            None => self.rtc.sleep_deep(&[ext0, &sensor]),
+        }
+    }
+}
@@ -0,0 +1,214 @@
+// Step/dir stepper driver (A4988, DRV8825, TMC2208 in standalone mode) for the jog page. Steps
+// are timed by a timer group interrupt rather than the loop: each interval follows a trapezoid
+// ramp, accelerating from MIN_SPEED and braking early enough to stop on the target.
+use core::cell::RefCell;
+use critical_section::Mutex;
+use esp_hal::{
+    Blocking,
+    delay::Delay,
+    gpio::{Level, Output, OutputPin},
+    handler,
+    time::Duration,
+    timer::{AnyTimer, OneShotTimer, Timer},
+};
+
+// steps per second; ramps start and end here, slow enough for any motor to pull in
+const MIN_SPEED: u32 = 100;
+const MAX_SPEED: u32 = 2000;
+const JOG_SPEED: u32 = 800;
+// steps per second squared
+const ACCELERATION: u32 = 4000;
+// A4988 wants 1 us high, DRV8825 1.9 us
+const PULSE_US: u32 = 2;
+// a held jog button runs towards this, far past the end of any rig
+const JOG_DISTANCE: i32 = 1_000_000;
+
+// shared with the interrupt, the loop only takes it for a command or a look at the position
+static DRIVER: Mutex<RefCell<Option<Driver>>> = Mutex::new(RefCell::new(None));
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum StepperCommand {
+    // a jog button is held, true is forward
+    Jog(bool),
+    // the jog button was let go or stop was tapped, brakes along the ramp
+    Stop,
+    MoveBy(i32),
+    MoveTo(i32),
+    // the current position becomes 0, only at rest
+    Zero,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct StepperView {
+    pub(crate) position: i32,
+    pub(crate) target: i32,
+    // steps per second, 0 at rest
+    pub(crate) speed: u32,
+}
+
+struct Motion {
+    position: i32,
+    target: i32,
+    // 0 at rest
+    speed: u32,
+    max_speed: u32,
+    forward: bool,
+}
+
+impl Motion {
+    // steps it takes to get from the current speed down to MIN_SPEED
+    fn braking_distance(&self) -> i32 {
+        let (speed, min) = (self.speed as u64, MIN_SPEED as u64);
+        ((speed * speed).saturating_sub(min * min) / (2 * ACCELERATION as u64)) as i32
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_micros(1_000_000 / self.speed as u64)
+    }
+
+    // after a step: sets the speed for the next one, 0 when the motor stops here
+    fn advance(&mut self) {
+        self.position += if self.forward { 1 } else { -1 };
+        let remaining = self.target - self.position;
+        let ahead = if self.forward { remaining > 0 } else { remaining < 0 };
+        // the change over one interval, v += a * t
+        let delta = (ACCELERATION * (1_000_000 / self.speed) / 1_000_000).max(1);
+        if remaining == 0 || (!ahead && self.speed <= MIN_SPEED) {
+            self.speed = 0;
+        } else if !ahead
+            || remaining.abs() <= self.braking_distance()
+            || self.speed > self.max_speed
+        {
+            // a target that moved behind is overshot while braking, then approached again
+            self.speed = self.speed.saturating_sub(delta).max(MIN_SPEED);
+        } else {
+            self.speed = (self.speed + delta).min(self.max_speed);
+        }
+    }
+}
+
+struct Driver {
+    timer: OneShotTimer<'static, Blocking>,
+    step: Output<'static>,
+    dir: Output<'static>,
+    motion: Motion,
+}
+
+impl Driver {
+    // sets the direction and schedules the first step, only from rest
+    fn start(&mut self) {
+        let motion = &mut self.motion;
+        if motion.speed != 0 || motion.target == motion.position {
+            return;
+        }
+        motion.forward = motion.target > motion.position;
+        self.dir.set_level(Level::from(motion.forward));
+        motion.speed = MIN_SPEED;
+        // the first step comes a whole slow interval later, plenty of direction setup time
+        let _ = self.timer.schedule(motion.interval());
+    }
+
+    fn on_timer(&mut self) {
+        self.timer.clear_interrupt();
+        if self.motion.speed == 0 {
+            return;
+        }
+        self.step.set_high();
+        Delay::new().delay_micros(PULSE_US);
+        self.step.set_low();
+        self.motion.advance();
+        if self.motion.speed == 0 {
+            // stopped past a target that moved behind, heads back
+            self.start();
+        } else {
+            let _ = self.timer.schedule(self.motion.interval());
+        }
+    }
+}
+
+// handle for the loop, the driver itself lives in DRIVER
+pub(crate) struct Stepper;
+
+impl Stepper {
+    pub(crate) fn new(
+        timer: impl Timer + Into<AnyTimer<'static>>,
+        step: impl OutputPin + 'static,
+        dir: impl OutputPin + 'static,
+    ) -> Self {
+        let mut timer = OneShotTimer::new(timer);
+        timer.set_interrupt_handler(step_handler);
+        timer.listen();
+        let motion =
+            Motion { position: 0, target: 0, speed: 0, max_speed: MAX_SPEED, forward: true };
+        let driver = Driver {
+            timer,
+            step: Output::new(step, Level::Low, Default::default()),
+            dir: Output::new(dir, Level::Low, Default::default()),
+            motion,
+        };
+        critical_section::with(|cs| DRIVER.borrow_ref_mut(cs).replace(driver));
+        Self
+    }
+
+    pub(crate) fn apply(&mut self, command: StepperCommand) {
+        with_driver(|driver| {
+            let motion = &mut driver.motion;
+            match command {
+                StepperCommand::Jog(forward) => {
+                    motion.max_speed = JOG_SPEED;
+                    let distance = if forward { JOG_DISTANCE } else { -JOG_DISTANCE };
+                    motion.target = motion.position.saturating_add(distance);
+                }
+                StepperCommand::Stop if motion.speed == 0 => motion.target = motion.position,
+                StepperCommand::Stop => {
+                    let braking = motion.braking_distance();
+                    let braking = if motion.forward { braking } else { -braking };
+                    motion.target = motion.position.saturating_add(braking);
+                }
+                // from the target, so quick taps add up
+                StepperCommand::MoveBy(steps) => {
+                    motion.max_speed = MAX_SPEED;
+                    motion.target = motion.target.saturating_add(steps);
+                }
+                StepperCommand::MoveTo(position) => {
+                    motion.max_speed = MAX_SPEED;
+                    motion.target = position;
+                }
+                StepperCommand::Zero if motion.speed == 0 => {
+                    motion.position = 0;
+                    motion.target = 0;
+                }
+                StepperCommand::Zero => {}
+            }
+            driver.start();
+        });
+    }
+
+    pub(crate) fn is_moving(&self) -> bool {
+        self.view().speed != 0
+    }
+
+    pub(crate) fn view(&self) -> StepperView {
+        with_driver(|driver| StepperView {
+            position: driver.motion.position,
+            target: driver.motion.target,
+            speed: driver.motion.speed,
+        })
+    }
+}
+
+fn with_driver<R>(f: impl FnOnce(&mut Driver) -> R) -> R {
+    critical_section::with(|cs| {
+        let mut driver = DRIVER.borrow_ref_mut(cs);
+        f(driver.as_mut().expect("Stepper driver not initialized"))
+    })
+}
+
+#[handler]
+fn step_handler() {
+    critical_section::with(|cs| {
+        if let Some(driver) = DRIVER.borrow_ref_mut(cs).as_mut() {
+            driver.on_timer();
+        }
+    });
+}
@@ -0,0 +1,110 @@
+use alloc::{format, string::String, vec::Vec};
+use esp_hal::timer::Timer;
+
+use crate::{
+    clock,
+    sdcard::{SdCardError, SdStorage},
+};
+
+pub(crate) const MAX_LAPS: usize = 99;
+// one line per lap: session (unix time of the start), lap number, lap and total in ms
+const LAPS_FILE: &str = "LAPS.CSV";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum StopwatchCommand {
+    Toggle,
+    Lap,
+    Reset,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Lap {
+    pub(crate) number: usize,
+    pub(crate) split_us: u64,
+    pub(crate) total_us: u64,
+}
+
+// Counts on a timer group timer of its own, so times come from the hardware counter and not
+// from how often the loop or the display gets around to looking. Pausing stops the counter.
+pub(crate) struct Stopwatch<T: Timer> {
+    timer: T,
+    running: bool,
+    laps: Vec<u64>,
+    session: u32,
+}
+
+impl<T: Timer> Stopwatch<T> {
+    pub(crate) fn new(timer: T) -> Self {
+        timer.stop();
+        timer.reset();
+        Self { timer, running: false, laps: Vec::new(), session: 0 }
+    }
+
+    pub(crate) fn is_running(&self) -> bool {
+        self.running
+    }
+
+    pub(crate) fn elapsed_us(&self) -> u64 {
+        self.timer.now().duration_since_epoch().as_micros()
+    }
+
+    pub(crate) fn toggle(&mut self) {
+        if self.running {
+            self.timer.stop();
+        } else {
+            if self.elapsed_us() == 0 {
+                self.session = clock::unix_now().unwrap_or(0);
+            }
+            self.timer.start();
+        }
+        self.running = !self.running;
+    }
+
+    // None while stopped or once the list is full
+    pub(crate) fn lap(&mut self) -> Option<Lap> {
+        if !self.running || self.laps.len() >= MAX_LAPS {
+            return None;
+        }
+        let total_us = self.elapsed_us();
+        let split_us = total_us - self.laps.last().copied().unwrap_or(0);
+        self.laps.push(total_us);
+        Some(Lap { number: self.laps.len(), split_us, total_us })
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.timer.stop();
+        self.timer.reset();
+        self.running = false;
+        self.laps.clear();
+    }
+
+    pub(crate) fn laps(&self) -> impl Iterator<Item = Lap> + '_ {
+        self.laps.iter().enumerate().map(|(i, &total_us)| Lap {
+            number: i + 1,
+            split_us: total_us - if i == 0 { 0 } else { self.laps[i - 1] },
+            total_us,
+        })
+    }
+
+    pub(crate) fn save_lap(&self, sd: &SdStorage<'_>, lap: &Lap) -> Result<(), SdCardError> {
+        let line = format!(
+            "{},{},{},{}\n",
+            self.session,
+            lap.number,
+            lap.split_us / 1000,
+            lap.total_us / 1000
+        );
+        sd.append(LAPS_FILE, line.as_bytes())
+    }
+}
+
+// "01:23.45", hours in front once there are any
+pub(crate) fn format_us(us: u64) -> String {
+    let cs = us / 10_000;
+    let (h, m, s, cs) = (cs / 360_000, cs / 6000 % 60, cs / 100 % 60, cs % 100);
+    if h > 0 {
+        format!("{}:{:02}:{:02}.{:02}", h, m, s, cs)
+    } else {
+        format!("{:02}:{:02}.{:02}", m, s, cs)
+    }
+}
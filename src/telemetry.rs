@@ -0,0 +1,144 @@
+use core::{
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use esp_alloc::{HEAP, MemoryCapability};
+use esp_hal::time::{Duration, Instant};
+
+// written over the unused stack at boot, whatever no longer holds it has been used
+const STACK_PAINT: u32 = 0xA5A5_A5A5;
+// keeps clear of the stack guard word esp-hal places just above the stack end
+const STACK_GUARD_MARGIN: usize = 256;
+// nothing below the current frame gets painted closer than this, interrupts may be using it
+const STACK_SP_MARGIN: usize = 512;
+
+unsafe extern "C" {
+    static _stack_start_cpu0: u8;
+    static _stack_end_cpu0: u8;
+}
+
+static PAINTED_FROM: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct MemoryStats {
+    pub(crate) heap_size: usize,
+    pub(crate) heap_used: usize,
+    pub(crate) heap_free: usize,
+    pub(crate) internal_free: usize,
+    pub(crate) largest_free_block: usize,
+    pub(crate) stack_size: usize,
+    // high water mark, 0 when the stack was never painted
+    pub(crate) stack_used: usize,
+}
+
+impl fmt::Display for MemoryStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "heap {}/{} used, {} free ({} internal, largest block {}), stack {}/{}",
+            self.heap_used,
+            self.heap_size,
+            self.heap_free,
+            self.internal_free,
+            self.largest_free_block,
+            self.stack_used,
+            self.stack_size,
+        )
+    }
+}
+
+// fills the stack between its end and the caller's frame, call once early in main
+pub(crate) fn paint_stack() {
+    let (_, bottom) = stack_bounds();
+    let from = bottom + STACK_GUARD_MARGIN;
+    let to = current_sp().saturating_sub(STACK_SP_MARGIN) & !3;
+    if to <= from {
+        return;
+    }
+    let mut addr = from;
+    while addr < to {
+        // below the live frame and above the guard, nothing else owns these words
+        unsafe { core::ptr::write_volatile(addr as *mut u32, STACK_PAINT) };
+        addr += 4;
+    }
+    PAINTED_FROM.store(from, Ordering::Relaxed);
+}
+
+pub(crate) fn sample() -> MemoryStats {
+    let (top, bottom) = stack_bounds();
+    MemoryStats {
+        heap_size: HEAP.used() + HEAP.free(),
+        heap_used: HEAP.used(),
+        heap_free: HEAP.free(),
+        internal_free: HEAP.free_caps(MemoryCapability::Internal.into()),
+        largest_free_block: largest_free_block(),
+        stack_size: top - bottom,
+        stack_used: stack_high_water(top),
+    }
+}
+
+// the most any one heap region has free, from the allocator's own region statistics; an upper
+// bound, fragmentation inside a region only shows as an allocation failing below it
+fn largest_free_block() -> usize {
+    HEAP.stats().region_stats.iter().flatten().map(|region| region.free).max().unwrap_or(0)
+}
+
+fn stack_high_water(top: usize) -> usize {
+    let from = PAINTED_FROM.load(Ordering::Relaxed);
+    if from == 0 {
+        return 0;
+    }
+    let mut addr = from;
+    while addr < top && unsafe { core::ptr::read_volatile(addr as *const u32) } == STACK_PAINT {
+        addr += 4;
+    }
+    top - addr
+}
+
+fn stack_bounds() -> (usize, usize) {
+    unsafe {
+        (
+            core::ptr::addr_of!(_stack_start_cpu0) as usize,
+            core::ptr::addr_of!(_stack_end_cpu0) as usize,
+        )
+    }
+}
+
+fn current_sp() -> usize {
+    let sp: usize;
+    // a1 is the stack pointer in the windowed ABI
+    unsafe { core::arch::asm!("mov {0}, a1", out(reg) sp) };
+    sp
+}
+
+pub(crate) enum TelemetryEvent {
+    Sample(MemoryStats),
+    LowHeap(MemoryStats),
+}
+
+// samples on an interval and reports a low heap once per dip below the threshold
+pub(crate) struct Telemetry {
+    interval: Duration,
+    low_heap_threshold: usize,
+    last_sample: Option<Instant>,
+    low: bool,
+}
+
+impl Telemetry {
+    pub(crate) fn new(interval: Duration, low_heap_threshold: usize) -> Self {
+        Self { interval, low_heap_threshold, last_sample: None, low: false }
+    }
+
+    pub(crate) fn poll(&mut self) -> Option<TelemetryEvent> {
+        if self.last_sample.is_some_and(|t| t.elapsed() < self.interval) {
+            return None;
+        }
+        self.last_sample = Some(Instant::now());
+
+        let stats = sample();
+        let low = stats.heap_free < self.low_heap_threshold;
+        let newly_low = low && !self.low;
+        self.low = low;
+        Some(if newly_low { TelemetryEvent::LowHeap(stats) } else { TelemetryEvent::Sample(stats) })
+    }
+}
@@ -0,0 +1,185 @@
+use alloc::{format, string::String, vec::Vec};
+use thiserror::Error;
+
+pub(crate) const MAX_TIMERS: usize = 6;
+pub(crate) const POMODORO_MINUTES: u32 = 25;
+pub(crate) const BREAK_MINUTES: u32 = 5;
+
+#[derive(Error, Debug)]
+pub(crate) enum TimerError {
+    #[error("At most {0} timers")]
+    Full(usize),
+    #[error("Clock not set yet")]
+    NoClock,
+    #[error("No timer {0}")]
+    Missing(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum CountdownState {
+    // unix time, so a running timer keeps counting through a reboot
+    Running { ends_at: u32 },
+    Paused { remaining: u32 },
+    Expired,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Countdown {
+    pub(crate) name: heapless::String<24>,
+    pub(crate) duration_secs: u32,
+    pub(crate) state: CountdownState,
+}
+
+impl Countdown {
+    pub(crate) fn remaining(&self, now: u32) -> u32 {
+        match self.state {
+            CountdownState::Running { ends_at } => ends_at.saturating_sub(now),
+            CountdownState::Paused { remaining } => remaining,
+            CountdownState::Expired => 0,
+        }
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let (tag, value) = match self.state {
+            CountdownState::Running { ends_at } => (0, ends_at),
+            CountdownState::Paused { remaining } => (1, remaining),
+            CountdownState::Expired => (2, 0),
+        };
+        let mut out = Vec::new();
+        out.extend_from_slice(&self.duration_secs.to_le_bytes());
+        out.push(tag);
+        out.extend_from_slice(&value.to_le_bytes());
+        out.extend_from_slice(self.name.as_bytes());
+        out
+    }
+
+    pub(crate) fn decode(value: &[u8]) -> Option<Self> {
+        let [d0, d1, d2, d3, tag, v0, v1, v2, v3, name @ ..] = value else {
+            return None;
+        };
+        let value = u32::from_le_bytes([*v0, *v1, *v2, *v3]);
+        let state = match tag {
+            0 => CountdownState::Running { ends_at: value },
+            1 => CountdownState::Paused { remaining: value },
+            2 => CountdownState::Expired,
+            _ => return None,
+        };
+
+        Some(Self {
+            name: heapless::String::try_from(core::str::from_utf8(name).ok()?).ok()?,
+            duration_secs: u32::from_le_bytes([*d0, *d1, *d2, *d3]),
+            state,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum TimerCommand {
+    // name and seconds, starts right away
+    Add(heapless::String<24>, u32),
+    // start, pause or resume; an expired timer starts over
+    Toggle(usize),
+    Reset(usize),
+    Remove(usize),
+}
+
+// what the timers page shows of one countdown
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct CountdownView {
+    pub(crate) name: heapless::String<24>,
+    pub(crate) remaining: String,
+    pub(crate) running: bool,
+    pub(crate) expired: bool,
+}
+
+// Named countdowns, kept in the settings record; every change is persisted by the caller.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Countdowns {
+    list: Vec<Countdown>,
+}
+
+impl Countdowns {
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Countdown> {
+        self.list.iter()
+    }
+
+    pub(crate) fn push_decoded(&mut self, countdown: Countdown) {
+        if self.list.len() < MAX_TIMERS {
+            self.list.push(countdown);
+        }
+    }
+
+    pub(crate) fn apply(
+        &mut self,
+        command: TimerCommand,
+        now: Option<u32>,
+    ) -> Result<(), TimerError> {
+        match command {
+            TimerCommand::Add(name, secs) => {
+                if self.list.len() >= MAX_TIMERS {
+                    return Err(TimerError::Full(MAX_TIMERS));
+                }
+                let now = now.ok_or(TimerError::NoClock)?;
+                let state = CountdownState::Running { ends_at: now + secs };
+                self.list.push(Countdown { name, duration_secs: secs, state });
+            }
+            TimerCommand::Toggle(i) => {
+                let countdown = self.list.get_mut(i).ok_or(TimerError::Missing(i))?;
+                countdown.state = match countdown.state {
+                    CountdownState::Running { ends_at } => CountdownState::Paused {
+                        remaining: ends_at.saturating_sub(now.ok_or(TimerError::NoClock)?),
+                    },
+                    CountdownState::Paused { remaining } => CountdownState::Running {
+                        ends_at: now.ok_or(TimerError::NoClock)? + remaining,
+                    },
+                    CountdownState::Expired => CountdownState::Running {
+                        ends_at: now.ok_or(TimerError::NoClock)? + countdown.duration_secs,
+                    },
+                };
+            }
+            TimerCommand::Reset(i) => {
+                let countdown = self.list.get_mut(i).ok_or(TimerError::Missing(i))?;
+                countdown.state = CountdownState::Paused { remaining: countdown.duration_secs };
+            }
+            TimerCommand::Remove(i) => {
+                if i >= self.list.len() {
+                    return Err(TimerError::Missing(i));
+                }
+                self.list.remove(i);
+            }
+        }
+        Ok(())
+    }
+
+    // names of the timers that ran out since the last call, those are marked expired
+    pub(crate) fn expire(&mut self, now: u32) -> Vec<heapless::String<24>> {
+        let mut expired = Vec::new();
+        for countdown in &mut self.list {
+            if let CountdownState::Running { ends_at } = countdown.state {
+                if ends_at <= now {
+                    countdown.state = CountdownState::Expired;
+                    expired.push(countdown.name.clone());
+                }
+            }
+        }
+        expired
+    }
+
+    pub(crate) fn view(&self, now: u32) -> Vec<CountdownView> {
+        self.list
+            .iter()
+            .map(|countdown| CountdownView {
+                name: countdown.name.clone(),
+                remaining: format_secs(countdown.remaining(now)),
+                running: matches!(countdown.state, CountdownState::Running { .. }),
+                expired: countdown.state == CountdownState::Expired,
+            })
+            .collect()
+    }
+}
+
+// "4:59", "1:02:03" from an hour up
+fn format_secs(secs: u32) -> String {
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if h > 0 { format!("{}:{:02}:{:02}", h, m, s) } else { format!("{}:{:02}", m, s) }
+}
@@ -0,0 +1,75 @@
+use alloc::vec::Vec;
+use esp_hal::time::Instant;
+
+use crate::{
+    display_screen::{PANEL_HEIGHT, PANEL_WIDTH},
+    touch_input::TouchInputResponse,
+};
+
+const CELL_PX: i32 = 20;
+// 16x12 in landscape, 12x16 in portrait
+const CELLS: usize = (PANEL_WIDTH * PANEL_HEIGHT / (CELL_PX * CELL_PX) as u32) as usize;
+const PUBLISH_INTERVAL_MS: u64 = 500;
+
+// Counts calibrated touch positions per 20x20 px cell. A worn resistive panel shows up as
+// cells that never light up while the area around them does, and a bad calibration as
+// counts piling up on an edge or landing off screen.
+pub(crate) struct TouchHeatmap {
+    cells: [u16; CELLS],
+    // the window's, in pixels
+    width: i32,
+    height: i32,
+    off_screen: u16,
+    dirty: bool,
+    published: Option<Instant>,
+}
+
+impl TouchHeatmap {
+    pub(crate) fn new() -> Self {
+        Self {
+            cells: [0; CELLS],
+            width: PANEL_WIDTH as i32,
+            height: PANEL_HEIGHT as i32,
+            off_screen: 0,
+            dirty: false,
+            published: None,
+        }
+    }
+
+    // after a rotation, the counts so far no longer line up with the cells
+    pub(crate) fn resize(&mut self, width: u32, height: u32) {
+        *self = Self { width: width as i32, height: height as i32, ..Self::new() };
+        self.dirty = true;
+    }
+
+    pub(crate) fn record(&mut self, input: &TouchInputResponse) {
+        let (x, y) = match *input {
+            TouchInputResponse::Pressed { x, y } | TouchInputResponse::Moved { x, y } => (x, y),
+            TouchInputResponse::Released { .. } | TouchInputResponse::NoInput => return,
+        };
+        if !(0..self.width).contains(&x) || !(0..self.height).contains(&y) {
+            self.off_screen = self.off_screen.saturating_add(1);
+        } else {
+            let cols = (self.width / CELL_PX) as usize;
+            let cell = &mut self.cells[(y / CELL_PX) as usize * cols + (x / CELL_PX) as usize];
+            *cell = cell.saturating_add(1);
+        }
+        self.dirty = true;
+    }
+
+    // intensities scaled to the busiest cell (0..=255, row major) and the off screen count,
+    // at most twice a second
+    pub(crate) fn publish(&mut self) -> Option<(Vec<u8>, u16)> {
+        if !self.dirty
+            || self.published.is_some_and(|t| t.elapsed().as_millis() < PUBLISH_INTERVAL_MS)
+        {
+            return None;
+        }
+        self.dirty = false;
+        self.published = Some(Instant::now());
+
+        let max = self.cells.iter().copied().max().unwrap_or(0).max(1) as u32;
+        let cells = self.cells.iter().map(|&count| (count as u32 * 255 / max) as u8).collect();
+        Some((cells, self.off_screen))
+    }
+}
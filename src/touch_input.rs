@@ -1,25 +1,106 @@
-use alloc::string::{String, ToString};
-use core::cell::RefCell;
-use embedded_hal_bus::spi::{NoDelay, RefCellDevice};
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 use esp_hal::{
-    Blocking,
-    delay::Delay,
-    gpio::{Input, InputPin, Level, Output, OutputPin},
-    spi::master::Spi,
+    gpio::{Event, Input, InputPin, Io},
+    handler,
+    peripherals::GPIO,
 };
+#[cfg(not(feature = "ft6236"))]
+use esp_hal::{delay::Delay, gpio::OutputPin, time::Rate};
 use thiserror::Error;
+#[cfg(not(feature = "ft6236"))]
 use xpt2046::Xpt2046;
 
+#[cfg(not(feature = "ft6236"))]
+use crate::{
+    display_screen::{PANEL_HEIGHT, PANEL_WIDTH, ScreenOrientation},
+    spi_bus::{ClockedDevice, SharedSpi},
+};
+#[cfg(feature = "ft6236")]
+use crate::display_screen::PANEL_HEIGHT;
+
+// the XPT2046 is rated for 2.5 MHz, faster clocks give noisy conversions
+#[cfg(not(feature = "ft6236"))]
+const SPI_RATE: Rate = Rate::from_mhz(2);
+// presses and releases between two dispatches, moves are merged so this is several taps
+const QUEUE_LEN: usize = 16;
+
 #[derive(Error, Debug)]
 pub(crate) enum TouchInputError {
+    #[cfg(not(feature = "ft6236"))]
     #[error("Failed to initialize Xpt2046 driver")]
     Xpt2046Init,
-    #[error("Failed to initialize SPI device for Xpt2046: {0}")]
-    SpiInit(String),
+    #[cfg(feature = "ft6236")]
+    #[error("Failed to initialize Ft6236 driver")]
+    Ft6236Init,
     #[error("Failed to acquire input data")]
     AcquireInputData,
 }
 
+// maps raw controller coordinates to screen pixels: x = raw_x * x_mul / 1000 + x_offset
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct TouchCalibration {
+    pub(crate) x_mul: i16,
+    pub(crate) x_offset: i16,
+    pub(crate) y_mul: i16,
+    pub(crate) y_offset: i16,
+}
+
+#[cfg(not(feature = "ft6236"))]
+impl Default for TouchCalibration {
+    fn default() -> Self {
+        //awkward adjustments but match my screen orientation
+        //taken on a 320x240 module, scaled for other panel sizes
+        Self {
+            x_mul: (-2000 * PANEL_WIDTH as i32 / 320) as i16,
+            x_offset: PANEL_WIDTH as i16,
+            y_mul: (2000 * PANEL_HEIGHT as i32 / 240) as i16,
+            y_offset: 0,
+        }
+    }
+}
+
+// the FT6236 reports pixels already, only the portrait axes need turning into landscape
+#[cfg(feature = "ft6236")]
+impl Default for TouchCalibration {
+    fn default() -> Self {
+        Self { x_mul: 1000, x_offset: 0, y_mul: -1000, y_offset: PANEL_HEIGHT as i16 - 1 }
+    }
+}
+
+impl TouchCalibration {
+    pub(crate) fn apply(&self, raw_x: i32, raw_y: i32) -> (i32, i32) {
+        (
+            raw_x * self.x_mul as i32 / 1000 + self.x_offset as i32,
+            raw_y * self.y_mul as i32 / 1000 + self.y_offset as i32,
+        )
+    }
+}
+
+// set from the PENIRQ falling edge, the controller is only read while this or a touch is active
+static TOUCH_IRQ: AtomicBool = AtomicBool::new(false);
+static TOUCH_IRQ_PIN: AtomicU8 = AtomicU8::new(u8::MAX);
+
+pub(crate) fn irq_pending() -> bool {
+    TOUCH_IRQ.load(Ordering::Relaxed)
+}
+
+// true once per edge since the last call
+pub(crate) fn take_irq() -> bool {
+    TOUCH_IRQ.swap(false, Ordering::Relaxed)
+}
+
+// the controller's active low interrupt line, both controllers hold it down while touched
+pub(crate) fn listen_irq<'a>(irq_pin: impl InputPin + 'a, io: &mut Io<'_>) -> Input<'a> {
+    TOUCH_IRQ_PIN.store(irq_pin.number(), Ordering::Relaxed);
+    let mut irq = Input::new(irq_pin, Default::default());
+    io.set_interrupt_handler(touch_irq_handler);
+    irq.listen(Event::FallingEdge);
+    // a press that is already held at boot never produces an edge
+    TOUCH_IRQ.store(irq.is_low(), Ordering::Relaxed);
+    irq
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub(crate) enum TouchInputResponse {
     Moved { x: i32, y: i32 },
     Pressed { x: i32, y: i32 },
@@ -31,23 +112,65 @@ pub(crate) trait TouchInputProvider {
     fn get_input(&mut self) -> Result<TouchInputResponse, TouchInputError>;
 }
 
+// touch read in between dispatches, the loop samples during a long frame so a tap that starts
+// and ends while slint renders still reaches the UI; drained before the next draw
+pub(crate) struct InputQueue {
+    events: heapless::Deque<TouchInputResponse, QUEUE_LEN>,
+}
+
+impl InputQueue {
+    pub(crate) fn new() -> Self {
+        Self { events: heapless::Deque::new() }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub(crate) fn sample(&mut self, touch: &mut impl TouchInputProvider) {
+        match touch.get_input() {
+            Ok(TouchInputResponse::NoInput) | Err(_) => {}
+            Ok(event) => self.push(event),
+        }
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<TouchInputResponse> {
+        self.events.pop_front()
+    }
+
+    fn push(&mut self, event: TouchInputResponse) {
+        // only the latest position of a drag matters
+        if let (TouchInputResponse::Moved { .. }, Some(last @ TouchInputResponse::Moved { .. })) =
+            (event, self.events.back_mut())
+        {
+            *last = event;
+            return;
+        }
+        // with moves merged a full queue takes more taps than a frame has samples
+        let _ = self.events.push_back(event);
+    }
+}
+
+#[cfg(not(feature = "ft6236"))]
 pub(crate) struct Xpt2046TouchInput<'a> {
-    driver: Xpt2046<RefCellDevice<'a, Spi<'a, Blocking>, Output<'a>, NoDelay>, Input<'a>>,
+    driver: Xpt2046<ClockedDevice<'a>, Input<'a>>,
     last_pos: Option<(i32, i32)>,
-    screen_width: i32,
+    calibration: TouchCalibration,
+    orientation: ScreenOrientation,
 }
 
+#[cfg(not(feature = "ft6236"))]
 impl<'a> Xpt2046TouchInput<'a> {
     pub(crate) fn create(
-        spi: &'a RefCell<Spi<'a, Blocking>>,
+        spi: &'a SharedSpi<'a>,
         touch_cs_pin: impl OutputPin + 'a,
         irq_pin: impl InputPin + 'a,
-        screen_width: i32,
+        io: &mut Io<'_>,
+        calibration: TouchCalibration,
+        orientation: ScreenOrientation,
     ) -> Result<Self, TouchInputError> {
-        let touch_irq_pin = Input::new(irq_pin, Default::default());
-        let touch_cs = Output::new(touch_cs_pin, Level::High, Default::default());
-        let touch_spi_dev = RefCellDevice::new_no_delay(spi, touch_cs)
-            .map_err(|e| TouchInputError::SpiInit(e.to_string()))?;
+        let touch_irq_pin = listen_irq(irq_pin, io);
+        let touch_spi_dev = ClockedDevice::new(spi, touch_cs_pin, SPI_RATE);
         let xpt = Xpt2046::new(
             touch_spi_dev,
             touch_irq_pin,
@@ -56,10 +179,21 @@ impl<'a> Xpt2046TouchInput<'a> {
         Ok(Self {
             driver: xpt,
             last_pos: None,
-            screen_width,
+            calibration,
+            orientation,
         })
     }
 
+    // a finger is on the panel, the controller gets polled every loop until it lifts
+    pub(crate) fn is_active(&self) -> bool {
+        self.last_pos.is_some()
+    }
+
+    // follows the display, the calibration itself stays in landscape panel pixels
+    pub(crate) fn set_orientation(&mut self, orientation: ScreenOrientation) {
+        self.orientation = orientation;
+    }
+
     pub(crate) fn init(&mut self) -> Result<(), TouchInputError> {
         self.driver
             .init(&mut Delay::new())
@@ -67,16 +201,22 @@ impl<'a> Xpt2046TouchInput<'a> {
     }
 }
 
+#[cfg(not(feature = "ft6236"))]
 impl<'a> TouchInputProvider for Xpt2046TouchInput<'a> {
     fn get_input(&mut self) -> Result<TouchInputResponse, TouchInputError> {
+        // idle panel: leave the shared bus to the display
+        if self.last_pos.is_none() && !take_irq() {
+            return Ok(TouchInputResponse::NoInput);
+        }
+
         self.driver
             .run()
             .map_err(|_| TouchInputError::AcquireInputData)?;
 
         if self.driver.is_touched() {
             let p = self.driver.get_touch_point();
-            let x = self.screen_width - 2 * p.x; //awkward adjustments but match my screen orientation
-            let y = 2 * p.y;
+            let (x, y) = self.calibration.apply(p.x, p.y);
+            let (x, y) = self.orientation.map_touch(x, y);
 
             match self.last_pos.replace((x, y)) {
                 Some(prev) if (prev.0 != x && prev.1 != y) => {
@@ -95,3 +235,30 @@ impl<'a> TouchInputProvider for Xpt2046TouchInput<'a> {
         }
     }
 }
+
+// PENIRQ can only be cleared through the status register, the pin itself belongs to the driver
+#[handler]
+fn touch_irq_handler() {
+    let pin = TOUCH_IRQ_PIN.load(Ordering::Relaxed);
+    if pin == u8::MAX {
+        return;
+    }
+
+    let gpio = GPIO::regs();
+    let mask = 1u32 << (pin % 32);
+    let pending = if pin < 32 {
+        gpio.status().read().bits() & mask != 0
+    } else {
+        gpio.status1().read().bits() & mask != 0
+    };
+    if !pending {
+        return;
+    }
+
+    if pin < 32 {
+        gpio.status_w1tc().write(|w| unsafe { w.bits(mask) });
+    } else {
+        gpio.status1_w1tc().write(|w| unsafe { w.bits(mask) });
+    }
+    TOUCH_IRQ.store(true, Ordering::Relaxed);
+}
@@ -0,0 +1,125 @@
+// Datagram sockets on the network core, the UDP side of what socket_pool is for TCP. A
+// `Datagram` owns its socket and the buffers smoltcp keeps for it, sized by the caller, and
+// drops them in that order; `send_to` and `recv_from` never wait. sntp and mDNS sit on it, so
+// can anything else that needs a port: a telemetry broadcaster would bind 0 for an ephemeral
+// port and `send_to` 255.255.255.255. The stack's socket set has room for SLOTS at once.
+use alloc::{boxed::Box, vec};
+use blocking_network_stack::{Stack, UdpSocket};
+use core::sync::atomic::{AtomicU16, Ordering};
+use esp_radio::wifi::WifiDevice;
+use smoltcp::{socket::udp::PacketMetadata, wire::IpAddress};
+use thiserror::Error;
+
+// mDNS for good, sntp while it asks, and one spare
+pub(crate) const SLOTS: usize = 3;
+const EPHEMERAL_FIRST: u16 = 49152;
+
+static NEXT_EPHEMERAL: AtomicU16 = AtomicU16::new(EPHEMERAL_FIRST);
+
+#[derive(Error, Debug)]
+pub(crate) enum UdpError {
+    #[error("Binding port {0} failed")]
+    Bind(u16),
+    // no route yet, or the tx buffer is full
+    #[error("Send to {0}:{1} failed")]
+    Send(IpAddress, u16),
+    #[error("Joining {0} failed")]
+    Join(IpAddress),
+}
+
+// the socket's buffers, held as raw pointers so handing them to the socket doesn't borrow
+// the struct, and freed when it is dropped
+struct UdpBuffers {
+    rx_meta: *mut [PacketMetadata],
+    rx: *mut [u8],
+    tx_meta: *mut [PacketMetadata],
+    tx: *mut [u8],
+}
+
+impl UdpBuffers {
+    fn new(packets: usize, bytes: usize) -> Self {
+        Self {
+            rx_meta: Box::into_raw(vec![PacketMetadata::EMPTY; packets].into_boxed_slice()),
+            rx: Box::into_raw(vec![0u8; bytes].into_boxed_slice()),
+            tx_meta: Box::into_raw(vec![PacketMetadata::EMPTY; packets].into_boxed_slice()),
+            tx: Box::into_raw(vec![0u8; bytes].into_boxed_slice()),
+        }
+    }
+}
+
+impl Drop for UdpBuffers {
+    fn drop(&mut self) {
+        // SAFETY: the pointers come from `Box::into_raw` in `new`, and the socket that was
+        // given them is dropped first (see `bind`), which takes it out of the socket set
+        unsafe {
+            drop(Box::from_raw(self.rx_meta));
+            drop(Box::from_raw(self.rx));
+            drop(Box::from_raw(self.tx_meta));
+            drop(Box::from_raw(self.tx));
+        }
+    }
+}
+
+pub(crate) struct Datagram<'s, 'a> {
+    socket: UdpSocket<'s, 'a, WifiDevice<'a>>,
+    // after the socket, fields are dropped in order
+    _buffers: UdpBuffers,
+}
+
+impl<'s, 'a> Datagram<'s, 'a> {
+    // a socket on `stack` bound to `port`, 0 takes the next ephemeral one; room for `packets`
+    // datagrams and `bytes` of payload each way
+    pub(crate) fn bind(
+        stack: &'s Stack<'a, WifiDevice<'a>>,
+        port: u16,
+        packets: usize,
+        bytes: usize,
+    ) -> Result<Self, UdpError> {
+        let buffers = UdpBuffers::new(packets, bytes);
+        // SAFETY: the allocations stay put until `buffers` is dropped and nothing else touches
+        // them. The socket gets the only references and is dropped before `buffers`, here by
+        // declaration order and in `Datagram` by field order; blocking_network_stack's
+        // `UdpSocket` removes itself from the stack's socket set when dropped, so smoltcp
+        // doesn't hold on to them past that
+        let (rx_meta, rx, tx_meta, tx) = unsafe {
+            (&mut *buffers.rx_meta, &mut *buffers.rx, &mut *buffers.tx_meta, &mut *buffers.tx)
+        };
+        let mut socket = stack.get_udp_socket(rx_meta, rx, tx_meta, tx);
+        let port = match port {
+            0 => ephemeral_port(),
+            port => port,
+        };
+        socket.bind(port).map_err(|_| UdpError::Bind(port))?;
+        Ok(Self { socket, _buffers: buffers })
+    }
+
+    // to a multicast group as well as to this address
+    pub(crate) fn join(&mut self, group: IpAddress) -> Result<(), UdpError> {
+        self.socket.join_multicast_group(group).map_err(|_| UdpError::Join(group))?;
+        Ok(())
+    }
+
+    pub(crate) fn send_to(
+        &mut self,
+        to: IpAddress,
+        port: u16,
+        data: &[u8],
+    ) -> Result<(), UdpError> {
+        self.socket.send(to, port, data).map_err(|_| UdpError::Send(to, port))
+    }
+
+    // the next datagram waiting as (length, sender, sender's port), None when there is none;
+    // one longer than `buf` is cut short
+    pub(crate) fn recv_from(&mut self, buf: &mut [u8]) -> Option<(usize, IpAddress, u16)> {
+        self.socket.work();
+        self.socket.receive(buf).ok()
+    }
+}
+
+fn ephemeral_port() -> u16 {
+    let port = NEXT_EPHEMERAL.fetch_add(1, Ordering::Relaxed);
+    if port == u16::MAX {
+        NEXT_EPHEMERAL.store(EPHEMERAL_FIRST, Ordering::Relaxed);
+    }
+    port
+}
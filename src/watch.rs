@@ -0,0 +1,81 @@
+use alloc::rc::Rc;
+use core::cell::RefCell;
+use core::time::Duration;
+use slint::{ComponentHandle, Timer, TimerMode};
+
+struct Slot<T> {
+    value: T,
+    version: u32,
+}
+
+// latest-value channel, the version only moves when the value actually changes
+pub(crate) struct Watch<T> {
+    slot: Rc<RefCell<Slot<T>>>,
+}
+
+impl<T> Clone for Watch<T> {
+    fn clone(&self) -> Self {
+        Self { slot: self.slot.clone() }
+    }
+}
+
+impl<T: PartialEq> Watch<T> {
+    pub(crate) fn new(initial: T) -> Self {
+        Self {
+            slot: Rc::new(RefCell::new(Slot { value: initial, version: 0 })),
+        }
+    }
+
+    pub(crate) fn send(&self, value: T) {
+        let mut slot = self.slot.borrow_mut();
+        if slot.value != value {
+            slot.value = value;
+            slot.version = slot.version.wrapping_add(1);
+        }
+    }
+
+    pub(crate) fn subscribe(&self) -> WatchReceiver<T> {
+        WatchReceiver {
+            slot: self.slot.clone(),
+            // a fresh receiver always sees the current value once
+            seen: self.slot.borrow().version.wrapping_sub(1),
+        }
+    }
+}
+
+pub(crate) struct WatchReceiver<T> {
+    slot: Rc<RefCell<Slot<T>>>,
+    seen: u32,
+}
+
+impl<T: Clone> WatchReceiver<T> {
+    pub(crate) fn changed(&mut self) -> Option<T> {
+        let slot = self.slot.borrow();
+        if slot.version == self.seen {
+            return None;
+        }
+        self.seen = slot.version;
+        Some(slot.value.clone())
+    }
+}
+
+// pushes new values into the UI only when they changed, avoiding redundant property sets
+pub(crate) fn bind<T, C>(
+    mut rx: WatchReceiver<T>,
+    ui: slint::Weak<C>,
+    apply: impl Fn(&C, T) + 'static,
+) -> Timer
+where
+    T: Clone + 'static,
+    C: ComponentHandle + 'static,
+{
+    let timer = Timer::default();
+    timer.start(TimerMode::Repeated, Duration::from_millis(100), move || {
+        if let Some(value) = rx.changed() {
+            if let Some(ui) = ui.upgrade() {
+                apply(&ui, value);
+            }
+        }
+    });
+    timer
+}
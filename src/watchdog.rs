@@ -0,0 +1,130 @@
+use core::fmt;
+use esp_hal::{
+    peripherals::TIMG1,
+    rtc_cntl::{SocResetReason, reset_reason},
+    system::Cpu,
+    time::Duration,
+    timer::timg::{MwdtStage, MwdtStageAction, Wdt},
+};
+
+use crate::network;
+
+// RTC fast memory survives the reset, only the magic tells a record from power-on noise
+const MAGIC: u32 = 0x00DD_0600;
+
+#[esp_hal::ram(unstable(rtc_fast, persistent))]
+static mut HEALTH: [u32; 2] = [0; 2];
+
+// what the loop is doing; each phase gets its own budget before the chip is reset
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Phase {
+    // everything between the named phases: settings, SD writes, timers
+    Loop = 0,
+    Touch = 1,
+    // the network core's loop, which feeds nothing and is checked through its heartbeat
+    Network = 2,
+    Render = 3,
+}
+
+impl Phase {
+    pub(crate) fn budget(&self) -> Duration {
+        match self {
+            Phase::Loop => Duration::from_secs(10),
+            Phase::Touch => Duration::from_secs(2),
+            // a ping or the console's `get` hold the loop for 10 s
+            Phase::Network => Duration::from_secs(30),
+            Phase::Render => Duration::from_secs(3),
+        }
+    }
+
+    fn from_u32(value: u32) -> Option<Self> {
+        Some(match value {
+            0 => Phase::Loop,
+            1 => Phase::Touch,
+            2 => Phase::Network,
+            3 => Phase::Render,
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Phase::Loop => "loop",
+            Phase::Touch => "touch",
+            Phase::Network => "network",
+            Phase::Render => "render",
+        })
+    }
+}
+
+// the phase that overran its budget, if this boot comes from a watchdog reset
+pub(crate) fn last_reset() -> Option<Phase> {
+    if reset_reason(Cpu::ProCpu) != Some(SocResetReason::CoreMwdt1) {
+        return None;
+    }
+    let [magic, phase] = unsafe { *core::ptr::addr_of!(HEALTH) };
+    if magic != MAGIC {
+        return None;
+    }
+    Phase::from_u32(phase)
+}
+
+// TIMG1's watchdog, fed on every phase change. A phase that doesn't finish within its budget
+// (a hung request, a deadlocked SPI bus) resets the system; the phase is left in RTC memory
+// for `last_reset` to report after the restart.
+pub(crate) struct Watchdog<'d> {
+    wdt: Wdt<TIMG1<'d>>,
+    phase: Phase,
+}
+
+impl<'d> Watchdog<'d> {
+    pub(crate) fn start(mut wdt: Wdt<TIMG1<'d>>) -> Self {
+        wdt.set_stage_action(MwdtStage::Stage0, MwdtStageAction::ResetSystem);
+        wdt.set_timeout(MwdtStage::Stage0, Phase::Loop.budget());
+        wdt.enable();
+        wdt.feed();
+        let mut watchdog = Self { wdt, phase: Phase::Loop };
+        watchdog.enter(Phase::Loop);
+        watchdog
+    }
+
+    pub(crate) fn enter(&mut self, phase: Phase) {
+        if phase != self.phase {
+            self.wdt.set_timeout(MwdtStage::Stage0, phase.budget());
+            self.phase = phase;
+        }
+        self.wdt.feed();
+        unsafe { *core::ptr::addr_of_mut!(HEALTH) = [MAGIC, phase as u32] };
+    }
+
+    // a network loop that stopped coming round is reset like a hang here, the watchdog is
+    // left to bite so the restart reports it the same way
+    pub(crate) fn check_network(&mut self) {
+        let Some(since) = network::since_heartbeat() else {
+            return;
+        };
+        if since < Phase::Network.budget() {
+            return;
+        }
+        unsafe { *core::ptr::addr_of_mut!(HEALTH) = [MAGIC, Phase::Network as u32] };
+        self.wdt.set_timeout(MwdtStage::Stage0, Duration::from_millis(1));
+        loop {
+            core::hint::spin_loop();
+        }
+    }
+
+    // standby and deliberate restarts must not be reported as hangs
+    pub(crate) fn disable(&mut self) {
+        self.wdt.disable();
+        unsafe { *core::ptr::addr_of_mut!(HEALTH) = [0; 2] };
+    }
+}
+
+// for the panic handler, which can't reach the loop's instance: keeps the panic screen up
+// instead of a reset that would be reported as a hang
+pub(crate) fn disarm() {
+    Wdt::<TIMG1<'static>>::new().disable();
+    unsafe { *core::ptr::addr_of_mut!(HEALTH) = [0; 2] };
+}
@@ -0,0 +1,163 @@
+use alloc::{format, string::String, vec::Vec};
+use core::fmt::Write;
+use esp_hal::efuse::Efuse;
+use log::warn;
+use thiserror::Error;
+
+use crate::{
+    clock,
+    outbox::Outbox,
+    rules::RuleEvent,
+    sdcard::SdStorage,
+};
+
+pub(crate) const WEBHOOKS_FILE: &str = "WEBHOOKS.TXT";
+const MAX_ENDPOINTS: usize = 8;
+
+// used for rules that name a route instead of an endpoint
+const DEFAULT_TEMPLATE: &str = r#"{"device":"{device}","timestamp":{timestamp},"channel":"{channel}","value":{value},"state":"{state}"}"#;
+
+#[derive(Error, Debug)]
+pub(crate) enum WebhookError {
+    #[error("Malformed endpoint: {0}")]
+    Malformed(String),
+    #[error("Unknown endpoint: {0}")]
+    UnknownEndpoint(String),
+}
+
+// `<name> <route> <json template>`, the template runs to the end of the line
+#[derive(Clone, Debug)]
+pub(crate) struct Endpoint {
+    pub(crate) name: heapless::String<16>,
+    pub(crate) route: heapless::String<48>,
+    pub(crate) template: String,
+}
+
+impl Endpoint {
+    pub(crate) fn parse(line: &str) -> Result<Self, WebhookError> {
+        let malformed = || WebhookError::Malformed(line.into());
+        let (name, rest) = line.split_once(' ').ok_or_else(malformed)?;
+        let (route, template) = rest.trim_start().split_once(' ').ok_or_else(malformed)?;
+        if !route.starts_with('/') {
+            return Err(malformed());
+        }
+        Ok(Self {
+            name: heapless::String::try_from(name).map_err(|_| malformed())?,
+            route: heapless::String::try_from(route).map_err(|_| malformed())?,
+            template: template.trim().into(),
+        })
+    }
+}
+
+// one endpoint per line, '#' starts a comment
+pub(crate) fn load_from_sd(sd: &SdStorage<'_>) -> Vec<Endpoint> {
+    let Ok(file) = sd.read_config(WEBHOOKS_FILE) else {
+        return Vec::new();
+    };
+    file.lines()
+        .filter_map(|line| {
+            Endpoint::parse(line)
+                .inspect_err(|e| warn!("Webhooks: {}", e))
+                .ok()
+        })
+        .take(MAX_ENDPOINTS)
+        .collect()
+}
+
+// values substituted into a template; `value` is null for events without one (UI taps)
+pub(crate) struct Trigger<'t> {
+    pub(crate) channel: &'t str,
+    pub(crate) value: Option<f32>,
+    pub(crate) state: &'t str,
+}
+
+impl<'t> Trigger<'t> {
+    pub(crate) fn from_rule_event(event: &'t RuleEvent) -> Self {
+        let (rule, value, state) = match event {
+            RuleEvent::Breached { rule, value } => (rule, value, "breached"),
+            RuleEvent::Cleared { rule, value } => (rule, value, "cleared"),
+        };
+        Self { channel: &rule.channel, value: Some(*value), state }
+    }
+}
+
+pub(crate) struct Webhooks {
+    endpoints: Vec<Endpoint>,
+    device_id: heapless::String<12>,
+    outbox: Outbox,
+}
+
+impl Webhooks {
+    pub(crate) fn new(endpoints: Vec<Endpoint>) -> Self {
+        let mut device_id = heapless::String::new();
+        for byte in Efuse::read_base_mac_address() {
+            write!(device_id, "{:02x}", byte).ok();
+        }
+        Self { endpoints, device_id, outbox: Outbox::new() }
+    }
+
+    // the base MAC in hex, what the server knows the unit by
+    pub(crate) fn device_id(&self) -> &str {
+        &self.device_id
+    }
+
+    pub(crate) fn outbox(&mut self) -> &mut Outbox {
+        &mut self.outbox
+    }
+
+    // renders the body now so the timestamp is the event's, then leaves delivery to the outbox;
+    // a target starting with '/' is a bare route posted with the default template
+    pub(crate) fn fire(
+        &mut self,
+        target: &str,
+        trigger: &Trigger<'_>,
+    ) -> Result<(), WebhookError> {
+        let (route, template) = if target.starts_with('/') {
+            (target, DEFAULT_TEMPLATE)
+        } else {
+            let endpoint = self
+                .endpoints
+                .iter()
+                .find(|e| e.name == target)
+                .ok_or_else(|| WebhookError::UnknownEndpoint(target.into()))?;
+            (endpoint.route.as_str(), endpoint.template.as_str())
+        };
+        let body = render(template, &self.device_id, trigger);
+        self.outbox.push(route, body);
+        Ok(())
+    }
+}
+
+// placeholders: {device}, {timestamp} (unix seconds, null before the clock syncs), {channel},
+// {value}, {state}; anything else in braces is copied through so JSON objects stay intact
+fn render(template: &str, device_id: &str, trigger: &Trigger<'_>) -> String {
+    let mut out = String::with_capacity(template.len() + 32);
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        let end = tail.find('}').map(|i| i + 1).unwrap_or(tail.len());
+        match &tail[..end] {
+            "{device}" => out.push_str(device_id),
+            "{timestamp}" => match clock::unix_now() {
+                Some(secs) => out.push_str(&format!("{}", secs)),
+                None => out.push_str("null"),
+            },
+            "{channel}" => out.push_str(trigger.channel),
+            "{value}" => match trigger.value {
+                Some(value) => out.push_str(&format!("{}", value)),
+                None => out.push_str("null"),
+            },
+            "{state}" => out.push_str(trigger.state),
+            // not a placeholder, step over the brace alone so nested ones still match
+            _ => {
+                out.push('{');
+                rest = &tail[1..];
+                continue;
+            }
+        }
+        rest = &tail[end..];
+    }
+    out.push_str(rest);
+    out
+}
@@ -1,66 +1,230 @@
 use blocking_network_stack::Stack;
-use esp_radio::wifi::{ClientConfig, Interfaces, ModeConfig, ScanConfig, WifiController, WifiDevice};
+use blocking_network_stack::ipv4::IpInfo;
+use blocking_network_stack::ipv4::{
+    ClientConfiguration, ClientSettings, Configuration, Ipv4Addr, Mask, Subnet,
+};
+use esp_hal::time::{Duration, Instant};
+use alloc::{format, string::String, vec::Vec};
+use esp_radio::wifi::{
+    AccessPointConfig, AuthMethod, ClientConfig, ModeConfig, ScanConfig, WifiController,
+    WifiDevice, WifiError as RadioError,
+};
+use log::{debug, info, warn};
 use smoltcp::{
     iface::{Interface, SocketSet, SocketStorage},
     socket::dhcpv4,
     wire::{DhcpOption, EthernetAddress, HardwareAddress},
 };
+use thiserror::Error;
+
+use crate::{coexistence::RadioWindow, config::StaticIp};
+
+pub(crate) const AP_IP: [u8; 4] = [192, 168, 2, 1];
+// a wrong password or an AP out of range never associates, the attempt is given up on
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(20);
+// what the radio reports per scan, before hidden and repeated SSIDs are dropped
+const SCAN_MAX: usize = 20;
+
+#[derive(Error, Debug)]
+pub(crate) enum WifiError {
+    #[error("Failed to set up the radio: {0:?}")]
+    Init(RadioError),
+    #[error("Failed to configure the radio: {0:?}")]
+    Config(RadioError),
+    #[error("Failed to start the radio: {0:?}")]
+    Start(RadioError),
+    #[error("Failed to stop the radio: {0:?}")]
+    Stop(RadioError),
+    #[error("Failed to connect: {0:?}")]
+    Connect(RadioError),
+    #[error("Not connected after {0} s")]
+    Timeout(u64),
+    #[error("Scan failed: {0:?}")]
+    Scan(RadioError),
+    #[error("Failed to set the address: {0}")]
+    Address(String),
+    #[error("Setup portal failed: {0}")]
+    Portal(String),
+}
+
+// a network found by `scan`
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct AccessPoint {
+    pub(crate) ssid: heapless::String<32>,
+    // dBm
+    pub(crate) rssi: i8,
+    pub(crate) auth: AuthMethod,
+    pub(crate) channel: u8,
+}
+
+impl AccessPoint {
+    pub(crate) fn secured(&self) -> bool {
+        self.auth != AuthMethod::None
+    }
+
+    pub(crate) fn auth_name(&self) -> &'static str {
+        match self.auth {
+            AuthMethod::None => "open",
+            AuthMethod::Wep => "WEP",
+            AuthMethod::Wpa => "WPA",
+            AuthMethod::Wpa2Enterprise => "WPA2-Enterprise",
+            AuthMethod::Wpa3Personal => "WPA3",
+            AuthMethod::Wpa2Wpa3Personal => "WPA2/WPA3",
+            _ => "WPA2",
+        }
+    }
+}
+
+// from the WiFi page
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum WifiCommand {
+    Scan,
+    // saved to the settings, the restart connects to it
+    Join { ssid: String, password: String },
+}
 
 pub(crate) struct Wifi<'a> {
     controller: WifiController<'a>,
-    pub(crate) interfaces: Interfaces<'a>,
+    sta: Option<WifiDevice<'a>>,
+    ap: Option<WifiDevice<'a>>,
 }
 
 impl<'a> Wifi<'a> {
     pub(crate) fn new(
         wifi: esp_hal::peripherals::WIFI<'a>,
         radio: &'a esp_radio::Controller,
+    ) -> Result<Self, WifiError> {
+        let (controller, interfaces) =
+            esp_radio::wifi::new(radio, wifi, Default::default()).map_err(WifiError::Init)?;
+
+        Ok(Self { controller, sta: Some(interfaces.sta), ap: Some(interfaces.ap) })
+    }
+
+    pub(crate) fn configure_client(
+        &mut self,
         ssid: &str,
         password: &str,
-    ) -> Self {
-        let (mut controller, interfaces) =
-            esp_radio::wifi::new(radio, wifi, Default::default())
-                .expect("wifi init failed");
-
-        controller
+    ) -> Result<(), WifiError> {
+        self.controller
             .set_config(&ModeConfig::Client(
                 ClientConfig::default()
                     .with_ssid(ssid.into())
                     .with_password(password.into()),
             ))
-            .unwrap();
+            .map_err(WifiError::Config)
+    }
+
+    pub(crate) fn start_access_point(&mut self, ssid: &str) -> Result<(), WifiError> {
+        self.controller
+            .set_config(&ModeConfig::AccessPoint(
+                AccessPointConfig::default().with_ssid(ssid.into()),
+            ))
+            .map_err(WifiError::Config)?;
+        self.start()?;
+        info!("Access point '{}' started", ssid);
+        Ok(())
+    }
+
+    pub(crate) fn stop(&mut self) -> Result<(), WifiError> {
+        self.controller.stop().map_err(WifiError::Stop)
+    }
+
+    // leaves the AP properly and powers the radio down, for light sleep
+    pub(crate) fn suspend(&mut self) {
+        self.controller.disconnect().ok();
+        self.controller.stop().ok();
+    }
+
+    // doesn't wait for the association, the link comes back like after any drop
+    pub(crate) fn resume(&mut self) {
+        let reconnect =
+            self.start().and_then(|_| self.controller.connect().map_err(WifiError::Connect));
+        if let Err(e) = reconnect {
+            warn!("WiFi: reconnect failed, {}", e);
+        }
+    }
+
+    pub(crate) fn take_sta(&mut self) -> WifiDevice<'a> {
+        self.sta.take().expect("STA interface already taken")
+    }
 
-        Self { controller, interfaces }
+    pub(crate) fn take_ap(&mut self) -> WifiDevice<'a> {
+        self.ap.take().expect("AP interface already taken")
     }
 
-    pub(crate) fn initialize(&mut self) {
-        self.start();
-        self.scan();
-        self.connect();
+    pub(crate) fn is_connected(&self) -> bool {
+        matches!(self.controller.is_connected(), Ok(true))
     }
 
-    fn start(&mut self) {
-        self.controller.start().unwrap();
+    // dBm of the AP we are associated with
+    pub(crate) fn rssi(&self) -> Option<i32> {
+        self.controller.rssi().ok()
     }
 
-    fn scan(&mut self) {
-        let cfg = ScanConfig::default().with_max(10);
-        let res = self.controller.scan_with_config(cfg).unwrap();
-        for ap in res {
-            esp_println::println!("{:?}", ap);
+    // blocks until associated; the caller retries a failed attempt
+    pub(crate) fn initialize(&mut self) -> Result<(), WifiError> {
+        let _radio = RadioWindow::open();
+        self.start()?;
+        // only logged, connecting doesn't depend on it
+        match self.scan() {
+            Ok(networks) => {
+                for ap in networks {
+                    debug!("{:?}", ap);
+                }
+            }
+            Err(e) => warn!("WiFi: {}", e),
         }
+        self.connect()
+    }
+
+    fn start(&mut self) -> Result<(), WifiError> {
+        self.controller.start().map_err(WifiError::Start)
     }
 
-    fn connect(&mut self) {
-        self.controller.connect().unwrap();
+    // blocks for the couple of seconds the radio takes to go through the channels; strongest
+    // first, an SSID seen on several APs only once
+    pub(crate) fn scan(&mut self) -> Result<Vec<AccessPoint>, WifiError> {
+        let _radio = RadioWindow::open();
+        let cfg = ScanConfig::default().with_max(SCAN_MAX);
+        let results = self.controller.scan_with_config(cfg).map_err(WifiError::Scan)?;
+        let mut found: Vec<AccessPoint> = results
+            .iter()
+            .filter(|ap| !ap.ssid.is_empty())
+            .filter_map(|ap| {
+                Some(AccessPoint {
+                    ssid: heapless::String::try_from(ap.ssid.as_str()).ok()?,
+                    rssi: ap.signal_strength,
+                    auth: ap.auth_method.unwrap_or(AuthMethod::None),
+                    channel: ap.channel,
+                })
+            })
+            .collect();
+        found.sort_by(|a, b| b.rssi.cmp(&a.rssi));
+        let mut networks: Vec<AccessPoint> = Vec::new();
+        for ap in found {
+            if !networks.iter().any(|n| n.ssid == ap.ssid) {
+                networks.push(ap);
+            }
+        }
+        Ok(networks)
+    }
+
+    fn connect(&mut self) -> Result<(), WifiError> {
+        self.controller.connect().map_err(WifiError::Connect)?;
+        let started = Instant::now();
         loop {
             match self.controller.is_connected() {
                 Ok(true) => break,
-                Ok(false) => {}
-                Err(e) => panic!("{:?}", e),
+                Ok(false) if started.elapsed() < CONNECT_TIMEOUT => {}
+                Ok(false) => {
+                    self.controller.disconnect().ok();
+                    return Err(WifiError::Timeout(CONNECT_TIMEOUT.as_secs()));
+                }
+                Err(e) => return Err(WifiError::Connect(e)),
             }
         }
-        esp_println::println!("Connected: {:?}", self.controller.is_connected());
+        debug!("Connected: {:?}", self.controller.is_connected());
+        Ok(())
     }
 }
 
@@ -90,33 +254,102 @@ pub fn init_sockets_with_dhcp<'a>(
     let mut dhcp = dhcpv4::Socket::new();
     dhcp.set_outgoing_options(&[DhcpOption {
         kind: 12,
-        data: b"implRust",
+        data: crate::mdns::HOSTNAME.as_bytes(),
     }]);
     set.add(dhcp);
 
     set
 }
 
+// DHCP unless a static address is to be used right away, `obtain_ip` sets that one (or falls
+// back to it later)
 pub fn build_stack<'a>(
     mut device: WifiDevice<'a>,
     socket_entries: &'a mut [SocketStorage<'a>],
     now_fn: fn() -> u64,
     rng_seed: u32,
+    static_ip: Option<&StaticIp>,
 ) -> Stack<'a, WifiDevice<'a>>
 {
     let iface = create_interface(&mut device);
-    let sockets = init_sockets_with_dhcp(socket_entries);
-
+    let sockets = match static_ip.filter(|s| s.dhcp_secs == 0) {
+        Some(_) => SocketSet::new(socket_entries),
+        None => init_sockets_with_dhcp(socket_entries),
+    };
     Stack::new(iface, device, sockets, now_fn, rng_seed)
 }
 
-pub fn obtain_ip(stack: &Stack<'_, WifiDevice<'_>>) {
-    esp_println::println!("Wait for IP address");
+// the access point has no DHCP client, it serves on a fixed address
+pub fn build_ap_stack<'a>(
+    mut device: WifiDevice<'a>,
+    socket_entries: &'a mut [SocketStorage<'a>],
+    now_fn: fn() -> u64,
+    rng_seed: u32,
+) -> Result<Stack<'a, WifiDevice<'a>>, WifiError>
+{
+    let iface = create_interface(&mut device);
+    let sockets = SocketSet::new(socket_entries);
+    let stack = Stack::new(iface, device, sockets, now_fn, rng_seed);
+
+    set_fixed_address(&stack, AP_IP, AP_IP, 24, None)?;
+    Ok(stack)
+}
+
+fn set_fixed_address(
+    stack: &Stack<'_, WifiDevice<'_>>,
+    ip: [u8; 4],
+    gateway: [u8; 4],
+    prefix_len: u8,
+    dns: Option<[u8; 4]>,
+) -> Result<(), WifiError> {
+    stack
+        .set_iface_configuration(&Configuration::Client(ClientConfiguration::Fixed(
+            ClientSettings {
+                ip: Ipv4Addr::from(ip),
+                subnet: Subnet { gateway: Ipv4Addr::from(gateway), mask: Mask(prefix_len) },
+                dns: dns.map(Ipv4Addr::from),
+                secondary_dns: None,
+            },
+        )))
+        .map_err(|e| WifiError::Address(format!("{:?}", e)))
+}
+
+// the address the mock server build shows, nothing answers on it
+pub fn loopback_info() -> IpInfo {
+    let loopback = Ipv4Addr::new(127, 0, 0, 1);
+    IpInfo {
+        ip: loopback,
+        subnet: Subnet { gateway: loopback, mask: Mask(8) },
+        dns: None,
+        secondary_dns: None,
+    }
+}
+
+// waits for DHCP, with a static configuration only its `dhcp_secs` before applying that (none
+// sets it right away)
+pub fn obtain_ip(
+    stack: &Stack<'_, WifiDevice<'_>>,
+    static_ip: Option<&StaticIp>,
+) -> Result<IpInfo, WifiError> {
+    info!("Wait for IP address");
+    let _radio = RadioWindow::open();
+    let started = Instant::now();
+    let mut fallback = static_ip;
     loop {
         stack.work();
         if stack.is_iface_up() {
-            esp_println::println!("IP acquired: {:?}", stack.get_ip_info());
-            break;
+            if let Ok(info) = stack.get_ip_info() {
+                info!("IP acquired: {:?}", info);
+                return Ok(info);
+            }
+        }
+        let timeout = |s: &&StaticIp| started.elapsed() >= Duration::from_secs(s.dhcp_secs as u64);
+        if let Some(s) = fallback.filter(timeout) {
+            if s.dhcp_secs > 0 {
+                warn!("No DHCP answer in {} s, using the static address", s.dhcp_secs);
+            }
+            set_fixed_address(stack, s.address, s.gateway, s.prefix_len, s.dns)?;
+            fallback = None;
         }
     }
 }
\ No newline at end of file
@@ -1,19 +1,29 @@
 use alloc::format;
+use alloc::rc::Rc;
 use alloc::string::String;
+use alloc::vec::Vec;
 use blocking_network_stack::{IoError, Socket};
-use embedded_io::{Read, Write};
+use embedded_io::{Read, ReadReady, Write};
 use embedded_websocket::framer::{Framer, ReadResult, Stream};
 use embedded_websocket::{
     WebSocketClient, WebSocketKey, WebSocketOptions, WebSocketSendMessageType,
 };
 use esp_hal::rng::Rng;
-use esp_println::println;
 use esp_radio::wifi::WifiDevice;
+use log::{debug, warn};
 use smoltcp::wire::IpAddress;
 
+#[cfg(feature = "mock-server")]
+use crate::mock_server::{Fixtures, MockWs};
+use crate::{
+    auth::{self, AuthProvider},
+    fault_injection,
+};
+
 pub(crate) struct WsClient {
     host: &'static str,
     ip: IpAddress,
+    auth: Option<Rc<dyn AuthProvider>>,
 
     ws: WebSocketClient<Rng>,
     ws_key: Option<WebSocketKey>,
@@ -22,8 +32,12 @@ pub(crate) struct WsClient {
     ws_tx: [u8; 2048],
     frame_buf: [u8; 1024],
     read_cursor: usize,
+    received: usize,
 
     connected: bool,
+    // plays the fixtures' frames instead of a connection
+    #[cfg(feature = "mock-server")]
+    mock: Option<MockWs>,
 }
 
 impl WsClient {
@@ -33,6 +47,7 @@ impl WsClient {
         Self {
             host,
             ip,
+            auth: None,
             ws: WebSocketClient::new_client(rng),
             ws_key: None,
 
@@ -40,23 +55,55 @@ impl WsClient {
             ws_tx: [0; 2048],
             frame_buf: [0; 1024],
             read_cursor: 0,
+            received: 0,
 
             connected: false,
+            #[cfg(feature = "mock-server")]
+            mock: None,
         }
     }
 
+    #[cfg(feature = "mock-server")]
+    pub(crate) fn with_mock(mut self, fixtures: Rc<Fixtures>) -> Self {
+        self.mock = Some(MockWs::new(fixtures));
+        self
+    }
+
+    // the provider signs the upgrade request, frames after it carry no credentials
+    pub(crate) fn with_auth(mut self, auth: Option<Rc<dyn AuthProvider>>) -> Self {
+        self.auth = auth;
+        self
+    }
+
     pub(crate) fn connect<'a>(
         &mut self,
         socket: &mut Socket<'a, 'a, WifiDevice<'a>>,
     ) -> Result<(), String> {
+        let mut auth_headers = String::new();
+        if let Some(auth) = &self.auth {
+            let request = auth::Request { method: "GET", route: "/", body: &[] };
+            auth.authorize(&request, &mut auth_headers).map_err(|e| format!("auth: {e}"))?;
+        }
+        // the handshake writer adds the line breaks itself
+        let headers: Vec<&str> = auth_headers.lines().collect();
+        #[cfg(feature = "mock-server")]
+        if self.mock.is_some() {
+            self.connected = true;
+            return Ok(());
+        }
+
         socket.open(self.ip, 8765).map_err(|e| format!("open failed {e}"))?;
+        // also a reconnect, nothing of the last connection's state carries over
+        self.ws = WebSocketClient::new_client(Rng::new());
+        self.connected = false;
+        self.read_cursor = 0;
 
         let opts = WebSocketOptions {
             path: "/",
             host: self.host,
             origin: "",
             sub_protocols: None,
-            additional_headers: None,
+            additional_headers: (!headers.is_empty()).then_some(headers.as_slice()),
         };
 
         let (len, key) = self
@@ -72,10 +119,40 @@ impl WsClient {
             .map_err(|_| "ws accept")?;
 
         self.ws_key = Some(key);
+        self.received = 0;
         self.connected = true;
         Ok(())
     }
 
+    pub(crate) fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    // keeps NAT and proxies from dropping an idle connection; a failed write means it is gone
+    pub(crate) fn ping<'a>(
+        &mut self,
+        socket: &mut Socket<'a, 'a, WifiDevice<'a>>,
+    ) -> Result<(), &'static str> {
+        if !self.connected {
+            return Err("not connected");
+        }
+        #[cfg(feature = "mock-server")]
+        if self.mock.is_some() {
+            return Ok(());
+        }
+        let len = self
+            .ws
+            .write(WebSocketSendMessageType::Ping, true, &[], &mut self.ws_tx)
+            .map_err(|_| "ws ping")?;
+        if fault_injection::drop_write() {
+            return Ok(());
+        }
+        socket.write_all(&self.ws_tx[..len]).map_err(|_| {
+            self.connected = false;
+            "ws write"
+        })
+    }
+
     // ---- send if there is input ----
     pub(crate) fn poll_send<'a>(
         &mut self,
@@ -90,6 +167,11 @@ impl WsClient {
             Some(m) => m,
             None => return,
         };
+        #[cfg(feature = "mock-server")]
+        if self.mock.is_some() {
+            debug!("WS TX (mock): {}", String::from_utf8_lossy(msg));
+            return;
+        }
 
         let len = match self.ws.write(
             WebSocketSendMessageType::Text,
@@ -101,6 +183,9 @@ impl WsClient {
             Err(_) => return,
         };
 
+        if fault_injection::drop_write() {
+            return;
+        }
         let _ = socket.write_all(&self.ws_tx[..len]);
     }
 
@@ -108,12 +193,27 @@ impl WsClient {
     pub(crate) fn poll_recv<'a>(
         &mut self,
         socket: &mut Socket<'a, 'a, WifiDevice<'a>>,
-    ) {
+    ) -> Option<String> {
         if !self.connected {
-            return;
+            return None;
+        }
+        #[cfg(feature = "mock-server")]
+        if let Some(mock) = self.mock.as_mut() {
+            return mock.recv();
         }
 
-        let mut ws_socket = WsSocket(socket);
+        if fault_injection::cut_read(self.received) {
+            warn!("WS: connection cut (injected)");
+            socket.disconnect();
+            self.connected = false;
+            return None;
+        }
+        // nothing buffered and nothing arrived, reading now would block the loop
+        if self.read_cursor == 0 && !socket.read_ready().unwrap_or(false) {
+            return None;
+        }
+
+        let mut ws_socket = WsSocket(socket, &mut self.received);
 
         let mut framer = Framer::<_, embedded_websocket::Client>::new(
             &mut self.ws_rx,
@@ -124,26 +224,19 @@ impl WsClient {
 
         match framer.read(&mut ws_socket, &mut self.frame_buf) {
             Ok(ReadResult::Text(txt)) => {
-                println!("WS RX: {txt}");
+                debug!("WS RX: {txt}");
+                Some(String::from(txt))
             }
-            Ok(_) => {}
-            Err(_) => {}
+            Ok(_) => None,
+            Err(_) => None,
         }
     }
-
-    pub(crate) fn poll<'a>(
-        &mut self,
-        socket: &mut Socket<'a, 'a, WifiDevice<'a>>,
-        send: Option<&[u8]>,
-    ) {
-        self.poll_send(socket, send);
-        self.poll_recv(socket);
-    }
 }
 
 // wrapper because rust doesnt allow impl for Socket directly
 struct WsSocket<'a, 'b, 'c>(
-    &'c mut Socket<'a, 'b, WifiDevice<'a>>
+    &'c mut Socket<'a, 'b, WifiDevice<'a>>,
+    &'c mut usize,
 );
 
 impl<'a, 'b, 'c> Stream<IoError> for WsSocket<'a, 'b, 'c>
@@ -152,7 +245,10 @@ where
         Read<Error = IoError> + Write<Error = IoError>,
 {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
-        self.0.read(buf)
+        fault_injection::delay_read();
+        let n = self.0.read(buf)?;
+        *self.1 += n;
+        Ok(n)
     }
 
     fn write_all(&mut self, buf: &[u8]) -> Result<(), IoError> {
@@ -0,0 +1,103 @@
+// What the server knows about us over the websocket, kept across reconnects. Every connection
+// starts with a hello naming the device, the session the server handed out last time, the
+// topics to subscribe to and the id of the last message received, so the server can restore
+// its context and replay what was missed while the link was down:
+//   -> {"type":"hello","device":"esp32-display","session":"...","topics":["chat"],"since":41}
+//   <- {"type":"welcome","session":"..."}
+// Frames with an `id` move `since` forward; a replayed one we already have is dropped. Servers
+// that don't speak this ignore the hello and never send ids, and nothing changes for them.
+use alloc::{format, string::String, vec::Vec};
+use serde::Deserialize;
+
+use crate::{data_binding::truncated, diagnostics::json_string, mdns, sdcard::SdStorage};
+
+const TOPICS_FILE: &str = "WS.TXT";
+const MAX_TOPICS: usize = 8;
+const WELCOME_TYPE: &str = "welcome";
+// subscribed to whatever WS.TXT says, the chat page needs its messages either way
+const CHAT_TOPIC: &str = "chat";
+
+#[derive(Deserialize)]
+struct Header {
+    #[serde(default, rename = "type", deserialize_with = "truncated")]
+    kind: heapless::String<16>,
+    #[serde(default)]
+    id: Option<u32>,
+    #[serde(default, deserialize_with = "truncated")]
+    session: heapless::String<64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Received {
+    // for the chat and the hooks as before
+    Message,
+    // the handshake's answer, or a replay of something already received
+    Consumed,
+}
+
+pub(crate) struct WsSession {
+    topics: Vec<String>,
+    session: Option<heapless::String<64>>,
+    last_id: Option<u32>,
+}
+
+impl WsSession {
+    pub(crate) fn new(mut topics: Vec<String>) -> Self {
+        if !topics.iter().any(|t| t == CHAT_TOPIC) {
+            topics.insert(0, CHAT_TOPIC.into());
+        }
+        Self { topics, session: None, last_id: None }
+    }
+
+    // the first frame on every new connection
+    pub(crate) fn hello(&self) -> String {
+        let topics: Vec<String> = self.topics.iter().map(|t| json_string(t)).collect();
+        let mut frame = format!(
+            "{{\"type\":\"hello\",\"device\":{},\"topics\":[{}]",
+            json_string(mdns::HOSTNAME),
+            topics.join(",")
+        );
+        if let Some(session) = &self.session {
+            frame.push_str(&format!(",\"session\":{}", json_string(session)));
+        }
+        if let Some(last_id) = self.last_id {
+            frame.push_str(&format!(",\"since\":{}", last_id));
+        }
+        frame.push('}');
+        frame
+    }
+
+    pub(crate) fn receive(&mut self, frame: &str) -> Received {
+        let mut unescaped = [0u8; 128];
+        let Ok((header, _)) =
+            serde_json_core::from_slice_escaped::<Header>(frame.as_bytes(), &mut unescaped)
+        else {
+            return Received::Message;
+        };
+        if header.kind == WELCOME_TYPE {
+            if !header.session.is_empty() {
+                self.session = Some(header.session);
+            }
+            return Received::Consumed;
+        }
+        match (header.id, self.last_id) {
+            (Some(id), Some(last)) if id <= last => Received::Consumed,
+            (Some(id), _) => {
+                self.last_id = Some(id);
+                Received::Message
+            }
+            (None, _) => Received::Message,
+        }
+    }
+}
+
+// WS.TXT: a topic per line beyond the chat, `#` comments
+pub(crate) fn load_topics(sd: &SdStorage<'_>) -> Vec<String> {
+    let Ok(file) = sd.read_config(TOPICS_FILE) else {
+        return Vec::new();
+    };
+    file.lines()
+        .take(MAX_TOPICS)
+        .map(String::from)
+        .collect()
+}
@@ -0,0 +1,111 @@
+// Several widgets fed by the one websocket. A frame naming a topic goes to the channel of that
+// name, on the UI core:
+//   <- {"topic":"weather","value":"Sunny, 21 C"}
+//   <- {"topic":"power","value":412.5}
+// A channel has Rust handlers registered with `on` and slint properties tied to it with `bind`;
+// the topics of WS.TXT also get a row on home with their latest value. Frames go to the
+// ws_message hooks and the script as well, whether a channel took them or not.
+use alloc::{boxed::Box, format, string::String, vec::Vec};
+use serde::Deserialize;
+use slint::SharedString;
+
+use crate::{data_binding::truncated, watch::Watch};
+
+const MAX_VALUE: usize = 128;
+
+#[derive(Deserialize)]
+struct TextMessage {
+    #[serde(deserialize_with = "truncated")]
+    topic: heapless::String<32>,
+    #[serde(deserialize_with = "truncated")]
+    value: heapless::String<MAX_VALUE>,
+}
+
+#[derive(Deserialize)]
+struct NumberMessage {
+    #[serde(deserialize_with = "truncated")]
+    topic: heapless::String<32>,
+    value: f32,
+}
+
+// a row on home
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct TopicValue {
+    pub(crate) topic: String,
+    pub(crate) value: String,
+}
+
+struct Channel {
+    name: String,
+    handlers: Vec<Box<dyn FnMut(&str)>>,
+    // listed on home
+    shown: bool,
+}
+
+pub(crate) struct TopicRouter {
+    channels: Vec<Channel>,
+    rows: Vec<TopicValue>,
+    values: Watch<Vec<TopicValue>>,
+}
+
+impl TopicRouter {
+    // `shown` are the topics listed on home
+    pub(crate) fn new(values: Watch<Vec<TopicValue>>, shown: &[String]) -> Self {
+        let channels = shown
+            .iter()
+            .map(|name| Channel { name: name.clone(), handlers: Vec::new(), shown: true })
+            .collect();
+        Self { channels, rows: Vec::new(), values }
+    }
+
+    pub(crate) fn on(&mut self, topic: &str, handler: impl FnMut(&str) + 'static) {
+        let channel = match self.channels.iter().position(|c| c.name == topic) {
+            Some(index) => &mut self.channels[index],
+            None => {
+                let name = topic.into();
+                self.channels.push(Channel { name, handlers: Vec::new(), shown: false });
+                self.channels.last_mut().unwrap()
+            }
+        };
+        channel.handlers.push(Box::new(handler));
+    }
+
+    // a slint property, through the watch bound to it
+    pub(crate) fn bind(&mut self, topic: &str, property: Watch<SharedString>) {
+        self.on(topic, move |value| property.send(value.into()));
+    }
+
+    // frames without a topic, or with one nobody listens to, are left alone
+    pub(crate) fn dispatch(&mut self, frame: &str) {
+        let Some((topic, value)) = parse(frame) else {
+            return;
+        };
+        let Some(channel) = self.channels.iter_mut().find(|c| c.name == topic.as_str()) else {
+            return;
+        };
+        for handler in channel.handlers.iter_mut() {
+            handler(&value);
+        }
+        if channel.shown {
+            match self.rows.iter_mut().find(|row| row.topic == channel.name) {
+                Some(row) => row.value = value,
+                None => self.rows.push(TopicValue { topic: channel.name.clone(), value }),
+            }
+            self.values.send(self.rows.clone());
+        }
+    }
+}
+
+// the topic and the value as text; a value that is neither a string nor a number is left out
+fn parse(frame: &str) -> Option<(heapless::String<32>, String)> {
+    let mut unescaped = [0u8; MAX_VALUE * 2];
+    let bytes = frame.as_bytes();
+    if let Ok((message, _)) =
+        serde_json_core::from_slice_escaped::<TextMessage>(bytes, &mut unescaped)
+    {
+        return Some((message.topic, message.value.as_str().into()));
+    }
+    let (message, _) =
+        serde_json_core::from_slice_escaped::<NumberMessage>(bytes, &mut unescaped).ok()?;
+    Some((message.topic, format!("{}", message.value)))
+}
@@ -0,0 +1,232 @@
+// Just enough XML for feeds: a push parser that takes the document in arbitrary chunks and
+// reports element names and text. Attributes, namespaces and DTDs are skipped, which is
+// fine for reading titles and dates but not for general XML.
+
+pub(crate) const MAX_TEXT: usize = 256;
+const MAX_NAME: usize = 32;
+const MAX_ENTITY: usize = 8;
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum XmlEvent<'a> {
+    // local names, `dc:date` reports as `date`
+    Start(&'a str),
+    End(&'a str),
+    // entity-decoded text between tags, CDATA included; cut at MAX_TEXT bytes
+    Text(&'a str),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Text,
+    Entity,
+    // just after `<`
+    TagOpen,
+    StartName,
+    EndName,
+    // attributes up to `>`, with the quote character when inside a value
+    Attributes(Option<u8>),
+    // `<!` followed by a comment, CDATA or a declaration
+    Bang,
+    Comment,
+    CData,
+    // `<?...?>` and `<!DOCTYPE ...>`
+    Skip,
+}
+
+pub(crate) struct XmlReader {
+    state: State,
+    text: heapless::Vec<u8, MAX_TEXT>,
+    name: heapless::Vec<u8, MAX_NAME>,
+    entity: heapless::Vec<u8, MAX_ENTITY>,
+    // the last bytes seen in a comment, CDATA section or `<!` prefix
+    tail: [u8; 8],
+    self_closing: bool,
+}
+
+impl XmlReader {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: State::Text,
+            text: heapless::Vec::new(),
+            name: heapless::Vec::new(),
+            entity: heapless::Vec::new(),
+            tail: [0; 8],
+            self_closing: false,
+        }
+    }
+
+    pub(crate) fn feed(&mut self, bytes: &[u8], mut f: impl FnMut(XmlEvent<'_>)) {
+        for &b in bytes {
+            self.step(b, &mut f);
+        }
+    }
+
+    fn step(&mut self, b: u8, f: &mut impl FnMut(XmlEvent<'_>)) {
+        match self.state {
+            State::Text => match b {
+                b'<' => {
+                    self.flush_text(f);
+                    self.state = State::TagOpen;
+                }
+                b'&' => {
+                    self.entity.clear();
+                    self.state = State::Entity;
+                }
+                _ => self.push_text(b),
+            },
+            State::Entity => match b {
+                b';' => {
+                    self.decode_entity();
+                    self.state = State::Text;
+                }
+                b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'#' if self.entity.push(b).is_ok() => {}
+                _ => {
+                    // a bare ampersand, keep what was there and look at this byte again
+                    self.push_text(b'&');
+                    for i in 0..self.entity.len() {
+                        self.push_text(self.entity[i]);
+                    }
+                    self.state = State::Text;
+                    self.step(b, f);
+                }
+            },
+            State::TagOpen => {
+                self.name.clear();
+                self.self_closing = false;
+                self.state = match b {
+                    b'/' => State::EndName,
+                    b'!' => {
+                        self.tail = [0; 8];
+                        State::Bang
+                    }
+                    b'?' => State::Skip,
+                    _ => {
+                        self.name.push(b).ok();
+                        State::StartName
+                    }
+                };
+            }
+            State::StartName => match b {
+                b'>' => self.close_start_tag(f),
+                b'/' => {
+                    self.self_closing = true;
+                    self.state = State::Attributes(None);
+                }
+                _ if b.is_ascii_whitespace() => self.state = State::Attributes(None),
+                _ => {
+                    self.name.push(b).ok();
+                }
+            },
+            State::EndName => match b {
+                b'>' => {
+                    f(XmlEvent::End(local_name(&self.name)));
+                    self.state = State::Text;
+                }
+                _ if b.is_ascii_whitespace() => {}
+                _ => {
+                    self.name.push(b).ok();
+                }
+            },
+            State::Attributes(quote) => match (quote, b) {
+                (Some(q), _) if b == q => self.state = State::Attributes(None),
+                (Some(_), _) => {}
+                (None, b'"' | b'\'') => self.state = State::Attributes(Some(b)),
+                (None, b'/') => self.self_closing = true,
+                (None, b'>') => self.close_start_tag(f),
+                (None, _) => self.self_closing = false,
+            },
+            State::Bang => {
+                self.shift_tail(b);
+                if self.tail.ends_with(b"--") {
+                    self.tail = [0; 8];
+                    self.state = State::Comment;
+                } else if self.tail.ends_with(b"[CDATA[") {
+                    self.state = State::CData;
+                } else if b == b'>' || !(b == b'-' || b == b'[' || b.is_ascii_uppercase()) {
+                    self.state = if b == b'>' { State::Text } else { State::Skip };
+                }
+            }
+            State::Comment => {
+                self.shift_tail(b);
+                if self.tail.ends_with(b"-->") {
+                    self.state = State::Text;
+                }
+            }
+            State::CData => {
+                self.shift_tail(b);
+                if self.tail.ends_with(b"]]>") {
+                    // the two brackets were pushed as text before the `>` gave them away
+                    let len = self.text.len().saturating_sub(2);
+                    self.text.truncate(len);
+                    self.state = State::Text;
+                } else {
+                    self.push_text(b);
+                }
+            }
+            State::Skip => {
+                if b == b'>' {
+                    self.state = State::Text;
+                }
+            }
+        }
+    }
+
+    fn close_start_tag(&mut self, f: &mut impl FnMut(XmlEvent<'_>)) {
+        let name = local_name(&self.name);
+        f(XmlEvent::Start(name));
+        if self.self_closing {
+            f(XmlEvent::End(name));
+        }
+        self.state = State::Text;
+    }
+
+    fn flush_text(&mut self, f: &mut impl FnMut(XmlEvent<'_>)) {
+        // whitespace between elements is layout, not content
+        if self.text.iter().any(|b| !b.is_ascii_whitespace()) {
+            let text = match core::str::from_utf8(&self.text) {
+                Ok(text) => text,
+                // a multi-byte character cut by MAX_TEXT
+                Err(e) => core::str::from_utf8(&self.text[..e.valid_up_to()]).unwrap_or(""),
+            };
+            f(XmlEvent::Text(text.trim()));
+        }
+        self.text.clear();
+    }
+
+    fn push_text(&mut self, b: u8) {
+        self.text.push(b).ok();
+    }
+
+    fn shift_tail(&mut self, b: u8) {
+        self.tail.copy_within(1.., 0);
+        self.tail[7] = b;
+    }
+
+    fn decode_entity(&mut self) {
+        let c = match &self.entity[..] {
+            b"amp" => Some('&'),
+            b"lt" => Some('<'),
+            b"gt" => Some('>'),
+            b"quot" => Some('"'),
+            b"apos" => Some('\''),
+            [b'#', b'x' | b'X', hex @ ..] => number(hex, 16),
+            [b'#', dec @ ..] => number(dec, 10),
+            // HTML entities such as &nbsp; are not XML, a space is the likely intent
+            _ => Some(' '),
+        };
+        let mut buf = [0u8; 4];
+        for &b in c.unwrap_or('\u{fffd}').encode_utf8(&mut buf).as_bytes() {
+            self.push_text(b);
+        }
+    }
+}
+
+fn number(digits: &[u8], radix: u32) -> Option<char> {
+    let digits = core::str::from_utf8(digits).ok()?;
+    char::from_u32(u32::from_str_radix(digits, radix).ok()?)
+}
+
+fn local_name(name: &[u8]) -> &str {
+    let name = core::str::from_utf8(name).unwrap_or("");
+    name.rsplit(':').next().unwrap_or(name)
+}